@@ -1,9 +1,13 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 /// E2E Test suite for fluido.
 #[derive(Parser, Debug)]
 #[command(version, about)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Only run tests matching this regex
     #[arg(value_name = "REGEX")]
     pub include: Option<regex::Regex>,
@@ -12,9 +16,105 @@ pub struct Args {
     #[arg(long, short, value_name = "REGEX")]
     pub exclude: Option<regex::Regex>,
 
+    /// Root directory of a test corpus, searched recursively for `test.toml` manifests. Can be
+    /// given multiple times. Defaults to the tests of this crate.
+    #[arg(long, value_name = "DIR")]
+    pub tests_dir: Vec<PathBuf>,
+
+    /// Skip tests whose path relative to their root directory matches this glob, i.e `slow/**`.
+    /// Can be given multiple times.
+    #[arg(long, value_name = "GLOB")]
+    pub ignore: Vec<glob::Pattern>,
+
     /// Print out warnings, errors, and output of print options
     #[arg(long, env = "FLUIDO_TEST_VERBOSE")]
     pub verbose: bool,
+
+    /// Write an example gallery of the found designs (markdown and DOT files) to this directory.
+    /// Defaults to `book/examples/` when given without a value.
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = crate::gallery::DEFAULT_GALLERY_DIR)]
+    pub gallery: Option<PathBuf>,
+
+    /// Instead of running the tests, shrink the input space and the time limit of the failing
+    /// test with this name while it keeps failing the same way, and write the smallest
+    /// reproducing manifest.
+    #[arg(long, value_name = "TEST", conflicts_with = "gallery")]
+    pub minimize: Option<String>,
+
+    /// Directory the manifest minimized with `--minimize` is written into.
+    #[arg(long, value_name = "DIR", default_value = "minimized")]
+    pub minimized_dir: PathBuf,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the full pipeline on random input spaces and targets. Each failing case is shrunk and
+    /// written as a reproducer manifest.
+    Stress(StressConfig),
+    /// Loop the selected tests for a while with random time limits, tracking failure rates and
+    /// memory usage to catch leaks and rare nondeterministic failures.
+    Soak(SoakConfig),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct StressConfig {
+    /// Number of random cases to run.
+    #[arg(long, default_value_t = 20)]
+    pub cases: usize,
+
+    /// Seed of the random cases, runs with the same seed draw the same cases.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Time limit of each search in seconds.
+    #[arg(long, default_value_t = 1)]
+    pub time_limit: u64,
+
+    /// Largest number of inputs of a case, each case has at least two.
+    #[arg(long, default_value_t = 3)]
+    pub max_inputs: usize,
+
+    /// Directory reproducer manifests of failing cases are written into.
+    #[arg(long, value_name = "DIR", default_value = "crashes")]
+    pub crashes_dir: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SoakConfig {
+    /// How long to keep running tests, in seconds.
+    #[arg(long, default_value_t = 600)]
+    pub duration: u64,
+
+    /// Seed of the tests and time limits drawn, runs with the same seed draw the same sequence.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Smallest time limit drawn for a search, in seconds.
+    #[arg(long, default_value_t = 1)]
+    pub min_time_limit: u64,
+
+    /// Largest time limit drawn for a search, in seconds.
+    #[arg(long, default_value_t = 3)]
+    pub max_time_limit: u64,
+
+    /// Seconds between two reports of the failure rate and the memory usage.
+    #[arg(long, default_value_t = 60)]
+    pub report_interval: u64,
+
+    /// Fail if the resident memory grows by more than this many MiB over the soak. Memory usage
+    /// is only tracked on Linux.
+    #[arg(long, value_name = "MIB")]
+    pub max_memory_growth: Option<u64>,
+
+    /// Directory reproducer manifests of failing iterations are written into.
+    #[arg(long, value_name = "DIR", default_value = "crashes")]
+    pub crashes_dir: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinimizeConfig {
+    pub test: String,
+    pub minimized_dir: PathBuf,
 }
 
 #[derive(Debug, Clone)]
@@ -23,7 +123,14 @@ pub struct FilterConfig {
     pub exclude: Option<regex::Regex>,
 }
 
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    pub roots: Vec<PathBuf>,
+    pub ignore: Vec<glob::Pattern>,
+}
+
 #[derive(Debug, Clone)]
 pub struct RunConfig {
     pub verbose: bool,
+    pub gallery: Option<PathBuf>,
 }
@@ -0,0 +1,107 @@
+//! Focused explanations of designs missing the expectations of their manifest, so that a failing
+//! test can be understood without rerunning it in verbose mode.
+use std::fmt::Write;
+
+use fluido_core::MixerDesign;
+use fluido_parse::parser::Parse;
+use fluido_types::{
+    expr::{Expr, ExprPath, MixOperand},
+    fluid::Concentration,
+};
+
+use crate::{
+    harness::{design_error, unmet_expectations},
+    manifest::Expected,
+};
+
+/// Explains how the design misses the expectations: each unmet expectation, the concentration the
+/// design produces, how it is stored and, if the manifest gives the mixer sequence, the smallest
+/// sub-expressions where the design differs from it. `None` if the design meets the expectations.
+pub fn explain_unmet_expectations(
+    expected: &Expected,
+    mixer_design: &MixerDesign,
+    target_concentration: &Concentration,
+) -> anyhow::Result<Option<String>> {
+    let unmet = unmet_expectations(expected, mixer_design, target_concentration)?;
+    if unmet.is_empty() {
+        return Ok(None);
+    }
+    let mut explanation = String::from("unmet expectations:\n");
+    for expectation in unmet {
+        writeln!(explanation, "  {expectation}")?;
+    }
+
+    let produced = mixer_design.certificate().result.concentration;
+    writeln!(
+        explanation,
+        "produced concentration: {} (target {}, off by {})",
+        f64::from(produced),
+        target_concentration,
+        design_error(mixer_design, target_concentration)
+    )?;
+
+    write!(
+        explanation,
+        "storage: {} units",
+        mixer_design.storage_units_needed()
+    )?;
+    if let Some(lower_bound) = mixer_design.storage_units_lower_bound() {
+        write!(explanation, " (at least {lower_bound})")?;
+    }
+    writeln!(explanation)?;
+    for assignment in mixer_design.storage_assignments().assignments {
+        writeln!(
+            explanation,
+            "  unit {}: {}",
+            assignment.storage_unit, assignment.fluid
+        )?;
+    }
+
+    if let Some(mixer_sequence) = &expected.mixer_sequence {
+        match Expr::parse(mixer_sequence) {
+            Ok(expected_expr) => {
+                let differences = expr_differences(&expected_expr, mixer_design.expr());
+                if !differences.is_empty() {
+                    writeln!(explanation, "sequence differs from the expected one:")?;
+                    for difference in differences {
+                        writeln!(explanation, "  {difference}")?;
+                    }
+                }
+            }
+            Err(err) => writeln!(explanation, "expected mixer sequence does not parse: {err}")?,
+        }
+    }
+    Ok(Some(explanation))
+}
+
+/// Smallest sub-expressions where the produced expression differs from the expected one, found by
+/// descending into mixes present in both.
+fn expr_differences(expected: &Expr, produced: &Expr) -> Vec<String> {
+    let mut differences = vec![];
+    let mut pending = vec![(expected, produced, ExprPath::default())];
+    while let Some((expected, produced, path)) = pending.pop() {
+        if expected == produced {
+            continue;
+        }
+        match (expected, produced) {
+            (Expr::Mix(expected_lhs, expected_rhs), Expr::Mix(produced_lhs, produced_rhs))
+                if expected_lhs == produced_rhs && expected_rhs == produced_lhs =>
+            {
+                differences.push(format!("at {path}: operands of `{produced}` are swapped"));
+            }
+            (Expr::Mix(expected_lhs, expected_rhs), Expr::Mix(produced_lhs, produced_rhs)) => {
+                let operand_path = |operand| {
+                    let mut path = path.clone();
+                    path.0.push(operand);
+                    path
+                };
+                pending.push((expected_rhs, produced_rhs, operand_path(MixOperand::Rhs)));
+                pending.push((expected_lhs, produced_lhs, operand_path(MixOperand::Lhs)));
+            }
+            _ => differences.push(format!(
+                "at {path}: expected `{expected}`, found `{produced}`"
+            )),
+        }
+    }
+    differences
+}
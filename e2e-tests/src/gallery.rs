@@ -0,0 +1,107 @@
+//! Generates an example gallery for the fluido book from the e2e test corpus.
+//!
+//! Every test with a found design gets a markdown page describing its setup, the resulting
+//! mixer tree and metrics, along with the DOT file of the mixer graph. If graphviz is installed,
+//! the graph is also rendered to SVG. An `index.md` lists all the examples.
+use std::{fmt::Write as _, fs, path::Path, process::Command};
+
+use fluido_core::{expr_to_graph, MixerDesign};
+use fluido_types::{precision::Precision, pretty::PrettyPrinter};
+
+use crate::{
+    harness::{design_error, setup_fluids},
+    manifest::TestManifest,
+};
+
+/// Default output directory of the gallery, relative to the workspace root.
+pub const DEFAULT_GALLERY_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../book/examples");
+
+/// A test manifest together with the design found for it.
+pub struct GalleryEntry<'a> {
+    pub manifest: &'a TestManifest,
+    pub design: &'a MixerDesign,
+}
+
+/// Writes the gallery pages, graphs and index into `out_dir`.
+pub fn write_gallery(entries: &[GalleryEntry], out_dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut index = String::from("# Examples\n\n");
+    for entry in entries {
+        let name = &entry.manifest.metadata.name;
+        let dot = expr_to_graph(entry.design.expr()).dot();
+        let dot_path = out_dir.join(format!("{name}.dot"));
+        fs::write(&dot_path, dot)?;
+        let has_svg = render_svg(&dot_path, &out_dir.join(format!("{name}.svg")));
+
+        fs::write(
+            out_dir.join(format!("{name}.md")),
+            example_page(entry, has_svg)?,
+        )?;
+        writeln!(index, "- [{name}]({name}.md)")?;
+    }
+    fs::write(out_dir.join("index.md"), index)?;
+    Ok(())
+}
+
+fn example_page(entry: &GalleryEntry, has_svg: bool) -> anyhow::Result<String> {
+    let manifest = entry.manifest;
+    let design = entry.design;
+    let name = &manifest.metadata.name;
+    let (_, target_concentration) = setup_fluids(&manifest.setup)?;
+
+    let mut page = format!("# {name}\n\n## Setup\n\n");
+    for (title, fluids) in [
+        ("Input", &manifest.setup.input),
+        ("Target", &manifest.setup.target),
+    ] {
+        writeln!(page, "| {title} | Concentration | Volume |")?;
+        writeln!(page, "|---|---|---|")?;
+        for (fluid_name, fluid) in fluids {
+            writeln!(
+                page,
+                "| {fluid_name} | {} | {} |",
+                fluid.concentration, fluid.volume
+            )?;
+        }
+        writeln!(page)?;
+    }
+
+    writeln!(page, "## Mixer\n")?;
+    writeln!(page, "```text")?;
+    writeln!(page, "{}", PrettyPrinter::new(2, true).print(design.expr()))?;
+    writeln!(page, "```\n")?;
+    if has_svg {
+        writeln!(page, "![mixer graph of {name}]({name}.svg)\n")?;
+    }
+    writeln!(page, "Mixer graph: [{name}.dot]({name}.dot)\n")?;
+
+    writeln!(page, "## Metrics\n")?;
+    writeln!(page, "| Metric | Value |")?;
+    writeln!(page, "|---|---|")?;
+    writeln!(page, "| Mix count | {} |", design.mix_count())?;
+    writeln!(
+        page,
+        "| Storage units | {} |",
+        design.storage_units_needed()
+    )?;
+    let precision = Precision::default();
+    writeln!(page, "| Cost | {} |", precision.format(design.cost()))?;
+    writeln!(
+        page,
+        "| Error | {} |",
+        precision.format(design_error(design, &target_concentration).abs())
+    )?;
+    Ok(page)
+}
+
+/// Renders the DOT file with graphviz, returns false if graphviz is not available.
+fn render_svg(dot_path: &Path, svg_path: &Path) -> bool {
+    Command::new("dot")
+        .arg("-Tsvg")
+        .arg(dot_path)
+        .arg("-o")
+        .arg(svg_path)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
@@ -1,60 +1,294 @@
-use std::str::FromStr;
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+};
 
-use fluido_core::{search_mixer_design, Config};
-use fluido_types::fluid::Fluid;
+use fluido_core::{
+    search_mixer_design, Config, LogConfig, MixerDesign, MixerGenerationConfig, MixerGenerator,
+};
+use fluido_ir::ir::{evaluate_ir, IROp, Operand};
+use fluido_parse::parser::Parse;
+use fluido_types::{
+    error::{FluidoError, MixerGenerationError},
+    expr::Expr,
+    fluid::{Concentration, Fluid, Volume},
+    input_space::{Input, InputSpace},
+    number::{validate_f64, Rational},
+    precision::Precision,
+};
 
-use crate::{manifest::TestManifest, util::run_and_capture_output};
+use crate::{
+    explain::explain_unmet_expectations,
+    manifest::{Expected, Setup, TestFluid, TestManifest},
+    util::run_and_capture_output,
+};
+
+/// Result of running a single test manifest.
+pub struct SaturationRun {
+    pub passed: bool,
+    /// Captured stdout and stderr of the run.
+    pub output: String,
+    /// Found design, if the search succeeded.
+    pub design: Option<MixerDesign>,
+    /// How the design misses the expectations of the manifest, if it does.
+    pub explanation: Option<String>,
+}
+
+/// How a test failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    Panic(String),
+    /// The search did not find a design for the target in time.
+    NotFound(String),
+    /// Any other error of the search.
+    Error(String),
+    InvariantViolation(String),
+    /// A design was found, but it does not meet the expectations of the manifest.
+    UnmetExpectation,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Panic(message) => write!(f, "panicked: {message}"),
+            Failure::NotFound(err) | Failure::Error(err) => write!(f, "failed: {err}"),
+            Failure::InvariantViolation(invariant) => write!(f, "invalid design: {invariant}"),
+            Failure::UnmetExpectation => write!(f, "design does not meet the expectations"),
+        }
+    }
+}
+
+fn parse_fluid(test_fluid: &TestFluid) -> anyhow::Result<Fluid> {
+    let fluid_str = format!("(fluid {} {})", test_fluid.concentration, test_fluid.volume);
+    // Convert the error into anyhow error.
+    Fluid::from_str(&fluid_str).map_err(|err| err.into())
+}
+
+/// Returns the inputs, named after their keys, and the target concentration described by the
+/// setup.
+pub fn setup_fluids(setup: &Setup) -> anyhow::Result<(InputSpace, Concentration)> {
+    let input_space = setup
+        .input
+        .iter()
+        .map(|(name, test_fluid)| {
+            let input = Input::new(parse_fluid(test_fluid)?).with_name(name);
+            Ok(input.with_tier(test_fluid.tier.unwrap_or_default()))
+        })
+        .collect::<anyhow::Result<InputSpace>>()?;
+    let target_fluids = setup
+        .target
+        .values()
+        .map(parse_fluid)
+        .collect::<anyhow::Result<Vec<Fluid>>>()?;
+    let target_fluid = target_fluids
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("manifest does not define a target fluid"))?;
+    Ok((input_space, target_fluid.concentration().clone()))
+}
+
+/// Config the search of the manifest is run with.
+pub fn test_config(manifest: &TestManifest) -> anyhow::Result<Config> {
+    // TODO: expose this to the test toml.
+    let mixer_generator = MixerGenerator::EqualitySaturation;
+    let mut mixer_config = MixerGenerationConfig::new(manifest.time_limit, mixer_generator);
+    if let Some(node_limit) = manifest.setup.saturation_node_count {
+        mixer_config = mixer_config.with_node_limit(node_limit);
+    }
+    if let Some(iter_limit) = manifest.setup.saturation_iter_limit {
+        mixer_config = mixer_config.with_iter_limit(iter_limit);
+    }
+    // TODO: expose extra logging steps to the test toml.
+    Ok(Config::new(mixer_config, LogConfig::silent()))
+}
+
+/// Runs the manifest and returns how it failed, if it did. Panics of the search are caught and
+/// reported as failures, callers may want to silence the panic hook meanwhile. Errors are only
+/// returned for manifests which cannot be run at all.
+pub fn failure_of(manifest: &TestManifest) -> anyhow::Result<Option<Failure>> {
+    let (input_space, target_concentration) = setup_fluids(&manifest.setup)?;
+    let config = test_config(manifest)?;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        search_mixer_design(config, target_concentration.clone(), &input_space)
+    }));
+    let mixer_design = match result {
+        Err(payload) => return Ok(Some(Failure::Panic(panic_message(payload)))),
+        Ok(Err(
+            err @ FluidoError::MixerGenerationError(
+                MixerGenerationError::TargetUnreachable(_)
+                | MixerGenerationError::TimeLimitReached(_),
+            ),
+        )) => return Ok(Some(Failure::NotFound(err.to_string()))),
+        Ok(Err(err)) => return Ok(Some(Failure::Error(err.to_string()))),
+        Ok(Ok(mixer_design)) => mixer_design,
+    };
+    if let Err(invariant) = check_invariants(&mixer_design, &input_space) {
+        return Ok(Some(Failure::InvariantViolation(invariant)));
+    }
+    if !check_expected(&manifest.expected, &mixer_design, &target_concentration)? {
+        return Ok(Some(Failure::UnmetExpectation));
+    }
+    Ok(None)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+/// Searches a design for the setup of the manifest.
+pub fn run_manifest(manifest: &TestManifest, config: Config) -> anyhow::Result<MixerDesign> {
+    let (input_space, target_concentration) = setup_fluids(&manifest.setup)?;
+    let mixer_design = search_mixer_design(config, target_concentration, &input_space)?;
+    Ok(mixer_design)
+}
+
+/// Checks the design against the expectations of the manifest.
+pub fn check_expected(
+    expected: &Expected,
+    mixer_design: &MixerDesign,
+    target_concentration: &Concentration,
+) -> anyhow::Result<bool> {
+    Ok(unmet_expectations(expected, mixer_design, target_concentration)?.is_empty())
+}
+
+/// Expectations of the manifest the design does not meet, each along with the value found.
+pub fn unmet_expectations(
+    expected: &Expected,
+    mixer_design: &MixerDesign,
+    target_concentration: &Concentration,
+) -> anyhow::Result<Vec<String>> {
+    let mut unmet = vec![];
+    if let Some(mixer_sequence) = &expected.mixer_sequence {
+        // Compared as mixer expressions, so that the formatting of the manifest does not matter.
+        let test_design = mixer_design.mix_tree();
+        let matches =
+            Expr::parse(mixer_sequence).is_ok_and(|expected| test_design.to_expr() == expected);
+        if !matches {
+            unmet.push(format!(
+                "mixer-sequence: expected `{mixer_sequence}`, found `{test_design}`"
+            ));
+        }
+    }
+    let test_storage_units = mixer_design.storage_units_needed();
+    if let Some(storage_units) = expected.storage_units {
+        if storage_units != test_storage_units {
+            unmet.push(format!(
+                "storage-units: expected {storage_units}, found {test_storage_units}"
+            ));
+        }
+    }
+    if let Some(storage_units_max) = expected.storage_units_max {
+        if test_storage_units > storage_units_max {
+            unmet.push(format!(
+                "storage-units-max: expected at most {storage_units_max}, found {test_storage_units}"
+            ));
+        }
+    }
+    if let Some(mix_count_max) = expected.mix_count_max {
+        let mix_count = mixer_design.mix_count();
+        if mix_count > mix_count_max {
+            unmet.push(format!(
+                "mix-count-max: expected at most {mix_count_max}, found {mix_count}"
+            ));
+        }
+    }
+    if let Some(error_max) = &expected.error_max {
+        let error_max = validate_f64(error_max.parse::<f64>()?)?;
+        let error = design_error(mixer_design, target_concentration).abs();
+        if error > error_max {
+            unmet.push(format!(
+                "error-max: expected at most {error_max}, found {}",
+                Precision::default().format(error)
+            ));
+        }
+    }
+    Ok(unmet)
+}
+
+/// Difference between the concentration the design produces and the target concentration.
+pub fn design_error(mixer_design: &MixerDesign, target_concentration: &Concentration) -> f64 {
+    let produced = mixer_design.certificate().result.concentration;
+    (produced - Rational::from(target_concentration)).into()
+}
+
+/// Checks the invariants every design must hold, regardless of the expectations of a test: its
+/// certificate verifies, its expression evaluates to a fluid, its leaves are drawn from the input
+/// fluids and the flat ir conserves the stored volume. Returns the violated invariant.
+pub fn check_invariants(
+    mixer_design: &MixerDesign,
+    input_space: &InputSpace,
+) -> Result<(), String> {
+    let certificate = mixer_design.certificate();
+    certificate
+        .verify()
+        .map_err(|err| format!("certificate does not verify: {err}"))?;
+    let produced = Fluid::try_from(mixer_design.expr())
+        .map_err(|err| format!("design does not evaluate: {err}"))?;
+    for leaf in &certificate.leaves {
+        let drawn = input_space
+            .iter()
+            .any(|input| Rational::from(input.concentration()) == leaf.concentration);
+        if !drawn {
+            return Err(format!(
+                "leaf with concentration {} is not an input",
+                f64::from(leaf.concentration)
+            ));
+        }
+    }
+
+    let ir = mixer_design.ir();
+    let stored = ir
+        .iter()
+        .filter_map(|op| match op {
+            IROp::Store((Operand::Const(fluid), _)) => Some(fluid.unit_volume().clone()),
+            _ => None,
+        })
+        .fold(Volume::from(0.0), |total, volume| total + volume);
+    let output = ir.len().checked_sub(1);
+    let fluids = evaluate_ir(ir);
+    let mixed = output
+        .and_then(|output| fluids.get(&output))
+        .map(|fluid| fluid.unit_volume().clone());
+    if mixed.as_ref() != Some(&stored) || produced.unit_volume() != &stored {
+        return Err(format!(
+            "volume is not conserved: {stored} units are stored, the ir produces {} and the design {}",
+            mixed.map_or("nothing".to_string(), |volume| volume.to_string()),
+            produced.unit_volume()
+        ));
+    }
+    Ok(())
+}
 
 pub async fn run_saturation(
     manifest: &TestManifest,
     config: Config,
-) -> anyhow::Result<(bool, String)> {
-    let (result, output) = run_and_capture_output(|| async {
-        let setup = &manifest.setup;
-        let expected = &manifest.expected;
-        let input_fluids = setup
-            .input
-            .values()
-            .map(|input_fluid| {
-                let fluid_str = format!(
-                    "(fluid {} {})",
-                    input_fluid.concentration, input_fluid.volume
-                );
-                // Convert the error into anyhow error.
-                Fluid::from_str(&fluid_str).map_err(|err| err.into())
-            })
-            .collect::<anyhow::Result<Vec<Fluid>>>()?;
-        let target_fluids = setup
-            .target
-            .values()
-            .map(|input_fluid| {
-                let fluid_str = format!(
-                    "(fluid {} {})",
-                    input_fluid.concentration, input_fluid.volume
-                );
-                // Convert the error into anyhow error.
-                Fluid::from_str(&fluid_str).map_err(|err| err.into())
-            })
-            .collect::<anyhow::Result<Vec<Fluid>>>()?;
-
-        let target_concentration = target_fluids[0].concentration().clone();
-        let mixer_design =
-            search_mixer_design(config, target_concentration, input_fluids.as_ref())?;
-
-        let mut result = true;
-        if let Some(mixer_sequence) = &expected.mixer_sequence {
-            let test_design = mixer_design.mixer_expr().to_string();
-            result &= *mixer_sequence == test_design;
-        }
-        if let Some(storage_units) = expected.storage_units {
-            let test_storage_units = mixer_design.storage_units_needed();
-            result &= storage_units == test_storage_units;
-        }
-
-        anyhow::Ok(result)
+) -> anyhow::Result<SaturationRun> {
+    let (result, mut output) = run_and_capture_output(|| async {
+        let (input_space, target_concentration) = setup_fluids(&manifest.setup)?;
+        let mixer_design = run_manifest(manifest, config)?;
+        check_invariants(&mixer_design, &input_space).map_err(|err| anyhow::anyhow!(err))?;
+        let explanation =
+            explain_unmet_expectations(&manifest.expected, &mixer_design, &target_concentration)?;
+        anyhow::Ok((explanation, mixer_design))
     })
     .await;
 
-    let run_result = result.unwrap_or_default();
-    Ok((run_result, output))
+    let (passed, design, explanation) = match result {
+        Ok((explanation, mixer_design)) => (explanation.is_none(), Some(mixer_design), explanation),
+        Err(err) => {
+            output.push_str(&format!("ERROR: {err}\n"));
+            (false, None, None)
+        }
+    };
+    Ok(SaturationRun {
+        passed,
+        output,
+        design,
+        explanation,
+    })
 }
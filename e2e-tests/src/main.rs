@@ -1,25 +1,59 @@
 mod cli;
+mod explain;
+mod gallery;
 mod harness;
 mod manifest;
+mod minimize;
 mod run;
+mod shrink;
+mod soak;
+mod stress;
 mod util;
 
 use clap::Parser;
-use cli::{FilterConfig, RunConfig};
+use cli::{Command, DiscoveryConfig, FilterConfig, MinimizeConfig, RunConfig};
+use minimize::run_minimize;
 use run::run;
+use soak::run_soak;
+use stress::run_stress;
+use util::default_tests_dir;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = cli::Args::parse();
+    let soak_config = match args.command {
+        Some(Command::Stress(stress_config)) => return run_stress(&stress_config),
+        Some(Command::Soak(soak_config)) => Some(soak_config),
+        None => None,
+    };
+    let discovery_config = DiscoveryConfig {
+        roots: if args.tests_dir.is_empty() {
+            vec![default_tests_dir()]
+        } else {
+            args.tests_dir
+        },
+        ignore: args.ignore,
+    };
+    if let Some(test) = args.minimize {
+        let minimize_config = MinimizeConfig {
+            test,
+            minimized_dir: args.minimized_dir,
+        };
+        return run_minimize(&minimize_config, &discovery_config);
+    }
     let filter_config = FilterConfig {
         include: args.include,
         exclude: args.exclude,
     };
+    if let Some(soak_config) = soak_config {
+        return run_soak(&soak_config, &discovery_config, &filter_config);
+    }
     let run_config = RunConfig {
         verbose: args.verbose,
+        gallery: args.gallery,
     };
 
-    run(&run_config, &filter_config).await?;
+    run(&run_config, &discovery_config, &filter_config).await?;
 
     Ok(())
 }
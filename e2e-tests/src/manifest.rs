@@ -1,21 +1,34 @@
 //! Defines test.toml for fluido e2e-tests.
 //! test.toml describes how an e2e test should be conducted, some fields are:
+//! - `disabled` -- skips the test.
+//! - `expected-failure` -- records the test of a known bug, which is expected to fail. An unexpected
+//!   pass fails the suite so that the test is re-enabled once the bug is fixed.
+//! - `reason` -- why the test is expected to fail, required along with `expected-failure`.
 //! - `[metadata]` - this table contains metadata information, information that does not effect the result of the test but gives us information about the test.
 //!   - `name`: Name of the test
 //! - `[setup]` -- this table contains state of the environment before the test begins.
 //!   - `[input-fluids]` -- set of fluids that are present at the beginning of the test.
 //!     - 'fluid-name = { concentration = "", unit_volume = "" }'* - each fluid can be described as a `value`.
-//!   `saturation-time` -- saturation time limit is for ending the saturation at specific time limit.``
-//!   `saturation-node-count` -- saturation node count is the limit for ending the saturation at specific node count.
-//!   `saturation-iter-limit` -- saturation iter limit is the limit for ending the saturation at specific iteration count.
+//!       An optional `tier = "abundant" | "limited" | "scarce"` tells how much of the input is at hand.
+//!   - `saturation-time` -- saturation time limit is for ending the saturation at specific time limit.``
+//!   - `saturation-node-count` -- saturation node count is the limit for ending the saturation at specific node count.
+//!   - `saturation-iter-limit` -- saturation iter limit is the limit for ending the saturation at specific iteration count.
 //! - `[output]` -- set of fluids that we expect to find in the output.
 //!   - 'fluid-name = { concentration = "", unit_volume = "" }'* - each fluid can be described as a `value`.
+//! - `[expected]` -- expectations the produced design is checked against, all of them are optional.
+//!   - `mixer-sequence` -- exact mixer expression.
+//!   - `storage-units` -- exact number of storage units.
+//!   - `storage-units-max` -- upper bound for the number of storage units.
+//!   - `mix-count-max` -- upper bound for the number of mix operations.
+//!   - `error-max` -- upper bound for the absolute difference between produced and target concentration.
 use std::{
     collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
 
+use fluido_core::InputTier;
+use fluido_types::suggest::{closest_match, did_you_mean};
 use serde::{Deserialize, Serialize};
 
 /// Root level struct for describing the `test.toml`
@@ -26,6 +39,12 @@ pub struct TestManifest {
     pub setup: Setup,
     #[serde(default)]
     pub disabled: bool,
+    /// Whether the test is expected to fail, i.e because of a known bug.
+    #[serde(default)]
+    pub expected_failure: bool,
+    /// Why the test is expected to fail.
+    #[serde(default)]
+    pub reason: Option<String>,
     /// Time limit in seconds.
     pub time_limit: u64,
     pub expected: Expected,
@@ -46,6 +65,12 @@ pub struct Metadata {
 pub struct Setup {
     pub input: BTreeMap<String, TestFluid>,
     pub target: BTreeMap<String, TestFluid>,
+    /// Number of e-nodes the saturation stops at.
+    #[serde(default)]
+    pub saturation_node_count: Option<usize>,
+    /// Number of iterations the saturation stops after.
+    #[serde(default)]
+    pub saturation_iter_limit: Option<usize>,
 }
 
 /// Describes the test fluid values in the manifest file.
@@ -55,14 +80,20 @@ pub struct Setup {
 pub struct TestFluid {
     pub concentration: String,
     pub volume: String,
+    /// How much of the fluid is at hand, only meaningful for inputs.
+    #[serde(default)]
+    pub tier: Option<InputTier>,
 }
 
 /// Describes the expected results of a test.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Expected {
     pub mixer_sequence: Option<String>,
     pub storage_units: Option<u64>,
+    pub storage_units_max: Option<u64>,
+    pub mix_count_max: Option<usize>,
+    pub error_max: Option<String>,
 }
 
 /// A specific instance of a `TestManifest` from disk.
@@ -78,17 +109,88 @@ impl TestManifest {
         let manifest_str = fs::read_to_string(path)
             .map_err(|e| anyhow::anyhow!("failed to read manifest at {:?}: {}", path, e))?;
         let toml_de = toml::de::Deserializer::new(&manifest_str);
-        let manifest: Self = serde_ignored::deserialize(toml_de, |path| {
-            let warning = format!("unused manifest key: {path}");
-            warnings.push(warning);
-        })
-        .map_err(|e| anyhow::anyhow!("failed to parse manifest: {}.", e))?;
+        let manifest: Result<Self, _> = serde_ignored::deserialize(toml_de, |path| {
+            warnings.push(unused_key_warning(&path.to_string()));
+        });
+        // A misspelled key often leaves a required one missing, so unused keys are listed along
+        // with the error.
+        let manifest = manifest.map_err(|e| {
+            let unused_keys = warnings
+                .iter()
+                .map(|warning| format!("\n{warning}"))
+                .collect::<String>();
+            let e = e.to_string();
+            anyhow::anyhow!("failed to parse manifest: {}.{unused_keys}", e.trim_end())
+        })?;
         for warning in warnings {
             // TODO: print this yellow.
             println!("WARNING: {}", warning);
         }
+        let has_reason = manifest
+            .reason
+            .as_ref()
+            .is_some_and(|reason| !reason.trim().is_empty());
+        if manifest.expected_failure && !has_reason {
+            anyhow::bail!("manifest expects the test to fail without a `reason`");
+        }
         Ok(manifest)
     }
+
+    /// Writes the manifest as `<dir>/<name>/test.toml`, with the comment on top of it.
+    pub fn write_to_dir(&self, dir: &Path, comment: &str) -> anyhow::Result<PathBuf> {
+        let comment = comment
+            .lines()
+            .map(|line| format!("# {line}\n"))
+            .collect::<String>();
+        let dir = dir.join(&self.metadata.name);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("test.toml");
+        fs::write(&path, comment + &toml::to_string(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Keys of the table at the path, for suggesting the key meant by an unused one.
+fn known_keys(table: &[&str]) -> &'static [&'static str] {
+    match table {
+        [] => &[
+            "metadata",
+            "setup",
+            "disabled",
+            "expected-failure",
+            "reason",
+            "time-limit",
+            "expected",
+        ],
+        ["metadata"] => &["name"],
+        ["setup"] => &[
+            "input",
+            "target",
+            "saturation-node-count",
+            "saturation-iter-limit",
+        ],
+        ["setup", "input" | "target", _] => &["concentration", "volume", "tier"],
+        ["expected"] => &[
+            "mixer-sequence",
+            "storage-units",
+            "storage-units-max",
+            "mix-count-max",
+            "error-max",
+        ],
+        _ => &[],
+    }
+}
+
+/// Warning for an unused key of the manifest, suggesting the key of its table it is closest to.
+fn unused_key_warning(path: &str) -> String {
+    let segments = path.split('.').collect::<Vec<_>>();
+    let suggestion = segments.split_last().and_then(|(key, table)| {
+        closest_match(key, known_keys(table).iter().copied()).map(ToString::to_string)
+    });
+    format!(
+        "unused manifest key: `{path}`.{}",
+        did_you_mean(&suggestion)
+    )
 }
 
 impl TestManifestFile {
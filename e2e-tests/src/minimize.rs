@@ -0,0 +1,152 @@
+//! Minimizing a failing test into the smallest manifest failing the same way, for bug reports.
+//!
+//! The input space and the time limit of the manifest are shrunk one step at a time, each step
+//! kept as long as the test keeps failing the same way as it did at first.
+use std::panic;
+
+use crate::{
+    cli::{DiscoveryConfig, MinimizeConfig},
+    harness::{self, Failure},
+    manifest::TestManifest,
+    shrink::shrink,
+    util::discover_test_configs,
+};
+
+/// Upper bound for the number of test runs spent on minimizing.
+const MAX_MINIMIZE_RUNS: usize = 64;
+
+/// Minimizes the failing test with the given name and writes the minimized manifest.
+pub fn run_minimize(
+    minimize_config: &MinimizeConfig,
+    discovery_config: &DiscoveryConfig,
+) -> anyhow::Result<()> {
+    let test_manifest = discover_test_configs(&discovery_config.roots, &discovery_config.ignore)?
+        .into_iter()
+        .map(|test_file| test_file.test_manifest)
+        .find(|test_manifest| test_manifest.metadata.name == minimize_config.test)
+        .ok_or_else(|| anyhow::anyhow!("no test named `{}` found", minimize_config.test))?;
+
+    // Panics are reported as failures of the test instead.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let minimized = minimize(test_manifest);
+    panic::set_hook(default_hook);
+    let Some((mut test_manifest, failure)) = minimized? else {
+        println!("Test {} passes, nothing to minimize.", minimize_config.test);
+        return Ok(());
+    };
+
+    test_manifest.metadata.name = format!("{}_minimized", minimize_config.test);
+    let comment = format!("Minimized from {}, {failure}", minimize_config.test);
+    let path = test_manifest.write_to_dir(&minimize_config.minimized_dir, &comment)?;
+    println!("Test {} {failure}", minimize_config.test);
+    println!(
+        "Minimized to {} inputs and a time limit of {} seconds, written to {}",
+        test_manifest.setup.input.len(),
+        test_manifest.time_limit,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Shrinks the manifest while it keeps failing the same way, `None` if it does not fail.
+fn minimize(test_manifest: TestManifest) -> anyhow::Result<Option<(TestManifest, Failure)>> {
+    let Some(failure) = harness::failure_of(&test_manifest)? else {
+        return Ok(None);
+    };
+    let minimized = shrink(
+        test_manifest,
+        failure,
+        MAX_MINIMIZE_RUNS,
+        shrink_candidates,
+        harness::failure_of,
+    )?;
+    Ok(Some(minimized))
+}
+
+/// Manifests with an input less, or with a shorter time limit. Cheaper candidates come first, so
+/// that shrinking spends its runs on quick searches.
+fn shrink_candidates(test_manifest: &TestManifest) -> Vec<TestManifest> {
+    let mut candidates = vec![];
+    let time_limit = test_manifest.time_limit;
+    for time_limit in [0, time_limit / 2, time_limit.saturating_sub(1)] {
+        if time_limit < test_manifest.time_limit
+            && candidates
+                .iter()
+                .all(|candidate: &TestManifest| candidate.time_limit != time_limit)
+        {
+            let mut candidate = test_manifest.clone();
+            candidate.time_limit = time_limit;
+            candidates.push(candidate);
+        }
+    }
+    if test_manifest.setup.input.len() > 1 {
+        for name in test_manifest.setup.input.keys() {
+            let mut candidate = test_manifest.clone();
+            candidate.setup.input.remove(name);
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shrink_candidates;
+    use crate::{harness::Failure, manifest::TestManifest, shrink::shrink};
+
+    fn manifest() -> TestManifest {
+        toml::from_str(
+            r#"
+            time-limit = 10
+
+            [metadata]
+            name = "failing"
+
+            [setup.input]
+            buffer = { concentration = "0.0", volume = "1" }
+            stock = { concentration = "0.4", volume = "1" }
+            water = { concentration = "0.0", volume = "1" }
+
+            [setup.target]
+            target = { concentration = "0.1", volume = "1" }
+
+            [expected]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn candidates_have_an_input_less_or_a_shorter_time_limit() {
+        let candidates = |manifest: &TestManifest| {
+            shrink_candidates(manifest)
+                .iter()
+                .map(|candidate| (candidate.time_limit, candidate.setup.input.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            candidates(&manifest()),
+            [(0, 3), (5, 3), (9, 3), (10, 2), (10, 2), (10, 2)]
+        );
+
+        // The last input and a time limit of zero are kept.
+        let mut minimal = manifest();
+        minimal.time_limit = 0;
+        minimal.setup.input.retain(|name, _| name == "stock");
+        assert!(shrink_candidates(&minimal).is_empty());
+    }
+
+    #[test]
+    fn manifests_are_minimized_to_a_fixpoint() {
+        // Fails as long as the stock is an input and the search has at least 3 seconds.
+        let run = |manifest: &TestManifest| {
+            let fails = manifest.setup.input.contains_key("stock") && manifest.time_limit >= 3;
+            Ok(fails.then(|| Failure::Error("unreachable".to_string())))
+        };
+        let failure = Failure::Error("unreachable".to_string());
+        let (minimized, _) = shrink(manifest(), failure, 64, shrink_candidates, run).unwrap();
+        assert_eq!(minimized.time_limit, 3);
+        assert_eq!(minimized.setup.input.keys().collect::<Vec<_>>(), ["stock"]);
+    }
+}
@@ -4,15 +4,20 @@ use std::{
 };
 
 use crate::{
-    cli::{FilterConfig, RunConfig},
-    harness,
+    cli::{DiscoveryConfig, FilterConfig, RunConfig},
+    gallery::{write_gallery, GalleryEntry},
+    harness::{self, SaturationRun},
     util::{discover_test_configs, VecExt},
 };
 use colored::Colorize;
-use fluido_core::{Config, LogConfig, MixerGenerationConfig, MixerGenerator};
 
-pub async fn run(run_config: &RunConfig, filter_config: &FilterConfig) -> anyhow::Result<()> {
-    let mut discovered_tests = discover_test_configs()?;
+pub async fn run(
+    run_config: &RunConfig,
+    discovery_config: &DiscoveryConfig,
+    filter_config: &FilterConfig,
+) -> anyhow::Result<()> {
+    let mut discovered_tests =
+        discover_test_configs(&discovery_config.roots, &discovery_config.ignore)?;
     let total_test_count = discovered_tests.len();
     let included_tests = filter_config
         .include
@@ -33,6 +38,10 @@ pub async fn run(run_config: &RunConfig, filter_config: &FilterConfig) -> anyhow
 
     let mut number_of_tests_executed = 0;
     let mut number_of_tests_failed = 0;
+    let mut number_of_expected_failures = 0;
+    let mut number_of_unexpected_passes = 0;
+
+    let mut designs = vec![];
 
     let instant = Instant::now();
     for test_file in discovered_tests.iter() {
@@ -41,29 +50,57 @@ pub async fn run(run_config: &RunConfig, filter_config: &FilterConfig) -> anyhow
         print!("Testing {}...", test_manifest.metadata.name);
         stdout().flush().unwrap();
 
-        let time_limit = test_manifest.time_limit;
-        // TODO: expose this to the test toml.
-        let mixer_generator = MixerGenerator::EqualitySaturation;
-        let mixer_config = MixerGenerationConfig::new(time_limit, mixer_generator);
-        // TODO: expose extra logging steps to the test toml.
-        let logging = LogConfig::silent();
-        let config = Config::new(mixer_config, logging);
+        let config = harness::test_config(test_manifest)?;
         // Runs the search_mixer_design routine with test setup
-        let (result, output) = harness::run_saturation(test_manifest, config).await?;
-        if !result {
-            number_of_tests_failed += 1;
-            println!("{}", "FAILED".red());
-        } else {
-            println!("{}", "ok".green());
+        let SaturationRun {
+            passed,
+            output,
+            design,
+            explanation,
+        } = harness::run_saturation(test_manifest, config).await?;
+        let reason = test_manifest.reason.as_deref().unwrap_or_default();
+        match (passed, test_manifest.expected_failure) {
+            (true, false) => println!("{}", "ok".green()),
+            (false, false) => {
+                number_of_tests_failed += 1;
+                println!("{}", "FAILED".red());
+                if let Some(explanation) = explanation {
+                    for line in explanation.lines() {
+                        println!("    {line}");
+                    }
+                }
+            }
+            (false, true) => {
+                number_of_expected_failures += 1;
+                println!("{} ({reason})", "XFAIL".yellow());
+            }
+            // The bug is fixed, the test should no longer be expected to fail.
+            (true, true) => {
+                number_of_unexpected_passes += 1;
+                println!("{} ({reason})", "XPASS".red());
+                println!("    passed although expected to fail, remove `expected-failure` from its manifest");
+            }
         }
         if run_config.verbose {
             println!("--- OUTPUT ---");
             println!("{output}");
         }
         number_of_tests_executed += 1;
+        if let Some(design) = design {
+            designs.push((test_manifest, design));
+        }
     }
     let duration = instant.elapsed();
 
+    if let Some(gallery_dir) = &run_config.gallery {
+        let entries = designs
+            .iter()
+            .map(|(manifest, design)| GalleryEntry { manifest, design })
+            .collect::<Vec<_>>();
+        write_gallery(&entries, gallery_dir)?;
+        println!("Wrote example gallery to {}", gallery_dir.display());
+    }
+
     if number_of_tests_executed == 0 {
         if let Some(include) = &filter_config.include {
             println!(
@@ -89,22 +126,29 @@ pub async fn run(run_config: &RunConfig, filter_config: &FilterConfig) -> anyhow
     } else {
         println!("_________________________________");
         println!(
-            "Fluido test results: {}. {} total, {} passed, {} failed; {} disabled [test duration: {} seconds]",
-            if number_of_tests_failed == 0 {
+            "Fluido test results: {}. {} total, {} passed, {} failed, {} expected failures, {} unexpected passes; {} disabled [test duration: {} seconds]",
+            if number_of_tests_failed + number_of_unexpected_passes == 0 {
                 "ok".green().bold()
             }else {
                 "failed".red().bold()
             },
             total_test_count,
-            number_of_tests_executed - number_of_tests_failed,
+            number_of_tests_executed
+                - number_of_tests_failed
+                - number_of_expected_failures
+                - number_of_unexpected_passes,
             number_of_tests_failed,
+            number_of_expected_failures,
+            number_of_unexpected_passes,
             disabled_tests.len(),
             duration.as_secs()
         )
     }
-    if number_of_tests_failed == 0 {
-        Ok(())
-    } else {
+    if number_of_tests_failed > 0 {
         anyhow::bail!("there are failing tests")
+    } else if number_of_unexpected_passes > 0 {
+        anyhow::bail!("there are tests passing although expected to fail")
+    } else {
+        Ok(())
     }
 }
@@ -0,0 +1,82 @@
+//! Greedy shrinking of failing cases, shared by stress testing and minimizing failing tests.
+use std::mem::discriminant;
+
+use crate::harness::Failure;
+
+/// Greedily replaces the case with the first of its candidates which keeps failing the same way,
+/// i.e panicking cases are only shrunk into panicking ones, until none does or `max_runs` cases
+/// were run.
+pub fn shrink<T>(
+    mut case: T,
+    mut failure: Failure,
+    max_runs: usize,
+    candidates: impl Fn(&T) -> Vec<T>,
+    mut run: impl FnMut(&T) -> anyhow::Result<Option<Failure>>,
+) -> anyhow::Result<(T, Failure)> {
+    let mut runs = 0;
+    'shrinking: while runs < max_runs {
+        for candidate in candidates(&case) {
+            if runs == max_runs {
+                break 'shrinking;
+            }
+            runs += 1;
+            if let Some(candidate_failure) = run(&candidate)? {
+                if discriminant(&candidate_failure) == discriminant(&failure) {
+                    case = candidate;
+                    failure = candidate_failure;
+                    continue 'shrinking;
+                }
+            }
+        }
+        break;
+    }
+    Ok((case, failure))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::shrink;
+    use crate::harness::Failure;
+
+    /// Cases of at least 5 panic, 3 and 4 fail with an error and smaller ones pass.
+    fn run(case: &u32) -> anyhow::Result<Option<Failure>> {
+        Ok(match case {
+            5.. => Some(Failure::Panic(format!("{case}"))),
+            3 | 4 => Some(Failure::Error(format!("{case}"))),
+            _ => None,
+        })
+    }
+
+    fn candidates(case: &u32) -> Vec<u32> {
+        [case / 2, case.saturating_sub(1)]
+            .into_iter()
+            .filter(|candidate| candidate < case)
+            .collect()
+    }
+
+    #[test]
+    fn cases_shrink_into_the_smallest_failing_the_same_way() {
+        let (case, failure) = shrink(40, run(&40).unwrap().unwrap(), 64, candidates, run).unwrap();
+        // 4 fails too, but with an error rather than a panic.
+        assert_eq!(case, 5);
+        assert!(matches!(failure, Failure::Panic(message) if message == "5"));
+
+        // Shrinking stops at the fixpoint, without running candidates of it again.
+        let mut runs = 0;
+        let counted = |case: &u32| {
+            runs += 1;
+            run(case)
+        };
+        let (case, _) = shrink(5, run(&5).unwrap().unwrap(), 64, candidates, counted).unwrap();
+        assert_eq!((case, runs), (5, 2));
+    }
+
+    #[test]
+    fn shrinking_stops_after_its_runs() {
+        // 40 shrinks to 20 and 10 in its first two runs.
+        let (case, _) = shrink(40, run(&40).unwrap().unwrap(), 2, candidates, run).unwrap();
+        assert_eq!(case, 10);
+        let (case, _) = shrink(40, run(&40).unwrap().unwrap(), 0, candidates, run).unwrap();
+        assert_eq!(case, 40);
+    }
+}
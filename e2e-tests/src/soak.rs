@@ -0,0 +1,246 @@
+//! Long running soak testing, looping the selected tests to catch leaks and rare failures before
+//! releases.
+//!
+//! Each iteration draws one of the tests and a time limit for it from a seeded generator, and runs
+//! it without its expectations, as they only hold for the time limit of the manifest. As in stress
+//! testing, a panic, an internal error or an invalid design is a failure, while not finding a
+//! design in time is a miss. The memory usage of the process is sampled after each report
+//! interval, so that memory growing with the number of searches points to a leak in the e-graph
+//! or z3 layers. Growth is measured from the first sample, as the first searches also pay for
+//! allocations made once per process.
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{stdout, Write},
+    panic,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    cli::{DiscoveryConfig, FilterConfig, SoakConfig},
+    harness::{self, Failure},
+    manifest::Expected,
+    util::discover_test_configs,
+};
+
+/// Outcomes of the iterations running a single test.
+#[derive(Debug, Clone, Default)]
+struct TestOutcomes {
+    runs: usize,
+    failures: usize,
+    misses: usize,
+}
+
+/// Counts of the iterations since the start of the soak, or since the last report.
+#[derive(Debug, Clone, Copy, Default)]
+struct Window {
+    runs: usize,
+    failures: usize,
+    misses: usize,
+}
+
+impl Window {
+    fn failure_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.runs as f64
+        }
+    }
+}
+
+pub fn run_soak(
+    soak_config: &SoakConfig,
+    discovery_config: &DiscoveryConfig,
+    filter_config: &FilterConfig,
+) -> anyhow::Result<()> {
+    let mut tests = discover_test_configs(&discovery_config.roots, &discovery_config.ignore)?;
+    tests.retain(|test| {
+        let name = &test.test_manifest.metadata.name;
+        !test.test_manifest.disabled
+            && !test.test_manifest.expected_failure
+            && filter_config
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(name))
+            && filter_config
+                .exclude
+                .as_ref()
+                .is_none_or(|exclude| !exclude.is_match(name))
+    });
+    if tests.is_empty() {
+        anyhow::bail!("no test to soak");
+    }
+    let min_time_limit = soak_config.min_time_limit.max(1);
+    let max_time_limit = soak_config.max_time_limit.max(min_time_limit);
+    println!(
+        "Soaking {} tests for {} seconds with seed {}, time limits of {min_time_limit}-{max_time_limit} seconds.",
+        tests.len(),
+        soak_config.duration,
+        soak_config.seed
+    );
+
+    let mut rng = StdRng::seed_from_u64(soak_config.seed);
+    // Panics are reported as failures of their iteration instead.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let start = Instant::now();
+    let duration = Duration::from_secs(soak_config.duration);
+    let report_interval = Duration::from_secs(soak_config.report_interval.max(1));
+    let initial_rss = resident_memory();
+    let mut peak_rss = initial_rss;
+    // Taken after the first report interval, once the process is warmed up.
+    let mut baseline_rss = None;
+    let mut outcomes: BTreeMap<String, TestOutcomes> = BTreeMap::new();
+    let mut total = Window::default();
+    let mut window = Window::default();
+    let mut last_report = start;
+    let mut iteration = 0;
+    while start.elapsed() < duration {
+        let test = &tests[rng.gen_range(0..tests.len())];
+        let time_limit = rng.gen_range(min_time_limit..=max_time_limit);
+        let mut manifest = test.test_manifest.clone();
+        manifest.time_limit = time_limit;
+        manifest.expected = Expected::default();
+        let name = manifest.metadata.name.clone();
+
+        let failure = harness::failure_of(&manifest)?;
+        let test_outcomes = outcomes.entry(name.clone()).or_default();
+        test_outcomes.runs += 1;
+        window.runs += 1;
+        match failure {
+            None => {}
+            Some(Failure::NotFound(_)) => {
+                test_outcomes.misses += 1;
+                window.misses += 1;
+            }
+            Some(failure) => {
+                test_outcomes.failures += 1;
+                window.failures += 1;
+                println!(
+                    "Iteration {iteration}, {name} with a time limit of {time_limit} seconds: {}",
+                    "FAILED".red()
+                );
+                println!("  {failure}");
+                manifest.metadata.name = format!("soak_{}_{iteration}_{name}", soak_config.seed);
+                let path = manifest.write_to_dir(&soak_config.crashes_dir, &failure.to_string())?;
+                println!("  reproducer written to {}", path.display());
+            }
+        }
+        iteration += 1;
+
+        if last_report.elapsed() >= report_interval {
+            let rss = resident_memory();
+            peak_rss = peak_rss.max(rss);
+            baseline_rss = baseline_rss.or(rss);
+            report(start.elapsed(), &window, rss, initial_rss);
+            total.runs += window.runs;
+            total.failures += window.failures;
+            total.misses += window.misses;
+            window = Window::default();
+            last_report = Instant::now();
+        }
+    }
+    panic::set_hook(default_hook);
+    total.runs += window.runs;
+    total.failures += window.failures;
+    total.misses += window.misses;
+    let final_rss = resident_memory();
+    peak_rss = peak_rss.max(final_rss);
+
+    println!("_________________________________");
+    for (name, test_outcomes) in outcomes
+        .iter()
+        .filter(|(_, test_outcomes)| test_outcomes.failures + test_outcomes.misses > 0)
+    {
+        println!(
+            "{name}: {} runs, {} failed, {} found no design in time",
+            test_outcomes.runs, test_outcomes.failures, test_outcomes.misses
+        );
+    }
+    if let (Some(initial_rss), Some(final_rss), Some(peak_rss)) = (initial_rss, final_rss, peak_rss)
+    {
+        println!(
+            "Memory: {} at the start, {} after warming up, {} at the end, {} at peak",
+            format_bytes(initial_rss),
+            format_bytes(baseline_rss.unwrap_or(initial_rss)),
+            format_bytes(final_rss),
+            format_bytes(peak_rss)
+        );
+    }
+    let growth = match (baseline_rss.or(initial_rss), final_rss) {
+        (Some(baseline_rss), Some(final_rss)) => final_rss.saturating_sub(baseline_rss),
+        _ => 0,
+    };
+    let leaked = soak_config
+        .max_memory_growth
+        .is_some_and(|max_growth| growth > max_growth * 1024 * 1024);
+    println!(
+        "Fluido soak results: {}. {} runs, {} failed ({:.2}%), {} found no design in time [soak duration: {} seconds]",
+        if total.failures == 0 && !leaked {
+            "ok".green().bold()
+        } else {
+            "failed".red().bold()
+        },
+        total.runs,
+        total.failures,
+        total.failure_rate() * 100.0,
+        total.misses,
+        start.elapsed().as_secs()
+    );
+    if total.failures > 0 {
+        anyhow::bail!("there are failing soak iterations")
+    }
+    if leaked {
+        anyhow::bail!(
+            "memory grew by {}, more than `--max-memory-growth`",
+            format_bytes(growth)
+        )
+    }
+    Ok(())
+}
+
+/// Prints the outcomes of the iterations since the last report, along with the memory in use.
+fn report(elapsed: Duration, window: &Window, rss: Option<u64>, initial_rss: Option<u64>) {
+    let memory = match (rss, initial_rss) {
+        (Some(rss), Some(initial_rss)) => format!(
+            "{} resident ({}{})",
+            format_bytes(rss),
+            if rss >= initial_rss { "+" } else { "-" },
+            format_bytes(rss.abs_diff(initial_rss))
+        ),
+        _ => "memory usage unavailable".to_string(),
+    };
+    println!(
+        "[{:>6}s] {} runs, {} failed ({:.2}%), {} found no design in time, {memory}",
+        elapsed.as_secs(),
+        window.runs,
+        window.failures,
+        window.failure_rate() * 100.0,
+        window.misses
+    );
+    stdout().flush().unwrap();
+}
+
+/// Resident memory of the process in bytes. Only available on Linux, where it is read from
+/// `/proc`.
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,257 @@
+//! Stress testing of the full pipeline with random input spaces and targets.
+//!
+//! Each case searches a design with a small time budget. A panic, an internal error or a design
+//! violating one of the invariants of `harness::check_invariants` is a failure. Failing cases are
+//! shrunk while they keep failing the same way, and written as reproducer manifests which the test
+//! runner picks up with `--tests-dir`.
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{stdout, Write},
+    panic,
+};
+
+use colored::Colorize;
+use fluido_core::benchmark::random_reachable_targets;
+use fluido_types::{fluid::Concentration, input_space::InputSpace};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    cli::StressConfig,
+    harness::{self, Failure},
+    manifest::{Expected, Metadata, Setup, TestFluid, TestManifest},
+    shrink::shrink,
+};
+
+/// Concentrations of the inputs are multiples of this.
+const CONCENTRATION_STEP: f64 = 0.01;
+/// Upper bound for the number of searches spent on shrinking a single failing case.
+const MAX_SHRINK_RUNS: usize = 32;
+
+/// A single search of the stress run.
+#[derive(Debug, Clone, PartialEq)]
+struct StressCase {
+    inputs: Vec<Concentration>,
+    target: Concentration,
+}
+
+impl Display for StressCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "target {} from [{inputs}]", self.target)
+    }
+}
+
+/// Runs random cases, shrinking and writing a reproducer for each failing one.
+pub fn run_stress(stress_config: &StressConfig) -> anyhow::Result<()> {
+    let mut rng = StdRng::seed_from_u64(stress_config.seed);
+    // Panics are reported as failures of their case instead.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut number_of_failures = 0;
+    let mut number_of_cases = 0;
+    for case_ix in 0..stress_config.cases {
+        let Some(case) = random_case(&mut rng, stress_config.max_inputs) else {
+            continue;
+        };
+        number_of_cases += 1;
+        print!("Case {case_ix}, {case}...");
+        stdout().flush().unwrap();
+        let name = format!("stress_{}_{case_ix}", stress_config.seed);
+        let run = |case: &StressCase| run_case(&case.manifest(&name, stress_config.time_limit));
+        let Some(failure) = run(&case)? else {
+            println!("{}", "ok".green());
+            continue;
+        };
+        number_of_failures += 1;
+        println!("{}", "FAILED".red());
+        let (case, failure) = shrink(case, failure, MAX_SHRINK_RUNS, shrink_candidates, run)?;
+        let path = case
+            .manifest(&name, stress_config.time_limit)
+            .write_to_dir(&stress_config.crashes_dir, &failure.to_string())?;
+        println!("  {failure}");
+        println!(
+            "  shrunk to {case}, reproducer written to {}",
+            path.display()
+        );
+    }
+    panic::set_hook(default_hook);
+
+    println!("_________________________________");
+    println!(
+        "Fluido stress results: {}. {} cases, {} failed",
+        if number_of_failures == 0 {
+            "ok".green().bold()
+        } else {
+            "failed".red().bold()
+        },
+        number_of_cases,
+        number_of_failures
+    );
+    if number_of_failures == 0 {
+        Ok(())
+    } else {
+        anyhow::bail!("there are failing stress cases")
+    }
+}
+
+/// Draws distinct input concentrations and a target reachable from them, `None` if the inputs
+/// cannot reach any target.
+fn random_case(rng: &mut StdRng, max_inputs: usize) -> Option<StressCase> {
+    let steps = (1.0 / CONCENTRATION_STEP) as u32;
+    let mut candidates = (0..=steps).collect::<Vec<_>>();
+    candidates.shuffle(rng);
+    let input_count = rng.gen_range(2..=max_inputs.max(2));
+    let inputs = candidates[..input_count]
+        .iter()
+        .map(|step| Concentration::from(*step as f64 * CONCENTRATION_STEP))
+        .collect::<Vec<_>>();
+    let target = random_reachable_targets(&input_space(&inputs), 1, rng.gen()).pop()?;
+    Some(StressCase { inputs, target })
+}
+
+fn input_space(inputs: &[Concentration]) -> InputSpace {
+    InputSpace::from_concentrations(inputs.iter().cloned())
+}
+
+/// Runs the manifest of a case. Small budgets are expected to miss designs, so only errors,
+/// panics and invalid designs are failures.
+fn run_case(manifest: &TestManifest) -> anyhow::Result<Option<Failure>> {
+    let failure = harness::failure_of(manifest)?;
+    Ok(failure.filter(|failure| !matches!(failure, Failure::NotFound(_))))
+}
+
+/// Simpler cases than the given one: with an input less, or with an input or the target given
+/// with fewer decimals.
+fn shrink_candidates(case: &StressCase) -> Vec<StressCase> {
+    let mut candidates = vec![];
+    if case.inputs.len() > 2 {
+        for ix in 0..case.inputs.len() {
+            let mut inputs = case.inputs.clone();
+            inputs.remove(ix);
+            candidates.push(StressCase {
+                inputs,
+                target: case.target.clone(),
+            });
+        }
+    }
+    for decimals in [1, 2] {
+        let scale = 10f64.powi(decimals);
+        let round = |concentration: &Concentration| {
+            let value: f64 = concentration.clone().into();
+            Concentration::from((value * scale).round() / scale)
+        };
+        let target = round(&case.target);
+        if target != case.target && !case.inputs.contains(&target) {
+            candidates.push(StressCase {
+                inputs: case.inputs.clone(),
+                target,
+            });
+        }
+        for ix in 0..case.inputs.len() {
+            let input = round(&case.inputs[ix]);
+            if input != case.inputs[ix] && !case.inputs.contains(&input) && input != case.target {
+                let mut inputs = case.inputs.clone();
+                inputs[ix] = input;
+                candidates.push(StressCase {
+                    inputs,
+                    target: case.target.clone(),
+                });
+            }
+        }
+    }
+    candidates
+}
+
+impl StressCase {
+    /// The case as a test manifest without expectations, so that only invalid designs fail it.
+    fn manifest(&self, name: &str, time_limit: u64) -> TestManifest {
+        let test_fluid = |concentration: &Concentration| TestFluid {
+            concentration: concentration.to_string(),
+            volume: "1".to_string(),
+            tier: None,
+        };
+        TestManifest {
+            metadata: Metadata {
+                name: name.to_string(),
+            },
+            setup: Setup {
+                input: self
+                    .inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, input)| (format!("fluid-{ix}"), test_fluid(input)))
+                    .collect(),
+                target: BTreeMap::from([("target".to_string(), test_fluid(&self.target))]),
+                saturation_node_count: None,
+                saturation_iter_limit: None,
+            },
+            disabled: false,
+            expected_failure: false,
+            reason: None,
+            time_limit,
+            expected: Expected {
+                mixer_sequence: None,
+                storage_units: None,
+                storage_units_max: None,
+                mix_count_max: None,
+                error_max: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shrink_candidates, StressCase};
+    use crate::{harness::Failure, shrink::shrink};
+    use fluido_types::fluid::Concentration;
+
+    fn case(inputs: &[f64], target: f64) -> StressCase {
+        StressCase {
+            inputs: inputs
+                .iter()
+                .map(|input| Concentration::from(*input))
+                .collect(),
+            target: target.into(),
+        }
+    }
+
+    #[test]
+    fn candidates_drop_inputs_and_decimals() {
+        let candidates = shrink_candidates(&case(&[0.37, 0.0, 0.5], 0.123));
+        assert!(candidates.contains(&case(&[0.0, 0.5], 0.123)));
+        assert!(candidates.contains(&case(&[0.37, 0.5], 0.123)));
+        assert!(candidates.contains(&case(&[0.37, 0.0], 0.123)));
+        assert!(candidates.contains(&case(&[0.37, 0.0, 0.5], 0.1)));
+        assert!(candidates.contains(&case(&[0.37, 0.0, 0.5], 0.12)));
+        assert!(candidates.contains(&case(&[0.4, 0.0, 0.5], 0.123)));
+        // Rounding 0.46 to one decimal gives 0.5, which is an input already.
+        let candidates = shrink_candidates(&case(&[0.46, 0.5], 0.123));
+        assert!(candidates
+            .iter()
+            .all(|candidate| candidate.inputs[0] != 0.5.into()));
+        // Two inputs are kept, and rounded concentrations are not changed.
+        assert!(shrink_candidates(&case(&[0.4, 0.0], 0.1)).is_empty());
+    }
+
+    #[test]
+    fn cases_shrink_to_a_fixpoint() {
+        // Panics as long as 0.0 is one of the inputs.
+        let run = |case: &StressCase| {
+            let fails = case.inputs.contains(&0.0.into());
+            Ok(fails.then(|| Failure::Panic("mixing with water".to_string())))
+        };
+        let failing = case(&[0.37, 0.0, 0.52, 0.81], 0.123);
+        let failure = Failure::Panic("mixing with water".to_string());
+        let (shrunk, _) = shrink(failing, failure, 64, shrink_candidates, run).unwrap();
+        // Inputs are dropped first, then the target and the inputs lose their decimals.
+        assert_eq!(shrunk, case(&[0.0, 0.8], 0.1));
+    }
+}
@@ -5,6 +5,7 @@ use std::{
 };
 
 use crate::manifest::TestManifestFile;
+use glob::Pattern;
 use regex::{Captures, Regex};
 
 /// Default name of the test manifest file.
@@ -46,20 +47,35 @@ where
     (result, output)
 }
 
-/// Starting from the `e2e-tests/src/tests/` discovers all the tests we have and collects their manifest files to be processed later on.
-pub fn discover_test_configs() -> anyhow::Result<Vec<TestManifestFile>> {
-    fn recursive_search(path: &Path, configs: &mut Vec<TestManifestFile>) -> anyhow::Result<()> {
-        let wrap_err = |e| {
-            let relative_path = path
-                .iter()
-                .skip_while(|part| part.to_string_lossy() != "test_programs")
-                .skip(1)
-                .collect::<PathBuf>();
-            anyhow::anyhow!("{}: {}", relative_path.display(), e)
-        };
+/// Root of the test corpus of this crate, used if no other root is given.
+pub fn default_tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests")
+}
+
+/// Discovers all the tests under each of the roots and collects their manifest files to be
+/// processed later on. Directories and manifests whose path relative to their root matches one of
+/// the ignore patterns are skipped, i.e `slow/**`.
+pub fn discover_test_configs(
+    roots: &[PathBuf],
+    ignore: &[Pattern],
+) -> anyhow::Result<Vec<TestManifestFile>> {
+    fn recursive_search(
+        root: &Path,
+        path: &Path,
+        ignore: &[Pattern],
+        configs: &mut Vec<TestManifestFile>,
+    ) -> anyhow::Result<()> {
+        let relative_path = path.strip_prefix(root).unwrap_or(path);
+        if ignore
+            .iter()
+            .any(|pattern| pattern.matches_path(relative_path))
+        {
+            return Ok(());
+        }
+        let wrap_err = |e| anyhow::anyhow!("{}: {}", relative_path.display(), e);
         if path.is_dir() {
-            for entry in std::fs::read_dir(path).unwrap() {
-                recursive_search(&entry.unwrap().path(), configs)?;
+            for entry in std::fs::read_dir(path)? {
+                recursive_search(root, &entry?.path(), ignore, configs)?;
             }
         } else if path.is_file()
             && path
@@ -73,11 +89,13 @@ pub fn discover_test_configs() -> anyhow::Result<Vec<TestManifestFile>> {
         Ok(())
     }
 
-    let manifest_dir = env!("CARGO_MANIFEST_DIR");
-    let tests_root_dir = format!("{manifest_dir}/src/tests");
-
     let mut configs = Vec::new();
-    recursive_search(&PathBuf::from(tests_root_dir), &mut configs)?;
+    for root in roots {
+        if !root.is_dir() {
+            anyhow::bail!("tests directory {} does not exist", root.display());
+        }
+        recursive_search(root, root, ignore, &mut configs)?;
+    }
     Ok(configs)
 }
 
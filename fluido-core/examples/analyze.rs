@@ -0,0 +1,36 @@
+//! Analyzes a design written by hand instead of searching one: derives its certificate, lowers it
+//! and allocates storage for it, by running the pipeline without its `generate` phase.
+//!
+//! ```console
+//! cargo run -p fluido-core --example analyze
+//! ```
+use fluido_core::{
+    pipeline::{Pipeline, PipelineState},
+    Config, LogConfig, MixerGenerationConfig, MixerGenerator,
+};
+use fluido_parse::parser::Parse;
+use fluido_types::{error::FluidoError, expr::Expr, fluid::Fluid, input_space::InputSpace};
+
+fn main() -> Result<(), FluidoError> {
+    let expr = Expr::parse("(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))")?;
+    // Fails with the node which does not produce a fluid, if there is one.
+    println!("evaluates to: {}", Fluid::try_from(&expr)?);
+    let generation = MixerGenerationConfig::new(0, MixerGenerator::EqualitySaturation);
+    let config = Config::new(generation, LogConfig::silent());
+
+    // The target is only used by the `generate` phase.
+    let mut state = PipelineState::new(config, 0.0.into(), &InputSpace::default());
+    state.expr = Some(expr);
+    Pipeline::default().without("generate")?.run(&mut state)?;
+
+    let design = state.design.expect("the report phase assembles the design");
+    println!("produces: {}", design.certificate().result.concentration);
+    println!("storage units: {}", design.storage_units_needed());
+    for intermediate in design.intermediate_fluids() {
+        println!(
+            "%{} holds {} in storage unit {}",
+            intermediate.vreg, intermediate.fluid, intermediate.storage_unit
+        );
+    }
+    Ok(())
+}
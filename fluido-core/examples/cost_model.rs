@@ -0,0 +1,37 @@
+//! Tunes the cost model of the search: designs are optimized for the total mixing time instead
+//! of the number of mixes, and drawing from a scarce input is penalized.
+//!
+//! ```console
+//! cargo run -p fluido-core --example cost_model
+//! ```
+use fluido_core::{
+    search_mixer_design, Config, CostObjective, InputTier, LogConfig, MixTimeModel,
+    MixerGenerationConfig, MixerGenerator,
+};
+use fluido_types::{
+    error::FluidoError,
+    fluid::Fluid,
+    input_space::{Input, InputSpace},
+};
+
+fn main() -> Result<(), FluidoError> {
+    let input_space = InputSpace::new([
+        Input::new(Fluid::new(0.4.into(), 1.0.into())).with_tier(InputTier::Scarce),
+        Input::new(Fluid::new(0.2.into(), 1.0.into())),
+        Input::new(Fluid::new(0.0.into(), 1.0.into())),
+    ]);
+    // Each mix takes 10 seconds, plus 2 seconds per unit of volume mixed.
+    let mix_time_model = MixTimeModel::new(10.0, 2.0);
+    let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation)
+        .with_objective(CostObjective::MixTime)
+        .with_mix_time_model(mix_time_model);
+    let config = Config::new(generation, LogConfig::silent());
+
+    let design = search_mixer_design(config, 0.1.into(), &input_space)?;
+    println!("design: {}", design.mixer_expr());
+    println!("cost: {}", design.cost());
+    if let Some(estimated_time) = design.estimated_time() {
+        println!("estimated time: {estimated_time} seconds");
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Adds an analysis of its own to the search, as a pass accounting the volume each step of the
+//! design produces, and reads its result back from the design.
+//!
+//! ```console
+//! cargo run -p fluido-core --example custom_analysis
+//! ```
+use fluido_core::{
+    search_mixer_design, AnalysisPass, AnalysisResult, Config, IROp, LogConfig,
+    MixerGenerationConfig, MixerGenerator,
+};
+use fluido_ir::ir::evaluate_ir;
+use fluido_types::{error::FluidoError, fluid::Fluid, input_space::InputSpace};
+
+/// Volume of the fluid each op of the ir produces, as a `Vec<f64>` in execution order.
+struct VolumePerStep;
+
+impl AnalysisPass for VolumePerStep {
+    fn pass_name(&self) -> &str {
+        "volume-per-step"
+    }
+
+    fn analyze(&self, ir_to_pass_over: &[IROp]) -> AnalysisResult {
+        let fluids = evaluate_ir(ir_to_pass_over);
+        let volumes = ir_to_pass_over
+            .iter()
+            .map(|op| {
+                op.target_vreg()
+                    .and_then(|vreg| fluids.get(&vreg))
+                    .map_or(0.0, |fluid| fluid.unit_volume().clone().into())
+            })
+            .collect::<Vec<f64>>();
+        AnalysisResult::new(volumes)
+    }
+}
+
+fn main() -> Result<(), FluidoError> {
+    let input_space = InputSpace::from_iter([
+        Fluid::new(0.04.into(), 1.0.into()),
+        Fluid::new(0.0.into(), 1.0.into()),
+    ]);
+    let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation);
+    let config = Config::new(generation, LogConfig::silent()).with_analysis_pass(VolumePerStep);
+
+    let design = search_mixer_design(config, 0.01.into(), &input_space)?;
+    println!("design: {}", design.mixer_expr());
+    let volumes = design
+        .analysis_results()
+        .get::<Vec<f64>>("volume-per-step")
+        .expect("the pass is registered in the config");
+    for (op, volume) in design.ir().iter().zip(volumes) {
+        println!("{op} --- volume {volume}");
+    }
+    Ok(())
+}
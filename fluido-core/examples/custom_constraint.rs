@@ -0,0 +1,48 @@
+//! Adds a constraint of its own to the search, as a phase rejecting designs with too many mixes
+//! right after their certificate is derived.
+//!
+//! ```console
+//! cargo run -p fluido-core --example custom_constraint
+//! ```
+use fluido_core::{
+    pipeline::{Phase, Pipeline, PipelineState},
+    search_mixer_design, Config, LogConfig, MixerGenerationConfig, MixerGenerator,
+};
+use fluido_types::{error::FluidoError, fluid::Fluid, input_space::InputSpace};
+
+/// Rejects designs needing more than `max_mixes` mixes.
+struct MaxMixes {
+    max_mixes: usize,
+}
+
+impl Phase for MaxMixes {
+    fn name(&self) -> &str {
+        "max-mixes"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let mixes = state.certificate.as_ref().map_or(0, |c| c.steps.len());
+        if mixes > self.max_mixes {
+            return Err(FluidoError::InvalidInput(format!(
+                "design needs {mixes} mixes, at most {} are allowed",
+                self.max_mixes
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), FluidoError> {
+    let input_space = InputSpace::from_iter([
+        Fluid::new(0.04.into(), 1.0.into()),
+        Fluid::new(0.0.into(), 1.0.into()),
+    ]);
+    let pipeline = Pipeline::default().insert_after("validate", MaxMixes { max_mixes: 2 })?;
+    println!("phases: {:?}", pipeline.phase_names());
+    let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation);
+    let config = Config::new(generation, LogConfig::silent()).with_pipeline(pipeline);
+
+    let design = search_mixer_design(config, 0.01.into(), &input_space)?;
+    println!("design: {}", design.mixer_expr());
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! Searches designs for a batch of targets produced one after another on the same chip, and
+//! plans the order producing them with the fewest storage units.
+//!
+//! ```console
+//! cargo run -p fluido-core --example multi_target
+//! ```
+use fluido_core::{planning::plan_batch, Config, LogConfig, MixerGenerationConfig, MixerGenerator};
+use fluido_types::{error::FluidoError, fluid::Fluid, input_space::InputSpace};
+
+fn main() -> Result<(), FluidoError> {
+    let input_space = InputSpace::from_iter([
+        Fluid::new(0.4.into(), 1.0.into()),
+        Fluid::new(0.0.into(), 1.0.into()),
+    ]);
+    let targets = [0.2.into(), 0.1.into()];
+    let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation);
+    let config = Config::new(generation, LogConfig::silent());
+
+    let plan = plan_batch(&config, &targets, &input_space)?;
+    for (target, design) in targets.iter().zip(&plan.designs) {
+        println!("{target}: {}", design.mixer_expr());
+    }
+    println!("order: {:?}", plan.order);
+    println!("storage units for the batch: {}", plan.storage_units_needed);
+    Ok(())
+}
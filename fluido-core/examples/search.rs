@@ -0,0 +1,23 @@
+//! Searches a design for a target concentration from the input fluids at hand.
+//!
+//! ```console
+//! cargo run -p fluido-core --example search
+//! ```
+use fluido_core::{search_mixer_design, Config, LogConfig, MixerGenerationConfig, MixerGenerator};
+use fluido_types::{error::FluidoError, fluid::Fluid, input_space::InputSpace};
+
+fn main() -> Result<(), FluidoError> {
+    let input_space = InputSpace::from_iter([
+        Fluid::new(0.04.into(), 1.0.into()),
+        Fluid::new(0.0.into(), 1.0.into()),
+    ]);
+    let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation);
+    let config = Config::new(generation, LogConfig::silent());
+
+    let design = search_mixer_design(config, 0.01.into(), &input_space)?;
+    println!("design: {}", design.mixer_expr());
+    println!("mixes: {}", design.mix_count());
+    println!("storage units: {}", design.storage_units_needed());
+    print!("{}", design.linear_listing());
+    Ok(())
+}
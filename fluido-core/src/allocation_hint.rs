@@ -0,0 +1,141 @@
+//! Keeping storage assignments stable across runs, so that a design which changed a little needs
+//! little relabeling of the physical setup.
+use crate::MixerDesign;
+use fluido_ir::ir::{evaluate_ir, IROp};
+use fluido_types::fluid::Fluid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Storage unit a fluid of the design is kept in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StorageAssignment {
+    pub fluid: Fluid,
+    pub storage_unit: u64,
+}
+
+/// Storage assignments of a previous allocation. Virtual registers holding the same fluid as one
+/// of the assignments are unchanged, and are kept in the same storage unit where the minimum
+/// number of storage units allows it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AllocationHint {
+    pub assignments: Vec<StorageAssignment>,
+}
+
+impl AllocationHint {
+    /// Assignments of the virtual registers of the ir which are allocated a storage unit, in
+    /// execution order.
+    pub fn from_allocation(ir: &[IROp], storage_allocation: &HashMap<usize, u64>) -> Self {
+        let fluids = evaluate_ir(ir);
+        let assignments = ir
+            .iter()
+            .filter_map(|op| {
+                let vreg = op.target_vreg()?;
+                Some(StorageAssignment {
+                    fluid: fluids.get(&vreg)?.clone(),
+                    storage_unit: *storage_allocation.get(&vreg)?,
+                })
+            })
+            .collect();
+        Self { assignments }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assignments.is_empty()
+    }
+
+    /// Hinted storage unit of each virtual register of the ir, matched by the fluid it holds. A
+    /// fluid assigned more than once is matched in execution order.
+    pub fn vreg_hint(&self, ir: &[IROp]) -> HashMap<usize, u64> {
+        let mut storage_units: HashMap<&Fluid, VecDeque<u64>> = HashMap::new();
+        for assignment in &self.assignments {
+            storage_units
+                .entry(&assignment.fluid)
+                .or_default()
+                .push_back(assignment.storage_unit);
+        }
+        let fluids = evaluate_ir(ir);
+        ir.iter()
+            .filter_map(|op| {
+                let vreg = op.target_vreg()?;
+                let storage_unit = storage_units.get_mut(fluids.get(&vreg)?)?.pop_front()?;
+                Some((vreg, storage_unit))
+            })
+            .collect()
+    }
+
+    /// Number of assignments of the design kept from this hint.
+    pub fn kept_assignments(&self, mixer_design: &MixerDesign) -> usize {
+        self.vreg_hint(&mixer_design.ir)
+            .iter()
+            .filter(|(vreg, storage_unit)| {
+                mixer_design.storage_allocation.get(vreg) == Some(storage_unit)
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllocationHint, StorageAssignment};
+    use crate::{
+        pipeline::{Pipeline, PipelineState},
+        test_utils::{design_from_expr, test_config},
+    };
+    use fluido_parse::parser::Parse;
+    use fluido_types::{expr::Expr, fluid::Fluid, input_space::InputSpace};
+
+    #[test]
+    fn unchanged_fluids_keep_their_storage_units() {
+        let expr =
+            "(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (mix (fluid 0.2 1.0) (fluid 0.0 1.0)))";
+        let design = design_from_expr(expr);
+        let assignments = design.storage_assignments();
+        assert_eq!(
+            assignments.kept_assignments(&design),
+            assignments.assignments.len()
+        );
+
+        // Swap the storage units of the hint around, the allocation follows them.
+        let max_unit = design.storage_units_needed() - 1;
+        let hint = AllocationHint {
+            assignments: assignments
+                .assignments
+                .iter()
+                .map(|assignment| StorageAssignment {
+                    fluid: assignment.fluid.clone(),
+                    storage_unit: max_unit - assignment.storage_unit,
+                })
+                .collect(),
+        };
+        let mut state = PipelineState::new(
+            test_config().with_allocation_hint(hint.clone()),
+            0.0.into(),
+            &InputSpace::default(),
+        );
+        state.expr = Some(Expr::parse(expr).unwrap());
+        Pipeline::default()
+            .without("generate")
+            .unwrap()
+            .run(&mut state)
+            .unwrap();
+        let hinted_design = state.design.unwrap();
+        assert_eq!(
+            hinted_design.storage_units_needed(),
+            design.storage_units_needed()
+        );
+        assert_eq!(hinted_design.storage_assignments(), hint);
+        assert_eq!(
+            hint.kept_assignments(&hinted_design),
+            hint.assignments.len()
+        );
+
+        // Fluids the hint does not know are allocated freely.
+        let unknown = AllocationHint {
+            assignments: vec![StorageAssignment {
+                fluid: Fluid::new(0.5.into(), 1.0.into()),
+                storage_unit: 0,
+            }],
+        };
+        assert_eq!(unknown.kept_assignments(&design), 0);
+    }
+}
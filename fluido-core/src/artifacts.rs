@@ -0,0 +1,79 @@
+//! Intermediate artifacts of a search, captured for debugging instead of being printed.
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+/// Artifacts captured by the phases of the pipeline. Each one is `None` if the phase producing it
+/// did not run, i.e the raw expressions are missing for designs which are not generated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunArtifacts {
+    /// Best expression as extracted from the e-graph.
+    pub raw_expr: Option<String>,
+    /// Best expression, normalized by its smallest volume.
+    pub normalized_expr: Option<String>,
+    /// E-class of the target and its neighborhood in dot format, if dumping it is configured.
+    pub target_class_dot: Option<String>,
+    /// Mixer graph in dot format.
+    pub graph_dot: Option<String>,
+    /// Flat ir, one op per line.
+    pub ir_listing: Option<String>,
+    /// Live virtual registers at each op of the flat ir.
+    pub liveness_table: Option<String>,
+    /// Interference graph in dot format.
+    pub interference_dot: Option<String>,
+    /// Storage unit allocated to each virtual register.
+    pub coloring: Option<BTreeMap<usize, u64>>,
+}
+
+impl RunArtifacts {
+    /// Writes each captured artifact into its own file in the directory, creating the directory
+    /// if needed.
+    pub fn write_to_dir(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let coloring = self.coloring.as_ref().map(|coloring| {
+            coloring
+                .iter()
+                .fold(String::new(), |mut listing, (vreg, unit)| {
+                    let _ = writeln!(listing, "%{vreg} : {unit}");
+                    listing
+                })
+        });
+        let files = [
+            ("raw_expr.txt", &self.raw_expr),
+            ("normalized_expr.txt", &self.normalized_expr),
+            ("target_class.dot", &self.target_class_dot),
+            ("graph.dot", &self.graph_dot),
+            ("ir.txt", &self.ir_listing),
+            ("liveness.txt", &self.liveness_table),
+            ("interference.dot", &self.interference_dot),
+            ("coloring.txt", &coloring),
+        ];
+        for (name, contents) in files {
+            if let Some(contents) = contents {
+                std::fs::write(dir.join(name), contents)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunArtifacts;
+
+    #[test]
+    fn captured_artifacts_are_written() {
+        let dir = std::env::temp_dir().join(format!("fluido-artifacts-{}", std::process::id()));
+        let artifacts = RunArtifacts {
+            ir_listing: Some("0 : store (fluid 0.2 1.0) %0\n".to_string()),
+            coloring: Some([(0, 1), (1, 0)].into_iter().collect()),
+            ..Default::default()
+        };
+        artifacts.write_to_dir(&dir).unwrap();
+
+        let coloring = std::fs::read_to_string(dir.join("coloring.txt")).unwrap();
+        assert_eq!(coloring, "%0 : 1\n%1 : 0\n");
+        assert!(dir.join("ir.txt").exists());
+        assert!(!dir.join("graph.dot").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
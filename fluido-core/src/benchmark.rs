@@ -0,0 +1,275 @@
+//! Benchmarking the mixer generation over randomly sampled targets.
+//!
+//! Targets are sampled by randomly mixing fluids of the input space, so every target is known to
+//! be reachable. Each target is then searched with the same configuration and the results are
+//! summarized, which makes it possible to compare rule sets and generators with each other.
+use crate::{search_mixer_design, Config};
+use fluido_types::{
+    fluid::Concentration, input_space::InputSpace, number::Rational, precision::Precision,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::{collections::HashSet, fmt::Display, time::Instant};
+
+/// Upper bound on the number of random mixes tried per requested target.
+const ATTEMPTS_PER_TARGET: usize = 1000;
+
+/// Result of searching a single benchmark target.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkRun {
+    pub target: f64,
+    /// Whether a valid design was found within the budget.
+    pub success: bool,
+    /// Absolute difference between the produced and the target concentration.
+    pub error: Option<f64>,
+    pub mix_count: Option<usize>,
+    /// Wall clock time spent on the search, in seconds.
+    pub seconds: f64,
+}
+
+/// Statistical summary of a benchmark.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkSummary {
+    pub samples: usize,
+    pub successes: usize,
+    pub success_rate: f64,
+    /// Median error of the successful runs.
+    pub median_error: Option<f64>,
+    /// Median mix count of the successful runs.
+    pub median_mix_count: Option<f64>,
+    pub time_p50: Option<f64>,
+    pub time_p90: Option<f64>,
+    pub time_p99: Option<f64>,
+    pub time_max: Option<f64>,
+    pub runs: Vec<BenchmarkRun>,
+}
+
+impl BenchmarkSummary {
+    pub fn from_runs(runs: Vec<BenchmarkRun>) -> Self {
+        let samples = runs.len();
+        let successful = runs.iter().filter(|run| run.success);
+        let successes = successful.clone().count();
+        let success_rate = if samples == 0 {
+            0.0
+        } else {
+            successes as f64 / samples as f64
+        };
+
+        let mut errors = successful
+            .clone()
+            .filter_map(|run| run.error)
+            .collect::<Vec<_>>();
+        let mut mix_counts = successful
+            .filter_map(|run| run.mix_count.map(|count| count as f64))
+            .collect::<Vec<_>>();
+        let mut times = runs.iter().map(|run| run.seconds).collect::<Vec<_>>();
+        for values in [&mut errors, &mut mix_counts, &mut times] {
+            values.sort_by(f64::total_cmp);
+        }
+
+        Self {
+            samples,
+            successes,
+            success_rate,
+            median_error: median(&errors),
+            median_mix_count: median(&mix_counts),
+            time_p50: percentile(&times, 50.0),
+            time_p90: percentile(&times, 90.0),
+            time_p99: percentile(&times, 99.0),
+            time_max: times.last().copied(),
+            runs,
+        }
+    }
+}
+
+impl BenchmarkSummary {
+    /// Human readable summary, with its numbers displayed with the precision.
+    pub fn report_with_precision(&self, precision: &Precision) -> String {
+        let show =
+            |value: Option<f64>| value.map_or("-".to_string(), |value| precision.format(value));
+        format!(
+            "samples: {}\nsuccess rate: {:.1}% ({}/{})\nmedian error: {}\nmedian mix count: {}\n\
+             time (s): p50 {}, p90 {}, p99 {}, max {}",
+            self.samples,
+            self.success_rate * 100.0,
+            self.successes,
+            self.samples,
+            show(self.median_error),
+            show(self.median_mix_count),
+            show(self.time_p50),
+            show(self.time_p90),
+            show(self.time_p99),
+            show(self.time_max)
+        )
+    }
+}
+
+impl Display for BenchmarkSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report_with_precision(&Precision::default()))
+    }
+}
+
+/// Samples up to `count` distinct targets which are reachable by mixing fluids from the input
+/// space in 1:1 ratios. Inputs themselves are never sampled.
+///
+/// Fewer targets are returned if the input space cannot produce enough distinct concentrations.
+pub fn random_reachable_targets(
+    input_space: &InputSpace,
+    count: usize,
+    seed: u64,
+) -> Vec<Concentration> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut reachable = input_space
+        .iter()
+        .map(|input| Rational::from(input.concentration()))
+        .collect::<Vec<_>>();
+    // Inputs may repeat a concentration anywhere in the input space, not only next to each other.
+    let mut seen = HashSet::new();
+    reachable.retain(|concentration| seen.insert(*concentration));
+    let mut targets = vec![];
+    if reachable.len() < 2 {
+        return targets;
+    }
+
+    for _ in 0..count * ATTEMPTS_PER_TARGET {
+        if targets.len() == count {
+            break;
+        }
+        let lhs = reachable[rng.gen_range(0..reachable.len())];
+        let rhs = reachable[rng.gen_range(0..reachable.len())];
+        let mixed = (lhs + rhs) / Rational::new(2, 1);
+        if reachable.contains(&mixed) {
+            continue;
+        }
+        // Only keep targets which can be expressed without rounding.
+        if let Ok(concentration) = Concentration::try_from(mixed) {
+            reachable.push(mixed);
+            targets.push(concentration);
+        }
+    }
+    targets
+}
+
+/// Searches a mixer design for each of the targets with the same configuration.
+pub fn run_benchmark(
+    config: &Config,
+    targets: &[Concentration],
+    input_space: &InputSpace,
+) -> BenchmarkSummary {
+    let runs = targets
+        .iter()
+        .map(|target| {
+            let start = Instant::now();
+            let result = search_mixer_design(config.clone(), target.clone(), input_space);
+            let seconds = start.elapsed().as_secs_f64();
+            let (success, error, mix_count) = match result {
+                Ok(design) => {
+                    let produced = design.certificate().result.concentration;
+                    let error: f64 = (produced - Rational::from(target)).into();
+                    (true, Some(error.abs()), Some(design.mix_count()))
+                }
+                Err(_) => (false, None, None),
+            };
+            BenchmarkRun {
+                target: Rational::from(target).into(),
+                success,
+                error,
+                mix_count,
+                seconds,
+            }
+        })
+        .collect();
+    BenchmarkSummary::from_runs(runs)
+}
+
+/// Median of already sorted values.
+fn median(sorted: &[f64]) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+    } else {
+        Some(sorted[mid])
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+pub(crate) fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{median, percentile, random_reachable_targets, BenchmarkRun, BenchmarkSummary};
+    use fluido_types::{input_space::InputSpace, precision::Precision};
+
+    fn input_space() -> InputSpace {
+        InputSpace::from_concentrations([0.0.into(), 0.4.into()])
+    }
+
+    #[test]
+    fn targets_are_reachable_and_distinct() {
+        let targets = random_reachable_targets(&input_space(), 5, 7);
+        assert_eq!(targets.len(), 5);
+        for (ix, target) in targets.iter().enumerate() {
+            let target: f64 = target.clone().into();
+            assert!(target > 0.0 && target < 0.4);
+            assert!(!targets[..ix].contains(&targets[ix]));
+        }
+        assert_eq!(targets, random_reachable_targets(&input_space(), 5, 7));
+    }
+
+    #[test]
+    fn no_targets_from_single_concentration() {
+        let input_space = InputSpace::from_concentrations([0.2.into()]);
+        assert!(random_reachable_targets(&input_space, 3, 0).is_empty());
+    }
+
+    #[test]
+    fn repeated_concentrations_are_ignored() {
+        let input_space = InputSpace::from_concentrations([0.0.into(), 0.4.into(), 0.0.into()]);
+        assert_eq!(
+            random_reachable_targets(&input_space, 5, 7),
+            random_reachable_targets(&self::input_space(), 5, 7)
+        );
+    }
+
+    #[test]
+    fn order_statistics() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[1.0, 2.0, 4.0]), Some(2.0));
+        assert_eq!(median(&[1.0, 2.0, 4.0, 8.0]), Some(3.0));
+
+        let values = (1..=10).map(f64::from).collect::<Vec<_>>();
+        assert_eq!(percentile(&values, 50.0), Some(5.0));
+        assert_eq!(percentile(&values, 90.0), Some(9.0));
+        assert_eq!(percentile(&values, 99.0), Some(10.0));
+    }
+
+    #[test]
+    fn summary_only_counts_successful_runs() {
+        let run = |success: bool, seconds: f64| BenchmarkRun {
+            target: 0.1,
+            success,
+            error: success.then_some(0.0),
+            mix_count: success.then_some(2),
+            seconds,
+        };
+        let summary = BenchmarkSummary::from_runs(vec![run(true, 1.0), run(false, 3.0)]);
+        assert_eq!(summary.successes, 1);
+        assert_eq!(summary.success_rate, 0.5);
+        assert_eq!(summary.median_error, Some(0.0));
+        assert_eq!(summary.median_mix_count, Some(2.0));
+        assert_eq!(summary.time_max, Some(3.0));
+        let report = summary.report_with_precision(&Precision::DecimalPlaces(2));
+        assert!(report.contains("median error: 0.00\n"));
+        assert!(report.ends_with(", max 3.00"));
+    }
+}
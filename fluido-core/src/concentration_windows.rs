@@ -0,0 +1,56 @@
+//! Validation of designs against forbidden concentration windows.
+use fluido_types::{
+    certificate::Certificate, error::DesignValidationError, fluid::ConcentrationWindow,
+};
+
+/// Fails if any intermediate of the derivation has a concentration inside one of the forbidden
+/// windows. The final fluid of the design is the target itself, not an intermediate.
+pub fn validate_concentration_windows(
+    certificate: &Certificate,
+    forbidden_windows: &[ConcentrationWindow],
+) -> Result<(), DesignValidationError> {
+    let intermediates = certificate.steps.len().saturating_sub(1);
+    for (step, intermediate) in certificate.steps[..intermediates].iter().enumerate() {
+        let concentration = intermediate.result.concentration;
+        if let Some(window) = forbidden_windows
+            .iter()
+            .find(|window| window.contains_exact(&concentration))
+        {
+            return Err(DesignValidationError::ForbiddenConcentration {
+                step,
+                concentration: concentration.to_string(),
+                window: window.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_concentration_windows;
+    use crate::test_utils::design_from_expr;
+    use fluido_types::{error::DesignValidationError, fluid::ConcentrationWindow};
+
+    #[test]
+    fn intermediates_inside_windows_are_rejected() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let window = ConcentrationWindow::new(0.4.into(), 0.5.into());
+        assert_eq!(
+            validate_concentration_windows(design.certificate(), std::slice::from_ref(&window)),
+            Err(DesignValidationError::ForbiddenConcentration {
+                step: 0,
+                concentration: "2/5".to_string(),
+                window,
+            })
+        );
+
+        // The target itself is not an intermediate.
+        let window = ConcentrationWindow::new(0.2.into(), 0.2.into());
+        assert_eq!(
+            validate_concentration_windows(design.certificate(), &[window]),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! How much a returned design can be trusted.
+//!
+//! Designs are derived with exact arithmetic in their certificate, but the search matches
+//! concentrations with a limited precision, so a design may produce a concentration slightly off
+//! from the target. A search cut short by its total time budget still returns the best design it
+//! found, which a complete search may improve on.
+use fluido_types::{certificate::Certificate, fluid::Concentration, number::Rational};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Classification of a design by how much it can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Confidence {
+    /// The certificate verifies, and proves that the design produces the target exactly.
+    Exact,
+    /// The certificate verifies, but the design produces a concentration off from the target by
+    /// `error`, which is within the precision the search matches concentrations with.
+    Approximate { error: f64 },
+    /// The total time budget was exhausted before the search finished, the design is the best one
+    /// found until then. Storage may be allocated greedily, and input subsets may be left
+    /// unexplored.
+    Truncated,
+}
+
+impl Confidence {
+    /// Classifies a design producing the certificate for the target. Designs given without a
+    /// target, i.e written by hand, are exact once their certificate verifies. Truncation takes
+    /// precedence, as it says nothing about how close a complete search would get to the target.
+    pub fn classify(
+        certificate: &Certificate,
+        target: Option<&Concentration>,
+        truncated: bool,
+    ) -> Self {
+        if truncated {
+            return Confidence::Truncated;
+        }
+        let target = match target {
+            Some(target) => Rational::from(target),
+            None => certificate.result.concentration,
+        };
+        if certificate.verify().is_ok() && certificate.result.concentration == target {
+            return Confidence::Exact;
+        }
+        let error = certificate.result.concentration - target;
+        Confidence::Approximate {
+            error: f64::from(error).abs(),
+        }
+    }
+}
+
+impl Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Confidence::Exact => write!(f, "exact"),
+            Confidence::Approximate { error } => write!(f, "approximate (off by {error})"),
+            Confidence::Truncated => write!(f, "truncated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Confidence;
+    use fluido_parse::parser::Parse;
+    use fluido_types::{certificate::Certificate, expr::Expr};
+
+    #[test]
+    fn designs_are_classified_by_their_certificate() {
+        // Produces exactly a third.
+        let expr = Expr::parse("(mix (fluid 1.0 1.0) (fluid 0.0 2.0))").unwrap();
+        let certificate = Certificate::from_expr(&expr).unwrap();
+
+        assert_eq!(
+            Confidence::classify(&certificate, Some(&0.5.into()), false),
+            Confidence::Approximate { error: 1.0 / 6.0 }
+        );
+        let Confidence::Approximate { error } =
+            Confidence::classify(&certificate, Some(&0.3333.into()), false)
+        else {
+            panic!("a third is not exactly 0.3333");
+        };
+        assert!((error - 1.0 / 30000.0).abs() < 1e-12);
+        assert_eq!(
+            Confidence::classify(&certificate, Some(&0.3333.into()), true),
+            Confidence::Truncated
+        );
+
+        let expr = Expr::parse("(mix (fluid 0.4 1.0) (fluid 0.0 1.0))").unwrap();
+        let certificate = Certificate::from_expr(&expr).unwrap();
+        assert_eq!(
+            Confidence::classify(&certificate, Some(&0.2.into()), false),
+            Confidence::Exact
+        );
+        assert_eq!(
+            Confidence::classify(&certificate, None, false),
+            Confidence::Exact
+        );
+    }
+}
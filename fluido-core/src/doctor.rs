@@ -0,0 +1,318 @@
+//! Health checks of the environment a search runs in, each with an actionable message if it does
+//! not pass.
+//!
+//! The checks are cheap, they solve a tiny coloring and convert a few thousand numbers, so they
+//! can run before every long search on an unfamiliar platform.
+use fluido_generation::{rewrite_rules, MixRewrite, SaturationConfig};
+#[cfg(feature = "smt")]
+use fluido_ir::regalloc::interference_graph::{solver_version, InterferenceGraphBuilder};
+use fluido_types::{fluid::LimitedFloat, number::Rational, precision::Precision};
+#[cfg(feature = "smt")]
+use std::{
+    collections::HashSet,
+    panic::{self, AssertUnwindSafe},
+};
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    /// The search works, but not as configured.
+    Warning,
+    /// The search fails, or gives wrong results.
+    Failed,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "ok"),
+            CheckStatus::Warning => write!(f, "warning"),
+            CheckStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Result of a single check, along with how to fix it if it did not pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: String) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            message,
+            fix: None,
+        }
+    }
+
+    fn not_ok(name: &'static str, status: CheckStatus, message: String, fix: String) -> Self {
+        Self {
+            name,
+            status,
+            message,
+            fix: Some(fix),
+        }
+    }
+}
+
+/// Configuration the environment is checked against.
+#[derive(Debug, Clone, Default)]
+pub struct DoctorConfig {
+    precision: Precision,
+    custom_rules: Vec<MixRewrite>,
+    output_paths: Vec<PathBuf>,
+}
+
+impl DoctorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Precision numbers are displayed with, checked against the precision they are computed with.
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Custom rewrite rules registered for the search, checked along with the builtin ones.
+    pub fn with_custom_rules(mut self, custom_rules: Vec<MixRewrite>) -> Self {
+        self.custom_rules = custom_rules;
+        self
+    }
+
+    /// Files and directories the search writes to, their directories must be writable.
+    pub fn with_output_paths(mut self, output_paths: Vec<PathBuf>) -> Self {
+        self.output_paths = output_paths;
+        self
+    }
+}
+
+/// Runs every check, in the order the search depends on them.
+pub fn run_checks(config: &DoctorConfig) -> Vec<Check> {
+    let mut checks = vec![
+        check_solver(),
+        check_number_backend(&config.precision),
+        check_rewrite_rules(&config.custom_rules),
+    ];
+    checks.extend(
+        config
+            .output_paths
+            .iter()
+            .map(|path| check_output_path(path)),
+    );
+    checks
+}
+
+/// Colors a triangle, which needs exactly three storage units, with the linked z3.
+#[cfg(feature = "smt")]
+fn check_solver() -> Check {
+    const NAME: &str = "z3";
+    let solve = || {
+        let liveness = [HashSet::from([0, 1, 2])];
+        let graph = InterferenceGraphBuilder::new(&liveness).build();
+        (graph.try_coloring(2), graph.try_coloring(3))
+    };
+    let version = solver_version();
+    match panic::catch_unwind(AssertUnwindSafe(solve)) {
+        Ok((None, Some(coloring))) if coloring.values().collect::<HashSet<_>>().len() == 3 => {
+            Check::ok(
+                NAME,
+                format!("version {version}, solves storage allocations correctly"),
+            )
+        }
+        Ok(_) => Check::not_ok(
+            NAME,
+            CheckStatus::Failed,
+            format!("version {version} gives a wrong storage allocation for a known graph"),
+            "rebuild the statically linked solver with `cargo clean -p z3-sys && cargo build`"
+                .to_string(),
+        ),
+        Err(_) => Check::not_ok(
+            NAME,
+            CheckStatus::Failed,
+            format!("version {version} panics while allocating storage"),
+            "make sure the platform is supported by z3 and rebuild with `cargo clean -p z3-sys && \
+             cargo build`"
+                .to_string(),
+        ),
+    }
+}
+
+#[cfg(not(feature = "smt"))]
+fn check_solver() -> Check {
+    Check::not_ok(
+        "z3",
+        CheckStatus::Warning,
+        "not built, storage is allocated heuristically and may use more units than needed"
+            .to_string(),
+        "enable the `smt` feature of fluido-core".to_string(),
+    )
+}
+
+/// Converts every concentration between zero and one through `f64` and back, and checks the
+/// display precision against the precision concentrations are computed with.
+fn check_number_backend(precision: &Precision) -> Check {
+    const NAME: &str = "number backend";
+    let scale = (1.0 / LimitedFloat::EPSILON).round() as i128;
+    let decimals = scale.ilog10() as usize;
+    let inexact = (0..=scale).find(|&step| {
+        let exact = Rational::new(step, scale);
+        let value: f64 = exact.into();
+        Rational::from(&LimitedFloat::from(value)) != exact
+            || Rational::from(&LimitedFloat::from(f64::from(LimitedFloat::from(value)))) != exact
+    });
+    if let Some(step) = inexact {
+        return Check::not_ok(
+            NAME,
+            CheckStatus::Failed,
+            format!(
+                "{} does not survive a conversion through f64 on this platform",
+                Rational::new(step, scale)
+            ),
+            "report the platform along with `rustc -vV`, designs may be reported with wrong \
+             concentrations"
+                .to_string(),
+        );
+    }
+    let message = format!(
+        "concentrations are exact to {} ({decimals} decimals)",
+        LimitedFloat::EPSILON
+    );
+    match *precision {
+        Precision::DecimalPlaces(requested) if requested > decimals => Check::not_ok(
+            NAME,
+            CheckStatus::Warning,
+            format!("{message}, {requested} decimals are requested"),
+            format!(
+                "digits after the {decimals}th decimal are always zero, use \
+                 `--decimals {decimals}`"
+            ),
+        ),
+        Precision::SignificantFigures(requested) if requested > decimals => Check::not_ok(
+            NAME,
+            CheckStatus::Warning,
+            format!("{message}, {requested} significant figures are requested"),
+            format!(
+                "concentrations below one have at most {decimals} significant figures, use \
+                 `--significant-figures {decimals}`"
+            ),
+        ),
+        _ => Check::ok(NAME, message),
+    }
+}
+
+/// Checks that the builtin and the custom rewrite rules can be registered together.
+fn check_rewrite_rules(custom_rules: &[MixRewrite]) -> Check {
+    const NAME: &str = "rewrite rules";
+    let config = SaturationConfig {
+        custom_rules: custom_rules.to_vec(),
+        ..Default::default()
+    };
+    match rewrite_rules(&config) {
+        Ok(rules) => Check::ok(
+            NAME,
+            format!(
+                "{} rules, {} of them custom",
+                rules.len(),
+                custom_rules.len()
+            ),
+        ),
+        Err(err) => Check::not_ok(
+            NAME,
+            CheckStatus::Failed,
+            err.to_string(),
+            "rename the custom rule, names must be distinct from each other and from the builtin \
+             rules"
+                .to_string(),
+        ),
+    }
+}
+
+/// Checks that the path can be written, by writing a probe file into the closest existing
+/// directory it would be created in.
+fn check_output_path(path: &Path) -> Check {
+    const NAME: &str = "output path";
+    let directory = match path.is_dir() {
+        true => Some(path),
+        false => path
+            .ancestors()
+            .skip(1)
+            .find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.is_dir()),
+    };
+    let directory = match directory {
+        Some(directory) if directory.as_os_str().is_empty() => Path::new("."),
+        Some(directory) => directory,
+        None => {
+            return Check::not_ok(
+                NAME,
+                CheckStatus::Failed,
+                format!("no directory of `{}` exists", path.display()),
+                "give a path in an existing directory".to_string(),
+            )
+        }
+    };
+    let probe = directory.join(".fluido-doctor-probe");
+    match fs::write(&probe, b"").and_then(|()| fs::remove_file(&probe)) {
+        Ok(()) => Check::ok(NAME, format!("`{}` is writable", path.display())),
+        Err(err) => Check::not_ok(
+            NAME,
+            CheckStatus::Failed,
+            format!("`{}` is not writable: {err}", directory.display()),
+            format!(
+                "fix the permissions of `{}`, or write to another path",
+                directory.display()
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_checks, CheckStatus, DoctorConfig};
+    use fluido_generation::{rewrite_rules, SaturationConfig};
+    use fluido_types::precision::Precision;
+    use std::env;
+
+    #[test]
+    fn checks_report_misconfigurations() {
+        let output_dir = env::temp_dir().join("fluido-doctor-test");
+        let checks =
+            run_checks(&DoctorConfig::new().with_output_paths(vec![output_dir.join("design.csv")]));
+        assert_eq!(checks.len(), 4);
+        // Without the solver, its check warns about the greedy allocation.
+        let solver_status = match cfg!(feature = "smt") {
+            true => CheckStatus::Ok,
+            false => CheckStatus::Warning,
+        };
+        assert_eq!(checks[0].status, solver_status);
+        assert!(checks[1..]
+            .iter()
+            .all(|check| check.status == CheckStatus::Ok && check.fix.is_none()));
+
+        // A custom rule named like a builtin one.
+        let duplicate = rewrite_rules(&SaturationConfig::default()).unwrap()[0].clone();
+        let checks = run_checks(
+            &DoctorConfig::new()
+                .with_precision(Precision::DecimalPlaces(6))
+                .with_custom_rules(vec![duplicate]),
+        );
+        let statuses = checks.iter().map(|check| check.status).collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [solver_status, CheckStatus::Warning, CheckStatus::Failed]
+        );
+        assert!(checks[1..].iter().all(|check| check.fix.is_some()));
+    }
+}
@@ -0,0 +1,226 @@
+//! Export to the JSON protocol format of OpenDrop-style digital microfluidics (DMF) controllers.
+//!
+//! Fluids are quantized into unit droplets: an input fluid with volume `n` is dispensed as `n`
+//! droplets. A mix merges all droplets of its operands into a single droplet, which is split back
+//! into unit droplets once mixing finishes, unless it is the final fluid of the design.
+//! Operations are timestamped with the schedule of the design.
+use crate::MixerDesign;
+use fluido_types::{
+    certificate::{CertificateOperand, ExactFluid},
+    error::ExportError,
+    number::Rational,
+};
+use serde::Serialize;
+
+/// A droplet on the chip.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Droplet {
+    pub id: usize,
+    pub concentration: Rational,
+    /// Volume in unit droplets.
+    pub volume: u64,
+}
+
+/// A single operation of the protocol.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum DmfOperation {
+    /// Dispense unit droplets from the reservoir of an input.
+    Dispense {
+        time: f64,
+        reservoir: Rational,
+        droplets: Vec<usize>,
+    },
+    /// Merge the input droplets into the output droplet and mix it.
+    Mix {
+        time: f64,
+        duration: f64,
+        inputs: Vec<usize>,
+        output: usize,
+    },
+    /// Split a droplet into unit droplets.
+    Split {
+        time: f64,
+        input: usize,
+        outputs: Vec<usize>,
+    },
+}
+
+impl DmfOperation {
+    fn time(&self) -> f64 {
+        match self {
+            DmfOperation::Dispense { time, .. }
+            | DmfOperation::Mix { time, .. }
+            | DmfOperation::Split { time, .. } => *time,
+        }
+    }
+}
+
+/// Droplet level protocol of a design.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DmfProtocol {
+    pub droplets: Vec<Droplet>,
+    /// Operations ordered by time.
+    pub operations: Vec<DmfOperation>,
+    /// Droplets holding the final fluid of the design.
+    pub output: Vec<usize>,
+}
+
+impl DmfProtocol {
+    fn add_droplet(&mut self, concentration: Rational, volume: u64) -> usize {
+        let id = self.droplets.len();
+        self.droplets.push(Droplet {
+            id,
+            concentration,
+            volume,
+        });
+        id
+    }
+
+    /// Dispenses the input fluid as unit droplets.
+    fn dispense(&mut self, fluid: &ExactFluid, time: f64) -> Result<Vec<usize>, ExportError> {
+        let droplets = (0..droplet_count(&fluid.volume)?)
+            .map(|_| self.add_droplet(fluid.concentration, 1))
+            .collect::<Vec<_>>();
+        self.operations.push(DmfOperation::Dispense {
+            time,
+            reservoir: fluid.concentration,
+            droplets: droplets.clone(),
+        });
+        Ok(droplets)
+    }
+}
+
+fn droplet_count(volume: &Rational) -> Result<u64, ExportError> {
+    if volume.denominator() != 1 || volume.numerator() <= 0 {
+        return Err(ExportError::NotDropletQuantized(volume.to_string()));
+    }
+    Ok(volume.numerator() as u64)
+}
+
+/// Converts the design into a droplet level protocol.
+pub fn to_dmf_protocol(design: &MixerDesign) -> Result<DmfProtocol, ExportError> {
+    let certificate = design.certificate();
+    let schedule = design.schedule();
+    let mut protocol = DmfProtocol {
+        droplets: vec![],
+        operations: vec![],
+        output: vec![],
+    };
+
+    if certificate.steps.is_empty() {
+        // The design is a single input fluid.
+        let leaf = certificate
+            .leaves
+            .first()
+            .ok_or(ExportError::MalformedIR(0))?;
+        protocol.output = protocol.dispense(leaf, 0.0)?;
+        return Ok(protocol);
+    }
+
+    // Unit droplets each step is split into.
+    let mut step_droplets: Vec<Vec<usize>> = vec![];
+    for (ix, step) in certificate.steps.iter().enumerate() {
+        let start = schedule.starts[ix];
+        let finish = schedule.finishes[ix];
+        let mut inputs = vec![];
+        for operand in [step.lhs, step.rhs] {
+            match operand {
+                CertificateOperand::Leaf(leaf) => {
+                    let fluid = certificate
+                        .leaves
+                        .get(leaf)
+                        .ok_or(ExportError::MalformedIR(ix))?;
+                    // Dispense just in time for the mix.
+                    inputs.extend(protocol.dispense(fluid, start)?);
+                }
+                CertificateOperand::Step(prev) => inputs.extend(
+                    step_droplets
+                        .get(prev)
+                        .ok_or(ExportError::MalformedIR(ix))?,
+                ),
+            }
+        }
+
+        let volume = droplet_count(&step.result.volume)?;
+        let merged = protocol.add_droplet(step.result.concentration, volume);
+        protocol.operations.push(DmfOperation::Mix {
+            time: start,
+            duration: finish - start,
+            inputs,
+            output: merged,
+        });
+
+        if ix == certificate.steps.len() - 1 {
+            protocol.output = vec![merged];
+        } else {
+            let outputs = (0..volume)
+                .map(|_| protocol.add_droplet(step.result.concentration, 1))
+                .collect::<Vec<_>>();
+            protocol.operations.push(DmfOperation::Split {
+                time: finish,
+                input: merged,
+                outputs: outputs.clone(),
+            });
+            step_droplets.push(outputs);
+        }
+    }
+    protocol
+        .operations
+        .sort_by(|lhs, rhs| lhs.time().total_cmp(&rhs.time()));
+    Ok(protocol)
+}
+
+/// Converts the design into the JSON protocol consumed by DMF controllers.
+pub fn to_dmf_json(design: &MixerDesign) -> Result<String, ExportError> {
+    let protocol = to_dmf_protocol(design)?;
+    Ok(serde_json::to_string_pretty(&protocol).expect("protocol is serializable"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_dmf_protocol, DmfOperation};
+    use crate::test_utils::design_from_expr;
+    use fluido_types::error::ExportError;
+
+    #[test]
+    fn droplets_are_dispensed_mixed_and_split() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let protocol = to_dmf_protocol(&design).unwrap();
+
+        // 1 + 1 dispensed, merged into 1 and split into 2, then 2 dispensed and merged into 1.
+        assert_eq!(protocol.droplets.len(), 8);
+        let ops = protocol
+            .operations
+            .iter()
+            .map(|op| match op {
+                DmfOperation::Dispense { droplets, .. } => format!("dispense {droplets:?}"),
+                DmfOperation::Mix { inputs, output, .. } => format!("mix {inputs:?} {output}"),
+                DmfOperation::Split { input, outputs, .. } => format!("split {input} {outputs:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec![
+                "dispense [0]",
+                "dispense [1]",
+                "mix [0, 1] 2",
+                "split 2 [3, 4]",
+                "dispense [5, 6]",
+                "mix [3, 4, 5, 6] 7",
+            ]
+        );
+        assert_eq!(protocol.output, vec![7]);
+        assert_eq!(protocol.droplets[7].volume, 4);
+    }
+
+    #[test]
+    fn fractional_volumes_are_rejected() {
+        let design = design_from_expr("(mix (fluid 0.2 0.5) (fluid 0.0 1.0))");
+        assert_eq!(
+            to_dmf_protocol(&design),
+            Err(ExportError::NotDropletQuantized("1/2".to_string()))
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! Exporters converting a `MixerDesign` into formats consumed by lab equipment.
+#[cfg(feature = "dmf")]
+pub mod dmf;
+pub mod plate_map;
+pub mod protocol;
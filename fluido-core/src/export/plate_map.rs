@@ -0,0 +1,199 @@
+//! CSV plate map / transfer list export, for liquid handlers working on well plates.
+//!
+//! Every virtual register of the design gets its own well. Input fluids are expected to be
+//! loaded into their wells beforehand, each mix is then expressed as two transfers from the
+//! operand wells into the destination well.
+use crate::MixerDesign;
+use fluido_ir::ir::{evaluate_ir, IROp, Operand};
+use fluido_types::{error::ExportError, precision::Precision};
+use std::collections::HashMap;
+
+/// Naming scheme of the wells on a plate, wells are named row-major as `A1`, `A2`, ..., `B1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WellNaming {
+    rows: usize,
+    columns: usize,
+}
+
+impl Default for WellNaming {
+    /// A standard 96 well plate.
+    fn default() -> Self {
+        Self {
+            rows: 8,
+            columns: 12,
+        }
+    }
+}
+
+impl WellNaming {
+    pub fn new(rows: usize, columns: usize) -> Self {
+        Self { rows, columns }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.rows * self.columns
+    }
+
+    /// Returns the name of the well at `index`, if the plate has that many wells.
+    pub fn well_name(&self, index: usize) -> Option<String> {
+        if index >= self.capacity() {
+            return None;
+        }
+        let row = index / self.columns;
+        let column = index % self.columns + 1;
+        Some(format!("{}{}", row_name(row), column))
+    }
+}
+
+/// Rows are named `A`..`Z`, then `AA`, `AB`, ... like spreadsheet columns.
+fn row_name(mut row: usize) -> String {
+    let mut name = String::new();
+    loop {
+        name.insert(0, (b'A' + (row % 26) as u8) as char);
+        if row < 26 {
+            break;
+        }
+        row = row / 26 - 1;
+    }
+    name
+}
+
+/// A single liquid transfer between two wells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub step: usize,
+    pub source_well: String,
+    pub destination_well: String,
+    pub volume: f64,
+}
+
+/// Converts the design into the list of transfers needed to execute it.
+pub fn transfer_list(
+    design: &MixerDesign,
+    naming: &WellNaming,
+) -> Result<Vec<Transfer>, ExportError> {
+    let ir = design.ir();
+    if ir.len() > naming.capacity() {
+        return Err(ExportError::OutOfWells {
+            needed: ir.len(),
+            available: naming.capacity(),
+        });
+    }
+    let fluids = evaluate_ir(ir);
+    let wells: HashMap<usize, String> = ir
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, op)| op.target_vreg().map(|vreg| (vreg, ix)))
+        .map(|(vreg, ix)| (vreg, naming.well_name(ix).expect("capacity checked above")))
+        .collect();
+
+    let mut transfers = vec![];
+    for (op_ix, op) in ir.iter().enumerate() {
+        let IROp::Mix((lhs, rhs, target)) = op else {
+            continue;
+        };
+        let (Operand::VirtualRegister(lhs), Operand::VirtualRegister(rhs)) = (lhs, rhs) else {
+            return Err(ExportError::MalformedIR(op_ix));
+        };
+        let destination_well = target
+            .as_virtual_register()
+            .and_then(|target| wells.get(&target))
+            .ok_or(ExportError::MalformedIR(op_ix))?;
+        let step = transfers.len() / 2 + 1;
+        for source in [lhs, rhs] {
+            let source_well = wells.get(source).ok_or(ExportError::MalformedIR(op_ix))?;
+            let volume = fluids
+                .get(source)
+                .ok_or(ExportError::MalformedIR(op_ix))?
+                .unit_volume()
+                .clone()
+                .into();
+            transfers.push(Transfer {
+                step,
+                source_well: source_well.clone(),
+                destination_well: destination_well.clone(),
+                volume,
+            });
+        }
+    }
+    Ok(transfers)
+}
+
+/// Renders the transfer list of the design as CSV with a header row, volumes at the given
+/// precision.
+pub fn to_csv(
+    design: &MixerDesign,
+    naming: &WellNaming,
+    precision: &Precision,
+) -> Result<String, ExportError> {
+    let transfers = transfer_list(design, naming)?;
+    let mut csv = String::from("step,source_well,destination_well,volume\n");
+    for transfer in transfers {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            transfer.step,
+            transfer.source_well,
+            transfer.destination_well,
+            precision.format(transfer.volume)
+        ));
+    }
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{row_name, to_csv, WellNaming};
+    use crate::test_utils::design_from_expr;
+    use fluido_types::{error::ExportError, precision::Precision};
+
+    #[test]
+    fn well_names_are_row_major() {
+        let naming = WellNaming::default();
+        assert_eq!(naming.well_name(0).unwrap(), "A1");
+        assert_eq!(naming.well_name(11).unwrap(), "A12");
+        assert_eq!(naming.well_name(12).unwrap(), "B1");
+        assert_eq!(naming.well_name(95).unwrap(), "H12");
+        assert!(naming.well_name(96).is_none());
+    }
+
+    #[test]
+    fn row_names_extend_past_z() {
+        assert_eq!(row_name(25), "Z");
+        assert_eq!(row_name(26), "AA");
+        assert_eq!(row_name(27), "AB");
+    }
+
+    #[test]
+    fn nested_mix_to_csv() {
+        let design = design_from_expr("(mix (mix (fluid 0.2 1) (fluid 0.0 1)) (fluid 0.1 2))");
+        let csv = to_csv(&design, &WellNaming::default(), &Precision::Full).unwrap();
+        let expected = "step,source_well,destination_well,volume
+1,A1,A3,1
+1,A2,A3,1
+2,A3,A5,2
+2,A4,A5,2
+";
+        assert_eq!(csv, expected);
+
+        let csv = to_csv(
+            &design,
+            &WellNaming::default(),
+            &Precision::DecimalPlaces(1),
+        )
+        .unwrap();
+        assert!(csv.ends_with("2,A4,A5,2.0\n"));
+    }
+
+    #[test]
+    fn too_small_plate() {
+        let design = design_from_expr("(mix (fluid 0.2 1) (fluid 0.0 1))");
+        let err = to_csv(&design, &WellNaming::new(1, 2), &Precision::Full).unwrap_err();
+        assert_eq!(
+            err,
+            ExportError::OutOfWells {
+                needed: 3,
+                available: 2
+            }
+        );
+    }
+}
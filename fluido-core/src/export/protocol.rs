@@ -0,0 +1,163 @@
+//! Machine readable protocol of a design, for controllers executing it step by step.
+//!
+//! Steps follow the flat ir of the design after register allocation: input fluids are loaded into
+//! registers, and each mix combines the fluids of two registers into a third one. Registers kept
+//! in storage carry the storage unit allocated to them.
+use crate::MixerDesign;
+use fluido_ir::ir::{evaluate_ir, IROp, Operand};
+use fluido_types::{decimal, error::ExportError, fluid::Fluid};
+use serde::Serialize;
+
+/// Virtual register of the flat ir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProtocolRegister {
+    pub vreg: usize,
+    /// Storage unit holding the fluid of the register, if it is kept in storage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_unit: Option<u64>,
+}
+
+/// Fluid held by a register.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtocolFluid {
+    pub concentration: f64,
+    /// Concentrations of the reagents after the first one, for designs of several reagents.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reagents: Vec<f64>,
+    pub volume: f64,
+}
+
+impl From<&Fluid> for ProtocolFluid {
+    fn from(fluid: &Fluid) -> Self {
+        let concentrations = fluid.concentrations();
+        let mut concentrations = concentrations.iter().map(decimal::to_f64);
+        Self {
+            concentration: concentrations.next().unwrap_or_default(),
+            reagents: concentrations.collect(),
+            volume: decimal::to_f64(fluid.unit_volume()),
+        }
+    }
+}
+
+/// A single step of the protocol.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum ProtocolStep {
+    /// Load an input fluid into the target register.
+    Load {
+        target: ProtocolRegister,
+        fluid: ProtocolFluid,
+    },
+    /// Mix the fluids of the input registers into the target register.
+    Mix {
+        inputs: [ProtocolRegister; 2],
+        target: ProtocolRegister,
+        fluid: ProtocolFluid,
+    },
+}
+
+/// Steps of a design in execution order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Protocol {
+    /// Storage units the steps are allocated to.
+    pub storage_units: u64,
+    pub steps: Vec<ProtocolStep>,
+    /// Register holding the final fluid of the design.
+    pub output: usize,
+}
+
+/// Converts the design into its protocol.
+pub fn to_protocol(design: &MixerDesign) -> Result<Protocol, ExportError> {
+    let fluids = evaluate_ir(&design.ir);
+    let register = |vreg: usize| ProtocolRegister {
+        vreg,
+        storage_unit: design.storage_allocation.get(&vreg).copied(),
+    };
+    let mut steps = vec![];
+    let mut output = None;
+    for (ix, op) in design.ir.iter().enumerate() {
+        let step = match op {
+            IROp::Store((Operand::Const(fluid), Operand::VirtualRegister(target))) => {
+                ProtocolStep::Load {
+                    target: register(*target),
+                    fluid: fluid.into(),
+                }
+            }
+            IROp::Mix((
+                Operand::VirtualRegister(lhs),
+                Operand::VirtualRegister(rhs),
+                Operand::VirtualRegister(target),
+            )) => ProtocolStep::Mix {
+                inputs: [register(*lhs), register(*rhs)],
+                target: register(*target),
+                fluid: fluids
+                    .get(target)
+                    .ok_or(ExportError::MalformedIR(ix))?
+                    .into(),
+            },
+            _ => return Err(ExportError::MalformedIR(ix)),
+        };
+        output = op.target_vreg();
+        steps.push(step);
+    }
+    Ok(Protocol {
+        storage_units: design.storage_units_needed,
+        steps,
+        output: output.ok_or(ExportError::MalformedIR(0))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_protocol, ProtocolRegister, ProtocolStep};
+    use crate::test_utils::design_from_expr;
+
+    #[test]
+    fn steps_follow_the_ir() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let protocol = to_protocol(&design).unwrap();
+        assert_eq!(protocol.storage_units, design.storage_units_needed());
+
+        // Registers are rendered as `%vreg@storage_unit`.
+        let register = |register: &ProtocolRegister| match register.storage_unit {
+            Some(storage_unit) => format!("%{}@{storage_unit}", register.vreg),
+            None => format!("%{}", register.vreg),
+        };
+        let steps = protocol
+            .steps
+            .iter()
+            .map(|step| match step {
+                ProtocolStep::Load { target, fluid } => format!(
+                    "load {} {} {}",
+                    register(target),
+                    fluid.concentration,
+                    fluid.volume
+                ),
+                ProtocolStep::Mix {
+                    inputs: [lhs, rhs],
+                    target,
+                    fluid,
+                } => format!(
+                    "mix {} {} {} {} {}",
+                    register(lhs),
+                    register(rhs),
+                    register(target),
+                    fluid.concentration,
+                    fluid.volume
+                ),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            [
+                "load %0@0 0.2 1",
+                "load %1@1 0 1",
+                "mix %0@0 %1@1 %2@0 0.1 2",
+                "load %3@1 0 2",
+                "mix %2@0 %3@1 %4 0.05 4",
+            ]
+        );
+        assert_eq!(protocol.output, 4);
+    }
+}
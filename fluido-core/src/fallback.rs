@@ -0,0 +1,352 @@
+//! Generators tried one after another, i.e a heuristic mixing the inputs around the target once,
+//! then saturations with coarse and with fine concentration steps.
+//!
+//! Each stage runs with a time limit of its own, and only if the designs of the previous stages
+//! are off from the target by more than the tolerance of the chain. If no stage reaches the
+//! tolerance, the closest design found is kept, so the chain only fails if every stage does.
+use crate::{generate_mixer_sequence, LogConfig, MixerGenerationConfig, MixerGenerator};
+use fluido_generation::{
+    direct_mix::direct_mix, exhaustive::exhaustive_search, extracted_expr, Sequence,
+};
+use fluido_types::{
+    certificate::Certificate, error::MixerGenerationError, fluid::Concentration,
+    input_space::InputSpace, number::Rational, precision::Precision,
+};
+use serde::Serialize;
+use std::{fmt::Display, str::FromStr, time::Instant};
+
+/// A single generator of a fallback chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStage {
+    pub generator: MixerGenerator,
+    /// Seconds the stage may run for, the time limit of the generation config if not set.
+    pub time_limit: Option<u64>,
+    /// Concentration steps of a saturation stage, the ones of the generation config if empty.
+    pub concentration_steps: Vec<f64>,
+}
+
+impl GenerationStage {
+    pub fn new(generator: MixerGenerator) -> Self {
+        Self {
+            generator,
+            time_limit: None,
+            concentration_steps: vec![],
+        }
+    }
+
+    pub fn with_time_limit(mut self, time_limit: u64) -> Self {
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    pub fn with_concentration_steps(mut self, concentration_steps: Vec<f64>) -> Self {
+        self.concentration_steps = concentration_steps;
+        self
+    }
+}
+
+impl FromStr for GenerationStage {
+    type Err = String;
+
+    /// Parses `<generator>[:<step>,...][@<seconds>]`, i.e `direct-mix`, `saturation:0.1@2` or
+    /// `saturation:0.01,0.001@10`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stage, time_limit) = match s.split_once('@') {
+            Some((stage, time_limit)) => {
+                let time_limit = time_limit
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| format!("`{}` is not a number of seconds", time_limit.trim()))?;
+                (stage, Some(time_limit))
+            }
+            None => (s, None),
+        };
+        let (generator, steps) = match stage.split_once(':') {
+            Some((generator, steps)) => (generator, Some(steps)),
+            None => (stage, None),
+        };
+        let generator = generator.trim().parse::<MixerGenerator>()?;
+        let concentration_steps = match steps {
+            Some(_) if generator != MixerGenerator::EqualitySaturation => {
+                return Err(format!("`{generator}` has no concentration steps"))
+            }
+            Some(steps) => steps
+                .split(',')
+                .map(|step| match step.trim().parse::<f64>() {
+                    Ok(step) if step > 0.0 && step < 1.0 => Ok(step),
+                    _ => Err(format!("`{}` is not a concentration step", step.trim())),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![],
+        };
+        Ok(Self {
+            generator,
+            time_limit,
+            concentration_steps,
+        })
+    }
+}
+
+impl Display for GenerationStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.generator)?;
+        if !self.concentration_steps.is_empty() {
+            let steps = self
+                .concentration_steps
+                .iter()
+                .map(|step| step.to_string())
+                .collect::<Vec<_>>();
+            write!(f, ":{}", steps.join(","))?;
+        }
+        if let Some(time_limit) = self.time_limit {
+            write!(f, "@{time_limit}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Stages of the chain in the order they are tried, along with the largest distance to the target
+/// a design may have to be accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FallbackChain {
+    pub stages: Vec<GenerationStage>,
+    pub tolerance: f64,
+}
+
+impl FallbackChain {
+    pub fn new(stages: Vec<GenerationStage>, tolerance: f64) -> Self {
+        Self { stages, tolerance }
+    }
+}
+
+/// How a stage of the chain ended.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StageOutcome {
+    /// The design of the stage is within the tolerance, the stages after it were not run.
+    WithinTolerance {
+        error: f64,
+    },
+    /// The design of the stage is off from the target by more than the tolerance.
+    OffTarget {
+        error: f64,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+impl StageOutcome {
+    /// Human readable outcome, with its error displayed with the precision.
+    pub fn report_with_precision(&self, precision: &Precision) -> String {
+        match self {
+            StageOutcome::WithinTolerance { error } => {
+                format!("within tolerance (off by {})", precision.format(*error))
+            }
+            StageOutcome::OffTarget { error } => {
+                format!("off target by {}", precision.format(*error))
+            }
+            StageOutcome::Failed { reason } => format!("failed: {reason}"),
+        }
+    }
+}
+
+impl Display for StageOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report_with_precision(&Precision::default()))
+    }
+}
+
+/// A stage the chain ran.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageAttempt {
+    /// Description of the stage, in the syntax it is parsed from.
+    pub stage: String,
+    pub outcome: StageOutcome,
+    pub elapsed_seconds: f64,
+}
+
+/// Stages a fallback chain ran, and the one whose design was kept.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FallbackReport {
+    pub attempts: Vec<StageAttempt>,
+    /// Index of the attempt producing the design, the closest one if none is within the tolerance.
+    pub selected: usize,
+}
+
+impl FallbackReport {
+    /// Attempt producing the design.
+    pub fn selected_attempt(&self) -> &StageAttempt {
+        &self.attempts[self.selected]
+    }
+
+    /// Whether the design is within the tolerance of the chain.
+    pub fn within_tolerance(&self) -> bool {
+        matches!(
+            self.selected_attempt().outcome,
+            StageOutcome::WithinTolerance { .. }
+        )
+    }
+}
+
+/// Runs the stages of the chain until one produces a design within its tolerance. The time limit
+/// of each stage is cut short to fit before the deadline. Cancelled and interrupted searches stop
+/// the chain, other failures move on to the next stage.
+pub(crate) fn generate_with_fallback(
+    chain: &FallbackChain,
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    generation_config: &MixerGenerationConfig,
+    logging: &LogConfig,
+    deadline: Option<Instant>,
+) -> Result<(Sequence, FallbackReport), MixerGenerationError> {
+    let mut attempts = vec![];
+    let mut closest: Option<(f64, usize, Sequence)> = None;
+    let mut last_err = MixerGenerationError::TargetUnreachable(target_concentration.clone());
+    for stage in &chain.stages {
+        let started = Instant::now();
+        let generated = match stage.generator {
+            MixerGenerator::DirectMix => {
+                direct_mix(target_concentration, input_space, chain.tolerance)
+            }
+            MixerGenerator::Exhaustive => exhaustive_search(
+                target_concentration,
+                input_space,
+                generation_config.exhaustive.max_mixes,
+            ),
+            MixerGenerator::EqualitySaturation => {
+                let mut stage_config = generation_config.clone();
+                stage_config.generator = stage.generator.clone();
+                if let Some(time_limit) = stage.time_limit {
+                    stage_config.time_limit = time_limit;
+                }
+                if let Some(deadline) = deadline {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    stage_config.time_limit = stage_config.time_limit.min(remaining.as_secs());
+                }
+                if !stage.concentration_steps.is_empty() {
+                    stage_config.concentration_steps = stage.concentration_steps.clone();
+                }
+                generate_mixer_sequence(
+                    target_concentration.clone(),
+                    input_space,
+                    stage_config,
+                    logging,
+                )
+            }
+        };
+        let outcome = match generated.and_then(|sequence| {
+            let error = distance_to_target(&sequence, target_concentration)?;
+            Ok((sequence, error))
+        }) {
+            Ok((sequence, error)) => {
+                let within_tolerance = error <= chain.tolerance;
+                if closest
+                    .as_ref()
+                    .is_none_or(|(closest_error, _, _)| error < *closest_error)
+                {
+                    closest = Some((error, attempts.len(), sequence));
+                }
+                match within_tolerance {
+                    true => StageOutcome::WithinTolerance { error },
+                    false => StageOutcome::OffTarget { error },
+                }
+            }
+            Err(err @ (MixerGenerationError::Cancelled | MixerGenerationError::Interrupted(_))) => {
+                return Err(err)
+            }
+            Err(err) => {
+                let reason = err.to_string();
+                last_err = err;
+                StageOutcome::Failed { reason }
+            }
+        };
+        let reached = matches!(outcome, StageOutcome::WithinTolerance { .. });
+        attempts.push(StageAttempt {
+            stage: stage.to_string(),
+            outcome,
+            elapsed_seconds: started.elapsed().as_secs_f64(),
+        });
+        if reached {
+            break;
+        }
+    }
+    let (_, selected, sequence) = closest.ok_or(last_err)?;
+    Ok((sequence, FallbackReport { attempts, selected }))
+}
+
+/// Exact distance between the concentration the design of the sequence produces and the target.
+fn distance_to_target(
+    sequence: &Sequence,
+    target_concentration: &Concentration,
+) -> Result<f64, MixerGenerationError> {
+    let expr = extracted_expr(&sequence.best_expr)?;
+    let certificate = Certificate::from_expr(&expr)
+        .map_err(|err| MixerGenerationError::SaturationError(err.to_string()))?;
+    let error = certificate.result.concentration - Rational::from(target_concentration);
+    Ok(f64::from(error).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FallbackChain, GenerationStage, StageOutcome};
+    use crate::{Config, LogConfig, MixerGenerationConfig, MixerGenerator};
+    use fluido_types::input_space::InputSpace;
+
+    #[test]
+    fn stages_are_parsed_and_displayed() {
+        let stage = "saturation:0.1,0.01@2".parse::<GenerationStage>().unwrap();
+        assert_eq!(
+            stage,
+            GenerationStage::new(MixerGenerator::EqualitySaturation)
+                .with_concentration_steps(vec![0.1, 0.01])
+                .with_time_limit(2)
+        );
+        assert_eq!(stage.to_string(), "saturation:0.1,0.01@2");
+        assert_eq!(
+            "direct-mix".parse::<GenerationStage>().unwrap(),
+            GenerationStage::new(MixerGenerator::DirectMix)
+        );
+        assert!("direct-mix:0.1".parse::<GenerationStage>().is_err());
+        assert!("saturation:2".parse::<GenerationStage>().is_err());
+        assert!("saturation@soon".parse::<GenerationStage>().is_err());
+        assert!("heuristic".parse::<GenerationStage>().is_err());
+    }
+
+    #[test]
+    fn later_stages_run_only_if_earlier_ones_miss_the_tolerance() {
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        let search = |tolerance| {
+            let stages = vec![
+                GenerationStage::new(MixerGenerator::DirectMix),
+                GenerationStage::new(MixerGenerator::EqualitySaturation).with_time_limit(1),
+            ];
+            let generation = MixerGenerationConfig::new(0, MixerGenerator::EqualitySaturation)
+                .with_fallback(FallbackChain::new(stages, tolerance));
+            crate::search_mixer_design(
+                Config::new(generation, LogConfig::silent()),
+                0.0137.into(),
+                &input_space,
+            )
+            .unwrap()
+        };
+
+        // Mixing the inputs once is close enough.
+        let design = search(0.001);
+        let report = design.fallback_report().unwrap();
+        assert_eq!(report.attempts.len(), 1);
+        assert_eq!(report.selected_attempt().stage, "direct-mix");
+        assert!(report.within_tolerance());
+        assert_eq!(design.mix_count(), 1);
+
+        // Otherwise the saturation runs, and the closer of the designs is kept.
+        let design = search(0.0);
+        let report = design.fallback_report().unwrap();
+        assert_eq!(report.attempts.len(), 2);
+        assert!(matches!(
+            report.attempts[0].outcome,
+            StageOutcome::OffTarget { .. }
+        ));
+        assert_eq!(report.selected_attempt().stage, "saturation@1");
+    }
+}
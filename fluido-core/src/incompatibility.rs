@@ -0,0 +1,130 @@
+//! Validation of designs against inputs which must not be mixed directly.
+//!
+//! The operands of each mix are broken down into the volume each input contributes to them. A mix
+//! of an operand containing one input of an incompatible pair with an operand containing the
+//! other is rejected, unless both of them are diluted to at most the allowed share of their
+//! operand.
+use crate::intermediate_volumes::{render_number, render_operand};
+use fluido_types::{
+    certificate::{Certificate, CertificateOperand},
+    error::DesignValidationError,
+    fluid::{Concentration, Incompatibility, LimitedFloat},
+    number::Rational,
+};
+use std::collections::HashMap;
+
+/// Volume each input concentration contributes to an operand, along with its total volume.
+type Composition = (HashMap<Rational, Rational>, Rational);
+
+/// Fails if any mix of the derivation combines incompatible inputs which are not diluted enough.
+pub fn validate_incompatibilities(
+    certificate: &Certificate,
+    incompatibilities: &[Incompatibility],
+) -> Result<(), DesignValidationError> {
+    if incompatibilities.is_empty() {
+        return Ok(());
+    }
+    let mut compositions: Vec<Composition> = Vec::with_capacity(certificate.steps.len());
+    let composition_of = |compositions: &[Composition], operand| match operand {
+        CertificateOperand::Leaf(leaf) => {
+            let leaf = &certificate.leaves[leaf];
+            (
+                HashMap::from([(leaf.concentration, leaf.volume)]),
+                leaf.volume,
+            )
+        }
+        CertificateOperand::Step(step) => compositions[step].clone(),
+    };
+    for (step, mix) in certificate.steps.iter().enumerate() {
+        let lhs = composition_of(&compositions, mix.lhs);
+        let rhs = composition_of(&compositions, mix.rhs);
+        for incompatibility in incompatibilities {
+            let [a, b] = incompatibility.inputs();
+            for (lhs_input, rhs_input) in [(a, b), (b, a)] {
+                let (Some(lhs_fraction), Some(rhs_fraction)) =
+                    (fraction(&lhs, lhs_input), fraction(&rhs, rhs_input))
+                else {
+                    continue;
+                };
+                let diluted =
+                    |fraction: Rational| f64::from(fraction) <= incompatibility.max_fraction();
+                if !diluted(lhs_fraction) || !diluted(rhs_fraction) {
+                    return Err(DesignValidationError::IncompatibleInputsMixed {
+                        step,
+                        node: render_operand(certificate, CertificateOperand::Step(step)),
+                        incompatibility: incompatibility.clone(),
+                        lhs_fraction: render_number(lhs_fraction),
+                        rhs_fraction: render_number(rhs_fraction),
+                    });
+                }
+            }
+        }
+        let (mut contributions, lhs_volume) = lhs;
+        for (input, volume) in rhs.0 {
+            let contribution = contributions.entry(input).or_insert(Rational::new(0, 1));
+            *contribution = *contribution + volume;
+        }
+        compositions.push((contributions, lhs_volume + rhs.1));
+    }
+    Ok(())
+}
+
+/// Share of the volume of the operand the input makes up, if it is part of the operand. Leaves
+/// within the tolerance of the input count as the input.
+fn fraction((contributions, volume): &Composition, input: &Concentration) -> Option<Rational> {
+    let contribution = contributions
+        .iter()
+        .filter(|(leaf, _)| {
+            LimitedFloat::try_from(**leaf).is_ok_and(|leaf| Incompatibility::matches(input, &leaf))
+        })
+        .fold(Rational::new(0, 1), |sum, (_, contribution)| {
+            sum + *contribution
+        });
+    (!contribution.is_zero()).then(|| contribution / *volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_incompatibilities;
+    use crate::test_utils::design_from_expr;
+    use fluido_types::{error::DesignValidationError, fluid::Incompatibility};
+
+    #[test]
+    fn undiluted_incompatible_inputs_are_rejected() {
+        let direct = design_from_expr("(mix (fluid 0.8 1.0) (fluid 0.4 1.0))");
+        let incompatibility = Incompatibility::new(0.4.into(), 0.8.into());
+        let err = validate_incompatibilities(
+            direct.certificate(),
+            std::slice::from_ref(&incompatibility),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            DesignValidationError::IncompatibleInputsMixed {
+                step: 0,
+                node: "(mix (fluid 0.8 1.0) (fluid 0.4 1.0))".to_string(),
+                incompatibility: incompatibility.clone(),
+                lhs_fraction: "1.0".to_string(),
+                rhs_fraction: "1.0".to_string(),
+            }
+        );
+
+        // 0.8 is diluted to a quarter of the intermediate before it meets 0.4, which itself is a
+        // third of the other operand.
+        let diluted = design_from_expr(
+            "(mix (mix (fluid 0.8 1.0) (fluid 0.0 3.0)) (mix (fluid 0.4 1.0) (fluid 0.0 2.0)))",
+        );
+        let validate = |max_fraction| {
+            validate_incompatibilities(
+                diluted.certificate(),
+                &[incompatibility.clone().with_max_fraction(max_fraction)],
+            )
+        };
+        assert!(validate(0.25).is_err());
+        assert_eq!(validate(0.34), Ok(()));
+        assert_eq!(
+            validate_incompatibilities(diluted.certificate(), &[]),
+            Ok(())
+        );
+    }
+}
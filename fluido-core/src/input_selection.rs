@@ -0,0 +1,100 @@
+//! Selecting a subset of the input space for a design with a limited number of inputs.
+//!
+//! A target can only be reached by mixing if it lies between the smallest and the largest input
+//! concentration used. So promising subsets are the ones bracketing the target, preferring fewer
+//! inputs and inputs closer to the target.
+use fluido_types::{
+    fluid::Concentration,
+    input_space::{Input, InputSpace},
+};
+
+/// Number of inputs closest to the target, on each side of it, that subsets are built from.
+const POOL_SIZE_PER_SIDE: usize = 4;
+
+/// Maximum number of subsets evaluated for a design.
+pub const MAX_CANDIDATE_SUBSETS: usize = 8;
+
+/// Returns the subsets of at most `max_inputs` inputs which can possibly reach the target, most
+/// promising first.
+pub fn candidate_subsets(
+    input_space: &InputSpace,
+    target_concentration: &Concentration,
+    max_inputs: usize,
+) -> Vec<InputSpace> {
+    let mut inputs = input_space.iter().cloned().collect::<Vec<Input>>();
+    inputs.sort_by(|lhs, rhs| lhs.concentration().cmp(rhs.concentration()));
+    inputs.dedup_by(|lhs, rhs| lhs.concentration() == rhs.concentration());
+
+    // Closest inputs below and above the target.
+    let split = inputs.partition_point(|input| input.concentration() < target_concentration);
+    let below_start = split.saturating_sub(POOL_SIZE_PER_SIDE);
+    let above_end = (split + POOL_SIZE_PER_SIDE).min(inputs.len());
+    let pool = &inputs[below_start..above_end];
+
+    let mut subsets = (1..(1u32 << pool.len()))
+        .filter(|mask| mask.count_ones() as usize <= max_inputs)
+        .map(|mask| {
+            pool.iter()
+                .enumerate()
+                .filter(|(ix, _)| mask & (1 << ix) != 0)
+                .map(|(_, input)| input.clone())
+                .collect::<Vec<_>>()
+        })
+        .filter(|subset| {
+            // Subsets are sorted by concentration.
+            let min = subset[0].concentration();
+            let max = subset[subset.len() - 1].concentration();
+            min <= target_concentration && target_concentration <= max
+        })
+        .collect::<Vec<_>>();
+    subsets.sort_by_key(|subset| {
+        let spread =
+            subset[subset.len() - 1].concentration().clone() - subset[0].concentration().clone();
+        (subset.len(), spread)
+    });
+    subsets.truncate(MAX_CANDIDATE_SUBSETS);
+    subsets.into_iter().map(InputSpace::new).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::candidate_subsets;
+    use fluido_types::{fluid::Concentration, input_space::InputSpace};
+
+    fn concentrations(subset: &InputSpace) -> Vec<String> {
+        subset
+            .iter()
+            .map(|input| input.concentration().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn subsets_bracket_the_target() {
+        let input_space = InputSpace::from_concentrations(
+            [0.0, 0.1, 0.5, 0.8, 0.1]
+                .into_iter()
+                .map(Concentration::from),
+        );
+        let subsets = candidate_subsets(&input_space, &Concentration::from(0.3), 2);
+        let subsets = subsets.iter().map(concentrations).collect::<Vec<_>>();
+        assert_eq!(
+            subsets,
+            vec![
+                vec!["0.1", "0.5"],
+                vec!["0.0", "0.5"],
+                vec!["0.1", "0.8"],
+                vec!["0.0", "0.8"]
+            ]
+        );
+    }
+
+    #[test]
+    fn single_input_only_for_exact_match() {
+        let input_space =
+            InputSpace::from_concentrations([0.0, 0.2, 0.4].into_iter().map(Concentration::from));
+        let subsets = candidate_subsets(&input_space, &Concentration::from(0.2), 1);
+        assert_eq!(subsets.len(), 1);
+        assert_eq!(concentrations(&subsets[0]), vec!["0.2"]);
+        assert!(candidate_subsets(&input_space, &Concentration::from(0.3), 1).is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Validation of designs against the maximum volume of their intermediates.
+use fluido_types::{
+    certificate::{Certificate, CertificateOperand},
+    error::DesignValidationError,
+    fluid::{LimitedFloat, Volume},
+    number::Rational,
+};
+
+/// Fails if any intermediate of the derivation has a larger volume than the maximum. The final
+/// fluid of the design is the target itself, not an intermediate.
+pub fn validate_intermediate_volumes(
+    certificate: &Certificate,
+    max_intermediate_volume: Option<&Volume>,
+) -> Result<(), DesignValidationError> {
+    let Some(max_intermediate_volume) = max_intermediate_volume else {
+        return Ok(());
+    };
+    let max = Rational::from(max_intermediate_volume);
+    let intermediates = certificate.steps.len().saturating_sub(1);
+    for (step, intermediate) in certificate.steps[..intermediates].iter().enumerate() {
+        let volume = intermediate.result.volume;
+        if (volume - max).numerator() > 0 {
+            return Err(DesignValidationError::IntermediateVolumeExceeded {
+                step,
+                node: render_operand(certificate, CertificateOperand::Step(step)),
+                volume: render_number(volume),
+                max: max_intermediate_volume.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Renders the operand as a mixer expression.
+pub(crate) fn render_operand(certificate: &Certificate, operand: CertificateOperand) -> String {
+    match operand {
+        CertificateOperand::Leaf(leaf) => {
+            let leaf = &certificate.leaves[leaf];
+            format!(
+                "(fluid {} {})",
+                render_number(leaf.concentration),
+                render_number(leaf.volume)
+            )
+        }
+        CertificateOperand::Step(step) => {
+            let step = &certificate.steps[step];
+            format!(
+                "(mix {} {})",
+                render_operand(certificate, step.lhs),
+                render_operand(certificate, step.rhs)
+            )
+        }
+    }
+}
+
+/// Renders the number as a decimal if it can be represented as one, as a fraction otherwise.
+pub(crate) fn render_number(number: Rational) -> String {
+    LimitedFloat::try_from(number).map_or_else(|_| number.to_string(), |lf| lf.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_intermediate_volumes;
+    use crate::test_utils::design_from_expr;
+    use fluido_types::{error::DesignValidationError, fluid::Volume};
+
+    #[test]
+    fn intermediates_above_the_maximum_are_rejected() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.8 1.0) (fluid 0.0 3.0)) (fluid 0.0 4.0))");
+        let max = Volume::from(3.0);
+        let err = validate_intermediate_volumes(design.certificate(), Some(&max)).unwrap_err();
+        assert_eq!(
+            err,
+            DesignValidationError::IntermediateVolumeExceeded {
+                step: 0,
+                node: "(mix (fluid 0.8 1.0) (fluid 0.0 3.0))".to_string(),
+                volume: "4.0".to_string(),
+                max,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Mix 0 `(mix (fluid 0.8 1.0) (fluid 0.0 3.0))` produces an intermediate of volume 4.0, which exceeds the maximum intermediate volume of 3.0."
+        );
+
+        // The final fluid is not an intermediate.
+        let max = Volume::from(4.0);
+        assert_eq!(
+            validate_intermediate_volumes(design.certificate(), Some(&max)),
+            Ok(())
+        );
+    }
+}
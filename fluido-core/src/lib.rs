@@ -1,31 +1,177 @@
-use fluido_generation::Sequence;
+pub mod allocation_hint;
+pub mod artifacts;
+pub mod benchmark;
+pub mod concentration_windows;
+pub mod confidence;
+pub mod doctor;
+pub mod export;
+pub mod fallback;
+pub mod incompatibility;
+pub mod input_selection;
+pub mod intermediate_volumes;
+pub mod merge;
+pub mod mix_ratios;
+pub mod mix_tree;
+pub mod pipeline;
+pub mod planning;
+pub mod pool;
+pub mod reagents;
+pub mod recipe;
+pub mod required_intermediates;
+#[cfg(feature = "results-db")]
+pub mod results_db;
+pub mod run_directory;
+pub mod schedule;
+pub mod sensitivity;
+pub mod simulation;
+pub mod stability;
+pub mod storage_pins;
+
+use allocation_hint::AllocationHint;
+use artifacts::RunArtifacts;
+use confidence::Confidence;
+use export::protocol::{to_protocol, Protocol};
+use fallback::{FallbackChain, FallbackReport};
+pub use fluido_generation::{
+    checkpoint::CheckpointConfig,
+    class_dump::ClassDumpConfig,
+    exhaustive::{ExhaustiveLimits, EXHAUSTIVE_MAX_VOLUME},
+    extract::{ExtractionMode, ExtractionRoot},
+    lower_bound::equal_mixes_lower_bound,
+    progress::{ProgressCallback, SaturationProgress},
+    report::{SaturationReport, SaturationStopReason},
+    warm_start::WarmExtractor,
+    CostObjective, InputTier, MixTimeModel, ProximityCost, COARSE_TO_FINE_STAGES,
+    DEFAULT_ITER_LIMIT, DEFAULT_NODE_LIMIT, MAX_EXPANDED_CONCENTRATIONS, MAX_EXPANSION_DEPTH,
+};
+use fluido_generation::{
+    direct_mix::direct_mix, exhaustive::exhaustive_search, extracted_expr,
+    multi_reagent::mix_reagents, saturate_many, MixRewrite, SaturationConfig, Sequence,
+};
 use fluido_ir::{
-    analysis::liveness::LivenessAnalysis,
-    graph::Graph,
-    ir::IROp,
+    analysis::liveness::{LivenessAnalysis, LivenessResult},
+    ir::{evaluate_ir, linearize_ir_with_precision},
     ir_builder::IRBuilder,
     pass_manager::IRPassManager,
     regalloc::interference_graph::{InterferenceGraph, InterferenceGraphBuilder},
 };
-use fluido_parse::parser::Parse;
+pub use fluido_ir::{
+    graph::Graph,
+    ir::IROp,
+    pass_manager::{AnalysisPass, AnalysisResult, AnalysisResults},
+    regalloc::{
+        interference_graph::{
+            ColorCountBounds, ColorCountStrategy, RegAllocStrategy, EXACT_CLIQUE_MAX_NODES,
+        },
+        register_allocator::{RegisterAllocation, RegisterAllocator},
+    },
+};
 use fluido_types::{
+    certificate::Certificate,
+    decimal,
     error::{
-        FluidoError, IRGenerationError, InterefenceGraphGenerationError, MixerGenerationError,
+        ExportError, FluidoError, IRGenerationError, InterefenceGraphGenerationError,
+        MixerGenerationError,
     },
     expr::Expr,
-    fluid::{Concentration, Fluid},
+    fluid::{
+        Concentration, ConcentrationWindow, Fluid, Incompatibility, MixerCapabilities, Volume,
+    },
+    input_space::InputSpace,
+    log::LogSink,
+    precision::Precision,
 };
+use input_selection::candidate_subsets;
+use mix_tree::MixTree;
+use pipeline::{Pipeline, PipelineState, PostExtractHook, PrintAfter, StorageBoundsCallback};
+use reagents::{ReagentLimits, ReagentUsage};
+use required_intermediates::RequiredIntermediate;
+use schedule::ScheduleEstimate;
+use sensitivity::SensitivityReport;
+use serde::Serialize;
+use stability::StabilityViolation;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{Display, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+use storage_pins::StoragePin;
 
 /// A mixer generator for a specific target concentration from a given input space.
+#[derive(Debug, Clone, Serialize)]
 pub struct MixerDesign {
-    mixer_expr: String,
+    /// Mixes of the design, with the storage unit and schedule step of each.
+    mix_tree: MixTree,
     cost: f64,
     storage_units_needed: u64,
+    certificate: Certificate,
+    reagent_usage: Vec<ReagentUsage>,
+    estimated_time: Option<f64>,
+    schedule: ScheduleEstimate,
+    /// Lower bound of the storage units, set if the total time limit was reached before the
+    /// minimum was found. `storage_units_needed` is then the number of units of a greedy
+    /// allocation, which suffices but may not be the minimum.
+    storage_units_lower_bound: Option<u64>,
+    /// Size of the largest clique of the interference graph. Fluids of a clique are all stored at
+    /// once, so this is a lower bound of the storage units, which usually meets them.
+    storage_units_clique_bound: u64,
+    /// Mixes producing the required intermediates of the search.
+    required_intermediates: Vec<RequiredIntermediate>,
+    sensitivity: SensitivityReport,
+    /// Intermediates consumed later than their inputs stay stable for.
+    stability_violations: Vec<StabilityViolation>,
+    confidence: Confidence,
+    /// Whether the design is proven to reach the target with the fewest mixes, see
+    /// `MixerGenerator::Exhaustive`.
+    optimal: bool,
+    /// SHA-256 of the canonical recipe of the design, equal for designs prescribing the same
+    /// protocol.
+    recipe_hash: String,
+    #[serde(skip)]
+    expr: Expr,
+    #[serde(skip)]
+    ir: Vec<IROp>,
+    /// Storage unit allocated to each virtual register of the ir.
+    #[serde(skip)]
+    storage_allocation: HashMap<usize, u64>,
+    /// Results of the analysis passes registered with `Config::with_analysis_pass`.
+    #[serde(skip)]
+    analysis_results: AnalysisResults,
+    /// Stages of the fallback chain, if the search was configured with one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fallback_report: Option<FallbackReport>,
+    saturation_report: Option<SaturationReport>,
+}
+
+/// A fluid produced by a mix of the design and consumed by a later one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntermediateFluid {
+    /// Virtual register the fluid is produced into.
+    pub vreg: usize,
+    #[serde(serialize_with = "decimal::serialize_fluid")]
+    pub fluid: Fluid,
+    /// Storage unit holding the fluid until it is consumed.
+    pub storage_unit: u64,
 }
 
 impl MixerDesign {
-    pub fn mixer_expr(&self) -> &str {
-        &self.mixer_expr
+    /// Mixes of the design as a tree, with the fluid, storage unit and schedule step of each.
+    pub fn mix_tree(&self) -> &MixTree {
+        &self.mix_tree
+    }
+
+    /// Mixer expression of the design, i.e `(mix (fluid 0.2 1.0) (fluid 0.0 1.0))`, the
+    /// displayed `mix_tree`.
+    pub fn mixer_expr(&self) -> String {
+        self.mix_tree.to_string()
+    }
+
+    /// Parsed mixer expression of the design.
+    pub fn expr(&self) -> &Expr {
+        &self.expr
     }
 
     pub fn cost(&self) -> f64 {
@@ -35,6 +181,187 @@ impl MixerDesign {
     pub fn storage_units_needed(&self) -> u64 {
         self.storage_units_needed
     }
+
+    /// Lower bound of the storage units, if the total time limit was reached before the minimum
+    /// number of storage units was found, or the search stopped at the storage bound gap.
+    pub fn storage_units_lower_bound(&self) -> Option<u64> {
+        self.storage_units_lower_bound
+    }
+
+    /// Lower bound of the storage units certified by a clique of the interference graph, exact
+    /// for graphs of up to `EXACT_CLIQUE_MAX_NODES` virtual registers.
+    pub fn storage_units_clique_bound(&self) -> u64 {
+        self.storage_units_clique_bound
+    }
+
+    /// Whether the search of the storage units was cut short, i.e the storage unit count is not
+    /// exact.
+    pub fn is_partial(&self) -> bool {
+        self.storage_units_lower_bound.is_some()
+    }
+
+    /// Required intermediate concentrations of the search, along with the mix producing each.
+    pub fn required_intermediates(&self) -> &[RequiredIntermediate] {
+        &self.required_intermediates
+    }
+
+    /// Sensitivity of the concentration the design produces to its leaves and inputs.
+    pub fn sensitivity(&self) -> &SensitivityReport {
+        &self.sensitivity
+    }
+
+    /// Intermediates of the design consumed later than one of their inputs stays stable for,
+    /// empty if no input has a limited stability.
+    pub fn stability_violations(&self) -> &[StabilityViolation] {
+        &self.stability_violations
+    }
+
+    /// How much the design can be trusted, i.e whether it produces the target exactly.
+    pub fn confidence(&self) -> Confidence {
+        self.confidence
+    }
+
+    /// Whether the design is proven to reach the target with the fewest mixes of any design within
+    /// `EXHAUSTIVE_MAX_VOLUME` units of volume. Only designs of the exhaustive search are.
+    pub fn is_optimal(&self) -> bool {
+        self.optimal
+    }
+
+    /// Canonical hash of the recipe of the design, see `recipe::recipe_hash`.
+    pub fn recipe_hash(&self) -> &str {
+        &self.recipe_hash
+    }
+
+    /// Exact derivation of the concentration this design produces.
+    pub fn certificate(&self) -> &Certificate {
+        &self.certificate
+    }
+
+    /// Number of mix operations needed to execute the design.
+    pub fn mix_count(&self) -> usize {
+        self.ir
+            .iter()
+            .filter(|op| matches!(op, IROp::Mix(_)))
+            .count()
+    }
+
+    /// Volume of each input fluid consumed by the design, including dead volumes.
+    pub fn reagent_usage(&self) -> &[ReagentUsage] {
+        &self.reagent_usage
+    }
+
+    /// Distinct input concentrations the design draws from, in ascending order.
+    pub fn inputs_used(&self) -> Vec<Concentration> {
+        fn collect(expr: &Expr, inputs: &mut BTreeSet<Concentration>) {
+            match expr {
+                Expr::Mix(lhs, rhs) => {
+                    collect(lhs, inputs);
+                    collect(rhs, inputs);
+                }
+                Expr::Fluid(fluid) => {
+                    inputs.insert(fluid.concentration().clone());
+                }
+                Expr::LimitedFloat(_) => {}
+            }
+        }
+        let mut inputs = BTreeSet::new();
+        collect(&self.expr, &mut inputs);
+        inputs.into_iter().collect()
+    }
+
+    /// Estimated total time of the mixes in the design, if a mix time model is configured.
+    pub fn estimated_time(&self) -> Option<f64> {
+        self.estimated_time
+    }
+
+    /// Critical path and makespan of the design on the configured number of mixers. Mixes take
+    /// one unit of time each, unless a mix time model is configured.
+    pub fn schedule(&self) -> &ScheduleEstimate {
+        &self.schedule
+    }
+
+    /// Fewest mixers the design runs on within its critical path, reusing each mixer for as many
+    /// mixes as that allows.
+    pub fn min_mixers(&self) -> usize {
+        self.schedule.min_mixers
+    }
+
+    /// Flat ir of the design, in execution order.
+    pub fn ir(&self) -> &[IROp] {
+        &self.ir
+    }
+
+    /// Storage unit of each virtual register of the ir, including the final fluid, which is put
+    /// into the first unit its operands free.
+    pub fn register_allocation(&self) -> RegisterAllocation {
+        RegisterAllocator::new(&self.ir).allocate_with_coloring(self.storage_allocation.clone())
+    }
+
+    /// Flat ir of the design with the storage unit of each virtual register in its place, for
+    /// controllers executing it.
+    pub fn physical_ir(&self) -> Vec<IROp> {
+        self.register_allocation().rewrite(&self.ir)
+    }
+
+    /// Results of the analysis passes registered with `Config::with_analysis_pass`, looked up by
+    /// pass name and result type.
+    pub fn analysis_results(&self) -> &AnalysisResults {
+        &self.analysis_results
+    }
+
+    /// Stages the fallback chain of the search ran, and the one which produced the design. Not
+    /// set unless the search was configured with `MixerGenerationConfig::with_fallback`.
+    pub fn fallback_report(&self) -> Option<&FallbackReport> {
+        self.fallback_report.as_ref()
+    }
+
+    /// Statistics of the saturation the design was extracted from. Not set for designs which
+    /// were not saturated for, i.e given by the user or searched exhaustively.
+    pub fn saturation_report(&self) -> Option<&SaturationReport> {
+        self.saturation_report.as_ref()
+    }
+
+    /// Every intermediate fluid the design produces, in execution order, along with the storage
+    /// unit it occupies. The final fluid of the design is not an intermediate.
+    pub fn intermediate_fluids(&self) -> Vec<IntermediateFluid> {
+        let fluids = evaluate_ir(&self.ir);
+        let last_mix = self.ir.iter().rposition(|op| matches!(op, IROp::Mix(_)));
+        self.ir
+            .iter()
+            .enumerate()
+            .filter(|(ix, op)| matches!(op, IROp::Mix(_)) && Some(*ix) != last_mix)
+            .filter_map(|(_, op)| {
+                let vreg = op.target_vreg()?;
+                Some(IntermediateFluid {
+                    vreg,
+                    fluid: fluids.get(&vreg)?.clone(),
+                    storage_unit: *self.storage_allocation.get(&vreg)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Flat ir of the design as a linear protocol, with the fluid held by each virtual register.
+    pub fn linear_listing(&self) -> String {
+        self.linear_listing_with_precision(&Precision::Full)
+    }
+
+    /// Same as `linear_listing`, with the fluids displayed at the given precision.
+    pub fn linear_listing_with_precision(&self, precision: &Precision) -> String {
+        linearize_ir_with_precision(&self.ir, precision)
+    }
+
+    /// Storage unit of each fluid the design keeps in storage, in execution order. Can be given
+    /// to later searches as an `AllocationHint`, to keep the assignments stable.
+    pub fn storage_assignments(&self) -> AllocationHint {
+        AllocationHint::from_allocation(&self.ir, &self.storage_allocation)
+    }
+
+    /// Steps of the design in execution order, with the registers, storage units and fluids of
+    /// each, serializable for controllers executing it.
+    pub fn to_protocol(&self) -> Result<Protocol, ExportError> {
+        to_protocol(self)
+    }
 }
 
 /// General configuration for fluido. Contains configuration settings for:
@@ -44,6 +371,22 @@ impl MixerDesign {
 pub struct Config {
     generation: MixerGenerationConfig,
     logging: LogConfig,
+    reagent_limits: Vec<ReagentLimits>,
+    input_uncertainties: HashMap<Concentration, f64>,
+    input_stability: HashMap<Concentration, usize>,
+    max_inputs: Option<usize>,
+    mixers: usize,
+    pipeline: Pipeline,
+    capture_artifacts: bool,
+    deadline: Option<Instant>,
+    allocation_hint: AllocationHint,
+    storage_pins: Vec<StoragePin>,
+    analysis_passes: Vec<Arc<dyn AnalysisPass>>,
+    storage_bound_gap: u64,
+    color_count_strategy: ColorCountStrategy,
+    reg_alloc_strategy: RegAllocStrategy,
+    storage_bounds: Option<StorageBoundsCallback>,
+    post_extract_hook: Option<PostExtractHook>,
 }
 
 impl Config {
@@ -51,116 +394,647 @@ impl Config {
         Self {
             generation,
             logging,
+            reagent_limits: vec![],
+            input_uncertainties: HashMap::new(),
+            input_stability: HashMap::new(),
+            max_inputs: None,
+            mixers: 1,
+            pipeline: Pipeline::default(),
+            capture_artifacts: false,
+            deadline: None,
+            allocation_hint: AllocationHint::default(),
+            storage_pins: vec![],
+            analysis_passes: vec![],
+            storage_bound_gap: 0,
+            color_count_strategy: ColorCountStrategy::default(),
+            reg_alloc_strategy: RegAllocStrategy::default(),
+            storage_bounds: None,
+            post_extract_hook: None,
         }
     }
+
+    /// Caps the total time of the search, starting from now. Saturation is cut short to fit into
+    /// it, and if the minimum number of storage units is not found in time, a greedy allocation is
+    /// reported along with a lower bound instead.
+    pub fn with_max_total_time(mut self, max_total_time: Duration) -> Self {
+        self.deadline = Some(Instant::now() + max_total_time);
+        self
+    }
+
+    /// Stops searching the minimum number of storage units once it is known within the gap, i.e a
+    /// gap of one may report 5 storage units along with a lower bound of 4. Defaults to zero, the
+    /// exact minimum.
+    pub fn with_storage_bound_gap(mut self, storage_bound_gap: u64) -> Self {
+        self.storage_bound_gap = storage_bound_gap;
+        self
+    }
+
+    /// How the minimum number of storage units is searched for, defaults to a binary search. The
+    /// optimizer finds it with a single solver call, but reports no bounds until it finishes.
+    pub fn with_color_count_strategy(mut self, color_count_strategy: ColorCountStrategy) -> Self {
+        self.color_count_strategy = color_count_strategy;
+        self
+    }
+
+    /// Whether storage is allocated with the minimum number of units, found with the solver, or
+    /// heuristically without it. Defaults to `RegAllocStrategy::Exact`, which allocates
+    /// heuristically anyway if fluido-core is built without the `smt` feature.
+    pub fn with_reg_alloc_strategy(mut self, reg_alloc_strategy: RegAllocStrategy) -> Self {
+        self.reg_alloc_strategy = reg_alloc_strategy;
+        self
+    }
+
+    /// Reports the bounds of the minimum number of storage units as the search tightens them.
+    pub fn with_storage_bounds(mut self, storage_bounds: StorageBoundsCallback) -> Self {
+        self.storage_bounds = Some(storage_bounds);
+        self
+    }
+
+    /// Transforms each extracted design before it is validated and lowered. The transformed
+    /// design is validated like the extracted one, and is reported with the cost of the extracted
+    /// one. Designs given by the user are not transformed.
+    pub fn with_post_extract_hook(mut self, post_extract_hook: PostExtractHook) -> Self {
+        self.post_extract_hook = Some(post_extract_hook);
+        self
+    }
+
+    /// Phases each search runs through, defaults to `Pipeline::default()`.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Capture the intermediate artifacts of the search into `RunArtifacts`, returned by
+    /// `search_mixer_design_with_artifacts`.
+    pub fn with_artifact_capture(mut self, capture_artifacts: bool) -> Self {
+        self.capture_artifacts = capture_artifacts;
+        self
+    }
+
+    /// Storage assignments of a previous run, kept for the fluids the design still stores. The
+    /// number of storage units stays the minimum regardless of the hint.
+    pub fn with_allocation_hint(mut self, allocation_hint: AllocationHint) -> Self {
+        self.allocation_hint = allocation_hint;
+        self
+    }
+
+    /// Inputs kept in fixed storage units, i.e inputs entering through dedicated ports. Unlike the
+    /// allocation hint, pins are kept even if they need more storage units than the minimum, and
+    /// the search fails if no allocation can keep them.
+    pub fn with_storage_pins(mut self, storage_pins: Vec<StoragePin>) -> Self {
+        self.storage_pins = storage_pins;
+        self
+    }
+
+    /// Registers an analysis pass of the user, run over the ir of each design along with the
+    /// builtin analyses. Its result is available from `MixerDesign::analysis_results` under the
+    /// name of the pass, which should differ from the names of the other registered passes.
+    pub fn with_analysis_pass(mut self, analysis_pass: impl AnalysisPass + 'static) -> Self {
+        self.analysis_passes.push(Arc::new(analysis_pass));
+        self
+    }
+
+    /// Number of independent mixers the design is scheduled on, defaults to one.
+    pub fn with_mixers(mut self, mixers: usize) -> Self {
+        self.mixers = mixers;
+        self
+    }
+
+    /// Limits the number of distinct inputs a design may use. The most promising subsets of the
+    /// input space are searched one after another, each within the configured time limit, and
+    /// the cheapest design is selected.
+    pub fn with_max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = Some(max_inputs);
+        self
+    }
+
+    pub fn with_logging(mut self, logging: LogConfig) -> Self {
+        self.logging = logging;
+        self
+    }
+
+    /// Where the logs are printed to, keeping the rest of the logging config.
+    pub fn with_log_sink(mut self, sink: LogSink) -> Self {
+        self.logging.sink = sink;
+        self
+    }
+
+    /// Reservoir limits of the input fluids, designs drawing an input below its dead volume are
+    /// rejected.
+    pub fn with_reagent_limits(mut self, reagent_limits: Vec<ReagentLimits>) -> Self {
+        self.reagent_limits = reagent_limits;
+        self
+    }
+
+    /// Uncertainty of the concentration of each input by its concentration, used as the
+    /// perturbation of its leaves when analyzing the sensitivity of the design.
+    pub fn with_input_uncertainties(
+        mut self,
+        input_uncertainties: HashMap<Concentration, f64>,
+    ) -> Self {
+        self.input_uncertainties = input_uncertainties;
+        self
+    }
+
+    /// Number of mixes each input stays stable for by its concentration. Intermediates containing
+    /// an input and consumed later than that are reported as stability violations of the design.
+    pub fn with_input_stability(mut self, input_stability: HashMap<Concentration, usize>) -> Self {
+        self.input_stability = input_stability;
+        self
+    }
 }
 
 /// Settings for controlling various logging options.
 #[derive(Debug, Clone)]
 pub struct LogConfig {
+    show_saturation_report: bool,
     show_mixer_graph: bool,
     show_ir: bool,
     show_liveness: bool,
     show_interference_graph: bool,
+    print_after: PrintAfter,
+    precision: Precision,
+    sink: LogSink,
 }
 
 impl LogConfig {
     pub fn new(
+        show_saturation_report: bool,
         show_mixer_graph: bool,
         show_ir: bool,
         show_liveness: bool,
         show_interference_graph: bool,
     ) -> Self {
         Self {
+            show_saturation_report,
             show_mixer_graph,
             show_ir,
             show_liveness,
             show_interference_graph,
+            print_after: PrintAfter::default(),
+            precision: Precision::default(),
+            sink: LogSink::stdout(),
         }
     }
     pub fn silent() -> Self {
         Self {
+            show_saturation_report: false,
             show_mixer_graph: false,
             show_ir: false,
             show_liveness: false,
             show_interference_graph: false,
+            print_after: PrintAfter::default(),
+            precision: Precision::default(),
+            sink: LogSink::stdout(),
         }
     }
+
+    /// Phases of the pipeline the ir is dumped after, for debugging where the output of the
+    /// pipeline diverges from expectations.
+    pub fn with_print_after(mut self, print_after: PrintAfter) -> Self {
+        self.print_after = print_after;
+        self
+    }
+
+    /// Precision of the numbers in the printed saturation report, mixer graph and ir dumps, also
+    /// used for the captured artifacts. Defaults to `Precision::Readable`.
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Where the logs are printed to, defaults to stdout. Searches running concurrently should
+    /// each get their own sink, so that their logs do not interleave.
+    pub fn with_sink(mut self, sink: LogSink) -> Self {
+        self.sink = sink;
+        self
+    }
 }
 
 /// Different types of mixer generation handlers.
-///
-/// Currently fluido only supports equality saturation for mixer generation but it will eventually
-/// add support for heuristics to generate initial mixer.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MixerGenerator {
     EqualitySaturation,
+    /// Mixes the two inputs around the target once, in the volume ratio closest to it, see
+    /// `fluido_generation::direct_mix`. Takes no time but only approximates most targets, so it
+    /// is mostly useful as the first stage of a fallback chain.
+    DirectMix,
+    /// Enumerates every design up to the mixes of the `ExhaustiveLimits` of the config, see
+    /// `fluido_generation::exhaustive`. Proves the design reaching the target with
+    /// the fewest mixes optimal, but only the tiniest problems finish in time.
+    Exhaustive,
+}
+
+impl Display for MixerGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MixerGenerator::EqualitySaturation => write!(f, "saturation"),
+            MixerGenerator::DirectMix => write!(f, "direct-mix"),
+            MixerGenerator::Exhaustive => write!(f, "exhaustive"),
+        }
+    }
+}
+
+impl FromStr for MixerGenerator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "saturation" => Ok(MixerGenerator::EqualitySaturation),
+            "direct-mix" => Ok(MixerGenerator::DirectMix),
+            "exhaustive" => Ok(MixerGenerator::Exhaustive),
+            _ => Err(format!(
+                "unknown generator `{s}`, expected `saturation`, `direct-mix` or `exhaustive`"
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct MixerGenerationConfig {
     time_limit: u64,
+    node_limit: Option<usize>,
+    iter_limit: Option<usize>,
     generator: MixerGenerator,
+    checkpoint: Option<CheckpointConfig>,
+    resume_from: Option<PathBuf>,
+    objective: CostObjective,
+    mix_time_model: Option<MixTimeModel>,
+    proximity_cost: ProximityCost,
+    extraction: ExtractionMode,
+    extraction_root: ExtractionRoot,
+    forbidden_windows: Vec<ConcentrationWindow>,
+    incompatibilities: Vec<Incompatibility>,
+    max_intermediate_volume: Option<Volume>,
+    capabilities: Option<MixerCapabilities>,
+    required_intermediates: Vec<Concentration>,
+    custom_rules: Vec<MixRewrite>,
+    target_class_dump: Option<ClassDumpConfig>,
+    concentration_steps: Vec<f64>,
+    stages: Vec<f64>,
+    expansion_depth: Option<usize>,
+    cancellation: Option<Arc<AtomicBool>>,
+    progress: Option<ProgressCallback>,
+    fallback: Option<FallbackChain>,
+    exhaustive: ExhaustiveLimits,
 }
 
 impl MixerGenerationConfig {
     pub fn new(time_limit: u64, generator: MixerGenerator) -> Self {
         Self {
             time_limit,
+            node_limit: None,
+            iter_limit: None,
             generator,
+            checkpoint: None,
+            resume_from: None,
+            objective: CostObjective::MixCount,
+            mix_time_model: None,
+            proximity_cost: ProximityCost::Flat,
+            extraction: ExtractionMode::Penalized,
+            extraction_root: ExtractionRoot::Target,
+            forbidden_windows: vec![],
+            incompatibilities: vec![],
+            max_intermediate_volume: None,
+            capabilities: None,
+            required_intermediates: vec![],
+            custom_rules: vec![],
+            target_class_dump: None,
+            concentration_steps: vec![],
+            stages: vec![],
+            expansion_depth: None,
+            cancellation: None,
+            progress: None,
+            fallback: None,
+            exhaustive: ExhaustiveLimits::default(),
         }
     }
+
+    /// Number of e-nodes the saturation stops at, defaults to `DEFAULT_NODE_LIMIT`. A saturation
+    /// stopped by it still extracts the best design found so far.
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = Some(node_limit);
+        self
+    }
+
+    /// Number of iterations the saturation stops after, defaults to `DEFAULT_ITER_LIMIT`.
+    pub fn with_iter_limit(mut self, iter_limit: usize) -> Self {
+        self.iter_limit = Some(iter_limit);
+        self
+    }
+
+    /// Whether arithmetic nodes can be part of the generated mixer, defaults to penalizing them.
+    pub fn with_extraction(mut self, extraction: ExtractionMode) -> Self {
+        self.extraction = extraction;
+        self
+    }
+
+    /// Which e-classes the design is extracted from, defaults to the one of the target node.
+    pub fn with_extraction_root(mut self, extraction_root: ExtractionRoot) -> Self {
+        self.extraction_root = extraction_root;
+        self
+    }
+
+    /// What the generated mixer is optimized for, defaults to the number of mixes.
+    pub fn with_objective(mut self, objective: CostObjective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Model for estimating the time of mixes. Used when optimizing for
+    /// `CostObjective::MixTime` and for reporting the estimated time of the design.
+    pub fn with_mix_time_model(mut self, mix_time_model: MixTimeModel) -> Self {
+        self.mix_time_model = Some(mix_time_model);
+        self
+    }
+
+    /// How leaves of concentrations none of the inputs has are penalized, defaults to
+    /// `ProximityCost::Flat`.
+    pub fn with_proximity_cost(mut self, proximity_cost: ProximityCost) -> Self {
+        self.proximity_cost = proximity_cost;
+        self
+    }
+
+    /// Periodically checkpoint the search state, so that an interrupted search can be resumed.
+    pub fn with_checkpoint(mut self, checkpoint: CheckpointConfig) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Resume the search from a checkpoint written by a previous run.
+    pub fn with_resume_from(mut self, resume_from: PathBuf) -> Self {
+        self.resume_from = Some(resume_from);
+        self
+    }
+
+    /// Concentration ranges the design must not pass through, no intermediate fluid of the
+    /// design has a concentration inside them.
+    pub fn with_forbidden_windows(mut self, forbidden_windows: Vec<ConcentrationWindow>) -> Self {
+        self.forbidden_windows = forbidden_windows;
+        self
+    }
+
+    /// Pairs of inputs the design must not mix directly. Each input of a pair only meets the
+    /// other once it is diluted to at most the allowed share of the fluid carrying it.
+    pub fn with_incompatibilities(mut self, incompatibilities: Vec<Incompatibility>) -> Self {
+        self.incompatibilities = incompatibilities;
+        self
+    }
+
+    /// Largest volume an intermediate fluid of the design may have, in units of the smallest fluid
+    /// of the design.
+    pub fn with_max_intermediate_volume(mut self, max_intermediate_volume: Volume) -> Self {
+        self.max_intermediate_volume = Some(max_intermediate_volume);
+        self
+    }
+
+    /// Volume ratios the mixer of the hardware supports, any ratio if not set. Designs only mix
+    /// in the supported ratios, and the exhaustive search is not selected.
+    pub fn with_capabilities(mut self, capabilities: MixerCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    /// Concentrations the design must pass through, each one is produced by a mix of the design
+    /// before the target.
+    pub fn with_required_intermediates(
+        mut self,
+        required_intermediates: Vec<Concentration>,
+    ) -> Self {
+        self.required_intermediates = required_intermediates;
+        self
+    }
+
+    /// Rewrite rules explored along with the builtin ones, see `fluido_generation::rules`.
+    pub fn with_custom_rules(mut self, custom_rules: Vec<MixRewrite>) -> Self {
+        self.custom_rules = custom_rules;
+        self
+    }
+
+    /// Dump the e-class of the target, and the choices the extractor had, as dot. The dump is
+    /// captured as an artifact of the run.
+    pub fn with_target_class_dump(mut self, target_class_dump: ClassDumpConfig) -> Self {
+        self.target_class_dump = Some(target_class_dump);
+        self
+    }
+
+    /// Steps the rules shift the concentrations of a mix apart by, defaults to
+    /// `fluido_generation::DEFAULT_CONCENTRATION_STEPS`.
+    pub fn with_concentration_steps(mut self, concentration_steps: Vec<f64>) -> Self {
+        self.concentration_steps = concentration_steps;
+        self
+    }
+
+    /// Search in stages from coarse to fine concentration steps, i.e `COARSE_TO_FINE_STAGES`.
+    /// Each stage refines the design of the previous one, which keeps the e-graph much smaller
+    /// than exploring the fine steps everywhere. The time limit is shared by the stages, and
+    /// checkpoints are not supported.
+    pub fn with_stages(mut self, stages: Vec<f64>) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Seed the e-graph with every concentration the inputs reach within `expansion_depth` mixes
+    /// of equal volumes before saturating, leaving saturation only the rules simplifying the
+    /// designs. Targets out of reach of the depth fail with `MixerGenerationError::TargetUnreachable`,
+    /// and depths above `MAX_EXPANSION_DEPTH` with `MixerGenerationError::ExpansionDepthTooLarge`.
+    pub fn with_expansion_depth(mut self, expansion_depth: usize) -> Self {
+        self.expansion_depth = Some(expansion_depth);
+        self
+    }
+
+    /// Flag cancelling the search once set, i.e by a server whose client went away. A cancelled
+    /// search stops saturating and fails with `MixerGenerationError::Cancelled`.
+    pub fn with_cancellation(mut self, cancellation: Arc<AtomicBool>) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Called with the progress of the saturation after each of its iterations, i.e to show how
+    /// many distinct concentrations the search has reached so far.
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Generate with the stages of the chain instead of the generator of the config, running each
+    /// stage only if the previous ones missed the tolerance of the chain. The rest of the config
+    /// applies to every saturation stage, whose time limits default to the one of the config.
+    /// Which stage produced the design is reported by `MixerDesign::fallback_report`.
+    pub fn with_fallback(mut self, fallback: FallbackChain) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    /// Problems small enough to be searched exhaustively instead of saturated, and the mixes the
+    /// exhaustive search enumerates up to. Limits of zero mixes always saturate.
+    pub fn with_exhaustive_limits(mut self, exhaustive: ExhaustiveLimits) -> Self {
+        self.exhaustive = exhaustive;
+        self
+    }
+
+    /// Whether a saturation of the inputs is replaced by the exhaustive search. The exhaustive
+    /// search only minimizes the mixes, so it is not selected for searches constraining the design
+    /// or optimizing anything else.
+    fn searches_exhaustively(&self, input_space: &InputSpace) -> bool {
+        self.generator == MixerGenerator::EqualitySaturation
+            && self.exhaustive.covers(input_space)
+            && self.objective == CostObjective::MixCount
+            && self.proximity_cost == ProximityCost::Flat
+            && self.extraction_root == ExtractionRoot::Target
+            && self.resume_from.is_none()
+            && self.stages.is_empty()
+            && self.expansion_depth.is_none()
+            && self.forbidden_windows.is_empty()
+            && self.incompatibilities.is_empty()
+            && self.max_intermediate_volume.is_none()
+            && self.capabilities.is_none()
+            && self.required_intermediates.is_empty()
+            && self.custom_rules.is_empty()
+            && input_space
+                .iter()
+                .all(|input| input.tier() == InputTier::default())
+    }
 }
 
 /// Generate a mixer for the target_concentration from input space.
 fn generate_mixer_sequence(
     target_concentration: Concentration,
-    input_space: &[Fluid],
-    time_limit: u64,
-    mixer_generator: MixerGenerator,
+    input_space: &InputSpace,
+    generation_config: MixerGenerationConfig,
+    logging: &LogConfig,
 ) -> Result<Sequence, MixerGenerationError> {
-    match mixer_generator {
+    let exhaustive = generation_config.exhaustive;
+    if generation_config.searches_exhaustively(input_space) {
+        // Targets out of reach of the enumerated mixes are left to the saturation.
+        let sequence = exhaustive_search(&target_concentration, input_space, exhaustive.max_mixes)?;
+        if sequence.optimal {
+            return Ok(sequence);
+        }
+    }
+    match generation_config.generator {
         MixerGenerator::EqualitySaturation => {
+            let saturation_config = saturation_config(generation_config, logging);
             let generated_mixer_sequence =
-                fluido_generation::saturate(target_concentration, time_limit, input_space)?;
+                fluido_generation::saturate(target_concentration, input_space, &saturation_config)?;
+            if logging.show_saturation_report {
+                log_saturation_report(&[&generated_mixer_sequence], logging);
+            }
             Ok(generated_mixer_sequence)
         }
+        // Without a fallback chain there is no tolerance, so the closest ratio is taken.
+        MixerGenerator::DirectMix => direct_mix(&target_concentration, input_space, 0.0),
+        MixerGenerator::Exhaustive => {
+            exhaustive_search(&target_concentration, input_space, exhaustive.max_mixes)
+        }
     }
 }
 
-/// Generates a `mixer-graph` from expr.
-fn generate_graph(sequence: Sequence) -> Result<Graph, IRGenerationError> {
-    let best_expr = sequence.best_expr;
-    let expr_str = format!("{best_expr}");
-    let expr = Expr::parse(&expr_str)?;
-    Ok(Graph::from(&expr))
+/// Settings of the saturation the generation config describes.
+fn saturation_config(
+    generation_config: MixerGenerationConfig,
+    logging: &LogConfig,
+) -> SaturationConfig {
+    SaturationConfig {
+        time_limit: generation_config.time_limit,
+        node_limit: generation_config.node_limit,
+        iter_limit: generation_config.iter_limit,
+        show_report: logging.show_saturation_report,
+        log: logging.sink.clone(),
+        checkpoint: generation_config.checkpoint,
+        resume_from: generation_config.resume_from,
+        objective: generation_config.objective,
+        mix_time_model: generation_config.mix_time_model.unwrap_or_default(),
+        proximity_cost: generation_config.proximity_cost,
+        extraction: generation_config.extraction,
+        extraction_root: generation_config.extraction_root,
+        forbidden_windows: generation_config.forbidden_windows,
+        incompatibilities: generation_config.incompatibilities,
+        max_intermediate_volume: generation_config.max_intermediate_volume,
+        capabilities: generation_config.capabilities,
+        required_intermediates: generation_config.required_intermediates,
+        custom_rules: generation_config.custom_rules,
+        target_class_dump: generation_config.target_class_dump,
+        concentration_steps: generation_config.concentration_steps,
+        stages: generation_config.stages,
+        expansion_depth: generation_config.expansion_depth,
+        cancellation: generation_config.cancellation,
+        progress: generation_config.progress,
+    }
+}
+
+/// Prints the statistics of the saturation the sequences were extracted from, the mixer space it
+/// explored and the design extracted for each target.
+fn log_saturation_report(sequences: &[&Sequence], logging: &LogConfig) {
+    let (sink, precision) = (&logging.sink, &logging.precision);
+    if let Some(sequence) = sequences.first() {
+        if let Some(report) = &sequence.report {
+            writeln!(sink, "{}", report.report_with_precision(precision));
+        }
+        write!(sink, "{}", sequence.coverage);
+        if sequence.rejected.total() > 0 {
+            writeln!(sink, "{}", sequence.rejected);
+        }
+    }
+    for sequence in sequences {
+        writeln!(
+            sink,
+            "{} cost {}",
+            sequence.best_expr,
+            precision.format(sequence.cost)
+        );
+    }
 }
 
-/// Generates interference graph from flat ir.
+/// Converts the best expression of the sequence into an `Expr`.
+fn sequence_expr(sequence: &Sequence) -> Result<Expr, IRGenerationError> {
+    Ok(extracted_expr(&sequence.best_expr)?)
+}
+
+/// Builds the mixer graph of a mixer expression.
+pub fn expr_to_graph(expr: &Expr) -> Graph {
+    Graph::from(expr)
+}
+
+/// Lowers a mixer graph into flat ir, in execution order.
+///
+/// The virtual register defined by each op is the index of that op in the returned ir.
+pub fn graph_to_ir(graph: Graph) -> Vec<IROp> {
+    IRBuilder::default().build_ir(graph)
+}
+
+/// Duration of each mix in the design, using the exact volumes of its derivation. Without a mix
+/// time model every mix takes one unit of time.
+fn mix_durations(certificate: &Certificate, mix_time_model: Option<&MixTimeModel>) -> Vec<f64> {
+    certificate
+        .steps
+        .iter()
+        .map(|step| mix_time_model.map_or(1.0, |model| model.mix_time(step.result.volume.into())))
+        .collect()
+}
+
+/// Generates interference graph from flat ir, along with the liveness table it is built from.
 fn generate_interference_graph(
     ir_ops: Vec<IROp>,
-    show_liveness: bool,
-) -> Result<InterferenceGraph, InterefenceGraphGenerationError> {
+) -> Result<(InterferenceGraph, String), InterefenceGraphGenerationError> {
     let mut ir_pass_manager = IRPassManager::new(ir_ops.clone(), vec![]);
     let liveness_analysis = LivenessAnalysis::default();
     ir_pass_manager.register_analysis_pass(&liveness_analysis);
 
     let analysis_results = ir_pass_manager.apply_analysis_passes();
-    let liveness_result = &analysis_results
-        .get("liveness")
+    let liveness_result = analysis_results
+        .get::<LivenessResult>("liveness")
         .ok_or(InterefenceGraphGenerationError::MissingLivenessAnalysis)?;
-    if show_liveness {
-        // Print liveness analysis result with flat-ir next to it.
-        println!("ix  |  ir  |  live vreg set |");
-        for (ix, (ir, liveset)) in ir_ops.iter().zip(&liveness_result.sets_per_ir).enumerate() {
-            println!("{} : {} --- {:?}", ix, ir, liveset)
-        }
+    // Liveness analysis result with flat-ir next to it.
+    let mut liveness_table = "ix  |  ir  |  live vreg set |\n".to_string();
+    for (ix, (ir, liveset)) in ir_ops.iter().zip(&liveness_result.sets_per_ir).enumerate() {
+        let _ = writeln!(liveness_table, "{} : {} --- {:?}", ix, ir, liveset);
     }
 
     let intereference_graph_builder = InterferenceGraphBuilder::new(&liveness_result.sets_per_ir);
     let interference_graph = intereference_graph_builder.build();
 
-    Ok(interference_graph)
+    Ok((interference_graph, liveness_table))
 }
 
 /// Searches a mixer design which is:
@@ -169,45 +1043,364 @@ fn generate_interference_graph(
 pub fn search_mixer_design(
     config: Config,
     target_concentration: Concentration,
-    input_space: &[Fluid],
+    input_space: &InputSpace,
 ) -> Result<MixerDesign, FluidoError> {
-    let mixer_generator = config.generation.generator;
-    let time_limit = config.generation.time_limit;
+    search_mixer_design_with_artifacts(config, target_concentration, input_space)
+        .map(|(design, _)| design)
+}
 
-    let mixer_sequence = generate_mixer_sequence(
-        target_concentration,
+/// Searches a mixer design like `search_mixer_design`, returning the artifacts captured while
+/// searching it along with the design. Artifacts are only captured if enabled with
+/// `Config::with_artifact_capture`.
+pub fn search_mixer_design_with_artifacts(
+    config: Config,
+    target_concentration: Concentration,
+    input_space: &InputSpace,
+) -> Result<(MixerDesign, RunArtifacts), FluidoError> {
+    let distinct_inputs = input_space.concentrations().len();
+    match config.max_inputs {
+        Some(max_inputs) if max_inputs < distinct_inputs => {
+            search_with_input_subsets(config, target_concentration, input_space, max_inputs)
+        }
+        _ => search_with_inputs(config, target_concentration, input_space),
+    }
+}
+
+/// Searches a design for each promising subset of at most `max_inputs` inputs and returns the
+/// cheapest one.
+fn search_with_input_subsets(
+    config: Config,
+    target_concentration: Concentration,
+    input_space: &InputSpace,
+    max_inputs: usize,
+) -> Result<(MixerDesign, RunArtifacts), FluidoError> {
+    let subsets = candidate_subsets(input_space, &target_concentration, max_inputs);
+    let mut best: Option<(MixerDesign, RunArtifacts)> = None;
+    let mut last_err = MixerGenerationError::TargetUnreachable(target_concentration.clone()).into();
+    for subset in subsets {
+        // Keep the best design found so far once out of time, the remaining subsets are left
+        // unexplored.
+        if let Some((design, _)) = &mut best {
+            if config
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                design.confidence = Confidence::Truncated;
+                break;
+            }
+        }
+        match search_with_inputs(config.clone(), target_concentration.clone(), &subset) {
+            Ok((design, artifacts)) => {
+                let is_better = best.as_ref().is_none_or(|(best, _)| {
+                    (design.cost, design.inputs_used().len())
+                        < (best.cost, best.inputs_used().len())
+                });
+                if is_better {
+                    best = Some((design, artifacts));
+                }
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    best.ok_or(last_err)
+}
+
+/// Searches a design for each target, like `search_mixer_design` does, but from a single
+/// saturation of the mixer space holding every target. Targets share the cost of building the
+/// e-graph instead of saturating it once each, i.e for the targets of a dilution ladder. Searches
+/// which do not saturate, search subsets of the inputs or fall back to other generators search
+/// each target on its own instead.
+///
+/// The outer error is a failure of the saturation itself, the inner ones are per target failures
+/// in the order of `target_concentrations`.
+pub fn search_mixer_designs(
+    config: Config,
+    target_concentrations: &[Concentration],
+    input_space: &InputSpace,
+) -> Result<Vec<Result<MixerDesign, FluidoError>>, FluidoError> {
+    let generation = &config.generation;
+    let shares_saturation = generation.generator == MixerGenerator::EqualitySaturation
+        && generation.fallback.is_none()
+        && !generation.searches_exhaustively(input_space)
+        && config
+            .max_inputs
+            .is_none_or(|max_inputs| max_inputs >= input_space.concentrations().len());
+    if !shares_saturation {
+        return Ok(target_concentrations
+            .iter()
+            .map(|target| search_mixer_design(config.clone(), target.clone(), input_space))
+            .collect());
+    }
+
+    let pipeline = config.pipeline.clone().without("generate")?;
+    let mut generation = config.generation.clone();
+    if let Some(deadline) = config.deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        generation.time_limit = generation.time_limit.min(remaining.as_secs());
+    }
+    let sequences = saturate_many(
+        target_concentrations,
         input_space,
-        time_limit,
-        mixer_generator,
+        &saturation_config(generation, &config.logging),
     )?;
+    if config.logging.show_saturation_report {
+        let extracted = sequences.iter().flatten().collect::<Vec<_>>();
+        log_saturation_report(&extracted, &config.logging);
+    }
+    Ok(target_concentrations
+        .iter()
+        .zip(sequences)
+        .map(|(target_concentration, sequence)| {
+            let mut state =
+                PipelineState::new(config.clone(), target_concentration.clone(), input_space);
+            state.accept_sequence(sequence?)?;
+            pipeline.run(&mut state)?;
+            Ok(state.design.expect("the report phase assembles the design"))
+        })
+        .collect())
+}
+
+/// Analyzes a design written by hand instead of searching one: derives its certificate, lowers
+/// it and allocates storage for it, by running the pipeline of the configuration without its
+/// `generate` phase.
+pub fn analyze_mixer_design(config: Config, expr: Expr) -> Result<MixerDesign, FluidoError> {
+    let pipeline = config.pipeline.clone().without("generate")?;
+    // The target is only used by the `generate` phase.
+    let mut state = PipelineState::new(config, 0.0.into(), &InputSpace::default());
+    state.expr = Some(expr);
+    pipeline.run(&mut state)?;
+    Ok(state.design.expect("the report phase assembles the design"))
+}
+
+/// Searches a mixer design for a fluid of several reagents, i.e `[0.1 0.3]` for a buffer along
+/// with a dye. The inputs are mixed once with `mix_reagents`, which fails unless every reagent of
+/// the mix is the target's, within the time limit of the configuration. The rest of the pipeline
+/// follows the first reagent of the design like any other.
+pub fn search_multi_reagent_design(
+    config: Config,
+    target: &[Concentration],
+    input_space: &InputSpace,
+) -> Result<MixerDesign, FluidoError> {
+    let target_concentration = target
+        .first()
+        .cloned()
+        .ok_or_else(|| FluidoError::InvalidInput("the target has no reagents".to_string()))?;
+    let saturation_config = saturation_config(config.generation.clone(), &config.logging);
+    let expr = mix_reagents(target, input_space, 0.0, &saturation_config)?;
+    let pipeline = config.pipeline.clone().without("generate")?;
+    let mut state = PipelineState::new(config, target_concentration, input_space);
+    state.expr = Some(expr);
+    pipeline.run(&mut state)?;
+    Ok(state.design.expect("the report phase assembles the design"))
+}
+
+fn search_with_inputs(
+    config: Config,
+    target_concentration: Concentration,
+    input_space: &InputSpace,
+) -> Result<(MixerDesign, RunArtifacts), FluidoError> {
+    let pipeline = config.pipeline.clone();
+    pipeline.search_with_artifacts(config, target_concentration, input_space)
+}
+
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use crate::{
+        analyze_mixer_design, Config, LogConfig, MixerDesign, MixerGenerationConfig, MixerGenerator,
+    };
+    use fluido_parse::parser::Parse;
+    use fluido_types::expr::Expr;
+
+    /// Silent configuration with default settings.
+    pub(crate) fn test_config() -> Config {
+        Config::new(
+            MixerGenerationConfig::new(0, MixerGenerator::EqualitySaturation),
+            LogConfig::silent(),
+        )
+    }
 
-    let expr_str = format!("{}", mixer_sequence.best_expr);
-    let cost = mixer_sequence.cost;
+    /// Builds a design for the given mixer expression without searching for it.
+    pub(crate) fn design_from_expr(expr_str: &str) -> MixerDesign {
+        analyze_mixer_design(test_config(), Expr::parse(expr_str).unwrap()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        confidence::Confidence,
+        expr_to_graph, graph_to_ir, mix_durations, search_mixer_design, search_mixer_designs,
+        search_multi_reagent_design,
+        test_utils::{design_from_expr, test_config},
+        Config, CostObjective, ExhaustiveLimits, IROp, LogConfig, MixTimeModel,
+        MixerGenerationConfig, MixerGenerator,
+    };
+    use fluido_parse::parser::Parse;
+    use fluido_types::{
+        certificate::Certificate, error::FluidoError, expr::Expr, fluid::Fluid,
+        input_space::InputSpace,
+    };
+
+    #[test]
+    fn mix_durations_scale_with_volume() {
+        let expr = Expr::parse("(mix (mix (fluid 0.2 1.0) (fluid 0 1.0)) (fluid 0 2.0))").unwrap();
+        let certificate = Certificate::from_expr(&expr).unwrap();
+        // Mixes 2 and 4 units of volume.
+        let model = MixTimeModel::new(10.0, 0.5);
+        assert_eq!(mix_durations(&certificate, Some(&model)), vec![11.0, 12.0]);
+        assert_eq!(mix_durations(&certificate, None), vec![1.0, 1.0]);
+    }
 
-    let graph = generate_graph(mixer_sequence)?;
-    if config.logging.show_mixer_graph {
-        println!("{}", graph.dot());
+    #[test]
+    fn expr_lowers_to_ir() {
+        let expr = Expr::parse("(mix (fluid 0.2 1.0) (fluid 0.4 1.0))").unwrap();
+        let ir = graph_to_ir(expr_to_graph(&expr));
+        assert_eq!(ir.len(), 3);
+        assert!(matches!(ir.last(), Some(IROp::Mix(_))));
     }
 
-    let mut ir_builder = IRBuilder::default();
-    let ir_ops = ir_builder.build_ir(graph);
-    if config.logging.show_ir {
-        for (op_index, op) in ir_ops.iter().enumerate() {
-            println!("{} : {}", op_index, op)
+    #[test]
+    fn deeper_operands_are_lowered_first() {
+        let bushy =
+            "(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (mix (fluid 0.2 1.0) (fluid 0.0 1.0)))";
+        let design = design_from_expr(&format!("(mix (fluid 0.0 4.0) {bushy})"));
+        // The bushy operand needs 3 units, holding the stored fluid next to it would need 4.
+        assert_eq!(design.storage_units_needed(), 3);
+        // The fluid is stored after the bushy operand is mixed, but stays the lhs of the mix.
+        assert!(matches!(design.ir()[7], IROp::Store(_)));
+        assert_eq!(
+            design.mixer_expr(),
+            format!("(mix (fluid 0.0 4.0) {bushy})")
+        );
+    }
+
+    #[test]
+    fn physical_ir_keeps_the_storage_allocation() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let allocation = design.register_allocation();
+        for (vreg, storage_unit) in &design.storage_allocation {
+            assert_eq!(allocation.storage_unit(*vreg), Some(*storage_unit));
         }
+        // The final fluid needs no unit of its own.
+        assert_eq!(
+            allocation.storage_units_needed(),
+            design.storage_units_needed()
+        );
+        let physical_ir = design.physical_ir();
+        assert!(physical_ir
+            .iter()
+            .all(|op| op.target_vreg().is_none() && !op.to_string().contains('%')));
+    }
+
+    #[test]
+    fn intermediate_fluids_are_listed_in_execution_order() {
+        let design = design_from_expr(
+            "(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (mix (fluid 0.4 1.0) (fluid 0.0 1.0)))",
+        );
+        let intermediates = design.intermediate_fluids();
+        let fluids = intermediates
+            .iter()
+            .map(|intermediate| intermediate.fluid.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fluids,
+            vec![
+                Fluid::new(0.1.into(), 2.0.into()),
+                Fluid::new(0.2.into(), 2.0.into())
+            ]
+        );
+        // The first intermediate is kept while the second one is produced.
+        assert_ne!(intermediates[0].storage_unit, intermediates[1].storage_unit);
+        assert!(intermediates
+            .iter()
+            .all(|intermediate| intermediate.storage_unit < design.storage_units_needed()));
     }
 
-    let interference_graph = generate_interference_graph(ir_ops, config.logging.show_liveness)?;
-    if config.logging.show_interference_graph {
-        println!("{}", interference_graph.dot());
+    #[test]
+    fn designs_mix_every_reagent_of_the_target() {
+        let reagents = |concentrations: &[f64]| {
+            concentrations
+                .iter()
+                .map(|concentration| (*concentration).into())
+                .collect::<Vec<_>>()
+        };
+        let input_space = InputSpace::from_iter([
+            Fluid::with_reagents(reagents(&[0.4]), 1.0.into()),
+            Fluid::with_reagents(reagents(&[0.0, 0.6]), 1.0.into()),
+        ]);
+        let design =
+            search_multi_reagent_design(test_config(), &reagents(&[0.2, 0.3]), &input_space)
+                .unwrap();
+        assert_eq!(
+            design.mixer_expr(),
+            "(mix (fluid 0.4 1.0) (fluid [0.0 0.6] 1.0))"
+        );
+        assert_eq!(
+            Fluid::try_from(design.expr()).unwrap().concentrations(),
+            reagents(&[0.2, 0.3])
+        );
+        // The certificate follows the first reagent.
+        assert_eq!(f64::from(design.certificate().result.concentration), 0.2);
+        assert!(matches!(
+            search_multi_reagent_design(test_config(), &[], &input_space),
+            Err(FluidoError::InvalidInput(_))
+        ));
     }
 
-    let min_needed_color = interference_graph.find_min_color_count();
+    #[test]
+    fn small_problems_are_searched_exhaustively() {
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        let search = |generation: MixerGenerationConfig| {
+            search_mixer_design(
+                Config::new(generation, LogConfig::silent()),
+                0.01.into(),
+                &input_space,
+            )
+            .unwrap()
+        };
+        let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation);
+        let design = search(generation.clone());
+        assert!(design.is_optimal());
+        assert_eq!(
+            design.mixer_expr(),
+            "(mix (fluid 0.04 1.0) (fluid 0.0 3.0))"
+        );
+        assert_eq!(design.confidence(), Confidence::Exact);
+        assert!(design.saturation_report().is_none());
 
-    let mixer_design = MixerDesign {
-        mixer_expr: expr_str,
-        cost,
-        storage_units_needed: min_needed_color,
-    };
-    Ok(mixer_design)
+        // Limits of zero mixes, or an objective other than the mixes, saturate instead.
+        let design = search(
+            generation
+                .clone()
+                .with_exhaustive_limits(ExhaustiveLimits::new(3, 0)),
+        );
+        assert!(!design.is_optimal());
+        assert!(design.saturation_report().is_some());
+        let design = search(generation.with_objective(CostObjective::MixTime));
+        assert!(!design.is_optimal());
+    }
+
+    #[test]
+    fn targets_share_a_single_saturation() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let generation = MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation)
+            .with_exhaustive_limits(ExhaustiveLimits::new(3, 0));
+        let config = Config::new(generation, LogConfig::silent());
+        let designs = search_mixer_designs(config, &[0.2.into(), 0.1.into()], &input_space)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        // Both targets are a single dilution of `0.4`, with 1 and 3 units of water.
+        assert_eq!(designs.len(), 2);
+        assert!(designs[0].mixer_expr().contains("(fluid 0.0 1.0)"));
+        assert!(designs[1].mixer_expr().contains("(fluid 0.0 3.0)"));
+        // Every design reports the statistics of the same saturation.
+        assert_eq!(
+            designs[0].saturation_report(),
+            designs[1].saturation_report()
+        );
+        assert!(designs[0].saturation_report().is_some());
+    }
 }
@@ -0,0 +1,177 @@
+//! Merging designs of different targets into a single plan.
+//!
+//! Mixes producing the same intermediate in both designs are run once, and the intermediate is
+//! kept in storage until the second design consumes it. Like in capacity planning, the shared
+//! intermediate is assumed to suffice for both designs.
+use crate::{generate_interference_graph, reagents::reagent_usage, MixerDesign};
+use fluido_ir::ir::{linearize_ir, IROp, Operand};
+use fluido_types::{error::FluidoError, expr::Expr};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Two designs merged into a single plan.
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedPlan {
+    /// Virtual registers holding the final fluid of the first and the second design.
+    pub outputs: [usize; 2],
+    pub mix_count: usize,
+    /// Storage units needed to run the merged ir.
+    pub storage_units_needed: u64,
+    /// Total volume drawn from the inputs.
+    pub input_volume: f64,
+    pub savings: MergeSavings,
+    /// Flat ir producing both targets, the first design before the second.
+    #[serde(skip)]
+    pub ir: Vec<IROp>,
+}
+
+/// What the merged plan saves compared to running the designs independently, one after another on
+/// the same chip. Negative savings are costs of the merge, i.e a shared intermediate kept in
+/// storage while the second design runs may need an additional storage unit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergeSavings {
+    pub mixes: usize,
+    pub storage_units: i64,
+    pub input_volume: f64,
+}
+
+impl MergedPlan {
+    /// Merged ir as a linear protocol, with the fluid held by each virtual register.
+    pub fn linear_listing(&self) -> String {
+        linearize_ir(&self.ir)
+    }
+}
+
+/// Merges two designs into a single plan, producing each intermediate the designs have in common
+/// once. Storage is allocated again for the merged ir.
+pub fn merge_designs(first: &MixerDesign, second: &MixerDesign) -> Result<MergedPlan, FluidoError> {
+    let mut builder = MergedIRBuilder::default();
+    let first_output = builder.lower(first.expr());
+    // Only mixes of the first design are shared, mixing an intermediate with itself within a
+    // design would not produce the design.
+    builder.shared = std::mem::take(&mut builder.produced);
+    let second_output = builder.lower(second.expr());
+    let ir = builder.ir;
+
+    let (interference_graph, _) = generate_interference_graph(ir.clone())?;
+    #[cfg(feature = "smt")]
+    let storage_units_needed = interference_graph.find_min_color_count();
+    // Without the solver the heuristic allocation is the best one known.
+    #[cfg(not(feature = "smt"))]
+    let storage_units_needed = interference_graph
+        .heuristic_coloring_with_pins(&HashMap::new(), &HashMap::new())
+        .values()
+        .max()
+        .map_or(0, |max_unit| max_unit + 1);
+    let mix_count = ir.iter().filter(|op| matches!(op, IROp::Mix(_))).count();
+    let input_volume = total_input_volume(&ir);
+
+    let independent_storage_units = first
+        .storage_units_needed()
+        .max(second.storage_units_needed());
+    let independent_input_volume = total_input_volume(first.ir()) + total_input_volume(second.ir());
+    let savings = MergeSavings {
+        mixes: first.mix_count() + second.mix_count() - mix_count,
+        storage_units: independent_storage_units as i64 - storage_units_needed as i64,
+        input_volume: independent_input_volume - input_volume,
+    };
+    Ok(MergedPlan {
+        outputs: [first_output, second_output],
+        mix_count,
+        storage_units_needed,
+        input_volume,
+        savings,
+        ir,
+    })
+}
+
+/// Volume of all the inputs stored by the ir.
+fn total_input_volume(ir: &[IROp]) -> f64 {
+    reagent_usage(ir, &[])
+        .iter()
+        .map(|reagent| reagent.used_volume)
+        .sum()
+}
+
+/// Lowers designs one after another into the same ir, reusing the registers of shared mixes.
+///
+/// The virtual register defined by each op is the index of that op in the ir.
+#[derive(Default)]
+struct MergedIRBuilder {
+    ir: Vec<IROp>,
+    /// Register of each mix lowered for the current design, by its mixer expression.
+    produced: HashMap<String, usize>,
+    /// Registers of the mixes of previous designs, by their mixer expressions.
+    shared: HashMap<String, usize>,
+}
+
+impl MergedIRBuilder {
+    /// Returns the register holding the result of the expression.
+    fn lower(&mut self, expr: &Expr) -> usize {
+        let (op, key) = match expr {
+            Expr::Mix(lhs, rhs) => {
+                let key = expr.to_string();
+                if let Some(vreg) = self.shared.get(&key) {
+                    return *vreg;
+                }
+                let lhs = self.lower(lhs);
+                let rhs = self.lower(rhs);
+                let target = Operand::VirtualRegister(self.ir.len());
+                let op = IROp::Mix((
+                    Operand::VirtualRegister(lhs),
+                    Operand::VirtualRegister(rhs),
+                    target,
+                ));
+                (op, Some(key))
+            }
+            Expr::Fluid(fluid) => {
+                let target = Operand::VirtualRegister(self.ir.len());
+                (IROp::Store((Operand::Const(fluid.clone()), target)), None)
+            }
+            Expr::LimitedFloat(_) => unreachable!("designs only consist of mixes and fluids"),
+        };
+        let vreg = self.ir.len();
+        self.ir.push(op);
+        if let Some(key) = key {
+            self.produced.insert(key, vreg);
+        }
+        vreg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_designs;
+    use crate::test_utils::design_from_expr;
+    use fluido_ir::ir::evaluate_ir;
+    use fluido_types::fluid::Fluid;
+
+    const SHARED: &str = "(mix (fluid 0.4 1.0) (fluid 0.0 1.0))";
+
+    #[test]
+    fn shared_mixes_are_run_once() {
+        let first = design_from_expr(&format!("(mix {SHARED} (fluid 0.0 2.0))"));
+        let second = design_from_expr(&format!("(mix {SHARED} (fluid 0.4 2.0))"));
+        let plan = merge_designs(&first, &second).unwrap();
+
+        assert_eq!(plan.mix_count, 3);
+        assert_eq!(plan.savings.mixes, 1);
+        assert_eq!(plan.savings.input_volume, 2.0);
+        // The shared intermediate is kept while the second design stores its input.
+        assert_eq!(plan.storage_units_needed, 2);
+        assert_eq!(plan.savings.storage_units, 0);
+
+        let fluids = evaluate_ir(&plan.ir);
+        assert_eq!(fluids[&plan.outputs[0]], Fluid::new(0.1.into(), 4.0.into()));
+        assert_eq!(fluids[&plan.outputs[1]], Fluid::new(0.3.into(), 4.0.into()));
+    }
+
+    #[test]
+    fn repeated_mixes_within_a_design_are_not_shared() {
+        let design = design_from_expr(&format!("(mix {SHARED} {SHARED})"));
+        let plan = merge_designs(&design, &design).unwrap();
+        // The second design is the first one's final fluid as a whole.
+        assert_eq!(plan.mix_count, 3);
+        assert_eq!(plan.outputs[0], plan.outputs[1]);
+    }
+}
@@ -0,0 +1,75 @@
+//! Validation of designs against the volume ratios the mixer supports.
+use crate::intermediate_volumes::{render_number, render_operand};
+use fluido_types::{
+    certificate::{Certificate, CertificateOperand},
+    error::DesignValidationError,
+    fluid::{MixRatio, MixerCapabilities},
+};
+
+/// Fails if any mix of the derivation combines its fluids in a ratio the mixer does not support.
+pub fn validate_mix_ratios(
+    certificate: &Certificate,
+    capabilities: Option<&MixerCapabilities>,
+) -> Result<(), DesignValidationError> {
+    let Some(capabilities) = capabilities else {
+        return Ok(());
+    };
+    let volume_of = |operand| match operand {
+        CertificateOperand::Leaf(leaf) => certificate.leaves[leaf].volume,
+        CertificateOperand::Step(step) => certificate.steps[step].result.volume,
+    };
+    for (step, mix) in certificate.steps.iter().enumerate() {
+        let (lhs, rhs) = (volume_of(mix.lhs), volume_of(mix.rhs));
+        let ratio = MixRatio::of(lhs, rhs);
+        if !ratio.is_some_and(|ratio| capabilities.ratios().contains(&ratio)) {
+            return Err(DesignValidationError::UnsupportedMixRatio {
+                step,
+                node: render_operand(certificate, CertificateOperand::Step(step)),
+                ratio: ratio.map_or_else(
+                    || format!("{}:{}", render_number(lhs), render_number(rhs)),
+                    |ratio| ratio.to_string(),
+                ),
+                supported: capabilities.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_mix_ratios;
+    use crate::test_utils::design_from_expr;
+    use fluido_types::{
+        error::DesignValidationError,
+        fluid::{MixRatio, MixerCapabilities},
+    };
+
+    #[test]
+    fn mixes_in_unsupported_ratios_are_rejected() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 6.0))");
+        let capabilities = MixerCapabilities::new([MixRatio::ONE_TO_ONE]);
+        let err = validate_mix_ratios(design.certificate(), Some(&capabilities)).unwrap_err();
+        assert_eq!(
+            err,
+            DesignValidationError::UnsupportedMixRatio {
+                step: 1,
+                node: "(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 6.0))".to_string(),
+                ratio: "1:3".to_string(),
+                supported: capabilities,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Mix 1 `(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 6.0))` mixes its fluids in the ratio 1:3, which the mixer does not support. Supported ratios are 1:1."
+        );
+
+        let capabilities = MixerCapabilities::new([MixRatio::ONE_TO_ONE, "1:3".parse().unwrap()]);
+        assert_eq!(
+            validate_mix_ratios(design.certificate(), Some(&capabilities)),
+            Ok(())
+        );
+        assert_eq!(validate_mix_ratios(design.certificate(), None), Ok(()));
+    }
+}
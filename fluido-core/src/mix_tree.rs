@@ -0,0 +1,196 @@
+//! Typed tree of the mixes of a design, the primary representation of its result.
+//!
+//! Nodes are numbered children first, lhs before rhs, which is the order the certificate derives
+//! them in. The step of each mix thus indexes the certificate steps, and the start and finish
+//! times of the schedule. Each node also carries the virtual register the flat ir produces it
+//! into, along with the storage unit allocated to that register.
+use fluido_ir::ir::{evaluate_ir, IROp, Operand};
+use fluido_types::{decimal, expr::Expr, fluid::Fluid};
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Display};
+
+/// A fluid of the design, either an input fluid or the result of a mix.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MixNode {
+    #[serde(serialize_with = "decimal::serialize_fluid")]
+    pub fluid: Fluid,
+    /// Nodes mixed into this one, lhs first. Not set for input fluids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<[usize; 2]>,
+    /// Virtual register of the flat ir holding the fluid.
+    pub vreg: usize,
+    /// Storage unit holding the fluid, if it is kept in storage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_unit: Option<u64>,
+    /// Index of the mix in the certificate steps and the schedule. Not set for input fluids.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<usize>,
+}
+
+/// Mixes of a design, displayed as its mixer expression.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MixTree {
+    nodes: Vec<MixNode>,
+    root: usize,
+}
+
+impl MixTree {
+    /// Builds the tree of the flat ir lowered from a mixer expression, with the storage unit
+    /// allocated to each virtual register.
+    pub fn from_ir(ir: &[IROp], storage_allocation: &HashMap<usize, u64>) -> Self {
+        enum Visit {
+            Enter(usize),
+            Exit(usize),
+        }
+        let ops = ir
+            .iter()
+            .filter_map(|op| Some((op.target_vreg()?, op)))
+            .collect::<HashMap<_, _>>();
+        let operands = |vreg: usize| match ops.get(&vreg) {
+            Some(IROp::Mix((Operand::VirtualRegister(lhs), Operand::VirtualRegister(rhs), _))) => {
+                Some([*lhs, *rhs])
+            }
+            _ => None,
+        };
+        let fluids = evaluate_ir(ir);
+        let root = ir
+            .last()
+            .and_then(IROp::target_vreg)
+            .expect("Internal Compiler Error, please open an issue!");
+
+        let mut nodes: Vec<MixNode> = vec![];
+        let mut node_of_vreg = HashMap::new();
+        let mut steps = 0;
+        let mut visits = vec![Visit::Enter(root)];
+        while let Some(visit) = visits.pop() {
+            match visit {
+                Visit::Enter(vreg) => {
+                    visits.push(Visit::Exit(vreg));
+                    if let Some([lhs, rhs]) = operands(vreg) {
+                        visits.push(Visit::Enter(rhs));
+                        visits.push(Visit::Enter(lhs));
+                    }
+                }
+                Visit::Exit(vreg) => {
+                    let children =
+                        operands(vreg).map(|vregs| vregs.map(|vreg| node_of_vreg[&vreg]));
+                    let step = children.map(|_| {
+                        steps += 1;
+                        steps - 1
+                    });
+                    node_of_vreg.insert(vreg, nodes.len());
+                    nodes.push(MixNode {
+                        fluid: fluids
+                            .get(&vreg)
+                            .expect("Internal Compiler Error, please open an issue!")
+                            .clone(),
+                        children,
+                        vreg,
+                        storage_unit: storage_allocation.get(&vreg).copied(),
+                        step,
+                    });
+                }
+            }
+        }
+        Self {
+            root: nodes.len() - 1,
+            nodes,
+        }
+    }
+
+    /// Nodes of the tree, children before their parents.
+    pub fn nodes(&self) -> &[MixNode] {
+        &self.nodes
+    }
+
+    /// Node of the final fluid of the design.
+    pub fn root(&self) -> &MixNode {
+        &self.nodes[self.root]
+    }
+
+    /// Input fluids of the design, in the order they are mixed.
+    pub fn leaves(&self) -> impl Iterator<Item = &MixNode> {
+        self.nodes.iter().filter(|node| node.children.is_none())
+    }
+
+    /// Mixes of the design, in the order of the certificate steps.
+    pub fn mixes(&self) -> impl Iterator<Item = &MixNode> {
+        self.nodes.iter().filter(|node| node.children.is_some())
+    }
+
+    /// Mixer expression of the tree.
+    pub fn to_expr(&self) -> Expr {
+        let mut exprs: Vec<Option<Expr>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let expr = match node.children {
+                Some([lhs, rhs]) => {
+                    let mut take = |ix: usize| Box::new(exprs[ix].take().expect("child is built"));
+                    Expr::Mix(take(lhs), take(rhs))
+                }
+                None => Expr::Fluid(node.fluid.clone()),
+            };
+            exprs.push(Some(expr));
+        }
+        exprs[self.root].take().expect("root is built")
+    }
+
+    fn fmt_node(&self, ix: usize, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let node = &self.nodes[ix];
+        match node.children {
+            Some([lhs, rhs]) => {
+                write!(f, "(mix ")?;
+                self.fmt_node(lhs, f)?;
+                write!(f, " ")?;
+                self.fmt_node(rhs, f)?;
+                write!(f, ")")
+            }
+            None => write!(f, "{}", node.fluid),
+        }
+    }
+}
+
+/// Displays the tree as its mixer expression, i.e `(mix (fluid 0.2 1.0) (fluid 0.0 1.0))`.
+impl Display for MixTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_node(self.root, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::design_from_expr;
+
+    #[test]
+    fn nodes_follow_the_certificate() {
+        let expr = "(mix (fluid 0.0 2.0) (mix (fluid 0.2 1.0) (fluid 0.0 1.0)))";
+        let design = design_from_expr(expr);
+        let tree = design.mix_tree();
+        assert_eq!(tree.to_string(), expr);
+        assert_eq!(tree.to_expr(), *design.expr());
+
+        let nodes = tree
+            .nodes()
+            .iter()
+            .map(|node| (node.fluid.to_string(), node.children, node.step))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            [
+                ("(fluid 0.0 2.0)".to_string(), None, None),
+                ("(fluid 0.2 1.0)".to_string(), None, None),
+                ("(fluid 0.0 1.0)".to_string(), None, None),
+                ("(fluid 0.1 2.0)".to_string(), Some([1, 2]), Some(0)),
+                ("(fluid 0.05 4.0)".to_string(), Some([0, 3]), Some(1)),
+            ]
+        );
+        // The intermediate of the inner mix is kept in storage while the other leaf is loaded.
+        let inner = &tree.nodes()[3];
+        assert!(inner.storage_unit.is_some());
+        assert_eq!(
+            inner.storage_unit,
+            design.storage_allocation.get(&inner.vreg).copied()
+        );
+        assert_eq!(tree.root().step, Some(1));
+        assert_eq!(tree.leaves().count(), 3);
+    }
+}
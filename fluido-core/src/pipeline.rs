@@ -0,0 +1,1151 @@
+//! Searching a design as a pipeline of phases.
+//!
+//! The default pipeline runs `generate -> validate -> lower -> analyze -> allocate -> report`,
+//! each phase reading the artifacts of the previous ones from a shared `PipelineState` and adding
+//! its own. Library users can insert their own phases, i.e a filter rejecting extracted designs,
+//! or replace the default ones without forking the search.
+use crate::{
+    artifacts::RunArtifacts,
+    concentration_windows::validate_concentration_windows,
+    confidence::Confidence,
+    expr_to_graph,
+    fallback::{generate_with_fallback, FallbackReport},
+    generate_interference_graph, generate_mixer_sequence, graph_to_ir,
+    incompatibility::validate_incompatibilities,
+    intermediate_volumes::validate_intermediate_volumes,
+    mix_durations,
+    mix_ratios::validate_mix_ratios,
+    mix_tree::MixTree,
+    reagents::{reagent_usage, validate_reagent_usage, ReagentUsage},
+    recipe::recipe_hash,
+    required_intermediates::{locate_required_intermediates, validate_required_intermediates},
+    schedule::{estimate_schedule, ScheduleEstimate},
+    sensitivity::{analyze_sensitivity, SensitivityReport},
+    sequence_expr,
+    stability::{check_stability, StabilityViolation},
+    storage_pins::pinned_vregs,
+    Config, MixerDesign,
+};
+use fluido_generation::Sequence;
+use fluido_ir::{
+    ir::{linearize_ir_with_precision, IROp},
+    pass_manager::{AnalysisResults, IRPassManager},
+    regalloc::interference_graph::{ColorCountBounds, InterferenceGraph, RegAllocStrategy},
+};
+use fluido_types::{
+    certificate::Certificate,
+    error::{FluidoError, PipelineError, StorageAllocationError},
+    expr::Expr,
+    fluid::{Concentration, Fluid},
+    input_space::InputSpace,
+};
+use std::{collections::HashMap, fmt::Debug, sync::Arc, time::Instant};
+
+/// Artifacts of a single search, filled in by the phases of the pipeline as they run.
+pub struct PipelineState {
+    pub config: Config,
+    pub target_concentration: Concentration,
+    pub input_space: InputSpace,
+    /// Best sequence extracted by the generation.
+    pub sequence: Option<Sequence>,
+    /// Stages the fallback chain of the generation ran, if it has one.
+    pub fallback_report: Option<FallbackReport>,
+    /// Parsed mixer expression of the extracted sequence.
+    pub expr: Option<Expr>,
+    pub certificate: Option<Certificate>,
+    /// Flat ir of the design, in execution order.
+    pub ir: Option<Vec<IROp>>,
+    pub interference_graph: Option<InterferenceGraph>,
+    /// Results of the analysis passes registered in the config.
+    pub analysis_results: Option<AnalysisResults>,
+    pub reagent_usage: Option<Vec<ReagentUsage>>,
+    pub schedule: Option<ScheduleEstimate>,
+    pub estimated_time: Option<f64>,
+    pub sensitivity: Option<SensitivityReport>,
+    pub stability_violations: Option<Vec<StabilityViolation>>,
+    pub storage_units_needed: Option<u64>,
+    /// Lower bound of the storage units, if the allocation ran out of time before finding the
+    /// minimum.
+    pub storage_units_lower_bound: Option<u64>,
+    /// Size of the largest clique of the interference graph, a lower bound of the storage units.
+    pub storage_units_clique_bound: Option<u64>,
+    /// Storage unit allocated to each virtual register of the ir.
+    pub storage_allocation: Option<HashMap<usize, u64>>,
+    /// The design assembled from the artifacts, the result of the pipeline.
+    pub design: Option<MixerDesign>,
+    /// Debugging artifacts, captured if enabled in the config.
+    pub artifacts: RunArtifacts,
+}
+
+impl PipelineState {
+    pub fn new(
+        config: Config,
+        target_concentration: Concentration,
+        input_space: &InputSpace,
+    ) -> Self {
+        Self {
+            config,
+            target_concentration,
+            input_space: input_space.clone(),
+            sequence: None,
+            fallback_report: None,
+            expr: None,
+            certificate: None,
+            ir: None,
+            interference_graph: None,
+            analysis_results: None,
+            reagent_usage: None,
+            schedule: None,
+            estimated_time: None,
+            sensitivity: None,
+            stability_violations: None,
+            storage_units_needed: None,
+            storage_units_lower_bound: None,
+            storage_units_clique_bound: None,
+            storage_allocation: None,
+            design: None,
+            artifacts: RunArtifacts::default(),
+        }
+    }
+}
+
+impl PipelineState {
+    /// Takes the generated sequence as the design of the search, parsing its mixer expression and
+    /// transforming it with the post extraction hook of the config.
+    pub fn accept_sequence(&mut self, sequence: Sequence) -> Result<(), FluidoError> {
+        let expr = sequence_expr(&sequence)?;
+        // Validated by the following phases, like any other design.
+        self.expr = Some(match &self.config.post_extract_hook {
+            Some(hook) => hook.call(expr),
+            None => expr,
+        });
+        if self.config.capture_artifacts {
+            self.artifacts.raw_expr = Some(sequence.raw_expr.to_string());
+            self.artifacts.normalized_expr = Some(sequence.best_expr.to_string());
+            self.artifacts.target_class_dot = sequence.target_class_dot.clone();
+        }
+        self.sequence = Some(sequence);
+        Ok(())
+    }
+}
+
+/// Returns the artifact, or an error naming the phase that needed it.
+fn require<'a, T>(
+    artifact: &'a Option<T>,
+    phase: &str,
+    name: &'static str,
+) -> Result<&'a T, PipelineError> {
+    artifact
+        .as_ref()
+        .ok_or_else(|| PipelineError::MissingArtifact {
+            phase: phase.to_string(),
+            artifact: name,
+        })
+}
+
+/// A single step of the search. Phases are shared by the searches running concurrently with the
+/// same pipeline, so they must be `Send` and `Sync`.
+pub trait Phase: Send + Sync {
+    /// Name of the phase, used for inserting phases relative to it or replacing it.
+    fn name(&self) -> &str;
+
+    /// Reads the artifacts of the previous phases from the state and adds its own.
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError>;
+}
+
+/// Generates a sequence for the target, through the fallback chain of the config if it has one, and
+/// parses the extracted mixer expression.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Generate;
+
+impl Phase for Generate {
+    fn name(&self) -> &str {
+        "generate"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let mut generation = state.config.generation.clone();
+        if let Some(deadline) = state.config.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            generation.time_limit = generation.time_limit.min(remaining.as_secs());
+        }
+        let sequence = match &generation.fallback {
+            Some(fallback) => {
+                let (sequence, report) = generate_with_fallback(
+                    fallback,
+                    &state.target_concentration,
+                    &state.input_space,
+                    &generation,
+                    &state.config.logging,
+                    state.config.deadline,
+                )?;
+                state.fallback_report = Some(report);
+                sequence
+            }
+            None => generate_mixer_sequence(
+                state.target_concentration.clone(),
+                &state.input_space,
+                generation,
+                &state.config.logging,
+            )?,
+        };
+        state.accept_sequence(sequence)
+    }
+}
+
+/// Checks that every node of the expression produces a fluid and derives its certificate,
+/// rejecting designs passing through forbidden concentration windows, exceeding the maximum
+/// intermediate volume, mixing incompatible inputs, mixing in a ratio the mixer does not support
+/// or lacking a required intermediate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Validate;
+
+impl Phase for Validate {
+    fn name(&self) -> &str {
+        "validate"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let expr = require(&state.expr, self.name(), "expr")?;
+        // Names the failing node, which the certificate derivation does not.
+        Fluid::try_from(expr)?;
+        let certificate = Certificate::from_expr(expr)?;
+        // Rules never create forbidden concentrations, but a resumed search may have explored them.
+        validate_concentration_windows(&certificate, &state.config.generation.forbidden_windows)?;
+        validate_intermediate_volumes(
+            &certificate,
+            state.config.generation.max_intermediate_volume.as_ref(),
+        )?;
+        validate_incompatibilities(&certificate, &state.config.generation.incompatibilities)?;
+        validate_mix_ratios(&certificate, state.config.generation.capabilities.as_ref())?;
+        validate_required_intermediates(
+            &certificate,
+            &state.config.generation.required_intermediates,
+        )?;
+        state.certificate = Some(certificate);
+        Ok(())
+    }
+}
+
+/// Lowers the expression into flat ir.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lower;
+
+impl Phase for Lower {
+    fn name(&self) -> &str {
+        "lower"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let expr = require(&state.expr, self.name(), "expr")?;
+        let graph = expr_to_graph(expr);
+        let graph_dot = graph.dot_with_precision(&state.config.logging.precision);
+        if state.config.logging.show_mixer_graph {
+            writeln!(state.config.logging.sink, "{graph_dot}");
+        }
+
+        let ir_ops = graph_to_ir(graph);
+        let ir_listing = ir_ops
+            .iter()
+            .enumerate()
+            .map(|(op_index, op)| format!("{} : {}\n", op_index, op))
+            .collect::<String>();
+        if state.config.logging.show_ir {
+            write!(state.config.logging.sink, "{ir_listing}");
+        }
+        if state.config.capture_artifacts {
+            state.artifacts.graph_dot = Some(graph_dot);
+            state.artifacts.ir_listing = Some(ir_listing);
+        }
+        state.ir = Some(ir_ops);
+        Ok(())
+    }
+}
+
+/// Builds the interference graph of the ir, accounts the consumed reagents, estimates the
+/// schedule, analyzes the sensitivity of the design to its leaves and checks its intermediates
+/// against the stability of their inputs. The analysis passes registered in the config run last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Analyze;
+
+impl Phase for Analyze {
+    fn name(&self) -> &str {
+        "analyze"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let ir_ops = require(&state.ir, self.name(), "ir")?;
+        let certificate = require(&state.certificate, self.name(), "certificate")?;
+        let (interference_graph, liveness_table) = generate_interference_graph(ir_ops.clone())?;
+        if state.config.logging.show_liveness {
+            write!(state.config.logging.sink, "{liveness_table}");
+        }
+        if state.config.logging.show_interference_graph {
+            writeln!(state.config.logging.sink, "{}", interference_graph.dot());
+        }
+        if state.config.capture_artifacts {
+            state.artifacts.liveness_table = Some(liveness_table);
+            state.artifacts.interference_dot = Some(interference_graph.dot());
+        }
+
+        let usage = reagent_usage(ir_ops, &state.config.reagent_limits);
+        validate_reagent_usage(&usage)?;
+
+        let mix_time_model = state.config.generation.mix_time_model;
+        let durations = mix_durations(certificate, mix_time_model.as_ref());
+        state.estimated_time = mix_time_model.map(|_| durations.iter().sum());
+        state.schedule = Some(estimate_schedule(
+            certificate,
+            &durations,
+            state.config.mixers,
+        ));
+        state.sensitivity = Some(analyze_sensitivity(
+            certificate,
+            &state.config.input_uncertainties,
+        ));
+        state.stability_violations = Some(check_stability(ir_ops, &state.config.input_stability));
+        let analysis_passes = state
+            .config
+            .analysis_passes
+            .iter()
+            .map(|analysis_pass| analysis_pass.as_ref())
+            .collect();
+        state.analysis_results =
+            Some(IRPassManager::new(ir_ops.clone(), analysis_passes).apply_analysis_passes());
+        state.reagent_usage = Some(usage);
+        state.interference_graph = Some(interference_graph);
+        Ok(())
+    }
+}
+
+/// Allocates storage units to the virtual registers of the ir with the minimum number of units, or
+/// heuristically if the config asks for `RegAllocStrategy::Heuristic`.
+///
+/// If the minimum is not found before the deadline of the search, or the search stops at the
+/// storage bound gap of the config, the fewest units found are allocated and a lower bound of the
+/// minimum is recorded instead. Heuristic allocations record one unless they meet it. Either way,
+/// registers keep the storage unit the allocation hint of the config gives them where possible.
+/// Registers storing a pinned input always get the unit of their pin, taking more units than the
+/// minimum if needed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Allocate;
+
+impl Phase for Allocate {
+    fn name(&self) -> &str {
+        "allocate"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let interference_graph =
+            require(&state.interference_graph, self.name(), "interference graph")?;
+        let hint = match state.config.allocation_hint.is_empty() {
+            true => None,
+            false => Some(state.config.allocation_hint.vreg_hint(require(
+                &state.ir,
+                self.name(),
+                "ir",
+            )?)),
+        };
+        let pins = match state.config.storage_pins.is_empty() {
+            true => HashMap::new(),
+            false => {
+                let pinned = pinned_vregs(
+                    &state.config.storage_pins,
+                    require(&state.ir, self.name(), "ir")?,
+                    &state.input_space,
+                )?;
+                let pins = pinned
+                    .iter()
+                    .map(|(vreg, (storage_unit, _))| (*vreg, *storage_unit))
+                    .collect();
+                if let Some((first_vreg, second_vreg)) = interference_graph.pin_conflict(&pins) {
+                    return Err(StorageAllocationError::ConflictingPins {
+                        storage_unit: pins[&first_vreg],
+                        first: pinned[&first_vreg].1.clone(),
+                        second: pinned[&second_vreg].1.clone(),
+                        first_vreg,
+                        second_vreg,
+                    }
+                    .into());
+                }
+                pins
+            }
+        };
+        state.storage_units_clique_bound = Some(interference_graph.color_count_lower_bound());
+        let (bounds, minimum_allocation) = match state.config.reg_alloc_strategy {
+            RegAllocStrategy::Exact => {
+                minimum_allocation(interference_graph, &state.config, &pins, hint.as_ref())
+            }
+            RegAllocStrategy::Heuristic => {
+                heuristic_allocation(interference_graph, &state.config, &pins, hint.as_ref())
+            }
+        };
+        let (storage_units_needed, storage_allocation) = match minimum_allocation {
+            Some(minimum_allocation) => minimum_allocation,
+            None => {
+                let storage_allocation = interference_graph
+                    .heuristic_coloring_with_pins(&pins, hint.as_ref().unwrap_or(&HashMap::new()));
+                let storage_units_needed = storage_allocation
+                    .values()
+                    .max()
+                    .map_or(0, |max_unit| max_unit + 1);
+                state.storage_units_lower_bound = Some(bounds.lower);
+                (storage_units_needed, storage_allocation)
+            }
+        };
+        if !bounds.is_exact() {
+            state.storage_units_lower_bound = Some(bounds.lower);
+        }
+        if state.config.capture_artifacts {
+            state.artifacts.coloring = Some(storage_allocation.clone().into_iter().collect());
+        }
+        state.storage_allocation = Some(storage_allocation);
+        state.storage_units_needed = Some(storage_units_needed);
+        Ok(())
+    }
+}
+
+/// Number of storage units, along with the unit of each virtual register.
+type Allocation = (u64, HashMap<usize, u64>);
+
+/// Allocation with the minimum number of storage units keeping the pins, and keeping the hinted
+/// units where possible, along with the bounds of the minimum the search found. `None` if the
+/// minimum is not found before the deadline, unless the allocation of the search can be kept.
+#[cfg(feature = "smt")]
+fn minimum_allocation(
+    interference_graph: &InterferenceGraph,
+    config: &Config,
+    pins: &HashMap<usize, u64>,
+    hint: Option<&HashMap<usize, u64>>,
+) -> (ColorCountBounds, Option<Allocation>) {
+    // Without a hint to keep, a greedy allocation meeting the clique bound is the minimum, which
+    // needs no solver.
+    if hint.is_none() {
+        let lower = interference_graph.color_count_lower_bound();
+        let storage_allocation =
+            interference_graph.greedy_coloring_with_pins(pins, &HashMap::new());
+        let upper = storage_allocation
+            .values()
+            .max()
+            .map_or(0, |max_unit| max_unit + 1);
+        if upper == lower {
+            let bounds = ColorCountBounds { lower, upper };
+            if let Some(storage_bounds) = &config.storage_bounds {
+                storage_bounds.call(&bounds);
+            }
+            return (bounds, Some((upper, storage_allocation)));
+        }
+    }
+    let deadline = config.deadline;
+    let (bounds, coloring) = interference_graph.search_color_count_bounds_with(
+        config.color_count_strategy,
+        deadline,
+        config.storage_bound_gap,
+        |bounds| {
+            if let Some(storage_bounds) = &config.storage_bounds {
+                storage_bounds.call(bounds);
+            }
+        },
+    );
+    if !bounds.is_exact() {
+        // The coloring of the search ignores the hint and the pins, so it is only kept without
+        // them.
+        let allocation = (pins.is_empty() && hint.is_none()).then_some((bounds.upper, coloring));
+        return (bounds, allocation);
+    }
+    let storage_units_needed = bounds.upper;
+    let no_hint = HashMap::new();
+    let hint = hint.unwrap_or(&no_hint);
+    if pins.is_empty() {
+        let storage_allocation = interference_graph
+            .try_coloring_with_hint(storage_units_needed, hint)
+            .unwrap_or_default();
+        return (bounds, Some((storage_units_needed, storage_allocation)));
+    }
+    // Pins may need more units than the minimum, at most as many as a greedy allocation keeping
+    // them.
+    let greedy_units = interference_graph
+        .greedy_coloring_with_pins(pins, hint)
+        .values()
+        .max()
+        .map_or(0, |max_unit| max_unit + 1);
+    let max_pin = pins.values().max().map_or(0, |max_unit| max_unit + 1);
+    let allocation = (storage_units_needed.max(max_pin)..=greedy_units).find_map(|storage_units| {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return None;
+        }
+        let storage_allocation =
+            interference_graph.try_coloring_with_pins(storage_units, pins, hint)?;
+        Some((storage_units, storage_allocation))
+    });
+    (bounds, allocation)
+}
+
+/// Without the solver the minimum is never searched, storage is allocated heuristically instead.
+#[cfg(not(feature = "smt"))]
+fn minimum_allocation(
+    interference_graph: &InterferenceGraph,
+    config: &Config,
+    pins: &HashMap<usize, u64>,
+    hint: Option<&HashMap<usize, u64>>,
+) -> (ColorCountBounds, Option<Allocation>) {
+    heuristic_allocation(interference_graph, config, pins, hint)
+}
+
+/// Allocation of the heuristic coloring keeping the pins, and the hinted units where they take no
+/// more units, along with the bounds it certifies: the clique bound and the units it allocates.
+fn heuristic_allocation(
+    interference_graph: &InterferenceGraph,
+    config: &Config,
+    pins: &HashMap<usize, u64>,
+    hint: Option<&HashMap<usize, u64>>,
+) -> (ColorCountBounds, Option<Allocation>) {
+    let storage_allocation =
+        interference_graph.heuristic_coloring_with_pins(pins, hint.unwrap_or(&HashMap::new()));
+    let storage_units_needed = storage_allocation
+        .values()
+        .max()
+        .map_or(0, |max_unit| max_unit + 1);
+    let bounds = ColorCountBounds {
+        lower: interference_graph.color_count_lower_bound(),
+        upper: storage_units_needed,
+    };
+    if let Some(storage_bounds) = &config.storage_bounds {
+        storage_bounds.call(&bounds);
+    }
+    (bounds, Some((storage_units_needed, storage_allocation)))
+}
+
+/// Called with the bounds of the minimum number of storage units before searching it, and each
+/// time the search tightens them. Clones call the same function.
+#[derive(Clone)]
+pub struct StorageBoundsCallback(Arc<dyn Fn(&ColorCountBounds) + Send + Sync>);
+
+impl StorageBoundsCallback {
+    pub fn new(callback: impl Fn(&ColorCountBounds) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    fn call(&self, bounds: &ColorCountBounds) {
+        (self.0)(bounds)
+    }
+}
+
+impl Debug for StorageBoundsCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StorageBoundsCallback")
+    }
+}
+
+/// Transforms each extracted design before it is validated and lowered, i.e reordering its mixes
+/// into a left-deep tree for a mixer which only keeps a single intermediate. Clones call the same
+/// function.
+#[derive(Clone)]
+pub struct PostExtractHook(Arc<dyn Fn(Expr) -> Expr + Send + Sync>);
+
+impl PostExtractHook {
+    pub fn new(hook: impl Fn(Expr) -> Expr + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    fn call(&self, expr: Expr) -> Expr {
+        (self.0)(expr)
+    }
+}
+
+impl Debug for PostExtractHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PostExtractHook")
+    }
+}
+
+/// Assembles the design from the artifacts of the previous phases.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Report;
+
+impl Phase for Report {
+    fn name(&self) -> &str {
+        "report"
+    }
+
+    fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let name = self.name();
+        let expr = require(&state.expr, name, "expr")?.clone();
+        let certificate = require(&state.certificate, name, "certificate")?;
+        // Designs transformed after the extraction, i.e by the post extraction hook, are reported
+        // as transformed, but keep the cost of the extracted one.
+        let extracted = state
+            .sequence
+            .as_ref()
+            .filter(|sequence| sequence_expr(sequence).is_ok_and(|extracted| extracted == expr));
+        // Designs which are not generated, i.e given by the user, have no cost.
+        let cost = state
+            .sequence
+            .as_ref()
+            .map_or(0.0, |sequence| sequence.cost);
+        // Designs which are not generated have no target either.
+        let target = state.sequence.as_ref().map(|_| &state.target_concentration);
+        let truncated = state
+            .config
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let confidence = Confidence::classify(certificate, target, truncated);
+        let ir = require(&state.ir, name, "ir")?;
+        let storage_allocation = require(&state.storage_allocation, name, "storage allocation")?;
+        state.design = Some(MixerDesign {
+            mix_tree: MixTree::from_ir(ir, storage_allocation),
+            cost,
+            storage_units_needed: *require(&state.storage_units_needed, name, "storage units")?,
+            certificate: certificate.clone(),
+            reagent_usage: require(&state.reagent_usage, name, "reagent usage")?.clone(),
+            estimated_time: state.estimated_time,
+            schedule: require(&state.schedule, name, "schedule")?.clone(),
+            storage_units_lower_bound: state.storage_units_lower_bound,
+            storage_units_clique_bound: *require(
+                &state.storage_units_clique_bound,
+                name,
+                "storage clique bound",
+            )?,
+            required_intermediates: locate_required_intermediates(
+                certificate,
+                &state.config.generation.required_intermediates,
+            ),
+            sensitivity: require(&state.sensitivity, name, "sensitivity")?.clone(),
+            stability_violations: require(
+                &state.stability_violations,
+                name,
+                "stability violations",
+            )?
+            .clone(),
+            confidence,
+            optimal: extracted.is_some_and(|sequence| sequence.optimal),
+            recipe_hash: recipe_hash(certificate),
+            expr,
+            ir: ir.clone(),
+            storage_allocation: storage_allocation.clone(),
+            analysis_results: require(&state.analysis_results, name, "analysis results")?.clone(),
+            fallback_report: state.fallback_report.clone(),
+            saturation_report: state
+                .sequence
+                .as_ref()
+                .and_then(|sequence| sequence.report.clone()),
+        });
+        Ok(())
+    }
+}
+
+/// Phases the state of the pipeline is dumped after, like `-print-after` of LLVM.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PrintAfter {
+    #[default]
+    None,
+    /// After every phase.
+    All,
+    /// After each of the named phases.
+    Phases(Vec<String>),
+}
+
+impl PrintAfter {
+    fn includes(&self, name: &str) -> bool {
+        match self {
+            PrintAfter::None => false,
+            PrintAfter::All => true,
+            PrintAfter::Phases(names) => names.iter().any(|phase| phase == name),
+        }
+    }
+}
+
+/// Dumps the ir of the state, or the mixer expression if it is not lowered yet, under a header
+/// naming the phase.
+fn dump_after(name: &str, state: &PipelineState) -> String {
+    let dump = match (&state.ir, &state.expr) {
+        (Some(ir), _) => linearize_ir_with_precision(ir, &state.config.logging.precision),
+        (None, Some(expr)) => format!("{expr}\n"),
+        (None, None) => "; nothing to dump yet\n".to_string(),
+    };
+    format!("*** IR Dump After {name} ***\n{dump}")
+}
+
+/// An ordered list of phases producing a design.
+#[derive(Clone)]
+pub struct Pipeline {
+    phases: Vec<Arc<dyn Phase>>,
+}
+
+impl Default for Pipeline {
+    /// `generate -> validate -> lower -> analyze -> allocate -> report`.
+    fn default() -> Self {
+        Self::empty()
+            .with_phase(Generate)
+            .with_phase(Validate)
+            .with_phase(Lower)
+            .with_phase(Analyze)
+            .with_phase(Allocate)
+            .with_phase(Report)
+    }
+}
+
+impl Debug for Pipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.phase_names()).finish()
+    }
+}
+
+impl Pipeline {
+    /// A pipeline without any phases.
+    pub fn empty() -> Self {
+        Self { phases: vec![] }
+    }
+
+    /// Names of the phases, in the order they run.
+    pub fn phase_names(&self) -> Vec<&str> {
+        self.phases.iter().map(|phase| phase.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, PipelineError> {
+        self.phases
+            .iter()
+            .position(|phase| phase.name() == name)
+            .ok_or_else(|| PipelineError::UnknownPhase(name.to_string()))
+    }
+
+    /// Appends the phase to the end of the pipeline.
+    pub fn with_phase(mut self, phase: impl Phase + 'static) -> Self {
+        self.phases.push(Arc::new(phase));
+        self
+    }
+
+    /// Inserts the phase right before the phase with the given name.
+    pub fn insert_before(
+        mut self,
+        name: &str,
+        phase: impl Phase + 'static,
+    ) -> Result<Self, PipelineError> {
+        let position = self.position(name)?;
+        self.phases.insert(position, Arc::new(phase));
+        Ok(self)
+    }
+
+    /// Inserts the phase right after the phase with the given name.
+    pub fn insert_after(
+        mut self,
+        name: &str,
+        phase: impl Phase + 'static,
+    ) -> Result<Self, PipelineError> {
+        let position = self.position(name)?;
+        self.phases.insert(position + 1, Arc::new(phase));
+        Ok(self)
+    }
+
+    /// Replaces the phase with the given name.
+    pub fn replace(
+        mut self,
+        name: &str,
+        phase: impl Phase + 'static,
+    ) -> Result<Self, PipelineError> {
+        let position = self.position(name)?;
+        self.phases[position] = Arc::new(phase);
+        Ok(self)
+    }
+
+    /// Removes the phase with the given name.
+    pub fn without(mut self, name: &str) -> Result<Self, PipelineError> {
+        let position = self.position(name)?;
+        self.phases.remove(position);
+        Ok(self)
+    }
+
+    /// Runs the phases in order over the state, stopping at the first failing one.
+    ///
+    /// The state is dumped after the phases configured with `LogConfig::with_print_after`, all of
+    /// which must be part of the pipeline.
+    pub fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+        let print_after = state.config.logging.print_after.clone();
+        if let PrintAfter::Phases(names) = &print_after {
+            for name in names {
+                self.position(name)?;
+            }
+        }
+        self.phases.iter().try_for_each(|phase| {
+            phase.run(state)?;
+            if print_after.includes(phase.name()) {
+                write!(
+                    state.config.logging.sink,
+                    "{}",
+                    dump_after(phase.name(), state)
+                );
+            }
+            Ok(())
+        })
+    }
+
+    /// Searches a design for the target, returning the design assembled by the pipeline.
+    pub fn search(
+        &self,
+        config: Config,
+        target_concentration: Concentration,
+        input_space: &InputSpace,
+    ) -> Result<MixerDesign, FluidoError> {
+        self.search_with_artifacts(config, target_concentration, input_space)
+            .map(|(design, _)| design)
+    }
+
+    /// Searches a design for the target, returning the artifacts captured by the phases along
+    /// with the design.
+    pub fn search_with_artifacts(
+        &self,
+        config: Config,
+        target_concentration: Concentration,
+        input_space: &InputSpace,
+    ) -> Result<(MixerDesign, RunArtifacts), FluidoError> {
+        let mut state = PipelineState::new(config, target_concentration, input_space);
+        self.run(&mut state)?;
+        let design = state.design.ok_or_else(|| PipelineError::MissingArtifact {
+            phase: "pipeline".to_string(),
+            artifact: "design",
+        })?;
+        Ok((design, state.artifacts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dump_after, Phase, Pipeline, PipelineState, PostExtractHook, PrintAfter,
+        StorageBoundsCallback,
+    };
+    use crate::{
+        analyze_mixer_design,
+        confidence::Confidence,
+        test_utils::{design_from_expr, test_config},
+        AnalysisPass, AnalysisResult, ColorCountBounds, Config, IROp, LogConfig, RegAllocStrategy,
+        EXACT_CLIQUE_MAX_NODES,
+    };
+    use fluido_ir::ir::Operand;
+    use fluido_parse::parser::Parse;
+    use fluido_types::{
+        error::{FluidoError, PipelineError},
+        expr::Expr,
+        input_space::InputSpace,
+    };
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    /// Rejects designs with more than one mix.
+    struct SingleMix;
+
+    impl Phase for SingleMix {
+        fn name(&self) -> &str {
+            "single-mix"
+        }
+
+        fn run(&self, state: &mut PipelineState) -> Result<(), FluidoError> {
+            let steps = state.certificate.as_ref().map_or(0, |c| c.steps.len());
+            if steps > 1 {
+                return Err(FluidoError::InvalidInput(format!("{steps} mixes")));
+            }
+            Ok(())
+        }
+    }
+
+    /// Number of mixes each op of the ir is the result of, including the mixes of its inputs.
+    struct MixesPerStep;
+
+    impl AnalysisPass for MixesPerStep {
+        fn pass_name(&self) -> &str {
+            "mixes-per-step"
+        }
+
+        fn analyze(&self, ir_to_pass_over: &[IROp]) -> AnalysisResult {
+            let mut mixes: Vec<usize> = vec![];
+            for op in ir_to_pass_over {
+                let count = match op {
+                    IROp::Store(_) => 0,
+                    IROp::Mix((first, second, _)) => {
+                        let [first, second] = [first, second].map(|operand| match operand {
+                            Operand::VirtualRegister(vreg) => mixes[*vreg],
+                            Operand::Const(_) | Operand::StorageUnit(_) => 0,
+                        });
+                        first + second + 1
+                    }
+                };
+                mixes.push(count);
+            }
+            AnalysisResult::new(mixes)
+        }
+    }
+
+    #[test]
+    fn registered_analysis_passes_run_on_the_design() {
+        let expr =
+            Expr::parse("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))").unwrap();
+        let config = test_config().with_analysis_pass(MixesPerStep);
+        let design = analyze_mixer_design(config, expr).unwrap();
+
+        let results = design.analysis_results();
+        assert_eq!(
+            results.get::<Vec<usize>>("mixes-per-step"),
+            Some(&vec![0, 0, 1, 0, 2])
+        );
+        assert!(results.get::<Vec<u64>>("mixes-per-step").is_none());
+        assert!(design_from_expr("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))")
+            .analysis_results()
+            .is_empty());
+    }
+
+    #[test]
+    fn phases_are_inserted_replaced_and_removed() {
+        let pipeline = Pipeline::default()
+            .insert_after("validate", SingleMix)
+            .unwrap()
+            .without("generate")
+            .unwrap();
+        assert_eq!(
+            pipeline.phase_names(),
+            vec![
+                "validate",
+                "single-mix",
+                "lower",
+                "analyze",
+                "allocate",
+                "report"
+            ]
+        );
+        assert!(matches!(
+            Pipeline::default().replace("missing", SingleMix),
+            Err(PipelineError::UnknownPhase(_))
+        ));
+    }
+
+    #[test]
+    fn custom_phase_filters_designs() {
+        let pipeline = Pipeline::default()
+            .without("generate")
+            .unwrap()
+            .insert_after("validate", SingleMix)
+            .unwrap();
+        let run = |expr: &str| {
+            let mut state = PipelineState::new(test_config(), 0.0.into(), &InputSpace::default());
+            state.expr = Some(design_from_expr(expr).expr().clone());
+            pipeline.run(&mut state).map(|_| state.design.unwrap())
+        };
+
+        let design = run("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))").unwrap();
+        assert_eq!(design.mix_count(), 1);
+        assert!(matches!(
+            run("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))"),
+            Err(FluidoError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn artifacts_are_captured_when_enabled() {
+        let run = |capture| {
+            let mut state = PipelineState::new(
+                test_config().with_artifact_capture(capture),
+                0.0.into(),
+                &InputSpace::default(),
+            );
+            state.expr = Some(
+                design_from_expr("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))")
+                    .expr()
+                    .clone(),
+            );
+            Pipeline::default()
+                .without("generate")
+                .unwrap()
+                .run(&mut state)
+                .unwrap();
+            state.artifacts
+        };
+
+        let artifacts = run(true);
+        assert_eq!(
+            artifacts.ir_listing.as_deref(),
+            Some("0 : store (fluid 0.2 1.0) %0\n1 : store (fluid 0.0 1.0) %1\n2 : mix %0 %1 %2\n")
+        );
+        assert!(artifacts.liveness_table.is_some());
+        assert!(artifacts.interference_dot.is_some());
+        assert_eq!(artifacts.coloring.map(|coloring| coloring.len()), Some(2));
+        // Not generated, so there is no extracted expression.
+        assert_eq!(artifacts.raw_expr, None);
+        assert_eq!(run(false), Default::default());
+    }
+
+    #[test]
+    fn allocation_out_of_time_reports_a_lower_bound() {
+        let run = |config: Config, expr: &str| {
+            let mut state = PipelineState::new(config, 0.0.into(), &InputSpace::default());
+            state.expr = Some(Expr::parse(expr).unwrap());
+            Pipeline::default()
+                .without("generate")
+                .unwrap()
+                .run(&mut state)
+                .unwrap();
+            state.design.unwrap()
+        };
+        // The greedy bounds of the storage units of this one are 3 and 4. Mixing it with further
+        // inputs takes it past the registers the clique bound is exact for.
+        let expr = concat!(
+            "(mix (mix (fluid 0.2 1.0) (mix (mix (fluid 0.3 1.0) (mix (fluid 0.4 1.0) ",
+            "(fluid 0.5 1.0))) (fluid 0.6 1.0))) (mix (fluid 0.7 1.0) (mix (mix (mix ",
+            "(fluid 0.8 1.0) (fluid 0.9 1.0)) (mix (fluid 0.1 1.0) (fluid 0.2 1.0))) (mix ",
+            "(mix (fluid 0.3 1.0) (fluid 0.4 1.0)) (mix (fluid 0.5 1.0) (fluid 0.6 1.0))))))"
+        )
+        .to_string();
+        let expr = &(0..EXACT_CLIQUE_MAX_NODES / 2)
+            .fold(expr, |expr, _| format!("(mix {expr} (fluid 0.0 1.0))"));
+        let reported = Arc::new(Mutex::new(vec![]));
+        let storage_bounds = StorageBoundsCallback::new({
+            let reported = reported.clone();
+            move |bounds| reported.lock().unwrap().push(*bounds)
+        });
+        let config = test_config()
+            .with_max_total_time(Duration::ZERO)
+            .with_storage_bounds(storage_bounds);
+        let design = run(config, expr);
+
+        assert!(design.is_partial());
+        assert_eq!(design.confidence(), Confidence::Truncated);
+        let greedy_units = design.storage_units_needed();
+        let lower_bound = design.storage_units_lower_bound().unwrap();
+        assert!(lower_bound >= 1 && lower_bound < design.storage_units_needed());
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![ColorCountBounds {
+                lower: lower_bound,
+                upper: design.storage_units_needed()
+            }]
+        );
+
+        // Stopping at a gap of one needs no time at all, while the exact minimum is searched.
+        let design = run(test_config().with_storage_bound_gap(1), expr);
+        assert_eq!(design.storage_units_lower_bound(), Some(lower_bound));
+        #[cfg(feature = "smt")]
+        {
+            let design = run(test_config(), expr);
+            assert!(!design.is_partial());
+            assert!(design.storage_units_needed() >= lower_bound);
+        }
+
+        // Heuristic allocations need no solver, and report the clique bound unless they meet it.
+        let design = run(
+            test_config().with_reg_alloc_strategy(RegAllocStrategy::Heuristic),
+            expr,
+        );
+        assert!(design.storage_units_needed() <= greedy_units);
+        let clique_bound = design.storage_units_clique_bound();
+        assert_eq!(
+            design.storage_units_lower_bound(),
+            (design.storage_units_needed() > clique_bound).then_some(clique_bound)
+        );
+
+        // Bounds meeting without the solver are the minimum even out of time.
+        let design = run(
+            test_config().with_max_total_time(Duration::ZERO),
+            "(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))",
+        );
+        assert!(!design.is_partial());
+        assert_eq!(design.intermediate_fluids().len(), 1);
+        assert_eq!(
+            design.storage_units_clique_bound(),
+            design.storage_units_needed()
+        );
+    }
+
+    #[test]
+    fn extracted_designs_are_transformed_and_revalidated() {
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        let search = |hook: fn(Expr) -> Expr| {
+            let config = test_config().with_post_extract_hook(PostExtractHook::new(hook));
+            Pipeline::default().search(config, 0.01.into(), &input_space)
+        };
+
+        let design = search(|expr| match expr {
+            Expr::Mix(lhs, rhs) => Expr::Mix(rhs, lhs),
+            expr => expr,
+        })
+        .unwrap();
+        assert_eq!(
+            design.mixer_expr(),
+            "(mix (fluid 0.0 3.0) (fluid 0.04 1.0))"
+        );
+        assert_eq!(design.expr().to_string(), design.mixer_expr());
+        // Only the extracted design is known to be optimal.
+        assert!(!design.is_optimal());
+        assert!(search(|expr| expr).unwrap().is_optimal());
+
+        let err = search(|expr| match expr {
+            Expr::Mix(lhs, _) => Expr::Mix(lhs, Box::new(Expr::LimitedFloat(0.5.into()))),
+            expr => expr,
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node `0.5` at root.rhs is an arithmetic remnant, not a fluid."
+        );
+    }
+
+    #[test]
+    fn validation_names_the_node_not_producing_a_fluid() {
+        let mut state = PipelineState::new(test_config(), 0.1.into(), &InputSpace::default());
+        let fluid = Expr::parse("(fluid 0.2 1.0)").unwrap();
+        state.expr = Some(Expr::Mix(
+            Box::new(fluid),
+            Box::new(Expr::LimitedFloat(0.5.into())),
+        ));
+        let err = Pipeline::default()
+            .without("generate")
+            .unwrap()
+            .run(&mut state)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node `0.5` at root.rhs is an arithmetic remnant, not a fluid."
+        );
+    }
+
+    #[test]
+    fn state_is_dumped_after_named_phases() {
+        let config = test_config().with_logging(
+            LogConfig::silent().with_print_after(PrintAfter::Phases(vec!["lower".to_string()])),
+        );
+        let mut state = PipelineState::new(config, 0.0.into(), &InputSpace::default());
+        state.expr = Some(Expr::parse("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))").unwrap());
+        assert_eq!(
+            dump_after("validate", &state),
+            "*** IR Dump After validate ***\n(mix (fluid 0.2 1.0) (fluid 0.0 1.0))\n"
+        );
+        let pipeline = Pipeline::default().without("generate").unwrap();
+        pipeline.run(&mut state).unwrap();
+        assert_eq!(
+            dump_after("lower", &state),
+            "*** IR Dump After lower ***\n%0 = store (0.2,1.0)\n%1 = store (0.0,1.0)\n%2 = mix %0(0.2,1.0) %1(0.0,1.0) -> (0.1,2.0)\n"
+        );
+
+        let config = test_config().with_logging(
+            LogConfig::silent().with_print_after(PrintAfter::Phases(vec!["missing".to_string()])),
+        );
+        let mut state = PipelineState::new(config, 0.0.into(), &InputSpace::default());
+        assert!(matches!(
+            pipeline.run(&mut state),
+            Err(FluidoError::PipelineError(PipelineError::UnknownPhase(_)))
+        ));
+    }
+
+    #[test]
+    fn missing_artifacts_name_the_phase() {
+        let mut state = PipelineState::new(test_config(), 0.1.into(), &InputSpace::default());
+        let err = Pipeline::default()
+            .without("generate")
+            .unwrap()
+            .run(&mut state)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Phase `validate` needs the expr, which no earlier phase produced."
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Capacity planning for a batch of targets produced one after another on the same chip.
+//!
+//! Intermediates needed by several designs of the batch are produced once, by the first design
+//! needing them, and kept in storage until the last design using them is produced. While kept they
+//! occupy a storage unit on top of the storage units the designs produced in between need
+//! themselves, so the order of the targets decides the peak storage of the batch.
+//!
+//! Sub-designs producing the same fluid are reused across the batch even if the designs mix them
+//! differently, which lists the intermediates worth preparing once as stocks.
+use crate::{search_mixer_designs, Config, MixerDesign};
+use fluido_generation::reuse::{self, ReusedSubdesign};
+use fluido_types::{
+    certificate::{CertificateOperand, ExactFluid},
+    error::FluidoError,
+    fluid::Concentration,
+    input_space::InputSpace,
+};
+use serde::Serialize;
+
+/// Batches of up to this many targets are ordered exhaustively, larger ones greedily.
+const MAX_EXHAUSTIVE_TARGETS: usize = 8;
+
+/// An intermediate fluid needed by more than one design of the batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SharedIntermediate {
+    pub fluid: ExactFluid,
+    /// Indices of the designs producing the intermediate, ascending.
+    pub designs: Vec<usize>,
+}
+
+/// Production plan of a batch of targets.
+#[derive(Debug, Clone, Serialize)]
+pub struct CapacityPlan {
+    /// Design of each target, in the order the targets were given.
+    pub designs: Vec<MixerDesign>,
+    /// Order the targets are produced in, as indices into `designs`.
+    pub order: Vec<usize>,
+    /// Storage units in use while producing each target of `order`.
+    pub storage_per_target: Vec<u64>,
+    /// Storage units that suffice for the whole batch.
+    pub storage_units_needed: u64,
+    pub shared_intermediates: Vec<SharedIntermediate>,
+    /// Intermediates produced by more than one design, along with the volume of a stock covering
+    /// all their uses.
+    pub reused_subdesigns: Vec<ReusedSubdesign>,
+}
+
+impl CapacityPlan {
+    /// Plans the production of already found designs, picking the order with minimum peak
+    /// storage.
+    pub fn from_designs(designs: Vec<MixerDesign>) -> Self {
+        let shared_intermediates = shared_intermediates(&designs);
+        let order = if designs.len() <= MAX_EXHAUSTIVE_TARGETS {
+            exhaustive_order(&designs, &shared_intermediates)
+        } else {
+            greedy_order(designs.len(), &shared_intermediates)
+        };
+        let storage_per_target = storage_per_target(&designs, &shared_intermediates, &order);
+        let storage_units_needed = storage_per_target.iter().copied().max().unwrap_or(0);
+        let exprs = designs
+            .iter()
+            .map(|design| design.mixer_expr().parse())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("designs are printed as mixer expressions");
+        let reused_subdesigns = reuse::reused_subdesigns(&exprs);
+        Self {
+            designs,
+            order,
+            storage_per_target,
+            storage_units_needed,
+            shared_intermediates,
+            reused_subdesigns,
+        }
+    }
+}
+
+/// Searches a design for each target, from a single saturation shared by the targets, and plans
+/// their production.
+pub fn plan_batch(
+    config: &Config,
+    targets: &[Concentration],
+    input_space: &InputSpace,
+) -> Result<CapacityPlan, FluidoError> {
+    let designs = search_mixer_designs(config.clone(), targets, input_space)?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CapacityPlan::from_designs(designs))
+}
+
+/// Fluid produced by each mix of the design, along with the index of the mix consuming it.
+fn mixes(design: &MixerDesign) -> Vec<(ExactFluid, Option<usize>)> {
+    let steps = &design.certificate().steps;
+    let mut consumers = vec![None; steps.len()];
+    for (ix, step) in steps.iter().enumerate() {
+        for operand in [step.lhs, step.rhs] {
+            if let CertificateOperand::Step(prev) = operand {
+                consumers[prev] = Some(ix);
+            }
+        }
+    }
+    steps
+        .iter()
+        .map(|step| step.result)
+        .zip(consumers)
+        .collect()
+}
+
+/// Whether the fluid of the mix is consumed, directly or not, by a mix producing `fluid`.
+fn produced_within(
+    mixes: &[(ExactFluid, Option<usize>)],
+    mut mix: usize,
+    fluid: &ExactFluid,
+) -> bool {
+    while let Some(consumer) = mixes[mix].1 {
+        if &mixes[consumer].0 == fluid {
+            return true;
+        }
+        mix = consumer;
+    }
+    false
+}
+
+/// Collects the intermediates produced by more than one design.
+///
+/// Intermediates which are only ever produced as part of a larger shared intermediate, by the same
+/// designs, are left out as reusing the larger one already covers them.
+fn shared_intermediates(designs: &[MixerDesign]) -> Vec<SharedIntermediate> {
+    let mixes = designs.iter().map(mixes).collect::<Vec<_>>();
+    let mut shared: Vec<SharedIntermediate> = vec![];
+    for (design, design_mixes) in mixes.iter().enumerate() {
+        // The final fluid of a design is the target itself, not an intermediate.
+        for (fluid, _) in design_mixes
+            .iter()
+            .filter(|(_, consumer)| consumer.is_some())
+        {
+            match shared
+                .iter_mut()
+                .find(|intermediate| &intermediate.fluid == fluid)
+            {
+                Some(intermediate) if !intermediate.designs.contains(&design) => {
+                    intermediate.designs.push(design)
+                }
+                Some(_) => {}
+                None => shared.push(SharedIntermediate {
+                    fluid: *fluid,
+                    designs: vec![design],
+                }),
+            }
+        }
+    }
+    shared.retain(|intermediate| intermediate.designs.len() > 1);
+
+    let covered = |intermediate: &SharedIntermediate, larger: &SharedIntermediate| {
+        larger.fluid != intermediate.fluid
+            && larger.designs == intermediate.designs
+            && intermediate.designs.iter().all(|&design| {
+                let design_mixes = &mixes[design];
+                (0..design_mixes.len())
+                    .filter(|&mix| design_mixes[mix].0 == intermediate.fluid)
+                    .all(|mix| produced_within(design_mixes, mix, &larger.fluid))
+            })
+    };
+    shared
+        .iter()
+        .filter(|intermediate| !shared.iter().any(|larger| covered(intermediate, larger)))
+        .cloned()
+        .collect()
+}
+
+/// Storage units in use while producing each target of the order.
+fn storage_per_target(
+    designs: &[MixerDesign],
+    shared_intermediates: &[SharedIntermediate],
+    order: &[usize],
+) -> Vec<u64> {
+    let mut positions = vec![0; designs.len()];
+    for (position, &design) in order.iter().enumerate() {
+        positions[design] = position;
+    }
+    let mut storage = order
+        .iter()
+        .map(|&design| designs[design].storage_units_needed())
+        .collect::<Vec<_>>();
+    for intermediate in shared_intermediates {
+        let intermediate_positions = intermediate.designs.iter().map(|&design| positions[design]);
+        let first = intermediate_positions.clone().min().unwrap_or(0);
+        let last = intermediate_positions.max().unwrap_or(0);
+        // Kept from the design producing it first until the last design consumes it.
+        for units in &mut storage[first..last] {
+            *units += 1;
+        }
+    }
+    storage
+}
+
+/// Tries every order and returns the first one with minimum peak storage.
+fn exhaustive_order(
+    designs: &[MixerDesign],
+    shared_intermediates: &[SharedIntermediate],
+) -> Vec<usize> {
+    fn permute(
+        order: &mut Vec<usize>,
+        remaining: &mut Vec<usize>,
+        visit: &mut impl FnMut(&[usize]),
+    ) {
+        if remaining.is_empty() {
+            visit(order);
+            return;
+        }
+        for ix in 0..remaining.len() {
+            let design = remaining.remove(ix);
+            order.push(design);
+            permute(order, remaining, visit);
+            order.pop();
+            remaining.insert(ix, design);
+        }
+    }
+
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    permute(
+        &mut vec![],
+        &mut (0..designs.len()).collect(),
+        &mut |order| {
+            let peak = storage_per_target(designs, shared_intermediates, order)
+                .into_iter()
+                .max()
+                .unwrap_or(0);
+            if best.as_ref().is_none_or(|(best_peak, _)| peak < *best_peak) {
+                best = Some((peak, order.to_vec()));
+            }
+        },
+    );
+    best.map(|(_, order)| order).unwrap_or_default()
+}
+
+/// Builds the order one target at a time, always picking the target which leaves the fewest
+/// shared intermediates waiting in storage.
+fn greedy_order(design_count: usize, shared_intermediates: &[SharedIntermediate]) -> Vec<usize> {
+    let mut produced = vec![false; design_count];
+    let mut order = Vec::with_capacity(design_count);
+    while order.len() < design_count {
+        let open_after = |design: usize| {
+            shared_intermediates
+                .iter()
+                .filter(|intermediate| {
+                    let is_produced = |other: &usize| produced[*other] || *other == design;
+                    intermediate.designs.iter().any(is_produced)
+                        && !intermediate.designs.iter().all(is_produced)
+                })
+                .count()
+        };
+        let next = (0..design_count)
+            .filter(|&design| !produced[design])
+            .min_by_key(|&design| open_after(design))
+            .expect("a target is left to produce");
+        produced[next] = true;
+        order.push(next);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{greedy_order, CapacityPlan};
+    use crate::test_utils::design_from_expr;
+    use fluido_types::number::Rational;
+
+    const SHARED: &str = "(mix (fluid 0.2 1.0) (fluid 0.0 1.0))";
+
+    #[test]
+    fn designs_sharing_intermediates_are_produced_together() {
+        let designs = vec![
+            design_from_expr(&format!("(mix {SHARED} (fluid 0.0 2.0))")),
+            design_from_expr(
+                "(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (mix (fluid 0.4 1.0) (fluid 0.2 1.0)))",
+            ),
+            design_from_expr(&format!("(mix {SHARED} (fluid 0.2 2.0))")),
+        ];
+        let plan = CapacityPlan::from_designs(designs);
+
+        assert_eq!(plan.shared_intermediates.len(), 1);
+        assert_eq!(plan.shared_intermediates[0].designs, vec![0, 2]);
+        // Producing the unrelated target in between would keep the shared intermediate around
+        // while it is being produced.
+        let position = |design| plan.order.iter().position(|&ix| ix == design).unwrap();
+        assert_eq!(position(0).abs_diff(position(2)), 1);
+        let peak = plan.designs[1]
+            .storage_units_needed()
+            .max(plan.designs[0].storage_units_needed() + 1)
+            .max(plan.designs[2].storage_units_needed() + 1);
+        assert_eq!(plan.storage_units_needed, peak);
+
+        assert_eq!(plan.reused_subdesigns.len(), 1);
+        assert_eq!(plan.reused_subdesigns[0].expr, SHARED);
+        assert_eq!(plan.reused_subdesigns[0].designs, vec![0, 2]);
+        assert_eq!(plan.reused_subdesigns[0].total_volume, 4.0.into());
+    }
+
+    #[test]
+    fn nested_shared_intermediates_are_covered() {
+        let larger = format!("(mix {SHARED} (fluid 0.0 2.0))");
+        let designs = vec![
+            design_from_expr(&format!("(mix {larger} (fluid 0.0 4.0))")),
+            design_from_expr(&format!("(mix {larger} (fluid 0.2 4.0))")),
+        ];
+        let plan = CapacityPlan::from_designs(designs);
+        assert_eq!(plan.shared_intermediates.len(), 1);
+        assert_eq!(
+            plan.shared_intermediates[0].fluid.volume,
+            Rational::new(4, 1)
+        );
+    }
+
+    #[test]
+    fn greedy_order_closes_shared_intermediates() {
+        let designs = vec![
+            design_from_expr(&format!("(mix {SHARED} (fluid 0.0 2.0))")),
+            design_from_expr("(mix (fluid 0.4 1.0) (fluid 0.0 1.0))"),
+            design_from_expr(&format!("(mix {SHARED} (fluid 0.2 2.0))")),
+        ];
+        let shared_intermediates = CapacityPlan::from_designs(designs).shared_intermediates;
+        assert_eq!(greedy_order(3, &shared_intermediates), vec![1, 0, 2]);
+    }
+}
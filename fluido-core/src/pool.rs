@@ -0,0 +1,264 @@
+//! Running several searches concurrently on a fixed number of worker threads.
+//!
+//! Searches are CPU bound and run until their time limit, so the pool runs at most one search per
+//! worker and queues the others. The budget of a search is counted from the moment a worker
+//! starts it rather than from the moment it is submitted, so queued searches do not lose their
+//! time waiting. Handles of submitted searches can be waited on, or awaited from any async runtime.
+use crate::{artifacts::RunArtifacts, search_mixer_design_with_artifacts, Config, MixerDesign};
+use fluido_types::{
+    error::{FluidoError, PipelineError},
+    fluid::Concentration,
+    input_space::InputSpace,
+};
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Result of a search run by the pool.
+pub type SearchResult = Result<(MixerDesign, RunArtifacts), FluidoError>;
+
+/// A search waiting to be run by the pool.
+#[derive(Debug, Clone)]
+pub struct SearchJob {
+    config: Config,
+    target_concentration: Concentration,
+    input_space: InputSpace,
+    budget: Option<Duration>,
+}
+
+impl SearchJob {
+    pub fn new(
+        config: Config,
+        target_concentration: Concentration,
+        input_space: &InputSpace,
+    ) -> Self {
+        Self {
+            config,
+            target_concentration,
+            input_space: input_space.clone(),
+            budget: None,
+        }
+    }
+
+    /// Caps the total time of the search like `Config::with_max_total_time`, starting once a
+    /// worker picks the search up.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    fn run(self) -> SearchResult {
+        let config = match self.budget {
+            Some(budget) => self.config.with_max_total_time(budget),
+            None => self.config,
+        };
+        let search = || {
+            search_mixer_design_with_artifacts(config, self.target_concentration, &self.input_space)
+        };
+        // A panicking search must still complete its handle, and must not take the worker down.
+        panic::catch_unwind(AssertUnwindSafe(search)).unwrap_or_else(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(PipelineError::SearchPanicked(message).into())
+        })
+    }
+}
+
+/// Result of a submitted search, shared by the worker running it and its handle.
+#[derive(Default)]
+struct Slot {
+    state: Mutex<SlotState>,
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct SlotState {
+    result: Option<SearchResult>,
+    /// Task awaiting the handle, woken once the result is in.
+    waker: Option<Waker>,
+}
+
+impl Slot {
+    fn complete(&self, result: SearchResult) {
+        let mut state = self.state.lock().unwrap();
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        self.finished.notify_all();
+    }
+}
+
+/// Handle of a search submitted to the pool. Either wait on it with `wait`, or await it.
+pub struct SearchHandle {
+    slot: Arc<Slot>,
+}
+
+impl SearchHandle {
+    /// Whether the search is finished, in which case waiting on it returns immediately.
+    pub fn is_finished(&self) -> bool {
+        self.slot.state.lock().unwrap().result.is_some()
+    }
+
+    /// Blocks the current thread until the search is finished.
+    pub fn wait(self) -> SearchResult {
+        let mut state = self.slot.state.lock().unwrap();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            state = self.slot.finished.wait(state).unwrap();
+        }
+    }
+}
+
+impl Future for SearchHandle {
+    type Output = SearchResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.slot.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Fixed number of worker threads running submitted searches in submission order.
+///
+/// Dropping the pool waits for the submitted searches to finish.
+pub struct SearchPool {
+    sender: Option<mpsc::Sender<(SearchJob, Arc<Slot>)>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl SearchPool {
+    /// Starts the workers, at least one.
+    pub fn new(workers: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<(SearchJob, Arc<Slot>)>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                thread::spawn(move || loop {
+                    // The lock is released before running the search, so other workers can pick
+                    // up the next one meanwhile.
+                    let next = receiver.lock().unwrap().recv();
+                    let Ok((job, slot)) = next else {
+                        // The pool is dropped and the queue is drained.
+                        return;
+                    };
+                    slot.complete(job.run());
+                })
+            })
+            .collect();
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues the search, it starts once a worker is free.
+    pub fn submit(&self, job: SearchJob) -> SearchHandle {
+        let slot = Arc::new(Slot::default());
+        self.sender
+            .as_ref()
+            .expect("sender is only taken when dropping the pool")
+            .send((job, slot.clone()))
+            .expect("workers only stop once the pool is dropped");
+        SearchHandle { slot }
+    }
+}
+
+impl Drop for SearchPool {
+    fn drop(&mut self) {
+        // Closing the queue stops the workers once they drained it.
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchJob, SearchPool};
+    use crate::{Config, LogConfig, MixerDesign, MixerGenerationConfig, MixerGenerator};
+    use fluido_types::{error::FluidoError, input_space::InputSpace};
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+        time::Duration,
+    };
+
+    /// Everything a search is given or returns can be moved to another thread.
+    #[test]
+    fn searches_can_move_between_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Config>();
+        assert_send::<SearchJob>();
+        assert_send::<MixerDesign>();
+        assert_send::<FluidoError>();
+    }
+
+    /// Wakes the thread blocked on a future.
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor, polling the future on the current thread until it is ready.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_searches_complete_their_handles() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = Config::new(
+            MixerGenerationConfig::new(1, MixerGenerator::EqualitySaturation),
+            LogConfig::silent(),
+        );
+        let pool = SearchPool::new(2);
+        let handles = [0.2, 0.1, 0.3]
+            .map(|target| {
+                let job = SearchJob::new(config.clone(), target.into(), &input_space)
+                    .with_budget(Duration::from_secs(5));
+                pool.submit(job)
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut handles = handles.into_iter();
+        let (design, _) = block_on(handles.next().unwrap()).unwrap();
+        assert_eq!(design.mixer_expr(), "(mix (fluid 0.4 1.0) (fluid 0.0 1.0))");
+        for handle in handles {
+            assert!(handle.wait().is_ok());
+        }
+    }
+}
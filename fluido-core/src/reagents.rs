@@ -0,0 +1,165 @@
+//! Accounting of the input fluids (reagents) consumed by a design.
+//!
+//! Designs measure volumes in units of their smallest fluid, while reservoirs are measured in a
+//! physical unit, i.e µL. `ReagentLimits::with_unit_volume` gives the volume of a design unit in
+//! the unit of the limits, and the consumption is converted with it before it is compared with
+//! them. Without it the limits are taken to be in design units.
+use fluido_ir::ir::{IROp, Operand};
+use fluido_types::{
+    decimal,
+    error::DesignValidationError,
+    fluid::{Concentration, Volume},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Volume limits of the reservoir holding an input fluid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReagentLimits {
+    concentration: Concentration,
+    available_volume: Option<Volume>,
+    dead_volume: Volume,
+    unit_volume: Volume,
+}
+
+impl ReagentLimits {
+    /// Creates limits for the input with the given concentration.
+    ///
+    /// `dead_volume` is the part of the reservoir that cannot be drawn, i.e the last few µL a
+    /// pipette cannot reach. If `available_volume` is `None`, the reservoir is assumed to be large
+    /// enough and only the accounting is done.
+    pub fn new(
+        concentration: Concentration,
+        available_volume: Option<Volume>,
+        dead_volume: Volume,
+    ) -> Self {
+        Self {
+            concentration,
+            available_volume,
+            dead_volume,
+            unit_volume: 1.0.into(),
+        }
+    }
+
+    /// Volume of a unit of the design, i.e of its smallest fluid, in the unit of the available and
+    /// dead volumes. The volume drawn by the design is scaled by it.
+    pub fn with_unit_volume(mut self, unit_volume: Volume) -> Self {
+        self.unit_volume = unit_volume;
+        self
+    }
+}
+
+/// Consumption of a single input fluid by a design.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReagentUsage {
+    #[serde(serialize_with = "decimal::serialize")]
+    pub concentration: Concentration,
+    /// Volume drawn from the reservoir by the design, in the unit of its limits, or in design units
+    /// without limits.
+    pub used_volume: f64,
+    /// Volume that has to stay in the reservoir.
+    pub dead_volume: f64,
+    pub available_volume: Option<f64>,
+}
+
+impl ReagentUsage {
+    /// Total volume of the input needed to run the design, including the dead volume.
+    pub fn total_volume(&self) -> f64 {
+        self.used_volume + self.dead_volume
+    }
+}
+
+/// Sums up the volume of each input fluid stored by the ir, and adds the dead volume of their
+/// reservoirs on top.
+pub fn reagent_usage(ir: &[IROp], limits: &[ReagentLimits]) -> Vec<ReagentUsage> {
+    let mut used: BTreeMap<Concentration, f64> = BTreeMap::new();
+    for op in ir {
+        if let IROp::Store((Operand::Const(fluid), _)) = op {
+            let volume: f64 = fluid.unit_volume().clone().into();
+            *used.entry(fluid.concentration().clone()).or_default() += volume;
+        }
+    }
+    used.into_iter()
+        .map(|(concentration, used_volume)| {
+            let limit = limits.iter().find(|l| l.concentration == concentration);
+            let unit_volume = limit.map_or(1.0, |l| l.unit_volume.clone().into());
+            ReagentUsage {
+                dead_volume: limit.map_or(0.0, |l| l.dead_volume.clone().into()),
+                available_volume: limit
+                    .and_then(|l| l.available_volume.clone())
+                    .map(|v| v.into()),
+                concentration,
+                used_volume: used_volume * unit_volume,
+            }
+        })
+        .collect()
+}
+
+/// Fails if any input would need to be drawn below its dead volume.
+pub fn validate_reagent_usage(usage: &[ReagentUsage]) -> Result<(), DesignValidationError> {
+    for reagent in usage {
+        if let Some(available) = reagent.available_volume {
+            if reagent.total_volume() > available {
+                return Err(DesignValidationError::BelowDeadVolume {
+                    concentration: reagent.concentration.clone(),
+                    used: reagent.used_volume,
+                    dead: reagent.dead_volume,
+                    available,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reagent_usage, validate_reagent_usage, ReagentLimits};
+    use crate::test_utils::design_from_expr;
+    use fluido_types::error::DesignValidationError;
+
+    #[test]
+    fn usage_includes_dead_volume() {
+        let design = design_from_expr("(mix (mix (fluid 0.2 1) (fluid 0.0 1)) (fluid 0.0 2))");
+        let limits = vec![ReagentLimits::new(0.0.into(), None, 0.5.into())];
+        let usage = reagent_usage(design.ir(), &limits);
+
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].concentration, 0.0.into());
+        assert_eq!(usage[0].used_volume, 3.0);
+        assert_eq!(usage[0].total_volume(), 3.5);
+        assert_eq!(usage[1].concentration, 0.2.into());
+        assert_eq!(usage[1].total_volume(), 1.0);
+        assert!(validate_reagent_usage(&usage).is_ok());
+    }
+
+    #[test]
+    fn drawing_below_dead_volume_fails() {
+        let design = design_from_expr("(mix (fluid 0.2 1) (fluid 0.0 3))");
+        let limits = vec![ReagentLimits::new(0.0.into(), Some(3.2.into()), 0.5.into())];
+        let usage = reagent_usage(design.ir(), &limits);
+
+        let err = validate_reagent_usage(&usage).unwrap_err();
+        assert!(matches!(err, DesignValidationError::BelowDeadVolume { .. }));
+    }
+
+    #[test]
+    fn design_units_are_converted_to_the_unit_of_the_limits() {
+        let design = design_from_expr("(mix (fluid 0.2 1) (fluid 0.0 3))");
+        // 40 µL reservoirs, of which 5 µL are dead, for designs mixed in units of 10 µL.
+        let limits = [0.2, 0.0].map(|concentration| {
+            ReagentLimits::new(concentration.into(), Some(40.0.into()), 5.0.into())
+                .with_unit_volume(10.0.into())
+        });
+        let usage = reagent_usage(design.ir(), &limits);
+        assert_eq!(usage[0].used_volume, 30.0);
+        assert_eq!(usage[1].used_volume, 10.0);
+        assert!(validate_reagent_usage(&usage).is_ok());
+
+        // 4 units of 10 µL need more than 40 µL along with the dead volume.
+        let design = design_from_expr("(mix (fluid 0.2 1) (fluid 0.0 4))");
+        let usage = reagent_usage(design.ir(), &limits);
+        assert_eq!(usage[0].total_volume(), 45.0);
+        assert!(validate_reagent_usage(&usage).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! Canonical hash of the recipe a design prescribes, so that identical protocols can be recognized
+//! across runs, i.e by lab information systems deduplicating the protocols they store.
+//!
+//! The hash covers the derivation of the design only: its inputs, its mixes and the exact
+//! concentrations and volumes involved. Designs found on different days, with different configs
+//! or with the operands of their mixes in a different order hash the same.
+use fluido_types::certificate::{Certificate, CertificateOperand, ExactFluid};
+use sha2::{Digest, Sha256};
+
+/// Recipe of the design in canonical form, i.e
+/// `(mix (fluid 0/1 1/1) (fluid 2/5 1/1))`. Leaves are written with their exact concentration and
+/// volume, and the operands of each mix are ordered, as mixing is commutative.
+pub fn canonical_recipe(certificate: &Certificate) -> String {
+    fn leaf(fluid: &ExactFluid) -> String {
+        format!("(fluid {} {})", fluid.concentration, fluid.volume)
+    }
+
+    // Steps only refer to previous steps, so each is canonicalized once, in order.
+    let mut steps: Vec<String> = Vec::with_capacity(certificate.steps.len());
+    for step in &certificate.steps {
+        let operand = |operand: CertificateOperand| match operand {
+            CertificateOperand::Leaf(ix) => leaf(&certificate.leaves[ix]),
+            CertificateOperand::Step(ix) => steps[ix].clone(),
+        };
+        let (lhs, rhs) = (operand(step.lhs), operand(step.rhs));
+        let (first, second) = if lhs <= rhs { (lhs, rhs) } else { (rhs, lhs) };
+        steps.push(format!("(mix {first} {second})"));
+    }
+    match steps.pop() {
+        Some(root) => root,
+        // A design without mixes is a single input.
+        None => leaf(&certificate.result),
+    }
+}
+
+/// SHA-256 of the canonical recipe of the design, as lowercase hex.
+pub fn recipe_hash(certificate: &Certificate) -> String {
+    Sha256::digest(canonical_recipe(certificate).as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_recipe, recipe_hash};
+    use fluido_parse::parser::Parse;
+    use fluido_types::{certificate::Certificate, expr::Expr};
+
+    fn certificate(expr: &str) -> Certificate {
+        Certificate::from_expr(&Expr::parse(expr).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn recipes_hash_regardless_of_operand_order() {
+        let design = certificate("(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let swapped = certificate("(mix (fluid 0.0 2.0) (mix (fluid 0.0 1.0) (fluid 0.4 1.0)))");
+        assert_eq!(
+            canonical_recipe(&design),
+            "(mix (fluid 0/1 2/1) (mix (fluid 0/1 1/1) (fluid 2/5 1/1)))"
+        );
+        assert_eq!(recipe_hash(&design), recipe_hash(&swapped));
+        assert_eq!(recipe_hash(&design).len(), 64);
+
+        // Same concentration, but drawn from other volumes.
+        let other = certificate("(mix (mix (fluid 0.4 2.0) (fluid 0.0 2.0)) (fluid 0.0 4.0))");
+        assert_ne!(recipe_hash(&design), recipe_hash(&other));
+    }
+}
@@ -0,0 +1,98 @@
+//! Validation of designs against the intermediates they are required to produce.
+use fluido_types::{
+    certificate::Certificate,
+    decimal,
+    error::DesignValidationError,
+    fluid::{Concentration, LimitedFloat},
+};
+use serde::Serialize;
+
+/// A required intermediate concentration, along with the mix of the design producing it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequiredIntermediate {
+    #[serde(serialize_with = "decimal::serialize")]
+    pub concentration: Concentration,
+    /// Index of the first step of the certificate producing the concentration.
+    pub step: usize,
+}
+
+/// Finds the first intermediate of the derivation with each of the required concentrations, at
+/// the precision of `LimitedFloat`. Concentrations no intermediate has are left out. The final
+/// fluid of the design is the target itself, not an intermediate.
+pub fn locate_required_intermediates(
+    certificate: &Certificate,
+    required_intermediates: &[Concentration],
+) -> Vec<RequiredIntermediate> {
+    let intermediates = certificate.steps.len().saturating_sub(1);
+    let produced = certificate.steps[..intermediates]
+        .iter()
+        .map(|intermediate| LimitedFloat::from(f64::from(intermediate.result.concentration)))
+        .collect::<Vec<_>>();
+    required_intermediates
+        .iter()
+        .filter_map(|concentration| {
+            let step = produced.iter().position(|fluid| fluid == concentration)?;
+            Some(RequiredIntermediate {
+                concentration: concentration.clone(),
+                step,
+            })
+        })
+        .collect()
+}
+
+/// Fails if no intermediate of the derivation has one of the required concentrations.
+pub fn validate_required_intermediates(
+    certificate: &Certificate,
+    required_intermediates: &[Concentration],
+) -> Result<(), DesignValidationError> {
+    let located = locate_required_intermediates(certificate, required_intermediates);
+    match required_intermediates.iter().find(|concentration| {
+        !located
+            .iter()
+            .any(|intermediate| &intermediate.concentration == *concentration)
+    }) {
+        Some(missing) => Err(DesignValidationError::MissingRequiredIntermediate(
+            missing.clone(),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        locate_required_intermediates, validate_required_intermediates, RequiredIntermediate,
+    };
+    use crate::test_utils::design_from_expr;
+    use fluido_types::error::DesignValidationError;
+
+    #[test]
+    fn designs_lacking_a_required_intermediate_are_rejected() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        assert_eq!(
+            validate_required_intermediates(design.certificate(), &[0.4.into()]),
+            Ok(())
+        );
+        assert_eq!(
+            locate_required_intermediates(design.certificate(), &[0.4.into()]),
+            vec![RequiredIntermediate {
+                concentration: 0.4.into(),
+                step: 0
+            }]
+        );
+        assert_eq!(
+            validate_required_intermediates(design.certificate(), &[0.4.into(), 0.6.into()]),
+            Err(DesignValidationError::MissingRequiredIntermediate(
+                0.6.into()
+            ))
+        );
+        // The target itself is not an intermediate.
+        assert_eq!(
+            validate_required_intermediates(design.certificate(), &[0.2.into()]),
+            Err(DesignValidationError::MissingRequiredIntermediate(
+                0.2.into()
+            ))
+        );
+    }
+}
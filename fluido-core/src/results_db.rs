@@ -0,0 +1,231 @@
+//! Results of many runs kept in a single file, i.e for tracking experiments in a lab notebook over
+//! weeks without scripts of its own.
+//!
+//! Each run appends a `RunRecord` as a line of JSON, holding the fingerprint and the description
+//! of its config, the metrics of the search and the recipe hash of its design. Appends of
+//! concurrent runs do not interleave, as each record is written with a single append.
+use crate::{
+    run_directory::{fingerprint, utc_timestamp},
+    MixerDesign,
+};
+use fluido_types::fluid::Concentration;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Metrics of a run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMetrics {
+    /// Seconds the whole search took.
+    pub elapsed_seconds: f64,
+    pub cost: f64,
+    pub mix_count: usize,
+    pub storage_units_needed: u64,
+    /// Estimated time of the design, if the run had a mix time model.
+    pub estimated_time: Option<f64>,
+}
+
+/// A single run of the results database.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Start of the run as a UTC timestamp, i.e `20240102T030405Z`.
+    pub timestamp: String,
+    /// Fingerprint of the config, equal to the one naming the run folder of the run.
+    pub fingerprint: String,
+    /// Description of the config the run was started with.
+    pub config: String,
+    pub target: String,
+    /// Search backend of the run, see `search_backend`.
+    pub backend: String,
+    pub metrics: RunMetrics,
+    /// Recipe hash of the design, equal for runs finding the same protocol.
+    pub recipe_hash: String,
+    pub mixer_expr: String,
+}
+
+impl RunRecord {
+    /// Record of a run started at `started` with the described config, which found the design for
+    /// the target in `elapsed`.
+    pub fn new(
+        config: &str,
+        target: &Concentration,
+        started: SystemTime,
+        elapsed: Duration,
+        design: &MixerDesign,
+    ) -> Self {
+        Self {
+            timestamp: utc_timestamp(started),
+            fingerprint: fingerprint(config),
+            config: config.to_string(),
+            target: target.to_string(),
+            backend: search_backend().to_string(),
+            metrics: RunMetrics {
+                elapsed_seconds: elapsed.as_secs_f64(),
+                cost: design.cost(),
+                mix_count: design.mix_count(),
+                storage_units_needed: design.storage_units_needed(),
+                estimated_time: design.estimated_time(),
+            },
+            recipe_hash: design.recipe_hash().to_string(),
+            mixer_expr: design.mixer_expr(),
+        }
+    }
+
+    /// Whether the run searched for the target. Targets are compared as concentrations, so that
+    /// records written with a different formatting still match.
+    fn has_target(&self, target: &Concentration) -> bool {
+        self.target.parse::<Concentration>().ok().as_ref() == Some(target)
+    }
+}
+
+/// Backend of the searches of this build: the generator along with the storage allocation, which
+/// is minimal with the `smt` feature and greedy without it.
+pub fn search_backend() -> &'static str {
+    if cfg!(feature = "smt") {
+        "equality-saturation+smt"
+    } else {
+        "equality-saturation+greedy"
+    }
+}
+
+/// A JSON lines file holding a `RunRecord` for each run, created with the first record appended to
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultsDb {
+    path: PathBuf,
+}
+
+impl ResultsDb {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends the record as a new line.
+    pub fn append(&self, record: &RunRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    /// Every record, in the order they were appended. Empty if no record was appended yet, fails
+    /// with the number of the first malformed line.
+    pub fn records(&self) -> io::Result<Vec<RunRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(ix, line)| {
+                serde_json::from_str(line).map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: {err}", self.path.display(), ix + 1),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Cheapest design ever found for the target, the one needing fewer storage units among equally
+    /// cheap ones, and the earliest among those.
+    pub fn best_design(&self, target: &Concentration) -> io::Result<Option<RunRecord>> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter(|record| record.has_target(target))
+            .min_by(|lhs, rhs| {
+                lhs.metrics.cost.total_cmp(&rhs.metrics.cost).then(
+                    lhs.metrics
+                        .storage_units_needed
+                        .cmp(&rhs.metrics.storage_units_needed),
+                )
+            }))
+    }
+
+    /// Average seconds a run took, by backend.
+    pub fn average_time_by_backend(&self) -> io::Result<BTreeMap<String, f64>> {
+        let mut totals: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+        for record in self.records()? {
+            let (seconds, runs) = totals.entry(record.backend).or_default();
+            *seconds += record.metrics.elapsed_seconds;
+            *runs += 1;
+        }
+        Ok(totals
+            .into_iter()
+            .map(|(backend, (seconds, runs))| (backend, seconds / runs as f64))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResultsDb, RunRecord};
+    use crate::test_utils::design_from_expr;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn records_are_appended_and_queried() {
+        let path =
+            std::env::temp_dir().join(format!("fluido_results_db_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = ResultsDb::open(&path);
+        assert!(db.records().unwrap().is_empty());
+
+        let single = design_from_expr("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))");
+        let double =
+            design_from_expr("(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let record = |config: &str, design, seconds| {
+            let mut record = RunRecord::new(
+                config,
+                &0.1.into(),
+                SystemTime::now(),
+                Duration::from_secs(seconds),
+                design,
+            );
+            record.metrics.cost = record.metrics.mix_count as f64;
+            record
+        };
+        let slow = RunRecord {
+            backend: "other".to_string(),
+            ..record("time_limit: 10", &double, 4)
+        };
+        db.append(&record("time_limit: 1", &double, 1)).unwrap();
+        db.append(&record("time_limit: 2", &single, 2)).unwrap();
+        db.append(&slow).unwrap();
+
+        let records = db.records().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2], slow);
+        assert_ne!(records[0].fingerprint, records[1].fingerprint);
+        let best = db.best_design(&0.1.into()).unwrap().unwrap();
+        assert_eq!(best.mixer_expr, single.mixer_expr());
+        assert_eq!(best.recipe_hash, single.recipe_hash());
+        assert_eq!(db.best_design(&0.2.into()).unwrap(), None);
+        let averages = db.average_time_by_backend().unwrap();
+        assert_eq!(averages.len(), 2);
+        assert_eq!(averages["other"], 4.0);
+        assert_eq!(averages[super::search_backend()], 1.5);
+
+        std::fs::write(&path, "{}\n").unwrap();
+        let err = db.records().unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains(":1: "));
+    }
+}
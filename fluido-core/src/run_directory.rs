@@ -0,0 +1,251 @@
+//! Layout of the outputs of a run, kept next to the outputs of previous runs.
+//!
+//! Each run gets its own folder below the output root, named after the time it started and a
+//! fingerprint of its config, i.e `run-20240102T030405Z-1a2b3c4d`:
+//!
+//! ```text
+//! <root>/
+//!   LATEST                  name of the latest run folder
+//!   latest -> run-...       link to the latest run folder, where the platform allows it
+//!   run-.../
+//!     config.txt            config the run was started with
+//!     artifacts/            intermediate artifacts of the search
+//!     logs/                 logs of the search
+//!     reports/              reports of the found design
+//! ```
+//!
+//! Scripts should read `LATEST` rather than follow `latest`, as creating links needs extra
+//! privileges on Windows.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of the run folders, other entries of the root are left alone.
+const RUN_PREFIX: &str = "run-";
+/// File holding the name of the latest run folder.
+const LATEST_FILE: &str = "LATEST";
+/// Link to the latest run folder.
+const LATEST_LINK: &str = "latest";
+
+/// Folder holding the outputs of a single run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunDirectory {
+    path: PathBuf,
+}
+
+impl RunDirectory {
+    /// Creates the folder of a new run below the root, creating the root if needed, and marks it
+    /// as the latest run. `config` is a description of the config of the run, written into the
+    /// folder and fingerprinted into its name.
+    pub fn create(root: &Path, config: &str) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        let name = format!(
+            "{RUN_PREFIX}{}-{}",
+            utc_timestamp(SystemTime::now()),
+            fingerprint(config)
+        );
+        // Runs with the same config started within the same second get a suffix.
+        let mut path = root.join(&name);
+        let mut suffix = 1;
+        while path.exists() {
+            suffix += 1;
+            path = root.join(format!("{name}-{suffix}"));
+        }
+        fs::create_dir(&path)?;
+        let run = Self { path };
+        for dir in [run.artifacts(), run.logs(), run.reports()] {
+            fs::create_dir(dir)?;
+        }
+        fs::write(run.path.join("config.txt"), config)?;
+        run.mark_latest(root)?;
+        Ok(run)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn artifacts(&self) -> PathBuf {
+        self.path.join("artifacts")
+    }
+
+    pub fn logs(&self) -> PathBuf {
+        self.path.join("logs")
+    }
+
+    pub fn reports(&self) -> PathBuf {
+        self.path.join("reports")
+    }
+
+    fn name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .expect("run folders are named by `create`")
+    }
+
+    /// Points `LATEST` and `latest` to the run. Both are replaced by renaming, so that scripts
+    /// never observe them half written.
+    fn mark_latest(&self, root: &Path) -> io::Result<()> {
+        let pending = root.join(format!("{LATEST_FILE}.{}", std::process::id()));
+        fs::write(&pending, format!("{}\n", self.name()))?;
+        fs::rename(&pending, root.join(LATEST_FILE))?;
+
+        let pending = root.join(format!("{LATEST_LINK}.{}", std::process::id()));
+        let _ = fs::remove_file(&pending);
+        if link_dir(Path::new(self.name()), &pending).is_ok() {
+            #[cfg(windows)]
+            let _ = fs::remove_dir(root.join(LATEST_LINK));
+            fs::rename(&pending, root.join(LATEST_LINK))?;
+        }
+        Ok(())
+    }
+}
+
+/// Creates a link at `link` to the folder `target`, relative to the folder of the link.
+#[cfg(unix)]
+fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Creates a link at `link` to the folder `target`, relative to the folder of the link. Fails
+/// without developer mode or administrator rights.
+#[cfg(windows)]
+fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn link_dir(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Folder of the latest run below the root, if a run was created there.
+pub fn latest_run(root: &Path) -> io::Result<Option<PathBuf>> {
+    match fs::read_to_string(root.join(LATEST_FILE)) {
+        Ok(name) => Ok(Some(root.join(name.trim()))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Removes all but the `keep` most recent run folders below the root, returning the removed ones.
+pub fn prune_runs(root: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let mut runs = fs::read_dir(root)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(RUN_PREFIX))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    // Names start with the timestamp, so they sort chronologically.
+    runs.sort();
+    let stale = runs.len().saturating_sub(keep);
+    let removed = runs.drain(..stale).collect::<Vec<_>>();
+    for run in &removed {
+        fs::remove_dir_all(run)?;
+    }
+    Ok(removed)
+}
+
+/// Path as it should be shown to users. Canonical paths on Windows are verbatim, i.e
+/// `\\?\C:\runs`, which most tools do not accept, so the prefix is dropped where the path stays
+/// valid without it.
+pub fn display_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{unc}");
+    }
+    match path.strip_prefix(r"\\?\") {
+        // Drive paths only, other verbatim paths have no plain form.
+        Some(plain) if plain.as_bytes().get(1) == Some(&b':') => plain.to_string(),
+        _ => path,
+    }
+}
+
+/// Stable fingerprint of a config description, as 8 hex digits. Uses FNV-1a rather than the
+/// standard hasher, whose output may change between Rust releases.
+pub fn fingerprint(config: &str) -> String {
+    let hash = config.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:08x}", (hash >> 32) ^ (hash & 0xffff_ffff))
+}
+
+/// Time formatted as a compact ISO 8601 UTC timestamp, i.e `20240102T030405Z`.
+pub(crate) fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (days, seconds_of_day) = (seconds / 86400, seconds % 86400);
+    // Civil date of the day count, from Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display_path, fingerprint, latest_run, prune_runs, utc_timestamp, RunDirectory};
+    use std::{
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn runs_are_laid_out_below_the_root() {
+        let root = std::env::temp_dir().join(format!("fluido-runs-{}", std::process::id()));
+        let first = RunDirectory::create(&root, "time_limit: 1").unwrap();
+        let second = RunDirectory::create(&root, "time_limit: 1").unwrap();
+        assert_ne!(first, second);
+        assert!(first.artifacts().is_dir() && first.logs().is_dir() && first.reports().is_dir());
+        let config = std::fs::read_to_string(first.path().join("config.txt")).unwrap();
+        assert_eq!(config, "time_limit: 1");
+        assert_eq!(latest_run(&root).unwrap().as_deref(), Some(second.path()));
+        #[cfg(unix)]
+        assert_eq!(
+            root.join("latest").canonicalize().unwrap(),
+            second.path().canonicalize().unwrap()
+        );
+
+        let removed = prune_runs(&root, 1).unwrap();
+        assert_eq!(removed, vec![first.path().to_path_buf()]);
+        assert!(second.path().is_dir());
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(latest_run(&root).unwrap(), None);
+    }
+
+    #[test]
+    fn names_are_stable() {
+        let time = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3 * 3600 + 4 * 60 + 5);
+        assert_eq!(utc_timestamp(time), "20000229T030405Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(fingerprint("time_limit: 1"), fingerprint("time_limit: 1"));
+        assert_ne!(fingerprint("time_limit: 1"), fingerprint("time_limit: 2"));
+        assert_eq!(fingerprint("").len(), 8);
+    }
+
+    #[test]
+    fn verbatim_prefixes_are_dropped() {
+        let display = |path: &str| display_path(Path::new(path));
+        assert_eq!(display(r"\\?\C:\runs\latest"), r"C:\runs\latest");
+        assert_eq!(display(r"\\?\UNC\lab\share\runs"), r"\\lab\share\runs");
+        assert_eq!(display(r"\\?\Volume{1234}\runs"), r"\\?\Volume{1234}\runs");
+        assert_eq!(display("/tmp/runs"), "/tmp/runs");
+    }
+}
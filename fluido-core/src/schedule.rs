@@ -0,0 +1,203 @@
+//! Estimating the protocol time of a design executed on multiple independent mixers.
+//!
+//! Mixes which do not depend on each other can run in parallel, so the protocol time is bounded
+//! by the critical path of the design rather than the total time of its mixes. Fewer mixers than
+//! the design can use in parallel reuse each mixer for more mixes, and stretch the protocol
+//! beyond its critical path.
+use fluido_types::certificate::{Certificate, CertificateOperand};
+use serde::Serialize;
+
+/// Schedule estimate of a design for a number of mixers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduleEstimate {
+    /// Number of independent mixers the design is scheduled on.
+    pub mixers: usize,
+    /// Duration of the longest chain of dependent mixes, a lower bound for any schedule.
+    pub critical_path: f64,
+    /// Duration of the list schedule on `mixers` mixers.
+    pub makespan: f64,
+    /// Start time of each mix in the list schedule, indexed like the certificate steps.
+    pub starts: Vec<f64>,
+    /// Finish time of each mix in the list schedule, indexed like the certificate steps.
+    pub finishes: Vec<f64>,
+    /// Fewest mixers the list schedule finishes within the critical path on.
+    pub min_mixers: usize,
+    /// Makespan of the list schedule on one mixer, two mixers and so on up to `min_mixers`.
+    pub makespans: Vec<f64>,
+}
+
+impl ScheduleEstimate {
+    /// Whether the schedule on `mixers` mixers is as short as the critical path, i.e no mix waits
+    /// for a mixer to become free.
+    pub fn fits(&self) -> bool {
+        self.makespan <= self.critical_path
+    }
+}
+
+/// Schedules the mixes of the certificate on `mixers` mixers. `durations` holds the duration of
+/// each step of the certificate.
+///
+/// Uses list scheduling: whenever a mixer is free, the ready mix with the longest remaining path
+/// to the end of the design is started. The fewest mixers are searched the same way, by
+/// scheduling on one more mixer until the makespan reaches the critical path. For mixes of equal
+/// duration in a design without shared intermediates this is Hu's algorithm, which is optimal,
+/// otherwise `min_mixers` is an upper bound.
+pub fn estimate_schedule(
+    certificate: &Certificate,
+    durations: &[f64],
+    mixers: usize,
+) -> ScheduleEstimate {
+    let mixers = mixers.max(1);
+    let step_count = certificate.steps.len();
+    let dependencies = certificate
+        .steps
+        .iter()
+        .map(|step| {
+            [step.lhs, step.rhs]
+                .into_iter()
+                .filter_map(|operand| match operand {
+                    CertificateOperand::Step(ix) => Some(ix),
+                    CertificateOperand::Leaf(_) => None,
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    // Steps only depend on previous steps, so walking backwards visits successors first.
+    let mut bottom_level = durations.to_vec();
+    for step in (0..step_count).rev() {
+        for &dependency in &dependencies[step] {
+            bottom_level[dependency] =
+                bottom_level[dependency].max(durations[dependency] + bottom_level[step]);
+        }
+    }
+    let critical_path = bottom_level.iter().copied().fold(0.0, f64::max);
+
+    // One mixer per mix runs every mix as soon as it is ready, which reaches the critical path.
+    let mut makespans = vec![];
+    while makespans.len() < step_count.max(1) {
+        let (_, finishes) =
+            list_schedule(&dependencies, durations, &bottom_level, makespans.len() + 1);
+        let makespan = finishes.iter().copied().fold(0.0, f64::max);
+        makespans.push(makespan);
+        if makespan <= critical_path {
+            break;
+        }
+    }
+
+    let (starts, finishes) = list_schedule(&dependencies, durations, &bottom_level, mixers);
+    let makespan = finishes.iter().copied().fold(0.0, f64::max);
+
+    ScheduleEstimate {
+        mixers,
+        critical_path,
+        makespan,
+        starts,
+        finishes,
+        min_mixers: makespans.len(),
+        makespans,
+    }
+}
+
+/// Start and finish time of each step in the list schedule on `mixers` mixers, prioritizing
+/// ready steps by their bottom level.
+fn list_schedule(
+    dependencies: &[Vec<usize>],
+    durations: &[f64],
+    bottom_level: &[f64],
+    mixers: usize,
+) -> (Vec<f64>, Vec<f64>) {
+    let step_count = dependencies.len();
+    let mut starts = vec![0.0; step_count];
+    let mut started = vec![false; step_count];
+    let mut finishes = vec![f64::INFINITY; step_count];
+    let mut running: Vec<usize> = vec![];
+    let mut time = 0.0;
+    let mut scheduled = 0;
+    while scheduled < step_count {
+        running.retain(|&step| finishes[step] > time);
+        let mut ready = (0..step_count)
+            .filter(|&step| {
+                !started[step]
+                    && dependencies[step]
+                        .iter()
+                        .all(|&dependency| finishes[dependency] <= time)
+            })
+            .collect::<Vec<_>>();
+        ready.sort_by(|lhs, rhs| bottom_level[*rhs].total_cmp(&bottom_level[*lhs]));
+        for step in ready.into_iter().take(mixers - running.len()) {
+            started[step] = true;
+            starts[step] = time;
+            finishes[step] = time + durations[step];
+            running.push(step);
+            scheduled += 1;
+        }
+        time = running
+            .iter()
+            .map(|&step| finishes[step])
+            .fold(f64::INFINITY, f64::min);
+    }
+    (starts, finishes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::estimate_schedule;
+    use fluido_parse::parser::Parse;
+    use fluido_types::{certificate::Certificate, expr::Expr};
+
+    fn certificate(expr_str: &str) -> Certificate {
+        Certificate::from_expr(&Expr::parse(expr_str).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn balanced_tree_runs_in_parallel() {
+        // Two independent mixes followed by a final one.
+        let certificate = certificate(
+            "(mix (mix (fluid 0.1 1.0) (fluid 0.2 1.0)) (mix (fluid 0.3 1.0) (fluid 0.4 1.0)))",
+        );
+        let durations = [1.0, 1.0, 1.0];
+
+        let single = estimate_schedule(&certificate, &durations, 1);
+        assert_eq!(single.critical_path, 2.0);
+        assert_eq!(single.makespan, 3.0);
+
+        let parallel = estimate_schedule(&certificate, &durations, 2);
+        assert_eq!(parallel.critical_path, 2.0);
+        assert_eq!(parallel.makespan, 2.0);
+        assert_eq!(parallel.starts, vec![0.0, 0.0, 1.0]);
+        assert_eq!(parallel.finishes, vec![1.0, 1.0, 2.0]);
+        assert!(!single.fits() && parallel.fits());
+        assert_eq!(single.min_mixers, 2);
+        assert_eq!(single.makespans, vec![3.0, 2.0]);
+    }
+
+    #[test]
+    fn fewer_mixers_stretch_the_protocol() {
+        // Four independent mixes, combined pairwise.
+        let certificate = certificate(
+            "(mix (mix (mix (fluid 0.1 1.0) (fluid 0.2 1.0)) (mix (fluid 0.3 1.0) (fluid 0.4 1.0))) (mix (mix (fluid 0.5 1.0) (fluid 0.6 1.0)) (mix (fluid 0.7 1.0) (fluid 0.8 1.0))))",
+        );
+        let durations = [1.0; 7];
+
+        let schedule = estimate_schedule(&certificate, &durations, 2);
+        assert_eq!(schedule.critical_path, 3.0);
+        assert_eq!(schedule.makespan, 4.0);
+        assert!(!schedule.fits());
+        assert_eq!(schedule.min_mixers, 4);
+        assert_eq!(schedule.makespans, vec![7.0, 4.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn longest_path_is_scheduled_first() {
+        // Chain of two mixes next to a single slow mix.
+        let certificate = certificate(
+            "(mix (mix (mix (fluid 0.1 1.0) (fluid 0.2 1.0)) (fluid 0.3 1.0)) (mix (fluid 0.3 1.0) (fluid 0.4 1.0)))",
+        );
+        let durations = [1.0, 1.0, 3.0, 1.0];
+
+        let schedule = estimate_schedule(&certificate, &durations, 2);
+        assert_eq!(schedule.critical_path, 4.0);
+        assert_eq!(schedule.makespan, 4.0);
+    }
+}
@@ -0,0 +1,153 @@
+//! Sensitivity of the concentration a design produces to the concentrations of its leaves.
+//!
+//! The final concentration is a volume weighted average of the leaf concentrations, so it changes
+//! linearly with each of them. Perturbing a leaf by ±ε changes the final concentration by
+//! ±coefficient·ε, where the coefficient is the share of the leaf in the final volume.
+use fluido_types::{
+    certificate::{Certificate, CertificateOperand, ExactFluid},
+    decimal,
+    fluid::{Concentration, LimitedFloat},
+    number::Rational,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Perturbation of leaves whose input has no declared uncertainty, the precision concentrations
+/// are given with.
+pub const DEFAULT_PERTURBATION: f64 = LimitedFloat::EPSILON;
+
+/// How much the final concentration moves with a single leaf of the design.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LeafSensitivity {
+    /// Index of the leaf in the certificate of the design.
+    pub leaf: usize,
+    #[serde(serialize_with = "decimal::serialize")]
+    pub concentration: Concentration,
+    /// Change of the final concentration per unit change of the leaf concentration.
+    pub coefficient: f64,
+    /// Perturbation the leaf is analyzed with, the uncertainty of its input if declared.
+    pub perturbation: f64,
+    /// Change of the final concentration when the leaf is perturbed.
+    pub deviation: f64,
+}
+
+/// How much the final concentration moves with an input, all leaves drawing from it together.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StockSensitivity {
+    #[serde(serialize_with = "decimal::serialize")]
+    pub concentration: Concentration,
+    pub coefficient: f64,
+    pub perturbation: f64,
+    pub deviation: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SensitivityReport {
+    /// Sensitivity to each leaf, in the order of the certificate leaves.
+    pub leaves: Vec<LeafSensitivity>,
+    /// Sensitivity to each input, the one whose accuracy matters most first.
+    pub stocks: Vec<StockSensitivity>,
+}
+
+/// Analyzes the sensitivity of the derivation to its leaves, perturbing each leaf by the
+/// uncertainty of its input, or by `DEFAULT_PERTURBATION` if it has none.
+pub fn analyze_sensitivity(
+    certificate: &Certificate,
+    uncertainties: &HashMap<Concentration, f64>,
+) -> SensitivityReport {
+    let base = certificate.result.concentration;
+    let leaves = certificate
+        .leaves
+        .iter()
+        .enumerate()
+        .map(|(leaf, fluid)| {
+            let concentration = LimitedFloat::from(f64::from(fluid.concentration));
+            // Exact change of the final concentration for a unit change of the leaf.
+            let coefficient = f64::from(derive_perturbed(certificate, leaf) - base);
+            let perturbation = uncertainties
+                .get(&concentration)
+                .copied()
+                .unwrap_or(DEFAULT_PERTURBATION);
+            LeafSensitivity {
+                leaf,
+                concentration,
+                coefficient,
+                perturbation,
+                deviation: coefficient * perturbation,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut stocks: BTreeMap<Concentration, StockSensitivity> = BTreeMap::new();
+    for leaf in &leaves {
+        let stock = stocks
+            .entry(leaf.concentration.clone())
+            .or_insert_with(|| StockSensitivity {
+                concentration: leaf.concentration.clone(),
+                coefficient: 0.0,
+                perturbation: leaf.perturbation,
+                deviation: 0.0,
+            });
+        stock.coefficient += leaf.coefficient;
+        stock.deviation += leaf.deviation;
+    }
+    let mut stocks = stocks.into_values().collect::<Vec<_>>();
+    stocks.sort_by(|lhs, rhs| rhs.deviation.abs().total_cmp(&lhs.deviation.abs()));
+    SensitivityReport { leaves, stocks }
+}
+
+/// Final concentration of the derivation with the concentration of the leaf increased by one.
+fn derive_perturbed(certificate: &Certificate, perturbed_leaf: usize) -> Rational {
+    let mut leaves = certificate.leaves.clone();
+    leaves[perturbed_leaf].concentration =
+        leaves[perturbed_leaf].concentration + Rational::new(1, 1);
+    let mut results: Vec<ExactFluid> = Vec::with_capacity(certificate.steps.len());
+    for step in &certificate.steps {
+        let operand = |operand: CertificateOperand| match operand {
+            CertificateOperand::Leaf(ix) => leaves[ix],
+            CertificateOperand::Step(ix) => results[ix],
+        };
+        let (lhs, rhs) = (operand(step.lhs), operand(step.rhs));
+        let volume = lhs.volume + rhs.volume;
+        let concentration =
+            (lhs.concentration * lhs.volume + rhs.concentration * rhs.volume) / volume;
+        results.push(ExactFluid {
+            concentration,
+            volume,
+        });
+    }
+    results
+        .last()
+        .unwrap_or(&leaves[perturbed_leaf])
+        .concentration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze_sensitivity, DEFAULT_PERTURBATION};
+    use crate::test_utils::design_from_expr;
+    use std::collections::HashMap;
+
+    #[test]
+    fn coefficients_are_volume_shares_of_the_leaves() {
+        let design =
+            design_from_expr("(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let uncertainties = HashMap::from([(0.8.into(), 0.01)]);
+        let report = analyze_sensitivity(design.certificate(), &uncertainties);
+
+        let coefficients = report
+            .leaves
+            .iter()
+            .map(|leaf| leaf.coefficient)
+            .collect::<Vec<_>>();
+        assert_eq!(coefficients, vec![0.25, 0.25, 0.5]);
+        assert_eq!(report.leaves[0].deviation, 0.0025);
+        assert_eq!(report.leaves[1].perturbation, DEFAULT_PERTURBATION);
+
+        // Leaves of the same input move together, and the uncertain stock matters most.
+        assert_eq!(report.stocks.len(), 2);
+        assert_eq!(report.stocks[0].concentration, 0.8.into());
+        assert_eq!(report.stocks[1].concentration, 0.0.into());
+        assert_eq!(report.stocks[1].coefficient, 0.75);
+    }
+}
@@ -0,0 +1,292 @@
+//! Monte Carlo simulation of how faithfully a design produces its concentration on a real chip.
+//!
+//! Each sample carries out the derivation of the design with errors drawn for every operation: the
+//! volume of each operand drawn into a mix is off by a pipetting error, and the concentration each
+//! mix produces is off by a mixing error, both relative to the nominal value. The spread of the
+//! final concentration over many samples tells designs of similar nominal cost apart, i.e one with
+//! fewer mixes or more even ratios is usually the more faithful one.
+use crate::benchmark::percentile;
+use fluido_types::{
+    certificate::{Certificate, CertificateOperand},
+    fluid::Concentration,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::{fmt::Display, str::FromStr};
+
+/// Distribution of a relative error, i.e `Normal { std_dev: 0.01 }` for a 1% standard deviation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorDistribution {
+    /// The operation is exact.
+    #[default]
+    None,
+    Normal {
+        std_dev: f64,
+    },
+    /// Errors are equally likely anywhere within ±`half_width`.
+    Uniform {
+        half_width: f64,
+    },
+}
+
+impl ErrorDistribution {
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            ErrorDistribution::None => 0.0,
+            // Box-Muller transform of two uniform samples.
+            ErrorDistribution::Normal { std_dev } => {
+                let (u1, u2) = (1.0 - rng.gen::<f64>(), rng.gen::<f64>());
+                std_dev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            }
+            ErrorDistribution::Uniform { half_width } if half_width > 0.0 => {
+                rng.gen_range(-half_width..=half_width)
+            }
+            ErrorDistribution::Uniform { .. } => 0.0,
+        }
+    }
+}
+
+impl FromStr for ErrorDistribution {
+    type Err = String;
+
+    /// Parses `none`, `normal:<std dev>` or `uniform:<half width>`, i.e `normal:0.01`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "none" {
+            return Ok(ErrorDistribution::None);
+        }
+        let (kind, width) = s.split_once(':').ok_or_else(|| {
+            format!("expected `normal:<std dev>` or `uniform:<half width>`, found `{s}`")
+        })?;
+        let width = match width.trim().parse::<f64>() {
+            Ok(width) if width.is_finite() && width >= 0.0 => width,
+            _ => return Err(format!("`{}` is not a relative error", width.trim())),
+        };
+        match kind.trim() {
+            "normal" => Ok(ErrorDistribution::Normal { std_dev: width }),
+            "uniform" => Ok(ErrorDistribution::Uniform { half_width: width }),
+            kind => Err(format!(
+                "unknown distribution `{kind}`, expected `normal` or `uniform`"
+            )),
+        }
+    }
+}
+
+impl Display for ErrorDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorDistribution::None => write!(f, "none"),
+            ErrorDistribution::Normal { std_dev } => write!(f, "normal:{std_dev}"),
+            ErrorDistribution::Uniform { half_width } => write!(f, "uniform:{half_width}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationConfig {
+    samples: usize,
+    seed: u64,
+    pipetting_error: ErrorDistribution,
+    mixing_error: ErrorDistribution,
+    interval: f64,
+}
+
+impl SimulationConfig {
+    pub fn new(samples: usize) -> Self {
+        Self {
+            samples,
+            seed: 0,
+            pipetting_error: ErrorDistribution::None,
+            mixing_error: ErrorDistribution::None,
+            interval: 0.95,
+        }
+    }
+
+    /// Seed of the sampled errors, the same seed samples the same errors for the same design.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Relative error of the volume of each operand drawn into a mix.
+    pub fn with_pipetting_error(mut self, pipetting_error: ErrorDistribution) -> Self {
+        self.pipetting_error = pipetting_error;
+        self
+    }
+
+    /// Relative error of the concentration each mix produces, i.e of incomplete mixing.
+    pub fn with_mixing_error(mut self, mixing_error: ErrorDistribution) -> Self {
+        self.mixing_error = mixing_error;
+        self
+    }
+
+    /// Share of the samples between the percentile bounds of the report, defaults to 0.95.
+    pub fn with_interval(mut self, interval: f64) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// Empirical distribution of the final concentration of a design.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimulationReport {
+    pub samples: usize,
+    /// Concentration the design produces without errors.
+    pub nominal: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Share of the samples between `lower` and `upper`.
+    pub interval: f64,
+    /// Percentile of the samples below the interval.
+    pub lower: f64,
+    /// Percentile of the samples above the interval.
+    pub upper: f64,
+    /// Root mean square distance of the samples to the target, the single number to compare
+    /// designs by.
+    pub rms_error: f64,
+}
+
+/// Samples the final concentration of the derivation with the errors of the config, and reports
+/// its distribution along with its distance to the target. `None` without samples.
+pub fn simulate_fidelity(
+    certificate: &Certificate,
+    target_concentration: &Concentration,
+    config: &SimulationConfig,
+) -> Option<SimulationReport> {
+    if config.samples == 0 {
+        return None;
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut concentrations = (0..config.samples)
+        .map(|_| sample_concentration(certificate, config, &mut rng))
+        .collect::<Vec<_>>();
+    concentrations.sort_by(f64::total_cmp);
+
+    let samples = concentrations.len() as f64;
+    let mean = concentrations.iter().sum::<f64>() / samples;
+    let variance = concentrations
+        .iter()
+        .map(|concentration| (concentration - mean).powi(2))
+        .sum::<f64>()
+        / samples;
+    let target = f64::from(target_concentration.clone());
+    let squared_error = concentrations
+        .iter()
+        .map(|concentration| (concentration - target).powi(2))
+        .sum::<f64>();
+    let tail = (1.0 - config.interval.clamp(0.0, 1.0)) / 2.0 * 100.0;
+    Some(SimulationReport {
+        samples: config.samples,
+        nominal: certificate.result.concentration.into(),
+        mean,
+        std_dev: variance.sqrt(),
+        interval: config.interval,
+        lower: percentile(&concentrations, tail)?,
+        upper: percentile(&concentrations, 100.0 - tail)?,
+        rms_error: (squared_error / samples).sqrt(),
+    })
+}
+
+/// Final concentration of a single run of the derivation with sampled errors.
+fn sample_concentration(
+    certificate: &Certificate,
+    config: &SimulationConfig,
+    rng: &mut StdRng,
+) -> f64 {
+    let leaves = certificate
+        .leaves
+        .iter()
+        .map(|leaf| (f64::from(leaf.concentration), f64::from(leaf.volume)))
+        .collect::<Vec<_>>();
+    let mut results: Vec<(f64, f64)> = Vec::with_capacity(certificate.steps.len());
+    for step in &certificate.steps {
+        let mut operand = |operand: CertificateOperand| {
+            let (concentration, volume) = match operand {
+                CertificateOperand::Leaf(ix) => leaves[ix],
+                CertificateOperand::Step(ix) => results[ix],
+            };
+            let drawn = volume * (1.0 + config.pipetting_error.sample(rng)).max(0.0);
+            (concentration, drawn)
+        };
+        let (lhs, rhs) = (operand(step.lhs), operand(step.rhs));
+        let volume = lhs.1 + rhs.1;
+        let concentration = match volume > 0.0 {
+            true => (lhs.0 * lhs.1 + rhs.0 * rhs.1) / volume,
+            false => 0.0,
+        };
+        let concentration = concentration * (1.0 + config.mixing_error.sample(rng));
+        results.push((concentration.max(0.0), volume));
+    }
+    results
+        .last()
+        .or(leaves.first())
+        .map_or(0.0, |(concentration, _)| *concentration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate_fidelity, ErrorDistribution, SimulationConfig};
+    use crate::test_utils::design_from_expr;
+
+    #[test]
+    fn errors_spread_the_final_concentration() {
+        let design = design_from_expr("(mix (fluid 0.4 1.0) (fluid 0.0 1.0))");
+        let exact = SimulationConfig::new(100);
+        let report = simulate_fidelity(design.certificate(), &0.2.into(), &exact).unwrap();
+        // Up to the rounding of summing the samples.
+        assert!((report.mean - 0.2).abs() < 1e-12);
+        assert!(report.std_dev < 1e-12 && report.rms_error < 1e-12);
+        assert_eq!((report.lower, report.upper), (0.2, 0.2));
+        assert!(
+            simulate_fidelity(design.certificate(), &0.2.into(), &SimulationConfig::new(0))
+                .is_none()
+        );
+
+        let noisy = SimulationConfig::new(2000)
+            .with_pipetting_error(ErrorDistribution::Normal { std_dev: 0.05 })
+            .with_mixing_error(ErrorDistribution::Uniform { half_width: 0.01 })
+            .with_seed(3);
+        let report = simulate_fidelity(design.certificate(), &0.2.into(), &noisy).unwrap();
+        assert_eq!(report.nominal, 0.2);
+        assert!((report.mean - 0.2).abs() < 0.002);
+        assert!(report.std_dev > 0.0 && report.std_dev < 0.02);
+        assert!(report.lower < 0.2 && 0.2 < report.upper);
+        assert_eq!(
+            simulate_fidelity(design.certificate(), &0.2.into(), &noisy),
+            Some(report)
+        );
+
+        // Every mix adds its own error, so diluting in more steps is less faithful.
+        let mixing = SimulationConfig::new(2000)
+            .with_mixing_error(ErrorDistribution::Normal { std_dev: 0.05 });
+        let direct = design_from_expr("(mix (fluid 0.8 1.0) (fluid 0.0 7.0))");
+        let chained = design_from_expr(
+            "(mix (mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0)) (fluid 0.0 4.0))",
+        );
+        let direct = simulate_fidelity(direct.certificate(), &0.1.into(), &mixing).unwrap();
+        let chained = simulate_fidelity(chained.certificate(), &0.1.into(), &mixing).unwrap();
+        assert!(chained.rms_error > direct.rms_error);
+    }
+
+    #[test]
+    fn distributions_are_parsed() {
+        assert_eq!(
+            "normal:0.01".parse::<ErrorDistribution>(),
+            Ok(ErrorDistribution::Normal { std_dev: 0.01 })
+        );
+        assert_eq!(
+            "uniform:0.02"
+                .parse::<ErrorDistribution>()
+                .unwrap()
+                .to_string(),
+            "uniform:0.02"
+        );
+        assert_eq!(
+            "none".parse::<ErrorDistribution>(),
+            Ok(ErrorDistribution::None)
+        );
+        assert!("normal:-1".parse::<ErrorDistribution>().is_err());
+        assert!("gamma:0.1".parse::<ErrorDistribution>().is_err());
+        assert!("0.1".parse::<ErrorDistribution>().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Checking designs against inputs which are only stable for a limited number of steps.
+//!
+//! An intermediate containing a degrading input has to be consumed within the number of mixes the
+//! input stays stable for. Steps are counted along the execution order of the flat ir, an
+//! intermediate consumed by the very next mix lives for one step.
+use fluido_ir::ir::{evaluate_ir, IROp, Operand};
+use fluido_types::{
+    decimal,
+    fluid::{Concentration, Fluid},
+};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// An intermediate which is consumed later than one of its inputs stays stable for.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StabilityViolation {
+    /// The intermediate fluid.
+    #[serde(serialize_with = "decimal::serialize_fluid")]
+    pub fluid: Fluid,
+    /// Index of the mix producing the intermediate, in execution order.
+    pub produced_at: usize,
+    /// Index of the mix consuming the intermediate, in execution order.
+    pub consumed_at: usize,
+    /// The input with the tightest limit among the inputs of the intermediate.
+    #[serde(serialize_with = "decimal::serialize")]
+    pub input: Concentration,
+    /// Number of mixes the input stays stable for.
+    pub max_steps_live: usize,
+}
+
+impl StabilityViolation {
+    /// Number of mixes the intermediate lives for.
+    pub fn steps_live(&self) -> usize {
+        self.consumed_at - self.produced_at
+    }
+}
+
+/// Finds the intermediates of the ir which are consumed later than their inputs allow, given the
+/// number of mixes each degrading input stays stable for.
+pub fn check_stability(
+    ir: &[IROp],
+    max_steps_live: &HashMap<Concentration, usize>,
+) -> Vec<StabilityViolation> {
+    if max_steps_live.is_empty() {
+        return vec![];
+    }
+    let fluids = evaluate_ir(ir);
+    // Inputs each virtual register contains, and the mix producing it.
+    let mut inputs: HashMap<usize, BTreeSet<Concentration>> = HashMap::new();
+    let mut produced_at: HashMap<usize, usize> = HashMap::new();
+    let mut violations = vec![];
+    let mut mix_ix = 0;
+    for op in ir {
+        let (lhs, rhs, target) = match op {
+            IROp::Mix(mix) => mix,
+            IROp::Store((Operand::Const(fluid), Operand::VirtualRegister(target))) => {
+                inputs.insert(*target, BTreeSet::from([fluid.concentration().clone()]));
+                continue;
+            }
+            IROp::Store(_) => continue,
+        };
+        let mut contained = BTreeSet::new();
+        for operand in [lhs, rhs] {
+            let Some(vreg) = operand.as_virtual_register() else {
+                continue;
+            };
+            let operand_inputs = inputs.get(&vreg).cloned().unwrap_or_default();
+            if let Some(&produced) = produced_at.get(&vreg) {
+                let tightest = operand_inputs
+                    .iter()
+                    .filter_map(|input| Some((input, *max_steps_live.get(input)?)))
+                    .min_by_key(|(_, max_steps_live)| *max_steps_live);
+                if let (Some((input, max_steps_live)), Some(fluid)) = (tightest, fluids.get(&vreg))
+                {
+                    if mix_ix - produced > max_steps_live {
+                        violations.push(StabilityViolation {
+                            fluid: fluid.clone(),
+                            produced_at: produced,
+                            consumed_at: mix_ix,
+                            input: input.clone(),
+                            max_steps_live,
+                        });
+                    }
+                }
+            }
+            contained.extend(operand_inputs);
+        }
+        if let Some(target) = target.as_virtual_register() {
+            inputs.insert(target, contained);
+            produced_at.insert(target, mix_ix);
+        }
+        mix_ix += 1;
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_stability;
+    use crate::test_utils::design_from_expr;
+    use fluido_types::fluid::{Concentration, Fluid};
+    use std::collections::HashMap;
+
+    #[test]
+    fn late_consumed_intermediates_are_reported() {
+        // The mix of 0.8 and 0.0 is lowered first, then two more mixes run before it is consumed
+        // by the last one.
+        let design = design_from_expr(
+            "(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0)))",
+        );
+        let limits = |limits: &[(f64, usize)]| {
+            limits
+                .iter()
+                .map(|(input, steps)| (Concentration::from(*input), *steps))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let violations = check_stability(design.ir(), &limits(&[(0.8, 2)]));
+        assert_eq!(violations.len(), 1);
+        let violation = &violations[0];
+        assert_eq!(violation.fluid, Fluid::new(0.4.into(), 2.0.into()));
+        assert_eq!((violation.produced_at, violation.consumed_at), (0, 3));
+        assert_eq!(violation.steps_live(), 3);
+        assert_eq!(violation.input, 0.8.into());
+        assert!(check_stability(design.ir(), &limits(&[(0.8, 3)])).is_empty());
+
+        // The tightest limit of the inputs counts, intermediates of intermediates included.
+        let violations = check_stability(design.ir(), &limits(&[(0.0, 1), (0.8, 5)]));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].input, 0.0.into());
+        assert_eq!(violations[0].max_steps_live, 1);
+        assert!(check_stability(design.ir(), &HashMap::new()).is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! Inputs pinned to fixed storage units, i.e for chips whose inputs enter through dedicated ports.
+//!
+//! Unlike an `AllocationHint`, pins are never dropped: every leaf drawing from a pinned input is
+//! allocated its storage unit, even if that needs more storage units than the minimum. Pins which
+//! no allocation can keep, as the design holds two fluids pinned to the same unit at once, fail
+//! the search.
+use fluido_ir::ir::{IROp, Operand};
+use fluido_types::{
+    error::{ConversionError, StorageAllocationError},
+    fluid::Concentration,
+    input_space::{Input, InputSpace},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+/// Input a pin refers to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PinnedInput {
+    /// The input with the name.
+    Name(String),
+    /// The input with the concentration, looked up with the tolerance of the input space.
+    Concentration(Concentration),
+}
+
+impl Display for PinnedInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinnedInput::Name(name) => write!(f, "{name}"),
+            PinnedInput::Concentration(concentration) => write!(f, "{concentration}"),
+        }
+    }
+}
+
+/// Storage unit every leaf drawing from an input is kept in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoragePin {
+    pub input: PinnedInput,
+    pub storage_unit: u64,
+}
+
+impl FromStr for StoragePin {
+    type Err = String;
+
+    /// Parses `<input>=<storage unit>`, where the input is a name or a concentration, i.e
+    /// `stock=1` or `0.4=1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (input, storage_unit) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<input>=<storage unit>`, found `{s}`"))?;
+        let storage_unit = storage_unit
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("`{}` is not a storage unit id", storage_unit.trim()))?;
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(format!("missing input in `{s}`"));
+        }
+        let input = match input.parse::<Concentration>() {
+            Ok(concentration) => PinnedInput::Concentration(concentration),
+            Err(ConversionError::Unparsable(_)) => PinnedInput::Name(input.to_string()),
+            Err(err) => return Err(err.to_string()),
+        };
+        Ok(Self {
+            input,
+            storage_unit,
+        })
+    }
+}
+
+/// Pinned storage unit of each virtual register of the ir storing a pinned input, along with the
+/// input it stores.
+pub fn pinned_vregs(
+    pins: &[StoragePin],
+    ir: &[IROp],
+    input_space: &InputSpace,
+) -> Result<HashMap<usize, (u64, String)>, StorageAllocationError> {
+    let pinned_inputs = pins
+        .iter()
+        .map(|pin| {
+            let input = match &pin.input {
+                PinnedInput::Name(name) => input_space.by_name(name),
+                PinnedInput::Concentration(concentration) => input_space.get(concentration),
+            };
+            let concentration = input
+                .map(Input::concentration)
+                .ok_or_else(|| StorageAllocationError::UnknownPinnedInput(pin.input.to_string()))?;
+            Ok((concentration, pin.storage_unit))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let pinned = ir
+        .iter()
+        .filter_map(|op| match op {
+            IROp::Store((Operand::Const(fluid), Operand::VirtualRegister(vreg))) => {
+                let input = input_space.get(fluid.concentration())?;
+                let (_, storage_unit) = pinned_inputs
+                    .iter()
+                    .find(|(concentration, _)| *concentration == input.concentration())?;
+                let name = match input.name() {
+                    Some(name) => format!("`{name}`"),
+                    None => format!("input `{}`", input.concentration()),
+                };
+                Some((*vreg, (*storage_unit, name)))
+            }
+            _ => None,
+        })
+        .collect();
+    Ok(pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PinnedInput, StoragePin};
+    use crate::{
+        pipeline::{Pipeline, PipelineState},
+        test_utils::test_config,
+    };
+    use fluido_parse::parser::Parse;
+    use fluido_types::{
+        error::{FluidoError, StorageAllocationError},
+        expr::Expr,
+        fluid::Fluid,
+        input_space::{Input, InputSpace},
+    };
+
+    fn allocate(expr: &str, pins: &[&str]) -> Result<PipelineState, FluidoError> {
+        let input_space = InputSpace::new([
+            Input::new(Fluid::new(0.8.into(), 1.0.into())).with_name("stock"),
+            Input::new(Fluid::new(0.2.into(), 1.0.into())),
+            Input::new(Fluid::new(0.0.into(), 1.0.into())).with_name("water"),
+        ]);
+        let pins = pins.iter().map(|pin| pin.parse().unwrap()).collect();
+        let mut state = PipelineState::new(
+            test_config().with_storage_pins(pins),
+            0.0.into(),
+            &input_space,
+        );
+        state.expr = Some(Expr::parse(expr).unwrap());
+        Pipeline::default()
+            .without("generate")
+            .unwrap()
+            .run(&mut state)?;
+        Ok(state)
+    }
+
+    #[test]
+    fn pinned_inputs_keep_their_storage_units() {
+        assert_eq!(
+            "stock=1".parse::<StoragePin>(),
+            Ok(StoragePin {
+                input: PinnedInput::Name("stock".to_string()),
+                storage_unit: 1,
+            })
+        );
+        assert!("stock".parse::<StoragePin>().is_err());
+        assert!("stock=first".parse::<StoragePin>().is_err());
+        assert!("NaN=0".parse::<StoragePin>().is_err());
+
+        // The minimum is two units, pinning the stock to a fourth one still keeps it there.
+        let expr =
+            "(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (mix (fluid 0.2 1.0) (fluid 0.0 1.0)))";
+        let state = allocate(expr, &["stock=3", "0.2=0"]).unwrap();
+        let design = state.design.unwrap();
+        assert_eq!(design.storage_allocation[&0], 3);
+        assert_eq!(design.storage_allocation[&3], 0);
+        assert_eq!(design.storage_units_needed(), 4);
+        #[cfg(feature = "smt")]
+        assert_eq!(design.storage_units_lower_bound(), None);
+
+        assert!(matches!(
+            allocate(expr, &["juice=0"]),
+            Err(FluidoError::StorageAllocationError(
+                StorageAllocationError::UnknownPinnedInput(_)
+            ))
+        ));
+        // Both inputs of the first mix are held at once.
+        let err = allocate(expr, &["water=1", "stock=1"]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Storage unit 1 is pinned to both `stock` and `water`, which the design holds at the same time (instructions 0 and 1)."
+        );
+    }
+}
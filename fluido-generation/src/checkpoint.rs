@@ -0,0 +1,171 @@
+use crate::{ArithmeticAnalysis, MixLang, MixRunner};
+use egg::EGraph;
+use fluido_types::error::MixerGenerationError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Settings for periodically writing the saturation state to disk.
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    /// File the checkpoints are written to. Each checkpoint overwrites the previous one.
+    pub path: PathBuf,
+    /// Minimum duration between two consecutive checkpoints.
+    pub interval: Duration,
+    /// Once set, saturation stops, a final checkpoint is written and the search fails with
+    /// `MixerGenerationError::Interrupted`.
+    pub interrupted: Arc<AtomicBool>,
+    /// Whether a saturation is running, and so has progress a final checkpoint would save.
+    saturating: Arc<AtomicBool>,
+}
+
+impl CheckpointConfig {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            saturating: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Stops the running saturation, so that its final checkpoint is written. Returns `false` if
+    /// no saturation is running, in which case there is nothing left to checkpoint and the caller
+    /// can stop the search right away, i.e by exiting the process.
+    pub fn interrupt(&self) -> bool {
+        self.interrupted.store(true, Ordering::SeqCst);
+        self.saturating.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_saturating(&self, saturating: bool) {
+        self.saturating.store(saturating, Ordering::SeqCst);
+    }
+}
+
+/// Stop reason of a runner the checkpoint hook stopped as the search was interrupted.
+pub(crate) const INTERRUPTED: &str = "interrupted";
+
+/// Snapshot of a saturation run, which can be used to continue the run later on.
+#[derive(Deserialize)]
+pub(crate) struct Checkpoint {
+    /// Number of iterations done before this checkpoint was taken, across all resumed runs.
+    pub(crate) iterations: usize,
+    /// Seconds spent saturating before this checkpoint was taken, across all resumed runs.
+    pub(crate) saturation_seconds: f64,
+    pub(crate) egraph: EGraph<MixLang, ArithmeticAnalysis>,
+}
+
+/// Borrowed counterpart of `Checkpoint`, so that the e-graph does not need to be cloned for
+/// writing.
+#[derive(Serialize)]
+pub(crate) struct CheckpointRef<'a> {
+    iterations: usize,
+    saturation_seconds: f64,
+    egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+}
+
+impl Checkpoint {
+    pub(crate) fn load(path: &Path) -> Result<Self, MixerGenerationError> {
+        let checkpoint_str = fs::read_to_string(path).map_err(|e| {
+            MixerGenerationError::CheckpointError(format!("failed to read {:?}: {}", path, e))
+        })?;
+        serde_json::from_str(&checkpoint_str).map_err(|e| {
+            MixerGenerationError::CheckpointError(format!("failed to parse {:?}: {}", path, e))
+        })
+    }
+}
+
+impl<'a> CheckpointRef<'a> {
+    /// Creates a checkpoint from the current state of the runner, on top of the progress that was
+    /// already done before the runner started.
+    pub(crate) fn from_runner(
+        runner: &'a MixRunner,
+        prior_iterations: usize,
+        prior_saturation_seconds: f64,
+    ) -> Self {
+        let saturation_seconds: f64 = runner.iterations.iter().map(|iter| iter.total_time).sum();
+        Self {
+            iterations: prior_iterations + runner.iterations.len(),
+            saturation_seconds: prior_saturation_seconds + saturation_seconds,
+            egraph: &runner.egraph,
+        }
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<(), MixerGenerationError> {
+        let checkpoint_str = serde_json::to_string(self)
+            .map_err(|e| MixerGenerationError::CheckpointError(e.to_string()))?;
+        // Write to a temporary file first so that an interruption while writing does not
+        // corrupt the previous checkpoint.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, checkpoint_str)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|e| {
+                MixerGenerationError::CheckpointError(format!("failed to write {:?}: {}", path, e))
+            })
+    }
+}
+
+/// Returns a runner hook which writes a checkpoint every `config.interval` and stops the runner
+/// once `config.interrupted` is set.
+pub(crate) fn checkpoint_hook(
+    config: CheckpointConfig,
+    prior_iterations: usize,
+    prior_saturation_seconds: f64,
+) -> impl FnMut(&mut MixRunner) -> Result<(), String> + 'static {
+    let mut last_checkpoint = Instant::now();
+    move |runner| {
+        if config.interrupted.load(Ordering::SeqCst) {
+            return Err(INTERRUPTED.to_string());
+        }
+        if last_checkpoint.elapsed() >= config.interval {
+            CheckpointRef::from_runner(runner, prior_iterations, prior_saturation_seconds)
+                .write(&config.path)
+                .map_err(|e| e.to_string())?;
+            last_checkpoint = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, CheckpointRef};
+    use crate::{ArithmeticAnalysis, MixLang};
+    use egg::{EGraph, RecExpr};
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let expr = "(mix (fluid 0.1 1.0) (fluid 0.2 1.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        egraph.add_expr(&expr);
+        egraph.rebuild();
+        let checkpoint = CheckpointRef {
+            iterations: 3,
+            saturation_seconds: 1.5,
+            egraph: &egraph,
+        };
+
+        let path = std::env::temp_dir().join("fluido_checkpoint_round_trip.json");
+        checkpoint.write(&path).unwrap();
+        let mut loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        loaded.egraph.rebuild();
+
+        assert_eq!(loaded.iterations, 3);
+        assert_eq!(loaded.saturation_seconds, 1.5);
+        assert_eq!(
+            loaded.egraph.total_number_of_nodes(),
+            egraph.total_number_of_nodes()
+        );
+        assert!(loaded.egraph.lookup_expr(&expr).is_some());
+    }
+}
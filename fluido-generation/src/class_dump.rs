@@ -0,0 +1,211 @@
+//! Dot rendering of the e-class of the target and its neighborhood, showing the choices the
+//! extractor had and what each of them costs.
+use crate::{
+    extract::{cheapest_nodes, is_arithmetic, ExtractionMode},
+    is_design_cost, ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang,
+};
+use egg::{CostFunction, EGraph, Id, Language};
+use fluido_types::precision::Precision;
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    fmt::Write,
+};
+
+/// Part of the e-graph around the target e-class which is dumped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassDumpConfig {
+    /// E-classes reachable from the target e-class through at most this many e-nodes are
+    /// included.
+    pub depth: usize,
+    /// Number of members of the target e-class listed with their cheapest expression.
+    pub top_k: usize,
+    /// Precision of the analysis data and costs in the labels. Listed expressions are kept as
+    /// they are.
+    pub precision: Precision,
+}
+
+impl Default for ClassDumpConfig {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            top_k: 5,
+            precision: Precision::Full,
+        }
+    }
+}
+
+/// Renders the target e-class and the e-classes below it as dot, each e-class as a cluster
+/// labeled with its analysis data. E-nodes are labeled with the total cost of their cheapest
+/// expression, and the cheapest e-node of each e-class is highlighted. The target e-class
+/// additionally lists its `top_k` cheapest members with their expressions.
+pub(crate) fn target_class_dot<CF>(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    root: Id,
+    mut cost_fn: CF,
+    mode: ExtractionMode,
+    config: ClassDumpConfig,
+) -> String
+where
+    CF: CostFunction<MixLang, Cost = f64>,
+{
+    let root = egraph.find(root);
+    let best = cheapest_nodes(egraph, &mut cost_fn, mode);
+    let mut member_cost = |enode: &MixLang| {
+        let extractable = mode == ExtractionMode::Penalized || !is_arithmetic(enode);
+        (extractable && enode.all(|id| best.contains_key(&egraph.find(id))))
+            .then(|| cost_fn.cost(enode, |id| best[&egraph.find(id)].0))
+    };
+
+    // Breadth first, so that each e-class is at its shortest distance from the target.
+    let mut depths = HashMap::from([(root, 0)]);
+    let mut classes = vec![];
+    let mut queue = VecDeque::from([root]);
+    while let Some(id) = queue.pop_front() {
+        classes.push(id);
+        let depth = depths[&id];
+        if depth == config.depth {
+            continue;
+        }
+        for enode in &egraph[id].nodes {
+            for child in enode.children() {
+                let child = egraph.find(*child);
+                if let Entry::Vacant(entry) = depths.entry(child) {
+                    entry.insert(depth + 1);
+                    queue.push_back(child);
+                }
+            }
+        }
+    }
+
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph target_class {{");
+    let _ = writeln!(dot, "  compound=true");
+    let _ = writeln!(dot, "  clusterrank=local");
+    let mut edges = vec![];
+    for id in &classes {
+        let class = &egraph[*id];
+        let mut label = format!(
+            "e-class {id}: {}",
+            render_data(&class.data, &config.precision)
+        );
+        if *id == root {
+            let mut members = class
+                .nodes
+                .iter()
+                .filter_map(|enode| Some((member_cost(enode)?, enode)))
+                .collect::<Vec<_>>();
+            members.sort_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+            for (rank, (cost, enode)) in members.iter().take(config.top_k).enumerate() {
+                let expr = enode.build_recexpr(|id| best[&egraph.find(id)].1.clone());
+                let _ = write!(
+                    label,
+                    "\\n#{} cost {}: {expr}",
+                    rank + 1,
+                    render_cost(*cost, &config.precision)
+                );
+            }
+        }
+        let _ = writeln!(dot, "  subgraph cluster_{id} {{");
+        let style = if *id == root { "bold" } else { "dotted" };
+        let _ = writeln!(dot, "    style={style}");
+        let _ = writeln!(dot, "    label=\"{label}\"");
+        for (ix, enode) in class.nodes.iter().enumerate() {
+            let cost = member_cost(enode).map_or("unextractable".to_string(), |cost| {
+                render_cost(cost, &config.precision)
+            });
+            let chosen = best.get(id).is_some_and(|(_, chosen)| chosen == enode);
+            let color = if chosen { ", color=red" } else { "" };
+            let _ = writeln!(
+                dot,
+                "    \"{id}.{ix}\" [label=\"{enode}\\ncost {cost}\"{color}]"
+            );
+            if depths[id] < config.depth {
+                for child in enode.children() {
+                    edges.push((*id, ix, egraph.find(*child)));
+                }
+            }
+        }
+        let _ = writeln!(dot, "  }}");
+    }
+    for (id, ix, child) in edges {
+        let _ = writeln!(
+            dot,
+            "  \"{id}.{ix}\" -> \"{child}.0\" [lhead=cluster_{child}]"
+        );
+    }
+    let _ = writeln!(dot, "}}");
+    dot
+}
+
+fn render_data(data: &ArithmeticAnalysisPayload, precision: &Precision) -> String {
+    match data {
+        ArithmeticAnalysisPayload::LimitedFloat(value) => precision.format_limited(value),
+        ArithmeticAnalysisPayload::Fluid(fluid) => precision.format_fluid(fluid),
+        ArithmeticAnalysisPayload::None => "no data".to_string(),
+    }
+}
+
+/// Expressions with the target fluid as a leaf cost `TARGET_LEAF_COST`, they are not designs.
+fn render_cost(cost: f64, precision: &Precision) -> String {
+    if !is_design_cost(cost) {
+        "inf".to_string()
+    } else {
+        precision.format(cost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{target_class_dot, ClassDumpConfig};
+    use crate::{extract::ExtractionMode, ArithmeticAnalysis, MixLang, OpCost};
+    use egg::{EGraph, RecExpr};
+    use fluido_types::{input_space::InputSpace, precision::Precision};
+
+    #[test]
+    fn target_class_lists_its_members_by_cost() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let designs = [
+            "(mix (mix (fluid 0.2 0.5) (fluid 0.0 0.5)) (mix (fluid 0.2 0.5) (fluid 0.0 0.5)))",
+            "(mix (fluid 0.2 1.0) (fluid 0.0 1.0))",
+            "(mix (fluid 0.0 1.0) (fluid 0.2 1.0))",
+        ]
+        .map(|design| egraph.add_expr(&design.parse::<RecExpr<MixLang>>().unwrap()));
+        for design in &designs[1..] {
+            egraph.union(designs[0], *design);
+        }
+        egraph.rebuild();
+        let root = egraph.find(designs[0]);
+
+        let input_space = InputSpace::from_concentrations([0.2.into(), 0.0.into()]);
+        let dump = |config| {
+            let cost_fn = OpCost::new(0.1.into(), input_space.clone(), &egraph);
+            target_class_dot(&egraph, root, cost_fn, ExtractionMode::Penalized, config)
+        };
+
+        let dot = dump(ClassDumpConfig {
+            depth: 0,
+            top_k: 2,
+            ..Default::default()
+        });
+        assert!(dot.contains(&format!("subgraph cluster_{root}")));
+        assert!(dot.contains("#1 cost 1: (mix (fluid 0.2 1.0) (fluid 0.0 1.0))"));
+        assert!(dot.contains("#2 cost 1"));
+        assert!(!dot.contains("#3"));
+        assert!(dot.contains("mix\\ncost 3"));
+        // The fluid of the class itself has the target concentration, it is not a design.
+        assert!(dot.contains("fluid\\ncost inf"));
+        assert_eq!(dot.matches("subgraph").count(), 1);
+        assert!(!dot.contains("->"));
+
+        // Fluids below the mixes and the literals below the fluids.
+        let dot = dump(ClassDumpConfig {
+            depth: 2,
+            top_k: 3,
+            precision: Precision::DecimalPlaces(1),
+        });
+        assert!(dot.contains("#3 cost 3.0"));
+        assert!(dot.contains("e-class") && dot.matches("subgraph").count() > 3);
+        assert!(dot.contains(&format!("\"{root}.0\" ->")));
+        assert!(dot.contains("color=red"));
+    }
+}
@@ -0,0 +1,160 @@
+//! Regression tests for the cost function.
+//!
+//! Each fixture under `fixtures/cost/` stores a small problem, the number of iterations it is
+//! saturated for, and the expression and cost the extractor chose for it. The e-graph is rebuilt
+//! from the problem on every run, saturating without a time or node limit so that it is the same
+//! each time. Changing `OpCost` or the rules changes these results, which makes the test fail until
+//! the fixtures are updated with `FLUIDO_BLESS=1 cargo test`. This way cost model changes show up
+//! as reviewable fixture diffs.
+//!
+//! Fixtures which are missing, or describe another problem than their case, fail the test too,
+//! and are only recorded with `FLUIDO_BLESS=1`.
+use crate::{
+    extract::{CheapestExtractor, ExtractionMode},
+    generate_rewrite_rules, target_node, ArithmeticAnalysis, OpCost, SaturationConfig,
+};
+use egg::{EGraph, Runner};
+use fluido_types::{fluid::Concentration, input_space::InputSpace};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, time::Duration};
+
+/// A problem recorded as fixture, saturated for `iterations` iterations.
+struct FixtureCase {
+    name: &'static str,
+    target: f64,
+    input_space: &'static [f64],
+    iterations: usize,
+}
+
+const CASES: &[FixtureCase] = &[
+    FixtureCase {
+        name: "quarter_dilution",
+        target: 0.01,
+        input_space: &[0.04, 0.0],
+        iterations: 3,
+    },
+    FixtureCase {
+        name: "half_dilution",
+        target: 0.2,
+        input_space: &[0.4, 0.0],
+        iterations: 3,
+    },
+    FixtureCase {
+        name: "between_two_stocks",
+        target: 0.3,
+        input_space: &[0.2, 0.4],
+        iterations: 3,
+    },
+];
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct CostFixture {
+    target: Concentration,
+    input_space: Vec<Concentration>,
+    iterations: usize,
+    expected_expr: String,
+    expected_cost: f64,
+}
+
+impl CostFixture {
+    /// Problem of the case, without the expression and cost extracted for it.
+    fn problem(case: &FixtureCase) -> Self {
+        Self {
+            target: Concentration::from(case.target),
+            input_space: case
+                .input_space
+                .iter()
+                .map(|conc| Concentration::from(*conc))
+                .collect(),
+            iterations: case.iterations,
+            expected_expr: String::new(),
+            expected_cost: 0.0,
+        }
+    }
+
+    /// Fixture of the case, with the expression and cost extracted today.
+    fn record(case: &FixtureCase) -> Self {
+        let mut fixture = Self::problem(case);
+        (fixture.expected_cost, fixture.expected_expr) = fixture.extract();
+        fixture
+    }
+
+    /// Whether the fixture describes the problem of the case.
+    fn describes(&self, case: &FixtureCase) -> bool {
+        let problem = Self::problem(case);
+        (&self.target, &self.input_space, self.iterations)
+            == (&problem.target, &problem.input_space, problem.iterations)
+    }
+
+    /// Saturates the problem for its iterations and extracts from the target.
+    fn extract(&self) -> (f64, String) {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let root = egraph.add_expr(&target_node(&self.target));
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(self.iterations)
+            .with_node_limit(usize::MAX)
+            .with_time_limit(Duration::MAX)
+            .run(&generate_rewrite_rules(&SaturationConfig::default()));
+        let root = runner.egraph.find(root);
+        let input_space = InputSpace::from_concentrations(self.input_space.iter().cloned());
+        let cost_function = OpCost::new(self.target.clone(), input_space, &runner.egraph);
+        let (cost, expr) =
+            CheapestExtractor::new(&runner.egraph, cost_function, ExtractionMode::Penalized)
+                .find_best(root)
+                .unwrap();
+        (cost, expr.to_string())
+    }
+}
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/cost")
+        .join(format!("{name}.json"))
+}
+
+#[test]
+fn cost_function_regressions() {
+    let bless = std::env::var_os("FLUIDO_BLESS").is_some();
+    let mut failures = vec![];
+    for case in CASES {
+        let path = fixture_path(case.name);
+        let recorded = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<CostFixture>(&json).ok())
+            .filter(|fixture| fixture.describes(case));
+        let fixture = CostFixture::record(case);
+        if recorded.as_ref() == Some(&fixture) {
+            continue;
+        }
+        if bless {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&fixture).unwrap() + "\n",
+            )
+            .unwrap();
+            continue;
+        }
+        failures.push(match recorded {
+            Some(recorded) => format!(
+                "{}: expected `{}` with cost {}, extracted `{}` with cost {}",
+                case.name,
+                recorded.expected_expr,
+                recorded.expected_cost,
+                fixture.expected_expr,
+                fixture.expected_cost
+            ),
+            None => format!(
+                "{}: {} is missing or describes another problem",
+                case.name,
+                path.display()
+            ),
+        });
+    }
+    assert!(
+        failures.is_empty(),
+        "cost function results changed, rerun with `FLUIDO_BLESS=1` if this is intended:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,92 @@
+use crate::{ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang};
+use egg::EGraph;
+use fluido_types::{fluid::Concentration, number::Rational};
+use std::{collections::BTreeSet, fmt::Display};
+
+/// Number of equal width buckets the `[0, 1]` concentration range is divided into.
+pub const COVERAGE_BUCKETS: usize = 10;
+
+/// Statistics about the concentrations represented in a saturated e-graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcentrationCoverage {
+    /// Number of distinct fluid concentrations.
+    pub distinct: usize,
+    pub min: Option<Concentration>,
+    pub max: Option<Concentration>,
+    /// Number of distinct concentrations falling into each bucket, from `0` to `1`.
+    pub histogram: [usize; COVERAGE_BUCKETS],
+}
+
+impl ConcentrationCoverage {
+    pub(crate) fn from_egraph(egraph: &EGraph<MixLang, ArithmeticAnalysis>) -> Self {
+        let concentrations = egraph
+            .classes()
+            .filter_map(|class| match &class.data {
+                ArithmeticAnalysisPayload::Fluid(fluid) => Some(fluid.concentration().clone()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        Self::from_concentrations(&concentrations)
+    }
+
+    pub(crate) fn from_concentrations(concentrations: &BTreeSet<Concentration>) -> Self {
+        let mut histogram = [0; COVERAGE_BUCKETS];
+        for concentration in concentrations {
+            // Use exact arithmetic, so that bucket boundaries like `0.2` are not misplaced.
+            let value = Rational::from(concentration);
+            let bucket = (value.numerator() * COVERAGE_BUCKETS as i128 / value.denominator())
+                .clamp(0, COVERAGE_BUCKETS as i128 - 1);
+            histogram[bucket as usize] += 1;
+        }
+        Self {
+            distinct: concentrations.len(),
+            min: concentrations.first().cloned(),
+            max: concentrations.last().cloned(),
+            histogram,
+        }
+    }
+}
+
+impl Display for ConcentrationCoverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Concentration coverage")?;
+        writeln!(f, "======================")?;
+        writeln!(f, "  Distinct concentrations: {}", self.distinct)?;
+        if let (Some(min), Some(max)) = (&self.min, &self.max) {
+            writeln!(f, "  Range: {min} - {max}")?;
+        }
+        let width = 1.0 / COVERAGE_BUCKETS as f64;
+        for (ix, count) in self.histogram.iter().enumerate() {
+            let low = ix as f64 * width;
+            writeln!(f, "    [{:.1}, {:.1}): {}", low, low + width, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcentrationCoverage;
+    use crate::{ArithmeticAnalysis, MixLang};
+    use egg::{EGraph, RecExpr};
+    use fluido_types::fluid::Concentration;
+
+    #[test]
+    fn coverage_of_mixed_fluids() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        for expr in [
+            "(mix (fluid 0.0 1.0) (fluid 0.4 1.0))",
+            "(mix (fluid 0.4 1.0) (fluid 1.0 1.0))",
+        ] {
+            egraph.add_expr(&expr.parse::<RecExpr<MixLang>>().unwrap());
+        }
+        egraph.rebuild();
+
+        let coverage = ConcentrationCoverage::from_egraph(&egraph);
+        // Inputs 0.0, 0.4 and 1.0, mixes 0.2 and 0.7.
+        assert_eq!(coverage.distinct, 5);
+        assert_eq!(coverage.min, Some(Concentration::from(0.0)));
+        assert_eq!(coverage.max, Some(Concentration::from(1.0)));
+        assert_eq!(coverage.histogram, [1, 0, 1, 0, 1, 0, 0, 1, 0, 1]);
+    }
+}
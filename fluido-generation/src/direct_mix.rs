@@ -0,0 +1,124 @@
+//! A heuristic generator mixing the two inputs around the target once, in the volume ratio closest
+//! to the target. It takes no time, but only approximates targets which need more than one mix to
+//! be reached exactly, so it is the first stage of a fallback chain rather than a search of its
+//! own.
+use crate::{coverage::ConcentrationCoverage, gcd, MixLang, RejectedCandidates, Sequence};
+use egg::RecExpr;
+use fluido_types::{
+    error::MixerGenerationError,
+    fluid::{Concentration, LimitedFloat},
+    input_space::InputSpace,
+};
+use std::collections::BTreeSet;
+
+/// Largest total volume of the mix, in units of the smallest one of its fluids.
+pub const DIRECT_MIX_MAX_VOLUME: u64 = 64;
+
+/// Mix of the two inputs around the target, with the smallest total volume whose concentration is
+/// within the tolerance of the target, or the closest one if none is. Fails if no two inputs are
+/// around the target.
+pub fn direct_mix(
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    tolerance: f64,
+) -> Result<Sequence, MixerGenerationError> {
+    let concentrations = input_space.concentrations();
+    let unreachable = || MixerGenerationError::TargetUnreachable(target_concentration.clone());
+    let low = concentrations
+        .iter()
+        .filter(|concentration| *concentration <= target_concentration)
+        .max()
+        .ok_or_else(unreachable)?;
+    let high = concentrations
+        .iter()
+        .filter(|concentration| *concentration >= target_concentration)
+        .min()
+        .ok_or_else(unreachable)?;
+
+    let (target, low_value, high_value) = (
+        f64::from(target_concentration.clone()),
+        f64::from(low.clone()),
+        f64::from(high.clone()),
+    );
+    // Share of the high input in the mix.
+    let share = match high_value - low_value {
+        width if width > 0.0 => (target - low_value) / width,
+        _ => 0.0,
+    };
+    let mut closest: Option<(f64, u64, u64)> = None;
+    for total in 1..=DIRECT_MIX_MAX_VOLUME {
+        let high_volume = (share * total as f64).round() as u64;
+        let error = ((high_volume as f64 / total as f64) - share).abs() * (high_value - low_value);
+        if closest.is_none_or(|(closest_error, _, _)| error < closest_error) {
+            closest = Some((error, high_volume, total - high_volume));
+        }
+        if error <= tolerance {
+            break;
+        }
+    }
+    let (_, high_volume, low_volume) = closest.expect("the total volume has at least one value");
+    // A share of zero or one is a mix of the input with itself.
+    let (lhs, rhs) = match (high_volume, low_volume) {
+        (0, _) => ((low, 1), (low, 1)),
+        (_, 0) => ((high, 1), (high, 1)),
+        _ => {
+            let divisor = gcd(high_volume, low_volume);
+            ((high, high_volume / divisor), (low, low_volume / divisor))
+        }
+    };
+
+    let mut expr = RecExpr::default();
+    let mut add_fluid = |(concentration, volume): (&Concentration, u64)| {
+        let concentration = expr.add(MixLang::LimitedFloat(concentration.clone()));
+        let volume = expr.add(MixLang::LimitedFloat(LimitedFloat::from(volume as f64)));
+        expr.add(MixLang::Fluid([concentration, volume]))
+    };
+    let lhs_id = add_fluid(lhs);
+    let rhs_id = add_fluid(rhs);
+    expr.add(MixLang::Mix([lhs_id, rhs_id]));
+
+    let mixed = (f64::from(lhs.0.clone()) * lhs.1 as f64 + f64::from(rhs.0.clone()) * rhs.1 as f64)
+        / (lhs.1 + rhs.1) as f64;
+    let explored = BTreeSet::from([low.clone(), high.clone(), Concentration::from(mixed)]);
+    Ok(Sequence {
+        cost: 1.0,
+        best_expr: expr.clone(),
+        raw_expr: expr,
+        coverage: ConcentrationCoverage::from_concentrations(&explored),
+        rejected: RejectedCandidates::default(),
+        target_class_dot: None,
+        optimal: false,
+        report: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::direct_mix;
+    use fluido_types::{error::MixerGenerationError, input_space::InputSpace};
+
+    #[test]
+    fn inputs_around_the_target_are_mixed_once() {
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into(), 0.5.into()]);
+        let sequence = direct_mix(&0.01.into(), &input_space, 0.0).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.04 1.0) (fluid 0.0 3.0))"
+        );
+        // Only approximated, with the smallest volumes within the tolerance.
+        let sequence = direct_mix(&0.3333.into(), &input_space, 0.01).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.5 5.0) (fluid 0.04 3.0))"
+        );
+        let sequence = direct_mix(&0.04.into(), &input_space, 0.0).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.04 1.0) (fluid 0.04 1.0))"
+        );
+        assert!(matches!(
+            direct_mix(&0.7.into(), &input_space, 0.0),
+            Err(MixerGenerationError::TargetUnreachable(_))
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+//! An exhaustive generator for tiny input spaces, enumerating every design up to a number of mixes
+//! and a total volume.
+//!
+//! A sub-design only matters to the mix consuming it through the concentration and the volume it
+//! produces, so the cheapest design of each concentration and volume is built from the cheapest
+//! designs of the two operands it mixes. Enumerating them by the number of mixes reaching them
+//! thus finds the target with the fewest mixes possible, which proves the design optimal rather
+//! than only the best one a saturation happened to find. With a handful of inputs and mixes this
+//! takes no time, but the number of designs grows exponentially with the mixes.
+use crate::{coverage::ConcentrationCoverage, gcd, MixLang, RejectedCandidates, Sequence};
+use egg::{Id, RecExpr};
+use fluido_types::{
+    error::MixerGenerationError,
+    fluid::{Concentration, LimitedFloat},
+    input_space::InputSpace,
+    number::Rational,
+};
+use std::collections::{hash_map::Entry, BTreeSet, HashMap};
+
+/// Largest total volume of the enumerated designs, in units of the smallest one of their fluids.
+pub const EXHAUSTIVE_MAX_VOLUME: u64 = 16;
+
+/// Size of the problems the exhaustive search is selected for automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExhaustiveLimits {
+    /// Largest number of distinct input concentrations.
+    pub max_inputs: usize,
+    /// Largest number of mixes of the enumerated designs.
+    pub max_mixes: usize,
+}
+
+impl Default for ExhaustiveLimits {
+    fn default() -> Self {
+        Self {
+            max_inputs: 3,
+            max_mixes: 4,
+        }
+    }
+}
+
+impl ExhaustiveLimits {
+    pub fn new(max_inputs: usize, max_mixes: usize) -> Self {
+        Self {
+            max_inputs,
+            max_mixes,
+        }
+    }
+
+    /// Whether the input space is small enough to be searched exhaustively.
+    pub fn covers(&self, input_space: &InputSpace) -> bool {
+        self.max_mixes > 0 && input_space.concentrations().len() <= self.max_inputs
+    }
+}
+
+/// How a fluid is produced with the fewest mixes.
+enum Node {
+    Input(Concentration),
+    Mix(usize, usize),
+}
+
+/// A fluid some design produces, along with the cheapest such design.
+struct State {
+    concentration: Rational,
+    volume: u64,
+    mixes: usize,
+    node: Node,
+}
+
+/// Design reaching the target with the fewest mixes, and the smallest volume among those,
+/// enumerating designs of up to `max_mixes` mixes and `EXHAUSTIVE_MAX_VOLUME` units of volume. The
+/// sequence is `optimal` if it reaches the target exactly, otherwise it is the design closest to
+/// the target. Fails if there are no inputs.
+pub fn exhaustive_search(
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    max_mixes: usize,
+) -> Result<Sequence, MixerGenerationError> {
+    let target = Rational::from(target_concentration);
+    let mut states: Vec<State> = vec![];
+    let mut reached: HashMap<(Rational, u64), usize> = HashMap::new();
+    // States by the number of mixes reaching them.
+    let mut by_mixes: Vec<Vec<usize>> = vec![vec![]];
+    for concentration in input_space.concentrations().iter() {
+        let value = Rational::from(concentration);
+        for volume in 1..=EXHAUSTIVE_MAX_VOLUME {
+            if let Entry::Vacant(entry) = reached.entry((value, volume)) {
+                entry.insert(states.len());
+                by_mixes[0].push(states.len());
+                states.push(State {
+                    concentration: value,
+                    volume,
+                    mixes: 0,
+                    node: Node::Input(concentration.clone()),
+                });
+            }
+        }
+    }
+    if states.is_empty() {
+        return Err(MixerGenerationError::TargetUnreachable(
+            target_concentration.clone(),
+        ));
+    }
+
+    for mixes in 1..=max_mixes {
+        if by_mixes[mixes - 1]
+            .iter()
+            .any(|&ix| states[ix].concentration == target)
+        {
+            break;
+        }
+        let mut level = vec![];
+        for lhs_mixes in (mixes - 1).div_ceil(2)..mixes {
+            let rhs_mixes = mixes - 1 - lhs_mixes;
+            for (lhs_pos, &lhs) in by_mixes[lhs_mixes].iter().enumerate() {
+                // Operands with as many mixes are only paired once.
+                let rhs_start = if lhs_mixes == rhs_mixes { lhs_pos } else { 0 };
+                for &rhs in &by_mixes[rhs_mixes][rhs_start..] {
+                    let (lhs_state, rhs_state) = (&states[lhs], &states[rhs]);
+                    let volume = lhs_state.volume + rhs_state.volume;
+                    if volume > EXHAUSTIVE_MAX_VOLUME {
+                        continue;
+                    }
+                    let amount = |state: &State| {
+                        state.concentration * Rational::new(state.volume as i128, 1)
+                    };
+                    let concentration =
+                        (amount(lhs_state) + amount(rhs_state)) / Rational::new(volume as i128, 1);
+                    // With as many mixes the more concentrated operand comes first.
+                    let (lhs, rhs) = match lhs_mixes == rhs_mixes
+                        && f64::from(lhs_state.concentration) < f64::from(rhs_state.concentration)
+                    {
+                        true => (rhs, lhs),
+                        false => (lhs, rhs),
+                    };
+                    if let Entry::Vacant(entry) = reached.entry((concentration, volume)) {
+                        entry.insert(states.len());
+                        level.push(states.len());
+                        states.push(State {
+                            concentration,
+                            volume,
+                            mixes,
+                            node: Node::Mix(lhs, rhs),
+                        });
+                    }
+                }
+            }
+        }
+        by_mixes.push(level);
+    }
+
+    // The closest design, with the fewest mixes and then the smallest volume among those. An
+    // input takes a mix with itself.
+    let distance = |state: &State| f64::from(state.concentration - target).abs();
+    let (root, root_state) = states
+        .iter()
+        .enumerate()
+        .min_by(|(_, lhs), (_, rhs)| {
+            distance(lhs)
+                .total_cmp(&distance(rhs))
+                .then(lhs.mixes.max(1).cmp(&rhs.mixes.max(1)))
+                .then(lhs.volume.cmp(&rhs.volume))
+        })
+        .expect("there is at least one input");
+    let optimal = root_state.concentration == target;
+
+    let mut expr = RecExpr::default();
+    let cost = match root_state.node {
+        Node::Input(_) => {
+            let lhs = add_state(&states, root, root_state.volume, &mut expr);
+            let rhs = add_state(&states, root, root_state.volume, &mut expr);
+            expr.add(MixLang::Mix([lhs, rhs]));
+            1.0
+        }
+        Node::Mix(..) => {
+            let divisor = input_volumes(&states, root).fold(0, gcd);
+            add_state(&states, root, divisor, &mut expr);
+            root_state.mixes as f64
+        }
+    };
+
+    let explored = states
+        .iter()
+        .filter_map(|state| LimitedFloat::try_from(state.concentration).ok())
+        .collect::<BTreeSet<_>>();
+    Ok(Sequence {
+        cost,
+        best_expr: expr.clone(),
+        raw_expr: expr,
+        coverage: ConcentrationCoverage::from_concentrations(&explored),
+        rejected: RejectedCandidates::default(),
+        target_class_dot: None,
+        optimal,
+        report: None,
+    })
+}
+
+/// Volumes of the inputs of the design of the state.
+fn input_volumes(states: &[State], ix: usize) -> Box<dyn Iterator<Item = u64> + '_> {
+    match states[ix].node {
+        Node::Input(_) => Box::new(std::iter::once(states[ix].volume)),
+        Node::Mix(lhs, rhs) => {
+            Box::new(input_volumes(states, lhs).chain(input_volumes(states, rhs)))
+        }
+    }
+}
+
+/// Adds the design of the state to the expression, with the volumes of its inputs divided by
+/// `divisor`.
+fn add_state(states: &[State], ix: usize, divisor: u64, expr: &mut RecExpr<MixLang>) -> Id {
+    match &states[ix].node {
+        Node::Input(concentration) => {
+            let volume = (states[ix].volume / divisor) as f64;
+            let concentration = expr.add(MixLang::LimitedFloat(concentration.clone()));
+            let volume = expr.add(MixLang::LimitedFloat(LimitedFloat::from(volume)));
+            expr.add(MixLang::Fluid([concentration, volume]))
+        }
+        Node::Mix(lhs, rhs) => {
+            let lhs = add_state(states, *lhs, divisor, expr);
+            let rhs = add_state(states, *rhs, divisor, expr);
+            expr.add(MixLang::Mix([lhs, rhs]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exhaustive_search, ExhaustiveLimits};
+    use fluido_types::{error::MixerGenerationError, input_space::InputSpace};
+
+    #[test]
+    fn designs_reach_the_target_with_the_fewest_mixes() {
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        let sequence = exhaustive_search(&0.01.into(), &input_space, 4).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.04 1.0) (fluid 0.0 3.0))"
+        );
+        assert!(sequence.optimal);
+        assert_eq!(sequence.cost, 1.0);
+
+        // The pair of inputs closest to the target takes the smallest volume.
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.08.into(), 0.0.into()]);
+        let sequence = exhaustive_search(&0.05.into(), &input_space, 4).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.08 1.0) (fluid 0.04 3.0))"
+        );
+
+        // Reaching the target takes all three inputs.
+        let input_space = InputSpace::from_concentrations([0.1.into(), 0.7.into(), 0.0.into()]);
+        let sequence = exhaustive_search(&0.225.into(), &input_space, 4).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (mix (fluid 0.1 2.0) (fluid 0.0 1.0)) (fluid 0.7 1.0))"
+        );
+        assert!(sequence.optimal);
+        assert_eq!(sequence.cost, 2.0);
+
+        // An input is mixed with itself.
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.08.into(), 0.0.into()]);
+        let sequence = exhaustive_search(&0.04.into(), &input_space, 4).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.04 1.0) (fluid 0.04 1.0))"
+        );
+
+        // Out of reach within the volume of the enumerated designs, so only approximated.
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        let sequence = exhaustive_search(&0.0137.into(), &input_space, 4).unwrap();
+        assert!(!sequence.optimal);
+
+        assert!(matches!(
+            exhaustive_search(&0.01.into(), &InputSpace::default(), 4),
+            Err(MixerGenerationError::TargetUnreachable(_))
+        ));
+    }
+
+    #[test]
+    fn limits_cover_small_input_spaces() {
+        let limits = ExhaustiveLimits::default();
+        let input_space = InputSpace::from_concentrations([0.04.into(), 0.0.into()]);
+        assert!(limits.covers(&input_space));
+        assert!(!ExhaustiveLimits::new(1, 4).covers(&input_space));
+        assert!(!ExhaustiveLimits::new(3, 0).covers(&input_space));
+    }
+}
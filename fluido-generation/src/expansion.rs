@@ -0,0 +1,214 @@
+//! Pre-population of the e-graph with every concentration reachable from the input space, so that
+//! saturation only has to simplify the designs rather than discover them.
+//!
+//! A fluid `d` mixes below the target has the volume of the target halved `d` times, and can have
+//! any concentration the inputs reach within the mixes left below it. So the fluids of the
+//! deepest level are the inputs, and each level above holds the inputs along with a mix of each
+//! pair of fluids of the level below. Concentrations are told apart at the granularity of
+//! `LimitedFloat::EPSILON`, each one is added with the first mix found reaching it. The fluids are
+//! the nodes the expanding rules would build, so they merge with the e-classes of the same
+//! concentration and volume, and a target reached within the depth merges with its root.
+//!
+//! The number of concentrations grows quickly with the depth, with two inputs a level holds twice
+//! as many as the one below it. So a level stops growing at `MAX_EXPANDED_CONCENTRATIONS`, and
+//! the mixes of a level are checked against the time limit and the cancellation flag as they are
+//! added, since a level takes time quadratic in the size of the one below it.
+use crate::{
+    ArithmeticAnalysis, MixLang, SaturationConfig, MAX_EXPANDED_CONCENTRATIONS,
+    MAX_EXPANSION_DEPTH, TARGET_VOLUME,
+};
+use egg::EGraph;
+use fluido_types::{
+    error::MixerGenerationError,
+    fluid::{Concentration, Fluid, LimitedFloat},
+    input_space::InputSpace,
+};
+use std::{
+    collections::BTreeMap,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// Number of pairs mixed between checks of the time limit and the cancellation flag.
+const PAIRS_PER_CHECK: usize = 1 << 12;
+
+/// Adds a fluid of each concentration reachable from the inputs within `depth` mixes, at each
+/// volume a design of the targets mixes it at. Mixes producing a concentration inside a forbidden
+/// window of the configuration, or mixing incompatible inputs directly, are not added. Returns the
+/// number of mixes added.
+///
+/// Fails if the depth exceeds `MAX_EXPANSION_DEPTH`, or once the time limit of the configuration
+/// is reached or the search is cancelled.
+pub(crate) fn expand_input_space(
+    egraph: &mut EGraph<MixLang, ArithmeticAnalysis>,
+    input_space: &InputSpace,
+    target_concentrations: &[Concentration],
+    depth: usize,
+    config: &SaturationConfig,
+) -> Result<usize, MixerGenerationError> {
+    if depth > MAX_EXPANSION_DEPTH {
+        return Err(MixerGenerationError::ExpansionDepthTooLarge(
+            depth,
+            MAX_EXPANSION_DEPTH,
+        ));
+    }
+    let deadline = Instant::now() + Duration::from_secs(config.time_limit);
+    let mut pairs = 0usize;
+    let mut check_limits = || {
+        pairs += 1;
+        if !pairs.is_multiple_of(PAIRS_PER_CHECK) {
+            return Ok(());
+        }
+        if config
+            .cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.load(Ordering::SeqCst))
+        {
+            return Err(MixerGenerationError::Cancelled);
+        }
+        match Instant::now() >= deadline {
+            true => Err(MixerGenerationError::TimeLimitReached(config.time_limit)),
+            false => Ok(()),
+        }
+    };
+    // Volume of each level, the target first, halved the way the expanding rules halve it.
+    let mut volumes = vec![TARGET_VOLUME];
+    for _ in 0..depth {
+        let volume = volumes.last().expect("a level").clone();
+        volumes.push(
+            volume
+                .checked_div(LimitedFloat::from(2.0))
+                .expect("halving never divides by zero"),
+        );
+    }
+    let fluid_node = |egraph: &mut EGraph<MixLang, ArithmeticAnalysis>,
+                      concentration: &Concentration,
+                      volume: &LimitedFloat| {
+        let concentration = egraph.add(MixLang::LimitedFloat(concentration.clone()));
+        let volume = egraph.add(MixLang::LimitedFloat(volume.clone()));
+        egraph.add(MixLang::Fluid([concentration, volume]))
+    };
+    let inputs_at = |egraph: &mut EGraph<MixLang, ArithmeticAnalysis>, volume: &LimitedFloat| {
+        input_space
+            .concentrations()
+            .iter()
+            .map(|concentration| {
+                (
+                    concentration.clone(),
+                    fluid_node(egraph, concentration, volume),
+                )
+            })
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    let mut mixes = 0;
+    let mut below = inputs_at(egraph, &volumes[depth]);
+    for level in (0..depth).rev() {
+        let mut fluids = match level {
+            0 => BTreeMap::new(),
+            _ => inputs_at(egraph, &volumes[level]),
+        };
+        let operand_volume = &volumes[level + 1];
+        let operands = below.iter().collect::<Vec<_>>();
+        'level: for (ix, (lhs, lhs_id)) in operands.iter().enumerate() {
+            for (rhs, rhs_id) in &operands[ix..] {
+                check_limits()?;
+                if fluids.len() >= MAX_EXPANDED_CONCENTRATIONS {
+                    break 'level;
+                }
+                if config
+                    .incompatibilities
+                    .iter()
+                    .any(|incompatibility| incompatibility.forbids_direct(lhs, rhs))
+                {
+                    continue;
+                }
+                let mixed = Fluid::new((*lhs).clone(), operand_volume.clone())
+                    .mix(&Fluid::new((*rhs).clone(), operand_volume.clone()));
+                let concentration = mixed.concentration();
+                // Only the targets are worth adding at their volume, and they are not
+                // intermediates.
+                let wanted = match level {
+                    0 => target_concentrations.contains(concentration),
+                    _ => !config
+                        .forbidden_windows
+                        .iter()
+                        .any(|window| window.contains(concentration)),
+                };
+                if !wanted || fluids.contains_key(concentration) {
+                    continue;
+                }
+                // The mix is only known to be the fluid once the e-graph is rebuilt, as its
+                // operands may be mixes themselves.
+                let fluid = fluid_node(egraph, concentration, &volumes[level]);
+                let mix = egraph.add(MixLang::Mix([**lhs_id, **rhs_id]));
+                egraph.union(fluid, mix);
+                fluids.insert(concentration.clone(), fluid);
+                mixes += 1;
+            }
+        }
+        below = fluids;
+    }
+    egraph.rebuild();
+    Ok(mixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{saturate, SaturationConfig, MAX_EXPANSION_DEPTH};
+    use fluido_types::{error::MixerGenerationError, input_space::InputSpace};
+    use std::{
+        sync::{atomic::AtomicBool, Arc},
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn expanded_targets_only_need_simplification() {
+        let input_space = InputSpace::from_concentrations([0.2.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 5,
+            expansion_depth: Some(2),
+            ..Default::default()
+        };
+        // 0.15 takes two mixes of equal volumes, which compression turns into a single one. The
+        // rules shifting concentrations are not run at all.
+        let sequence = saturate(0.15.into(), &input_space, &config).unwrap();
+        assert_eq!(
+            sequence.best_expr.to_string(),
+            "(mix (fluid 0.0 1.0) (fluid 0.2 3.0))"
+        );
+        assert_eq!(sequence.cost, 1.0);
+
+        // 0.125 takes three.
+        assert!(matches!(
+            saturate(0.125.into(), &input_space, &config),
+            Err(MixerGenerationError::TargetUnreachable(_))
+        ));
+    }
+
+    #[test]
+    fn deep_expansions_stop() {
+        let input_space = InputSpace::from_concentrations([0.2.into(), 0.0.into(), 0.7.into()]);
+        let config = SaturationConfig {
+            time_limit: 60,
+            expansion_depth: Some(MAX_EXPANSION_DEPTH + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            saturate(0.15.into(), &input_space, &config),
+            Err(MixerGenerationError::ExpansionDepthTooLarge(..))
+        ));
+
+        let config = SaturationConfig {
+            expansion_depth: Some(MAX_EXPANSION_DEPTH),
+            cancellation: Some(Arc::new(AtomicBool::new(true))),
+            ..config
+        };
+        let started = Instant::now();
+        assert!(matches!(
+            saturate(0.15.into(), &input_space, &config),
+            Err(MixerGenerationError::Cancelled)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,582 @@
+use crate::{is_design_cost, ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang};
+use egg::{CostFunction, EGraph, Id, Language, RecExpr};
+use fluido_types::fluid::Concentration;
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, str::FromStr};
+
+/// How the best expression is extracted from the saturated e-graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtractionMode {
+    /// Arithmetic nodes are extractable but penalized by the cost function.
+    #[default]
+    Penalized,
+    /// Arithmetic nodes are never extracted. E-classes which can only be represented with
+    /// arithmetic nodes are unextractable.
+    Constrained,
+}
+
+/// Which e-classes the best expression is extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExtractionRoot {
+    /// The e-class of the target node.
+    #[default]
+    Target,
+    /// The cheapest of the e-class of the target node and every e-class whose fluid is within the
+    /// tolerance of the target. Finds designs the saturation produced without ever unifying them
+    /// with the target node.
+    BestFluid { tolerance: f64 },
+}
+
+impl FromStr for ExtractionRoot {
+    type Err = String;
+
+    /// Parses `target`, `best-fluid` or `best-fluid:<tolerance>`, i.e `best-fluid:0.001`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (root, tolerance) = match s.split_once(':') {
+            Some((root, tolerance)) => (root.trim(), Some(tolerance.trim())),
+            None => (s.trim(), None),
+        };
+        match (root, tolerance) {
+            ("target", None) => Ok(ExtractionRoot::Target),
+            ("best-fluid", None) => Ok(ExtractionRoot::BestFluid { tolerance: 0.0 }),
+            ("best-fluid", Some(tolerance)) => match tolerance.parse::<f64>() {
+                Ok(tolerance) if tolerance.is_finite() && tolerance >= 0.0 => {
+                    Ok(ExtractionRoot::BestFluid { tolerance })
+                }
+                _ => Err(format!("`{tolerance}` is not a tolerance")),
+            },
+            ("target", Some(_)) => Err("`target` has no tolerance".to_string()),
+            (root, _) => Err(format!(
+                "unknown extraction root `{root}`, expected `target` or `best-fluid`"
+            )),
+        }
+    }
+}
+
+impl Display for ExtractionRoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExtractionRoot::Target => write!(f, "target"),
+            ExtractionRoot::BestFluid { tolerance } => write!(f, "best-fluid:{tolerance}"),
+        }
+    }
+}
+
+/// E-classes other than the root whose fluid is within the tolerance of the target, in the order
+/// of their ids.
+pub(crate) fn fluid_classes_near(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    root: Id,
+    target: &Concentration,
+    tolerance: f64,
+) -> Vec<Id> {
+    let target = f64::from(target.clone());
+    let mut classes = egraph
+        .classes()
+        .filter(|class| class.id != root)
+        .filter(|class| match &class.data {
+            ArithmeticAnalysisPayload::Fluid(fluid) => {
+                (f64::from(fluid.concentration().clone()) - target).abs() <= tolerance
+            }
+            _ => false,
+        })
+        .map(|class| class.id)
+        .collect::<Vec<_>>();
+    classes.sort();
+    classes
+}
+
+/// Orders two extracted expressions lexicographically in prefix order: by their rendered operators
+/// first, then by their children from left to right. Extractors break ties in cost with this
+/// order, so that equal cost designs are chosen the same way regardless of the order e-classes
+/// and e-nodes are visited in.
+///
+/// Children are given by keys, `extracted` returns the node a key is extracted with along with
+/// the keys of its own children. Equal keys are extracted the same way and are not compared
+/// further.
+fn cmp_extracted<'a, K, F>(
+    (lhs, lhs_children): (&MixLang, Vec<K>),
+    (rhs, rhs_children): (&MixLang, Vec<K>),
+    extracted: &F,
+) -> Ordering
+where
+    K: PartialEq,
+    F: Fn(&K) -> (&'a MixLang, Vec<K>),
+{
+    lhs.to_string()
+        .cmp(&rhs.to_string())
+        .then_with(|| lhs_children.len().cmp(&rhs_children.len()))
+        .then_with(|| {
+            lhs_children
+                .iter()
+                .zip(&rhs_children)
+                .filter(|(lhs, rhs)| lhs != rhs)
+                .map(|(lhs, rhs)| cmp_extracted(extracted(lhs), extracted(rhs), extracted))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+/// Canonical e-classes of the children of the e-node.
+fn child_classes(egraph: &EGraph<MixLang, ArithmeticAnalysis>, enode: &MixLang) -> Vec<Id> {
+    enode.children().iter().map(|id| egraph.find(*id)).collect()
+}
+
+/// Cheapest extractable node of each e-class with its total cost. Arithmetic nodes are skipped in
+/// `ExtractionMode::Constrained`, e-classes without an extractable node are left out. Nodes of
+/// equal cost are ordered by `cmp_extracted`.
+pub(crate) fn cheapest_nodes<CF>(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    cost_fn: &mut CF,
+    mode: ExtractionMode,
+) -> HashMap<Id, (f64, MixLang)>
+where
+    CF: CostFunction<MixLang, Cost = f64>,
+{
+    let mut best = HashMap::new();
+    let classes = egraph.classes().map(|class| class.id).collect::<Vec<_>>();
+    update_cheapest_nodes(egraph, cost_fn, mode, &mut best, &classes);
+    best
+}
+
+/// Same as `cheapest_nodes`, but only the given e-classes are (re)computed, the others keep their
+/// entry in `best`. The given e-classes must be missing from `best`, and must include every
+/// e-class whose cost may depend on them.
+pub(crate) fn update_cheapest_nodes<CF>(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    cost_fn: &mut CF,
+    mode: ExtractionMode,
+    best: &mut HashMap<Id, (f64, MixLang)>,
+    classes: &[Id],
+) where
+    CF: CostFunction<MixLang, Cost = f64>,
+{
+    // Iterate until a fixpoint, costs only decrease and ties are only replaced by nodes ordered
+    // before them, so this terminates.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for class in classes.iter().map(|id| &egraph[*id]) {
+            let extracted = |id: &Id| {
+                let enode = &best[id].1;
+                (enode, child_classes(egraph, enode))
+            };
+            let cmp = |(lhs_cost, lhs): &(f64, &MixLang), (rhs_cost, rhs): &(f64, &MixLang)| {
+                lhs_cost.total_cmp(rhs_cost).then_with(|| {
+                    cmp_extracted(
+                        (lhs, child_classes(egraph, lhs)),
+                        (rhs, child_classes(egraph, rhs)),
+                        &extracted,
+                    )
+                })
+            };
+            let candidate = class
+                .nodes
+                .iter()
+                .filter(|enode| mode == ExtractionMode::Penalized || !is_arithmetic(enode))
+                .filter(|enode| enode.all(|id| best.contains_key(&egraph.find(id))))
+                .map(|enode| {
+                    let cost = cost_fn.cost(enode, |id| best[&egraph.find(id)].0);
+                    (cost, enode)
+                })
+                .min_by(cmp);
+            if let Some(candidate) = candidate {
+                let improves = best.get(&class.id).is_none_or(|(best_cost, best_node)| {
+                    cmp(&candidate, &(*best_cost, best_node)).is_lt()
+                });
+                if improves {
+                    let candidate = (candidate.0, candidate.1.clone());
+                    best.insert(class.id, candidate);
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+/// Extractor of the cheapest expression of an e-class, which breaks ties in cost deterministically.
+/// In `ExtractionMode::Constrained` it skips arithmetic e-nodes (`+`, `-`, `*`, `/`) entirely, so
+/// the extracted expression only consists of mixes, fluids and literals.
+pub(crate) struct CheapestExtractor<'a> {
+    egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+    /// Cheapest extractable node of each e-class, with its total cost.
+    best: HashMap<Id, (f64, MixLang)>,
+}
+
+pub(crate) fn is_arithmetic(enode: &MixLang) -> bool {
+    matches!(
+        enode,
+        MixLang::Add(_) | MixLang::Sub(_) | MixLang::Mult(_) | MixLang::Div(_)
+    )
+}
+
+impl<'a> CheapestExtractor<'a> {
+    pub(crate) fn new<CF>(
+        egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+        mut cost_fn: CF,
+        mode: ExtractionMode,
+    ) -> Self
+    where
+        CF: CostFunction<MixLang, Cost = f64>,
+    {
+        let best = cheapest_nodes(egraph, &mut cost_fn, mode);
+        Self { egraph, best }
+    }
+
+    /// Returns the cheapest expression of the e-class, if it can be extracted in the mode.
+    pub(crate) fn find_best(&self, eclass: Id) -> Option<(f64, RecExpr<MixLang>)> {
+        let (cost, root) = self.best.get(&self.egraph.find(eclass))?;
+        let expr = root.build_recexpr(|id| self.best[&self.egraph.find(id)].1.clone());
+        Some((*cost, expr))
+    }
+}
+
+/// Extractor which only extracts expressions producing every required intermediate, i.e
+/// expressions with a mix producing each of the required concentrations below their root.
+pub(crate) struct RequiredIntermediatesExtractor<'a> {
+    egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+    /// Cheapest extractable node of each e-class per set of required intermediates produced by its
+    /// expression, with its total cost and the sets its children are extracted with. Sets are bit
+    /// masks over the required intermediates.
+    best: HashMap<(Id, u64), (f64, MixLang, Vec<u64>)>,
+    /// Sets of required intermediates each e-class can be extracted with.
+    sets: HashMap<Id, Vec<u64>>,
+    /// Set of all the required intermediates.
+    all: u64,
+}
+
+/// Concentration of the fluids in the e-class, if it holds fluids.
+fn class_concentration(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    id: Id,
+) -> Option<Concentration> {
+    egraph[id].nodes.iter().find_map(|enode| match enode {
+        MixLang::Fluid([concentration, _]) => {
+            egraph[*concentration].data.clone().expect_limited_float()
+        }
+        _ => None,
+    })
+}
+
+impl<'a> RequiredIntermediatesExtractor<'a> {
+    /// Mixes of the root itself are the final fluid, not an intermediate, so they never produce a
+    /// required intermediate. Arithmetic nodes are skipped in `ExtractionMode::Constrained`.
+    pub(crate) fn new<CF>(
+        egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+        mut cost_fn: CF,
+        mode: ExtractionMode,
+        required: &[Concentration],
+        root: Id,
+    ) -> Self
+    where
+        CF: CostFunction<MixLang, Cost = f64>,
+    {
+        assert!(
+            required.len() < u64::BITS as usize,
+            "too many required intermediates"
+        );
+        let root = egraph.find(root);
+        let produces = egraph
+            .classes()
+            .filter(|class| class.id != root)
+            .filter_map(|class| {
+                let concentration = class_concentration(egraph, class.id)?;
+                let set = required
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, required)| **required == concentration)
+                    .fold(0, |set, (ix, _)| set | 1 << ix);
+                Some((class.id, set))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut best: HashMap<(Id, u64), (f64, MixLang, Vec<u64>)> = HashMap::new();
+        let mut sets: HashMap<Id, Vec<u64>> = HashMap::new();
+        // Iterate until a fixpoint, costs only decrease and ties are only replaced by nodes ordered
+        // before them, so this terminates.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for class in egraph.classes() {
+                let extractable = class
+                    .nodes
+                    .iter()
+                    .filter(|enode| mode == ExtractionMode::Penalized || !is_arithmetic(enode));
+                for enode in extractable {
+                    let children = enode
+                        .children()
+                        .iter()
+                        .map(|id| egraph.find(*id))
+                        .collect::<Vec<_>>();
+                    // Every combination of the sets the children can be extracted with.
+                    let mut combinations = vec![vec![]];
+                    for child in &children {
+                        let child_sets = sets.get(child).map(Vec::as_slice).unwrap_or_default();
+                        combinations = combinations
+                            .into_iter()
+                            .flat_map(|combination: Vec<u64>| {
+                                child_sets.iter().map(move |set| {
+                                    let mut combination = combination.clone();
+                                    combination.push(*set);
+                                    combination
+                                })
+                            })
+                            .collect();
+                    }
+                    for child_sets in combinations {
+                        // The cost function looks children up by id, so an e-class appearing more
+                        // than once among the children is extracted the same way each time.
+                        let consistent = children.iter().zip(&child_sets).all(|(id, set)| {
+                            children
+                                .iter()
+                                .zip(&child_sets)
+                                .all(|(other, other_set)| id != other || set == other_set)
+                        });
+                        if !consistent {
+                            continue;
+                        }
+                        let cost = cost_fn.cost(enode, |id| {
+                            let id = egraph.find(id);
+                            let ix = children.iter().position(|child| *child == id).unwrap();
+                            best[&(id, child_sets[ix])].0
+                        });
+                        let mut set = child_sets.iter().fold(0, |set, child| set | child);
+                        if matches!(enode, MixLang::Mix(_)) {
+                            set |= produces.get(&class.id).copied().unwrap_or_default();
+                        }
+                        let extracted = |(id, set): &(Id, u64)| {
+                            let (_, enode, child_sets) = &best[&(*id, *set)];
+                            let children = child_classes(egraph, enode);
+                            (
+                                enode,
+                                children.into_iter().zip(child_sets.clone()).collect(),
+                            )
+                        };
+                        let improves = best.get(&(class.id, set)).is_none_or(
+                            |(best_cost, best_node, best_child_sets)| {
+                                cost.total_cmp(best_cost)
+                                    .then_with(|| {
+                                        cmp_extracted(
+                                            (
+                                                enode,
+                                                children
+                                                    .iter()
+                                                    .copied()
+                                                    .zip(child_sets.clone())
+                                                    .collect(),
+                                            ),
+                                            (
+                                                best_node,
+                                                child_classes(egraph, best_node)
+                                                    .into_iter()
+                                                    .zip(best_child_sets.clone())
+                                                    .collect(),
+                                            ),
+                                            &extracted,
+                                        )
+                                    })
+                                    .is_lt()
+                            },
+                        );
+                        if improves {
+                            let class_sets = sets.entry(class.id).or_default();
+                            if !class_sets.contains(&set) {
+                                class_sets.push(set);
+                            }
+                            best.insert((class.id, set), (cost, enode.clone(), child_sets));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            egraph,
+            best,
+            sets,
+            all: (1 << required.len()) - 1,
+        }
+    }
+
+    /// Returns the cheapest expression of the e-class producing every required intermediate, if
+    /// there is one.
+    pub(crate) fn find_best(&self, eclass: Id) -> Option<(f64, RecExpr<MixLang>)> {
+        let eclass = self.egraph.find(eclass);
+        let (cost, _, _) = self.best.get(&(eclass, self.all))?;
+        let mut expr = RecExpr::default();
+        self.build(eclass, self.all, &mut expr, &mut HashMap::new());
+        Some((*cost, expr))
+    }
+
+    /// Adds the best node of the e-class for the set, after its children, to the expression.
+    fn build(
+        &self,
+        eclass: Id,
+        set: u64,
+        expr: &mut RecExpr<MixLang>,
+        built: &mut HashMap<(Id, u64), Id>,
+    ) -> Id {
+        if let Some(id) = built.get(&(eclass, set)) {
+            return *id;
+        }
+        let (_, enode, child_sets) = &self.best[&(eclass, set)];
+        let mut child_sets = child_sets.iter();
+        let enode = enode.clone().map_children(|child| {
+            let child_set = *child_sets.next().unwrap();
+            self.build(self.egraph.find(child), child_set, expr, built)
+        });
+        let id = expr.add(enode);
+        built.insert((eclass, set), id);
+        id
+    }
+
+    /// Whether the e-class can be extracted with a finite cost, regardless of the intermediates it
+    /// produces.
+    pub(crate) fn is_extractable(&self, eclass: Id) -> bool {
+        let eclass = self.egraph.find(eclass);
+        self.sets.get(&eclass).is_some_and(|sets| {
+            sets.iter()
+                .any(|set| is_design_cost(self.best[&(eclass, *set)].0))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CheapestExtractor, ExtractionMode, ExtractionRoot, RequiredIntermediatesExtractor,
+    };
+    use crate::{ArithmeticAnalysis, MixLang};
+    use egg::{CostFunction, EGraph, Id, Language, RecExpr};
+
+    struct NodeCount;
+
+    impl CostFunction<MixLang> for NodeCount {
+        type Cost = f64;
+
+        fn cost<C>(&mut self, enode: &MixLang, mut costs: C) -> Self::Cost
+        where
+            C: FnMut(Id) -> Self::Cost,
+        {
+            enode.fold(1.0, |sum, id| sum + costs(id))
+        }
+    }
+
+    #[test]
+    fn extraction_roots_are_parsed_and_displayed() {
+        assert_eq!("target".parse(), Ok(ExtractionRoot::Target));
+        assert_eq!(
+            "best-fluid".parse(),
+            Ok(ExtractionRoot::BestFluid { tolerance: 0.0 })
+        );
+        let root = "best-fluid:0.001".parse::<ExtractionRoot>().unwrap();
+        assert_eq!(root, ExtractionRoot::BestFluid { tolerance: 0.001 });
+        assert_eq!(root.to_string(), "best-fluid:0.001");
+        assert!("best-fluid:-1".parse::<ExtractionRoot>().is_err());
+        assert!("target:0.1".parse::<ExtractionRoot>().is_err());
+        assert!("root".parse::<ExtractionRoot>().is_err());
+    }
+
+    #[test]
+    fn arithmetic_only_classes_are_unextractable() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let arithmetic = egraph.add_expr(&"(/ 1.0 2.0)".parse::<RecExpr<MixLang>>().unwrap());
+        let fluid = egraph.add_expr(
+            &"(fluid 0.1 (/ 1.0 2.0))"
+                .parse::<RecExpr<MixLang>>()
+                .unwrap(),
+        );
+        egraph.rebuild();
+
+        let extractor = CheapestExtractor::new(&egraph, NodeCount, ExtractionMode::Constrained);
+        assert!(extractor.find_best(arithmetic).is_none());
+        // The fluid is still extractable through the literal volume added by the analysis.
+        let (_, best) = extractor.find_best(fluid).unwrap();
+        assert_eq!(best.to_string(), "(fluid 0.1 0.5)");
+    }
+
+    /// Counts nodes, but never extracts the mixed fluid of `equal_costs_are_broken_by_expression`
+    /// as a leaf.
+    struct NodeCountWithoutLeafTarget;
+
+    impl CostFunction<MixLang> for NodeCountWithoutLeafTarget {
+        type Cost = f64;
+
+        fn cost<C>(&mut self, enode: &MixLang, costs: C) -> Self::Cost
+        where
+            C: FnMut(Id) -> Self::Cost,
+        {
+            match enode {
+                MixLang::LimitedFloat(volume) if *volume == 4.0.into() => f64::INFINITY,
+                _ => NodeCount.cost(enode, costs),
+            }
+        }
+    }
+
+    #[test]
+    fn equal_costs_are_broken_by_expression() {
+        let lhs_first = "(mix (fluid 0.0 1.0) (fluid 0.4 3.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let rhs_first = "(mix (fluid 0.4 3.0) (fluid 0.0 1.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        for exprs in [[&lhs_first, &rhs_first], [&rhs_first, &lhs_first]] {
+            let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+            let ids = exprs.map(|expr| egraph.add_expr(expr));
+            egraph.rebuild();
+            assert_eq!(egraph.find(ids[0]), egraph.find(ids[1]));
+
+            for mode in [ExtractionMode::Penalized, ExtractionMode::Constrained] {
+                let extractor = CheapestExtractor::new(&egraph, NodeCountWithoutLeafTarget, mode);
+                let (cost, best) = extractor.find_best(ids[0]).unwrap();
+                assert_eq!(cost, 7.0);
+                assert_eq!(best.to_string(), lhs_first.to_string());
+            }
+            let extractor = RequiredIntermediatesExtractor::new(
+                &egraph,
+                NodeCountWithoutLeafTarget,
+                ExtractionMode::Constrained,
+                &[],
+                ids[0],
+            );
+            let (_, best) = extractor.find_best(ids[0]).unwrap();
+            assert_eq!(best.to_string(), lhs_first.to_string());
+        }
+    }
+
+    #[test]
+    fn extracted_expressions_produce_the_required_intermediates() {
+        let direct = "(mix (fluid 0.4 1.0) (fluid 0.0 3.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let through_half = "(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let root = egraph.add_expr(&direct);
+        let other = egraph.add_expr(&through_half);
+        egraph.union(root, other);
+        egraph.rebuild();
+
+        let extractor = RequiredIntermediatesExtractor::new(
+            &egraph,
+            NodeCount,
+            ExtractionMode::Constrained,
+            &[0.2.into()],
+            root,
+        );
+        let (_, best) = extractor.find_best(root).unwrap();
+        assert_eq!(best.to_string(), through_half.to_string());
+
+        // The root is the final fluid, not an intermediate.
+        let extractor = RequiredIntermediatesExtractor::new(
+            &egraph,
+            NodeCount,
+            ExtractionMode::Constrained,
+            &[0.1.into()],
+            root,
+        );
+        assert!(extractor.find_best(root).is_none());
+        assert!(extractor.is_extractable(root));
+    }
+}
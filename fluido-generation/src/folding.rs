@@ -0,0 +1,125 @@
+//! Constant folding of the arithmetic rules build, before it is added to the e-graph.
+//!
+//! Rules halve volumes and shift concentrations with arithmetic like `(/ ?b 2.0)`, whose operands
+//! are always known values once matched. Adding the arithmetic as e-nodes would leave the analysis
+//! to evaluate it, and keep an e-node for it around for the rest of the saturation, for every
+//! application of the rule. Folding it adds the value instead, computed the way the analysis
+//! computes it. Arithmetic whose value is undefined, i.e a division by zero, is added as it is so
+//! that the analysis rejects it.
+use crate::{ArithmeticAnalysis, MixLang};
+use egg::{Applier, EGraph, ENodeOrVar, Id, Language, Pattern, PatternAst, Subst, Symbol, Var};
+use fluido_types::fluid::LimitedFloat;
+
+/// Applies the pattern with its arithmetic folded into values.
+pub(crate) struct Folded(Pattern<MixLang>);
+
+impl Folded {
+    pub(crate) fn new(pattern: &str) -> Self {
+        Self(pattern.parse().expect("builtin patterns parse"))
+    }
+}
+
+/// Value of the arithmetic node over the operand values, as the analysis computes it. `None` if the
+/// node is not arithmetic or its value is undefined.
+fn fold(node: &MixLang, lhs: LimitedFloat, rhs: LimitedFloat) -> Option<LimitedFloat> {
+    match node {
+        MixLang::Add(_) => Some(lhs + rhs),
+        MixLang::Sub(_) => Some(lhs - rhs),
+        MixLang::Mult(_) => Some(lhs * rhs),
+        MixLang::Div(_) => lhs.checked_div(rhs),
+        _ => None,
+    }
+}
+
+impl Applier<MixLang, ArithmeticAnalysis> for Folded {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<MixLang, ArithmeticAnalysis>,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&PatternAst<MixLang>>,
+        _rule_name: Symbol,
+    ) -> Vec<Id> {
+        let ast = self.0.ast.as_ref();
+        let mut ids: Vec<Id> = Vec::with_capacity(ast.len());
+        let mut values: Vec<Option<LimitedFloat>> = Vec::with_capacity(ast.len());
+        for pattern_node in ast {
+            let (id, value) = match pattern_node {
+                ENodeOrVar::Var(var) => {
+                    let id = subst[*var];
+                    (id, egraph[id].data.clone().expect_limited_float())
+                }
+                ENodeOrVar::ENode(MixLang::LimitedFloat(value)) => (
+                    egraph.add(MixLang::LimitedFloat(value.clone())),
+                    Some(value.clone()),
+                ),
+                ENodeOrVar::ENode(node) => {
+                    let folded = match node.children() {
+                        [lhs, rhs] => values[usize::from(*lhs)]
+                            .clone()
+                            .zip(values[usize::from(*rhs)].clone())
+                            .and_then(|(lhs, rhs)| fold(node, lhs, rhs)),
+                        _ => None,
+                    };
+                    match folded {
+                        Some(value) => (
+                            egraph.add(MixLang::LimitedFloat(value.clone())),
+                            Some(value),
+                        ),
+                        None => (
+                            egraph.add(node.clone().map_children(|child| ids[usize::from(child)])),
+                            None,
+                        ),
+                    }
+                }
+            };
+            ids.push(id);
+            values.push(value);
+        }
+        let id = *ids.last().expect("patterns are not empty");
+        match egraph.union(eclass, id) {
+            true => vec![eclass],
+            false => vec![],
+        }
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.0.vars()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Folded;
+    use crate::{ArithmeticAnalysis, MixLang};
+    use egg::{rewrite as rw, EGraph, RecExpr, Runner};
+
+    #[test]
+    fn rule_arithmetic_is_added_as_values() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        egraph.add_expr(&"(fluid 0.4 1.0)".parse::<RecExpr<MixLang>>().unwrap());
+        let halve = rw!("halve"; "(fluid ?a ?b)" => {
+            Folded::new("(mix (fluid ?a (/ ?b 2.0)) (fluid ?a (/ ?b 2.0)))")
+        });
+        let split_by_zero = rw!("split-by-zero"; "(fluid ?a 1.0)" => {
+            Folded::new("(mix (fluid ?a (/ 1.0 0.0)) (fluid ?a (- 1.0 1.0)))")
+        });
+        let runner = Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(1)
+            .run(&[halve, split_by_zero]);
+        let egraph = runner.egraph;
+
+        // The halves are fluids of a folded volume.
+        let halves = egraph.lookup_expr(&"(fluid 0.4 0.5)".parse().unwrap());
+        assert!(halves.is_some());
+        // Only the division by zero is left, for the analysis to reject.
+        let divisions = egraph
+            .classes()
+            .flat_map(|class| &class.nodes)
+            .filter(|node| matches!(node, MixLang::Div(_)))
+            .count();
+        assert_eq!(divisions, 1);
+        assert_eq!(egraph.analysis.rejected().division_by_zero, 1);
+    }
+}
@@ -1,11 +1,58 @@
+pub mod checkpoint;
+pub mod class_dump;
+#[cfg(test)]
+mod cost_regression;
+pub mod coverage;
+pub mod direct_mix;
+pub mod exhaustive;
+mod expansion;
+pub mod extract;
+mod folding;
+pub mod lower_bound;
+pub mod multi_reagent;
+pub mod progress;
+pub mod report;
+pub mod reuse;
+#[cfg(feature = "custom-rules")]
+pub mod rules;
+pub mod warm_start;
+
+use checkpoint::{checkpoint_hook, Checkpoint, CheckpointConfig, CheckpointRef, INTERRUPTED};
+use class_dump::{target_class_dot, ClassDumpConfig};
+use coverage::ConcentrationCoverage;
 use egg::{rewrite as rw, *};
+use expansion::expand_input_space;
+use extract::{
+    fluid_classes_near, CheapestExtractor, ExtractionMode, ExtractionRoot,
+    RequiredIntermediatesExtractor,
+};
+pub use fluido_types::input_space::InputTier;
 use fluido_types::{
-    error::MixerGenerationError,
-    fluid::{Concentration, Fluid, LimitedFloat, Volume},
+    error::{ExtractedExprError, ExtractedNodeFailure, MixerGenerationError},
+    expr::{Expr, ExprPath, MixOperand},
+    fluid::{
+        Concentration, ConcentrationWindow, Fluid, Incompatibility, LimitedFloat, MixRatio,
+        MixerCapabilities, Volume,
+    },
+    input_space::InputSpace,
+    log::LogSink,
+    number::RoundingMode,
+};
+use folding::Folded;
+use progress::{last_progress, progress_hook, IterationCoverage, ProgressCallback};
+use report::SaturationReport;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
-use std::{collections::HashSet, time::Duration};
 
 define_language! {
+    #[derive(Serialize, Deserialize)]
     pub enum MixLang {
         LimitedFloat(LimitedFloat),
         "+" = Add([Id; 2]),
@@ -16,11 +63,107 @@ define_language! {
         "fluid" = Fluid([Id; 2]),
     }
 }
+/// Volume of the target fluid, which no real fluid can have.
+const TARGET_VOLUME: LimitedFloat = LimitedFloat { wrapped: i64::MAX };
+
+/// Node of the fluid with the target concentration, the root of the search.
+pub(crate) fn target_node(target_concentration: &Concentration) -> RecExpr<MixLang> {
+    let mut node = RecExpr::default();
+    let concentration = node.add(MixLang::LimitedFloat(target_concentration.clone()));
+    let volume = node.add(MixLang::LimitedFloat(TARGET_VOLUME));
+    node.add(MixLang::Fluid([concentration, volume]));
+    node
+}
+
+/// Greatest common divisor of two integer volumes, for reducing designs to their smallest volumes.
+pub(crate) fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+/// A rewrite rule over the mixer space.
+pub type MixRewrite = Rewrite<MixLang, ArithmeticAnalysis>;
+
+/// Runner saturating the mixer space.
+type MixRunner = Runner<MixLang, ArithmeticAnalysis, IterationCoverage>;
+
+/// Analysis evaluating the arithmetic and the fluids of each e-class, so that rule conditions can
+/// inspect the values they match.
 #[derive(Default)]
-struct ArithmeticAnalysis;
+pub struct ArithmeticAnalysis {
+    /// Operations whose result is invalid, and thus never becomes a fluid.
+    rejected: Mutex<RejectedCandidates>,
+}
+
+/// Operations the analysis rejected as their result is undefined, by reason. Each operation is
+/// counted once per e-node performing it. A rejected operation has no value, so no fluid is built
+/// from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RejectedCandidates {
+    /// Divisions by zero, i.e splitting a fluid without volume.
+    pub division_by_zero: usize,
+    /// Mixes of fluids without volume, whose concentration is undefined.
+    pub empty_mixes: usize,
+}
+
+impl RejectedCandidates {
+    pub fn total(&self) -> usize {
+        self.division_by_zero + self.empty_mixes
+    }
+}
+
+impl std::fmt::Display for RejectedCandidates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rejected {} candidates with undefined values: {} divisions by zero, {} mixes without volume.",
+            self.total(),
+            self.division_by_zero,
+            self.empty_mixes
+        )
+    }
+}
+
+/// Rejections are counted per saturation, so clones start without any. This lets rules over the
+/// analysis be cloned.
+impl Clone for ArithmeticAnalysis {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// Rejections are counted per saturation, so the analysis is serialized as a unit struct. This also
+/// keeps checkpoints and fixtures written before rejections were counted loadable.
+impl Serialize for ArithmeticAnalysis {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit_struct("ArithmeticAnalysis")
+    }
+}
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum ArithmeticAnalysisPayload {
+impl<'de> Deserialize<'de> for ArithmeticAnalysis {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <()>::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
+
+impl ArithmeticAnalysis {
+    /// Operations rejected so far, since the analysis was created.
+    pub fn rejected(&self) -> RejectedCandidates {
+        *self.rejected.lock().expect("rejections are never poisoned")
+    }
+
+    fn reject(&self, update: impl FnOnce(&mut RejectedCandidates)) -> ArithmeticAnalysisPayload {
+        update(&mut self.rejected.lock().expect("rejections are never poisoned"));
+        ArithmeticAnalysisPayload::None
+    }
+}
+
+/// Value of an e-class, as computed by `ArithmeticAnalysis`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum ArithmeticAnalysisPayload {
     LimitedFloat(LimitedFloat),
     Fluid(Fluid),
     None,
@@ -81,8 +224,10 @@ impl Analysis<MixLang> for ArithmeticAnalysis {
                     let fluid_a = Fluid::new(fluid_a_conc.clone(), fluid_a_vol.clone());
                     let fluid_b = Fluid::new(fluid_b_conc.clone(), fluid_b_vol.clone());
 
-                    let mixed_fluid = fluid_a.mix(&fluid_b);
-                    ArithmeticAnalysisPayload::Fluid(mixed_fluid)
+                    if fluid_a.unit_volume().is_zero() && fluid_b.unit_volume().is_zero() {
+                        return egraph.analysis.reject(|rejected| rejected.empty_mixes += 1);
+                    }
+                    ArithmeticAnalysisPayload::Fluid(fluid_a.mix(&fluid_b))
                 } else {
                     ArithmeticAnalysisPayload::None
                 }
@@ -109,8 +254,13 @@ impl Analysis<MixLang> for ArithmeticAnalysis {
                 let node_a = &egraph[node_a_id].data;
                 let node_b = &egraph[node_b_id].data;
 
-                let val_a = node_a.clone().expect_limited_float().unwrap();
-                let val_b = node_b.clone().expect_limited_float().unwrap();
+                // Operands without a value, i.e rejected ones, leave the result without one.
+                let (Some(val_a), Some(val_b)) = (
+                    node_a.clone().expect_limited_float(),
+                    node_b.clone().expect_limited_float(),
+                ) else {
+                    return ArithmeticAnalysisPayload::None;
+                };
                 let result = val_a + val_b;
                 ArithmeticAnalysisPayload::LimitedFloat(result)
             }
@@ -121,8 +271,12 @@ impl Analysis<MixLang> for ArithmeticAnalysis {
                 let node_a = &egraph[node_a_id].data;
                 let node_b = &egraph[node_b_id].data;
 
-                let val_a = node_a.clone().expect_limited_float().unwrap();
-                let val_b = node_b.clone().expect_limited_float().unwrap();
+                let (Some(val_a), Some(val_b)) = (
+                    node_a.clone().expect_limited_float(),
+                    node_b.clone().expect_limited_float(),
+                ) else {
+                    return ArithmeticAnalysisPayload::None;
+                };
 
                 let result = val_a - val_b;
                 ArithmeticAnalysisPayload::LimitedFloat(result)
@@ -131,44 +285,55 @@ impl Analysis<MixLang> for ArithmeticAnalysis {
                 let node_a_id = div[0];
                 let node_b_id = div[1];
 
-                let node_a = egraph[node_a_id].clone().data;
-                let node_b = egraph[node_b_id].clone().data;
+                let node_a = &egraph[node_a_id].data;
+                let node_b = &egraph[node_b_id].data;
 
-                let val_a = node_a.clone().expect_limited_float().unwrap();
-                let val_b = node_b.clone().expect_limited_float().unwrap();
-                let result = val_a.clone() / val_b.clone();
-                ArithmeticAnalysisPayload::LimitedFloat(result)
+                let (Some(val_a), Some(val_b)) = (
+                    node_a.clone().expect_limited_float(),
+                    node_b.clone().expect_limited_float(),
+                ) else {
+                    return ArithmeticAnalysisPayload::None;
+                };
+                match val_a.checked_div(val_b) {
+                    Some(quotient) => ArithmeticAnalysisPayload::LimitedFloat(quotient),
+                    None => egraph
+                        .analysis
+                        .reject(|rejected| rejected.division_by_zero += 1),
+                }
             }
             MixLang::Mult(mult) => {
                 let node_a_id = mult[0];
                 let node_b_id = mult[1];
 
-                let node_a = egraph[node_a_id].clone().data;
-                let node_b = egraph[node_b_id].clone().data;
+                let node_a = &egraph[node_a_id].data;
+                let node_b = &egraph[node_b_id].data;
 
-                let val_a = node_a.clone().expect_limited_float().unwrap();
-                let val_b = node_b.clone().expect_limited_float().unwrap();
-                let result = val_a.clone() * val_b.clone();
-                ArithmeticAnalysisPayload::LimitedFloat(result)
+                let (Some(val_a), Some(val_b)) = (
+                    node_a.clone().expect_limited_float(),
+                    node_b.clone().expect_limited_float(),
+                ) else {
+                    return ArithmeticAnalysisPayload::None;
+                };
+                ArithmeticAnalysisPayload::LimitedFloat(val_a * val_b)
             }
         }
     }
 
     fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
-        let mut to = match to {
-            ArithmeticAnalysisPayload::None => None,
-            a => Some(a),
-        };
-        let mut binding = match from {
-            ArithmeticAnalysisPayload::None => None,
-            a => Some(a),
-        };
-        let from = binding.as_mut();
-
-        merge_option(&mut to, from, |a, b| {
-            assert_eq!(*a, b, "Merged non-equal constants");
-            DidMerge(false, false)
-        })
+        match (&*to, from) {
+            (ArithmeticAnalysisPayload::None, ArithmeticAnalysisPayload::None) => {
+                DidMerge(false, false)
+            }
+            (_, ArithmeticAnalysisPayload::None) => DidMerge(false, true),
+            (ArithmeticAnalysisPayload::None, from) => {
+                *to = from;
+                DidMerge(true, false)
+            }
+            (to, from) => {
+                assert_eq!(*to, from, "Merged non-equal constants");
+                DidMerge(false, false)
+            }
+        }
     }
 
     fn modify(egraph: &mut EGraph<MixLang, Self>, id: Id) {
@@ -183,22 +348,188 @@ impl Analysis<MixLang> for ArithmeticAnalysis {
     }
 }
 
+/// Time it takes to execute a single mix, as a function of the volume being mixed:
+/// `per_mix + per_volume * (lhs volume + rhs volume)`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MixTimeModel {
+    /// Fixed time of each mix, i.e for loading and cleaning the mixer.
+    pub per_mix: f64,
+    /// Additional time per unit of volume mixed.
+    pub per_volume: f64,
+}
+
+impl Default for MixTimeModel {
+    /// Every mix takes one unit of time, regardless of the volume.
+    fn default() -> Self {
+        Self {
+            per_mix: 1.0,
+            per_volume: 0.0,
+        }
+    }
+}
+
+impl MixTimeModel {
+    pub fn new(per_mix: f64, per_volume: f64) -> Self {
+        Self {
+            per_mix,
+            per_volume,
+        }
+    }
+
+    /// Time it takes to mix fluids with the given total volume.
+    pub fn mix_time(&self, total_volume: f64) -> f64 {
+        self.per_mix + self.per_volume * total_volume
+    }
+}
+
+/// What the extracted mixer is optimized for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CostObjective {
+    /// Minimize the number of mixes.
+    #[default]
+    MixCount,
+    /// Minimize the total mixing time, according to a `MixTimeModel`.
+    MixTime,
+    /// Minimize the volume of the inputs consumed beyond the produced volume. Inputs are
+    /// dispensed in whole units, so the smallest fluid of a design takes at least one unit and
+    /// the design consumes its total volume in units of that fluid to produce the one unit of the
+    /// target it needs. Designs wasting as much prefer fewer nested mixes, input tiers are not
+    /// taken into account.
+    Waste,
+}
+
+/// How leaves of concentrations none of the inputs has are penalized, by the distance of their
+/// concentration to the nearest input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProximityCost {
+    /// The distance alone, whatever the volume of the leaf.
+    #[default]
+    Flat,
+    /// The distance scaled by the share of the produced volume the leaf makes up, which is how far
+    /// the leaf moves the design off its target. Designs needing less of a missing concentration
+    /// are preferred.
+    VolumeWeighted,
+}
+
+/// Cost of a mix while minimizing waste, breaking ties between designs wasting as much.
+const WASTE_MIX_COST: f64 = 0.001;
+
+/// Cost of a leaf of the target fluid itself. An expression containing one is not a design, and
+/// whatever else it contains, it costs more than any design.
+pub const TARGET_LEAF_COST: f64 = f64::INFINITY;
+
+/// Cost of a mix in a ratio the mixer does not support. An expression containing one is not a
+/// design either.
+pub const UNSUPPORTED_RATIO_COST: f64 = f64::INFINITY;
+
+/// Whether the cost is one of a design, i.e it does not contain a leaf of the target fluid.
+pub fn is_design_cost(cost: f64) -> bool {
+    cost.is_finite()
+}
+
 pub struct OpCost<'a> {
     target: Concentration,
-    input_space: HashSet<Concentration>,
+    /// Inputs along with their tiers, matched with a tolerance so that leaves off by a rounding
+    /// error still count.
+    input_space: InputSpace,
     egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+    objective: CostObjective,
+    mix_time_model: MixTimeModel,
+    /// Volume of the target fluid, mixed volumes are measured relative to it while optimizing for
+    /// mix time, and so are the volumes consumed from the inputs.
+    output_volume: f64,
+    /// Ratios mixes may be extracted in, any ratio if not set.
+    capabilities: Option<MixerCapabilities>,
+    proximity: ProximityCost,
 }
 
 impl<'a> OpCost<'a> {
     pub(crate) fn new(
         target: Concentration,
-        input_space: HashSet<Concentration>,
+        input_space: InputSpace,
         egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
     ) -> Self {
         Self {
             target,
             input_space,
             egraph,
+            objective: CostObjective::MixCount,
+            mix_time_model: MixTimeModel::default(),
+            output_volume: 1.0,
+            capabilities: None,
+            proximity: ProximityCost::Flat,
+        }
+    }
+
+    pub(crate) fn with_mix_time(
+        mut self,
+        objective: CostObjective,
+        mix_time_model: MixTimeModel,
+        output_volume: f64,
+    ) -> Self {
+        self.objective = objective;
+        self.mix_time_model = mix_time_model;
+        self.output_volume = output_volume;
+        self
+    }
+
+    pub(crate) fn with_capabilities(mut self, capabilities: Option<MixerCapabilities>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub(crate) fn with_proximity(mut self, proximity: ProximityCost) -> Self {
+        self.proximity = proximity;
+        self
+    }
+
+    /// Volume of the fluid the e-class holds, relative to the volume of the target.
+    fn relative_volume(&self, id: Id) -> Option<f64> {
+        match &self.egraph[id].data {
+            ArithmeticAnalysisPayload::Fluid(fluid) => {
+                let volume: f64 = fluid.unit_volume().clone().into();
+                Some(volume / self.output_volume)
+            }
+            _ => None,
+        }
+    }
+
+    /// Penalty of a leaf consuming the input fluid, proportional to the consumed volume relative
+    /// to the produced volume. While minimizing waste, it is the volume wasted if the leaf were
+    /// the smallest fluid of the design instead.
+    fn input_cost(&self, fluid: &Fluid) -> f64 {
+        let volume: f64 = fluid.unit_volume().clone().into();
+        if self.objective == CostObjective::Waste {
+            return self.output_volume / volume - 1.0;
+        }
+        let tier = self
+            .input_space
+            .get(fluid.concentration())
+            .map_or(InputTier::default(), |input| input.tier());
+        tier.penalty() * volume / self.output_volume
+    }
+
+    fn mix_cost(&self, mix: &[Id; 2]) -> f64 {
+        if let Some(capabilities) = &self.capabilities {
+            // Mixes of operands without a known volume cannot be checked, so they are not extracted.
+            let supported = match mix.map(|id| self.relative_volume(id)) {
+                [Some(lhs), Some(rhs)] => capabilities.supports(lhs, rhs),
+                _ => false,
+            };
+            if !supported {
+                return UNSUPPORTED_RATIO_COST;
+            }
+        }
+        match self.objective {
+            CostObjective::MixCount => 1.0,
+            CostObjective::Waste => WASTE_MIX_COST,
+            CostObjective::MixTime => {
+                let total_volume = mix
+                    .iter()
+                    .map(|id| self.relative_volume(*id).unwrap_or(0.0))
+                    .sum();
+                self.mix_time_model.mix_time(total_volume)
+            }
         }
     }
 
@@ -211,16 +542,21 @@ impl<'a> OpCost<'a> {
     }
 
     fn proximity_cost(&self, conc: &Concentration) -> f64 {
-        let mut min = 1.0;
-        for val in self.input_space.iter() {
-            let diff = conc.clone() - val.clone();
-            let diff: f64 = diff.into();
-            let diff = diff.abs();
-            if diff < min {
-                min = diff;
+        self.input_space.nearest(conc).map_or(1.0, |nearest| {
+            (nearest.concentration().delta(conc) as f64 * Concentration::EPSILON).min(1.0)
+        })
+    }
+
+    /// Penalty of a leaf of a concentration none of the inputs has.
+    fn missing_input_cost(&self, fluid: &Fluid) -> f64 {
+        let cost = self.proximity_cost(fluid.concentration()) * (1.0 / Concentration::EPSILON);
+        match self.proximity {
+            ProximityCost::Flat => cost,
+            ProximityCost::VolumeWeighted => {
+                let volume: f64 = fluid.unit_volume().clone().into();
+                cost * volume / self.output_volume
             }
         }
-        min
     }
 }
 
@@ -237,7 +573,7 @@ impl<'a> egg::CostFunction<MixLang> for OpCost<'a> {
             MixLang::Sub(_) => 100.0,
             MixLang::Div(_) => 100.0,
             MixLang::Mult(_) => 100.0,
-            MixLang::Mix(_) => 1.0,
+            MixLang::Mix(mix) => self.mix_cost(mix),
             MixLang::Fluid(fl) => {
                 let conc_id = fl[0];
                 let vol_id = fl[1];
@@ -249,75 +585,335 @@ impl<'a> egg::CostFunction<MixLang> for OpCost<'a> {
                     let fluid = Fluid::new(conc, vol);
                     let concentration = fluid.concentration();
                     if self.is_direct_fluid_available(&fluid) {
-                        0.0
+                        self.input_cost(&fluid)
                     } else if self.target == *concentration {
-                        f64::MAX
+                        TARGET_LEAF_COST
                     } else {
-                        self.proximity_cost(concentration) * (1.0 / Concentration::EPSILON)
+                        self.missing_input_cost(&fluid)
                     }
                 } else {
                     1000.0
                 }
             }
         };
-        enode.fold(base_cost, |sum, id| sum + costs(id))
+        match (self.objective, enode) {
+            // A design wastes as much as its smallest fluid makes it, whichever operand holds it.
+            (CostObjective::Waste, MixLang::Mix(_)) => {
+                enode.fold(0.0, |waste, id| f64::max(waste, costs(id))) + base_cost
+            }
+            _ => enode.fold(base_cost, |sum, id| sum + costs(id)),
+        }
     }
 }
 
-fn generate_rewrite_rules() -> Vec<Rewrite<MixLang, ArithmeticAnalysis>> {
+/// Concentration steps of the rules shifting the concentrations of a mix apart, unless the
+/// configuration gives its own.
+pub const DEFAULT_CONCENTRATION_STEPS: [f64; 2] = [0.01, 0.1];
+
+/// Concentration steps of the stages of a coarse to fine search.
+pub const COARSE_TO_FINE_STAGES: [f64; 3] = [0.1, 0.01, 0.001];
+
+/// Number of e-nodes a saturation stops at, unless the configuration gives its own limit. Large
+/// enough for the time limit to stop a saturation first.
+pub const DEFAULT_NODE_LIMIT: usize = 10_000_000_000_000_000;
+
+/// Number of iterations a saturation stops after, unless the configuration gives its own limit.
+pub const DEFAULT_ITER_LIMIT: usize = 100_000;
+
+/// Largest number of mixes the input space is expanded by, see `SaturationConfig::expansion_depth`.
+pub const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// Number of concentrations each level of an expansion of the input space stops growing at.
+pub const MAX_EXPANDED_CONCENTRATIONS: usize = 1 << 12;
+
+/// Rewrite rules exploring the mixer space. Rules creating new concentrations never create one
+/// inside the forbidden windows, so that no intermediate of an extracted design can have it.
+/// Rules creating mixes do not create ones exceeding the maximum intermediate volume.
+///
+/// With an expansion depth the e-graph already holds the reachable concentrations, so only the
+/// simplification rules are run.
+///
+/// Fluids are halved and mixes of equal volumes are shifted apart whatever the capabilities of the
+/// mixer are, as compressing them builds mixes of other ratios. Each other supported ratio gets
+/// rules splitting fluids and shifting mixes in that ratio.
+fn generate_rewrite_rules(config: &SaturationConfig) -> Vec<MixRewrite> {
+    let forbidden_windows = &config.forbidden_windows;
+    let incompatibilities = &config.incompatibilities;
+    let max_volume = config.max_intermediate_volume.clone();
+    if config.expansion_depth.is_some() {
+        return simplification_rules(max_volume);
+    }
+    let steps = match config.concentration_steps.is_empty() {
+        true => &DEFAULT_CONCENTRATION_STEPS[..],
+        false => &config.concentration_steps[..],
+    };
+    let mut rules = vec![rw!("expand-fluid-to-mix";
+        "(fluid ?a ?b)" => { Folded::new("(mix (fluid ?a (/ ?b 2.0)) (fluid ?a (/ ?b 2.0)))") }
+        if (volume_valid("?b"))
+        if intermediate_volume_valid(&["?b"], &["?b"], max_volume.clone()))];
+    rules.extend(steps.iter().map(|&step| {
+        rw!(format!("diff-mixers-l-{step}");
+            "(mix (fluid ?a ?b) (fluid ?c ?b))" => {
+                Folded::new(&format!("(mix (fluid (+ ?a {step}) ?b) (fluid (- ?c {step}) ?b))"))
+            }
+            if concentration_valid("?a", Op::Add, "?c", Op::Remove, step, forbidden_windows)
+            if mix_compatible("?a", Op::Add, "?c", Op::Remove, step, incompatibilities))
+    }));
+    let ratios = config
+        .capabilities
+        .iter()
+        .flat_map(MixerCapabilities::ratios)
+        .filter(|ratio| **ratio != MixRatio::ONE_TO_ONE);
+    for &ratio in ratios {
+        let (small, large) = (ratio.lhs(), ratio.rhs());
+        let parts = small + large;
+        rules.push(rw!(format!("split-fluid-{small}-{large}");
+            "(fluid ?a ?b)" => {
+                Folded::new(&format!(
+                    "(mix (fluid ?a (* (/ ?b {parts}.0) {small}.0)) \
+                     (fluid ?a (* (/ ?b {parts}.0) {large}.0)))"
+                ))
+            }
+            if ratio_split_valid("?a", "?b", ratio, max_volume.clone())));
+        // The fluid of the smaller part is shifted by the larger part and the other way around,
+        // for each order of the operands.
+        for (lhs, rhs) in [(small, large), (large, small)] {
+            rules.extend(steps.iter().map(|&step| {
+                let shifts = [rhs, lhs].map(|part| LimitedFloat::from(step * f64::from(part)));
+                rw!(format!("diff-mixers-{lhs}-{rhs}-{step}");
+                "(mix (fluid ?a ?b) (fluid ?c ?d))" => {
+                    Folded::new(&format!(
+                        "(mix (fluid (+ ?a {}) ?b) (fluid (- ?c {}) ?d))",
+                        shifts[0], shifts[1]
+                    ))
+                }
+                if ratio_shift_valid(
+                    [lhs, rhs],
+                    shifts.clone(),
+                    forbidden_windows,
+                    incompatibilities
+                ))
+            }));
+        }
+    }
+    rules.extend(simplification_rules(max_volume));
+    rules
+}
+
+/// Rules rewriting designs into equivalent ones, without creating new concentrations.
+fn simplification_rules(max_volume: Option<Volume>) -> Vec<MixRewrite> {
     vec![
-        rw!("expand-fluid-to-mix";
-            "(fluid ?a ?b)" => "(mix (fluid ?a (/ ?b 2.0)) (fluid ?a (/ ?b 2.0)))"
-            if (volume_valid("?b"))),
-        rw!("diff-mixers-l-0.01";
-            "(mix (fluid ?a ?b) (fluid ?c ?b))" => "(mix (fluid (+ ?a 0.01) ?b) (fluid (- ?c 0.01) ?b))"
-        if concentration_valid("?a", Op::Add, "?c", Op::Remove, 0.01)),
-        rw!("diff-mixers-l-0.1";
-            "(mix (fluid ?a ?b) (fluid ?c ?b))" => "(mix (fluid (+ ?a 0.1) ?b) (fluid (- ?c 0.1) ?b))"
-            if concentration_valid("?a", Op::Add, "?c", Op::Remove, 0.1)),
         rw!("mixer-assoc";
             "(mix (fluid ?a ?b) (fluid ?c ?d))" => "(mix (fluid ?c ?d) (fluid ?a ?b))"),
-        rw!("mixer-compress-with-0";
-            "(mix (mix (fluid ?a ?b) (fluid 0.0 ?b)) (fluid 0.0 ?c))" => "(mix (fluid ?a (/ ?b 2.0)) (fluid 0.0 (* 3.0 (/ ?b 2.0))))"
-        if volume_multiple("?b", "?c", 0.5)),
+        // Fluids of the same concentration mixed in one after another can be mixed in at once,
+        // saving a mix whatever their volumes are.
+        rw!("mixer-compress";
+            "(mix (mix ?x (fluid ?y ?c)) (fluid ?z ?d))" => { Folded::new("(mix ?x (fluid ?z (+ ?c ?d)))") }
+            if compression_consistent("?x", "?y", "?z", "?c", "?d")
+            if intermediate_volume_valid(&["?x"], &["?c", "?d"], max_volume)),
     ]
 }
 
-fn volume_multiple(
+/// Whether the mix a rule builds from `lhs` and `rhs` stays within the maximum intermediate
+/// volume. The volume of each side is the sum of the volumes bound to its variables, a variable
+/// bound to a fluid counts with the volume of the fluid.
+///
+/// Volumes of a design are measured in units of its smallest fluid, which is at most as large as
+/// either side. So a mix larger than the maximum in units of its smaller side is larger than the
+/// maximum in the design as well. The final mix of the design is not an intermediate, so mixes
+/// built for the target itself are always valid.
+pub fn intermediate_volume_valid(
+    lhs: &'static [&'static str],
+    rhs: &'static [&'static str],
+    max_intermediate_volume: Option<Volume>,
+) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let parse = |vars: &[&str]| {
+        vars.iter()
+            .map(|var| var.parse::<Var>().unwrap())
+            .collect::<Vec<_>>()
+    };
+    let (lhs, rhs) = (parse(lhs), parse(rhs));
+    move |egraph, id, subst| {
+        let Some(max_intermediate_volume) = &max_intermediate_volume else {
+            return true;
+        };
+        let volume_of = |id: Id| match &egraph[id].data {
+            ArithmeticAnalysisPayload::Fluid(fluid) => Some(fluid.unit_volume().wrapped),
+            ArithmeticAnalysisPayload::LimitedFloat(volume) => Some(volume.wrapped),
+            ArithmeticAnalysisPayload::None => None,
+        };
+        if volume_of(id) == Some(TARGET_VOLUME.wrapped) {
+            return true;
+        }
+        let side_volume = |vars: &[Var]| {
+            vars.iter()
+                .map(|var| volume_of(subst[*var]).map(i128::from))
+                .sum::<Option<i128>>()
+        };
+        let (Some(lhs_volume), Some(rhs_volume)) = (side_volume(&lhs), side_volume(&rhs)) else {
+            return false;
+        };
+        // `(lhs + rhs) / min(lhs, rhs) <= max`, compared on the wrapped values.
+        let scale = (1.0 / Volume::EPSILON).round() as i128;
+        (lhs_volume + rhs_volume) * scale
+            <= i128::from(max_intermediate_volume.wrapped) * lhs_volume.min(rhs_volume)
+    }
+}
+
+/// Whether compressing `(mix (mix ?x (fluid ?y ?c)) (fluid ?z ?d))` into
+/// `(mix ?x (fluid ?z (+ ?c ?d)))` is sound: `?y` and `?z` must hold the same concentration, and
+/// the two must evaluate to the same fluid in `LimitedFloat` precision. They are equal in exact
+/// arithmetic, but rounding the intermediate mix may shift the result by an epsilon, which must
+/// not be merged.
+///
+/// Equal concentrations are compared by value, as they are not necessarily in the same e-class.
+pub fn compression_consistent(
+    fluid: &'static str,
+    concentration_a: &'static str,
+    concentration_b: &'static str,
     vol_a: &'static str,
     vol_b: &'static str,
-    multiple: f64,
 ) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let var_fluid: Var = fluid.parse().unwrap();
+    let var_concentration_a: Var = concentration_a.parse().unwrap();
+    let var_concentration_b: Var = concentration_b.parse().unwrap();
     let var_vol_a: Var = vol_a.parse().unwrap();
     let var_vol_b: Var = vol_b.parse().unwrap();
     move |egraph, _, subst| {
-        let vol_a = subst[var_vol_a];
-        let vol_node_a = &egraph[vol_a];
-        let vol_a = vol_node_a.data.clone().expect_limited_float().unwrap();
-        let vol_a_float: f64 = vol_a.clone().into();
+        let ArithmeticAnalysisPayload::Fluid(fluid) = &egraph[subst[var_fluid]].data else {
+            return false;
+        };
+        let limited_float = |var: Var| egraph[subst[var]].data.clone().expect_limited_float();
+        let (Some(concentration), Some(concentration_b), Some(vol_a), Some(vol_b)) = (
+            limited_float(var_concentration_a),
+            limited_float(var_concentration_b),
+            limited_float(var_vol_a),
+            limited_float(var_vol_b),
+        ) else {
+            return false;
+        };
+        // Mixing into a fluid of the same concentration only grows it, there is no mix to save.
+        if concentration != concentration_b
+            || *fluid.concentration() == concentration
+            || vol_a.wrapped.checked_add(vol_b.wrapped).is_none()
+        {
+            return false;
+        }
 
-        let vol_b = subst[var_vol_b];
-        let vol_node_b = &egraph[vol_b];
-        let vol_b = vol_node_b.data.clone().expect_limited_float().unwrap();
-        let vol_b_float: f64 = vol_b.clone().into();
+        let chained = fluid
+            .mix(&Fluid::new(concentration.clone(), vol_a.clone()))
+            .mix(&Fluid::new(concentration.clone(), vol_b.clone()));
+        let compressed = fluid.mix(&Fluid::new(concentration, vol_a + vol_b));
+        chained == compressed
+    }
+}
 
-        let div = vol_a_float / vol_b_float;
-        div == multiple
+/// Whether the fluid of the concentration bound to `concentration` and the volume bound to `vol`
+/// can be split in the ratio, i.e the parts mix back into the very same fluid, and the mix of the
+/// parts stays within the maximum intermediate volume. Mixes built for the target itself are
+/// always within it.
+pub fn ratio_split_valid(
+    concentration: &'static str,
+    vol: &'static str,
+    ratio: MixRatio,
+    max_intermediate_volume: Option<Volume>,
+) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let var_concentration: Var = concentration.parse().unwrap();
+    let var_vol: Var = vol.parse().unwrap();
+    move |egraph, id, subst| {
+        let limited_float = |var: Var| egraph[subst[var]].data.clone().expect_limited_float();
+        let (Some(_), Some(vol)) = (limited_float(var_concentration), limited_float(var_vol))
+        else {
+            return false;
+        };
+        let ArithmeticAnalysisPayload::Fluid(fluid) = egraph[id].data.clone() else {
+            return false;
+        };
+        let parts = ratio.lhs() + ratio.rhs();
+        if let Some(max_intermediate_volume) = &max_intermediate_volume {
+            // `parts / lhs <= max`, compared on the wrapped values.
+            let scale = (1.0 / Volume::EPSILON).round() as i128;
+            if vol.wrapped != TARGET_VOLUME.wrapped
+                && i128::from(parts) * scale
+                    > i128::from(max_intermediate_volume.wrapped) * i128::from(ratio.lhs())
+            {
+                return false;
+            }
+        }
+        // Computed the way the analysis computes the volumes of the rewritten fluids.
+        let share = vol / LimitedFloat::from(f64::from(parts));
+        let part = |part: u32| share.clone() * LimitedFloat::from(f64::from(part));
+        let (lhs, rhs) = (part(ratio.lhs()), part(ratio.rhs()));
+        if lhs.is_zero() {
+            return false;
+        }
+        let concentration = fluid.concentration().clone();
+        Fluid::new(concentration.clone(), lhs).mix(&Fluid::new(concentration, rhs)) == fluid
     }
 }
 
-fn volume_valid(
+/// Whether shifting the concentrations `?a` and `?c` of `(mix (fluid ?a ?b) (fluid ?c ?d))`, a
+/// mix of volumes in the ratio `parts`, apart by `shifts` is sound. `?a` is raised by the first
+/// shift and `?c` lowered by the second one. Both of them must stay valid, outside of the forbidden
+/// windows and compatible, and the mix must still produce the very same fluid.
+pub fn ratio_shift_valid(
+    parts: [u32; 2],
+    shifts: [LimitedFloat; 2],
+    forbidden_windows: &[ConcentrationWindow],
+    incompatibilities: &[Incompatibility],
+) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let forbidden_windows = forbidden_windows.to_vec();
+    let incompatibilities = incompatibilities.to_vec();
+    let vars = ["?a", "?b", "?c", "?d"].map(|var| var.parse::<Var>().unwrap());
+    move |egraph, id, subst| {
+        let [Some(concentration_a), Some(vol_b), Some(concentration_c), Some(vol_d)] =
+            vars.map(|var| egraph[subst[var]].data.clone().expect_limited_float())
+        else {
+            return false;
+        };
+        let (lhs, rhs): (f64, f64) = (vol_b.clone().into(), vol_d.clone().into());
+        let in_ratio = MixRatio::new(parts[0], parts[1])
+            .is_some_and(|ratio| ratio.matches(lhs, rhs) && (lhs < rhs) == (parts[0] < parts[1]));
+        if !in_ratio {
+            return false;
+        }
+        let ArithmeticAnalysisPayload::Fluid(mixed) = egraph[id].data.clone() else {
+            return false;
+        };
+        let [shift_a, shift_c] = shifts.clone();
+        let (concentration_a, concentration_c) =
+            (concentration_a + shift_a, concentration_c - shift_c);
+        let allowed = |concentration: &Concentration| {
+            concentration.valid()
+                && !forbidden_windows
+                    .iter()
+                    .any(|window| window.contains(concentration))
+        };
+        allowed(&concentration_a)
+            && allowed(&concentration_c)
+            && !incompatibilities.iter().any(|incompatibility| {
+                incompatibility.forbids_direct(&concentration_a, &concentration_c)
+            })
+            && Fluid::new(concentration_a, vol_b).mix(&Fluid::new(concentration_c, vol_d)) == mixed
+    }
+}
+
+/// Whether the volume bound to `vol` can be halved without losing precision.
+pub fn volume_valid(
     vol: &'static str,
 ) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
     let var_vol: Var = vol.parse().unwrap();
     move |egraph, _, subst| {
         let vol = subst[var_vol];
         let vol_node = &egraph[vol];
-        let vol = vol_node.data.clone().expect_limited_float().unwrap();
+        // Volumes without a value, i.e rejected divisions, are never halved.
+        let Some(vol) = vol_node.data.clone().expect_limited_float() else {
+            return false;
+        };
         let vol_float: f64 = vol.clone().into();
         let two = Volume::from(2.0);
-        let res = vol / two;
-        let res: f64 = res.into();
+        let half = vol.clone() / two;
+        let res: f64 = half.clone().into();
 
         let res_float = vol_float / 2.0;
 
@@ -327,165 +923,1187 @@ fn volume_valid(
         // Physically we know that a volume is positive.
         let volume_is_positive = res > 0.0;
 
-        volume_is_positive && precision_preserved
+        // Sums of volumes, i.e of compressed fluids, need not be representable as `f64`, in which
+        // case the halves would mix into a slightly different volume.
+        let half_fluid = Fluid::new(Concentration::from(0.0), half);
+        let halves_mix_back = half_fluid.mix(&half_fluid).unit_volume() == &vol;
+
+        volume_is_positive && precision_preserved && halves_mix_back
     }
 }
 
-enum Op {
+/// How a rule changes a concentration by its step.
+pub enum Op {
     Add,
     Remove,
 }
 
-fn concentration_valid(
+/// Concentration bound to the variable, changed by `step`. `None` if the variable has no value,
+/// i.e it is bound to a rejected operation.
+fn shifted_concentration(
+    egraph: &EGraph<MixLang, ArithmeticAnalysis>,
+    subst: &Subst,
+    var: Var,
+    op: &Op,
+    step: f64,
+) -> Option<Concentration> {
+    let concentration = egraph[subst[var]].data.clone().expect_limited_float()?;
+    let concentration: f64 = concentration.into();
+    let res = match op {
+        Op::Add => concentration + step,
+        Op::Remove => concentration - step,
+    };
+    Some(Concentration::saturating_from_f64(res))
+}
+
+/// Whether changing the concentrations bound to `concentration_a` and `concentration_b` by `step`
+/// keeps both of them valid and outside of the forbidden windows.
+pub fn concentration_valid(
     concentration_a: &'static str,
     op_a: Op,
     concentration_b: &'static str,
     op_b: Op,
     step: f64,
+    forbidden_windows: &[ConcentrationWindow],
 ) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let forbidden_windows = forbidden_windows.to_vec();
     let var_concentration_a: Var = concentration_a.parse().unwrap();
     let var_concentration_b: Var = concentration_b.parse().unwrap();
     move |egraph, _, subst| {
-        let conc_a = subst[var_concentration_a];
-        let conc_node_a = &egraph[conc_a];
-        let concentration_a = conc_node_a.data.clone().expect_limited_float().unwrap();
-        let concentration_a: f64 = concentration_a.into();
-
-        let res_a = match op_a {
-            Op::Add => concentration_a + step,
-            Op::Remove => concentration_a - step,
+        let (Some(concentration_a), Some(concentration_b)) = (
+            shifted_concentration(egraph, subst, var_concentration_a, &op_a, step),
+            shifted_concentration(egraph, subst, var_concentration_b, &op_b, step),
+        ) else {
+            return false;
         };
-        let concentration_a = Concentration::from(res_a);
 
-        let conc_b = subst[var_concentration_b];
-        let conc_node_b = &egraph[conc_b];
-        let concentration_b = conc_node_b.data.clone().expect_limited_float().unwrap();
-        let concentration_b: f64 = concentration_b.into();
-        let res_b = match op_b {
-            Op::Add => concentration_b + step,
-            Op::Remove => concentration_b - step,
+        let allowed = |concentration: &Concentration| {
+            !forbidden_windows
+                .iter()
+                .any(|window| window.contains(concentration))
         };
-        let concentration_b = Concentration::from(res_b);
-
-        concentration_a.valid() && concentration_b.valid()
+        concentration_a.valid()
+            && concentration_b.valid()
+            && allowed(&concentration_a)
+            && allowed(&concentration_b)
     }
 }
 
-fn normalize_expr_by_min_volume(expr: &RecExpr<MixLang>) -> String {
-    // Find the smallest volume in the expression
-    let mut min_volume: Option<f64> = None;
-    for node in expr.as_ref() {
-        if let MixLang::Fluid(fluid) = node {
-            if let MixLang::LimitedFloat(vol) = &expr[fluid[1]] {
-                let vol_float: f64 = vol.clone().into();
-                min_volume = Some(min_volume.map_or(vol_float, |min| min.min(vol_float)));
-            }
+/// Whether the fluids of the concentrations bound to `concentration_a` and `concentration_b`,
+/// changed by `step`, may be mixed directly. Fluids at the concentration of an input are taken to
+/// be the undiluted input, so two incompatible inputs are never mixed directly. Designs mixing
+/// them after diluting are validated once extracted.
+pub fn mix_compatible(
+    concentration_a: &'static str,
+    op_a: Op,
+    concentration_b: &'static str,
+    op_b: Op,
+    step: f64,
+    incompatibilities: &[Incompatibility],
+) -> impl Fn(&mut EGraph<MixLang, ArithmeticAnalysis>, Id, &Subst) -> bool {
+    let incompatibilities = incompatibilities.to_vec();
+    let var_concentration_a: Var = concentration_a.parse().unwrap();
+    let var_concentration_b: Var = concentration_b.parse().unwrap();
+    move |egraph, _, subst| {
+        if incompatibilities.is_empty() {
+            return true;
         }
+        let (Some(concentration_a), Some(concentration_b)) = (
+            shifted_concentration(egraph, subst, var_concentration_a, &op_a, step),
+            shifted_concentration(egraph, subst, var_concentration_b, &op_b, step),
+        ) else {
+            return false;
+        };
+        !incompatibilities.iter().any(|incompatibility| {
+            incompatibility.forbids_direct(&concentration_a, &concentration_b)
+        })
     }
+}
 
-    // If there's no fluid node or min_volume is still None, return the original expression as a string
-    let min_volume = match min_volume {
-        Some(vol) => vol,
-        None => return format!("{}", expr),
+/// The expression with the volume of each fluid divided by the smallest one, so that the smallest
+/// fluid has a unit volume. Fails with the fluid whose volume cannot be divided, naming where it
+/// is in the expression.
+fn normalize_expr_by_min_volume(
+    expr: &RecExpr<MixLang>,
+) -> Result<RecExpr<MixLang>, ExtractedExprError> {
+    // Find the smallest volume in the expression
+    let min_volume = expr
+        .as_ref()
+        .iter()
+        .filter_map(|node| match node {
+            MixLang::Fluid([_, volume]) => match &expr[*volume] {
+                MixLang::LimitedFloat(volume) => Some(f64::from(volume.clone())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .reduce(f64::min);
+    // Without fluids there is nothing to normalize.
+    let Some(min_volume) = min_volume else {
+        return Ok(expr.clone());
     };
+    let mut normalized = RecExpr::default();
+    let root = Id::from(expr.as_ref().len() - 1);
+    normalize_node(
+        expr,
+        root,
+        min_volume,
+        &mut ExprPath::default(),
+        &mut normalized,
+    )?;
+    Ok(normalized)
+}
 
-    // Helper function to format the nodes
-    fn format_node(expr: &RecExpr<MixLang>, id: Id, min_volume: f64) -> String {
-        match &expr[id] {
-            MixLang::Fluid(fluid) => {
-                let conc = &expr[fluid[0]];
-                let vol = &expr[fluid[1]];
-                if let MixLang::LimitedFloat(vol) = vol {
-                    let vol_float: f64 = vol.clone().into();
-                    let normalized_vol = vol_float / min_volume;
-                    if let MixLang::LimitedFloat(conc) = conc {
-                        return format!("(fluid {} {})", conc, normalized_vol);
-                    }
-                }
-                String::new()
-            }
-            MixLang::Mix(mix) => {
-                let left = format_node(expr, mix[0], min_volume);
-                let right = format_node(expr, mix[1], min_volume);
-                format!("(mix {} {})", left, right)
-            }
-            MixLang::Add(add) => {
-                let left = format_node(expr, add[0], min_volume);
-                let right = format_node(expr, add[1], min_volume);
-                format!("(+ {} {})", left, right)
-            }
-            MixLang::Sub(sub) => {
-                let left = format_node(expr, sub[0], min_volume);
-                let right = format_node(expr, sub[1], min_volume);
-                format!("(- {} {})", left, right)
-            }
-            MixLang::Div(div) => {
-                let left = format_node(expr, div[0], min_volume);
-                let right = format_node(expr, div[1], min_volume);
-                format!("(/ {} {})", left, right)
+/// Adds the node, with the volumes of the fluids below it normalized, to `normalized`.
+fn normalize_node(
+    expr: &RecExpr<MixLang>,
+    id: Id,
+    min_volume: f64,
+    path: &mut ExprPath,
+    normalized: &mut RecExpr<MixLang>,
+) -> Result<Id, ExtractedExprError> {
+    let node = match &expr[id] {
+        MixLang::Fluid([concentration, volume]) => {
+            let fail = |reason| extracted_expr_error(expr, id, path, reason);
+            let volume = match &expr[*volume] {
+                MixLang::LimitedFloat(volume) => LimitedFloat::from_f64_rounded(
+                    f64::from(volume.clone()) / min_volume,
+                    RoundingMode::Nearest,
+                )
+                .map_err(|err| fail(ExtractedNodeFailure::UnnormalizableVolume(err)))?,
+                _ => return Err(fail(ExtractedNodeFailure::NonNumericVolume)),
+            };
+            let concentration = normalize_node(expr, *concentration, min_volume, path, normalized)?;
+            let volume = normalized.add(MixLang::LimitedFloat(volume));
+            MixLang::Fluid([concentration, volume])
+        }
+        MixLang::Mix([lhs, rhs]) => {
+            let mut operands = [*lhs, *rhs];
+            for (operand, side) in operands.iter_mut().zip([MixOperand::Lhs, MixOperand::Rhs]) {
+                path.0.push(side);
+                *operand = normalize_node(expr, *operand, min_volume, path, normalized)?;
+                path.0.pop();
             }
-            MixLang::Mult(mult) => {
-                let left = format_node(expr, mult[0], min_volume);
-                let right = format_node(expr, mult[1], min_volume);
-                format!("(* {} {})", left, right)
+            MixLang::Mix(operands)
+        }
+        // Arithmetic nodes do not hold fluids, only their children are copied.
+        node => {
+            let mut copied = node.clone();
+            for child in copied.children_mut() {
+                *child = normalize_node(expr, *child, min_volume, path, normalized)?;
             }
-            MixLang::LimitedFloat(lf) => format!("{}", lf),
+            copied
+        }
+    };
+    Ok(normalized.add(node))
+}
+
+/// Converts an extracted expression into an `Expr`, failing with the first node which is neither a
+/// mix nor a fluid, i.e an arithmetic node extracted without `ExtractionMode::Strict`.
+pub fn extracted_expr(expr: &RecExpr<MixLang>) -> Result<Expr, ExtractedExprError> {
+    let root = Id::from(expr.as_ref().len() - 1);
+    convert_node(expr, root, &mut ExprPath::default())
+}
+
+fn convert_node(
+    expr: &RecExpr<MixLang>,
+    id: Id,
+    path: &mut ExprPath,
+) -> Result<Expr, ExtractedExprError> {
+    match &expr[id] {
+        MixLang::Mix([lhs, rhs]) => {
+            path.0.push(MixOperand::Lhs);
+            let lhs = convert_node(expr, *lhs, path)?;
+            path.0.pop();
+            path.0.push(MixOperand::Rhs);
+            let rhs = convert_node(expr, *rhs, path)?;
+            path.0.pop();
+            Ok(Expr::Mix(Box::new(lhs), Box::new(rhs)))
         }
+        MixLang::Fluid([concentration, volume]) => match (&expr[*concentration], &expr[*volume]) {
+            (MixLang::LimitedFloat(concentration), MixLang::LimitedFloat(volume)) => Ok(
+                Expr::Fluid(Fluid::new(concentration.clone(), volume.clone())),
+            ),
+            _ => Err(extracted_expr_error(
+                expr,
+                id,
+                path,
+                ExtractedNodeFailure::NotAMixOrFluid,
+            )),
+        },
+        MixLang::LimitedFloat(value) => Ok(Expr::LimitedFloat(value.clone())),
+        _ => Err(extracted_expr_error(
+            expr,
+            id,
+            path,
+            ExtractedNodeFailure::NotAMixOrFluid,
+        )),
+    }
+}
+
+/// Error naming the node of the extracted expression, along with the whole expression.
+fn extracted_expr_error(
+    expr: &RecExpr<MixLang>,
+    id: Id,
+    path: &ExprPath,
+    reason: ExtractedNodeFailure,
+) -> ExtractedExprError {
+    ExtractedExprError {
+        expr: expr.to_string(),
+        path: path.clone(),
+        node: expr[id]
+            .build_recexpr(|child| expr[child].clone())
+            .to_string(),
+        reason,
     }
+}
 
-    // Format the root node
-    let root_id = expr.as_ref().len() - 1;
-    format_node(expr, Id::from(root_id), min_volume)
+/// Settings for a single saturation run.
+#[derive(Debug, Clone, Default)]
+pub struct SaturationConfig {
+    /// Time limit in seconds.
+    pub time_limit: u64,
+    /// Number of e-nodes the saturation stops at, `DEFAULT_NODE_LIMIT` if not set.
+    pub node_limit: Option<usize>,
+    /// Number of iterations the saturation stops after, `DEFAULT_ITER_LIMIT` if not set.
+    pub iter_limit: Option<usize>,
+    /// Print notes on the saturation, i.e the checkpoint it resumed from. Its statistics are
+    /// returned with the sequences as a `SaturationReport` instead.
+    pub show_report: bool,
+    /// Where the notes are printed to.
+    pub log: LogSink,
+    /// Periodically write the e-graph to disk so that the run can be resumed.
+    pub checkpoint: Option<CheckpointConfig>,
+    /// Continue saturating from a checkpoint written by a previous run.
+    pub resume_from: Option<PathBuf>,
+    /// What the extracted mixer is optimized for.
+    pub objective: CostObjective,
+    /// Mix time model used when optimizing for `CostObjective::MixTime`.
+    pub mix_time_model: MixTimeModel,
+    /// How leaves of concentrations none of the inputs has are penalized.
+    pub proximity_cost: ProximityCost,
+    /// Whether arithmetic nodes can be extracted.
+    pub extraction: ExtractionMode,
+    /// Which e-classes the design is extracted from. Extraction with required intermediates is
+    /// always rooted at the target node.
+    pub extraction_root: ExtractionRoot,
+    /// Concentration ranges no intermediate fluid may have.
+    pub forbidden_windows: Vec<ConcentrationWindow>,
+    /// Pairs of inputs which must not be mixed directly, rules never mix fluids at their
+    /// concentrations.
+    pub incompatibilities: Vec<Incompatibility>,
+    /// Largest volume an intermediate fluid may have, in units of the smallest fluid of the design.
+    pub max_intermediate_volume: Option<Volume>,
+    /// Volume ratios the mixer supports, designs mixing in any other ratio are never extracted.
+    /// Any ratio is supported if not set.
+    pub capabilities: Option<MixerCapabilities>,
+    /// Concentrations the extracted design must produce as intermediates, i.e mixes below its
+    /// root. Extraction fails if no design in the e-graph produces all of them.
+    pub required_intermediates: Vec<Concentration>,
+    /// Dump the e-class of the target, and the choices the extractor had, as dot.
+    pub target_class_dump: Option<ClassDumpConfig>,
+    /// Rules run along with the builtin ones. Their names must differ from each other and from the
+    /// names of the builtin rules.
+    pub custom_rules: Vec<MixRewrite>,
+    /// Steps the concentrations of a mix are shifted apart by, `DEFAULT_CONCENTRATION_STEPS` if
+    /// empty.
+    pub concentration_steps: Vec<f64>,
+    /// Concentration steps of a staged search, from coarse to fine, i.e `COARSE_TO_FINE_STAGES`.
+    /// Each stage saturates with a single step and an equal share of the time limit, starting
+    /// from the design of the previous stage, so that fine steps only explore around it. Empty for
+    /// a single saturation with every step of `concentration_steps`.
+    ///
+    /// Checkpoints are neither written nor resumed from by staged searches.
+    pub stages: Vec<f64>,
+    /// Number of mixes the e-graph is seeded with every concentration the inputs reach within,
+    /// before saturating. Saturation then only runs the rules simplifying designs, so targets out
+    /// of reach are not found. The expanding rules explore the mixer space if not set. At most
+    /// `MAX_EXPANSION_DEPTH`, and the expansion counts towards the time limit.
+    pub expansion_depth: Option<usize>,
+    /// Once set, saturation stops and the search fails with `MixerGenerationError::Cancelled`.
+    pub cancellation: Option<Arc<AtomicBool>>,
+    /// Called with the progress of the saturation after each iteration.
+    pub progress: Option<ProgressCallback>,
+}
+
+/// Builtin rules of the configuration followed by its custom rules.
+pub fn rewrite_rules(config: &SaturationConfig) -> Result<Vec<MixRewrite>, MixerGenerationError> {
+    let mut rules = generate_rewrite_rules(config);
+    for rule in &config.custom_rules {
+        if rules.iter().any(|existing| existing.name == rule.name) {
+            return Err(MixerGenerationError::DuplicateRuleName(
+                rule.name.to_string(),
+            ));
+        }
+        rules.push(rule.clone());
+    }
+    Ok(rules)
 }
 
 /// Saturate to find out an optimized sequence according to the cost function.
 pub fn saturate(
     target_concentration: Concentration,
-    time_limit: u64,
-    input_space: &[Fluid],
+    input_space: &InputSpace,
+    config: &SaturationConfig,
 ) -> Result<Sequence, MixerGenerationError> {
-    let mut initial_egraph = EGraph::new(ArithmeticAnalysis);
-    let target_node = format!("(fluid {} {})", target_concentration, f64::MAX)
-        .parse::<RecExpr<MixLang>>()
-        .map_err(|_| MixerGenerationError::FailedToParseTarget(target_concentration.clone()))?;
+    saturate_many(
+        std::slice::from_ref(&target_concentration),
+        input_space,
+        config,
+    )?
+    .pop()
+    .expect("a result for each target")
+}
 
-    let target = initial_egraph.add_expr(&target_node);
+/// Saturates a single e-graph holding a root for each target, and extracts the best sequence of
+/// each root. Targets share the explored mixer space, so this is cheaper than saturating for each
+/// one of them separately.
+///
+/// The outer error is a failure of the saturation itself, the inner ones are per target failures
+/// in the order of `target_concentrations`.
+pub fn saturate_many(
+    target_concentrations: &[Concentration],
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+) -> Result<Vec<Result<Sequence, MixerGenerationError>>, MixerGenerationError> {
+    if config.stages.is_empty() {
+        return saturate_seeded(target_concentrations, input_space, config, None);
+    }
+    rewrite_rules(config)?;
+    Ok(target_concentrations
+        .iter()
+        .map(|target_concentration| saturate_staged(target_concentration, input_space, config))
+        .collect())
+}
 
-    let input_space = input_space
+/// Saturates with the steps of the stages one after another, each stage seeded with the design
+/// of the previous one. A stage not finding a design leaves the next one to start from scratch.
+fn saturate_staged(
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+) -> Result<Sequence, MixerGenerationError> {
+    let stage_time_limit = (config.time_limit / config.stages.len() as u64).max(1);
+    let mut best: Option<Sequence> = None;
+    let mut last_error = None;
+    for &step in &config.stages {
+        let stage_config = SaturationConfig {
+            time_limit: stage_time_limit,
+            checkpoint: None,
+            resume_from: None,
+            concentration_steps: vec![step],
+            stages: vec![],
+            ..config.clone()
+        };
+        let seed = best.as_ref().map(|sequence| &sequence.raw_expr);
+        let sequence = saturate_seeded(
+            std::slice::from_ref(target_concentration),
+            input_space,
+            &stage_config,
+            seed,
+        )?
+        .pop()
+        .expect("a result for the target");
+        match sequence {
+            // The seed is part of the e-graph, so a stage extracts a design at least as cheap.
+            Ok(sequence) if best.as_ref().is_none_or(|best| sequence.cost <= best.cost) => {
+                best = Some(sequence)
+            }
+            Ok(_) => {}
+            Err(MixerGenerationError::TimeLimitReached(_)) => {
+                last_error = Some(MixerGenerationError::TimeLimitReached(config.time_limit))
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    best.ok_or_else(|| last_error.expect("a staged search has at least one stage"))
+}
+
+/// Same as `saturate_many`, with the e-graph seeded with a design of the first target, which the
+/// saturation explores around.
+fn saturate_seeded(
+    target_concentrations: &[Concentration],
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+    seed: Option<&RecExpr<MixLang>>,
+) -> Result<Vec<Result<Sequence, MixerGenerationError>>, MixerGenerationError> {
+    let (runner, roots) = run_saturation(target_concentrations, input_space, config, seed)?;
+    let coverage = ConcentrationCoverage::from_egraph(&runner.egraph);
+    let rejected = runner.egraph.analysis.rejected();
+    let report = SaturationReport::from_runner(&runner);
+
+    let sequences = target_concentrations
         .iter()
-        .map(|fluid| fluid.concentration())
-        .cloned()
-        .collect::<HashSet<_>>();
+        .zip(roots)
+        .map(|(target_concentration, root)| {
+            // Egraph ids might have changed while rebuilding, use the canonical one for extraction.
+            let root = runner.egraph.find(root);
+            let (cost, raw_expr, best_expr) =
+                extract_sequence(&runner, root, target_concentration, input_space, config)?;
+            let target_class_dot = config.target_class_dump.map(|dump_config| {
+                let cost_function =
+                    cost_function(&runner.egraph, target_concentration, input_space, config);
+                target_class_dot(
+                    &runner.egraph,
+                    root,
+                    cost_function,
+                    config.extraction,
+                    dump_config,
+                )
+            });
+            Ok(Sequence {
+                cost,
+                best_expr,
+                raw_expr,
+                coverage: coverage.clone(),
+                rejected,
+                target_class_dot,
+                optimal: false,
+                report: Some(report.clone()),
+            })
+        })
+        .collect();
+    Ok(sequences)
+}
 
-    let runner: Runner<MixLang, ArithmeticAnalysis, ()> = Runner::new(ArithmeticAnalysis)
+/// Saturates an e-graph holding a root for each target, resuming from the checkpoint of the config
+/// if there is one, and writing one if configured. Returns the runner along with the roots, which
+/// may not be canonical anymore.
+fn run_saturation(
+    target_concentrations: &[Concentration],
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+    seed: Option<&RecExpr<MixLang>>,
+) -> Result<(MixRunner, Vec<Id>), MixerGenerationError> {
+    let rules = rewrite_rules(config)?;
+    let time_limit = config.time_limit;
+    let show_report = config.show_report;
+    let (mut initial_egraph, prior_iterations, prior_saturation_seconds) = match &config.resume_from
+    {
+        Some(checkpoint_path) => {
+            let checkpoint = Checkpoint::load(checkpoint_path)?;
+            if show_report {
+                writeln!(
+                        config.log,
+                        "Resuming from checkpoint {:?}: reusing {} iterations and {:.2} seconds of prior saturation ({} e-nodes, {} e-classes).",
+                        checkpoint_path,
+                        checkpoint.iterations,
+                        checkpoint.saturation_seconds,
+                        checkpoint.egraph.total_number_of_nodes(),
+                        checkpoint.egraph.number_of_classes()
+                    );
+            }
+            (
+                checkpoint.egraph,
+                checkpoint.iterations,
+                checkpoint.saturation_seconds,
+            )
+        }
+        None => (EGraph::new(ArithmeticAnalysis::default()), 0, 0.0),
+    };
+    let roots = target_concentrations
+        .iter()
+        .map(|target_concentration| initial_egraph.add_expr(&target_node(target_concentration)))
+        .collect::<Vec<_>>();
+    if let (Some(seed), Some(root)) = (seed, roots.first()) {
+        let seed_root = initial_egraph.add_expr(seed);
+        initial_egraph.union(*root, seed_root);
+        initial_egraph.rebuild();
+    }
+    let started = Instant::now();
+    if let Some(depth) = config.expansion_depth {
+        let mixes = expand_input_space(
+            &mut initial_egraph,
+            input_space,
+            target_concentrations,
+            depth,
+            config,
+        )?;
+        if show_report {
+            writeln!(
+                config.log,
+                "Expanded the input space by {mixes} mixes of up to {depth} levels."
+            );
+        }
+    }
+
+    let mut runner: MixRunner = Runner::new(ArithmeticAnalysis::default())
         .with_egraph(initial_egraph)
-        .with_node_limit(10000000000000000)
-        .with_iter_limit(100000)
-        .with_time_limit(Duration::from_secs(time_limit))
-        .run(&generate_rewrite_rules());
-
-    runner.print_report();
-
-    let extractor = Extractor::new(
-        &runner.egraph,
-        OpCost::new(target_concentration, input_space, &runner.egraph),
-    );
-
-    let (cost, best_expr) = extractor.find_best(target);
-    let best_expr_normalized_str = normalize_expr_by_min_volume(&best_expr);
-    let best_expr_normalized = best_expr_normalized_str
-        .parse::<RecExpr<MixLang>>()
-        .map_err(|e| MixerGenerationError::SaturationError(e.to_string()))?;
-
-    println!("{best_expr_normalized} cost {cost}");
-    let sequence = Sequence {
-        cost,
-        best_expr: best_expr_normalized,
+        .with_node_limit(config.node_limit.unwrap_or(DEFAULT_NODE_LIMIT))
+        .with_iter_limit(config.iter_limit.unwrap_or(DEFAULT_ITER_LIMIT))
+        .with_time_limit(Duration::from_secs(time_limit).saturating_sub(started.elapsed()))
+        // Compression matches every chain of mixes, most of which fail its condition. Banning
+        // it for the sheer number of matches would leave the useful ones unapplied.
+        .with_scheduler(BackoffScheduler::default().do_not_ban("mixer-compress"));
+    if let Some(checkpoint_config) = &config.checkpoint {
+        checkpoint_config.set_saturating(true);
+        runner = runner.with_hook(checkpoint_hook(
+            checkpoint_config.clone(),
+            prior_iterations,
+            prior_saturation_seconds,
+        ));
+    }
+    if let Some(cancellation) = &config.cancellation {
+        let cancellation = cancellation.clone();
+        runner = runner.with_hook(move |_| match cancellation.load(Ordering::SeqCst) {
+            true => Err("cancelled".to_string()),
+            false => Ok(()),
+        });
+    }
+    if let Some(callback) = &config.progress {
+        runner = runner.with_hook(progress_hook(callback.clone(), prior_iterations));
+    }
+    let runner = runner.run(&rules);
+    // Hooks only report the iterations before the last one.
+    if let (Some(callback), Some(progress)) =
+        (&config.progress, last_progress(&runner, prior_iterations))
+    {
+        callback.call(&progress);
+    }
+    if config
+        .cancellation
+        .as_ref()
+        .is_some_and(|cancellation| cancellation.load(Ordering::SeqCst))
+    {
+        return Err(MixerGenerationError::Cancelled);
+    }
+
+    if let Some(checkpoint_config) = &config.checkpoint {
+        let written =
+            CheckpointRef::from_runner(&runner, prior_iterations, prior_saturation_seconds)
+                .write(&checkpoint_config.path);
+        checkpoint_config.set_saturating(false);
+        written?;
+        if matches!(&runner.stop_reason, Some(StopReason::Other(reason)) if reason == INTERRUPTED) {
+            return Err(MixerGenerationError::Interrupted(
+                checkpoint_config.path.clone(),
+            ));
+        }
+    }
+    Ok((runner, roots))
+}
+
+/// Cost function extraction for the target uses.
+fn cost_function<'a>(
+    egraph: &'a EGraph<MixLang, ArithmeticAnalysis>,
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+) -> OpCost<'a> {
+    // Roots are target nodes, so designs produce the target volume.
+    let output_volume = TARGET_VOLUME.into();
+    OpCost::new(target_concentration.clone(), input_space.clone(), egraph)
+        .with_mix_time(config.objective, config.mix_time_model, output_volume)
+        .with_capabilities(config.capabilities.clone())
+        .with_proximity(config.proximity_cost)
+}
+
+/// Extracts the best expression of the root along with its cost, returning it both as extracted
+/// and normalized by its smallest volume.
+fn extract_sequence(
+    runner: &MixRunner,
+    root: Id,
+    target_concentration: &Concentration,
+    input_space: &InputSpace,
+    config: &SaturationConfig,
+) -> Result<(f64, RecExpr<MixLang>, RecExpr<MixLang>), MixerGenerationError> {
+    let cost_function = cost_function(&runner.egraph, target_concentration, input_space, config);
+    let best = if !config.required_intermediates.is_empty() {
+        let extractor = RequiredIntermediatesExtractor::new(
+            &runner.egraph,
+            cost_function,
+            config.extraction,
+            &config.required_intermediates,
+            root,
+        );
+        let best = extractor.find_best(root);
+        // Designs for the target exist, just none of them produces the required intermediates.
+        if best.is_none() && extractor.is_extractable(root) {
+            return Err(MixerGenerationError::RequiredIntermediatesUnreachable(
+                config.required_intermediates.clone(),
+            ));
+        }
+        best
+    } else {
+        let extractor = CheapestExtractor::new(&runner.egraph, cost_function, config.extraction);
+        let best = extractor.find_best(root);
+        match config.extraction_root {
+            ExtractionRoot::Target => best,
+            ExtractionRoot::BestFluid { tolerance } => {
+                fluid_classes_near(&runner.egraph, root, target_concentration, tolerance)
+                    .into_iter()
+                    .filter_map(|class| extractor.find_best(class))
+                    .filter(|(cost, _)| is_design_cost(*cost))
+                    .fold(best, |best, candidate| match best {
+                        Some(best) if is_design_cost(best.0) && best.0 <= candidate.0 => Some(best),
+                        _ => Some(candidate),
+                    })
+            }
+        }
     };
-    Ok(sequence)
+
+    // If the target fluid is still a leaf of the best expression (or nothing could be extracted
+    // at all) there is no design that could be extracted for the target.
+    let (cost, best_expr) = match best {
+        Some((cost, best_expr)) if is_design_cost(cost) => (cost, best_expr),
+        candidate => {
+            if let (Some((_, candidate)), true) = (candidate, config.show_report) {
+                writeln!(
+                    config.log,
+                    "warning: the cheapest candidate for {target_concentration} still has the \
+                     target as a leaf, it is not a design: {candidate}"
+                );
+            }
+            return match runner.stop_reason {
+                Some(StopReason::TimeLimit(_)) => {
+                    Err(MixerGenerationError::TimeLimitReached(config.time_limit))
+                }
+                Some(StopReason::NodeLimit(_)) => Err(MixerGenerationError::NodeLimitReached(
+                    config.node_limit.unwrap_or(DEFAULT_NODE_LIMIT),
+                )),
+                Some(StopReason::IterationLimit(_)) => {
+                    Err(MixerGenerationError::IterationLimitReached(
+                        config.iter_limit.unwrap_or(DEFAULT_ITER_LIMIT),
+                    ))
+                }
+                _ => Err(MixerGenerationError::TargetUnreachable(
+                    target_concentration.clone(),
+                )),
+            };
+        }
+    };
+    let best_expr_normalized = normalize_expr_by_min_volume(&best_expr)?;
+    Ok((cost, best_expr, best_expr_normalized))
 }
 
 pub struct Sequence {
     pub cost: f64,
+    /// Best expression, normalized by its smallest volume.
     pub best_expr: RecExpr<MixLang>,
+    /// Best expression as extracted from the e-graph.
+    pub raw_expr: RecExpr<MixLang>,
+    /// Concentrations explored by the saturation.
+    pub coverage: ConcentrationCoverage,
+    /// Operations the saturation rejected as their values are undefined.
+    pub rejected: RejectedCandidates,
+    /// E-class of the target and its neighborhood in dot format, if dumping it is configured.
+    pub target_class_dot: Option<String>,
+    /// Whether the design is proven to reach the target with the fewest mixes, which only the
+    /// exhaustive search proves.
+    pub optimal: bool,
+    /// Statistics of the saturation the design was extracted from, `None` for generators which do
+    /// not saturate.
+    pub report: Option<SaturationReport>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{checkpoint::CheckpointConfig, target_node, MixRunner};
+    use crate::{
+        extract::ExtractionRoot, extract_sequence, extracted_expr, generate_rewrite_rules,
+        is_design_cost, normalize_expr_by_min_volume, rewrite_rules, saturate, saturate_many,
+        ArithmeticAnalysis, ArithmeticAnalysisPayload, CostObjective, InputTier, MixLang,
+        MixRewrite, MixTimeModel, OpCost, ProximityCost, RejectedCandidates, SaturationConfig,
+        TARGET_LEAF_COST,
+    };
+    use egg::{rewrite as rw, EGraph, Extractor, RecExpr, Runner};
+    use fluido_types::error::{ExtractedNodeFailure, MixerGenerationError};
+    use fluido_types::fluid::Concentration;
+    use fluido_types::fluid::{Fluid, MixRatio, MixerCapabilities};
+    use fluido_types::input_space::{Input, InputSpace};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn extracted_expressions_are_converted_structurally() {
+        let parse = |expr: &str| expr.parse::<RecExpr<MixLang>>().unwrap();
+        let normalized = normalize_expr_by_min_volume(&parse(
+            "(mix (fluid 0.2 0.5) (mix (fluid 0.0 1.5) (+ 0.05 0.05)))",
+        ))
+        .unwrap();
+        assert_eq!(
+            normalized.to_string(),
+            "(mix (fluid 0.2 1.0) (mix (fluid 0.0 3.0) (+ 0.05 0.05)))"
+        );
+        // Arithmetic nodes are kept, but cannot be part of an `Expr`.
+        let err = extracted_expr(&normalized).unwrap_err();
+        assert_eq!(err.path.to_string(), "root.rhs.rhs");
+        assert_eq!(err.node, "(+ 0.05 0.05)");
+        assert_eq!(err.reason, ExtractedNodeFailure::NotAMixOrFluid);
+        let err =
+            extracted_expr(&parse("(mix (fluid 0.2 1.0) (fluid (+ 0.1 0.1) 1.0))")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Extracted expression `(mix (fluid 0.2 1.0) (fluid (+ 0.1 0.1) 1.0))` cannot be converted, node `(fluid (+ 0.1 0.1) 1.0)` at root.rhs is neither a mix nor a fluid."
+        );
+
+        let err =
+            normalize_expr_by_min_volume(&parse("(mix (fluid 0.2 0.5) (fluid 0.0 (* 2.0 0.5)))"))
+                .unwrap_err();
+        assert_eq!(err.path.to_string(), "root.rhs");
+        assert_eq!(err.reason, ExtractedNodeFailure::NonNumericVolume);
+        let err = normalize_expr_by_min_volume(&parse(
+            "(mix (fluid 0.2 0.0001) (fluid 0.0 900000000000000.0))",
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err.reason,
+            ExtractedNodeFailure::UnnormalizableVolume(_)
+        ));
+
+        let expr = extracted_expr(&parse("(mix (fluid 0.2 1.0) (fluid 0.0 3.0))")).unwrap();
+        assert_eq!(expr.to_string(), "(mix (fluid 0.2 1.0) (fluid 0.0 3.0))");
+    }
+
+    #[test]
+    fn merged_classes_keep_their_data() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let mut add = |expr: &str| egraph.add_expr(&expr.parse::<RecExpr<MixLang>>().unwrap());
+        let fluid = add("(fluid 0.4 1.0)");
+        let undefined = add("(fluid 0.4 (/ 1.0 0.0))");
+        let other_fluid = add("(fluid 0.2 1.0)");
+        let other_undefined = add("(fluid 0.2 (/ 2.0 0.0))");
+        // Whichever side lacks the data, the merged class has it.
+        egraph.union(undefined, fluid);
+        egraph.union(other_fluid, other_undefined);
+        egraph.rebuild();
+
+        assert_eq!(
+            egraph[fluid].data,
+            ArithmeticAnalysisPayload::Fluid(Fluid::new(0.4.into(), 1.0.into()))
+        );
+        assert_eq!(
+            egraph[other_fluid].data,
+            ArithmeticAnalysisPayload::Fluid(Fluid::new(0.2.into(), 1.0.into()))
+        );
+    }
+
+    #[test]
+    fn undefined_values_are_rejected() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let mut add = |expr: &str| egraph.add_expr(&expr.parse::<RecExpr<MixLang>>().unwrap());
+        let split = add("(fluid 0.4 (/ 1.0 0.0))");
+        let nested = add("(+ (/ 1.0 0.0) 1.0)");
+        let empty = add("(mix (fluid 0.4 0.0) (fluid 0.2 0.0))");
+        add("(/ 2.0 0.0)");
+        egraph.rebuild();
+
+        assert_eq!(egraph[split].data, ArithmeticAnalysisPayload::None);
+        assert_eq!(egraph[nested].data, ArithmeticAnalysisPayload::None);
+        assert_eq!(egraph[empty].data, ArithmeticAnalysisPayload::None);
+        assert_eq!(
+            egraph.analysis.rejected(),
+            RejectedCandidates {
+                division_by_zero: 2,
+                empty_mixes: 1,
+            }
+        );
+
+        // A rule dividing by zero leaves the search intact.
+        let divide_by_zero: MixRewrite = rw!("divide-by-zero";
+            "(fluid ?a ?b)" => "(fluid ?a (/ ?b 0.0))");
+        let config = SaturationConfig {
+            time_limit: 1,
+            custom_rules: vec![divide_by_zero],
+            ..Default::default()
+        };
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let sequence = saturate(0.2.into(), &input_space, &config).unwrap();
+        assert!(!sequence.best_expr.to_string().contains('/'));
+        assert!(sequence.rejected.division_by_zero > 0);
+    }
+
+    #[test]
+    fn compress_rule_is_volume_generic() {
+        let rules = generate_rewrite_rules(&SaturationConfig::default())
+            .into_iter()
+            .filter(|rule| rule.name.as_str() == "mixer-compress")
+            .collect::<Vec<_>>();
+        // Diluting twice with different volumes of water.
+        let chained = "(mix (mix (fluid 0.04 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let compressed = "(mix (fluid 0.04 1.0) (fluid 0.0 3.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let runner = Runner::default()
+            .with_egraph(EGraph::new(ArithmeticAnalysis::default()))
+            .with_expr(&chained)
+            .with_iter_limit(2)
+            .run(&rules);
+        assert!(!runner.egraph.equivs(&chained, &compressed).is_empty());
+    }
+
+    #[test]
+    fn compression_respects_max_intermediate_volume() {
+        let chained = "(mix (mix (fluid 0.04 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let compressed = "(mix (fluid 0.04 1.0) (fluid 0.0 3.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let compresses_with = |max_volume: f64| {
+            let config = SaturationConfig {
+                max_intermediate_volume: Some(max_volume.into()),
+                ..Default::default()
+            };
+            let rules = generate_rewrite_rules(&config)
+                .into_iter()
+                .filter(|rule| rule.name.as_str() == "mixer-compress")
+                .collect::<Vec<_>>();
+            let runner = Runner::default()
+                .with_egraph(EGraph::new(ArithmeticAnalysis::default()))
+                .with_expr(&chained)
+                .with_iter_limit(2)
+                .run(&rules);
+            !runner.egraph.equivs(&chained, &compressed).is_empty()
+        };
+        // The compressed mix is 4 units, in units of its smaller side.
+        assert!(!compresses_with(3.0));
+        assert!(compresses_with(4.0));
+    }
+
+    #[test]
+    fn roots_are_extracted_from_a_shared_saturation() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 1,
+            ..Default::default()
+        };
+        let sequences = saturate_many(&[0.2.into(), 0.1.into()], &input_space, &config).unwrap();
+        let best_exprs = sequences
+            .into_iter()
+            .map(|sequence| sequence.unwrap().best_expr.to_string())
+            .collect::<Vec<_>>();
+        // Both targets are a single dilution of `0.4`, with 1 and 3 units of water.
+        assert_eq!(best_exprs.len(), 2);
+        assert!(best_exprs
+            .iter()
+            .all(|expr| expr.matches("mix").count() == 1));
+        assert!(best_exprs[0].contains("(fluid 0.0 1.0)"));
+        assert!(best_exprs[1].contains("(fluid 0.0 3.0)"));
+    }
+
+    #[test]
+    fn staged_search_refines_the_coarse_design() {
+        let config = SaturationConfig {
+            concentration_steps: vec![0.001],
+            ..Default::default()
+        };
+        let rule_names = generate_rewrite_rules(&config)
+            .iter()
+            .map(|rule| rule.name.to_string())
+            .collect::<Vec<_>>();
+        assert!(rule_names.contains(&"diff-mixers-l-0.001".to_string()));
+        assert!(!rule_names.contains(&"diff-mixers-l-0.01".to_string()));
+
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 2,
+            stages: vec![0.1, 0.01],
+            ..Default::default()
+        };
+        let sequence = saturate_many(&[0.3.into()], &input_space, &config)
+            .unwrap()
+            .remove(0)
+            .unwrap();
+        // A single dilution of `0.4` with a third of water, which the coarse stage finds already.
+        let best_expr = sequence.best_expr.to_string();
+        assert_eq!(best_expr.matches("mix").count(), 1);
+        assert!(best_expr.contains("(fluid 0.4 3.0)"));
+    }
+
+    #[test]
+    fn cancelled_search_stops_saturating() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let cancellation = Arc::new(AtomicBool::new(true));
+        let config = SaturationConfig {
+            time_limit: 60,
+            cancellation: Some(cancellation.clone()),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let result = saturate(0.3.into(), &input_space, &config);
+        assert!(matches!(result, Err(MixerGenerationError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn interrupted_search_is_not_reported_unreachable() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let path = std::env::temp_dir().join("fluido_interrupted_search.json");
+        let checkpoint = CheckpointConfig::new(path.clone(), Duration::from_secs(60));
+        checkpoint.interrupted.store(true, Ordering::SeqCst);
+        let config = SaturationConfig {
+            time_limit: 60,
+            checkpoint: Some(checkpoint.clone()),
+            ..Default::default()
+        };
+        let result = saturate(0.3.into(), &input_space, &config);
+        assert!(matches!(result, Err(MixerGenerationError::Interrupted(p)) if p == path));
+        // The final checkpoint is written, and interrupting afterwards finds no saturation.
+        assert!(path.exists());
+        assert!(!checkpoint.interrupt());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saturation_stops_at_its_limits() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 60,
+            iter_limit: Some(1),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        // A single iteration only expands the target, it never reaches a design.
+        let result = saturate(0.0137.into(), &input_space, &config);
+        assert!(matches!(
+            result,
+            Err(MixerGenerationError::IterationLimitReached(1))
+        ));
+        assert!(started.elapsed() < Duration::from_secs(60));
+
+        let config = SaturationConfig {
+            time_limit: 60,
+            node_limit: Some(5),
+            ..Default::default()
+        };
+        // Expanding the target already adds more e-nodes.
+        let result = saturate(0.0137.into(), &input_space, &config);
+        assert!(matches!(
+            result,
+            Err(MixerGenerationError::NodeLimitReached(5))
+        ));
+    }
+
+    #[test]
+    fn mix_time_is_relative_to_the_target_volume() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 1,
+            objective: CostObjective::MixTime,
+            mix_time_model: MixTimeModel::new(10.0, 2.0),
+            ..Default::default()
+        };
+        let sequence = saturate_many(&[0.2.into()], &input_space, &config)
+            .unwrap()
+            .remove(0)
+            .unwrap();
+        // A single mix of two halves of the target volume.
+        assert_eq!(sequence.best_expr.to_string().matches("mix").count(), 1);
+        assert_eq!(sequence.cost, 12.0);
+    }
+
+    #[test]
+    fn custom_rules_run_after_builtin_ones() {
+        let swap: MixRewrite = rw!("swap-mix"; "(mix ?a ?b)" => "(mix ?b ?a)");
+        let config = SaturationConfig {
+            custom_rules: vec![swap],
+            ..Default::default()
+        };
+        let rules = rewrite_rules(&config).unwrap();
+        assert_eq!(
+            rules.len(),
+            generate_rewrite_rules(&SaturationConfig::default()).len() + 1
+        );
+        assert_eq!(rules.last().unwrap().name.as_str(), "swap-mix");
+
+        let shadowing: MixRewrite = rw!("mixer-assoc"; "(mix ?a ?b)" => "(mix ?b ?a)");
+        let config = SaturationConfig {
+            custom_rules: vec![shadowing],
+            ..Default::default()
+        };
+        assert!(matches!(
+            rewrite_rules(&config),
+            Err(MixerGenerationError::DuplicateRuleName(name)) if name == "mixer-assoc"
+        ));
+    }
+
+    #[test]
+    fn scarce_inputs_are_avoided() {
+        let scarce_design = "(mix (fluid 0.4 1.0) (fluid 0.0 1.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let abundant_design = "(mix (fluid 0.3 1.0) (fluid 0.1 1.0))"
+            .parse::<RecExpr<MixLang>>()
+            .unwrap();
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let root = egraph.add_expr(&scarce_design);
+        let other = egraph.add_expr(&abundant_design);
+        egraph.union(root, other);
+        egraph.rebuild();
+
+        let tiers = [
+            (0.4, InputTier::Scarce),
+            (0.0, InputTier::Abundant),
+            (0.3, InputTier::Limited),
+            (0.1, InputTier::Abundant),
+        ];
+        let input_space = tiers
+            .into_iter()
+            .map(|(concentration, tier)| {
+                Input::new(Fluid::new(concentration.into(), 1.0.into())).with_tier(tier)
+            })
+            .collect::<InputSpace>();
+        let cost_function = OpCost::new(0.2.into(), input_space, &egraph).with_mix_time(
+            Default::default(),
+            Default::default(),
+            2.0,
+        );
+        let (cost, best) = Extractor::new(&egraph, cost_function).find_best(root);
+
+        assert_eq!(best.to_string(), abundant_design.to_string());
+        // A mix, and half of the produced volume drawn from a limited input.
+        assert_eq!(cost, 1.5);
+    }
+
+    #[test]
+    fn waste_prefers_designs_with_larger_fluids() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let root = egraph.add_expr(&"(mix (fluid 0.4 0.25) (fluid 0.0 0.75))".parse().unwrap());
+        for design in [
+            "(mix (fluid 0.2 0.5) (fluid 0.0 0.5))",
+            "(mix (mix (fluid 0.4 0.25) (fluid 0.0 0.25)) (fluid 0.0 0.5))",
+        ] {
+            let other = egraph.add_expr(&design.parse().unwrap());
+            egraph.union(root, other);
+        }
+        egraph.rebuild();
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.2.into(), 0.0.into()]);
+        let cost_function = OpCost::new(0.1.into(), input_space, &egraph).with_mix_time(
+            CostObjective::Waste,
+            Default::default(),
+            1.0,
+        );
+        let (cost, best) = Extractor::new(&egraph, cost_function).find_best(root);
+
+        // Two units of fluid produce the one unit of the target, the others take four.
+        assert_eq!(best.to_string(), "(mix (fluid 0.2 0.5) (fluid 0.0 0.5))");
+        assert!((cost - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn volume_weighted_proximity_prefers_less_of_missing_concentrations() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        // Needs half of the produced volume at `0.2`, which is `0.2` off the inputs.
+        let root = egraph.add_expr(&"(mix (fluid 0.2 0.5) (fluid 0.0 0.5))".parse().unwrap());
+        // Needs a quarter at `0.25` and a quarter at `0.15`, each `0.15` off the inputs.
+        let other = egraph.add_expr(
+            &"(mix (mix (fluid 0.25 0.25) (fluid 0.15 0.25)) (fluid 0.0 0.5))"
+                .parse()
+                .unwrap(),
+        );
+        egraph.union(root, other);
+        egraph.rebuild();
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let extract = |proximity| {
+            let cost_function =
+                OpCost::new(0.1.into(), input_space.clone(), &egraph).with_proximity(proximity);
+            Extractor::new(&egraph, cost_function)
+                .find_best(root)
+                .1
+                .to_string()
+        };
+
+        // The first one is less off in total, but it needs more of its missing concentration.
+        assert_eq!(
+            extract(ProximityCost::Flat),
+            "(mix (fluid 0.2 0.5) (fluid 0.0 0.5))"
+        );
+        assert_eq!(
+            extract(ProximityCost::VolumeWeighted),
+            "(mix (mix (fluid 0.25 0.25) (fluid 0.15 0.25)) (fluid 0.0 0.5))"
+        );
+    }
+
+    #[test]
+    fn designs_mix_in_the_supported_ratios() {
+        let input_space = InputSpace::from_concentrations([0.9.into(), 0.0.into()]);
+        let saturate_with = |ratios: &[&str]| {
+            let config = SaturationConfig {
+                time_limit: 5,
+                iter_limit: Some(4),
+                concentration_steps: vec![0.3],
+                capabilities: Some(MixerCapabilities::new(
+                    ratios
+                        .iter()
+                        .map(|ratio| ratio.parse::<MixRatio>().unwrap()),
+                )),
+                ..Default::default()
+            };
+            saturate(0.3.into(), &input_space, &config)
+                .unwrap()
+                .best_expr
+                .to_string()
+        };
+
+        // A third of the input is only reachable by splitting fluids in thirds.
+        assert_eq!(
+            saturate_with(&["1:2"]),
+            "(mix (fluid 0.0 2.0) (fluid 0.9 1.0))"
+        );
+        // Halving alone leaves a leaf of a concentration none of the inputs has.
+        assert_eq!(
+            saturate_with(&["1:1"]),
+            "(mix (fluid 0.0 1.0) (fluid 0.6 1.0))"
+        );
+    }
+
+    #[test]
+    fn best_fluid_extraction_finds_designs_apart_from_the_target_node() {
+        let target: Concentration = 0.2.into();
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let root = egraph.add_expr(&target_node(&target));
+        // Produces the target, but was never unified with the target node.
+        egraph.add_expr(&"(mix (fluid 0.4 1.0) (fluid 0.0 1.0))".parse().unwrap());
+        egraph.add_expr(&"(mix (fluid 0.4 1.0) (fluid 0.0 3.0))".parse().unwrap());
+        egraph.rebuild();
+        let runner: MixRunner = Runner::new(ArithmeticAnalysis::default())
+            .with_egraph(egraph)
+            .run(&[]);
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let extract = |extraction_root| {
+            let config = SaturationConfig {
+                extraction_root,
+                ..Default::default()
+            };
+            extract_sequence(&runner, root, &target, &input_space, &config)
+                .map(|(_, _, best_expr)| best_expr.to_string())
+        };
+
+        assert!(matches!(
+            extract(ExtractionRoot::Target),
+            Err(MixerGenerationError::TargetUnreachable(_))
+        ));
+        assert_eq!(
+            extract(ExtractionRoot::BestFluid { tolerance: 0.0 }).unwrap(),
+            "(mix (fluid 0.4 1.0) (fluid 0.0 1.0))"
+        );
+    }
+
+    #[test]
+    fn target_leaves_cost_more_than_any_design() {
+        let mut egraph = EGraph::new(ArithmeticAnalysis::default());
+        let mut add = |expr: &str| egraph.add_expr(&expr.parse::<RecExpr<MixLang>>().unwrap());
+        // Both leaves are the target fluid, which is not an input.
+        let target_leaves = add("(mix (fluid 0.2 1.0) (fluid 0.2 1.0))");
+        let design = add("(mix (fluid 0.4 1.0) (fluid 0.3 1.0))");
+        egraph.rebuild();
+
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let extractor = Extractor::new(&egraph, OpCost::new(0.2.into(), input_space, &egraph));
+        let (cost, _) = extractor.find_best(target_leaves);
+        assert_eq!(cost, TARGET_LEAF_COST);
+        assert!(!is_design_cost(cost));
+        // Leaves which are not inputs are penalized, but still part of a design.
+        let (cost, _) = extractor.find_best(design);
+        assert!(is_design_cost(cost));
+        assert!(cost > 100.0);
+    }
 }
@@ -0,0 +1,84 @@
+//! Lower bound on the number of mixes a target needs, known before searching for a design.
+//!
+//! A 1:1 mix produces the mean of its operands, rounded to a multiple of `LimitedFloat::EPSILON`
+//! like every concentration of the e-graph. So the concentrations reachable with mixes nested at
+//! most `d` deep are the inputs and the rounded means of those reachable at depth `d - 1`. A design
+//! reaching the target with 1:1 mixes nests them at least as deep as the first depth the target is
+//! reachable at, and has at least as many mixes as it nests.
+//!
+//! The search only creates 1:1 mixes, mixes of unequal volumes come from compressing mixes of the
+//! same concentration into one. Those count as the 1:1 mixes they replace.
+use fluido_types::{
+    fluid::{Concentration, LimitedFloat},
+    input_space::InputSpace,
+};
+use std::collections::BTreeSet;
+
+/// Rounded mean of two concentrations given in steps of `EPSILON`, as a 1:1 mix computes it.
+fn mean(lhs: i64, rhs: i64) -> i64 {
+    let value = |wrapped| f64::from(LimitedFloat { wrapped });
+    LimitedFloat::from((value(lhs) + value(rhs)) / 2.0).wrapped
+}
+
+/// Minimum number of 1:1 mixes needed to produce the target from the inputs, or `None` if no
+/// number of them can. Leaves match the inputs within the tolerance of the input space, as they do
+/// in the search.
+pub fn equal_mixes_lower_bound(target: &Concentration, input_space: &InputSpace) -> Option<usize> {
+    let inputs = input_space.concentrations();
+    let tolerance = inputs.tolerance() as i64;
+    let mut reachable = inputs
+        .iter()
+        .flat_map(|input| input.wrapped - tolerance..=input.wrapped + tolerance)
+        .collect::<BTreeSet<_>>();
+    // Concentrations first reachable at the current depth, only means involving one of them can be
+    // new at the next depth.
+    let mut added = reachable.iter().copied().collect::<Vec<_>>();
+    for depth in 0.. {
+        if reachable.contains(&target.wrapped) {
+            return Some(depth);
+        }
+        added = added
+            .iter()
+            .flat_map(|lhs| reachable.iter().map(|rhs| mean(*lhs, *rhs)))
+            .filter(|mean| !reachable.contains(mean))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        // Means stay between the smallest and the largest leaf, so this happens eventually.
+        if added.is_empty() {
+            return None;
+        }
+        reachable.extend(&added);
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::equal_mixes_lower_bound;
+    use fluido_types::{fluid::Concentration, input_space::InputSpace};
+
+    #[test]
+    fn lower_bound_follows_dyadic_denominators() {
+        let inputs = |inputs: &[f64]| {
+            InputSpace::from_concentrations(inputs.iter().map(|input| Concentration::from(*input)))
+        };
+        let bound = |target: f64, input_space: &InputSpace| {
+            equal_mixes_lower_bound(&target.into(), input_space)
+        };
+        let stock = inputs(&[0.0, 0.8]);
+        assert_eq!(bound(0.8, &stock), Some(0));
+        // Within the tolerance of an input, the target is a leaf.
+        assert_eq!(bound(0.7999, &stock), Some(0));
+        assert_eq!(bound(0.4, &stock), Some(1));
+        assert_eq!(bound(0.1, &stock), Some(3));
+        assert_eq!(bound(0.3, &stock), Some(3));
+        // 0.05 is 1/16 of the stock.
+        assert_eq!(bound(0.05, &stock), Some(4));
+        // 3/16 of the stock, or the mean of 0.0 and the mean of 0.2 and 0.4.
+        assert_eq!(bound(0.15, &stock), Some(4));
+        assert_eq!(bound(0.15, &inputs(&[0.0, 0.2, 0.4])), Some(2));
+        // Means never leave the range of the inputs.
+        assert_eq!(bound(0.9, &stock), None);
+    }
+}
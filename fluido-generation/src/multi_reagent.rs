@@ -0,0 +1,289 @@
+//! A generator for fluids of several reagents, i.e a buffer along with a dye, mixing the inputs
+//! once in the volume ratio whose reagents are the closest to the target.
+//!
+//! Saturation rewrites a single concentration, so it cannot follow more than one reagent. Instead
+//! every split of a small total volume among the inputs is tried, which finds the exact design for
+//! any target the inputs reach within that volume. The number of splits grows exponentially with
+//! the number of inputs, so input spaces of more than `MULTI_REAGENT_MAX_INPUTS` fluids are
+//! rejected, and the enumeration stops at the time limit of the configuration.
+use crate::{gcd, SaturationConfig};
+use fluido_types::{
+    error::MixerGenerationError,
+    expr::Expr,
+    fluid::{Concentration, Fluid, LimitedFloat},
+    input_space::InputSpace,
+};
+use std::{
+    ops::ControlFlow,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// Largest total volume of the mix, in units of the smallest one of its fluids.
+pub const MULTI_REAGENT_MAX_VOLUME: u64 = 32;
+
+/// Largest number of distinct fluids a fluid of several reagents is mixed from.
+pub const MULTI_REAGENT_MAX_INPUTS: usize = 6;
+
+/// Number of splits tried between checks of the time limit and the cancellation flag.
+const SPLITS_PER_CHECK: u64 = 1 << 12;
+
+/// Mix of the inputs with the smallest total volume whose reagents are each within the tolerance
+/// of the target. Reagents are told apart at the granularity of `LimitedFloat::EPSILON`, so a
+/// tolerance of `0.0` asks for the exact target.
+///
+/// Fails if no mix is within the tolerance, if the input space has more than
+/// `MULTI_REAGENT_MAX_INPUTS` fluids, or once the time limit of the configuration is reached or
+/// the search is cancelled.
+pub fn mix_reagents(
+    target: &[Concentration],
+    input_space: &InputSpace,
+    tolerance: f64,
+    config: &SaturationConfig,
+) -> Result<Expr, MixerGenerationError> {
+    let mut inputs: Vec<Vec<Concentration>> = vec![];
+    for fluid in input_space.fluids() {
+        let concentrations = fluid.concentrations();
+        if !inputs.contains(&concentrations) {
+            inputs.push(concentrations);
+        }
+    }
+    if inputs.is_empty() {
+        return Err(MixerGenerationError::ReagentsUnreachable(target.to_vec()));
+    }
+    if inputs.len() > MULTI_REAGENT_MAX_INPUTS {
+        return Err(MixerGenerationError::TooManyInputs(
+            inputs.len(),
+            MULTI_REAGENT_MAX_INPUTS,
+        ));
+    }
+
+    let reagent_count = inputs
+        .iter()
+        .map(Vec::len)
+        .chain([target.len()])
+        .max()
+        .unwrap_or_default();
+    let component = |concentrations: &[Concentration], reagent: usize| {
+        concentrations
+            .get(reagent)
+            .map_or(0.0, |concentration| f64::from(concentration.clone()))
+    };
+    let target_by_reagent = (0..reagent_count)
+        .map(|reagent| component(target, reagent))
+        .collect::<Vec<_>>();
+    let inputs_by_reagent = inputs
+        .iter()
+        .map(|concentrations| {
+            (0..reagent_count)
+                .map(|reagent| component(concentrations, reagent))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // Largest distance of a reagent of the mix to the target.
+    let error = |volumes: &[u64], total: u64| {
+        (0..reagent_count)
+            .map(|reagent| {
+                let mixed = volumes
+                    .iter()
+                    .zip(&inputs_by_reagent)
+                    .map(|(volume, input)| *volume as f64 * input[reagent])
+                    .sum::<f64>()
+                    / total as f64;
+                (mixed - target_by_reagent[reagent]).abs()
+            })
+            .fold(0.0, f64::max)
+    };
+    let tolerance = tolerance + LimitedFloat::EPSILON / 2.0;
+
+    let deadline = Instant::now() + Duration::from_secs(config.time_limit);
+    let mut stopped = None;
+    let mut splits = 0u64;
+    let mut closest: Option<(f64, Vec<u64>)> = None;
+    for total in 1..=MULTI_REAGENT_MAX_VOLUME {
+        let mut volumes = vec![0; inputs.len()];
+        let _ = for_each_split(&mut volumes, 0, total, &mut |volumes| {
+            splits += 1;
+            if splits.is_multiple_of(SPLITS_PER_CHECK) {
+                if config
+                    .cancellation
+                    .as_ref()
+                    .is_some_and(|cancellation| cancellation.load(Ordering::SeqCst))
+                {
+                    stopped = Some(MixerGenerationError::Cancelled);
+                    return ControlFlow::Break(());
+                }
+                if Instant::now() >= deadline {
+                    stopped = Some(MixerGenerationError::TimeLimitReached(config.time_limit));
+                    return ControlFlow::Break(());
+                }
+            }
+            let error = error(volumes, total);
+            if closest
+                .as_ref()
+                .is_none_or(|(closest_error, _)| error < *closest_error)
+            {
+                closest = Some((error, volumes.to_vec()));
+            }
+            ControlFlow::Continue(())
+        });
+        if let Some(err) = stopped {
+            return Err(err);
+        }
+        if closest
+            .as_ref()
+            .is_some_and(|(closest_error, _)| *closest_error <= tolerance)
+        {
+            break;
+        }
+    }
+    let volumes = match closest {
+        Some((error, volumes)) if error <= tolerance => volumes,
+        _ => return Err(MixerGenerationError::ReagentsUnreachable(target.to_vec())),
+    };
+
+    let divisor = volumes.iter().copied().fold(0, gcd);
+    let mut fluids = volumes
+        .iter()
+        .zip(inputs)
+        .filter(|(volume, _)| **volume > 0)
+        .map(|(volume, concentrations)| {
+            let volume = (volume / divisor) as f64;
+            Expr::Fluid(Fluid::with_reagents(concentrations, volume.into()))
+        });
+    let first = fluids.next().expect("the total volume is not zero");
+    let expr = match fluids.next() {
+        Some(second) => fluids.fold(Expr::Mix(Box::new(first), Box::new(second)), |lhs, rhs| {
+            Expr::Mix(Box::new(lhs), Box::new(rhs))
+        }),
+        // A single input is mixed with itself.
+        None => Expr::Mix(Box::new(first.clone()), Box::new(first)),
+    };
+    Ok(expr)
+}
+
+/// Calls `f` with each split of `remaining` among the volumes from `ix` on, until it breaks.
+fn for_each_split(
+    volumes: &mut [u64],
+    ix: usize,
+    remaining: u64,
+    f: &mut impl FnMut(&[u64]) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    if ix + 1 == volumes.len() {
+        volumes[ix] = remaining;
+        return f(volumes);
+    }
+    for volume in 0..=remaining {
+        volumes[ix] = volume;
+        for_each_split(volumes, ix + 1, remaining - volume, f)?;
+    }
+    ControlFlow::Continue(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mix_reagents, MULTI_REAGENT_MAX_INPUTS};
+    use crate::SaturationConfig;
+    use fluido_types::{
+        error::MixerGenerationError,
+        fluid::{Concentration, Fluid},
+        input_space::InputSpace,
+    };
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    fn reagents(concentrations: &[f64]) -> Vec<Concentration> {
+        concentrations.iter().map(|c| (*c).into()).collect()
+    }
+
+    #[test]
+    fn every_reagent_is_mixed_towards_the_target() {
+        let input_space = InputSpace::from_iter([
+            Fluid::with_reagents(reagents(&[0.4]), 1.0.into()),
+            Fluid::with_reagents(reagents(&[0.0, 0.6]), 1.0.into()),
+            Fluid::new(0.0.into(), 1.0.into()),
+        ]);
+        let expr = mix_reagents(
+            &reagents(&[0.1, 0.3]),
+            &input_space,
+            0.0,
+            &SaturationConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "(mix (mix (fluid 0.4 1.0) (fluid [0.0 0.6] 2.0)) (fluid 0.0 1.0))"
+        );
+        assert_eq!(
+            Fluid::try_from(&expr).unwrap().concentrations(),
+            reagents(&[0.1, 0.3])
+        );
+
+        // A single input is mixed with itself.
+        let expr = mix_reagents(
+            &reagents(&[0.4]),
+            &input_space,
+            0.0,
+            &SaturationConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(expr.to_string(), "(mix (fluid 0.4 1.0) (fluid 0.4 1.0))");
+
+        assert!(matches!(
+            mix_reagents(
+                &reagents(&[0.1]),
+                &InputSpace::default(),
+                0.0,
+                &SaturationConfig::default()
+            ),
+            Err(MixerGenerationError::ReagentsUnreachable(_))
+        ));
+    }
+
+    #[test]
+    fn targets_out_of_reach_are_rejected() {
+        let input_space = InputSpace::from_iter([
+            Fluid::with_reagents(reagents(&[0.4]), 1.0.into()),
+            Fluid::with_reagents(reagents(&[0.0, 0.6]), 1.0.into()),
+        ]);
+        // No mix of the inputs has more of either reagent than they do.
+        let config = SaturationConfig::default();
+        assert!(matches!(
+            mix_reagents(&reagents(&[0.3, 0.3]), &input_space, 0.0, &config),
+            Err(MixerGenerationError::ReagentsUnreachable(_))
+        ));
+        // Unless it is close enough.
+        assert_eq!(
+            mix_reagents(&reagents(&[0.25, 0.2]), &input_space, 0.05, &config)
+                .unwrap()
+                .to_string(),
+            "(mix (fluid 0.4 2.0) (fluid [0.0 0.6] 1.0))"
+        );
+
+        let input_space = InputSpace::from_concentrations(
+            (0..=MULTI_REAGENT_MAX_INPUTS).map(|ix| (ix as f64 / 10.0).into()),
+        );
+        assert!(matches!(
+            mix_reagents(&reagents(&[0.05]), &input_space, 0.0, &config),
+            Err(MixerGenerationError::TooManyInputs(
+                7,
+                MULTI_REAGENT_MAX_INPUTS
+            ))
+        ));
+    }
+
+    #[test]
+    fn cancelled_enumeration_stops() {
+        let input_space = InputSpace::from_concentrations(
+            (0..MULTI_REAGENT_MAX_INPUTS).map(|ix| (ix as f64 / 10.0).into()),
+        );
+        let config = SaturationConfig {
+            time_limit: 60,
+            cancellation: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            mix_reagents(&reagents(&[0.01234]), &input_space, 0.0, &config),
+            Err(MixerGenerationError::Cancelled)
+        ));
+    }
+}
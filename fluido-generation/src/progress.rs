@@ -0,0 +1,138 @@
+//! Progress of a saturation, reported after each of its iterations.
+//!
+//! The number of distinct concentrations in the e-graph grows as the search reaches new parts of
+//! the mixer space, whereas most new e-nodes are rewrites of mixes it has already found. It is the
+//! better measure of how far a search got.
+use crate::{ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang, MixRunner};
+use egg::{EGraph, IterationData, Runner};
+use serde::Serialize;
+use std::{collections::HashSet, fmt::Debug, sync::Arc};
+
+/// Data the runner records for each iteration, once the e-graph is rebuilt.
+#[derive(Debug, Clone, Copy)]
+pub struct IterationCoverage {
+    pub nodes: usize,
+    pub classes: usize,
+    /// Number of distinct fluid concentrations in the e-graph.
+    pub distinct_concentrations: usize,
+}
+
+impl IterationData<MixLang, ArithmeticAnalysis> for IterationCoverage {
+    fn make(runner: &Runner<MixLang, ArithmeticAnalysis, Self>) -> Self {
+        Self {
+            nodes: runner.egraph.total_number_of_nodes(),
+            classes: runner.egraph.number_of_classes(),
+            distinct_concentrations: distinct_concentrations(&runner.egraph),
+        }
+    }
+}
+
+/// State of a saturation after one of its iterations.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SaturationProgress {
+    /// Number of the iteration, counting the iterations of the checkpoint resumed from.
+    pub iteration: usize,
+    /// Seconds spent saturating so far, excluding the run resumed from.
+    pub elapsed_seconds: f64,
+    pub nodes: usize,
+    pub classes: usize,
+    pub distinct_concentrations: usize,
+}
+
+/// Called with the progress of a saturation after each of its iterations. Clones call the same
+/// function.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&SaturationProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&SaturationProgress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    pub(crate) fn call(&self, progress: &SaturationProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// Number of distinct fluid concentrations in the e-graph.
+pub(crate) fn distinct_concentrations(egraph: &EGraph<MixLang, ArithmeticAnalysis>) -> usize {
+    egraph
+        .classes()
+        .filter_map(|class| match &class.data {
+            ArithmeticAnalysisPayload::Fluid(fluid) => Some(fluid.concentration()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Progress after the last iteration of the runner, if it ran any.
+pub(crate) fn last_progress(
+    runner: &MixRunner,
+    prior_iterations: usize,
+) -> Option<SaturationProgress> {
+    let last = runner.iterations.last()?;
+    Some(SaturationProgress {
+        iteration: prior_iterations + runner.iterations.len(),
+        elapsed_seconds: runner.iterations.iter().map(|iter| iter.total_time).sum(),
+        nodes: last.data.nodes,
+        classes: last.data.classes,
+        distinct_concentrations: last.data.distinct_concentrations,
+    })
+}
+
+/// Returns a runner hook which reports the progress of the previous iteration. Hooks run before
+/// each iteration, so the progress of the last one is left to be reported once the runner stops.
+pub(crate) fn progress_hook(
+    callback: ProgressCallback,
+    prior_iterations: usize,
+) -> impl FnMut(&mut MixRunner) -> Result<(), String> + 'static {
+    move |runner| {
+        if let Some(progress) = last_progress(runner, prior_iterations) {
+            callback.call(&progress);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgressCallback, SaturationProgress};
+    use crate::{saturate, SaturationConfig};
+    use fluido_types::input_space::InputSpace;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn progress_is_reported_after_each_iteration() {
+        let reported: Arc<Mutex<Vec<SaturationProgress>>> = Arc::default();
+        let progress = reported.clone();
+        let config = SaturationConfig {
+            time_limit: 1,
+            progress: Some(ProgressCallback::new(move |latest| {
+                progress.lock().unwrap().push(latest.clone())
+            })),
+            ..Default::default()
+        };
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let sequence = saturate(0.2.into(), &input_space, &config).unwrap();
+
+        let reported = reported.lock().unwrap();
+        assert!(!reported.is_empty());
+        for (ix, progress) in reported.iter().enumerate() {
+            assert_eq!(progress.iteration, ix + 1);
+        }
+        // Classes of distinct concentrations are never merged.
+        assert!(reported
+            .windows(2)
+            .all(|pair| pair[0].distinct_concentrations <= pair[1].distinct_concentrations));
+        let last = reported.last().unwrap();
+        assert_eq!(last.distinct_concentrations, sequence.coverage.distinct);
+        assert!(last.nodes >= last.classes);
+    }
+}
@@ -0,0 +1,169 @@
+//! Statistics of a saturation, returned along with the sequences extracted from it. Generation
+//! never prints them, callers decide whether and where to.
+use crate::MixRunner;
+use egg::StopReason;
+use fluido_types::precision::Precision;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Why a saturation stopped.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum SaturationStopReason {
+    /// An iteration left the e-graph unchanged, so the mixer space is fully explored.
+    Saturated,
+    /// Stopped at the iteration limit, carrying the limit.
+    IterationLimit(usize),
+    /// Stopped at the node limit, carrying the number of e-nodes.
+    NodeLimit(usize),
+    /// Stopped at the time limit, carrying the seconds spent.
+    TimeLimit(f64),
+    /// Stopped by a hook, i.e cancelled or failing to write a checkpoint.
+    Other(String),
+}
+
+impl From<&StopReason> for SaturationStopReason {
+    fn from(stop_reason: &StopReason) -> Self {
+        match stop_reason {
+            StopReason::Saturated => Self::Saturated,
+            StopReason::IterationLimit(iterations) => Self::IterationLimit(*iterations),
+            StopReason::NodeLimit(nodes) => Self::NodeLimit(*nodes),
+            StopReason::TimeLimit(seconds) => Self::TimeLimit(*seconds),
+            StopReason::Other(reason) => Self::Other(reason.clone()),
+        }
+    }
+}
+
+impl SaturationStopReason {
+    /// Human readable reason, with the seconds spent displayed with the precision.
+    pub fn report_with_precision(&self, precision: &Precision) -> String {
+        match self {
+            Self::Saturated => "saturated".to_string(),
+            Self::IterationLimit(iterations) => format!("iteration limit of {iterations}"),
+            Self::NodeLimit(nodes) => format!("node limit, at {nodes} e-nodes"),
+            Self::TimeLimit(seconds) => {
+                format!("time limit, after {} seconds", precision.format(*seconds))
+            }
+            Self::Other(reason) => reason.clone(),
+        }
+    }
+}
+
+impl Display for SaturationStopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report_with_precision(&Precision::default()))
+    }
+}
+
+/// Statistics of a single saturation run. Runs resumed from a checkpoint only count their own
+/// iterations and time.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SaturationReport {
+    pub iterations: usize,
+    /// Why the saturation stopped, if it ran at all.
+    pub stop_reason: Option<SaturationStopReason>,
+    pub nodes: usize,
+    pub classes: usize,
+    pub total_seconds: f64,
+    pub search_seconds: f64,
+    pub apply_seconds: f64,
+    pub rebuild_seconds: f64,
+    /// Distinct concentrations in the e-graph after each iteration.
+    pub distinct_concentrations: Vec<usize>,
+}
+
+impl SaturationReport {
+    pub(crate) fn from_runner(runner: &MixRunner) -> Self {
+        let report = runner.report();
+        Self {
+            iterations: report.iterations,
+            stop_reason: runner.stop_reason.as_ref().map(SaturationStopReason::from),
+            nodes: report.egraph_nodes,
+            classes: report.egraph_classes,
+            total_seconds: report.total_time,
+            search_seconds: report.search_time,
+            apply_seconds: report.apply_time,
+            rebuild_seconds: report.rebuild_time,
+            distinct_concentrations: runner
+                .iterations
+                .iter()
+                .map(|iter| iter.data.distinct_concentrations)
+                .collect(),
+        }
+    }
+
+    /// Human readable report, with its times displayed with the precision.
+    pub fn report_with_precision(&self, precision: &Precision) -> String {
+        // Share of the total time, zero for runs without iterations.
+        let share = |seconds: f64| match self.total_seconds > 0.0 {
+            true => seconds / self.total_seconds,
+            false => 0.0,
+        };
+        let mut report = "Saturation report\n=================\n".to_string();
+        if let Some(stop_reason) = &self.stop_reason {
+            report += &format!(
+                "  Stop reason: {}\n",
+                stop_reason.report_with_precision(precision)
+            );
+        }
+        report += &format!("  Iterations: {}\n", self.iterations);
+        report += &format!(
+            "  E-graph size: {} nodes, {} classes\n",
+            self.nodes, self.classes
+        );
+        report += &format!("  Total time: {}\n", precision.format(self.total_seconds));
+        for (name, seconds) in [
+            ("Search: ", self.search_seconds),
+            ("Apply:  ", self.apply_seconds),
+            ("Rebuild:", self.rebuild_seconds),
+        ] {
+            report += &format!(
+                "    {name} ({:.2}) {}\n",
+                share(seconds),
+                precision.format(seconds)
+            );
+        }
+        let distinct_concentrations = self
+            .distinct_concentrations
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        report + &format!("  Distinct concentrations by iteration: {distinct_concentrations}")
+    }
+}
+
+impl Display for SaturationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report_with_precision(&Precision::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SaturationStopReason;
+    use crate::{saturate, SaturationConfig};
+    use fluido_types::input_space::InputSpace;
+
+    #[test]
+    fn saturations_report_their_statistics() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 1,
+            iter_limit: Some(3),
+            ..Default::default()
+        };
+        let sequence = saturate(0.2.into(), &input_space, &config).unwrap();
+        let report = sequence.report.unwrap();
+        assert!(report.iterations <= 3);
+        assert_eq!(report.distinct_concentrations.len(), report.iterations);
+        assert!(report.nodes > 0 && report.classes > 0);
+        assert!(matches!(
+            report.stop_reason,
+            Some(SaturationStopReason::Saturated | SaturationStopReason::IterationLimit(3))
+        ));
+
+        let printed = report.to_string();
+        assert!(printed.starts_with("Saturation report\n"));
+        assert!(printed.contains(&format!("  Iterations: {}\n", report.iterations)));
+    }
+}
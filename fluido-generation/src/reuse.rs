@@ -0,0 +1,150 @@
+//! Sub-designs reused across the designs of a batch, i.e the intermediates worth preparing once as
+//! a stock instead of mixing them again for each target.
+//!
+//! The designs are added to a single e-graph and each of their mixes is unioned with the fluid it
+//! produces. Sub-designs producing the same fluid thus share an e-class, even if they mix it from
+//! different inputs.
+use crate::MixLang;
+use egg::{EGraph, Id, Language, RecExpr};
+use fluido_types::{
+    decimal,
+    fluid::{Fluid, Volume},
+};
+use serde::Serialize;
+
+/// An e-class of sub-designs produced by more than one design of the batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReusedSubdesign {
+    /// Fluid every sub-design of the e-class produces.
+    #[serde(serialize_with = "decimal::serialize_fluid")]
+    pub fluid: Fluid,
+    /// Sub-design of the e-class as it is first found in the designs.
+    pub expr: String,
+    /// Indices of the designs producing the e-class, ascending.
+    pub designs: Vec<usize>,
+    /// Number of times the designs produce the e-class, a design may produce it more than once.
+    pub uses: usize,
+    /// Volume of a stock covering every use, in the volume units of the designs.
+    #[serde(serialize_with = "decimal::serialize")]
+    pub total_volume: Volume,
+}
+
+/// A mix of one of the designs.
+struct MixUse {
+    design: usize,
+    class: Id,
+    fluid: Fluid,
+    expr: RecExpr<MixLang>,
+}
+
+/// Collects the e-classes of intermediates produced by more than one of the designs, in the order
+/// they are first produced. Final fluids of the designs are targets, not intermediates, and are
+/// left out.
+pub fn reused_subdesigns(designs: &[RecExpr<MixLang>]) -> Vec<ReusedSubdesign> {
+    let mut egraph = EGraph::<MixLang, ()>::default();
+    let mut uses = vec![];
+    for (design, expr) in designs.iter().enumerate() {
+        let nodes = expr.as_ref();
+        let mut ids: Vec<Id> = Vec::with_capacity(nodes.len());
+        let mut fluids: Vec<Option<Fluid>> = Vec::with_capacity(nodes.len());
+        for (ix, node) in nodes.iter().enumerate() {
+            let id = egraph.add(node.clone().map_children(|child| ids[usize::from(child)]));
+            let fluid = match node {
+                MixLang::Fluid([concentration, volume]) => match (
+                    &nodes[usize::from(*concentration)],
+                    &nodes[usize::from(*volume)],
+                ) {
+                    (MixLang::LimitedFloat(concentration), MixLang::LimitedFloat(volume)) => {
+                        Some(Fluid::new(concentration.clone(), volume.clone()))
+                    }
+                    _ => None,
+                },
+                MixLang::Mix([lhs, rhs]) => {
+                    match (&fluids[usize::from(*lhs)], &fluids[usize::from(*rhs)]) {
+                        (Some(lhs), Some(rhs)) => Some(lhs.mix(rhs)),
+                        _ => None,
+                    }
+                }
+                // Extracted designs carry no arithmetic.
+                _ => None,
+            };
+            if let (MixLang::Mix(_), Some(fluid)) = (node, &fluid) {
+                let concentration =
+                    egraph.add(MixLang::LimitedFloat(fluid.concentration().clone()));
+                let volume = egraph.add(MixLang::LimitedFloat(fluid.unit_volume().clone()));
+                let produced = egraph.add(MixLang::Fluid([concentration, volume]));
+                egraph.union(id, produced);
+                if ix + 1 < nodes.len() {
+                    uses.push(MixUse {
+                        design,
+                        class: id,
+                        fluid: fluid.clone(),
+                        // Children precede their parents, so the prefix up to the mix is rooted
+                        // at it.
+                        expr: RecExpr::from(nodes[..=ix].to_vec()),
+                    });
+                }
+            }
+            ids.push(id);
+            fluids.push(fluid);
+        }
+    }
+    egraph.rebuild();
+
+    let mut classes: Vec<(Id, ReusedSubdesign)> = vec![];
+    for mix_use in uses {
+        let class = egraph.find(mix_use.class);
+        match classes.iter_mut().find(|(id, _)| *id == class) {
+            Some((_, reused)) => {
+                if !reused.designs.contains(&mix_use.design) {
+                    reused.designs.push(mix_use.design);
+                }
+                reused.uses += 1;
+                reused.total_volume =
+                    reused.total_volume.clone() + mix_use.fluid.unit_volume().clone();
+            }
+            None => classes.push((
+                class,
+                ReusedSubdesign {
+                    total_volume: mix_use.fluid.unit_volume().clone(),
+                    fluid: mix_use.fluid,
+                    expr: mix_use.expr.to_string(),
+                    designs: vec![mix_use.design],
+                    uses: 1,
+                },
+            )),
+        }
+    }
+    classes
+        .into_iter()
+        .map(|(_, reused)| reused)
+        .filter(|reused| reused.designs.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reused_subdesigns;
+    use crate::MixLang;
+    use egg::RecExpr;
+    use fluido_types::fluid::{Fluid, Volume};
+
+    #[test]
+    fn equivalent_subdesigns_are_reused() {
+        let designs = [
+            "(mix (mix (fluid 0.4 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))",
+            // Mixes the same intermediate from other inputs, twice.
+            "(mix (mix (fluid 0.3 1.0) (fluid 0.1 1.0)) (mix (fluid 0.4 1.0) (fluid 0.0 1.0)))",
+            "(mix (fluid 0.4 1.0) (fluid 0.0 3.0))",
+        ]
+        .map(|design| design.parse::<RecExpr<MixLang>>().unwrap());
+        let reused = reused_subdesigns(&designs);
+
+        assert_eq!(reused.len(), 1);
+        assert_eq!(reused[0].fluid, Fluid::new(0.2.into(), 2.0.into()));
+        assert_eq!(reused[0].expr, "(mix (fluid 0.4 1.0) (fluid 0.0 1.0))");
+        assert_eq!(reused[0].designs, vec![0, 1]);
+        assert_eq!(reused[0].uses, 3);
+        assert_eq!(reused[0].total_volume, Volume::from(6.0));
+    }
+}
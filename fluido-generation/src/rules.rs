@@ -0,0 +1,21 @@
+//! Everything needed for writing custom rewrite rules, without depending on a matching version of
+//! `egg`.
+//!
+//! Custom rules are registered through `SaturationConfig::custom_rules`:
+//!
+//! ```
+//! use fluido_generation::{rules::*, SaturationConfig};
+//!
+//! let halve: MixRewrite = rewrite!("halve-fluid";
+//!     "(fluid ?a ?b)" => "(mix (fluid ?a (/ ?b 2.0)) (fluid ?a (/ ?b 2.0)))"
+//!     if volume_valid("?b"));
+//! let config = SaturationConfig {
+//!     custom_rules: vec![halve],
+//!     ..Default::default()
+//! };
+//! ```
+pub use crate::{
+    compression_consistent, concentration_valid, intermediate_volume_valid, volume_valid,
+    ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang, MixRewrite, Op,
+};
+pub use egg::{rewrite, Condition, EGraph, Id, Pattern, Rewrite, Subst, Var};
@@ -0,0 +1,243 @@
+//! Extraction for a changing target over an e-graph saturated once, i.e for interactive use where
+//! the target is moved with a slider.
+//!
+//! The cheapest node of each e-class is kept between targets. Only leaves of the old and the new
+//! target change their cost with the target, so a new target only recomputes the e-classes holding
+//! fluids of either of them, along with the e-classes above those. A design for the new target is
+//! then the cheapest one among the e-classes of its concentration, so targets the saturation did
+//! not explore cannot be extracted without saturating again.
+use crate::{
+    cost_function,
+    coverage::ConcentrationCoverage,
+    extract::{cheapest_nodes, update_cheapest_nodes},
+    is_design_cost, normalize_expr_by_min_volume,
+    report::SaturationReport,
+    run_saturation, ArithmeticAnalysis, ArithmeticAnalysisPayload, MixLang, RejectedCandidates,
+    SaturationConfig, Sequence,
+};
+use egg::{EGraph, Id, Language, RecExpr};
+use fluido_types::{error::MixerGenerationError, fluid::Concentration, input_space::InputSpace};
+use std::collections::{BTreeSet, HashMap};
+
+/// A saturated e-graph along with the cheapest node of each of its e-classes for the current
+/// target.
+pub struct WarmExtractor {
+    egraph: EGraph<MixLang, ArithmeticAnalysis>,
+    target: Concentration,
+    input_space: InputSpace,
+    config: SaturationConfig,
+    coverage: ConcentrationCoverage,
+    rejected: RejectedCandidates,
+    report: SaturationReport,
+    /// E-classes holding fluids of each concentration.
+    classes_by_concentration: HashMap<Concentration, Vec<Id>>,
+    best: HashMap<Id, (f64, MixLang)>,
+    recomputed_classes: usize,
+}
+
+impl WarmExtractor {
+    /// Saturates the mixer space for the target, as a search does. A staged search saturates once
+    /// with the steps of all its stages. Required intermediates and target class dumps are not
+    /// supported.
+    pub fn saturate(
+        target_concentration: Concentration,
+        input_space: &InputSpace,
+        config: &SaturationConfig,
+    ) -> Result<Self, MixerGenerationError> {
+        if !config.required_intermediates.is_empty() {
+            return Err(MixerGenerationError::SaturationError(
+                "warm extraction does not support required intermediates".to_string(),
+            ));
+        }
+        let mut config = config.clone();
+        if !config.stages.is_empty() {
+            config.concentration_steps = std::mem::take(&mut config.stages);
+        }
+        config.target_class_dump = None;
+        let (runner, _) = run_saturation(
+            std::slice::from_ref(&target_concentration),
+            input_space,
+            &config,
+            None,
+        )?;
+        let report = SaturationReport::from_runner(&runner);
+        let egraph = runner.egraph;
+
+        let mut classes_by_concentration: HashMap<Concentration, Vec<Id>> = HashMap::new();
+        for class in egraph.classes() {
+            if let ArithmeticAnalysisPayload::Fluid(fluid) = &class.data {
+                classes_by_concentration
+                    .entry(fluid.concentration().clone())
+                    .or_default()
+                    .push(class.id);
+            }
+        }
+        let best = cheapest_nodes(
+            &egraph,
+            &mut cost_function(&egraph, &target_concentration, input_space, &config),
+            config.extraction,
+        );
+        Ok(Self {
+            coverage: ConcentrationCoverage::from_egraph(&egraph),
+            rejected: egraph.analysis.rejected(),
+            recomputed_classes: best.len(),
+            report,
+            egraph,
+            target: target_concentration,
+            input_space: input_space.clone(),
+            config,
+            classes_by_concentration,
+            best,
+        })
+    }
+
+    /// Target the cheapest nodes are currently computed for.
+    pub fn target(&self) -> &Concentration {
+        &self.target
+    }
+
+    /// Number of e-classes whose cheapest node was computed for the current target, all of them
+    /// for the target the e-graph was saturated for.
+    pub fn recomputed_classes(&self) -> usize {
+        self.recomputed_classes
+    }
+
+    /// Statistics of the saturation, shared by the sequences of every target.
+    pub fn report(&self) -> &SaturationReport {
+        &self.report
+    }
+
+    /// Number of e-classes of the saturated e-graph.
+    pub fn number_of_classes(&self) -> usize {
+        self.egraph.number_of_classes()
+    }
+
+    /// Moves to a new target and extracts a design for it, recomputing only the e-classes whose
+    /// cost changed with the target. Fails with `MixerGenerationError::TargetNotExplored` if no
+    /// e-class holds fluids of the target.
+    pub fn retarget(
+        &mut self,
+        target_concentration: Concentration,
+    ) -> Result<Sequence, MixerGenerationError> {
+        if target_concentration != self.target {
+            let changed = self.classes_depending_on([&self.target, &target_concentration]);
+            for id in &changed {
+                self.best.remove(id);
+            }
+            self.target = target_concentration;
+            let mut cost_function =
+                cost_function(&self.egraph, &self.target, &self.input_space, &self.config);
+            update_cheapest_nodes(
+                &self.egraph,
+                &mut cost_function,
+                self.config.extraction,
+                &mut self.best,
+                &changed,
+            );
+            self.recomputed_classes = changed.len();
+        }
+        self.extract()
+    }
+
+    /// The cheapest design for the current target.
+    pub fn extract(&self) -> Result<Sequence, MixerGenerationError> {
+        let candidates = self
+            .classes_by_concentration
+            .get(&self.target)
+            .cloned()
+            .unwrap_or_default();
+        if candidates.is_empty() {
+            return Err(MixerGenerationError::TargetNotExplored(self.target.clone()));
+        }
+        let (cost, root_node) = candidates
+            .iter()
+            .filter_map(|id| self.best.get(id))
+            .filter(|(cost, _)| is_design_cost(*cost))
+            .min_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs))
+            .ok_or_else(|| MixerGenerationError::TargetUnreachable(self.target.clone()))?;
+        let raw_expr: RecExpr<MixLang> =
+            root_node.build_recexpr(|id| self.best[&self.egraph.find(id)].1.clone());
+        let best_expr = normalize_expr_by_min_volume(&raw_expr)?;
+        Ok(Sequence {
+            cost: *cost,
+            best_expr,
+            raw_expr,
+            coverage: self.coverage.clone(),
+            rejected: self.rejected,
+            target_class_dot: None,
+            optimal: false,
+            report: Some(self.report.clone()),
+        })
+    }
+
+    /// E-classes holding fluids of the concentrations, along with every e-class above them, in
+    /// ascending order.
+    fn classes_depending_on<'a>(
+        &self,
+        concentrations: impl IntoIterator<Item = &'a Concentration>,
+    ) -> Vec<Id> {
+        let mut pending = concentrations
+            .into_iter()
+            .filter_map(|concentration| self.classes_by_concentration.get(concentration))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        let mut classes = BTreeSet::new();
+        while let Some(id) = pending.pop() {
+            if classes.insert(id) {
+                pending.extend(
+                    self.egraph[id]
+                        .parents()
+                        .map(|(_, parent)| self.egraph.find(parent)),
+                );
+            }
+        }
+        classes.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WarmExtractor;
+    use crate::{cost_function, extract::cheapest_nodes, is_design_cost, SaturationConfig};
+    use fluido_types::{
+        error::MixerGenerationError, fluid::Concentration, input_space::InputSpace,
+    };
+
+    #[test]
+    fn retargeting_matches_a_full_extraction() {
+        let input_space = InputSpace::from_concentrations([0.4.into(), 0.0.into()]);
+        let config = SaturationConfig {
+            time_limit: 1,
+            ..Default::default()
+        };
+        let mut extractor = WarmExtractor::saturate(0.2.into(), &input_space, &config).unwrap();
+        let initial = extractor.extract().unwrap();
+        assert_eq!(
+            extractor.recomputed_classes(),
+            extractor.number_of_classes()
+        );
+
+        let target: Concentration = 0.1.into();
+        let sequence = extractor.retarget(target.clone()).unwrap();
+        assert!(extractor.recomputed_classes() < extractor.number_of_classes());
+        let full = cheapest_nodes(
+            &extractor.egraph,
+            &mut cost_function(&extractor.egraph, &target, &input_space, &config),
+            config.extraction,
+        );
+        assert_eq!(extractor.best, full);
+        // Only designs explored around the saturated target are found.
+        assert!(is_design_cost(sequence.cost));
+        assert!(sequence.best_expr.to_string().starts_with("(mix"));
+
+        let back = extractor.retarget(0.2.into()).unwrap();
+        assert_eq!(back.cost, initial.cost);
+        assert_eq!(back.best_expr, initial.best_expr);
+
+        assert!(matches!(
+            extractor.retarget(0.123.into()),
+            Err(MixerGenerationError::TargetNotExplored(_))
+        ));
+    }
+}
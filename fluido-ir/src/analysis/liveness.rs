@@ -8,8 +8,16 @@ use crate::{
 #[derive(Default)]
 pub struct LivenessAnalysis {}
 
+/// Result of `LivenessAnalysis`.
+#[derive(Debug, Clone, Default)]
+pub struct LivenessResult {
+    /// Virtual registers live before each op of the ir.
+    pub sets_per_ir: Vec<HashSet<usize>>,
+}
+
 impl AnalysisPass for LivenessAnalysis {
-    fn analyze(&self, ir_to_pass_over: &[IROp]) -> crate::pass_manager::AnalysisResult {
+    /// Returns a `LivenessResult`.
+    fn analyze(&self, ir_to_pass_over: &[IROp]) -> AnalysisResult {
         let mut live_regs = vec![];
         let mut ir = ir_to_pass_over.to_vec();
         ir.reverse();
@@ -52,9 +60,9 @@ impl AnalysisPass for LivenessAnalysis {
             live_regs.push(live_set);
         }
         live_regs.reverse();
-        AnalysisResult {
+        AnalysisResult::new(LivenessResult {
             sets_per_ir: live_regs,
-        }
+        })
     }
 
     fn pass_name(&self) -> &str {
@@ -64,7 +72,7 @@ impl AnalysisPass for LivenessAnalysis {
 
 #[cfg(test)]
 mod tests {
-    use super::LivenessAnalysis;
+    use super::{LivenessAnalysis, LivenessResult};
     use crate::{graph::Graph, ir::IROp, ir_builder::IRBuilder, pass_manager::AnalysisPass};
     use fluido_parse::parser::Parse;
     use fluido_types::expr::Expr;
@@ -85,8 +93,8 @@ mod tests {
         let result = liveness_analysis.analyze(&ir);
 
         let expected_sets = vec![HashSet::from([]), HashSet::from([0]), HashSet::from([0, 1])];
-        let result_sets = result.sets_per_ir;
+        let result_sets = &result.downcast_ref::<LivenessResult>().unwrap().sets_per_ir;
 
-        assert_eq!(expected_sets, result_sets)
+        assert_eq!(&expected_sets, result_sets)
     }
 }
@@ -1,4 +1,4 @@
-use fluido_types::expr::Expr;
+use fluido_types::{expr::Expr, precision::Precision};
 use petgraph::graph::{DiGraph, NodeIndex};
 
 pub struct Graph {
@@ -20,19 +20,42 @@ impl Graph {
         }
     }
 
+    /// Adds the nodes of the expression in pre-order, and the edges of each mix once both of its
+    /// operands are added. Iterative, so that deeply nested expressions do not exhaust the stack.
     fn add_expr(&mut self, expr: &Expr) -> NodeIndex {
-        let index = self.graph.add_node(expr.clone());
-        if self.root.is_none() {
-            self.root = Some(index);
+        enum Step<'a> {
+            Add(&'a Expr),
+            Connect(NodeIndex),
         }
-
-        if let Expr::Mix(left, right) = expr {
-            let left_index = self.add_expr(left);
-            let right_index = self.add_expr(right);
-            self.graph.add_edge(index, left_index, ());
-            self.graph.add_edge(index, right_index, ());
+        let mut steps = vec![Step::Add(expr)];
+        // Nodes of the operands added so far, whose mixes are not connected yet.
+        let mut added = vec![];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Add(expr) => {
+                    let index = self.graph.add_node(expr.clone());
+                    if self.root.is_none() {
+                        self.root = Some(index);
+                    }
+                    match expr {
+                        Expr::Mix(left, right) => {
+                            steps.push(Step::Connect(index));
+                            steps.push(Step::Add(right));
+                            steps.push(Step::Add(left));
+                        }
+                        _ => added.push(index),
+                    }
+                }
+                Step::Connect(index) => {
+                    let right_index = added.pop().expect("mixes have two operands");
+                    let left_index = added.pop().expect("mixes have two operands");
+                    self.graph.add_edge(index, left_index, ());
+                    self.graph.add_edge(index, right_index, ());
+                    added.push(index);
+                }
+            }
         }
-        index
+        added.pop().expect("the expression is added")
     }
 
     pub fn root_node(&self) -> Option<NodeIndex> {
@@ -40,6 +63,11 @@ impl Graph {
     }
 
     pub fn dot(&self) -> String {
+        self.dot_with_precision(&Precision::Full)
+    }
+
+    /// Same as `dot`, with the fluids labeled at the given precision.
+    pub fn dot_with_precision(&self, precision: &Precision) -> String {
         format!(
             "{:?}",
             petgraph::dot::Dot::with_attr_getters(
@@ -53,10 +81,10 @@ impl Graph {
                     let _node = &self.graph[nr.0];
                     let node_label = match _node {
                         Expr::Mix(_, _) => "mix".to_string(),
-                        Expr::Fluid(fl) => format!("{}", fl),
-                        Expr::LimitedFloat(fl) => format!("{}", fl),
+                        Expr::Fluid(fl) => precision.format_fluid(fl),
+                        Expr::LimitedFloat(fl) => precision.format_limited(fl),
                     };
-                    format!("label = {}", node_label)
+                    format!("label = \"{}\"", node_label)
                 },
             )
         )
@@ -107,13 +135,34 @@ mod tests {
         assert_eq!(graph_wrapper.graph.edge_count(), 4); // Edges connecting Mixes to Numbers
     }
 
+    #[test]
+    fn deeply_nested_mixes_are_lowered() {
+        let depth = 500;
+        let input_str = format!(
+            "{}(fluid 0.2 1){}",
+            "(mix ".repeat(depth),
+            " (fluid 0.0 1))".repeat(depth)
+        );
+        let expr = Expr::parse(&input_str).unwrap();
+        let graph_wrapper: Graph = (&expr).into();
+        assert_eq!(graph_wrapper.graph.node_count(), expr.size());
+        assert_eq!(graph_wrapper.graph.edge_count(), expr.size() - 1);
+
+        let ir = crate::ir_builder::IRBuilder::default().build_ir(graph_wrapper);
+        assert_eq!(ir.len(), expr.size());
+        assert_eq!(ir.last().unwrap().target_vreg(), Some(ir.len() - 1));
+    }
+
     #[test]
     fn graph_to_dot() {
         let expr_str = "(mix (mix (fluid 0.0 1) (fluid 0.2 1)) (fluid 0.1 1))";
         let expr = Expr::parse(expr_str).unwrap();
         let graph_wrapper: Graph = (&expr).into();
         let dot = graph_wrapper.dot();
-        let expected = "digraph {\n    0 [ label = mix]\n    1 [ label = mix]\n    2 [ label = (fluid 0.0 1.0)]\n    3 [ label = (fluid 0.2 1.0)]\n    4 [ label = (fluid 0.1 1.0)]\n    1 -> 2 [ label = \"()\"]\n    1 -> 3 [ label = \"()\"]\n    0 -> 1 [ label = \"()\"]\n    0 -> 4 [ label = \"()\"]\n}\n";
-        assert_eq!(dot, expected)
+        let expected = "digraph {\n    0 [ label = \"mix\"]\n    1 [ label = \"mix\"]\n    2 [ label = \"(fluid 0.0 1.0)\"]\n    3 [ label = \"(fluid 0.2 1.0)\"]\n    4 [ label = \"(fluid 0.1 1.0)\"]\n    1 -> 2 [ label = \"()\"]\n    1 -> 3 [ label = \"()\"]\n    0 -> 1 [ label = \"()\"]\n    0 -> 4 [ label = \"()\"]\n}\n";
+        assert_eq!(dot, expected);
+
+        let dot = graph_wrapper.dot_with_precision(&Precision::DecimalPlaces(2));
+        assert!(dot.contains("label = \"(fluid 0.20 1.00)\""));
     }
 }
@@ -1,4 +1,5 @@
-use fluido_types::fluid::Fluid;
+use fluido_types::{fluid::Fluid, precision::Precision};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 /// Possible IR operations for mixlang.
@@ -13,6 +14,8 @@ pub enum IROp {
 pub enum Operand {
     Const(Fluid),
     VirtualRegister(usize),
+    /// Physical storage unit, in place of a virtual register after register allocation.
+    StorageUnit(u64),
 }
 
 impl std::fmt::Display for IROp {
@@ -29,14 +32,109 @@ impl std::fmt::Display for Operand {
         match self {
             Operand::Const(num) => write!(f, "{}", num),
             Operand::VirtualRegister(v_reg) => write!(f, "%{}", v_reg),
+            Operand::StorageUnit(storage_unit) => write!(f, "${storage_unit}"),
         }
     }
 }
 
+impl IROp {
+    /// Returns the virtual register this operation writes to.
+    pub fn target_vreg(&self) -> Option<usize> {
+        let target = match self {
+            IROp::Store(store) => &store.1,
+            IROp::Mix(mix) => &mix.2,
+        };
+        target.as_virtual_register()
+    }
+}
+
+impl Operand {
+    pub fn as_virtual_register(&self) -> Option<usize> {
+        match self {
+            Operand::VirtualRegister(v_reg) => Some(*v_reg),
+            Operand::Const(_) | Operand::StorageUnit(_) => None,
+        }
+    }
+}
+
+/// Computes the fluid held by each virtual register after executing the given ir.
+pub fn evaluate_ir(ir: &[IROp]) -> HashMap<usize, Fluid> {
+    let mut fluids: HashMap<usize, Fluid> = HashMap::new();
+    for op in ir {
+        match op {
+            IROp::Store((Operand::Const(fluid), Operand::VirtualRegister(target))) => {
+                fluids.insert(*target, fluid.clone());
+            }
+            IROp::Mix((
+                Operand::VirtualRegister(lhs),
+                Operand::VirtualRegister(rhs),
+                Operand::VirtualRegister(target),
+            )) => {
+                if let (Some(lhs), Some(rhs)) = (fluids.get(lhs), fluids.get(rhs)) {
+                    let mixed = lhs.mix(rhs);
+                    fluids.insert(*target, mixed);
+                }
+            }
+            _ => {}
+        }
+    }
+    fluids
+}
+
+/// Lists the ir as a linear protocol, one line per op, with the fluid of each virtual register
+/// written next to it, i.e `%2 = mix %0(0.2,1.0) %1(0.0,1.0) -> (0.1,2.0)`.
+pub fn linearize_ir(ir: &[IROp]) -> String {
+    linearize_ir_with_precision(ir, &Precision::Full)
+}
+
+/// Same as `linearize_ir`, with the fluids displayed at the given precision.
+pub fn linearize_ir_with_precision(ir: &[IROp], precision: &Precision) -> String {
+    let fluids = evaluate_ir(ir);
+    let fluid_str = |fluid: &Fluid| {
+        format!(
+            "({},{})",
+            precision.format_limited(fluid.concentration()),
+            precision.format_limited(fluid.unit_volume())
+        )
+    };
+    let operand_str = |operand: &Operand| match operand {
+        Operand::Const(fluid) => fluid_str(fluid),
+        Operand::VirtualRegister(vreg) => match fluids.get(vreg) {
+            Some(fluid) => format!("%{vreg}{}", fluid_str(fluid)),
+            None => format!("%{vreg}(?)"),
+        },
+        Operand::StorageUnit(_) => operand.to_string(),
+    };
+    let target_str = |target: &Operand| {
+        let fluid = target
+            .as_virtual_register()
+            .and_then(|vreg| fluids.get(&vreg))
+            .map_or_else(|| "(?)".to_string(), fluid_str);
+        (target.to_string(), fluid)
+    };
+    ir.iter()
+        .map(|op| match op {
+            IROp::Store((value, target)) => {
+                let (target, _) = target_str(target);
+                format!("{target} = store {}\n", operand_str(value))
+            }
+            IROp::Mix((lhs, rhs, target)) => {
+                let (target, fluid) = target_str(target);
+                format!(
+                    "{target} = mix {} {} -> {fluid}\n",
+                    operand_str(lhs),
+                    operand_str(rhs)
+                )
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::ir::{IROp, Operand};
+    use crate::ir::{evaluate_ir, linearize_ir, linearize_ir_with_precision, IROp, Operand};
     use fluido_types::fluid::{Concentration, Fluid, Volume};
+    use fluido_types::precision::Precision;
 
     fn get_dummy_fluid() -> Fluid {
         let concentration_a = Concentration::from(0.1);
@@ -67,4 +165,55 @@ mod tests {
         ));
         assert_eq!(format!("{}", mix_op), "mix %1 %2 %3");
     }
+
+    #[test]
+    fn test_evaluate_ir() {
+        let ir = vec![
+            IROp::Store((
+                Operand::Const(Fluid::new(0.2.into(), 1.0.into())),
+                Operand::VirtualRegister(0),
+            )),
+            IROp::Store((
+                Operand::Const(Fluid::new(0.0.into(), 1.0.into())),
+                Operand::VirtualRegister(1),
+            )),
+            IROp::Mix((
+                Operand::VirtualRegister(0),
+                Operand::VirtualRegister(1),
+                Operand::VirtualRegister(2),
+            )),
+        ];
+        let fluids = evaluate_ir(&ir);
+
+        assert_eq!(fluids.len(), 3);
+        assert_eq!(fluids[&2], Fluid::new(0.1.into(), 2.0.into()));
+        assert_eq!(ir[2].target_vreg(), Some(2));
+    }
+
+    #[test]
+    fn test_linearize_ir() {
+        let ir = vec![
+            IROp::Store((
+                Operand::Const(Fluid::new(0.2.into(), 1.0.into())),
+                Operand::VirtualRegister(0),
+            )),
+            IROp::Store((
+                Operand::Const(Fluid::new(0.0.into(), 1.0.into())),
+                Operand::VirtualRegister(1),
+            )),
+            IROp::Mix((
+                Operand::VirtualRegister(0),
+                Operand::VirtualRegister(1),
+                Operand::VirtualRegister(2),
+            )),
+        ];
+        assert_eq!(
+            linearize_ir(&ir),
+            "%0 = store (0.2,1.0)\n%1 = store (0.0,1.0)\n%2 = mix %0(0.2,1.0) %1(0.0,1.0) -> (0.1,2.0)\n"
+        );
+        assert_eq!(
+            linearize_ir_with_precision(&ir, &Precision::SignificantFigures(2)),
+            "%0 = store (0.20,1.0)\n%1 = store (0.0,1.0)\n%2 = mix %0(0.20,1.0) %1(0.0,1.0) -> (0.10,2.0)\n"
+        );
+    }
 }
@@ -22,13 +22,55 @@ impl IRBuilder {
         self.context.ir_output.clone()
     }
 
-    /// Returns the expr's result v_reg.
+    /// Returns the expr's result v_reg. Compiles the operand of each mix needing more storage
+    /// units first, so that fewer results of finished operands are held while the other one is
+    /// compiled (Sethi-Ullman ordering). Operands keep their positions in the mix.
+    ///
+    /// Iterative, so that deeply nested expressions do not exhaust the stack.
     pub fn compile_expr(&mut self, expr: Expr) -> Option<usize> {
-        match expr {
-            Expr::Mix(l_expr, r_expr) => self.compile_mix(*l_expr, *r_expr),
-            Expr::Fluid(fluid) => self.compile_fluid(fluid),
-            _ => None,
+        enum Step {
+            Compile(usize),
+            /// Emits the mix of the last two results, the second of which is its lhs if swapped.
+            Mix {
+                swapped: bool,
+            },
         }
+        let (nodes, operands) = flatten(&expr);
+        let needs = storage_needs(&operands);
+        let mut steps = vec![Step::Compile(0)];
+        let mut results = vec![];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Compile(ix) => match (nodes[ix], operands[ix]) {
+                    (_, Some((lhs, rhs))) => {
+                        let swapped = needs[rhs] > needs[lhs];
+                        let (first, second) = if swapped { (rhs, lhs) } else { (lhs, rhs) };
+                        steps.push(Step::Mix { swapped });
+                        steps.push(Step::Compile(second));
+                        steps.push(Step::Compile(first));
+                    }
+                    (Expr::Fluid(fluid), None) => results.push(self.compile_fluid(fluid.clone())),
+                    _ => results.push(None),
+                },
+                Step::Mix { swapped } => {
+                    let second = results
+                        .pop()
+                        .flatten()
+                        .expect("Internal Compiler Error, please open an issue!");
+                    let first = results
+                        .pop()
+                        .flatten()
+                        .expect("Internal Compiler Error, please open an issue!");
+                    let (lhs, rhs) = if swapped {
+                        (second, first)
+                    } else {
+                        (first, second)
+                    };
+                    results.push(Some(self.emit_mix(lhs, rhs)));
+                }
+            }
+        }
+        results.pop().flatten()
     }
 
     pub fn compile_fluid(&mut self, fluid: Fluid) -> Option<usize> {
@@ -41,12 +83,11 @@ impl IRBuilder {
     }
 
     pub fn compile_mix(&mut self, lhs: Expr, rhs: Expr) -> Option<usize> {
-        let lhs_vreg_ix = self
-            .compile_expr(lhs)
-            .expect("Internal Compiler Error, please open an issue!");
-        let rhs_vreg_ix = self
-            .compile_expr(rhs)
-            .expect("Internal Compiler Error, please open an issue!");
+        self.compile_expr(Expr::Mix(Box::new(lhs), Box::new(rhs)))
+    }
+
+    /// Emits the mix of the results in the two virtual registers.
+    fn emit_mix(&mut self, lhs_vreg_ix: usize, rhs_vreg_ix: usize) -> usize {
         // TODO: return results, this may fail. If this fails this is a ICE and should be reported.
         let current_virtual_register_ix = self.context.ir_output.len();
         let lhs_vreg_operand = Operand::VirtualRegister(lhs_vreg_ix);
@@ -56,6 +97,44 @@ impl IRBuilder {
         let ir_op = IROp::Mix((lhs_vreg_operand, rhs_vreg_operand, target_vreg));
 
         self.context.ir_output.push(ir_op);
-        Some(current_virtual_register_ix)
+        current_virtual_register_ix
+    }
+}
+
+/// Indices of the operands of each node of a flattened expression, `None` for its leaves.
+type Operands = Vec<Option<(usize, usize)>>;
+
+/// Nodes of the expression in breadth-first order, along with the indices of the operands of each
+/// mix, which come after the mix.
+fn flatten(expr: &Expr) -> (Vec<&Expr>, Operands) {
+    let mut nodes = vec![expr];
+    let mut operands = vec![];
+    let mut ix = 0;
+    while ix < nodes.len() {
+        match nodes[ix] {
+            Expr::Mix(lhs, rhs) => {
+                operands.push(Some((nodes.len(), nodes.len() + 1)));
+                nodes.push(lhs);
+                nodes.push(rhs);
+            }
+            _ => operands.push(None),
+        }
+        ix += 1;
+    }
+    (nodes, operands)
+}
+
+/// Storage units needed to compile each node of a flattened expression, its Sethi-Ullman number.
+/// A mix of operands needing the same number of units needs one more, to hold the first result
+/// while compiling the second operand.
+fn storage_needs(operands: &[Option<(usize, usize)>]) -> Vec<usize> {
+    let mut needs = vec![1; operands.len()];
+    // Operands come after their mix, so they are computed first.
+    for (ix, operands) in operands.iter().enumerate().rev() {
+        if let Some((lhs, rhs)) = *operands {
+            let (lhs, rhs) = (needs[lhs], needs[rhs]);
+            needs[ix] = if lhs == rhs { lhs + 1 } else { lhs.max(rhs) };
+        }
     }
+    needs
 }
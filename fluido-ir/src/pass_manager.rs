@@ -1,6 +1,30 @@
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+//! Analysis passes over flat mixlang ir.
+//!
+//! Each pass produces a result of its own type, i.e the live virtual registers of each op for
+//! `LivenessAnalysis`. Results are kept type erased by the pass manager and looked up with the
+//! name of their pass and their type:
+//!
+//! ```
+//! use fluido_ir::{
+//!     analysis::liveness::{LivenessAnalysis, LivenessResult},
+//!     pass_manager::IRPassManager,
+//! };
+//!
+//! let liveness = LivenessAnalysis::default();
+//! let results = IRPassManager::new(vec![], vec![&liveness]).apply_analysis_passes();
+//! let liveness_result = results.get::<LivenessResult>("liveness").unwrap();
+//! assert!(liveness_result.sets_per_ir.is_empty());
+//! ```
+//!
+//! Passes defined outside of this crate are registered the same way as the builtin ones.
 use crate::ir::IROp;
-use std::collections::{HashMap, HashSet};
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::Arc,
+};
 
 /// Manages possible analysis passes over flat mixlang ir.
 pub struct IRPassManager<'a> {
@@ -21,36 +45,94 @@ impl<'a> IRPassManager<'a> {
         self.analysis_passes.push(pass_to_register);
     }
 
-    /// Returns results of registered analysis passes. In the form of `(pass_name, analysis result)`.
-    pub fn apply_analysis_passes(&self) -> HashMap<&str, AnalysisResult> {
+    /// Returns results of registered analysis passes by their pass names. Passes sharing a name
+    /// keep the result of the one registered last.
+    pub fn apply_analysis_passes(&self) -> AnalysisResults {
         let ir_to_pass_over = self.ir_to_pass_over.as_slice();
-        self.analysis_passes
+        let results = self
+            .analysis_passes
             .iter()
             .map(|analysis_pass| {
                 (
-                    analysis_pass.pass_name(),
+                    analysis_pass.pass_name().to_string(),
                     analysis_pass.analyze(ir_to_pass_over),
                 )
             })
-            .collect()
+            .collect();
+        AnalysisResults { results }
     }
 }
 
-#[derive(Default, Debug)]
-pub struct AnalysisResult {
-    pub sets_per_ir: Vec<HashSet<usize>>,
+/// Result of an analysis pass, of the type the pass produces. Clones share the result.
+#[derive(Clone)]
+pub struct AnalysisResult(Arc<dyn Any + Send + Sync>);
+
+impl AnalysisResult {
+    pub fn new<T: Any + Send + Sync>(result: T) -> Self {
+        Self(Arc::new(result))
+    }
+
+    /// The result, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+impl Debug for AnalysisResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AnalysisResult")
+    }
+}
+
+/// Results of the analysis passes applied to an ir, by pass name.
+#[derive(Debug, Clone, Default)]
+pub struct AnalysisResults {
+    results: HashMap<String, AnalysisResult>,
 }
 
-pub trait AnalysisPass {
+impl AnalysisResults {
+    /// Result of the pass, `None` if the pass did not run or its result is not a `T`.
+    pub fn get<T: Any>(&self, pass_name: &str) -> Option<&T> {
+        self.results.get(pass_name)?.downcast_ref()
+    }
+
+    pub fn contains(&self, pass_name: &str) -> bool {
+        self.results.contains_key(pass_name)
+    }
+
+    /// Names of the passes with a result, in no particular order.
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> {
+        self.results.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+/// An analysis over flat ir. Passes can be shared by searches running concurrently, so they must
+/// be `Send` and `Sync`.
+pub trait AnalysisPass: Send + Sync {
+    /// Name the result of the pass is looked up with.
     fn pass_name(&self) -> &str;
+    /// Analyzes the ir, returning a result of the type documented by the pass.
     fn analyze(&self, ir_to_pass_over: &[IROp]) -> AnalysisResult;
 }
 
+impl Debug for dyn AnalysisPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AnalysisPass({})", self.pass_name())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{AnalysisPass, AnalysisResult, IRPassManager};
+    use super::{AnalysisPass, AnalysisResult, AnalysisResults, IRPassManager};
     use crate::ir::IROp;
-    use std::collections::HashSet;
 
     #[derive(Debug)]
     struct DummyAnalysisPass {
@@ -62,10 +144,8 @@ mod tests {
             self.name
         }
 
-        fn analyze(&self, _ir_to_pass_over: &[IROp]) -> AnalysisResult {
-            AnalysisResult {
-                sets_per_ir: vec![HashSet::new()],
-            }
+        fn analyze(&self, ir_to_pass_over: &[IROp]) -> AnalysisResult {
+            AnalysisResult::new(ir_to_pass_over.len())
         }
     }
 
@@ -93,16 +173,20 @@ mod tests {
         let results = manager.apply_analysis_passes();
 
         assert_eq!(results.len(), 2);
-        assert!(results.contains_key("pass1"));
-        assert!(results.contains_key("pass2"));
-        assert_eq!(results["pass1"].sets_per_ir.len(), 1);
-        assert_eq!(results["pass2"].sets_per_ir.len(), 1);
+        assert!(results.contains("pass1"));
+        assert!(results.contains("pass2"));
+        assert_eq!(results.get::<usize>("pass1"), Some(&0));
+        assert_eq!(results.get::<usize>("pass2"), Some(&0));
+        // Results are only returned as the type their pass produced.
+        assert_eq!(results.get::<u64>("pass1"), None);
+        assert_eq!(results.get::<usize>("pass3"), None);
     }
 
     #[test]
-    fn test_analysis_result_default() {
-        let result = AnalysisResult::default();
-        assert!(result.sets_per_ir.is_empty());
+    fn test_analysis_results_default() {
+        let results = AnalysisResults::default();
+        assert!(results.is_empty());
+        assert_eq!(results.pass_names().count(), 0);
     }
 
     #[test]
@@ -113,7 +197,10 @@ mod tests {
         let result = pass.analyze(&ir);
 
         assert_eq!(pass.pass_name(), "dummy_pass");
-        assert_eq!(result.sets_per_ir.len(), 1);
-        assert!(result.sets_per_ir[0].is_empty());
+        assert_eq!(result.downcast_ref::<usize>(), Some(&0));
+        assert_eq!(
+            format!("{:?}", &pass as &dyn AnalysisPass),
+            "AnalysisPass(dummy_pass)"
+        );
     }
 }
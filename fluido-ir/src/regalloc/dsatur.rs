@@ -0,0 +1,120 @@
+//! DSATUR coloring of the interference graph, a heuristic needing no solver.
+//!
+//! Nodes are colored one at a time, always the uncolored node whose neighbors already have the
+//! most distinct colors, ties broken by the most uncolored neighbors and then by the lowest
+//! virtual register. Each node is given the lowest color none of its neighbors has. It colors
+//! bipartite graphs, cycles and wheels with the fewest colors, and usually needs fewer colors than
+//! coloring the nodes in a fixed order.
+use std::collections::{HashMap, HashSet};
+
+use petgraph::{graph::NodeIndex, prelude::UnGraph};
+
+/// Colors given so far, with the colors of the neighbors and the number of uncolored neighbors of
+/// each node by node index, kept up to date as nodes are colored.
+struct Coloring<'a> {
+    graph: &'a UnGraph<usize, ()>,
+    node_to_color: HashMap<usize, u64>,
+    neighbor_colors: Vec<HashSet<u64>>,
+    uncolored_degrees: Vec<usize>,
+}
+
+impl<'a> Coloring<'a> {
+    fn new(graph: &'a UnGraph<usize, ()>) -> Self {
+        Self {
+            graph,
+            node_to_color: HashMap::new(),
+            neighbor_colors: vec![HashSet::new(); graph.node_count()],
+            uncolored_degrees: graph
+                .node_indices()
+                .map(|node_ix| graph.neighbors(node_ix).count())
+                .collect(),
+        }
+    }
+
+    fn color(&mut self, node_ix: NodeIndex, color: u64) {
+        self.node_to_color.insert(self.graph[node_ix], color);
+        for neighbor_ix in self.graph.neighbors(node_ix) {
+            self.neighbor_colors[neighbor_ix.index()].insert(color);
+            self.uncolored_degrees[neighbor_ix.index()] -= 1;
+        }
+    }
+}
+
+/// Colors the graph, giving nodes the color the pins give them first. Other nodes are given the
+/// color the hint gives them if none of their neighbors has it yet.
+pub(crate) fn dsatur_coloring(
+    graph: &UnGraph<usize, ()>,
+    pins: &HashMap<usize, u64>,
+    hint: &HashMap<usize, u64>,
+) -> HashMap<usize, u64> {
+    let mut coloring = Coloring::new(graph);
+    let mut uncolored = vec![];
+    for node_ix in graph.node_indices() {
+        match pins.get(&graph[node_ix]) {
+            Some(color) => coloring.color(node_ix, *color),
+            None => uncolored.push(node_ix),
+        }
+    }
+    while !uncolored.is_empty() {
+        let (position, _) = uncolored
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, node_ix)| {
+                (
+                    coloring.neighbor_colors[node_ix.index()].len(),
+                    coloring.uncolored_degrees[node_ix.index()],
+                    std::cmp::Reverse(graph[**node_ix]),
+                )
+            })
+            .expect("an uncolored node is left");
+        let node_ix = uncolored.swap_remove(position);
+        let taken = &coloring.neighbor_colors[node_ix.index()];
+        let color = hint
+            .get(&graph[node_ix])
+            .filter(|color| !taken.contains(color))
+            .copied()
+            .unwrap_or_else(|| {
+                (0..)
+                    .find(|color| !taken.contains(color))
+                    .expect("a free color exists")
+            });
+        coloring.color(node_ix, color);
+    }
+    coloring.node_to_color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dsatur_coloring;
+    use petgraph::prelude::UnGraph;
+    use std::collections::HashMap;
+
+    #[test]
+    fn saturated_nodes_are_colored_first() {
+        // A crown graph, which coloring in the order of the nodes colors with a color per pair.
+        let mut crown = UnGraph::<usize, ()>::default();
+        let nodes = (0..8).map(|vreg| crown.add_node(vreg)).collect::<Vec<_>>();
+        for u in (0..8).step_by(2) {
+            for v in (1..8).step_by(2) {
+                if v != u + 1 {
+                    crown.add_edge(nodes[u], nodes[v], ());
+                }
+            }
+        }
+        let coloring = dsatur_coloring(&crown, &HashMap::new(), &HashMap::new());
+        assert_eq!(coloring.values().max(), Some(&1));
+        assert!(crown.edge_indices().all(|edge_ix| {
+            let (lhs, rhs) = crown.edge_endpoints(edge_ix).unwrap();
+            coloring[&crown[lhs]] != coloring[&crown[rhs]]
+        }));
+
+        // Pins are kept, and hints where no neighbor has the color yet.
+        let pins = HashMap::from([(0, 3)]);
+        let hint = HashMap::from([(1, 3), (3, 3)]);
+        let coloring = dsatur_coloring(&crown, &pins, &hint);
+        assert_eq!(coloring[&0], 3);
+        // The node paired with the pinned one is the only one of its side not its neighbor.
+        assert_eq!(coloring[&1], 3);
+        assert_ne!(coloring[&3], 3);
+    }
+}
@@ -1,12 +1,24 @@
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
-use std::collections::{HashMap, HashSet};
+#[cfg(feature = "smt")]
+use std::time::Instant;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
 
 use petgraph::prelude::UnGraph;
+
+use super::dsatur::dsatur_coloring;
+#[cfg(feature = "smt")]
 use z3::{
     ast::{Ast, Int},
-    Config, Context, Solver,
+    Config, Context, Optimize, Params, SatResult, Solver,
 };
 
+/// Largest number of nodes the clique bound of a graph is exact for.
+pub const EXACT_CLIQUE_MAX_NODES: usize = 64;
+
 #[derive(Debug)]
 pub struct InterferenceGraphBuilder<'a> {
     liveness_analysis: &'a [HashSet<usize>],
@@ -26,10 +38,100 @@ impl InterferenceGraph {
         format!("{:?}", petgraph::dot::Dot::new(&self.graph))
     }
 
+    #[cfg(feature = "smt")]
     pub fn try_coloring(&self, number_of_colors: u64) -> Option<HashMap<usize, u64>> {
+        match self.solve_coloring(number_of_colors, None) {
+            ColoringResult::Colored(coloring) => Some(coloring),
+            ColoringResult::Uncolorable | ColoringResult::TimedOut => None,
+        }
+    }
+
+    /// Same as `try_coloring`, but keeps as many nodes as possible at the color the hint gives
+    /// them. Nodes missing from the hint, or hinted a color out of range, are colored freely.
+    #[cfg(feature = "smt")]
+    pub fn try_coloring_with_hint(
+        &self,
+        number_of_colors: u64,
+        hint: &HashMap<usize, u64>,
+    ) -> Option<HashMap<usize, u64>> {
+        self.try_coloring_with_pins(number_of_colors, &HashMap::new(), hint)
+    }
+
+    /// Same as `try_coloring_with_hint`, but nodes are always given the color the pins give them.
+    /// `None` if the pins cannot be kept with the given number of colors.
+    #[cfg(feature = "smt")]
+    pub fn try_coloring_with_pins(
+        &self,
+        number_of_colors: u64,
+        pins: &HashMap<usize, u64>,
+        hint: &HashMap<usize, u64>,
+    ) -> Option<HashMap<usize, u64>> {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let optimize = Optimize::new(&ctx);
+        let graph = &self.graph;
+
+        let node_to_int: HashMap<_, _> = graph
+            .node_indices()
+            .map(|node_ix| graph[node_ix])
+            .map(|k| (k, Int::new_const(&ctx, format!("t{}", k))))
+            .collect();
+
+        let zero = Int::from_u64(&ctx, 0);
+        let num_colors = Int::from_u64(&ctx, number_of_colors);
+        for node_ix in graph.node_indices() {
+            let node = graph[node_ix];
+            let var = &node_to_int[&node];
+            optimize.assert(&var.ge(&zero));
+            optimize.assert(&var.lt(&num_colors));
+            for neighbor_ix in graph.neighbors(node_ix) {
+                let neighbor_var = &node_to_int[&graph[neighbor_ix]];
+                optimize.assert(&var._eq(neighbor_var).not())
+            }
+            if let Some(color) = pins.get(&node) {
+                optimize.assert(&var._eq(&Int::from_u64(&ctx, *color)));
+            } else if let Some(color) = hint.get(&node).filter(|color| **color < number_of_colors) {
+                optimize.assert_soft(&var._eq(&Int::from_u64(&ctx, *color)), 1, None);
+            }
+        }
+
+        if optimize.check(&[]) != SatResult::Sat {
+            return None;
+        }
+        let model = optimize
+            .get_model()
+            .expect("expected to get the model from solver");
+        let node_to_color = node_to_int
+            .iter()
+            .map(|(node, node_var)| {
+                let color_value = model
+                    .eval(node_var, true)
+                    .and_then(|color_value| color_value.as_u64())
+                    .expect("expected to get color value as u64");
+                (*node, color_value)
+            })
+            .collect();
+        Some(node_to_color)
+    }
+
+    /// Colors the graph with the given number of colors, giving up at the deadline.
+    #[cfg(feature = "smt")]
+    fn solve_coloring(&self, number_of_colors: u64, deadline: Option<Instant>) -> ColoringResult {
         let cfg = Config::new();
         let ctx = Context::new(&cfg);
         let solver = Solver::new(&ctx);
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return ColoringResult::TimedOut;
+            }
+            let mut params = Params::new(&ctx);
+            params.set_u32(
+                "timeout",
+                u32::try_from(remaining.as_millis()).unwrap_or(u32::MAX),
+            );
+            solver.set_params(&params);
+        }
         let graph = &self.graph;
 
         let node_to_int: HashMap<_, _> = graph
@@ -53,16 +155,20 @@ impl InterferenceGraph {
         }
 
         match solver.check() {
-            z3::SatResult::Unsat => {
+            SatResult::Unsat => {
                 // TODO: add logging to enable dbg statements
                 //println!("cannot color the graph with {} colors", number_of_colors);
-                return None;
+                return ColoringResult::Uncolorable;
             }
-            z3::SatResult::Unknown => {
+            SatResult::Unknown => {
                 //println!("unknown returned from the z3 solver for graph coloring");
-                return None;
+                return if deadline.is_some() {
+                    ColoringResult::TimedOut
+                } else {
+                    ColoringResult::Uncolorable
+                };
             }
-            z3::SatResult::Sat => {}
+            SatResult::Sat => {}
         }
 
         let model = solver
@@ -81,56 +187,471 @@ impl InterferenceGraph {
             node_to_color.insert(node, color_value);
         }
 
-        Some(node_to_color)
+        ColoringResult::Colored(node_to_color)
     }
 
-    /// Makes a binary search between 1 and max degree of the interference graph to find minimum
-    /// number of colors needed to color the graph.
+    /// Makes a binary search between the size of a clique and the colors of a greedy coloring to
+    /// find the minimum number of colors needed to color the graph.
+    #[cfg(feature = "smt")]
     pub fn find_min_color_count(&self) -> u64 {
+        self.search_color_count_bounds(None, 0, |_| {}).0.upper
+    }
+
+    /// Same as `find_min_color_count`, but gives up and returns `None` if the solver does not
+    /// finish before the deadline.
+    #[cfg(feature = "smt")]
+    pub fn find_min_color_count_until(&self, deadline: Instant) -> Option<u64> {
+        let (bounds, _) = self.search_color_count_bounds(Some(deadline), 0, |_| {});
+        bounds.is_exact().then_some(bounds.upper)
+    }
+
+    /// Same binary search as `find_min_color_count`, calling `on_bounds` with the bounds of the
+    /// minimum before the search and each time it tightens them. Stops once the bounds are at most
+    /// `max_gap` apart, or at the deadline, and returns the bounds along with a coloring with
+    /// `upper` colors. A gap of zero searches for the exact minimum.
+    #[cfg(feature = "smt")]
+    pub fn search_color_count_bounds(
+        &self,
+        deadline: Option<Instant>,
+        max_gap: u64,
+        mut on_bounds: impl FnMut(&ColorCountBounds),
+    ) -> (ColorCountBounds, HashMap<usize, u64>) {
+        let mut coloring = self.greedy_coloring();
+        let mut bounds = ColorCountBounds {
+            lower: self.color_count_lower_bound(),
+            upper: color_count(&coloring),
+        };
+        on_bounds(&bounds);
+        while bounds.gap() > max_gap {
+            let color_count = bounds.lower + bounds.gap() / 2;
+            match self.solve_coloring(color_count, deadline) {
+                ColoringResult::Colored(smaller_coloring) => {
+                    coloring = smaller_coloring;
+                    bounds.upper = color_count;
+                }
+                ColoringResult::Uncolorable => bounds.lower = color_count + 1,
+                ColoringResult::TimedOut => break,
+            }
+            on_bounds(&bounds);
+        }
+        (bounds, coloring)
+    }
+
+    /// Same as `search_color_count_bounds`, searching the minimum with the given strategy. The
+    /// optimizer only calls `on_bounds` before and after its single solver call. It is not called if
+    /// the initial bounds are within the gap, otherwise it searches the exact minimum.
+    #[cfg(feature = "smt")]
+    pub fn search_color_count_bounds_with(
+        &self,
+        strategy: ColorCountStrategy,
+        deadline: Option<Instant>,
+        max_gap: u64,
+        mut on_bounds: impl FnMut(&ColorCountBounds),
+    ) -> (ColorCountBounds, HashMap<usize, u64>) {
+        if strategy == ColorCountStrategy::BinarySearch {
+            return self.search_color_count_bounds(deadline, max_gap, on_bounds);
+        }
+        let mut coloring = self.greedy_coloring();
+        let mut bounds = ColorCountBounds {
+            lower: self.color_count_lower_bound(),
+            upper: color_count(&coloring),
+        };
+        on_bounds(&bounds);
+        if bounds.gap() > max_gap {
+            if let ColoringResult::Colored(min_coloring) =
+                self.solve_min_coloring(&bounds, deadline)
+            {
+                coloring = min_coloring;
+                bounds.upper = color_count(&coloring);
+                bounds.lower = bounds.upper;
+                on_bounds(&bounds);
+            }
+        }
+        (bounds, coloring)
+    }
+
+    /// Colors the graph with the fewest colors within the bounds, minimizing the largest color
+    /// with the z3 optimizer. Gives up at the deadline.
+    #[cfg(feature = "smt")]
+    fn solve_min_coloring(
+        &self,
+        bounds: &ColorCountBounds,
+        deadline: Option<Instant>,
+    ) -> ColoringResult {
+        // The optimizer takes no parameters of its own, so the timeout is set on its context.
+        let mut cfg = Config::new();
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return ColoringResult::TimedOut;
+            }
+            cfg.set_timeout_msec(u64::try_from(remaining.as_millis()).unwrap_or(u64::MAX));
+        }
+        let ctx = Context::new(&cfg);
+        let optimize = Optimize::new(&ctx);
         let graph = &self.graph;
-        let max_degreee = graph
+
+        let node_to_int: HashMap<_, _> = graph
             .node_indices()
-            .map(|node_ix| graph.edges(node_ix).count())
-            .max()
-            .expect("expected to find a maxdegree for the interference graph");
-        let mut min_color_count = 1;
-        let mut max_color_count = max_degreee + 1;
-        let mut current_min = max_color_count;
-        while min_color_count <= max_color_count {
-            let color_count = (min_color_count + max_color_count) / 2;
-            let result = self.try_coloring(color_count as u64);
-            if result.is_some() {
-                if color_count < current_min {
-                    current_min = color_count;
-                }
-                max_color_count = color_count - 1;
-            } else {
-                min_color_count = color_count + 1;
+            .map(|node_ix| graph[node_ix])
+            .map(|k| (k, Int::new_const(&ctx, format!("t{}", k))))
+            .collect();
+
+        let zero = Int::from_u64(&ctx, 0);
+        let max_color = Int::new_const(&ctx, "max_color");
+        optimize.assert(&max_color.ge(&Int::from_u64(&ctx, bounds.lower.saturating_sub(1))));
+        optimize.assert(&max_color.lt(&Int::from_u64(&ctx, bounds.upper)));
+        for node_ix in graph.node_indices() {
+            let var = &node_to_int[&graph[node_ix]];
+            optimize.assert(&var.ge(&zero));
+            optimize.assert(&var.le(&max_color));
+            for neighbor_ix in graph.neighbors(node_ix) {
+                let neighbor_var = &node_to_int[&graph[neighbor_ix]];
+                optimize.assert(&var._eq(neighbor_var).not())
             }
         }
+        optimize.minimize(&max_color);
+
+        match optimize.check(&[]) {
+            SatResult::Sat => {}
+            SatResult::Unsat => return ColoringResult::Uncolorable,
+            SatResult::Unknown => return ColoringResult::TimedOut,
+        }
+        let model = optimize
+            .get_model()
+            .expect("expected to get the model from solver");
+        let node_to_color = node_to_int
+            .iter()
+            .map(|(node, node_var)| {
+                let color_value = model
+                    .eval(node_var, true)
+                    .and_then(|color_value| color_value.as_u64())
+                    .expect("expected to get color value as u64");
+                (*node, color_value)
+            })
+            .collect();
+        ColoringResult::Colored(node_to_color)
+    }
+
+    /// Colors the nodes greedily in the order of their virtual registers. Needs no solver, but may
+    /// use more colors than necessary.
+    pub fn greedy_coloring(&self) -> HashMap<usize, u64> {
+        self.greedy_coloring_from(&HashMap::new(), &HashMap::new())
+    }
+
+    /// Same as `greedy_coloring`, but nodes are first given the color the hint gives them, as long
+    /// as no neighbor has it already. Never uses more colors than `greedy_coloring`, the hint is
+    /// dropped if keeping it would.
+    pub fn greedy_coloring_with_hint(&self, hint: &HashMap<usize, u64>) -> HashMap<usize, u64> {
+        self.greedy_coloring_with_pins(&HashMap::new(), hint)
+    }
+
+    /// Same as `greedy_coloring_with_hint`, but nodes are always given the color the pins give
+    /// them. The pins must be free of conflicts, see `pin_conflict`.
+    pub fn greedy_coloring_with_pins(
+        &self,
+        pins: &HashMap<usize, u64>,
+        hint: &HashMap<usize, u64>,
+    ) -> HashMap<usize, u64> {
+        let coloring = self.greedy_coloring_from(pins, &HashMap::new());
+        let max_color_count = color_count(&coloring);
+        let hint = hint
+            .iter()
+            .filter(|(_, color)| **color < max_color_count)
+            .map(|(node, color)| (*node, *color))
+            .collect();
+        let hinted_coloring = self.greedy_coloring_from(pins, &hint);
+        if color_count(&hinted_coloring) <= max_color_count {
+            hinted_coloring
+        } else {
+            coloring
+        }
+    }
+
+    /// Colors the graph with DSATUR, see `regalloc::dsatur`. Needs no solver, and usually uses
+    /// fewer colors than `greedy_coloring`.
+    pub fn dsatur_coloring(&self) -> HashMap<usize, u64> {
+        dsatur_coloring(&self.graph, &HashMap::new(), &HashMap::new())
+    }
+
+    /// The coloring of `greedy_coloring_with_pins` or of DSATUR keeping the same pins and hint,
+    /// whichever uses fewer colors. Needs no solver. As with the greedy coloring, the hint is
+    /// dropped if keeping it would take more colors.
+    pub fn heuristic_coloring_with_pins(
+        &self,
+        pins: &HashMap<usize, u64>,
+        hint: &HashMap<usize, u64>,
+    ) -> HashMap<usize, u64> {
+        let greedy = self.greedy_coloring_with_pins(pins, hint);
+        let coloring = dsatur_coloring(&self.graph, pins, &HashMap::new());
+        let max_color_count = color_count(&coloring);
+        let hint = hint
+            .iter()
+            .filter(|(_, color)| **color < max_color_count)
+            .map(|(node, color)| (*node, *color))
+            .collect();
+        let hinted_coloring = dsatur_coloring(&self.graph, pins, &hint);
+        let dsatur = if color_count(&hinted_coloring) <= max_color_count {
+            hinted_coloring
+        } else {
+            coloring
+        };
+        if color_count(&dsatur) < color_count(&greedy) {
+            dsatur
+        } else {
+            greedy
+        }
+    }
+
+    /// Two interfering nodes the pins give the same color, the first in the order of their
+    /// virtual registers. No coloring keeps both pins.
+    pub fn pin_conflict(&self, pins: &HashMap<usize, u64>) -> Option<(usize, usize)> {
+        let graph = &self.graph;
+        let mut conflicts = graph
+            .edge_indices()
+            .filter_map(|edge_ix| graph.edge_endpoints(edge_ix))
+            .map(|(lhs_ix, rhs_ix)| {
+                let (lhs, rhs) = (graph[lhs_ix], graph[rhs_ix]);
+                (lhs.min(rhs), lhs.max(rhs))
+            })
+            .filter(|(lhs, rhs)| {
+                pins.get(lhs)
+                    .is_some_and(|color| pins.get(rhs) == Some(color))
+            })
+            .collect::<Vec<_>>();
+        conflicts.sort();
+        conflicts.into_iter().next()
+    }
+
+    fn greedy_coloring_from(
+        &self,
+        pins: &HashMap<usize, u64>,
+        hint: &HashMap<usize, u64>,
+    ) -> HashMap<usize, u64> {
+        let graph = &self.graph;
+        let mut node_indices = graph.node_indices().collect::<Vec<_>>();
+        node_indices.sort_by_key(|node_ix| graph[*node_ix]);
+        let mut node_to_color: HashMap<usize, u64> = node_indices
+            .iter()
+            .filter_map(|node_ix| Some((graph[*node_ix], *pins.get(&graph[*node_ix])?)))
+            .collect();
+        for node_ix in &node_indices {
+            if node_to_color.contains_key(&graph[*node_ix]) {
+                continue;
+            }
+            let Some(color) = hint.get(&graph[*node_ix]) else {
+                continue;
+            };
+            let taken = graph
+                .neighbors(*node_ix)
+                .any(|neighbor_ix| node_to_color.get(&graph[neighbor_ix]) == Some(color));
+            if !taken {
+                node_to_color.insert(graph[*node_ix], *color);
+            }
+        }
+        for node_ix in node_indices {
+            if node_to_color.contains_key(&graph[node_ix]) {
+                continue;
+            }
+            let neighbor_colors = graph
+                .neighbors(node_ix)
+                .filter_map(|neighbor_ix| node_to_color.get(&graph[neighbor_ix]))
+                .collect::<HashSet<_>>();
+            let color = (0..)
+                .find(|color| !neighbor_colors.contains(color))
+                .expect("a free color exists");
+            node_to_color.insert(graph[node_ix], color);
+        }
+        node_to_color
+    }
+
+    /// Size of the largest clique for graphs of up to `EXACT_CLIQUE_MAX_NODES` nodes, and of a
+    /// clique found greedily for larger ones. Every node of a clique needs its own color, so this
+    /// is a lower bound of the minimum color count, which it usually meets.
+    pub fn color_count_lower_bound(&self) -> u64 {
+        match self.graph.node_count() <= EXACT_CLIQUE_MAX_NODES {
+            true => self.max_clique_size(),
+            false => self.greedy_clique_size(),
+        }
+    }
+
+    /// Size of the largest clique, by a branch and bound over the nodes as bits of a set.
+    fn max_clique_size(&self) -> u64 {
+        let graph = &self.graph;
+        let neighbors = graph
+            .node_indices()
+            .map(|node_ix| {
+                graph
+                    .neighbors(node_ix)
+                    .fold(0u64, |set, neighbor_ix| set | 1 << neighbor_ix.index())
+            })
+            .collect::<Vec<_>>();
+        let nodes = match graph.node_count() {
+            EXACT_CLIQUE_MAX_NODES => u64::MAX,
+            node_count => (1 << node_count) - 1,
+        };
+        let mut largest = 0;
+        grow_clique(&neighbors, 0, nodes, &mut largest);
+        largest
+    }
+
+    /// Size of a clique found greedily, starting from the nodes with the most neighbors.
+    fn greedy_clique_size(&self) -> u64 {
+        let graph = &self.graph;
+        let mut node_indices = graph.node_indices().collect::<Vec<_>>();
+        node_indices.sort_by_key(|node_ix| std::cmp::Reverse(graph.neighbors(*node_ix).count()));
+        let mut clique = vec![];
+        for node_ix in node_indices {
+            if clique
+                .iter()
+                .all(|member| graph.contains_edge(*member, node_ix))
+            {
+                clique.push(node_ix);
+            }
+        }
+        clique.len() as u64
+    }
+}
+
+/// Grows a clique of `size` nodes by each of the candidates, all of which are adjacent to every
+/// node of the clique, keeping the size of the largest clique in `largest`. Branches which cannot
+/// grow larger than it even with every candidate are cut.
+fn grow_clique(neighbors: &[u64], size: u64, mut candidates: u64, largest: &mut u64) {
+    *largest = (*largest).max(size);
+    while size + u64::from(candidates.count_ones()) > *largest {
+        let node = candidates.trailing_zeros() as usize;
+        candidates &= !(1 << node);
+        grow_clique(neighbors, size + 1, candidates & neighbors[node], largest);
+    }
+}
+
+/// Number of colors the coloring uses, assuming they are numbered from zero.
+fn color_count(coloring: &HashMap<usize, u64>) -> u64 {
+    coloring.values().max().map_or(0, |max_color| max_color + 1)
+}
+
+/// How the minimum number of colors of a graph is searched for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorCountStrategy {
+    /// Binary search between the bounds, one satisfiability check of a color count per step.
+    #[default]
+    BinarySearch,
+    /// A single call of the z3 optimizer, minimizing the largest color directly.
+    Optimize,
+}
+
+impl Display for ColorCountStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorCountStrategy::BinarySearch => write!(f, "binary-search"),
+            ColorCountStrategy::Optimize => write!(f, "optimize"),
+        }
+    }
+}
+
+impl FromStr for ColorCountStrategy {
+    type Err = String;
 
-        current_min as u64
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "binary-search" => Ok(ColorCountStrategy::BinarySearch),
+            "optimize" => Ok(ColorCountStrategy::Optimize),
+            strategy => Err(format!(
+                "unknown color count strategy `{strategy}`, expected `binary-search` or `optimize`"
+            )),
+        }
+    }
+}
+
+/// How storage units are allocated to the virtual registers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegAllocStrategy {
+    /// The minimum number of storage units, found with the z3 solver. Falls back to `Heuristic`
+    /// without the `smt` feature.
+    #[default]
+    Exact,
+    /// The fewer storage units of a greedy and a DSATUR coloring, along with a lower bound of the
+    /// minimum unless they meet it. Needs no solver.
+    Heuristic,
+}
+
+impl Display for RegAllocStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegAllocStrategy::Exact => write!(f, "exact"),
+            RegAllocStrategy::Heuristic => write!(f, "heuristic"),
+        }
     }
 }
 
+impl FromStr for RegAllocStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "exact" => Ok(RegAllocStrategy::Exact),
+            "heuristic" => Ok(RegAllocStrategy::Heuristic),
+            strategy => Err(format!(
+                "unknown storage allocation strategy `{strategy}`, expected `exact` or `heuristic`"
+            )),
+        }
+    }
+}
+
+/// Bounds of the minimum number of colors of a graph, the minimum is within `lower..=upper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorCountBounds {
+    pub lower: u64,
+    pub upper: u64,
+}
+
+impl ColorCountBounds {
+    pub fn gap(&self) -> u64 {
+        self.upper.saturating_sub(self.lower)
+    }
+
+    /// Whether the bounds meet, i.e the minimum is known.
+    pub fn is_exact(&self) -> bool {
+        self.gap() == 0
+    }
+}
+
+/// Version of the z3 solver the storage allocation is linked against, i.e `4.8.12.0`.
+#[cfg(feature = "smt")]
+pub fn solver_version() -> String {
+    let (mut major, mut minor, mut build, mut revision) = (0, 0, 0, 0);
+    // SAFETY: `Z3_get_version` only writes the four numbers, and needs no context.
+    unsafe { z3_sys::Z3_get_version(&mut major, &mut minor, &mut build, &mut revision) };
+    format!("{major}.{minor}.{build}.{revision}")
+}
+
+/// Outcome of coloring the graph with a given number of colors.
+#[cfg(feature = "smt")]
+enum ColoringResult {
+    Colored(HashMap<usize, u64>),
+    Uncolorable,
+    TimedOut,
+}
+
 impl<'a> InterferenceGraphBuilder<'a> {
     pub fn new(liveness_analysis: &'a [HashSet<usize>]) -> Self {
         Self { liveness_analysis }
     }
 
-    fn number_of_variables_used(&self) -> usize {
-        let mut all_vars: HashSet<usize> = HashSet::new();
+    /// Variables live at some point, in ascending order. Variables which are never read, i.e the
+    /// final fluids, are not among them.
+    fn variables_used(&self) -> BTreeSet<usize> {
+        let mut all_vars = BTreeSet::new();
         for var_set in self.liveness_analysis {
             all_vars.extend(var_set);
         }
-        all_vars.len()
+        all_vars
     }
 
     pub fn build(&self) -> InterferenceGraph {
         let mut graph = UnGraph::default();
-        let number_of_variables_used = self.number_of_variables_used();
         let mut var_ix_to_node_ix = HashMap::new();
-        for var_ix in 0..number_of_variables_used {
+        for var_ix in self.variables_used() {
             let node_ix = graph.add_node(var_ix);
             var_ix_to_node_ix.insert(var_ix, node_ix);
         }
@@ -169,6 +690,21 @@ mod tests {
         assert_eq!(graph.graph.edge_count(), 4);
     }
 
+    #[cfg(feature = "smt")]
+    #[test]
+    fn unread_variables_are_left_out() {
+        // Variable 1 is written but never read.
+        let liveness_analysis = vec![
+            vec![0].into_iter().collect(),
+            vec![0, 2].into_iter().collect(),
+        ];
+        let graph = InterferenceGraphBuilder::new(&liveness_analysis).build();
+
+        assert_eq!(graph.graph.node_count(), 2);
+        assert_eq!(graph.find_min_color_count(), 2);
+    }
+
+    #[cfg(feature = "smt")]
     #[test]
     fn test_try_coloring_success() {
         let liveness_analysis = vec![
@@ -186,6 +722,7 @@ mod tests {
         assert_eq!(coloring.len(), 5);
     }
 
+    #[cfg(feature = "smt")]
     #[test]
     fn test_try_coloring_failure() {
         let liveness_analysis = vec![
@@ -201,6 +738,7 @@ mod tests {
         assert!(coloring.is_none());
     }
 
+    #[cfg(feature = "smt")]
     #[test]
     fn test_find_min_color_count() {
         let liveness_analysis = vec![
@@ -215,4 +753,306 @@ mod tests {
         let min_colors = graph.find_min_color_count();
         assert_eq!(min_colors, 2);
     }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn color_count_bounds_tighten_until_the_gap() {
+        // An odd cycle needs three colors, while its largest clique has two nodes.
+        let liveness_analysis = vec![
+            vec![0, 1].into_iter().collect(),
+            vec![1, 2].into_iter().collect(),
+            vec![2, 3].into_iter().collect(),
+            vec![3, 4].into_iter().collect(),
+            vec![4, 0].into_iter().collect(),
+        ];
+        let graph = InterferenceGraphBuilder::new(&liveness_analysis).build();
+        let initial = ColorCountBounds { lower: 2, upper: 3 };
+
+        let mut reported = vec![];
+        let (bounds, coloring) =
+            graph.search_color_count_bounds(None, 0, |bounds| reported.push(*bounds));
+        assert_eq!(bounds, ColorCountBounds { lower: 3, upper: 3 });
+        assert!(bounds.is_exact());
+        assert_eq!(reported, vec![initial, bounds]);
+        assert_eq!(coloring.len(), 5);
+
+        // A gap of one is already met by the greedy bounds, so the solver is not asked.
+        let (bounds, _) = graph.search_color_count_bounds(None, 1, |_| {});
+        assert_eq!(bounds, initial);
+        // At the deadline, the bounds found so far are returned.
+        let (bounds, coloring) = graph.search_color_count_bounds(Some(Instant::now()), 0, |_| {});
+        assert_eq!((bounds, bounds.gap()), (initial, 1));
+        assert_eq!(coloring.values().max(), Some(&2));
+    }
+
+    /// Graph of the given number of nodes and edges between them.
+    #[cfg(feature = "smt")]
+    fn graph_of(
+        nodes: usize,
+        edges: impl IntoIterator<Item = (usize, usize)>,
+    ) -> InterferenceGraph {
+        let mut graph = UnGraph::<usize, ()>::default();
+        let nodes = (0..nodes)
+            .map(|vreg| graph.add_node(vreg))
+            .collect::<Vec<_>>();
+        for (lhs, rhs) in edges {
+            graph.add_edge(nodes[lhs], nodes[rhs], ());
+        }
+        InterferenceGraph::new(graph)
+    }
+
+    /// Compares the time the optimizer and the binary search take to find the minimum number of
+    /// colors of crown, Mycielski and random graphs. Run with
+    /// `cargo test -p fluido-ir --release -- --ignored --nocapture color_count_strategies`.
+    #[cfg(feature = "smt")]
+    #[test]
+    #[ignore = "benchmark"]
+    fn color_count_strategies_benchmark() {
+        let crown = |pairs: usize| {
+            let edges = (0..pairs).flat_map(|u| {
+                (0..pairs)
+                    .filter(move |v| *v != u)
+                    .map(move |v| (2 * u, 2 * v + 1))
+            });
+            graph_of(2 * pairs, edges)
+        };
+        // Mycielski graphs need one more color with each step, while having no triangles.
+        let mycielski = |steps: usize| {
+            let (mut nodes, mut edges) = (2, vec![(0, 1)]);
+            for _ in 0..steps {
+                let shadow = |node| node + nodes;
+                let apex = 2 * nodes;
+                edges.extend(
+                    edges
+                        .clone()
+                        .into_iter()
+                        .flat_map(|(lhs, rhs)| [(lhs, shadow(rhs)), (shadow(lhs), rhs)]),
+                );
+                edges.extend((0..nodes).map(|node| (shadow(node), apex)));
+                nodes = 2 * nodes + 1;
+            }
+            graph_of(nodes, edges)
+        };
+        // Random graphs, from a xorshift generator so that every run benchmarks the same ones.
+        let random = |nodes: usize, percent: u64, mut seed: u64| {
+            let mut edges = vec![];
+            for lhs in 0..nodes {
+                for rhs in lhs + 1..nodes {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    if seed % 100 < percent {
+                        edges.push((lhs, rhs));
+                    }
+                }
+            }
+            graph_of(nodes, edges)
+        };
+        let graphs = [
+            ("crown 40", crown(20)),
+            ("crown 60", crown(30)),
+            ("mycielski 11", mycielski(2)),
+            ("mycielski 23", mycielski(3)),
+            ("random 40", random(40, 30, 1)),
+            ("random 60", random(60, 20, 2)),
+        ];
+        for (name, graph) in graphs {
+            let timed = |strategy| {
+                let started = Instant::now();
+                let (bounds, _) = graph.search_color_count_bounds_with(strategy, None, 0, |_| {});
+                (bounds, started.elapsed())
+            };
+            let (binary_search, binary_search_time) = timed(ColorCountStrategy::BinarySearch);
+            let (optimize, optimize_time) = timed(ColorCountStrategy::Optimize);
+            assert_eq!(optimize, binary_search);
+            println!(
+                "{name}: {} colors, binary search {binary_search_time:?}, optimize {optimize_time:?} \
+                 ({:.1}x)",
+                optimize.upper,
+                binary_search_time.as_secs_f64() / optimize_time.as_secs_f64()
+            );
+        }
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn optimizer_finds_the_minimum_in_one_call() {
+        // A crown graph colored greedily in the order u0, v0, u1, v1, .. takes a color per pair,
+        // while it is bipartite.
+        let mut crown = UnGraph::<usize, ()>::default();
+        let nodes = (0..12).map(|vreg| crown.add_node(vreg)).collect::<Vec<_>>();
+        for u in (0..12).step_by(2) {
+            for v in (1..12).step_by(2) {
+                if v != u + 1 {
+                    crown.add_edge(nodes[u], nodes[v], ());
+                }
+            }
+        }
+        let graph = InterferenceGraph::new(crown);
+        let initial = ColorCountBounds { lower: 2, upper: 6 };
+
+        let mut reported = vec![];
+        let (bounds, coloring) =
+            graph.search_color_count_bounds_with(ColorCountStrategy::Optimize, None, 0, |bounds| {
+                reported.push(*bounds)
+            });
+        assert_eq!(bounds, ColorCountBounds { lower: 2, upper: 2 });
+        assert_eq!(reported, vec![initial, bounds]);
+        assert_eq!(color_count(&coloring), 2);
+        assert!(graph.graph.edge_indices().all(|edge_ix| {
+            let (lhs, rhs) = graph.graph.edge_endpoints(edge_ix).unwrap();
+            coloring[&graph.graph[lhs]] != coloring[&graph.graph[rhs]]
+        }));
+        let (binary_search, _) =
+            graph.search_color_count_bounds_with(ColorCountStrategy::BinarySearch, None, 0, |_| {});
+        assert_eq!(binary_search, bounds);
+
+        // Bounds within the gap, or a deadline already passed, leave the greedy coloring.
+        let optimize = |deadline, max_gap| {
+            graph
+                .search_color_count_bounds_with(
+                    ColorCountStrategy::Optimize,
+                    deadline,
+                    max_gap,
+                    |_| {},
+                )
+                .0
+        };
+        assert_eq!(optimize(None, 4), initial);
+        assert_eq!(optimize(Some(Instant::now()), 0), initial);
+
+        assert_eq!("optimize".parse(), Ok(ColorCountStrategy::Optimize));
+        assert_eq!(
+            ColorCountStrategy::BinarySearch.to_string().parse(),
+            Ok(ColorCountStrategy::BinarySearch)
+        );
+        assert!("linear".parse::<ColorCountStrategy>().is_err());
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn hinted_colors_are_kept() {
+        let liveness_analysis = vec![
+            vec![0, 1].into_iter().collect(),
+            vec![1, 2].into_iter().collect(),
+            vec![2, 3].into_iter().collect(),
+        ];
+        let graph = InterferenceGraphBuilder::new(&liveness_analysis).build();
+        let hint = HashMap::from([(0, 1), (1, 0), (2, 1), (3, 0)]);
+
+        assert_eq!(graph.try_coloring_with_hint(2, &hint), Some(hint.clone()));
+        assert_eq!(graph.greedy_coloring_with_hint(&hint), hint);
+
+        // Conflicting hints are kept as far as possible, out of range ones are ignored.
+        let hint = HashMap::from([(0, 1), (1, 1), (2, 5)]);
+        let coloring = graph.try_coloring_with_hint(2, &hint).unwrap();
+        assert!(coloring[&0] == 1 || coloring[&1] == 1);
+        assert!(coloring.values().all(|color| *color < 2));
+        let coloring = graph.greedy_coloring_with_hint(&hint);
+        assert_eq!((coloring[&0], coloring[&1], coloring[&2]), (1, 0, 1));
+    }
+
+    #[test]
+    fn pinned_colors_are_always_kept() {
+        let liveness_analysis = vec![
+            vec![0, 1].into_iter().collect(),
+            vec![1, 2].into_iter().collect(),
+            vec![2, 3].into_iter().collect(),
+        ];
+        let graph = InterferenceGraphBuilder::new(&liveness_analysis).build();
+
+        // Pins are kept even where they need more colors, hints are not.
+        let pins = HashMap::from([(0, 2), (2, 2)]);
+        let hint = HashMap::from([(1, 2)]);
+        assert_eq!(graph.pin_conflict(&pins), None);
+        let coloring = graph.greedy_coloring_with_pins(&pins, &hint);
+        assert_eq!((coloring[&0], coloring[&1], coloring[&2]), (2, 0, 2));
+        #[cfg(feature = "smt")]
+        {
+            assert_eq!(graph.try_coloring_with_pins(2, &pins, &hint), None);
+            let coloring = graph.try_coloring_with_pins(3, &pins, &hint).unwrap();
+            assert_eq!((coloring[&0], coloring[&2]), (2, 2));
+        }
+
+        // Interfering nodes cannot share a pinned color.
+        let pins = HashMap::from([(3, 0), (1, 1), (2, 1)]);
+        assert_eq!(graph.pin_conflict(&pins), Some((1, 2)));
+    }
+
+    #[test]
+    fn heuristic_coloring_takes_the_fewer_colors() {
+        let mut crown = UnGraph::<usize, ()>::default();
+        let nodes = (0..8).map(|vreg| crown.add_node(vreg)).collect::<Vec<_>>();
+        for u in (0..8).step_by(2) {
+            for v in (1..8).step_by(2) {
+                if v != u + 1 {
+                    crown.add_edge(nodes[u], nodes[v], ());
+                }
+            }
+        }
+        let graph = InterferenceGraph::new(crown);
+        assert_eq!(color_count(&graph.greedy_coloring()), 4);
+        assert_eq!(color_count(&graph.dsatur_coloring()), 2);
+        let no_pins = HashMap::new();
+        let coloring = graph.heuristic_coloring_with_pins(&no_pins, &HashMap::from([(0, 1)]));
+        assert_eq!((color_count(&coloring), coloring[&0]), (2, 1));
+        // A hint taking more colors is dropped.
+        let coloring = graph.heuristic_coloring_with_pins(&no_pins, &HashMap::from([(0, 3)]));
+        assert_eq!(color_count(&coloring), 2);
+    }
+
+    #[test]
+    fn test_color_count_bounds_without_solver() {
+        let liveness_analysis = vec![
+            vec![0, 1, 2].into_iter().collect(),
+            vec![1, 2].into_iter().collect(),
+            vec![2, 3].into_iter().collect(),
+        ];
+        let builder = InterferenceGraphBuilder::new(&liveness_analysis);
+        let graph = builder.build();
+
+        assert_eq!(graph.color_count_lower_bound(), 3);
+        let coloring = graph.greedy_coloring();
+        assert_eq!(coloring.len(), 4);
+        assert_eq!(coloring.values().max(), Some(&2));
+        // The bounds meet, so the minimum is known without asking the solver.
+        #[cfg(feature = "smt")]
+        assert_eq!(graph.find_min_color_count_until(Instant::now()), Some(3));
+    }
+
+    #[test]
+    fn clique_bound_is_exact_for_small_graphs() {
+        // The node with the most neighbors is part of no triangle, which the greedy search misses.
+        let mut graph = UnGraph::<usize, ()>::default();
+        let nodes = (0..9).map(|vreg| graph.add_node(vreg)).collect::<Vec<_>>();
+        for leaf in 1..=5 {
+            graph.add_edge(nodes[0], nodes[leaf], ());
+        }
+        for (lhs, rhs) in [(6, 7), (7, 8), (6, 8)] {
+            graph.add_edge(nodes[lhs], nodes[rhs], ());
+        }
+        let graph = InterferenceGraph::new(graph);
+        assert_eq!(graph.greedy_clique_size(), 2);
+        assert_eq!(graph.color_count_lower_bound(), 3);
+
+        // Every node interferes with every other one.
+        let mut complete = UnGraph::<usize, ()>::default();
+        let nodes = (0..EXACT_CLIQUE_MAX_NODES)
+            .map(|vreg| complete.add_node(vreg))
+            .collect::<Vec<_>>();
+        for (ix, lhs) in nodes.iter().enumerate() {
+            for rhs in &nodes[ix + 1..] {
+                complete.add_edge(*lhs, *rhs, ());
+            }
+        }
+        let complete = InterferenceGraph::new(complete);
+        assert_eq!(
+            complete.color_count_lower_bound(),
+            EXACT_CLIQUE_MAX_NODES as u64
+        );
+        assert_eq!(
+            InterferenceGraph::new(UnGraph::default()).color_count_lower_bound(),
+            0
+        );
+    }
 }
@@ -1 +1,3 @@
+pub mod dsatur;
 pub mod interference_graph;
+pub mod register_allocator;
@@ -0,0 +1,181 @@
+//! Assigning the virtual registers of the flat ir to physical storage units.
+//!
+//! Virtual registers live at the same time interfere, and are given different storage units by a
+//! coloring of the interference graph. Registers which are never read, i.e the final fluid, are
+//! not part of the graph, and are given the lowest storage unit free once they are written. The
+//! ir is then rewritten with the storage unit of each register in its place, so that it can be
+//! executed on a chip.
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    analysis::liveness::{LivenessAnalysis, LivenessResult},
+    ir::{IROp, Operand},
+    pass_manager::AnalysisPass,
+    regalloc::interference_graph::{InterferenceGraph, InterferenceGraphBuilder},
+};
+
+/// Allocates storage units to the virtual registers of an ir.
+pub struct RegisterAllocator {
+    /// Virtual registers live before each op of the ir.
+    live_sets: Vec<HashSet<usize>>,
+    /// Virtual register each op of the ir writes to.
+    targets: Vec<Option<usize>>,
+    interference_graph: InterferenceGraph,
+}
+
+impl RegisterAllocator {
+    pub fn new(ir: &[IROp]) -> Self {
+        let live_sets = LivenessAnalysis::default()
+            .analyze(ir)
+            .downcast_ref::<LivenessResult>()
+            .expect("liveness analysis returns a `LivenessResult`")
+            .sets_per_ir
+            .clone();
+        let interference_graph = InterferenceGraphBuilder::new(&live_sets).build();
+        Self {
+            targets: ir.iter().map(IROp::target_vreg).collect(),
+            live_sets,
+            interference_graph,
+        }
+    }
+
+    pub fn interference_graph(&self) -> &InterferenceGraph {
+        &self.interference_graph
+    }
+
+    /// Allocates the minimum number of storage units, coloring the interference graph with the
+    /// fewest colors it needs.
+    #[cfg(feature = "smt")]
+    pub fn allocate(&self) -> RegisterAllocation {
+        let color_count = self.interference_graph.find_min_color_count();
+        let coloring = self
+            .interference_graph
+            .try_coloring(color_count)
+            .expect("the graph is colorable with its minimum color count");
+        self.allocate_with_coloring(coloring)
+    }
+
+    /// Allocates storage units greedily, see `InterferenceGraph::greedy_coloring`. Needs no
+    /// solver, but may use more units than necessary.
+    pub fn allocate_greedily(&self) -> RegisterAllocation {
+        self.allocate_with_coloring(self.interference_graph.greedy_coloring())
+    }
+
+    /// Allocates the storage units of the given coloring of the interference graph, i.e one found
+    /// with an allocation hint, along with units for the registers which are never read.
+    pub fn allocate_with_coloring(&self, coloring: HashMap<usize, u64>) -> RegisterAllocation {
+        let mut storage_units = coloring;
+        for (ix, target) in self.targets.iter().enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+            if storage_units.contains_key(target) {
+                continue;
+            }
+            let taken = self
+                .live_sets
+                .get(ix + 1)
+                .into_iter()
+                .flatten()
+                .filter_map(|vreg| storage_units.get(vreg))
+                .collect::<HashSet<_>>();
+            let storage_unit = (0..)
+                .find(|storage_unit| !taken.contains(storage_unit))
+                .expect("a free storage unit exists");
+            storage_units.insert(*target, storage_unit);
+        }
+        RegisterAllocation { storage_units }
+    }
+}
+
+/// Storage unit of each virtual register of an ir.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterAllocation {
+    storage_units: HashMap<usize, u64>,
+}
+
+impl RegisterAllocation {
+    /// Storage unit allocated to the virtual register.
+    pub fn storage_unit(&self, vreg: usize) -> Option<u64> {
+        self.storage_units.get(&vreg).copied()
+    }
+
+    /// Storage unit of each virtual register.
+    pub fn storage_units(&self) -> &HashMap<usize, u64> {
+        &self.storage_units
+    }
+
+    /// Number of storage units the allocation uses.
+    pub fn storage_units_needed(&self) -> u64 {
+        self.storage_units
+            .values()
+            .max()
+            .map_or(0, |max_unit| max_unit + 1)
+    }
+
+    /// The ir with each virtual register replaced by its storage unit. Registers missing from the
+    /// allocation are kept as they are.
+    pub fn rewrite(&self, ir: &[IROp]) -> Vec<IROp> {
+        let physical = |operand: &Operand| match operand {
+            Operand::VirtualRegister(vreg) => self
+                .storage_unit(*vreg)
+                .map_or_else(|| operand.clone(), Operand::StorageUnit),
+            operand => operand.clone(),
+        };
+        ir.iter()
+            .map(|op| match op {
+                IROp::Store((value, target)) => IROp::Store((physical(value), physical(target))),
+                IROp::Mix((lhs, rhs, target)) => {
+                    IROp::Mix((physical(lhs), physical(rhs), physical(target)))
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RegisterAllocator;
+    use crate::{graph::Graph, ir::IROp, ir_builder::IRBuilder};
+    use fluido_parse::parser::Parse;
+    use fluido_types::expr::Expr;
+
+    fn lower(expr: &str) -> Vec<IROp> {
+        let expr = Expr::parse(expr).unwrap();
+        IRBuilder::default().build_ir(Graph::from(&expr))
+    }
+
+    fn listing(ir: &[IROp]) -> Vec<String> {
+        ir.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn registers_are_rewritten_to_storage_units() {
+        let ir = lower("(mix (mix (fluid 0.2 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))");
+        let allocator = RegisterAllocator::new(&ir);
+        let allocation = allocator.allocate_greedily();
+        // The final fluid is never read, and takes the first unit freed by its operands.
+        assert_eq!(allocation.storage_unit(4), Some(0));
+        assert_eq!(allocation.storage_units_needed(), 2);
+        assert_eq!(
+            listing(&allocation.rewrite(&ir)),
+            [
+                "store (fluid 0.2 1.0) $0",
+                "store (fluid 0.0 1.0) $1",
+                "mix $0 $1 $0",
+                "store (fluid 0.0 2.0) $1",
+                "mix $0 $1 $0",
+            ]
+        );
+        #[cfg(feature = "smt")]
+        assert_eq!(allocator.allocate().storage_units_needed(), 2);
+
+        // A single input is never read either, and still needs a unit.
+        let ir = lower("(fluid 0.2 1.0)");
+        let allocation = RegisterAllocator::new(&ir).allocate_greedily();
+        assert_eq!(
+            listing(&allocation.rewrite(&ir)),
+            ["store (fluid 0.2 1.0) $0"]
+        );
+    }
+}
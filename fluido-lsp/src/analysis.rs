@@ -0,0 +1,176 @@
+//! Language features for `.mix` files, independent of the protocol transport.
+//!
+//! A `.mix` file holds a single mixer expression. Every request reparses the whole document with
+//! the error-tolerant parser, documents are small enough for this to be instant.
+use fluido_parse::diagnostics::{ParseOutcome, ParseWithDiagnostics, Span};
+use fluido_types::{certificate::Certificate, expr::Expr, pretty::PrettyPrinter};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, Hover, HoverContents, MarkupContent, MarkupKind, Position,
+    Range, TextEdit,
+};
+
+/// Converts between byte offsets and LSP positions, which count UTF-16 code units per line.
+struct LineIndex<'a> {
+    text: &'a str,
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(ix, _)| ix + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (ix, char) in self.text[line_start..].char_indices() {
+            if units >= position.character as usize || char == '\n' {
+                return line_start + ix;
+            }
+            units += char.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+fn parse(text: &str) -> ParseOutcome<Expr> {
+    Expr::parse_with_diagnostics(text)
+}
+
+/// Syntax errors of the document.
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let index = LineIndex::new(text);
+    parse(text)
+        .diagnostics
+        .into_iter()
+        .map(|diagnostic| Diagnostic {
+            range: index.range(diagnostic.span),
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("fluido".to_string()),
+            message: diagnostic.message,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Concentration and volume of the innermost expression at the position.
+pub fn hover(text: &str, position: Position) -> Option<Hover> {
+    let index = LineIndex::new(text);
+    let offset = index.offset(position);
+    let node = parse(text)
+        .nodes
+        .into_iter()
+        .filter(|node| node.span.start <= offset && offset < node.span.end)
+        .min_by_key(|node| node.span.end - node.span.start)?;
+    let result = Certificate::from_expr(&node.value).ok()?.result;
+    let value = format!(
+        "concentration `{}` ({})  \nvolume `{}` ({})",
+        result.concentration,
+        f64::from(result.concentration),
+        result.volume,
+        f64::from(result.volume)
+    );
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(index.range(node.span)),
+    })
+}
+
+/// Edits replacing the document with its pretty-printed form. Documents with syntax errors are
+/// left as they are.
+pub fn format(text: &str) -> Option<Vec<TextEdit>> {
+    let outcome = parse(text);
+    if !outcome.diagnostics.is_empty() {
+        return None;
+    }
+    let formatted = format!("{}\n", PrettyPrinter::default().print(&outcome.value?));
+    if formatted == text {
+        return Some(vec![]);
+    }
+    let index = LineIndex::new(text);
+    let whole = Span {
+        start: 0,
+        end: text.len(),
+    };
+    Some(vec![TextEdit::new(index.range(whole), formatted)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diagnostics, format, hover, LineIndex};
+    use lsp_types::{HoverContents, Position, Range};
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "(mix\n  ; é\n  x)";
+        let index = LineIndex::new(text);
+        let x = text.rfind('x').unwrap();
+        assert_eq!(index.position(x), Position::new(2, 2));
+        assert_eq!(index.offset(Position::new(2, 2)), x);
+        // `é` is two bytes but a single UTF-16 unit.
+        let line_end = text.find("\n  x").unwrap();
+        assert_eq!(index.position(line_end), Position::new(1, 5));
+        assert_eq!(index.offset(Position::new(1, 40)), line_end);
+    }
+
+    #[test]
+    fn diagnostics_have_ranges() {
+        let diagnostics = diagnostics("(mix\n  (fluid 0.2 x)\n  (fluid 0.3 1.0))");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "invalid number `x`");
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(1, 13), Position::new(1, 14))
+        );
+    }
+
+    #[test]
+    fn hover_evaluates_innermost_node() {
+        let text = "(mix\n  (fluid 0.2 1.0)\n  (fluid 0.0 1.0))";
+        let hover_value = |position| match hover(text, position).unwrap().contents {
+            HoverContents::Markup(markup) => markup.value,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            hover_value(Position::new(0, 1)),
+            "concentration `1/10` (0.1)  \nvolume `2/1` (2)"
+        );
+        assert_eq!(
+            hover_value(Position::new(1, 5)),
+            "concentration `1/5` (0.2)  \nvolume `1/1` (1)"
+        );
+        assert!(hover(text, Position::new(3, 0)).is_none());
+    }
+
+    #[test]
+    fn format_pretty_prints() {
+        let edits = format("(mix (fluid 0.2 1.0) (fluid 0.0 1.0))").unwrap();
+        assert_eq!(
+            edits[0].new_text,
+            "(mix\n  (fluid 0.2 1.0)\n  (fluid 0.0 1.0))\n"
+        );
+        assert_eq!(format(&edits[0].new_text), Some(vec![]));
+        assert_eq!(format("(mix (fluid 0.2 1.0)"), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! Language server for `.mix` files, speaking LSP over stdio.
+//!
+//! Provides syntax diagnostics, hover with the concentration and volume each expression
+//! evaluates to, and formatting.
+mod analysis;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait, PublishDiagnostics,
+    },
+    request::{Formatting, HoverRequest, Request as RequestTrait},
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, HoverParams, HoverProviderCapability, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Url,
+};
+use std::collections::HashMap;
+
+fn main() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    main_loop(&connection)?;
+    // The writer thread only finishes once the connection is dropped.
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn main_loop(connection: &Connection) -> anyhow::Result<()> {
+    // Open documents, synced in full on every change.
+    let mut documents: HashMap<Url, String> = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = handle_request(&request, &documents);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                handle_notification(connection, notification, &mut documents)?
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(request: &Request, documents: &HashMap<Url, String>) -> Response {
+    let id = request.id.clone();
+    let params = request.params.clone();
+    let result = match request.method.as_str() {
+        HoverRequest::METHOD => serde_json::from_value::<HoverParams>(params).map(|params| {
+            let position = params.text_document_position_params;
+            let hover = documents
+                .get(&position.text_document.uri)
+                .and_then(|text| analysis::hover(text, position.position));
+            serde_json::to_value(hover)
+        }),
+        Formatting::METHOD => {
+            serde_json::from_value::<DocumentFormattingParams>(params).map(|params| {
+                let edits = documents
+                    .get(&params.text_document.uri)
+                    .and_then(|text| analysis::format(text));
+                serde_json::to_value(edits)
+            })
+        }
+        method => {
+            return Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled method `{method}`"),
+            )
+        }
+    };
+    match result {
+        Ok(Ok(result)) => Response::new_ok(id, result),
+        Ok(Err(err)) | Err(err) => {
+            Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string())
+        }
+    }
+}
+
+fn handle_notification(
+    connection: &Connection,
+    notification: Notification,
+    documents: &mut HashMap<Url, String>,
+) -> anyhow::Result<()> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+            let uri = params.text_document.uri;
+            publish_diagnostics(connection, &uri, &params.text_document.text)?;
+            documents.insert(uri, params.text_document.text);
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+            // Full sync, so the last change holds the whole document.
+            if let Some(change) = params.content_changes.into_iter().last() {
+                let uri = params.text_document.uri;
+                publish_diagnostics(connection, &uri, &change.text)?;
+                documents.insert(uri, change.text);
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+            documents.remove(&params.text_document.uri);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn publish_diagnostics(connection: &Connection, uri: &Url, text: &str) -> anyhow::Result<()> {
+    let params = PublishDiagnosticsParams::new(uri.clone(), analysis::diagnostics(text), None);
+    let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+    connection
+        .sender
+        .send(Message::Notification(notification))?;
+    Ok(())
+}
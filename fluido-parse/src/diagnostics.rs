@@ -0,0 +1,575 @@
+//! Error-tolerant parsing of mixlang, for editor integrations.
+//!
+//! Instead of stopping at the first syntax error, the parser records a diagnostic, skips or
+//! assumes tokens to get back in sync, and keeps going. So a single pass reports every syntax error
+//! of the input. If all errors could be repaired, i.e only closing parentheses are missing or
+//! extra tokens are present, the repaired expression is returned along with the diagnostics.
+use crate::parser::ParseLimits;
+use fluido_types::{
+    expr::Expr,
+    fluid::{Fluid, LimitedFloat},
+};
+use std::fmt::Display;
+
+/// Byte range of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+/// A syntax error along with the part of the input it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}..{}: {}",
+            self.span.start, self.span.end, self.message
+        )
+    }
+}
+
+/// A value along with the part of the input it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub span: Span,
+    pub value: T,
+}
+
+/// Result of an error-tolerant parse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOutcome<T> {
+    /// Parsed value, `None` if the input has errors which could not be repaired.
+    pub value: Option<T>,
+    /// Every node of the input which could be parsed, children before their parents. Nodes are
+    /// also collected from subtrees of an input which could not be repaired as a whole.
+    pub nodes: Vec<Spanned<T>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub trait ParseWithDiagnostics
+where
+    Self: Sized,
+{
+    /// Parses the input, collecting every syntax error instead of stopping at the first one.
+    /// Input exceeding the default `ParseLimits` is not parsed, and reported with a single
+    /// diagnostic spanning it.
+    fn parse_with_diagnostics(input_str: &str) -> ParseOutcome<Self>;
+}
+
+impl ParseWithDiagnostics for Expr {
+    fn parse_with_diagnostics(input_str: &str) -> ParseOutcome<Self> {
+        if let Err(err) = ParseLimits::default().check(input_str) {
+            return ParseOutcome {
+                value: None,
+                nodes: vec![],
+                diagnostics: vec![Diagnostic {
+                    span: Span {
+                        start: 0,
+                        end: input_str.len(),
+                    },
+                    message: err.to_string(),
+                }],
+            };
+        }
+        let mut parser = RecoveringParser::new(input_str);
+        let value = parser.expression();
+        if let Some(trailing) = parser.peek() {
+            let last = parser.tokens[parser.tokens.len() - 1];
+            parser.report(
+                trailing.span.to(last.span),
+                "unexpected input after the expression",
+            );
+        }
+        ParseOutcome {
+            value,
+            nodes: parser.nodes,
+            diagnostics: parser.diagnostics,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Open,
+    Close,
+    Word,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word_start = None;
+    for (ix, char) in input.char_indices() {
+        let is_delimiter = char.is_whitespace() || char == '(' || char == ')';
+        if !is_delimiter {
+            word_start.get_or_insert(ix);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            tokens.push(Token {
+                kind: TokenKind::Word,
+                span: Span { start, end: ix },
+            });
+        }
+        let kind = match char {
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            _ => continue,
+        };
+        tokens.push(Token {
+            kind,
+            span: Span {
+                start: ix,
+                end: ix + 1,
+            },
+        });
+    }
+    if let Some(start) = word_start {
+        tokens.push(Token {
+            kind: TokenKind::Word,
+            span: Span {
+                start,
+                end: input.len(),
+            },
+        });
+    }
+    tokens
+}
+
+/// Whether the word is a number accepted by the mixlang grammar, `-`? digits (`.` digits)?.
+fn is_number(word: &str) -> bool {
+    let unsigned = word.strip_prefix('-').unwrap_or(word);
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    is_digits(integer) && fraction.is_none_or(is_digits)
+}
+
+struct RecoveringParser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    nodes: Vec<Spanned<Expr>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> RecoveringParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            tokens: tokenize(input),
+            pos: 0,
+            nodes: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn text(&self, token: Token) -> &'a str {
+        &self.input[token.span.start..token.span.end]
+    }
+
+    fn report(&mut self, span: Span, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            span,
+            message: message.into(),
+        });
+    }
+
+    /// Span of the end of the input, for errors at the end of the input.
+    fn end_span(&self) -> Span {
+        Span {
+            start: self.input.len(),
+            end: self.input.len(),
+        }
+    }
+
+    /// Describes the next token for diagnostics.
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("`{}`", self.text(token)),
+            None => "end of input".to_string(),
+        }
+    }
+
+    /// Reports that `expected` is missing at the next token, without consuming it.
+    fn expected(&mut self, expected: &str) {
+        let span = self.peek().map_or(self.end_span(), |token| token.span);
+        let message = format!("expected {expected}, found {}", self.found());
+        self.report(span, message);
+    }
+
+    fn expression(&mut self) -> Option<Expr> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Open,
+                ..
+            }) => self.operation(),
+            Some(Token {
+                kind: TokenKind::Word,
+                ..
+            }) => {
+                self.expected("expression");
+                self.bump();
+                None
+            }
+            // A closing parenthesis belongs to the enclosing operation, so it is not consumed.
+            _ => {
+                self.expected("expression");
+                None
+            }
+        }
+    }
+
+    fn operation(&mut self) -> Option<Expr> {
+        let open = self.bump()?;
+        let operator = self.peek().filter(|token| token.kind == TokenKind::Word);
+        let expr = match operator.map(|token| (token.span, self.text(token))) {
+            Some((_, "mix")) => {
+                self.bump();
+                let lhs = self.expression();
+                let rhs = self.expression();
+                lhs.zip(rhs)
+                    .map(|(lhs, rhs)| Expr::Mix(Box::new(lhs), Box::new(rhs)))
+            }
+            Some((_, "fluid")) => {
+                self.bump();
+                let concentrations = self.concentrations();
+                let volume = self.number();
+                concentrations.zip(volume).map(|(concentrations, volume)| {
+                    Expr::Fluid(Fluid::with_reagents(concentrations, volume))
+                })
+            }
+            Some((span, operator)) => {
+                let message = format!("unknown operation `{operator}`, expected `mix` or `fluid`");
+                self.report(span, message);
+                self.skip_operation();
+                return None;
+            }
+            None => {
+                self.expected("`mix` or `fluid`");
+                self.skip_operation();
+                return None;
+            }
+        };
+        self.close(open);
+        if let Some(expr) = &expr {
+            let end = self.tokens[self.pos - 1].span;
+            self.nodes.push(Spanned {
+                span: open.span.to(end),
+                value: expr.clone(),
+            });
+        }
+        expr
+    }
+
+    fn number(&mut self) -> Option<LimitedFloat> {
+        let token = self.peek().filter(|token| token.kind == TokenKind::Word);
+        let Some(token) = token else {
+            self.expected("number");
+            return None;
+        };
+        self.bump();
+        self.parse_number(token.span, self.text(token))
+    }
+
+    fn parse_number(&mut self, span: Span, word: &str) -> Option<LimitedFloat> {
+        if !is_number(word) {
+            self.report(span, format!("invalid number `{word}`"));
+            return None;
+        }
+        match word.parse::<LimitedFloat>() {
+            Ok(number) => Some(number),
+            Err(e) => {
+                self.report(span, format!("invalid number `{word}`: {e}"));
+                None
+            }
+        }
+    }
+
+    /// Concentration of a fluid, or the concentrations of its reagents in brackets, i.e
+    /// `[0.2 0.1]`.
+    fn concentrations(&mut self) -> Option<Vec<LimitedFloat>> {
+        let token = self.peek().filter(|token| token.kind == TokenKind::Word);
+        if !token.is_some_and(|token| self.text(token).starts_with('[')) {
+            return self.number().map(|number| vec![number]);
+        }
+        let open = self.bump()?;
+        let mut words = vec![open];
+        while !self.text(words[words.len() - 1]).ends_with(']') {
+            match self.peek().filter(|token| token.kind == TokenKind::Word) {
+                Some(token) => {
+                    self.bump();
+                    words.push(token);
+                }
+                None => {
+                    self.expected("`]`");
+                    return None;
+                }
+            }
+        }
+        let mut numbers = vec![];
+        for token in words {
+            let word = self.text(token);
+            let word = word.strip_prefix('[').unwrap_or(word);
+            let word = word.strip_suffix(']').unwrap_or(word);
+            if !word.is_empty() {
+                numbers.push(self.parse_number(token.span, word));
+            }
+        }
+        if numbers.is_empty() {
+            self.report(open.span, "expected a concentration in `[]`");
+            return None;
+        }
+        numbers.into_iter().collect()
+    }
+
+    /// Consumes the closing parenthesis of the operation opened by `open`.
+    ///
+    /// Extra words are skipped. If another operation starts instead, the closing parenthesis is
+    /// assumed to be missing.
+    fn close(&mut self, open: Token) {
+        let extra_start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|token| token.kind == TokenKind::Word)
+        {
+            self.bump();
+        }
+        if self.pos > extra_start {
+            let span = self.tokens[extra_start]
+                .span
+                .to(self.tokens[self.pos - 1].span);
+            self.report(span, "unexpected arguments");
+        }
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Close,
+                ..
+            }) => {
+                self.bump();
+            }
+            _ => {
+                let message = format!(
+                    "expected `)` to close `(` at {}, found {}",
+                    open.span.start,
+                    self.found()
+                );
+                let span = self.peek().map_or(self.end_span(), |token| token.span);
+                self.report(span, message);
+            }
+        }
+    }
+
+    /// Skips the rest of the current operation, including its closing parenthesis.
+    fn skip_operation(&mut self) {
+        let mut depth = 0usize;
+        while let Some(token) = self.bump() {
+            match token.kind {
+                TokenKind::Open => depth += 1,
+                TokenKind::Close if depth == 0 => return,
+                TokenKind::Close => depth -= 1,
+                TokenKind::Word => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParseWithDiagnostics, Span, Spanned};
+    use crate::parser::Parse;
+    use fluido_types::expr::Expr;
+
+    fn messages(input_str: &str) -> Vec<String> {
+        Expr::parse_with_diagnostics(input_str)
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_input_matches_parse() {
+        for input_str in [
+            "(fluid 0.2 1.0)",
+            "(mix (fluid 0.2 1.0) (mix (fluid 0.3 1.0) (fluid -0.4 2)))",
+            "(mix\n\t(fluid 0.2 1.0)\n\t(fluid 0.3 1.0))",
+        ] {
+            let outcome = Expr::parse_with_diagnostics(input_str);
+            assert!(outcome.diagnostics.is_empty(), "{input_str}");
+            assert_eq!(outcome.value, Some(Expr::parse(input_str).unwrap()));
+        }
+    }
+
+    #[test]
+    fn reagents_are_parsed_in_brackets() {
+        let outcome = Expr::parse_with_diagnostics("(mix (fluid [0.2 0.1] 1.0) (fluid 0.0 1.0))");
+        assert!(outcome.diagnostics.is_empty());
+        assert_eq!(
+            outcome.value,
+            Some(Expr::parse("(mix (fluid [ 0.2 0.1 ] 1.0) (fluid 0.0 1.0))").unwrap())
+        );
+
+        let messages = |input_str| {
+            Expr::parse_with_diagnostics(input_str)
+                .diagnostics
+                .into_iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages("(fluid [0.2 x] 1.0)"),
+            vec!["12..14: invalid number `x`"]
+        );
+        assert_eq!(
+            messages("(fluid [0.2 0.1 1.0)"),
+            vec![
+                "19..20: expected `]`, found `)`",
+                "19..20: expected number, found `)`"
+            ]
+        );
+    }
+
+    #[test]
+    fn multiple_errors_are_reported() {
+        let input_str = "(mix (fluid 0.2 x) (mox 1 2))";
+        let outcome = Expr::parse_with_diagnostics(input_str);
+        assert_eq!(outcome.value, None);
+        assert_eq!(
+            messages(input_str),
+            vec![
+                "16..17: invalid number `x`",
+                "20..23: unknown operation `mox`, expected `mix` or `fluid`",
+            ]
+        );
+        assert_eq!(outcome.diagnostics[0].span, Span { start: 16, end: 17 });
+    }
+
+    #[test]
+    fn nodes_are_collected_with_spans() {
+        let outcome = Expr::parse_with_diagnostics("(mix (fluid 0.2 1.0) (fluid 0.3 1.0))");
+        let spans = outcome
+            .nodes
+            .iter()
+            .map(|node| node.span)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                Span { start: 5, end: 20 },
+                Span { start: 21, end: 36 },
+                Span { start: 0, end: 37 },
+            ]
+        );
+
+        // The valid fluid is kept even though the mix cannot be repaired.
+        let outcome = Expr::parse_with_diagnostics("(mix (fluid 0.2 1.0) (fluid x 1.0))");
+        assert_eq!(outcome.value, None);
+        assert_eq!(
+            outcome.nodes,
+            vec![Spanned {
+                span: Span { start: 5, end: 20 },
+                value: Expr::parse("(fluid 0.2 1.0)").unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn overflowing_numbers_are_reported() {
+        let input_str = format!("(fluid 0.2 1{})", "0".repeat(400));
+        let outcome = Expr::parse_with_diagnostics(&input_str);
+        assert_eq!(outcome.value, None);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert!(outcome.diagnostics[0]
+            .to_string()
+            .ends_with("is not a finite number."));
+    }
+
+    #[test]
+    fn oversized_input_is_reported() {
+        let input_str = format!("{}(fluid 0.2 1.0)", "(mix (fluid 0.0 1.0) ".repeat(1000));
+        let outcome = Expr::parse_with_diagnostics(&input_str);
+        assert_eq!(outcome.value, None);
+        assert_eq!(
+            messages(&input_str),
+            vec![format!(
+                "0..{}: Expression is nested 1001 levels deep, more than the limit of 512.",
+                input_str.len()
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_operands_are_reported() {
+        assert_eq!(
+            messages("(mix (fluid 0.2) )"),
+            vec![
+                "15..16: expected number, found `)`",
+                "17..18: expected expression, found `)`",
+            ]
+        );
+        assert_eq!(
+            messages(""),
+            vec!["0..0: expected expression, found end of input"]
+        );
+    }
+
+    #[test]
+    fn repairable_errors_keep_the_expression() {
+        let input_str = "(mix (fluid 0.2 1.0 2.0 (fluid 0.3 1.0) extra";
+        let outcome = Expr::parse_with_diagnostics(input_str);
+        assert_eq!(
+            outcome.value,
+            Some(Expr::parse("(mix (fluid 0.2 1.0) (fluid 0.3 1.0))").unwrap())
+        );
+        assert_eq!(
+            messages(input_str),
+            vec![
+                "20..23: unexpected arguments",
+                "24..25: expected `)` to close `(` at 5, found `(`",
+                "40..45: unexpected arguments",
+                "45..45: expected `)` to close `(` at 0, found end of input",
+            ]
+        );
+
+        let outcome = Expr::parse_with_diagnostics("(fluid 0.2 1.0)) (fluid 0.3 1.0)");
+        assert!(outcome.value.is_some());
+        assert_eq!(
+            outcome.diagnostics[0].to_string(),
+            "15..32: unexpected input after the expression"
+        );
+    }
+}
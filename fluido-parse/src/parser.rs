@@ -1,6 +1,6 @@
 #![allow(clippy::empty_docs)]
 use fluido_types::{
-    error::IRGenerationError,
+    error::{ExprLimitError, IRGenerationError},
     expr::Expr,
     fluid::{Fluid, LimitedFloat},
 };
@@ -15,15 +15,86 @@ use pest_derive::Parser;
 /// 2- Easily generatable during the saturation phase which happens at the beginning of the pipeline.
 struct MixLangParser;
 
+/// Largest expressions the parser accepts. Parsing, and the passes over a parsed `Expr`, recurse
+/// into its mixes, so that input nested thousands of mixes deep could exhaust the stack. Input
+/// exceeding the limits is rejected with `ExprLimitError` before it is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Largest number of nodes, see `Expr::size`.
+    pub max_size: usize,
+    /// Deepest nesting of nodes, see `Expr::depth`.
+    pub max_depth: usize,
+}
+
+impl ParseLimits {
+    pub const DEFAULT_MAX_SIZE: usize = 100_000;
+    pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Checks the size and depth of the expression the input would parse into, which are the
+    /// number of its opening parentheses and their deepest nesting.
+    pub fn check(&self, input_str: &str) -> Result<(), ExprLimitError> {
+        let (mut size, mut depth, mut nesting) = (0, 0, 0usize);
+        for c in input_str.chars() {
+            match c {
+                '(' => {
+                    size += 1;
+                    nesting += 1;
+                    depth = depth.max(nesting);
+                }
+                ')' => nesting = nesting.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if size > self.max_size {
+            return Err(ExprLimitError::TooLarge {
+                size,
+                limit: self.max_size,
+            });
+        }
+        if depth > self.max_depth {
+            return Err(ExprLimitError::TooDeep {
+                depth,
+                limit: self.max_depth,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_size: Self::DEFAULT_MAX_SIZE,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
 pub trait Parse
 where
     Self: Sized,
 {
-    fn parse(input_str: &str) -> Result<Self, IRGenerationError>;
+    /// Parses the input within the default `ParseLimits`.
+    fn parse(input_str: &str) -> Result<Self, IRGenerationError> {
+        Self::parse_with_limits(input_str, &ParseLimits::default())
+    }
+
+    fn parse_with_limits(input_str: &str, limits: &ParseLimits) -> Result<Self, IRGenerationError>;
 }
 
 impl Parse for Expr {
-    fn parse(input_str: &str) -> Result<Self, IRGenerationError> {
+    fn parse_with_limits(input_str: &str, limits: &ParseLimits) -> Result<Self, IRGenerationError> {
+        limits.check(input_str)?;
         let pairs = MixLangParser::parse(Rule::expression, input_str)
             .map_err(|e| IRGenerationError::ParseError(e.to_string()))?;
         build_ast(pairs)
@@ -42,12 +113,17 @@ fn build_ast(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr, IRGenerationEr
             Ok(Expr::Mix(Box::new(first_expr), Box::new(second_expr)))
         }
         Rule::float => {
-            let num = pair.as_str().parse::<f64>().unwrap();
-            let concentration = LimitedFloat::from(num);
+            let concentration = pair
+                .as_str()
+                .parse::<LimitedFloat>()
+                .map_err(|e| IRGenerationError::ParseError(e.to_string()))?;
             Ok(Expr::LimitedFloat(concentration))
         }
         Rule::fluid => {
-            let fluid = pair.as_str().parse::<Fluid>().unwrap();
+            let fluid = pair
+                .as_str()
+                .parse::<Fluid>()
+                .map_err(|e| IRGenerationError::ParseError(format!("{e:?}")))?;
             Ok(Expr::Fluid(fluid))
         }
         _ => unreachable!(),
@@ -56,8 +132,9 @@ fn build_ast(pairs: pest::iterators::Pairs<Rule>) -> Result<Expr, IRGenerationEr
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::Parse;
+    use crate::parser::{Parse, ParseLimits};
     use fluido_types::{
+        error::{ExprLimitError, IRGenerationError},
         expr::Expr,
         fluid::{Concentration, Fluid, Volume},
     };
@@ -108,4 +185,38 @@ mod tests {
 
         assert_eq!(final_mix, expr)
     }
+
+    #[test]
+    fn oversized_input_is_rejected() {
+        let nested = |depth: usize| {
+            format!(
+                "{}(fluid 0.2 1.0){}",
+                "(mix ".repeat(depth),
+                " (fluid 0.0 1.0))".repeat(depth)
+            )
+        };
+        let expr = Expr::parse(&nested(100)).unwrap();
+        assert_eq!((expr.size(), expr.depth()), (201, 101));
+
+        let limits = ParseLimits::default().with_max_depth(100);
+        assert_eq!(
+            Expr::parse_with_limits(&nested(100), &limits)
+                .unwrap_err()
+                .to_string(),
+            "Expression is nested 101 levels deep, more than the limit of 100."
+        );
+        // Rejected before parsing, which would exhaust the stack.
+        assert!(matches!(
+            Expr::parse(&nested(100_000)),
+            Err(IRGenerationError::ExprLimitExceeded(
+                ExprLimitError::TooLarge { size: 200_001, .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn parse_overflowing_number() {
+        let input_str = format!("(fluid 0.2 1{})", "0".repeat(400));
+        assert!(Expr::parse(&input_str).is_err());
+    }
 }
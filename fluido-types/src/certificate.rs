@@ -0,0 +1,241 @@
+//! Machine-checkable certificates for the concentration a mixer design produces.
+//!
+//! A certificate is the derivation of the final fluid from the leaf fluids of a design, carried
+//! out with exact rational arithmetic. It does not depend on how the design was found, so it can
+//! be checked independently with `Certificate::verify`.
+use crate::{
+    error::CertificateError,
+    expr::Expr,
+    fluid::{Concentration, Fluid},
+    number::Rational,
+};
+use serde::{Deserialize, Serialize};
+
+/// A fluid with exact concentration and volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExactFluid {
+    pub concentration: Rational,
+    pub volume: Rational,
+}
+
+impl ExactFluid {
+    /// Mixes two fluids in the given step, the resulting concentration is the volume weighted
+    /// average. Fails if the total volume is zero, or the arithmetic overflows.
+    pub(crate) fn mix(
+        &self,
+        other: &ExactFluid,
+        step: usize,
+    ) -> Result<ExactFluid, CertificateError> {
+        let overflow = || CertificateError::Overflow(step);
+        let volume = self.volume.checked_add(other.volume).ok_or_else(overflow)?;
+        if volume.is_zero() {
+            return Err(CertificateError::ZeroVolume(step));
+        }
+        let concentration = self
+            .concentration
+            .checked_mul(self.volume)
+            .zip(other.concentration.checked_mul(other.volume))
+            .and_then(|(lhs, rhs)| lhs.checked_add(rhs))
+            .and_then(|amount| amount.checked_div(volume))
+            .ok_or_else(overflow)?;
+        Ok(ExactFluid {
+            concentration,
+            volume,
+        })
+    }
+}
+
+impl From<&Fluid> for ExactFluid {
+    fn from(value: &Fluid) -> Self {
+        Self {
+            concentration: Rational::from(value.concentration()),
+            volume: Rational::from(value.unit_volume()),
+        }
+    }
+}
+
+/// Operand of a derivation step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CertificateOperand {
+    /// Index into `Certificate::leaves`.
+    Leaf(usize),
+    /// Index of a previous step in `Certificate::steps`.
+    Step(usize),
+}
+
+/// A single mix in the derivation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateStep {
+    pub lhs: CertificateOperand,
+    pub rhs: CertificateOperand,
+    pub result: ExactFluid,
+}
+
+/// Exact derivation of the fluid produced by a mixer design.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Certificate {
+    /// Input fluids used by the design.
+    pub leaves: Vec<ExactFluid>,
+    /// Mix operations in execution order.
+    pub steps: Vec<CertificateStep>,
+    /// Fluid the design concludes with.
+    pub result: ExactFluid,
+}
+
+impl Certificate {
+    /// Derives the certificate for the given mixer expression.
+    pub fn from_expr(expr: &Expr) -> Result<Self, CertificateError> {
+        let mut certificate = Self {
+            leaves: vec![],
+            steps: vec![],
+            result: ExactFluid {
+                concentration: Rational::new(0, 1),
+                volume: Rational::new(0, 1),
+            },
+        };
+        let root = certificate.derive(expr)?;
+        certificate.result = *certificate
+            .resolve(root, certificate.steps.len())
+            .expect("derived operands are always defined");
+        Ok(certificate)
+    }
+
+    fn derive(&mut self, expr: &Expr) -> Result<CertificateOperand, CertificateError> {
+        match expr {
+            Expr::Fluid(fluid) => {
+                self.leaves.push(ExactFluid::from(fluid));
+                Ok(CertificateOperand::Leaf(self.leaves.len() - 1))
+            }
+            Expr::Mix(lhs, rhs) => {
+                let lhs = self.derive(lhs)?;
+                let rhs = self.derive(rhs)?;
+                let step = self.steps.len();
+                let lhs_fluid = self.resolve(lhs, step).expect("operand derived before");
+                let rhs_fluid = self.resolve(rhs, step).expect("operand derived before");
+                let result = lhs_fluid.mix(rhs_fluid, step)?;
+                self.steps.push(CertificateStep { lhs, rhs, result });
+                Ok(CertificateOperand::Step(step))
+            }
+            Expr::LimitedFloat(lf) => Err(CertificateError::NonFluidLeaf(lf.to_string())),
+        }
+    }
+
+    /// Returns the fluid an operand refers to, if it is defined before `step`.
+    fn resolve(&self, operand: CertificateOperand, step: usize) -> Option<&ExactFluid> {
+        match operand {
+            CertificateOperand::Leaf(ix) => self.leaves.get(ix),
+            CertificateOperand::Step(ix) if ix < step => self.steps.get(ix).map(|s| &s.result),
+            CertificateOperand::Step(_) => None,
+        }
+    }
+
+    /// Re-checks every step of the derivation and the final conclusion.
+    pub fn verify(&self) -> Result<(), CertificateError> {
+        for (ix, step) in self.steps.iter().enumerate() {
+            let lhs = self
+                .resolve(step.lhs, ix)
+                .ok_or(CertificateError::UndefinedOperand(ix))?;
+            let rhs = self
+                .resolve(step.rhs, ix)
+                .ok_or(CertificateError::UndefinedOperand(ix))?;
+            let computed = lhs.mix(rhs, ix)?;
+            if computed != step.result {
+                return Err(CertificateError::StepMismatch {
+                    step: ix,
+                    claimed: format!("{:?}", step.result),
+                    computed: format!("{:?}", computed),
+                });
+            }
+        }
+        let computed = match self.steps.last() {
+            Some(step) => step.result,
+            None if self.leaves.len() == 1 => self.leaves[0],
+            None => return Err(CertificateError::UndefinedOperand(0)),
+        };
+        if computed != self.result {
+            return Err(CertificateError::ConclusionMismatch {
+                claimed: format!("{:?}", self.result),
+                computed: format!("{:?}", computed),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns true if the certificate is valid and concludes exactly with `target`.
+    pub fn proves(&self, target: &Concentration) -> bool {
+        self.verify().is_ok() && self.result.concentration == Rational::from(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fluid(concentration: f64, volume: f64) -> Box<Expr> {
+        Box::new(Expr::Fluid(Fluid::new(concentration.into(), volume.into())))
+    }
+
+    #[test]
+    fn certificate_for_weighted_mix() {
+        let expr = Expr::Mix(fluid(0.04, 1.0), fluid(0.0, 3.0));
+        let certificate = Certificate::from_expr(&expr).unwrap();
+
+        assert_eq!(certificate.leaves.len(), 2);
+        assert_eq!(certificate.steps.len(), 1);
+        assert_eq!(certificate.result.concentration, Rational::new(1, 100));
+        assert_eq!(certificate.result.volume, Rational::new(4, 1));
+        assert!(certificate.proves(&0.01.into()));
+        assert!(!certificate.proves(&0.02.into()));
+    }
+
+    #[test]
+    fn certificate_is_exact_for_non_representable_values() {
+        // (0.1 * 1.0 + 0.0 * 2.0) / 3.0 = 1/30, which is not representable with `LimitedFloat`.
+        let expr = Expr::Mix(fluid(0.1, 1.0), fluid(0.0, 2.0));
+        let certificate = Certificate::from_expr(&expr).unwrap();
+
+        assert_eq!(certificate.result.concentration, Rational::new(1, 30));
+        assert!(certificate.verify().is_ok());
+    }
+
+    #[test]
+    fn tampered_certificate_fails_verification() {
+        let expr = Expr::Mix(
+            Box::new(Expr::Mix(fluid(0.2, 1.0), fluid(0.0, 1.0))),
+            fluid(0.1, 2.0),
+        );
+        let mut certificate = Certificate::from_expr(&expr).unwrap();
+        assert!(certificate.verify().is_ok());
+
+        certificate.steps[0].result.concentration = Rational::new(1, 3);
+        assert!(matches!(
+            certificate.verify(),
+            Err(CertificateError::StepMismatch { step: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn overflowing_mixes_fail() {
+        let huge = |concentration: i128| ExactFluid {
+            concentration: Rational::new(concentration, i128::MAX / 3),
+            volume: Rational::new(i128::MAX / 2 + 1, 1),
+        };
+        assert_eq!(huge(1).mix(&huge(2), 3), Err(CertificateError::Overflow(3)));
+        let empty = ExactFluid {
+            concentration: Rational::new(1, 2),
+            volume: Rational::new(0, 1),
+        };
+        assert_eq!(empty.mix(&empty, 0), Err(CertificateError::ZeroVolume(0)));
+    }
+
+    #[test]
+    fn certificate_serializes_rationals_as_strings() {
+        let expr = Expr::Mix(fluid(0.04, 1.0), fluid(0.0, 3.0));
+        let certificate = Certificate::from_expr(&expr).unwrap();
+        let result = serde_json::to_value(certificate.result).unwrap();
+
+        assert_eq!(result["concentration"], "1/100");
+        assert_eq!(result["volume"], "4/1");
+    }
+}
@@ -0,0 +1,113 @@
+//! Set of concentrations looked up with a tolerance.
+//!
+//! Concentrations computed through `Frac` arithmetic can end up a step of `EPSILON` away from the
+//! value they stand for, i.e `0.0999` for `0.1`. Exact lookups miss those, so the set considers
+//! concentrations within the tolerance of a member to be that member.
+use crate::fluid::Concentration;
+
+/// Concentrations sorted for binary search, each matching the concentrations within the tolerance
+/// of it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConcentrationSet {
+    sorted: Vec<Concentration>,
+    /// Largest distance of a matching concentration, in steps of `Concentration::EPSILON`.
+    tolerance: u64,
+}
+
+impl ConcentrationSet {
+    /// Default tolerance, the rounding error of a single `Frac` operation.
+    pub const TOLERANCE: u64 = 1;
+
+    pub fn new(concentrations: impl IntoIterator<Item = Concentration>) -> Self {
+        let mut sorted = concentrations.into_iter().collect::<Vec<_>>();
+        sorted.sort();
+        sorted.dedup();
+        Self {
+            sorted,
+            tolerance: Self::TOLERANCE,
+        }
+    }
+
+    /// Largest distance of a matching concentration, in steps of `Concentration::EPSILON`. Zero
+    /// makes lookups exact.
+    pub fn with_tolerance(mut self, tolerance: u64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Largest distance of a matching concentration, in steps of `Concentration::EPSILON`.
+    pub fn tolerance(&self) -> u64 {
+        self.tolerance
+    }
+
+    /// The member closest to the concentration, the smaller one of two equally close members.
+    pub fn nearest(&self, concentration: &Concentration) -> Option<&Concentration> {
+        let ix = self.sorted.partition_point(|member| member < concentration);
+        let below = ix.checked_sub(1).and_then(|ix| self.sorted.get(ix));
+        match (below, self.sorted.get(ix)) {
+            (Some(below), Some(above))
+                if above.delta(concentration) < below.delta(concentration) =>
+            {
+                Some(above)
+            }
+            (Some(below), _) => Some(below),
+            (None, above) => above,
+        }
+    }
+
+    /// The member the concentration matches, if any is within the tolerance of it.
+    pub fn get(&self, concentration: &Concentration) -> Option<&Concentration> {
+        self.nearest(concentration)
+            .filter(|member| member.delta(concentration) <= self.tolerance)
+    }
+
+    pub fn contains(&self, concentration: &Concentration) -> bool {
+        self.get(concentration).is_some()
+    }
+
+    /// Members in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &Concentration> {
+        self.sorted.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sorted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sorted.is_empty()
+    }
+}
+
+impl FromIterator<Concentration> for ConcentrationSet {
+    fn from_iter<T: IntoIterator<Item = Concentration>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConcentrationSet;
+    use crate::fluid::Concentration;
+
+    #[test]
+    fn lookups_tolerate_rounding_errors() {
+        let set = [0.4, 0.1, 0.0, 0.1]
+            .into_iter()
+            .map(Concentration::from)
+            .collect::<ConcentrationSet>();
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.get(&0.0999.into()), Some(&0.1.into()));
+        assert_eq!(set.get(&0.1001.into()), Some(&0.1.into()));
+        assert!(!set.contains(&0.1002.into()));
+        assert!(set.contains(&0.0.into()));
+        assert_eq!(set.nearest(&0.3.into()), Some(&0.4.into()));
+        assert_eq!(set.nearest(&0.25.into()), Some(&0.1.into()));
+        assert_eq!(set.nearest(&0.9.into()), Some(&0.4.into()));
+
+        let exact = set.with_tolerance(0);
+        assert!(!exact.contains(&0.0999.into()));
+        assert!(exact.contains(&0.1.into()));
+        assert_eq!(ConcentrationSet::default().nearest(&0.1.into()), None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Serializes fixed point numbers and fluids as decimals, i.e `0.4` rather than the scaled integer
+//! a `LimitedFloat` keeps, for the JSON output of designs. Consumers of the output then do not
+//! depend on the representation of the numbers. Checkpoints and fixtures keep the derived form,
+//! which they are loaded back from.
+use crate::{
+    fluid::{Fluid, LimitedFloat},
+    number::Rational,
+};
+use serde::{ser::SerializeStruct, Serializer};
+
+/// The value as the nearest `f64`, i.e `0.05` rather than `0.04999999999999982`.
+pub fn to_f64(value: &LimitedFloat) -> f64 {
+    f64::from(Rational::from(value))
+}
+
+/// Serializes the value as a decimal, for `#[serde(serialize_with = "decimal::serialize")]`.
+pub fn serialize<S: Serializer>(value: &LimitedFloat, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(to_f64(value))
+}
+
+/// Serializes the fluid with its concentrations and volume as decimals, the reagents after the
+/// first one only for fluids of several reagents.
+pub fn serialize_fluid<S: Serializer>(fluid: &Fluid, serializer: S) -> Result<S::Ok, S::Error> {
+    let concentrations = fluid.concentrations();
+    let reagents = concentrations[1..].iter().map(to_f64).collect::<Vec<_>>();
+    let mut state = serializer.serialize_struct("Fluid", 2 + usize::from(!reagents.is_empty()))?;
+    state.serialize_field("concentration", &to_f64(fluid.concentration()))?;
+    state.serialize_field("unit_volume", &to_f64(fluid.unit_volume()))?;
+    if reagents.is_empty() {
+        state.skip_field("reagents")?;
+    } else {
+        state.serialize_field("reagents", &reagents)?;
+    }
+    state.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fluid::{Concentration, Fluid};
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Record {
+        #[serde(serialize_with = "super::serialize")]
+        concentration: Concentration,
+        #[serde(serialize_with = "super::serialize_fluid")]
+        fluid: Fluid,
+    }
+
+    #[test]
+    fn numbers_are_serialized_as_decimals() {
+        let record = Record {
+            concentration: 0.05.into(),
+            fluid: Fluid::new(0.4.into(), 2.0.into()),
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"concentration":0.05,"fluid":{"concentration":0.4,"unit_volume":2.0}}"#
+        );
+        let record = Record {
+            concentration: 0.0.into(),
+            fluid: Fluid::with_reagents(vec![0.4.into(), 0.1.into()], 1.0.into()),
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"concentration":0.0,"fluid":{"concentration":0.4,"unit_volume":1.0,"reagents":[0.1]}}"#
+        );
+    }
+}
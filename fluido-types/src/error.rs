@@ -1,18 +1,128 @@
-use crate::fluid::Concentration;
+use crate::{
+    expr::ExprPath,
+    fluid::{Concentration, ConcentrationWindow, Incompatibility, MixerCapabilities, Volume},
+    suggest::did_you_mean,
+    units::ConcentrationUnit,
+};
+use std::path::PathBuf;
 use thiserror::Error;
 
+/// Renders the concentrations as a comma separated list.
+fn render_concentrations(concentrations: &[Concentration]) -> String {
+    concentrations
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The hint as a sentence following an error message, empty without a hint.
+fn render_hint(hint: &Option<String>) -> String {
+    hint.as_ref()
+        .map(|hint| format!(" {hint}"))
+        .unwrap_or_default()
+}
+
 #[derive(Error, Debug)]
 pub enum MixerGenerationError {
     #[error("Saturation error while generating the mixer space: {0}")]
     SaturationError(String),
     #[error("Failed to parse target concentration (`{0}`) as a node.")]
     FailedToParseTarget(Concentration),
+    #[error("Target concentration (`{0}`) is not reachable from the given input space.")]
+    TargetUnreachable(Concentration),
+    #[error(
+        "Saturation time limit ({0} seconds) reached before a design for the target was found."
+    )]
+    TimeLimitReached(u64),
+    #[error(
+        "Saturation node limit ({0} e-nodes) reached before a design for the target was found."
+    )]
+    NodeLimitReached(usize),
+    #[error(
+        "Saturation iteration limit ({0} iterations) reached before a design for the target was found."
+    )]
+    IterationLimitReached(usize),
+    #[error("Checkpoint error: {0}")]
+    CheckpointError(String),
+    #[error("More than one rewrite rule is named `{0}`.")]
+    DuplicateRuleName(String),
+    #[error("No design producing the required intermediates ({}) was found.", render_concentrations(.0))]
+    RequiredIntermediatesUnreachable(Vec<Concentration>),
+    #[error("Search was cancelled.")]
+    Cancelled,
+    #[error("Search was interrupted, resume it from the checkpoint written to {0:?}.")]
+    Interrupted(PathBuf),
+    #[error("Target concentration (`{0}`) was not explored by the saturated mixer space, search for it from scratch.")]
+    TargetNotExplored(Concentration),
+    #[error("{0}")]
+    ExtractedExprError(ExtractedExprError),
+    #[error("Target reagents ({}) are not reachable from the given input space.", render_concentrations(.0))]
+    ReagentsUnreachable(Vec<Concentration>),
+    #[error(
+        "The input space has {0} fluids, fluids of several reagents are mixed from at most {1}."
+    )]
+    TooManyInputs(usize, usize),
+    #[error("Expansion depth {0} exceeds the maximum of {1} mixes.")]
+    ExpansionDepthTooLarge(usize, usize),
+}
+
+impl From<ExtractedExprError> for MixerGenerationError {
+    fn from(value: ExtractedExprError) -> Self {
+        Self::ExtractedExprError(value)
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum IRGenerationError {
     #[error("{0}")]
     ParseError(String),
+    #[error("{0}")]
+    ExtractedExprError(ExtractedExprError),
+    #[error("{0}")]
+    ExprLimitExceeded(ExprLimitError),
+}
+
+impl From<ExtractedExprError> for IRGenerationError {
+    fn from(value: ExtractedExprError) -> Self {
+        Self::ExtractedExprError(value)
+    }
+}
+
+impl From<ExprLimitError> for IRGenerationError {
+    fn from(value: ExprLimitError) -> Self {
+        Self::ExprLimitExceeded(value)
+    }
+}
+
+/// An expression exceeding the limits of the parser, rejected before it is parsed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExprLimitError {
+    #[error("Expression has {size} nodes, more than the limit of {limit}.")]
+    TooLarge { size: usize, limit: usize },
+    #[error("Expression is nested {depth} levels deep, more than the limit of {limit}.")]
+    TooDeep { depth: usize, limit: usize },
+}
+
+/// A node of an expression extracted from the e-graph which cannot be converted, i.e normalized
+/// by the smallest volume or turned into an `Expr`.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Extracted expression `{expr}` cannot be converted, node `{node}` at {path} {reason}")]
+pub struct ExtractedExprError {
+    pub expr: String,
+    pub path: ExprPath,
+    pub node: String,
+    pub reason: ExtractedNodeFailure,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ExtractedNodeFailure {
+    #[error("has a volume which is not a number.")]
+    NonNumericVolume,
+    #[error("has a volume which cannot be normalized, {0}")]
+    UnnormalizableVolume(ConversionError),
+    #[error("is neither a mix nor a fluid.")]
+    NotAMixOrFluid,
 }
 
 #[derive(Error, Debug)]
@@ -20,6 +130,186 @@ pub enum InterefenceGraphGenerationError {
     #[error("Missing liveness analysis in the ir analysis results.")]
     MissingLivenessAnalysis,
 }
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CertificateError {
+    #[error("Expression contains a non-fluid leaf (`{0}`), a certificate can only be derived for mixes of fluids.")]
+    NonFluidLeaf(String),
+    #[error("Step {0} references an operand that is not defined before it.")]
+    UndefinedOperand(usize),
+    #[error("Step {step} claims `{claimed}` but mixing its operands gives `{computed}`.")]
+    StepMismatch {
+        step: usize,
+        claimed: String,
+        computed: String,
+    },
+    #[error("Mixing requires a non-zero total volume (step {0}).")]
+    ZeroVolume(usize),
+    #[error("Step {0} overflows the exact arithmetic of the certificate.")]
+    Overflow(usize),
+    #[error("Certificate concludes `{claimed}` but its derivation gives `{computed}`.")]
+    ConclusionMismatch { claimed: String, computed: String },
+}
+
+/// A node of an expression which does not produce a fluid.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Node `{node}` at {path} {reason}.")]
+pub struct EvaluationError {
+    pub path: ExprPath,
+    pub node: String,
+    pub reason: EvaluationFailure,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvaluationFailure {
+    #[error("is an arithmetic remnant, not a fluid")]
+    NonFluid,
+    #[error("has a negative concentration ({0})")]
+    InvalidConcentration(Concentration),
+    #[error("has a negative volume ({0})")]
+    InvalidVolume(Volume),
+    #[error("has zero volume")]
+    ZeroVolume,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExportError {
+    #[error("Design needs {needed} wells but the plate only has {available}.")]
+    OutOfWells { needed: usize, available: usize },
+    #[error("Malformed ir operation at index {0}, cannot export it.")]
+    MalformedIR(usize),
+    #[error("Volume {0} is not a whole number of droplets, cannot export it.")]
+    NotDropletQuantized(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DesignValidationError {
+    #[error("Design uses {used} units of input `{concentration}` but only {available} units are available, {dead} units of which are dead volume.")]
+    BelowDeadVolume {
+        concentration: Concentration,
+        used: f64,
+        dead: f64,
+        available: f64,
+    },
+    #[error("Mix {step} produces an intermediate with concentration `{concentration}`, which is inside the forbidden window {window}.")]
+    ForbiddenConcentration {
+        step: usize,
+        concentration: String,
+        window: ConcentrationWindow,
+    },
+    #[error("Mix {step} `{node}` produces an intermediate of volume {volume}, which exceeds the maximum intermediate volume of {max}.")]
+    IntermediateVolumeExceeded {
+        step: usize,
+        node: String,
+        volume: String,
+        max: Volume,
+    },
+    #[error("Mix {step} `{node}` mixes its fluids in the ratio {ratio}, which the mixer does not support. Supported ratios are {supported}.")]
+    UnsupportedMixRatio {
+        step: usize,
+        node: String,
+        ratio: String,
+        supported: MixerCapabilities,
+    },
+    #[error("Design does not produce the required intermediate concentration `{0}`.")]
+    MissingRequiredIntermediate(Concentration),
+    #[error("Mix {step} `{node}` combines the incompatible inputs {incompatibility}, which make up {lhs_fraction} and {rhs_fraction} of the fluids mixed while at most {max_fraction} of each is allowed.", max_fraction = .incompatibility.max_fraction())]
+    IncompatibleInputsMixed {
+        step: usize,
+        node: String,
+        incompatibility: Incompatibility,
+        lhs_fraction: String,
+        rhs_fraction: String,
+    },
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StorageAllocationError {
+    #[error("Storage pin refers to `{0}`, which is not one of the inputs.")]
+    UnknownPinnedInput(String),
+    #[error("Storage unit {storage_unit} is pinned to both {first} and {second}, which the design holds at the same time (instructions {first_vreg} and {second_vreg}).")]
+    ConflictingPins {
+        storage_unit: u64,
+        first: String,
+        second: String,
+        first_vreg: usize,
+        second_vreg: usize,
+    },
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    #[error("`{0}` cannot be represented exactly, values are limited to a precision of {1}.")]
+    Inexact(f64, f64),
+    #[error("`{0}` is not a finite number.")]
+    NotFinite(f64),
+    #[error("`{0}` is too close to zero to be represented reliably.")]
+    Subnormal(f64),
+    #[error("`{0}` is not a number.")]
+    Unparsable(String),
+    #[error("`{0}` cannot be represented as a fraction with a power of two denominator.")]
+    NotDyadic(f64),
+    #[error("`{0}` is out of the representable range.")]
+    OutOfRange(f64),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum UnitError {
+    #[error(
+        "Unknown concentration unit `{unit}`, expected one of `%`, `M` or `mg/mL`.{}",
+        render_hint(.hint)
+    )]
+    UnknownUnit { unit: String, hint: Option<String> },
+    #[error("`{0}` is not a concentration.")]
+    InvalidValue(String),
+    #[error("{0}")]
+    ConversionError(#[from] ConversionError),
+    #[error("Converting `{unit}` needs the molecular weight of `{reagent}`.")]
+    MissingMolecularWeight {
+        reagent: String,
+        unit: ConcentrationUnit,
+    },
+    #[error("Molecular weight must be a positive number, found `{0}`.")]
+    InvalidMolecularWeight(f64),
+    #[error("Missing reagent name in `{0}`.")]
+    MissingReagentName(String),
+}
+
+/// Options of a run which do not fit together, each pointing at the option given.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error(
+        "`{flag} {value}` refers to unknown input `{name}`.{} Name inputs with `--input-space \
+         {name}=<concentration>`.",
+        did_you_mean(.suggestion)
+    )]
+    UnknownInput {
+        flag: String,
+        value: String,
+        name: String,
+        /// Name of the input closest to the unknown one.
+        suggestion: Option<String>,
+    },
+    #[error("Expected {expected} values for `{flag}`, one per `--input-space`, found {found}.")]
+    ValueCountMismatch {
+        flag: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    #[error("Phase `{phase}` needs the {artifact}, which no earlier phase produced.")]
+    MissingArtifact {
+        phase: String,
+        artifact: &'static str,
+    },
+    #[error("Pipeline has no phase named `{0}`.")]
+    UnknownPhase(String),
+    #[error("Search panicked: {0}")]
+    SearchPanicked(String),
+}
+
 #[derive(Error, Debug)]
 pub enum FluidoError {
     #[error("{0}")]
@@ -28,6 +318,22 @@ pub enum FluidoError {
     IRGenerationError(IRGenerationError),
     #[error("{0}")]
     InterferenceGraphGenerationError(InterefenceGraphGenerationError),
+    #[error("{0}")]
+    CertificateError(CertificateError),
+    #[error("{0}")]
+    DesignValidationError(DesignValidationError),
+    #[error("{0}")]
+    PipelineError(PipelineError),
+    #[error("{0}")]
+    StorageAllocationError(StorageAllocationError),
+    #[error("{0}")]
+    UnitError(UnitError),
+    #[error("{0}")]
+    EvaluationError(EvaluationError),
+    #[error("{0}")]
+    ConfigError(ConfigError),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
 }
 
 impl From<MixerGenerationError> for FluidoError {
@@ -47,3 +353,45 @@ impl From<InterefenceGraphGenerationError> for FluidoError {
         Self::InterferenceGraphGenerationError(value)
     }
 }
+
+impl From<CertificateError> for FluidoError {
+    fn from(value: CertificateError) -> Self {
+        Self::CertificateError(value)
+    }
+}
+
+impl From<DesignValidationError> for FluidoError {
+    fn from(value: DesignValidationError) -> Self {
+        Self::DesignValidationError(value)
+    }
+}
+
+impl From<PipelineError> for FluidoError {
+    fn from(value: PipelineError) -> Self {
+        Self::PipelineError(value)
+    }
+}
+
+impl From<StorageAllocationError> for FluidoError {
+    fn from(value: StorageAllocationError) -> Self {
+        Self::StorageAllocationError(value)
+    }
+}
+
+impl From<UnitError> for FluidoError {
+    fn from(value: UnitError) -> Self {
+        Self::UnitError(value)
+    }
+}
+
+impl From<EvaluationError> for FluidoError {
+    fn from(value: EvaluationError) -> Self {
+        Self::EvaluationError(value)
+    }
+}
+
+impl From<ConfigError> for FluidoError {
+    fn from(value: ConfigError) -> Self {
+        Self::ConfigError(value)
+    }
+}
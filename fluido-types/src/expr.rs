@@ -1,4 +1,9 @@
-use crate::fluid::{Concentration, Fluid};
+use crate::{
+    error::{EvaluationError, EvaluationFailure},
+    fluid::{Concentration, Fluid, Volume},
+    pretty::PrettyPrinter,
+};
+use std::fmt::Display;
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub enum Expr {
@@ -6,3 +11,199 @@ pub enum Expr {
     LimitedFloat(Concentration),
     Fluid(Fluid),
 }
+
+impl Expr {
+    /// Number of nodes of the expression, counting mixes, fluids and arithmetic remnants.
+    pub fn size(&self) -> usize {
+        let mut size = 0;
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            size += 1;
+            if let Expr::Mix(lhs, rhs) = expr {
+                pending.push(lhs);
+                pending.push(rhs);
+            }
+        }
+        size
+    }
+
+    /// Number of nodes on the longest path from the root to a leaf, one for a single fluid.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut pending = vec![(self, 1)];
+        while let Some((expr, level)) = pending.pop() {
+            depth = depth.max(level);
+            if let Expr::Mix(lhs, rhs) = expr {
+                pending.push((lhs, level + 1));
+                pending.push((rhs, level + 1));
+            }
+        }
+        depth
+    }
+}
+
+/// Prints the expression on a single line, or with `PrettyPrinter` when the alternate flag (`{:#}`)
+/// is given.
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", PrettyPrinter::default().print(self));
+        }
+        match self {
+            Expr::Mix(lhs, rhs) => write!(f, "(mix {lhs} {rhs})"),
+            Expr::LimitedFloat(lf) => write!(f, "{lf}"),
+            Expr::Fluid(fluid) => write!(f, "{fluid}"),
+        }
+    }
+}
+
+/// Operand of a mix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MixOperand {
+    Lhs,
+    Rhs,
+}
+
+/// Location of a sub-expression, the operands taken from the root to reach it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ExprPath(pub Vec<MixOperand>);
+
+/// Prints the path as i.e `root.lhs.rhs`.
+impl Display for ExprPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "root")?;
+        for operand in &self.0 {
+            match operand {
+                MixOperand::Lhs => write!(f, ".lhs")?,
+                MixOperand::Rhs => write!(f, ".rhs")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Evaluates the fluid the expression produces, mixing with `Fluid::mix`. Fails at the first node,
+/// in evaluation order, which does not produce a fluid.
+impl TryFrom<&Expr> for Fluid {
+    type Error = EvaluationError;
+
+    fn try_from(value: &Expr) -> Result<Self, Self::Error> {
+        evaluate(value, &mut ExprPath::default())
+    }
+}
+
+fn evaluate(expr: &Expr, path: &mut ExprPath) -> Result<Fluid, EvaluationError> {
+    let fail = |reason, path: &ExprPath| EvaluationError {
+        path: path.clone(),
+        node: expr.to_string(),
+        reason,
+    };
+    match expr {
+        Expr::LimitedFloat(_) => Err(fail(EvaluationFailure::NonFluid, path)),
+        Expr::Fluid(fluid) => {
+            let zero = Volume::from(0.0);
+            let negative = fluid
+                .concentrations()
+                .into_iter()
+                .find(|concentration| concentration < &zero);
+            if let Some(concentration) = negative {
+                Err(fail(
+                    EvaluationFailure::InvalidConcentration(concentration),
+                    path,
+                ))
+            } else if fluid.unit_volume() < &zero {
+                Err(fail(
+                    EvaluationFailure::InvalidVolume(fluid.unit_volume().clone()),
+                    path,
+                ))
+            } else if fluid.unit_volume() == &zero {
+                Err(fail(EvaluationFailure::ZeroVolume, path))
+            } else {
+                Ok(fluid.clone())
+            }
+        }
+        Expr::Mix(lhs, rhs) => {
+            let mut operand = |operand, expr| {
+                path.0.push(operand);
+                let fluid = evaluate(expr, path);
+                path.0.pop();
+                fluid
+            };
+            let lhs = operand(MixOperand::Lhs, lhs)?;
+            let rhs = operand(MixOperand::Rhs, rhs)?;
+            Ok(lhs.mix(&rhs))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, ExprPath, MixOperand};
+    use crate::{
+        error::{EvaluationError, EvaluationFailure},
+        fluid::Fluid,
+    };
+
+    fn fluid(concentration: f64, volume: f64) -> Box<Expr> {
+        Box::new(Expr::Fluid(Fluid::new(concentration.into(), volume.into())))
+    }
+
+    #[test]
+    fn evaluate_mixes_into_a_fluid() {
+        let expr = Expr::Mix(
+            Box::new(Expr::Mix(fluid(0.2, 1.0), fluid(0.0, 1.0))),
+            fluid(0.0, 2.0),
+        );
+        assert_eq!(
+            Fluid::try_from(&expr),
+            Ok(Fluid::new(0.05.into(), 4.0.into()))
+        );
+    }
+
+    #[test]
+    fn evaluation_failures_name_the_node() {
+        let remnant = Expr::Mix(
+            fluid(0.2, 1.0),
+            Box::new(Expr::Mix(
+                fluid(0.0, 1.0),
+                Box::new(Expr::LimitedFloat(0.1.into())),
+            )),
+        );
+        let err = Fluid::try_from(&remnant).unwrap_err();
+        assert_eq!(
+            err,
+            EvaluationError {
+                path: ExprPath(vec![MixOperand::Rhs, MixOperand::Rhs]),
+                node: "0.1".to_string(),
+                reason: EvaluationFailure::NonFluid,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Node `0.1` at root.rhs.rhs is an arithmetic remnant, not a fluid."
+        );
+
+        let zero_volume = Expr::Mix(fluid(0.2, 1.0), fluid(0.0, 0.0));
+        let err = Fluid::try_from(&zero_volume).unwrap_err();
+        assert_eq!(err.reason, EvaluationFailure::ZeroVolume);
+        assert_eq!(err.path.to_string(), "root.rhs");
+
+        let negative = Expr::Mix(fluid(-0.2, 1.0), fluid(0.0, -1.0));
+        let err = Fluid::try_from(&negative).unwrap_err();
+        assert_eq!(
+            err.reason,
+            EvaluationFailure::InvalidConcentration((-0.2).into())
+        );
+        assert_eq!(err.path.to_string(), "root.lhs");
+    }
+
+    #[test]
+    fn size_and_depth_count_nodes() {
+        let expr = Expr::Mix(
+            Box::new(Expr::Mix(fluid(0.2, 1.0), fluid(0.0, 1.0))),
+            fluid(0.0, 2.0),
+        );
+        assert_eq!((expr.size(), expr.depth()), (5, 3));
+        assert_eq!((fluid(0.2, 1.0).size(), fluid(0.2, 1.0).depth()), (1, 1));
+    }
+}
@@ -1,23 +1,46 @@
-use std::{fmt::Display, num::ParseFloatError, str::FromStr};
+use crate::{concentration_set::ConcentrationSet, error::ConversionError, number::Rational};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
 
 pub use crate::number::LimitedFloat;
 pub type Concentration = LimitedFloat;
 pub type Volume = LimitedFloat;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A fluid of one or more reagents. The concentration of the first reagent is `concentration`,
+/// which is the one the saturation searches for and certificates prove, the ones of the others
+/// are only carried along, see `Fluid::with_reagents`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Fluid {
     concentration: Concentration,
     unit_volume: Volume,
+    /// Concentrations of the reagents after the first one, without trailing zeros so that a fluid
+    /// lacking a reagent equals one with none of it. Not set for fluids of a single reagent, which
+    /// are thus never allocated.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_reagents"
+    )]
+    reagents: Option<Box<[Concentration]>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Reagents after the first one, an empty list is a fluid of a single reagent.
+fn deserialize_reagents<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Box<[Concentration]>>, D::Error> {
+    let reagents = Option::<Vec<Concentration>>::deserialize(deserializer)?.unwrap_or_default();
+    Ok(Fluid::trimmed_reagents(reagents))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum FluidParseError {
-    InvalidFloatParse(ParseFloatError),
-    InvalidVolumeParse(ParseFloatError),
+    InvalidFloatParse(ConversionError),
+    InvalidVolumeParse(ConversionError),
     MissingParanthesis,
     MissingFluidKeyword,
     MissingSpace,
     MissingVolAndOrConcentration,
+    MissingBracket,
 }
 
 impl From<FluidParseError> for anyhow::Error {
@@ -42,25 +65,38 @@ impl FromStr for Fluid {
                 .next()
                 .ok_or(FluidParseError::MissingVolAndOrConcentration)?
                 .trim();
-            let mut splitted_s = s.split(' ');
-            let concentration_str = splitted_s
-                .next()
-                .ok_or(FluidParseError::MissingSpace)?
-                .trim();
-            let unit_volume_str = splitted_s
+            let (concentration_strs, unit_volume_str) = match s.strip_prefix('[') {
+                Some(s) => {
+                    let (concentrations, rest) =
+                        s.split_once(']').ok_or(FluidParseError::MissingBracket)?;
+                    (concentrations.split_whitespace().collect::<Vec<_>>(), rest)
+                }
+                None => {
+                    let (concentration, rest) = s
+                        .split_once(char::is_whitespace)
+                        .ok_or(FluidParseError::MissingSpace)?;
+                    (vec![concentration.trim()], rest)
+                }
+            };
+            let unit_volume_str = unit_volume_str
+                .split_whitespace()
                 .next()
-                .ok_or(FluidParseError::MissingSpace)?
-                .trim();
+                .ok_or(FluidParseError::MissingSpace)?;
 
-            let concentration = Concentration::from_str(concentration_str)
-                .map_err(FluidParseError::InvalidFloatParse)?;
+            let concentrations = concentration_strs
+                .into_iter()
+                .map(|concentration_str| {
+                    Concentration::from_str(concentration_str)
+                        .map_err(FluidParseError::InvalidFloatParse)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if concentrations.is_empty() {
+                return Err(FluidParseError::MissingVolAndOrConcentration);
+            }
             let unit_volume =
                 Volume::from_str(unit_volume_str).map_err(FluidParseError::InvalidVolumeParse)?;
 
-            let fluid = Self {
-                concentration,
-                unit_volume,
-            };
+            let fluid = Self::with_reagents(concentrations, unit_volume);
             Ok(fluid)
         } else {
             Err(FluidParseError::MissingParanthesis)
@@ -68,12 +104,23 @@ impl FromStr for Fluid {
     }
 }
 
+/// Prints `(fluid <concentration> <volume>)`, or `(fluid [<concentration> ...] <volume>)` with
+/// the concentration of each reagent if there is more than one.
 impl Display for Fluid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "(")?;
         write!(f, "fluid")?;
         write!(f, " ")?;
-        write!(f, "{}", self.concentration)?;
+        if self.reagents.is_none() {
+            write!(f, "{}", self.concentration)?;
+        } else {
+            let concentrations = self
+                .concentrations()
+                .iter()
+                .map(|concentration| concentration.to_string())
+                .collect::<Vec<_>>();
+            write!(f, "[{}]", concentrations.join(" "))?;
+        }
         write!(f, " ")?;
         write!(f, "{}", self.unit_volume)?;
         write!(f, ")")
@@ -88,46 +135,340 @@ impl Fluid {
         Self {
             concentration,
             unit_volume,
+            reagents: None,
+        }
+    }
+
+    /// Creates a fluid of several reagents, i.e a buffer and a dye, with the concentration of each
+    /// one. Reagents missing from the end have a concentration of zero, and a fluid without any
+    /// is a fluid of a single reagent with no concentration.
+    pub fn with_reagents(concentrations: Vec<Concentration>, unit_volume: Volume) -> Self {
+        let mut concentrations = concentrations.into_iter();
+        let concentration = concentrations.next().unwrap_or_else(|| 0.0.into());
+        Self {
+            concentration,
+            unit_volume,
+            reagents: Self::trimmed_reagents(concentrations.collect()),
         }
     }
 
+    /// The reagents without trailing zeros, `None` if none is left.
+    fn trimmed_reagents(mut reagents: Vec<Concentration>) -> Option<Box<[Concentration]>> {
+        let zero = Concentration::from(0.0);
+        while reagents.last() == Some(&zero) {
+            reagents.pop();
+        }
+        (!reagents.is_empty()).then(|| reagents.into_boxed_slice())
+    }
+
+    /// Concentrations of the reagents after the first one.
+    fn other_reagents(&self) -> &[Concentration] {
+        self.reagents.as_deref().unwrap_or_default()
+    }
+
     /// Mix two fluids, this is a high level representation so it assumes:
     ///  1. Fluids mixes perfectly
     ///  2. Input fluids volumes summed equals to output fluid. (No loss in terms of liquid
     ///     volume).
+    ///
+    /// Every reagent is mixed the same way, a reagent missing from one of the fluids is diluted
+    /// by it.
     pub fn mix(&self, other: &Fluid) -> Self {
-        let self_conc: f64 = self.concentration.clone().into();
-        let other_conc: f64 = other.concentration.clone().into();
-
         let self_vol: f64 = self.unit_volume().clone().into();
         let other_vol: f64 = other.unit_volume().clone().into();
 
         let resulting_vol = self_vol + other_vol;
+        let mix = |self_conc: f64, other_conc: f64| {
+            Concentration::saturating_from_f64(
+                ((self_conc * self_vol) + (other_conc * other_vol)) / resulting_vol,
+            )
+        };
+        let concentration = mix(
+            self.concentration.clone().into(),
+            other.concentration.clone().into(),
+        );
+        let resulting_vol = Volume::saturating_from_f64(resulting_vol);
+        // Fluids of a single reagent, which is all the saturation mixes, are mixed without
+        // allocating.
+        if self.reagents.is_none() && other.reagents.is_none() {
+            return Self::new(concentration, resulting_vol);
+        }
 
-        let resulting_conc = ((self_conc * self_vol) + (other_conc * other_vol)) / resulting_vol;
-
-        let resulting_conc = Concentration::from(resulting_conc);
-        let resulting_vol = Volume::from(resulting_vol);
-
-        Self::new(resulting_conc, resulting_vol)
+        let (self_reagents, other_reagents) = (self.other_reagents(), other.other_reagents());
+        let conc = |reagents: &[Concentration], ix: usize| -> f64 {
+            reagents.get(ix).map_or(0.0, |conc| conc.clone().into())
+        };
+        let reagents = (0..self_reagents.len().max(other_reagents.len()))
+            .map(|ix| mix(conc(self_reagents, ix), conc(other_reagents, ix)))
+            .collect();
+        Self {
+            concentration,
+            unit_volume: resulting_vol,
+            reagents: Self::trimmed_reagents(reagents),
+        }
     }
 
-    /// Returns a reference to the underlying `Concentration` for this fluid.
+    /// Returns a reference to the underlying `Concentration` for this fluid, the one of its first
+    /// reagent.
     pub fn concentration(&self) -> &Concentration {
         &self.concentration
     }
 
+    /// Concentration of each reagent of the fluid, at least one.
+    pub fn concentrations(&self) -> Vec<Concentration> {
+        std::iter::once(&self.concentration)
+            .chain(self.other_reagents())
+            .cloned()
+            .collect()
+    }
+
+    /// Number of reagents of the fluid, up to the last one it has any of.
+    pub fn reagent_count(&self) -> usize {
+        1 + self.other_reagents().len()
+    }
+
     /// Returns a reference to the underlying unit_volume.
     pub fn unit_volume(&self) -> &Volume {
         &self.unit_volume
     }
 }
 
+/// A closed range of concentrations that intermediate fluids must not have, i.e a range where
+/// the solute aggregates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConcentrationWindow {
+    low: Concentration,
+    high: Concentration,
+}
+
+impl ConcentrationWindow {
+    /// Creates the window `[low, high]`, the bounds are swapped if given in reverse.
+    pub fn new(low: Concentration, high: Concentration) -> Self {
+        if low <= high {
+            Self { low, high }
+        } else {
+            Self {
+                low: high,
+                high: low,
+            }
+        }
+    }
+
+    pub fn low(&self) -> &Concentration {
+        &self.low
+    }
+
+    pub fn high(&self) -> &Concentration {
+        &self.high
+    }
+
+    pub fn contains(&self, concentration: &Concentration) -> bool {
+        &self.low <= concentration && concentration <= &self.high
+    }
+
+    /// Whether the window contains the exact concentration.
+    pub fn contains_exact(&self, concentration: &Rational) -> bool {
+        let at_least = |bound: &Concentration| (*concentration - Rational::from(bound)).numerator();
+        at_least(&self.low) >= 0 && at_least(&self.high) <= 0
+    }
+}
+
+impl Display for ConcentrationWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}]", self.low, self.high)
+    }
+}
+
+/// Two inputs which must not be mixed directly, i.e because they precipitate. They may only meet
+/// once each of them makes up at most `max_fraction` of the volume of the fluid carrying it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incompatibility {
+    inputs: [Concentration; 2],
+    max_fraction: f64,
+}
+
+impl Incompatibility {
+    /// Inputs which must never meet, whatever they are diluted to.
+    pub fn new(lhs: Concentration, rhs: Concentration) -> Self {
+        Self {
+            inputs: [lhs, rhs],
+            max_fraction: 0.0,
+        }
+    }
+
+    /// Largest share of the volume of a fluid either input may make up when the fluid is mixed
+    /// with a fluid containing the other input, defaults to zero.
+    pub fn with_max_fraction(mut self, max_fraction: f64) -> Self {
+        self.max_fraction = max_fraction;
+        self
+    }
+
+    pub fn inputs(&self) -> &[Concentration; 2] {
+        &self.inputs
+    }
+
+    pub fn max_fraction(&self) -> f64 {
+        self.max_fraction
+    }
+
+    /// Whether the concentration is the input, within the tolerance inputs are matched with.
+    pub fn matches(input: &Concentration, concentration: &Concentration) -> bool {
+        input.delta(concentration) <= ConcentrationSet::TOLERANCE
+    }
+
+    /// Whether mixing fluids of the two concentrations, taken to be the undiluted inputs, is
+    /// forbidden.
+    pub fn forbids_direct(&self, lhs: &Concentration, rhs: &Concentration) -> bool {
+        let [a, b] = &self.inputs;
+        let pairs = |a, b| Self::matches(a, lhs) && Self::matches(b, rhs);
+        self.max_fraction < 1.0 && (pairs(a, b) || pairs(b, a))
+    }
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} and {}", self.inputs[0], self.inputs[1])
+    }
+}
+
+/// Ratio of the volumes of the two fluids of a mix, i.e `1:3`. Ratios are reduced and unordered,
+/// mixing one part with three parts is the same as mixing three parts with one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MixRatio {
+    lhs: u32,
+    rhs: u32,
+}
+
+impl MixRatio {
+    /// Mixing equal volumes.
+    pub const ONE_TO_ONE: MixRatio = MixRatio { lhs: 1, rhs: 1 };
+
+    /// The ratio `lhs:rhs`, `None` if either part is zero.
+    pub fn new(lhs: u32, rhs: u32) -> Option<Self> {
+        if lhs == 0 || rhs == 0 {
+            return None;
+        }
+        let reduced = Rational::new(i128::from(lhs.min(rhs)), i128::from(lhs.max(rhs)));
+        Some(Self {
+            lhs: reduced.numerator() as u32,
+            rhs: reduced.denominator() as u32,
+        })
+    }
+
+    /// Ratio of the exact volumes, `None` if either is not positive or the reduced ratio does not
+    /// fit in whole parts.
+    pub fn of(lhs: Rational, rhs: Rational) -> Option<Self> {
+        if lhs.numerator() <= 0 || rhs.numerator() <= 0 {
+            return None;
+        }
+        let ratio = lhs / rhs;
+        Self::new(
+            u32::try_from(ratio.numerator()).ok()?,
+            u32::try_from(ratio.denominator()).ok()?,
+        )
+    }
+
+    /// The smaller part.
+    pub fn lhs(&self) -> u32 {
+        self.lhs
+    }
+
+    /// The larger part.
+    pub fn rhs(&self) -> u32 {
+        self.rhs
+    }
+
+    /// Whether fluids of the given volumes, in either order, are mixed in this ratio. Volumes of
+    /// the e-graph are rounded, so they are compared with a relative tolerance.
+    pub fn matches(&self, lhs: f64, rhs: f64) -> bool {
+        const TOLERANCE: f64 = 1e-9;
+        let (small, large) = (lhs.min(rhs), lhs.max(rhs));
+        let (small, large) = (small * f64::from(self.rhs), large * f64::from(self.lhs));
+        small > 0.0 && (small - large).abs() <= TOLERANCE * large
+    }
+}
+
+impl Display for MixRatio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.lhs, self.rhs)
+    }
+}
+
+impl FromStr for MixRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("invalid mix ratio `{s}`, expected two positive parts such as `1:3`");
+        let (lhs, rhs) = s.split_once(':').ok_or_else(invalid)?;
+        let part = |part: &str| part.trim().parse::<u32>().map_err(|_| invalid());
+        Self::new(part(lhs)?, part(rhs)?).ok_or_else(invalid)
+    }
+}
+
+/// What the mixer of the hardware can do. Without capabilities any ratio can be mixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixerCapabilities {
+    ratios: Vec<MixRatio>,
+}
+
+impl MixerCapabilities {
+    /// A mixer mixing fluids in the given volume ratios only.
+    pub fn new(ratios: impl IntoIterator<Item = MixRatio>) -> Self {
+        let mut ratios = ratios.into_iter().collect::<Vec<_>>();
+        ratios.sort_by_key(|ratio| (ratio.lhs, ratio.rhs));
+        ratios.dedup();
+        Self { ratios }
+    }
+
+    pub fn ratios(&self) -> &[MixRatio] {
+        &self.ratios
+    }
+
+    /// Whether the mixer can mix fluids of the given volumes.
+    pub fn supports(&self, lhs: f64, rhs: f64) -> bool {
+        self.ratios.iter().any(|ratio| ratio.matches(lhs, rhs))
+    }
+}
+
+impl Display for MixerCapabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratios = self
+            .ratios
+            .iter()
+            .map(MixRatio::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "{}", ratios.join(", "))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn mix_ratios_are_reduced_and_unordered() {
+        let ratio = MixRatio::from_str("6:2").unwrap();
+        assert_eq!(ratio, MixRatio::new(1, 3).unwrap());
+        assert_eq!(ratio.to_string(), "1:3");
+        assert!(MixRatio::from_str("0:1").is_err());
+        assert!(MixRatio::from_str("1/3").is_err());
+
+        assert_eq!(
+            MixRatio::of(Rational::new(3, 2), Rational::new(1, 2)),
+            Some(ratio)
+        );
+        assert_eq!(MixRatio::of(Rational::new(0, 1), Rational::new(1, 1)), None);
+
+        let capabilities = MixerCapabilities::new([ratio, MixRatio::ONE_TO_ONE, ratio]);
+        assert_eq!(capabilities.to_string(), "1:1, 1:3");
+        assert!(capabilities.supports(3.0, 1.0));
+        assert!(capabilities.supports(0.5, 0.5));
+        assert!(!capabilities.supports(1.0, 2.0));
+        assert!(!capabilities.supports(0.0, 0.0));
+    }
+
     #[test]
     fn mix_two_equal_volume_fluids() {
         let concentration_a = Concentration::from(0.1);
@@ -172,4 +513,74 @@ mod tests {
 
         assert_eq!(expected_fluid, parsed_fluid)
     }
+
+    #[test]
+    fn parse_fluid_str_rejects_non_finite() {
+        assert!(matches!(
+            Fluid::from_str("(fluid NaN 1.0)"),
+            Err(FluidParseError::InvalidFloatParse(_))
+        ));
+        assert!(matches!(
+            Fluid::from_str("(fluid 0.1 inf)"),
+            Err(FluidParseError::InvalidVolumeParse(_))
+        ));
+    }
+
+    #[test]
+    fn reagents_are_mixed_alike() {
+        let buffer_and_dye = Fluid::from_str("(fluid [0.4 0.2] 1.0)").unwrap();
+        assert_eq!(
+            buffer_and_dye,
+            Fluid::with_reagents(vec![0.4.into(), 0.2.into()], 1.0.into())
+        );
+        assert_eq!(buffer_and_dye.reagent_count(), 2);
+        assert_eq!(buffer_and_dye.to_string(), "(fluid [0.4 0.2] 1.0)");
+
+        // The buffer lacks the dye, which it dilutes.
+        let buffer = Fluid::new(0.0.into(), 3.0.into());
+        let mixed = buffer_and_dye.mix(&buffer);
+        assert_eq!(
+            mixed.concentrations(),
+            vec![Concentration::from(0.1), Concentration::from(0.05)]
+        );
+        assert_eq!(mixed.unit_volume(), &Volume::from(4.0));
+
+        // Fluids of a single reagent are serialized without the others, which an empty list of
+        // reagents deserializes the same as.
+        let single = serde_json::to_value(&buffer).unwrap();
+        assert!(single.get("reagents").is_none());
+        let empty_reagents = serde_json::json!({
+            "concentration": single["concentration"],
+            "unit_volume": single["unit_volume"],
+            "reagents": [],
+        });
+        assert_eq!(
+            serde_json::from_value::<Fluid>(empty_reagents).unwrap(),
+            buffer
+        );
+        let serialized = serde_json::to_string(&mixed).unwrap();
+        assert_eq!(serde_json::from_str::<Fluid>(&serialized).unwrap(), mixed);
+
+        // Reagents with no concentration at the end are left out.
+        assert_eq!(
+            Fluid::from_str("(fluid [0.1 0.0] 1.0)").unwrap(),
+            Fluid::new(0.1.into(), 1.0.into())
+        );
+        assert_eq!(
+            Fluid::from_str("(fluid [0.1 0.2 1.0)"),
+            Err(FluidParseError::MissingBracket)
+        );
+    }
+
+    #[test]
+    fn concentration_window_is_closed() {
+        let window = ConcentrationWindow::new(0.5.into(), 0.4.into());
+        assert_eq!(window.low(), &Concentration::from(0.4));
+        assert!(window.contains(&0.4.into()));
+        assert!(window.contains(&0.5.into()));
+        assert!(!window.contains(&0.3.into()));
+        assert!(window.contains_exact(&Rational::new(9, 20)));
+        assert!(window.contains_exact(&Rational::new(1, 2)));
+        assert!(!window.contains_exact(&Rational::new(51, 100)));
+    }
 }
@@ -0,0 +1,287 @@
+//! The fluids at hand for a search, along with what is known about each of them.
+//!
+//! Inputs are looked up by concentration with the tolerance of `ConcentrationSet`, so that a leaf
+//! off from an input by a rounding error is still recognized as that input.
+use crate::{
+    concentration_set::ConcentrationSet,
+    fluid::{Concentration, Fluid},
+    suggest::{closest_match, did_you_mean},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// How much of an input is at hand. Leaves drawing from less abundant inputs are penalized by the
+/// cost function, shifting the consumption of a design towards abundant inputs.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputTier {
+    /// Free to consume, the default.
+    #[default]
+    Abundant,
+    Limited,
+    Scarce,
+}
+
+impl InputTier {
+    /// Cost of consuming as much of the input as the volume of the produced fluid, a mix costs one.
+    pub fn penalty(&self) -> f64 {
+        match self {
+            InputTier::Abundant => 0.0,
+            InputTier::Limited => 1.0,
+            InputTier::Scarce => 10.0,
+        }
+    }
+}
+
+impl FromStr for InputTier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "abundant" => Ok(InputTier::Abundant),
+            "limited" => Ok(InputTier::Limited),
+            "scarce" => Ok(InputTier::Scarce),
+            other => Err(format!(
+                "unknown input tier `{other}`, expected `abundant`, `limited` or `scarce`.{}",
+                did_you_mean(
+                    &closest_match(other, ["abundant", "limited", "scarce"])
+                        .map(ToString::to_string)
+                )
+            )),
+        }
+    }
+}
+
+impl Display for InputTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tier = match self {
+            InputTier::Abundant => "abundant",
+            InputTier::Limited => "limited",
+            InputTier::Scarce => "scarce",
+        };
+        write!(f, "{tier}")
+    }
+}
+
+/// A fluid at hand, along with its name and how abundant it is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    fluid: Fluid,
+    name: Option<String>,
+    tier: InputTier,
+    /// Whether the input only dilutes, i.e water or a buffer. Inputs without solute are diluents
+    /// by default.
+    diluent: bool,
+}
+
+impl Input {
+    pub fn new(fluid: Fluid) -> Self {
+        let diluent = f64::from(fluid.concentration().clone()) == 0.0;
+        Self {
+            fluid,
+            name: None,
+            tier: InputTier::default(),
+            diluent,
+        }
+    }
+
+    /// Name the input is referred to with, i.e by relative targets.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// How much of the input is at hand, defaults to abundant.
+    pub fn with_tier(mut self, tier: InputTier) -> Self {
+        self.tier = tier;
+        self
+    }
+
+    /// Whether the input only dilutes, defaults to whether it has no solute.
+    pub fn with_diluent(mut self, diluent: bool) -> Self {
+        self.diluent = diluent;
+        self
+    }
+
+    pub fn fluid(&self) -> &Fluid {
+        &self.fluid
+    }
+
+    pub fn concentration(&self) -> &Concentration {
+        self.fluid.concentration()
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn tier(&self) -> InputTier {
+        self.tier
+    }
+
+    pub fn is_diluent(&self) -> bool {
+        self.diluent
+    }
+}
+
+impl From<Fluid> for Input {
+    fn from(fluid: Fluid) -> Self {
+        Self::new(fluid)
+    }
+}
+
+/// Inputs of a search in the order they are given, looked up by concentration with a tolerance.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputSpace {
+    inputs: Vec<Input>,
+    concentrations: ConcentrationSet,
+}
+
+impl InputSpace {
+    pub fn new(inputs: impl IntoIterator<Item = Input>) -> Self {
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let concentrations = inputs
+            .iter()
+            .map(|input| input.concentration().clone())
+            .collect();
+        Self {
+            inputs,
+            concentrations,
+        }
+    }
+
+    /// Inputs of a unit volume each, with the given concentrations.
+    pub fn from_concentrations(concentrations: impl IntoIterator<Item = Concentration>) -> Self {
+        concentrations
+            .into_iter()
+            .map(|concentration| Fluid::new(concentration, 1.0.into()))
+            .collect()
+    }
+
+    /// Inputs in the order they are given.
+    pub fn iter(&self) -> impl Iterator<Item = &Input> {
+        self.inputs.iter()
+    }
+
+    /// Fluids of the inputs in the order they are given.
+    pub fn fluids(&self) -> impl Iterator<Item = &Fluid> {
+        self.inputs.iter().map(Input::fluid)
+    }
+
+    /// Distinct concentrations of the inputs, in ascending order.
+    pub fn concentrations(&self) -> &ConcentrationSet {
+        &self.concentrations
+    }
+
+    /// The input closest to the concentration, regardless of how far it is.
+    pub fn nearest(&self, concentration: &Concentration) -> Option<&Input> {
+        let nearest = self.concentrations.nearest(concentration)?;
+        self.inputs
+            .iter()
+            .find(|input| input.concentration() == nearest)
+    }
+
+    /// The input the concentration matches, if any is within the tolerance of it.
+    pub fn get(&self, concentration: &Concentration) -> Option<&Input> {
+        let member = self.concentrations.get(concentration)?;
+        self.inputs
+            .iter()
+            .find(|input| input.concentration() == member)
+    }
+
+    /// Whether an input is within the tolerance of the concentration.
+    pub fn contains(&self, concentration: &Concentration) -> bool {
+        self.concentrations.contains(concentration)
+    }
+
+    /// The input with the name.
+    pub fn by_name(&self, name: &str) -> Option<&Input> {
+        self.inputs.iter().find(|input| input.name() == Some(name))
+    }
+
+    /// Inputs which only dilute.
+    pub fn diluents(&self) -> impl Iterator<Item = &Input> {
+        self.inputs.iter().filter(|input| input.is_diluent())
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+impl FromIterator<Input> for InputSpace {
+    fn from_iter<T: IntoIterator<Item = Input>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+impl FromIterator<Fluid> for InputSpace {
+    fn from_iter<T: IntoIterator<Item = Fluid>>(iter: T) -> Self {
+        Self::new(iter.into_iter().map(Input::new))
+    }
+}
+
+impl From<&[Fluid]> for InputSpace {
+    fn from(fluids: &[Fluid]) -> Self {
+        fluids.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Input, InputSpace, InputTier};
+    use crate::fluid::Fluid;
+
+    #[test]
+    fn inputs_are_looked_up_with_a_tolerance() {
+        let input_space = InputSpace::new([
+            Input::new(Fluid::new(0.4.into(), 1.0.into()))
+                .with_name("stock")
+                .with_tier(InputTier::Scarce),
+            Input::new(Fluid::new(0.0.into(), 1.0.into())),
+            Input::new(Fluid::new(0.1.into(), 1.0.into())),
+        ]);
+        assert_eq!(input_space.len(), 3);
+        assert_eq!(
+            input_space.get(&0.3999.into()).map(Input::tier),
+            Some(InputTier::Scarce)
+        );
+        assert!(input_space.get(&0.3998.into()).is_none());
+        assert_eq!(
+            input_space.nearest(&0.3.into()).and_then(Input::name),
+            Some("stock")
+        );
+        assert!(input_space.contains(&0.0001.into()));
+        assert_eq!(
+            input_space.by_name("stock").map(Input::concentration),
+            Some(&0.4.into())
+        );
+
+        // Only the input without solute dilutes, unless marked otherwise.
+        let diluents = input_space
+            .diluents()
+            .map(Input::concentration)
+            .collect::<Vec<_>>();
+        assert_eq!(diluents, [&0.0.into()]);
+        let buffer = Input::new(Fluid::new(0.1.into(), 1.0.into())).with_diluent(true);
+        assert!(buffer.is_diluent());
+
+        assert_eq!(
+            InputSpace::from_concentrations([0.4.into(), 0.0.into()]),
+            InputSpace::from(
+                &[
+                    Fluid::new(0.4.into(), 1.0.into()),
+                    Fluid::new(0.0.into(), 1.0.into())
+                ][..]
+            )
+        );
+        assert_eq!("scarce".parse::<InputTier>(), Ok(InputTier::Scarce));
+        assert!("rare".parse::<InputTier>().is_err());
+    }
+}
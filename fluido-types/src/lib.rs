@@ -1,4 +1,13 @@
+pub mod certificate;
+pub mod concentration_set;
+pub mod decimal;
 pub mod error;
 pub mod expr;
 pub mod fluid;
+pub mod input_space;
+pub mod log;
 pub mod number;
+pub mod precision;
+pub mod pretty;
+pub mod suggest;
+pub mod units;
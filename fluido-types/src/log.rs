@@ -0,0 +1,89 @@
+//! Destination of the logs a search prints, i.e its saturation report and ir dumps.
+//!
+//! Searches running concurrently in one process would interleave their logs on stdout, so each
+//! search can be given its own sink instead.
+use std::{
+    fmt::{self, Debug},
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+/// Where the logs of a search are written to, stdout by default. Clones write to the same
+/// destination.
+#[derive(Clone, Default)]
+pub struct LogSink {
+    writer: Option<Arc<Mutex<dyn Write + Send>>>,
+}
+
+impl LogSink {
+    pub fn stdout() -> Self {
+        Self::default()
+    }
+
+    /// Writes the logs into the writer, i.e a file or a buffer kept by a server for each search.
+    pub fn to_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Some(Arc::new(Mutex::new(writer))),
+        }
+    }
+
+    /// Writes the formatted logs, so that the sink can be used with `write!` and `writeln!`. Logs
+    /// which cannot be written are dropped, failing the search over them would lose its result.
+    pub fn write_fmt(&self, args: fmt::Arguments<'_>) {
+        let _ = match &self.writer {
+            Some(writer) => match writer.lock() {
+                Ok(mut writer) => writer.write_fmt(args),
+                // A search panicked while logging, the writer itself is still usable.
+                Err(poisoned) => poisoned.into_inner().write_fmt(args),
+            },
+            None => io::stdout().lock().write_fmt(args),
+        };
+    }
+}
+
+impl Debug for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.writer {
+            Some(_) => write!(f, "LogSink(writer)"),
+            None => write!(f, "LogSink(stdout)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogSink;
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    /// Buffer shared with the test, so that the logs can be inspected after writing them.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn clones_write_to_the_same_writer() {
+        let buffer = SharedBuffer::default();
+        let sink = LogSink::to_writer(buffer.clone());
+        writeln!(sink, "cost {}", 1);
+        let clone = sink.clone();
+        std::thread::spawn(move || write!(clone, "from a worker"))
+            .join()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap(),
+            "cost 1\nfrom a worker"
+        );
+    }
+}
@@ -1,7 +1,16 @@
+//! Numbers of the mixer space.
+//!
+//! `LimitedFloat` holds concentrations and volumes as multiples of `LimitedFloat::EPSILON`. Exact
+//! arithmetic, i.e of certificates, uses `Rational` rather than `Frac`: a `Frac` is a dyadic
+//! fraction, with a power of two denominator, which only holds the results of mixes in equal
+//! volumes. Mixing in any other ratio divides by a sum of volumes which need not be a power of two,
+//! i.e one part of `0.1` with two parts of `0.0` gives `1/30`, so only a rational can represent it.
+//! Its arithmetic is checked, as mixes multiply the denominators of their operands.
+use crate::error::ConversionError;
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
-    num::ParseFloatError,
+    fmt::Display,
     ops::{Add, Div, Mul, Sub},
     str::FromStr,
 };
@@ -75,12 +84,334 @@ impl Div for Frac {
     }
 }
 
+/// An exact rational number, always kept in its reduced form with a positive denominator.
+///
+/// Used where precision loss is not acceptable, i.e for proving that a design produces a
+/// concentration.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Rational {
+    /// Creates a new rational `numerator / denominator`.
+    ///
+    /// Panics if the denominator is zero.
+    pub fn new(numerator: i128, denominator: i128) -> Self {
+        assert!(denominator != 0, "rational with zero denominator");
+        let sign = denominator.signum();
+        let divisor = gcd(numerator, denominator).max(1);
+        Self {
+            numerator: sign * numerator / divisor,
+            denominator: sign * denominator / divisor,
+        }
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    /// Sum of the rationals, `None` if it overflows.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        // Scaling to the least common denominator keeps the intermediate values small.
+        let divisor = gcd(self.denominator, other.denominator);
+        let lhs_scale = other.denominator / divisor;
+        let rhs_scale = self.denominator / divisor;
+        let numerator = self
+            .numerator
+            .checked_mul(lhs_scale)?
+            .checked_add(other.numerator.checked_mul(rhs_scale)?)?;
+        Some(Self::new(
+            numerator,
+            self.denominator.checked_mul(lhs_scale)?,
+        ))
+    }
+
+    /// Difference of the rationals, `None` if it overflows.
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_add(Self {
+            numerator: other.numerator.checked_neg()?,
+            denominator: other.denominator,
+        })
+    }
+
+    /// Product of the rationals, `None` if it overflows.
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        // Reducing crosswise first keeps the products small.
+        let lhs_divisor = gcd(self.numerator, other.denominator).max(1);
+        let rhs_divisor = gcd(other.numerator, self.denominator).max(1);
+        Some(Self::new(
+            (self.numerator / lhs_divisor).checked_mul(other.numerator / rhs_divisor)?,
+            (self.denominator / rhs_divisor).checked_mul(other.denominator / lhs_divisor)?,
+        ))
+    }
+
+    /// Quotient of the rationals, `None` if it overflows or `other` is zero.
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        self.checked_mul(Self::new(other.denominator, other.numerator))
+    }
+}
+
+impl Add for Rational {
+    type Output = Self;
+
+    /// Panics on overflow, see `Rational::checked_add`.
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other)
+            .expect("rational addition overflowed")
+    }
+}
+
+impl Sub for Rational {
+    type Output = Self;
+
+    /// Panics on overflow, see `Rational::checked_sub`.
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .expect("rational subtraction overflowed")
+    }
+}
+
+impl Mul for Rational {
+    type Output = Self;
+
+    /// Panics on overflow, see `Rational::checked_mul`.
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other)
+            .expect("rational multiplication overflowed")
+    }
+}
+
+impl Div for Rational {
+    type Output = Self;
+
+    /// Panics on overflow or a zero divisor, see `Rational::checked_div`.
+    fn div(self, other: Self) -> Self {
+        assert!(!other.is_zero(), "rational division by zero");
+        self.checked_div(other)
+            .expect("rational division overflowed")
+    }
+}
+
+impl From<Frac> for Rational {
+    fn from(value: Frac) -> Self {
+        if value.power >= 0 {
+            Self::new(value.numerator as i128, 1i128 << value.power)
+        } else {
+            Self::new((value.numerator as i128) << -value.power, 1)
+        }
+    }
+}
+
+impl From<&LimitedFloat> for Rational {
+    /// Exact value of the limited float, which is a multiple of `LimitedFloat::EPSILON`.
+    fn from(value: &LimitedFloat) -> Self {
+        let scale = (1.0 / LimitedFloat::EPSILON).round() as i128;
+        Self::new(value.wrapped as i128, scale)
+    }
+}
+
+impl TryFrom<Rational> for LimitedFloat {
+    type Error = ConversionError;
+
+    /// Succeeds if the rational is a multiple of `LimitedFloat::EPSILON`.
+    fn try_from(value: Rational) -> Result<Self, Self::Error> {
+        let scale = (1.0 / LimitedFloat::EPSILON).round() as i128;
+        let scaled = value.numerator * scale;
+        if scaled % value.denominator != 0 {
+            return Err(ConversionError::Inexact(
+                value.into(),
+                LimitedFloat::EPSILON,
+            ));
+        }
+        let wrapped = i64::try_from(scaled / value.denominator)
+            .map_err(|_| ConversionError::OutOfRange(value.into()))?;
+        Ok(Self { wrapped })
+    }
+}
+
+impl TryFrom<Rational> for Frac {
+    type Error = ConversionError;
+
+    /// Succeeds if the denominator of the rational is a power of two.
+    fn try_from(value: Rational) -> Result<Self, Self::Error> {
+        let denominator = value.denominator;
+        if denominator.count_ones() != 1 {
+            return Err(ConversionError::NotDyadic(value.into()));
+        }
+        let numerator = i32::try_from(value.numerator)
+            .map_err(|_| ConversionError::OutOfRange(value.into()))?;
+        Ok(Frac::new(numerator, denominator.trailing_zeros() as i32))
+    }
+}
+
+impl TryFrom<Frac> for LimitedFloat {
+    type Error = ConversionError;
+
+    fn try_from(value: Frac) -> Result<Self, Self::Error> {
+        Rational::from(value).try_into()
+    }
+}
+
+impl TryFrom<&LimitedFloat> for Frac {
+    type Error = ConversionError;
+
+    fn try_from(value: &LimitedFloat) -> Result<Self, Self::Error> {
+        Rational::from(value).try_into()
+    }
+}
+
+impl From<Rational> for f64 {
+    fn from(value: Rational) -> Self {
+        value.numerator as f64 / value.denominator as f64
+    }
+}
+
+impl Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl FromStr for Rational {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = s.split_once('/').unwrap_or((s, "1"));
+        let numerator = numerator
+            .trim()
+            .parse::<i128>()
+            .map_err(|e| format!("invalid numerator in `{s}`: {e}"))?;
+        let denominator = denominator
+            .trim()
+            .parse::<i128>()
+            .map_err(|e| format!("invalid denominator in `{s}`: {e}"))?;
+        if denominator == 0 {
+            return Err(format!("zero denominator in `{s}`"));
+        }
+        Ok(Self::new(numerator, denominator))
+    }
+}
+
+impl From<Rational> for String {
+    fn from(value: Rational) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for Rational {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// How a value is rounded when it cannot be represented exactly as a `LimitedFloat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Round to the nearest representable value, this is what `LimitedFloat::from` does.
+    #[default]
+    Nearest,
+    /// Round towards negative infinity.
+    Floor,
+    /// Round towards positive infinity.
+    Ceil,
+    /// Fail with `ConversionError::Inexact` instead of rounding.
+    Exact,
+}
+
 impl LimitedFloat {
     pub fn valid(&self) -> bool {
         self.wrapped >= 0 && self.wrapped as f64 <= 1.0f64 / Self::EPSILON
     }
 
+    pub fn is_zero(&self) -> bool {
+        self.wrapped == 0
+    }
+
+    /// Division which is `None` if the divisor is zero or the quotient is out of range, where `/`
+    /// saturates instead.
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+        Self::from_f64_rounded(f64::from(self) / f64::from(rhs), RoundingMode::Nearest).ok()
+    }
+
     pub const EPSILON: f64 = 0.0001;
+
+    /// Distance to the other value, in steps of `EPSILON`.
+    pub fn delta(&self, other: &Self) -> u64 {
+        self.wrapped.abs_diff(other.wrapped)
+    }
+
+    /// Relative distance (in units of `EPSILON`) under which a value is considered to be exactly
+    /// representable. This absorbs the representation error of `f64` values such as `0.29`.
+    const EXACTNESS_TOLERANCE: f64 = 1e-6;
+
+    /// Saturating conversion for the arithmetic of the search: NaN becomes zero and infinities
+    /// become the bounds of `i64`, rather than failing the search.
+    pub fn saturating_from_f64(value: f64) -> Self {
+        Self {
+            wrapped: (value / Self::EPSILON).round() as i64,
+        }
+    }
+
+    /// Converts an `f64` into a `LimitedFloat` with the given rounding mode.
+    pub fn from_f64_rounded(value: f64, mode: RoundingMode) -> Result<Self, ConversionError> {
+        let value = validate_f64(value)?;
+        let scaled = value / Self::EPSILON;
+        if scaled.abs() >= i64::MAX as f64 {
+            return Err(ConversionError::OutOfRange(value));
+        }
+        let nearest = scaled.round();
+        let wrapped = if (scaled - nearest).abs() <= Self::EXACTNESS_TOLERANCE {
+            nearest
+        } else {
+            match mode {
+                RoundingMode::Nearest => nearest,
+                RoundingMode::Floor => scaled.floor(),
+                RoundingMode::Ceil => scaled.ceil(),
+                RoundingMode::Exact => return Err(ConversionError::Inexact(value, Self::EPSILON)),
+            }
+        };
+        Ok(Self {
+            wrapped: wrapped as i64,
+        })
+    }
+}
+
+/// Rejects `f64` values which cannot be a meaningful quantity: NaN, infinities and subnormals.
+pub fn validate_f64(value: f64) -> Result<f64, ConversionError> {
+    if !value.is_finite() {
+        Err(ConversionError::NotFinite(value))
+    } else if value.is_subnormal() {
+        Err(ConversionError::Subnormal(value))
+    } else {
+        Ok(value)
+    }
 }
 
 impl Sub for LimitedFloat {
@@ -115,7 +446,7 @@ impl Div for LimitedFloat {
         let rhs_val: f64 = rhs.into();
 
         let res = self_val / rhs_val;
-        LimitedFloat::from(res)
+        LimitedFloat::saturating_from_f64(res)
     }
 }
 
@@ -127,7 +458,7 @@ impl Mul for LimitedFloat {
         let rhs_val: f64 = rhs.into();
 
         let res = self_val * rhs_val;
-        LimitedFloat::from(res)
+        LimitedFloat::saturating_from_f64(res)
     }
 }
 
@@ -139,24 +470,28 @@ impl From<LimitedFloat> for f64 {
     }
 }
 
+/// Converts to the nearest value, like `LimitedFloat::from_f64_rounded` with `RoundingMode::Nearest`.
+///
+/// # Panics
+///
+/// Panics if the value is NaN, infinite, subnormal or out of range, like `Duration::from_secs_f64`
+/// does. Values which may be one of them, i.e values coming from users, should go through
+/// `LimitedFloat::from_f64_rounded` instead, which rejects them with an error.
 impl From<f64> for LimitedFloat {
     fn from(value: f64) -> Self {
-        Self {
-            wrapped: (value / Self::EPSILON).round() as i64,
-        }
+        Self::from_f64_rounded(value, RoundingMode::Nearest)
+            .unwrap_or_else(|err| panic!("invalid `LimitedFloat`: {err}"))
     }
 }
 
 impl FromStr for LimitedFloat {
-    type Err = ParseFloatError;
+    type Err = ConversionError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let f64_val = s.parse::<f64>()?;
-        let epsilon_corrected = (f64_val / Self::EPSILON).round() as i64;
-
-        Ok(Self {
-            wrapped: epsilon_corrected,
-        })
+        let f64_val = s
+            .parse::<f64>()
+            .map_err(|_| ConversionError::Unparsable(s.to_string()))?;
+        Self::from_f64_rounded(f64_val, RoundingMode::Nearest)
     }
 }
 
@@ -178,7 +513,11 @@ impl std::fmt::Display for LimitedFloat {
 mod tests {
     use serde_test::{assert_tokens, Token};
 
-    use crate::number::Frac;
+    use crate::{
+        error::ConversionError,
+        input_space::InputSpace,
+        number::{Frac, Rational, RoundingMode},
+    };
 
     use super::LimitedFloat;
 
@@ -203,6 +542,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_lf_rounding_modes() {
+        let round = |value: f64, mode: RoundingMode| {
+            LimitedFloat::from_f64_rounded(value, mode).map(|lf| lf.wrapped)
+        };
+        assert_eq!(round(0.12346, RoundingMode::Nearest), Ok(1235));
+        assert_eq!(round(0.12346, RoundingMode::Floor), Ok(1234));
+        assert_eq!(round(0.12344, RoundingMode::Ceil), Ok(1235));
+        assert_eq!(
+            round(0.12346, RoundingMode::Exact),
+            Err(ConversionError::Inexact(0.12346, LimitedFloat::EPSILON))
+        );
+        // Representation error of `f64` is not treated as inexact.
+        assert_eq!(round(0.29, RoundingMode::Floor), Ok(2900));
+        assert_eq!(round(0.29, RoundingMode::Exact), Ok(2900));
+        assert_eq!(
+            round(f64::NAN, RoundingMode::Nearest).map_err(|e| e.to_string()),
+            Err("`NaN` is not a finite number.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lf_rejects_non_finite_and_subnormal() {
+        let subnormal = f64::MIN_POSITIVE / 2.0;
+        assert_eq!(
+            LimitedFloat::from_f64_rounded(f64::INFINITY, RoundingMode::Nearest),
+            Err(ConversionError::NotFinite(f64::INFINITY))
+        );
+        assert_eq!(
+            LimitedFloat::from_f64_rounded(subnormal, RoundingMode::Nearest),
+            Err(ConversionError::Subnormal(subnormal))
+        );
+        assert_eq!(
+            LimitedFloat::from_f64_rounded(1e300, RoundingMode::Nearest),
+            Err(ConversionError::OutOfRange(1e300))
+        );
+        assert!(matches!(
+            "NaN".parse::<LimitedFloat>(),
+            Err(ConversionError::NotFinite(_))
+        ));
+        assert!(matches!(
+            "-inf".parse::<LimitedFloat>(),
+            Err(ConversionError::NotFinite(_))
+        ));
+        assert_eq!(
+            "0.1x".parse::<LimitedFloat>(),
+            Err(ConversionError::Unparsable("0.1x".to_string()))
+        );
+        assert_eq!("0.25".parse::<LimitedFloat>().unwrap().wrapped, 2500);
+    }
+
+    #[test]
+    #[should_panic(expected = "`NaN` is not a finite number.")]
+    fn test_lf_from_nan_panics() {
+        let _ = InputSpace::from_concentrations([0.4.into(), f64::NAN.into()]);
+    }
+
+    #[test]
+    fn test_lf_saturating_from_f64() {
+        assert_eq!(LimitedFloat::saturating_from_f64(f64::NAN).wrapped, 0);
+        assert_eq!(
+            LimitedFloat::saturating_from_f64(f64::INFINITY).wrapped,
+            i64::MAX
+        );
+        assert_eq!(LimitedFloat::saturating_from_f64(0.25).wrapped, 2500);
+        assert_eq!(LimitedFloat::from(0.25).wrapped, 2500);
+    }
+
+    #[test]
+    fn test_lf_frac_conversions() {
+        let lf = LimitedFloat::from(0.375);
+        let frac = Frac::try_from(&lf).unwrap();
+        assert_eq!(frac, Frac::new(3, 3));
+        assert_eq!(LimitedFloat::try_from(frac), Ok(lf));
+
+        // 1/3 is not a multiple of `EPSILON`, 0.1 is not dyadic.
+        assert!(matches!(
+            LimitedFloat::try_from(Rational::new(1, 3)),
+            Err(ConversionError::Inexact(..))
+        ));
+        assert!(matches!(
+            Frac::try_from(&LimitedFloat::from(0.1)),
+            Err(ConversionError::NotDyadic(_))
+        ));
+        // 1/2^5 has more digits than `EPSILON` allows.
+        assert!(LimitedFloat::try_from(Frac::new(1, 5)).is_err());
+    }
+
     #[test]
     fn test_lf_valid() {
         let num_a = 0.5;
@@ -263,8 +690,10 @@ mod tests {
         let num_b: LimitedFloat = 2.0f64.into();
 
         let expected: LimitedFloat = 0.5f64.into();
-        let diff = num_a / num_b;
-        assert_eq!(diff, expected)
+        let diff = num_a.clone() / num_b.clone();
+        assert_eq!(diff, expected);
+        assert_eq!(num_a.clone().checked_div(num_b), Some(expected));
+        assert_eq!(num_a.checked_div(0.0f64.into()), None);
     }
 
     #[test]
@@ -356,4 +785,48 @@ mod tests {
             ],
         );
     }
+
+    #[test]
+    fn test_rational_reduced() {
+        let a = Rational::new(2, -4);
+        assert_eq!(a.numerator(), -1);
+        assert_eq!(a.denominator(), 2);
+        assert_eq!(a, Rational::new(-1, 2));
+    }
+
+    #[test]
+    fn test_rational_arithmetic() {
+        let a = Rational::new(1, 3);
+        let b = Rational::new(1, 6);
+        assert_eq!(a + b, Rational::new(1, 2));
+        assert_eq!(a - b, Rational::new(1, 6));
+        assert_eq!(a * b, Rational::new(1, 18));
+        assert_eq!(a / b, Rational::new(2, 1));
+
+        let huge = Rational::new(i128::MAX / 2 + 1, 1);
+        assert_eq!(huge.checked_add(huge), None);
+        assert_eq!(huge.checked_mul(Rational::new(3, 1)), None);
+        assert_eq!(a.checked_div(Rational::new(0, 1)), None);
+        // Common factors are cancelled before multiplying.
+        let small = Rational::new(1, i128::MAX / 2 + 1);
+        assert_eq!(huge.checked_mul(small), Some(Rational::new(1, 1)));
+        assert_eq!(small.checked_sub(small), Some(Rational::new(0, 1)));
+    }
+
+    #[test]
+    fn test_rational_from_frac_and_lf() {
+        assert_eq!(Rational::from(Frac::new(3, 3)), Rational::new(3, 8));
+        assert_eq!(Rational::from(Frac::new(1, -1)), Rational::new(2, 1));
+        let lf: LimitedFloat = 0.04f64.into();
+        assert_eq!(Rational::from(&lf), Rational::new(1, 25));
+    }
+
+    #[test]
+    fn test_rational_str_round_trip() {
+        let a = Rational::new(3, 8);
+        assert_eq!(a.to_string(), "3/8");
+        assert_eq!("3/8".parse::<Rational>().unwrap(), a);
+        assert_eq!("2".parse::<Rational>().unwrap(), Rational::new(2, 1));
+        assert!("1/0".parse::<Rational>().is_err());
+    }
 }
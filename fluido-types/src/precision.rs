@@ -0,0 +1,182 @@
+//! Precision numbers are displayed with in reports.
+//!
+//! Only the presentation is affected: values are computed, and machine-readable fields like the
+//! certificate are written, at full precision regardless of it.
+use crate::{
+    fluid::{Fluid, LimitedFloat},
+    number::Rational,
+};
+
+/// Significant figures of `Precision::Readable`, fewer than a `f64` holds so that the error of
+/// floating point arithmetic in its last digits is rounded away.
+pub const READABLE_SIGNIFICANT_FIGURES: usize = 12;
+
+/// How many digits of a number are displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Every digit, as the number is displayed by default.
+    Full,
+    /// Up to `READABLE_SIGNIFICANT_FIGURES` significant figures without trailing zeros, i.e
+    /// `0.1 + 0.2` is displayed as `0.3` rather than `0.30000000000000004`. Exact numbers are
+    /// displayed in full.
+    #[default]
+    Readable,
+    /// Rounded to this many digits after the decimal point.
+    DecimalPlaces(usize),
+    /// Rounded to this many significant figures, at least one.
+    SignificantFigures(usize),
+}
+
+impl Precision {
+    /// Position of the last displayed digit relative to the decimal point, positive after it and
+    /// negative before it. `None` for every digit.
+    fn last_digit(&self, value: f64) -> Option<i32> {
+        match *self {
+            Precision::Full => None,
+            Precision::Readable => {
+                Precision::SignificantFigures(READABLE_SIGNIFICANT_FIGURES).last_digit(value)
+            }
+            Precision::DecimalPlaces(decimals) => Some(decimals as i32),
+            Precision::SignificantFigures(figures) => {
+                let figures = figures.max(1);
+                // The exponent of the rounded value, which `log10` of the value itself is off by
+                // one from for values like `0.0999` or `9.99`.
+                let magnitude = match format!("{value:.0$e}", figures - 1).split_once('e') {
+                    Some((_, exponent)) if value.is_finite() => exponent.parse().unwrap_or(0),
+                    _ => 0,
+                };
+                Some(figures as i32 - 1 - magnitude)
+            }
+        }
+    }
+
+    /// Renders the value with this precision, trailing zeros included unless it is readable.
+    pub fn format(&self, value: f64) -> String {
+        if *self == Precision::Readable && value.is_finite() {
+            let formatted =
+                Precision::SignificantFigures(READABLE_SIGNIFICANT_FIGURES).format(value);
+            return match formatted.contains('.') {
+                true => formatted
+                    .trim_end_matches('0')
+                    .trim_end_matches('.')
+                    .to_string(),
+                false => formatted,
+            };
+        }
+        match self.last_digit(value) {
+            Some(decimals) if decimals >= 0 => format!("{value:.0$}", decimals as usize),
+            // Significant figures of a number larger than they can express.
+            Some(last_digit) => {
+                let scale = 10f64.powi(-last_digit);
+                format!("{:.0}", (value / scale).round() * scale)
+            }
+            None => value.to_string(),
+        }
+    }
+
+    /// Rounds the value to this precision, for outputs which keep numbers as numbers.
+    pub fn round(&self, value: f64) -> f64 {
+        match self.last_digit(value) {
+            Some(_) if value.is_finite() => {
+                // Formatting rounds correctly where scaling by a power of ten would not.
+                self.format(value).parse().unwrap_or(value)
+            }
+            _ => value,
+        }
+    }
+
+    /// Renders the limited float with this precision, as it is displayed by default if the
+    /// precision is full.
+    pub fn format_limited(&self, value: &LimitedFloat) -> String {
+        match self {
+            Precision::Full | Precision::Readable => value.to_string(),
+            _ => self.format(value.clone().into()),
+        }
+    }
+
+    /// Renders the exact number with this precision, as a fraction if the precision is full.
+    pub fn format_rational(&self, value: &Rational) -> String {
+        match self {
+            Precision::Full | Precision::Readable => value.to_string(),
+            _ => self.format((*value).into()),
+        }
+    }
+
+    /// Renders the fluid as `(fluid <concentration> <volume>)` with this precision, with the
+    /// concentrations in brackets for a fluid of several reagents.
+    pub fn format_fluid(&self, fluid: &Fluid) -> String {
+        let concentration = match fluid.reagent_count() {
+            1 => self.format_limited(fluid.concentration()),
+            _ => {
+                let concentrations = fluid
+                    .concentrations()
+                    .iter()
+                    .map(|concentration| self.format_limited(concentration))
+                    .collect::<Vec<_>>();
+                format!("[{}]", concentrations.join(" "))
+            }
+        };
+        format!(
+            "(fluid {} {})",
+            concentration,
+            self.format_limited(fluid.unit_volume())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Precision;
+    use crate::{fluid::Fluid, number::Rational};
+
+    #[test]
+    fn format_with_precision() {
+        assert_eq!(Precision::Full.format(0.123456), "0.123456");
+        assert_eq!(Precision::DecimalPlaces(3).format(0.123456), "0.123");
+        assert_eq!(Precision::DecimalPlaces(2).format(1000.9999), "1001.00");
+        assert_eq!(Precision::DecimalPlaces(0).format(2.5), "2");
+        assert_eq!(Precision::SignificantFigures(2).format(0.0012345), "0.0012");
+        assert_eq!(Precision::SignificantFigures(3).format(1234.5), "1230");
+        assert_eq!(Precision::SignificantFigures(3).format(0.0), "0.00");
+        assert_eq!(Precision::SignificantFigures(0).format(0.26), "0.3");
+        assert_eq!(Precision::SignificantFigures(2).format(0.09999), "0.10");
+        assert_eq!(Precision::SignificantFigures(2).format(9.99), "10");
+        assert_eq!(Precision::Readable.format(0.1 + 0.2), "0.3");
+        assert_eq!(
+            Precision::Readable.format(60.064849359999975),
+            "60.06484936"
+        );
+        assert_eq!(Precision::Readable.format(1.0), "1");
+        assert_eq!(Precision::Readable.format(-0.000125), "-0.000125");
+        assert_eq!(Precision::Readable.format(2.5e15), "2500000000000000");
+        assert_eq!(Precision::Readable.format(f64::NAN), "NaN");
+
+        assert_eq!(
+            Precision::DecimalPlaces(2).round(1000.9999999999964),
+            1001.0
+        );
+        assert_eq!(Precision::SignificantFigures(1).round(5e-5), 5e-5);
+        assert_eq!(Precision::Readable.round(0.1 + 0.2), 0.3);
+        assert_eq!(
+            Precision::DecimalPlaces(2).round(f64::INFINITY),
+            f64::INFINITY
+        );
+
+        assert_eq!(
+            Precision::Full.format_rational(&Rational::new(3, 20)),
+            "3/20"
+        );
+        assert_eq!(
+            Precision::DecimalPlaces(2).format_rational(&Rational::new(1, 3)),
+            "0.33"
+        );
+
+        let fluid = Fluid::new(0.1234.into(), 1.0.into());
+        assert_eq!(Precision::Full.format_fluid(&fluid), fluid.to_string());
+        assert_eq!(Precision::Readable.format_fluid(&fluid), fluid.to_string());
+        assert_eq!(
+            Precision::DecimalPlaces(2).format_fluid(&fluid),
+            "(fluid 0.12 1.00)"
+        );
+    }
+}
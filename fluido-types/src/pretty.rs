@@ -0,0 +1,150 @@
+//! Multi-line printing of mixer expressions.
+//!
+//! Every operand of a mix is printed on its own line, indented one level deeper than the mix it
+//! belongs to, so operands of the same mix are aligned with each other:
+//!
+//! ```text
+//! (mix ; concentration 0.1, volume 4
+//!   (fluid 0.2 2.0)
+//!   (mix ; concentration 0, volume 2
+//!     (fluid 0.0 1.0)
+//!     (fluid 0.0 1.0)))
+//! ```
+use crate::{certificate::ExactFluid, expr::Expr, precision::Precision};
+
+/// Pretty-printer for `Expr`.
+#[derive(Debug, Clone)]
+pub struct PrettyPrinter {
+    /// Number of spaces per nesting level.
+    indent: usize,
+    /// Annotate each mix with the concentration and volume it produces.
+    annotate: bool,
+    /// Precision of the annotations. Leaves are printed as they are, so that the output still
+    /// parses into the same expression.
+    precision: Precision,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            annotate: false,
+            precision: Precision::Full,
+        }
+    }
+}
+
+impl PrettyPrinter {
+    pub fn new(indent: usize, annotate: bool) -> Self {
+        Self {
+            indent,
+            annotate,
+            precision: Precision::Full,
+        }
+    }
+
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn print(&self, expr: &Expr) -> String {
+        let mut lines = vec![];
+        self.print_expr(expr, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    /// Appends the lines of `expr` and returns the fluid it evaluates to, if it is a fluid.
+    fn print_expr(&self, expr: &Expr, depth: usize, lines: &mut Vec<String>) -> Option<ExactFluid> {
+        let pad = " ".repeat(depth * self.indent);
+        match expr {
+            Expr::Fluid(fluid) => {
+                lines.push(format!("{pad}{fluid}"));
+                Some(ExactFluid::from(fluid))
+            }
+            Expr::LimitedFloat(lf) => {
+                lines.push(format!("{pad}{lf}"));
+                None
+            }
+            Expr::Mix(lhs, rhs) => {
+                let header = lines.len();
+                lines.push(format!("{pad}(mix"));
+                let lhs = self.print_expr(lhs, depth + 1, lines);
+                let rhs = self.print_expr(rhs, depth + 1, lines);
+                // The last line always belongs to a leaf, so closing it can not end up in an
+                // annotation.
+                lines.last_mut().expect("operands are printed").push(')');
+
+                // Mixes which cannot be derived are left without an annotation, so the step of
+                // the error does not matter.
+                let result = lhs.zip(rhs).and_then(|(lhs, rhs)| lhs.mix(&rhs, 0).ok());
+                if let (true, Some(result)) = (self.annotate, result) {
+                    let concentration = self.precision.format(result.concentration.into());
+                    let volume = self.precision.format(result.volume.into());
+                    lines[header].push_str(&format!(
+                        " ; concentration {concentration}, volume {volume}"
+                    ));
+                }
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrettyPrinter;
+    use crate::{expr::Expr, fluid::Fluid, precision::Precision};
+    use std::str::FromStr;
+
+    fn fluid(fluid_str: &str) -> Box<Expr> {
+        Box::new(Expr::Fluid(Fluid::from_str(fluid_str).unwrap()))
+    }
+
+    fn nested_expr() -> Expr {
+        Expr::Mix(
+            fluid("(fluid 0.2 2.0)"),
+            Box::new(Expr::Mix(fluid("(fluid 0 1.0)"), fluid("(fluid 0 1.0)"))),
+        )
+    }
+
+    #[test]
+    fn indents_operands() {
+        let expected = "\
+(mix
+  (fluid 0.2 2.0)
+  (mix
+    (fluid 0.0 1.0)
+    (fluid 0.0 1.0)))";
+        assert_eq!(PrettyPrinter::default().print(&nested_expr()), expected);
+        assert_eq!(format!("{:#}", nested_expr()), expected);
+    }
+
+    #[test]
+    fn annotates_mixes() {
+        let expected = "\
+(mix ; concentration 0.1, volume 4
+    (fluid 0.2 2.0)
+    (mix ; concentration 0, volume 2
+        (fluid 0.0 1.0)
+        (fluid 0.0 1.0)))";
+        assert_eq!(PrettyPrinter::new(4, true).print(&nested_expr()), expected);
+
+        let expected = "\
+(mix ; concentration 0.10, volume 4.00
+  (fluid 0.2 2.0)
+  (mix ; concentration 0.00, volume 2.00
+    (fluid 0.0 1.0)
+    (fluid 0.0 1.0)))";
+        let printer = PrettyPrinter::new(2, true).with_precision(Precision::DecimalPlaces(2));
+        assert_eq!(printer.print(&nested_expr()), expected);
+    }
+
+    #[test]
+    fn single_line_display() {
+        assert_eq!(
+            nested_expr().to_string(),
+            "(mix (fluid 0.2 2.0) (mix (fluid 0.0 1.0) (fluid 0.0 1.0)))"
+        );
+    }
+}
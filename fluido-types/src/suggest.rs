@@ -0,0 +1,79 @@
+//! Suggestions for misspelled names, i.e of units, input tiers or inputs, for error messages.
+
+/// Candidate closest to the given name, if any is close enough to be what was meant. Names are
+/// compared ignoring case, a candidate starting with the name, i.e `mg/mL` for `mg`, is close
+/// enough, and otherwise at most a third of the characters of the name may be edited.
+pub fn closest_match<'a>(
+    given: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let given = given.trim().to_lowercase();
+    if given.is_empty() {
+        return None;
+    }
+    let max_distance = given.chars().count() / 3;
+    candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let lowercase = candidate.to_lowercase();
+            let distance = match lowercase.starts_with(&given) {
+                true => 0,
+                false => edit_distance(&given, &lowercase),
+            };
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// ` Did you mean `<suggestion>`?` to append to an error message, empty without a suggestion.
+pub fn did_you_mean(suggestion: &Option<String>) -> String {
+    suggestion
+        .as_ref()
+        .map(|suggestion| format!(" Did you mean `{suggestion}`?"))
+        .unwrap_or_default()
+}
+
+/// Number of characters to insert, remove or replace to turn one string into the other.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect::<Vec<_>>();
+    let mut previous = (0..=rhs.len()).collect::<Vec<_>>();
+    for (ix, lhs_char) in lhs.chars().enumerate() {
+        let mut current = vec![ix + 1];
+        for (jx, rhs_char) in rhs.iter().enumerate() {
+            let replace = previous[jx] + usize::from(lhs_char != *rhs_char);
+            current.push(replace.min(previous[jx + 1] + 1).min(current[jx] + 1));
+        }
+        previous = current;
+    }
+    previous[rhs.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest_match, did_you_mean, edit_distance};
+
+    #[test]
+    fn close_names_are_suggested() {
+        assert_eq!(edit_distance("scarse", "scarce"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+
+        let tiers = ["abundant", "limited", "scarce"];
+        assert_eq!(closest_match("scarse", tiers), Some("scarce"));
+        assert_eq!(closest_match("Limted", tiers), Some("limited"));
+        assert_eq!(closest_match("plenty", tiers), None);
+
+        let units = ["%", "M", "mg/mL"];
+        assert_eq!(closest_match("m", units), Some("M"));
+        assert_eq!(closest_match("mg", units), Some("mg/mL"));
+        // Replacing the only character of a name is not a suggestion.
+        assert_eq!(closest_match("x", units), None);
+        assert_eq!(closest_match("", units), None);
+
+        assert_eq!(
+            did_you_mean(&Some("stockA".to_string())),
+            " Did you mean `stockA`?"
+        );
+        assert_eq!(did_you_mean(&None), "");
+    }
+}
@@ -0,0 +1,292 @@
+//! Conversion of concentrations between the units they are commonly given in.
+//!
+//! The search itself is unitless, every concentration it sees must be in the same unit. Values
+//! given in different units are converted into a single working unit before searching.
+use crate::{error::UnitError, number::validate_f64, suggest::closest_match};
+use serde::Serialize;
+use std::{fmt::Display, str::FromStr};
+
+/// Unit of a concentration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum ConcentrationUnit {
+    /// Weight per volume percent, grams of reagent in 100 mL.
+    Percent,
+    /// Moles of reagent per liter.
+    Molar,
+    /// Milligrams of reagent per milliliter, same as grams per liter.
+    MilligramsPerMilliliter,
+}
+
+impl ConcentrationUnit {
+    /// Whether converting from or to this unit needs the molecular weight of the reagent.
+    pub fn needs_molecular_weight(&self) -> bool {
+        matches!(self, Self::Molar)
+    }
+}
+
+impl FromStr for ConcentrationUnit {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "%" => Ok(Self::Percent),
+            "M" => Ok(Self::Molar),
+            unit if unit.eq_ignore_ascii_case("mg/mL") => Ok(Self::MilligramsPerMilliliter),
+            unit => Err(unknown_unit(unit, None)),
+        }
+    }
+}
+
+/// Units as they are written.
+const UNITS: [&str; 3] = ["%", "M", "mg/mL"];
+
+/// Error for an unknown unit, with a hint at the unit meant. Molarities with a prefix are hinted
+/// with their value in `M` if the value is known, other units with the closest known one.
+fn unknown_unit(unit: &str, value: Option<f64>) -> UnitError {
+    let prefixed_molar = unit.strip_suffix('M').and_then(|prefix| match prefix {
+        "m" => Some(("millimolar", 1e3)),
+        "u" | "µ" | "μ" => Some(("micromolar", 1e6)),
+        "n" => Some(("nanomolar", 1e9)),
+        _ => None,
+    });
+    let hint = match (prefixed_molar, value) {
+        (Some((name, factor)), Some(value)) => Some(format!(
+            "Give {name} concentrations in `M`, i.e `{}M` for `{value}{unit}`.",
+            value / factor
+        )),
+        (Some((name, factor)), None) => Some(format!(
+            "Give {name} concentrations in `M`, divided by {factor}."
+        )),
+        (None, _) if unit.eq_ignore_ascii_case("mol/L") => {
+            Some("Write `mol/L` as `M`.".to_string())
+        }
+        (None, _) if unit.eq_ignore_ascii_case("g/L") => {
+            Some("`g/L` is the same as `mg/mL`.".to_string())
+        }
+        (None, _) => closest_match(unit, UNITS).map(|unit| format!("Did you mean `{unit}`?")),
+    };
+    UnitError::UnknownUnit {
+        unit: unit.to_string(),
+        hint,
+    }
+}
+
+impl Display for ConcentrationUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self {
+            Self::Percent => "%",
+            Self::Molar => "M",
+            Self::MilligramsPerMilliliter => "mg/mL",
+        };
+        write!(f, "{unit}")
+    }
+}
+
+/// Splits a concentration into its value and unit, i.e `0.5M`, `2 %` or `10mg/mL`. The unit is
+/// `None` for a plain number.
+pub fn parse_concentration(s: &str) -> Result<(f64, Option<ConcentrationUnit>), UnitError> {
+    let s = s.trim();
+    let unit_start = s
+        .rfind(|c: char| c.is_ascii_digit() || c == '.')
+        .map_or(0, |ix| ix + 1);
+    let (value, unit) = s.split_at(unit_start);
+    let value = value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| UnitError::InvalidValue(s.to_string()))?;
+    let value = validate_f64(value)?;
+    let unit = match unit.trim() {
+        "" => None,
+        unit => Some(unit.parse().map_err(|_| unknown_unit(unit, Some(value)))?),
+    };
+    Ok((value, unit))
+}
+
+/// The reagent concentrations refer to, parsed from `<name>` or `<name>=<molecular weight>`, i.e
+/// `NaCl=58.44`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reagent {
+    pub name: String,
+    /// Molecular weight in g/mol, needed to convert molarities.
+    pub molecular_weight: Option<f64>,
+}
+
+impl Reagent {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            molecular_weight: None,
+        }
+    }
+
+    pub fn with_molecular_weight(mut self, molecular_weight: f64) -> Result<Self, UnitError> {
+        if !molecular_weight.is_finite() || molecular_weight <= 0.0 {
+            return Err(UnitError::InvalidMolecularWeight(molecular_weight));
+        }
+        self.molecular_weight = Some(molecular_weight);
+        Ok(self)
+    }
+
+    /// Converts a concentration of the reagent from one unit to another.
+    pub fn convert(
+        &self,
+        value: f64,
+        from: ConcentrationUnit,
+        to: ConcentrationUnit,
+    ) -> Result<f64, UnitError> {
+        if from == to {
+            return Ok(value);
+        }
+        let milligrams_per_milliliter = value * self.milligrams_per_milliliter(from)?;
+        Ok(milligrams_per_milliliter / self.milligrams_per_milliliter(to)?)
+    }
+
+    /// How many mg/mL one unit of the given unit is.
+    fn milligrams_per_milliliter(&self, unit: ConcentrationUnit) -> Result<f64, UnitError> {
+        match unit {
+            ConcentrationUnit::Percent => Ok(10.0),
+            ConcentrationUnit::MilligramsPerMilliliter => Ok(1.0),
+            ConcentrationUnit::Molar => {
+                self.molecular_weight
+                    .ok_or_else(|| UnitError::MissingMolecularWeight {
+                        reagent: self.name.clone(),
+                        unit,
+                    })
+            }
+        }
+    }
+}
+
+impl Default for Reagent {
+    fn default() -> Self {
+        Self::new("reagent")
+    }
+}
+
+impl FromStr for Reagent {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, molecular_weight) = match s.split_once('=') {
+            Some((name, molecular_weight)) => (name, Some(molecular_weight)),
+            None => (s, None),
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(UnitError::MissingReagentName(s.to_string()));
+        }
+        let reagent = Self::new(name);
+        match molecular_weight {
+            Some(molecular_weight) => {
+                let molecular_weight = molecular_weight
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| UnitError::InvalidValue(molecular_weight.to_string()))?;
+                reagent.with_molecular_weight(molecular_weight)
+            }
+            None => Ok(reagent),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_concentration, ConcentrationUnit, Reagent};
+    use crate::error::UnitError;
+
+    #[test]
+    fn parse_concentrations_with_units() {
+        assert_eq!(parse_concentration("0.4"), Ok((0.4, None)));
+        assert_eq!(
+            parse_concentration("0.5M"),
+            Ok((0.5, Some(ConcentrationUnit::Molar)))
+        );
+        assert_eq!(
+            parse_concentration(" 2 %"),
+            Ok((2.0, Some(ConcentrationUnit::Percent)))
+        );
+        assert_eq!(
+            parse_concentration("10mg/ml"),
+            Ok((10.0, Some(ConcentrationUnit::MilligramsPerMilliliter)))
+        );
+        assert_eq!(
+            parse_concentration("1e-3M"),
+            Ok((0.001, Some(ConcentrationUnit::Molar)))
+        );
+        assert!(matches!(
+            parse_concentration("0.5mol"),
+            Err(UnitError::UnknownUnit { hint: None, .. })
+        ));
+        assert!(parse_concentration("M").is_err());
+        assert!(parse_concentration("infM").is_err());
+    }
+
+    #[test]
+    fn unknown_units_are_hinted() {
+        let hint = |s: &str| match parse_concentration(s) {
+            Err(UnitError::UnknownUnit { hint, .. }) => hint,
+            other => panic!("`{s}` parsed into {other:?}"),
+        };
+        assert_eq!(
+            hint("0.1mM").as_deref(),
+            Some("Give millimolar concentrations in `M`, i.e `0.0001M` for `0.1mM`.")
+        );
+        assert_eq!(hint("2m").as_deref(), Some("Did you mean `M`?"));
+        assert_eq!(hint("10mg").as_deref(), Some("Did you mean `mg/mL`?"));
+        assert_eq!(
+            hint("10g/L").as_deref(),
+            Some("`g/L` is the same as `mg/mL`.")
+        );
+        assert_eq!(
+            "uM".parse::<ConcentrationUnit>(),
+            Err(UnitError::UnknownUnit {
+                unit: "uM".to_string(),
+                hint: Some(
+                    "Give micromolar concentrations in `M`, divided by 1000000.".to_string()
+                )
+            })
+        );
+        assert_eq!(
+            hint("0.1mM").map(|_| parse_concentration("0.1mM").unwrap_err().to_string()),
+            Some(
+                "Unknown concentration unit `mM`, expected one of `%`, `M` or `mg/mL`. Give \
+                 millimolar concentrations in `M`, i.e `0.0001M` for `0.1mM`."
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn convert_between_units() {
+        let nacl = "NaCl=58.44".parse::<Reagent>().unwrap();
+        let molar = nacl
+            .convert(5.844, ConcentrationUnit::Percent, ConcentrationUnit::Molar)
+            .unwrap();
+        assert!((molar - 1.0).abs() < 1e-12);
+        assert_eq!(
+            nacl.convert(
+                0.5,
+                ConcentrationUnit::Percent,
+                ConcentrationUnit::MilligramsPerMilliliter
+            ),
+            Ok(5.0)
+        );
+
+        // Percent and mg/mL do not depend on the reagent.
+        let unknown = "buffer".parse::<Reagent>().unwrap();
+        assert_eq!(
+            unknown.convert(
+                20.0,
+                ConcentrationUnit::MilligramsPerMilliliter,
+                ConcentrationUnit::Percent
+            ),
+            Ok(2.0)
+        );
+        assert!(matches!(
+            unknown.convert(1.0, ConcentrationUnit::Molar, ConcentrationUnit::Percent),
+            Err(UnitError::MissingMolecularWeight { .. })
+        ));
+        assert!("NaCl=-1".parse::<Reagent>().is_err());
+        assert!("=58.44".parse::<Reagent>().is_err());
+    }
+}
@@ -0,0 +1,539 @@
+//! Endpoints of the server, translating JSON requests into searches and designs into JSON.
+//!
+//! `POST /search` takes a `SearchRequest` and `POST /analyze` an `AnalyzeRequest`, both answer
+//! with the JSON design, or with `{"error": ...}` and a non-success status. Searches asked for
+//! with `Accept: text/event-stream` are streamed as server-sent events instead:
+//!
+//! * `progress`, every second while the search is queued or running, with its elapsed seconds
+//!   and, once it saturates, the `SaturationProgress` of its last iteration under `saturation`.
+//! * `log`, the saturation report and the dumps of the search, as they are written.
+//! * `design` or `error`, the final event, with the same JSON as the plain response.
+//!
+//! A streamed search is cancelled once its client goes away. Searches exceeding the `Limits` of
+//! the server are rejected with 400.
+use crate::http::{write_response, EventStream, Request, RequestError, Status};
+use fluido_core::{
+    analyze_mixer_design,
+    pool::{SearchHandle, SearchJob, SearchPool, SearchResult},
+    Config, LogConfig, MixerDesign, MixerGenerationConfig, MixerGenerator, ProgressCallback,
+    SaturationProgress,
+};
+use fluido_parse::parser::Parse;
+use fluido_types::{
+    error::{FluidoError, IRGenerationError, MixerGenerationError, StorageAllocationError},
+    expr::Expr,
+    fluid::{Concentration, Fluid},
+    input_space::InputSpace,
+    log::LogSink,
+    number::RoundingMode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, BufReader, Write},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Interval of the `progress` events of a streamed search.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bounds of the requests the server accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Largest `time_limit` and `max_total_time` of a search in seconds, and the total time of
+    /// searches without a `max_total_time`.
+    pub max_time_limit: u64,
+    /// Largest number of inputs of a search.
+    pub max_inputs: usize,
+    /// Time a connection may take to send its request, or to receive a part of the response.
+    pub io_timeout: Duration,
+}
+
+/// Search for a target concentration from a set of inputs.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchRequest {
+    /// Target concentration.
+    target: f64,
+    /// Concentrations of the input fluids.
+    inputs: Vec<f64>,
+    /// Saturation time limit in seconds.
+    #[serde(default = "default_time_limit")]
+    time_limit: u64,
+    /// Cap of the total time of the search in seconds, counted from the moment it starts.
+    max_total_time: Option<f64>,
+    /// Number of independent mixers the design is scheduled on.
+    #[serde(default = "default_mixers")]
+    mixers: usize,
+    /// Largest number of distinct inputs the design may use.
+    max_inputs: Option<usize>,
+}
+
+/// Design written by hand, analyzed instead of searched.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyzeRequest {
+    /// Mixer expression of the design, i.e `(mix (fluid 0.2 1.0) (fluid 0.0 1.0))`.
+    expr: String,
+    /// Number of independent mixers the design is scheduled on.
+    #[serde(default = "default_mixers")]
+    mixers: usize,
+}
+
+fn default_time_limit() -> u64 {
+    10
+}
+
+fn default_mixers() -> usize {
+    1
+}
+
+/// Response of a search, the design along with the target it was searched for.
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    target: String,
+    #[serde(flatten)]
+    design: &'a MixerDesign,
+}
+
+/// Failure of a request, answered with the status and `{"error": message}`.
+#[derive(Debug)]
+struct ApiError {
+    status: Status,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl ToString) -> Self {
+        Self {
+            status: Status::BAD_REQUEST,
+            message: message.to_string(),
+        }
+    }
+
+    fn body(&self) -> String {
+        serde_json::json!({ "error": self.message }).to_string()
+    }
+}
+
+impl From<RequestError> for ApiError {
+    fn from(err: RequestError) -> Self {
+        Self {
+            status: err.status,
+            message: err.message,
+        }
+    }
+}
+
+impl From<FluidoError> for ApiError {
+    fn from(err: FluidoError) -> Self {
+        let status = match &err {
+            FluidoError::MixerGenerationError(err) => match err {
+                MixerGenerationError::FailedToParseTarget(_)
+                | MixerGenerationError::DuplicateRuleName(_)
+                | MixerGenerationError::TooManyInputs(..)
+                | MixerGenerationError::ExpansionDepthTooLarge(..) => Status::BAD_REQUEST,
+                MixerGenerationError::TargetUnreachable(_)
+                | MixerGenerationError::TimeLimitReached(_)
+                | MixerGenerationError::NodeLimitReached(_)
+                | MixerGenerationError::IterationLimitReached(_)
+                | MixerGenerationError::RequiredIntermediatesUnreachable(_)
+                | MixerGenerationError::TargetNotExplored(_)
+                | MixerGenerationError::ReagentsUnreachable(_) => Status::UNPROCESSABLE_ENTITY,
+                MixerGenerationError::SaturationError(_)
+                | MixerGenerationError::CheckpointError(_)
+                | MixerGenerationError::Cancelled
+                | MixerGenerationError::Interrupted(_)
+                | MixerGenerationError::ExtractedExprError(_) => Status::INTERNAL_SERVER_ERROR,
+            },
+            FluidoError::DesignValidationError(_)
+            | FluidoError::StorageAllocationError(StorageAllocationError::ConflictingPins {
+                ..
+            }) => Status::UNPROCESSABLE_ENTITY,
+            FluidoError::InvalidInput(_)
+            | FluidoError::UnitError(_)
+            | FluidoError::ConfigError(_)
+            | FluidoError::IRGenerationError(IRGenerationError::ExprLimitExceeded(_))
+            | FluidoError::StorageAllocationError(StorageAllocationError::UnknownPinnedInput(_)) => {
+                Status::BAD_REQUEST
+            }
+            FluidoError::IRGenerationError(
+                IRGenerationError::ParseError(_) | IRGenerationError::ExtractedExprError(_),
+            )
+            | FluidoError::InterferenceGraphGenerationError(_)
+            | FluidoError::CertificateError(_)
+            | FluidoError::PipelineError(_)
+            | FluidoError::EvaluationError(_) => Status::INTERNAL_SERVER_ERROR,
+        };
+        Self {
+            status,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::bad_request(format!("invalid request body: {err}"))
+    }
+}
+
+/// Forwards the logs of a search to the connection streaming it, a line at a time since logs are
+/// written in pieces.
+struct LogForwarder {
+    sender: mpsc::Sender<String>,
+    line: Vec<u8>,
+}
+
+impl LogForwarder {
+    fn new(sender: mpsc::Sender<String>) -> Self {
+        Self {
+            sender,
+            line: vec![],
+        }
+    }
+
+    fn send(&mut self, line: Vec<u8>) {
+        // The connection may be gone already, its search is cancelled then.
+        let _ = self
+            .sender
+            .send(String::from_utf8_lossy(&line).into_owned());
+    }
+}
+
+impl Write for LogForwarder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            match byte {
+                b'\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    self.send(line);
+                }
+                _ => self.line.push(byte),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogForwarder {
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.send(line);
+        }
+    }
+}
+
+/// Reads the request of the connection and writes its response.
+pub fn handle_connection(stream: TcpStream, pool: &SearchPool, limits: &Limits) -> io::Result<()> {
+    stream.set_read_timeout(Some(limits.io_timeout))?;
+    stream.set_write_timeout(Some(limits.io_timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let request = match Request::read(&mut reader) {
+        Ok(request) => request,
+        Err(err) => return write_error(&mut writer, &err.into()),
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/search") => search(&request, writer, pool, limits),
+        ("POST", "/analyze") => match analyze(&request) {
+            Ok(body) => {
+                write_response(&mut writer, Status::OK, "application/json", body.as_bytes())
+            }
+            Err(err) => write_error(&mut writer, &err),
+        },
+        (_, "/search" | "/analyze") => write_error(
+            &mut writer,
+            &ApiError {
+                status: Status::METHOD_NOT_ALLOWED,
+                message: format!("{} only accepts POST", request.path),
+            },
+        ),
+        _ => write_error(
+            &mut writer,
+            &ApiError {
+                status: Status::NOT_FOUND,
+                message: format!("no endpoint at {}", request.path),
+            },
+        ),
+    }
+}
+
+fn write_error(writer: &mut impl Write, err: &ApiError) -> io::Result<()> {
+    write_response(
+        writer,
+        err.status,
+        "application/json",
+        err.body().as_bytes(),
+    )
+}
+
+/// Converts a requested concentration, rejecting values which would silently lose precision.
+fn exact_concentration(value: f64) -> Result<Concentration, ApiError> {
+    Concentration::from_f64_rounded(value, RoundingMode::Exact).map_err(ApiError::bad_request)
+}
+
+/// Rejects searches exceeding the limits of the server.
+fn check_limits(request: &SearchRequest, limits: &Limits) -> Result<(), ApiError> {
+    if request.time_limit > limits.max_time_limit {
+        return Err(ApiError::bad_request(format!(
+            "time limit `{}` exceeds the maximum of {} seconds",
+            request.time_limit, limits.max_time_limit
+        )));
+    }
+    if let Some(seconds) = request
+        .max_total_time
+        .filter(|seconds| *seconds > limits.max_time_limit as f64)
+    {
+        return Err(ApiError::bad_request(format!(
+            "max total time `{seconds}` exceeds the maximum of {} seconds",
+            limits.max_time_limit
+        )));
+    }
+    if request.inputs.len() > limits.max_inputs {
+        return Err(ApiError::bad_request(format!(
+            "{} inputs exceed the maximum of {}",
+            request.inputs.len(),
+            limits.max_inputs
+        )));
+    }
+    Ok(())
+}
+
+/// Job of the search request, logging into the sink and cancelled once the flag is set.
+fn search_job(
+    request: &SearchRequest,
+    limits: &Limits,
+    sink: LogSink,
+    cancellation: Arc<AtomicBool>,
+    progress: Arc<Mutex<Option<SaturationProgress>>>,
+    stream_logs: bool,
+) -> Result<SearchJob, ApiError> {
+    check_limits(request, limits)?;
+    let target = exact_concentration(request.target)?;
+    let input_space = request
+        .inputs
+        .iter()
+        .map(|&input| Ok(Fluid::new(exact_concentration(input)?, 1.0.into())))
+        .collect::<Result<InputSpace, ApiError>>()?;
+    let generation =
+        MixerGenerationConfig::new(request.time_limit, MixerGenerator::EqualitySaturation)
+            .with_cancellation(cancellation)
+            .with_progress(ProgressCallback::new(move |latest| {
+                if let Ok(mut progress) = progress.lock() {
+                    *progress = Some(latest.clone());
+                }
+            }));
+    let logging = LogConfig::new(stream_logs, false, false, false, false).with_sink(sink);
+    let mut config = Config::new(generation, logging).with_mixers(request.mixers);
+    if let Some(max_inputs) = request.max_inputs {
+        config = config.with_max_inputs(max_inputs);
+    }
+    let job = SearchJob::new(config, target, &input_space);
+    match request.max_total_time {
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .map(|budget| job.with_budget(budget))
+            .map_err(|_| ApiError::bad_request(format!("invalid max total time `{seconds}`"))),
+        None => Ok(job.with_budget(Duration::from_secs(limits.max_time_limit))),
+    }
+}
+
+/// JSON of the result of a search.
+fn search_response(request: &SearchRequest, result: SearchResult) -> Result<String, ApiError> {
+    let (design, _) = result?;
+    let response = SearchResponse {
+        target: request.target.to_string(),
+        design: &design,
+    };
+    Ok(serde_json::to_string(&response)?)
+}
+
+fn search(
+    request: &Request,
+    mut writer: TcpStream,
+    pool: &SearchPool,
+    limits: &Limits,
+) -> io::Result<()> {
+    let search_request = match serde_json::from_slice::<SearchRequest>(&request.body) {
+        Ok(search_request) => search_request,
+        Err(err) => return write_error(&mut writer, &err.into()),
+    };
+    let stream_events = request.accepts_event_stream();
+    let cancellation = Arc::new(AtomicBool::new(false));
+    let (log_sender, logs) = mpsc::channel();
+    let sink = match stream_events {
+        true => LogSink::to_writer(LogForwarder::new(log_sender)),
+        false => LogSink::to_writer(io::sink()),
+    };
+    let progress = Arc::new(Mutex::new(None));
+    let job = match search_job(
+        &search_request,
+        limits,
+        sink,
+        cancellation.clone(),
+        progress.clone(),
+        stream_events,
+    ) {
+        Ok(job) => job,
+        Err(err) => return write_error(&mut writer, &err),
+    };
+    let handle = pool.submit(job);
+
+    if !stream_events {
+        return match search_response(&search_request, handle.wait()) {
+            Ok(body) => {
+                write_response(&mut writer, Status::OK, "application/json", body.as_bytes())
+            }
+            Err(err) => write_error(&mut writer, &err),
+        };
+    }
+    let mut events = EventStream::start(writer)?;
+    let result = match stream_search(&mut events, handle, &logs, &progress) {
+        Ok(result) => result,
+        Err(err) => {
+            // Nobody is waiting for the design anymore.
+            cancellation.store(true, Ordering::SeqCst);
+            return Err(err);
+        }
+    };
+    match search_response(&search_request, result) {
+        Ok(body) => events.send("design", &body),
+        Err(err) => events.send("error", &err.body()),
+    }
+}
+
+/// Streams the progress and the logs of the search until it is finished. Fails once the client is
+/// gone.
+fn stream_search(
+    events: &mut EventStream<TcpStream>,
+    handle: SearchHandle,
+    logs: &mpsc::Receiver<String>,
+    saturation: &Mutex<Option<SaturationProgress>>,
+) -> io::Result<SearchResult> {
+    let started = Instant::now();
+    let mut last_progress = started;
+    loop {
+        match logs.recv_timeout(PROGRESS_INTERVAL) {
+            Ok(log) => events.send("log", &log)?,
+            // The search dropped its sink, so it is finished and every log is received.
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) if handle.is_finished() => break,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            let saturation = saturation.lock().ok().and_then(|progress| progress.clone());
+            let progress = serde_json::json!({
+                "elapsed_seconds": started.elapsed().as_secs(),
+                "saturation": saturation,
+            });
+            events.send("progress", &progress.to_string())?;
+            last_progress = Instant::now();
+        }
+    }
+    for log in logs.try_iter() {
+        events.send("log", &log)?;
+    }
+    Ok(handle.wait())
+}
+
+/// JSON of the analyzed design of the request.
+fn analyze(request: &Request) -> Result<String, ApiError> {
+    let analyze_request = serde_json::from_slice::<AnalyzeRequest>(&request.body)?;
+    let expr = Expr::parse(&analyze_request.expr).map_err(ApiError::bad_request)?;
+    let generation = MixerGenerationConfig::new(0, MixerGenerator::EqualitySaturation);
+    let logging = LogConfig::silent().with_sink(LogSink::to_writer(io::sink()));
+    let config = Config::new(generation, logging).with_mixers(analyze_request.mixers);
+    let design = analyze_mixer_design(config, expr)?;
+    Ok(serde_json::to_string(&design)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, check_limits, ApiError, Limits, SearchRequest};
+    use crate::http::{Request, Status};
+    use fluido_types::error::{FluidoError, MixerGenerationError};
+    use std::{io::BufReader, time::Duration};
+
+    fn post(path: &str, body: &str) -> Request {
+        let raw = format!(
+            "POST {path} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        Request::read(&mut BufReader::new(raw.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn analyze_answers_with_the_design() {
+        let body = analyze(&post(
+            "/analyze",
+            r#"{"expr": "(mix (mix (fluid 0.8 1.0) (fluid 0.0 1.0)) (fluid 0.0 2.0))"}"#,
+        ))
+        .unwrap();
+        let design: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(design["storage_units_needed"], 2);
+        let nodes = design["mix_tree"]["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 5);
+        let steps = nodes.iter().filter_map(|node| node["step"].as_u64());
+        assert_eq!(steps.collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(nodes[4]["fluid"]["concentration"], 0.2);
+        assert_eq!(nodes[4]["fluid"]["unit_volume"], 4.0);
+
+        let err = analyze(&post("/analyze", r#"{"expr": "(mix (fluid 0.8 1.0)"}"#)).unwrap_err();
+        assert_eq!(err.status, Status::BAD_REQUEST);
+        let err = analyze(&post("/analyze", r#"{"expression": "(fluid 0.8 1.0)"}"#)).unwrap_err();
+        assert_eq!(err.status, Status::BAD_REQUEST);
+    }
+
+    #[test]
+    fn search_failures_map_to_statuses() {
+        let status = |err: MixerGenerationError| ApiError::from(FluidoError::from(err)).status;
+        assert_eq!(
+            status(MixerGenerationError::TargetUnreachable(0.1.into())),
+            Status::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status(MixerGenerationError::FailedToParseTarget(0.1.into())),
+            Status::BAD_REQUEST
+        );
+        assert_eq!(
+            status(MixerGenerationError::Cancelled),
+            Status::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn searches_beyond_the_limits_are_rejected() {
+        let limits = Limits {
+            max_time_limit: 60,
+            max_inputs: 2,
+            io_timeout: Duration::from_secs(1),
+        };
+        let check = |body: &str| {
+            let request = serde_json::from_str::<SearchRequest>(body).unwrap();
+            check_limits(&request, &limits).map_err(|err| err.status)
+        };
+        assert!(check(r#"{"target": 0.1, "inputs": [0.2, 0.0], "time_limit": 60}"#).is_ok());
+        assert_eq!(
+            check(r#"{"target": 0.1, "inputs": [0.2, 0.0], "time_limit": 61}"#),
+            Err(Status::BAD_REQUEST)
+        );
+        assert_eq!(
+            check(r#"{"target": 0.1, "inputs": [0.2, 0.0], "max_total_time": 60.5}"#),
+            Err(Status::BAD_REQUEST)
+        );
+        assert_eq!(
+            check(r#"{"target": 0.1, "inputs": [0.2, 0.0, 0.4]}"#),
+            Err(Status::BAD_REQUEST)
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Just enough HTTP/1.1 for the api: a request with a `Content-Length` body per connection, and
+//! either a plain response or a stream of server-sent events. Connections are closed after each
+//! response.
+use std::io::{self, BufRead, Read, Write};
+
+/// Largest request body accepted, configs and designs are far smaller.
+const MAX_BODY_LEN: usize = 1 << 20;
+/// Largest number of header lines accepted.
+const MAX_HEADERS: usize = 64;
+
+/// Status line of a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status(pub u16, pub &'static str);
+
+impl Status {
+    pub const OK: Status = Status(200, "OK");
+    pub const BAD_REQUEST: Status = Status(400, "Bad Request");
+    pub const NOT_FOUND: Status = Status(404, "Not Found");
+    pub const METHOD_NOT_ALLOWED: Status = Status(405, "Method Not Allowed");
+    pub const PAYLOAD_TOO_LARGE: Status = Status(413, "Payload Too Large");
+    pub const UNPROCESSABLE_ENTITY: Status = Status(422, "Unprocessable Entity");
+    pub const INTERNAL_SERVER_ERROR: Status = Status(500, "Internal Server Error");
+    pub const SERVICE_UNAVAILABLE: Status = Status(503, "Service Unavailable");
+}
+
+/// Request which could not be read, answered with the status before closing the connection.
+#[derive(Debug)]
+pub struct RequestError {
+    pub status: Status,
+    pub message: String,
+}
+
+impl RequestError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            status: Status::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<io::Error> for RequestError {
+    fn from(err: io::Error) -> Self {
+        Self::bad_request(err.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads the request line, the headers and the body of the next request.
+    pub fn read(reader: &mut impl BufRead) -> Result<Self, RequestError> {
+        let request_line = read_line(reader)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(RequestError::bad_request(format!(
+                "malformed request line `{request_line}`"
+            )));
+        };
+        // Queries are not used by any endpoint.
+        let path = target.split('?').next().unwrap_or_default().to_string();
+        let method = method.to_string();
+
+        let mut headers = vec![];
+        loop {
+            let line = read_line(reader)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(RequestError::bad_request("too many headers"));
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(RequestError::bad_request(format!(
+                    "malformed header `{line}`"
+                )));
+            };
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        let mut request = Self {
+            method,
+            path,
+            headers,
+            body: vec![],
+        };
+        let body_len = match request.header("content-length") {
+            Some(len) => len.parse::<usize>().map_err(|_| {
+                RequestError::bad_request(format!("invalid content length `{len}`"))
+            })?,
+            None => 0,
+        };
+        if body_len > MAX_BODY_LEN {
+            return Err(RequestError {
+                status: Status::PAYLOAD_TOO_LARGE,
+                message: format!("bodies are limited to {MAX_BODY_LEN} bytes"),
+            });
+        }
+        request.body = vec![0; body_len];
+        reader.read_exact(&mut request.body)?;
+        Ok(request)
+    }
+
+    /// Value of the header, names are case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the client asks for server-sent events rather than a single response.
+    pub fn accepts_event_stream(&self) -> bool {
+        self.header("accept")
+            .is_some_and(|accept| accept.contains("text/event-stream"))
+    }
+}
+
+/// Reads a line without its line ending, failing if the connection is closed before it ends.
+fn read_line(reader: &mut impl BufRead) -> Result<String, RequestError> {
+    let mut line = String::new();
+    // Bounds the line, so that a client cannot make the server buffer without limit.
+    let read = reader.take(8 * 1024).read_line(&mut line)?;
+    if read == 0 || !line.ends_with('\n') {
+        return Err(RequestError::bad_request("incomplete request"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Writes a complete response.
+pub fn write_response(
+    writer: &mut impl Write,
+    status: Status,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let Status(code, reason) = status;
+    write!(
+        writer,
+        "HTTP/1.1 {code} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Response streaming server-sent events, each sent as soon as it is written.
+pub struct EventStream<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> EventStream<W> {
+    /// Writes the headers of the stream, events follow until the connection is closed.
+    pub fn start(mut writer: W) -> io::Result<Self> {
+        write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+             Connection: close\r\n\r\n"
+        )?;
+        writer.flush()?;
+        Ok(Self { writer })
+    }
+
+    /// Sends an event, each line of the data as a `data` field. Fails once the client is gone.
+    pub fn send(&mut self, event: &str, data: &str) -> io::Result<()> {
+        writeln!(self.writer, "event: {event}")?;
+        for line in data.split('\n') {
+            writeln!(self.writer, "data: {line}")?;
+        }
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventStream, Request, Status};
+    use std::io::BufReader;
+
+    #[test]
+    fn requests_are_read_up_to_their_body() {
+        let raw = "POST /search?verbose HTTP/1.1\r\nHost: lab\r\nACCEPT: text/event-stream\r\n\
+                   Content-Length: 4\r\n\r\n{}{}trailing";
+        let request = Request::read(&mut BufReader::new(raw.as_bytes())).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("POST", "/search")
+        );
+        assert_eq!(request.header("host"), Some("lab"));
+        assert!(request.accepts_event_stream());
+        assert_eq!(request.body, b"{}{}");
+
+        let raw = "POST /search HTTP/1.1\r\nContent-Length: 4\r\n\r\n{}";
+        let err = Request::read(&mut BufReader::new(raw.as_bytes())).unwrap_err();
+        assert_eq!(err.status, Status::BAD_REQUEST);
+        let raw = "POST /search HTTP/1.1\r\nContent-Length: 100000000\r\n\r\n";
+        let err = Request::read(&mut BufReader::new(raw.as_bytes())).unwrap_err();
+        assert_eq!(err.status, Status::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn multiline_event_data_is_split_into_fields() {
+        let mut stream = EventStream { writer: vec![] };
+        stream.send("log", "first\nsecond").unwrap();
+        assert_eq!(
+            String::from_utf8(stream.writer).unwrap(),
+            "event: log\ndata: first\ndata: second\n\n"
+        );
+    }
+}
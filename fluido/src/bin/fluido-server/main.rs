@@ -0,0 +1,97 @@
+//! HTTP service running searches, for labs preferring a long running service over invoking the
+//! CLI for each target. See `api` for the endpoints.
+//!
+//! ```console
+//! cargo run -p fluido --features server --bin fluido-server -- --workers 4
+//! ```
+mod api;
+mod http;
+
+use api::Limits;
+use clap::Parser;
+use fluido_core::pool::SearchPool;
+use http::{write_response, Status};
+use std::{
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Serves fluido searches over HTTP")]
+struct Args {
+    /// Address the server listens on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+    /// Number of searches run at the same time, the others are queued.
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+    /// Number of connections handled at the same time, as many more wait to be handled and the
+    /// others are answered with 503.
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u64).range(1..))]
+    max_connections: u64,
+    /// Seconds a connection may take to send its request, or to receive a part of the response.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+    io_timeout: u64,
+    /// Largest `time_limit` and `max_total_time` of a search in seconds. Searches without a
+    /// `max_total_time` are capped at it.
+    #[arg(long, default_value_t = 300)]
+    max_time_limit: u64,
+    /// Largest number of inputs of a search.
+    #[arg(long, default_value_t = 32)]
+    max_inputs: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let listener = TcpListener::bind(&args.address)?;
+    println!("listening on {}", listener.local_addr()?);
+    let pool = Arc::new(SearchPool::new(args.workers));
+    let limits = Limits {
+        max_time_limit: args.max_time_limit,
+        max_inputs: args.max_inputs,
+        io_timeout: Duration::from_secs(args.io_timeout),
+    };
+    let max_connections = args.max_connections as usize;
+    // Connections only wait on their search, the pool bounds the searches running at once.
+    let (connections, queue) = mpsc::sync_channel::<TcpStream>(max_connections);
+    let queue = Arc::new(Mutex::new(queue));
+    for _ in 0..max_connections {
+        let (pool, queue) = (pool.clone(), queue.clone());
+        thread::spawn(move || loop {
+            let Ok(stream) = queue.lock().map(|queue| queue.recv()) else {
+                return;
+            };
+            let Ok(stream) = stream else {
+                return;
+            };
+            if let Err(err) = api::handle_connection(stream, &pool, &limits) {
+                eprintln!("connection error: {err}");
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("failed to accept a connection: {err}");
+                continue;
+            }
+        };
+        if let Err(TrySendError::Full(mut stream)) = connections.try_send(stream) {
+            let _ = stream.set_write_timeout(Some(limits.io_timeout));
+            let body = serde_json::json!({ "error": "too many connections" }).to_string();
+            let _ = write_response(
+                &mut stream,
+                Status::SERVICE_UNAVAILABLE,
+                "application/json",
+                body.as_bytes(),
+            );
+        }
+    }
+    Ok(())
+}
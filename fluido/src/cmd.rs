@@ -1,22 +1,251 @@
-use clap::Parser;
+use crate::target::{ConcentrationSpec, IncompatibleSpec, InputSpec, RelativeTarget};
+use clap::{Parser, Subcommand};
+use fluido_core::{
+    fallback::GenerationStage, simulation::ErrorDistribution, storage_pins::StoragePin,
+    ColorCountStrategy, ExhaustiveLimits, ExtractionRoot, InputTier, RegAllocStrategy,
+    MAX_EXPANSION_DEPTH,
+};
+use fluido_types::{
+    error::UnitError,
+    fluid::MixRatio,
+    number::validate_f64,
+    units::{ConcentrationUnit, Reagent},
+};
+use std::{path::PathBuf, str::FromStr};
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  a mixer design is found
+  1  internal error
+  2  invalid input
+  3  target is not reachable from the input space
+  4  time limit reached before a design is found
+  5  found design violates a configured constraint";
 
 /// Searching a mixer configuration from given input space and target concantration.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = EXIT_CODES_HELP,
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Target concentration
-    #[arg(long)]
-    pub target_concentration: f64,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Target concentration, optionally with a unit, i.e `0.1`, `0.1M`, `2%` or `10mg/mL`.
+    #[arg(
+        long,
+        required_unless_present_any = ["benchmark", "target"]
+    )]
+    pub target_concentration: Option<ConcentrationSpec>,
+
+    /// Target concentration relative to a named input, i.e `0.25x:stockA` for a quarter of the
+    /// concentration of `stockA`.
+    #[arg(
+        long,
+        value_name = "FACTORx:STOCK",
+        conflicts_with = "target_concentration"
+    )]
+    pub target: Option<RelativeTarget>,
 
-    /// Input space, intial concentrations at hand.
-    /// example_input: `--input-space 0 --input-space 0.4`
+    /// Input space, intial concentrations at hand. Inputs can be named for `--target`, and given
+    /// with a unit like the target concentration.
+    /// example_input: `--input-space 0 --input-space stockA=0.4`
     #[arg(long)]
-    pub input_space: Vec<f64>,
+    pub input_space: Vec<InputSpec>,
+
+    /// Unit concentrations are converted into before searching: `%`, `M` or `mg/mL`. Defaults to
+    /// the unit of the target concentration, or of the first input with a unit.
+    #[arg(long, value_parser = parse_unit)]
+    pub unit: Option<ConcentrationUnit>,
+
+    /// Reagent the concentrations refer to, with its molecular weight in g/mol for converting
+    /// molarities, i.e `NaCl=58.44`.
+    #[arg(long, value_name = "NAME[=MOLECULAR_WEIGHT]", value_parser = parse_reagent)]
+    pub reagent: Option<Reagent>,
+
+    /// Available volume of each input, in the same order as `--input-space`.
+    /// If given, designs drawing an input below its dead volume are rejected. In µL with
+    /// `--unit-volume`, in units of the smallest fluid of the design otherwise.
+    #[arg(long, value_parser = finite_f64)]
+    pub input_volume: Vec<f64>,
+
+    /// Dead volume of each input's reservoir, in the same order as `--input-space`.
+    /// This volume is added to the consumption of the input. In µL with `--unit-volume`, in
+    /// units of the smallest fluid of the design otherwise.
+    #[arg(long, value_parser = finite_f64)]
+    pub dead_volume: Vec<f64>,
+
+    /// Volume of the smallest fluid of the design in µL. The consumption of the inputs is
+    /// converted into µL with it, and `--input-volume` and `--dead-volume` are taken in µL.
+    #[arg(long, value_name = "MICROLITERS", value_parser = finite_f64)]
+    pub unit_volume: Option<f64>,
+
+    /// How much of each input is at hand, in the same order as `--input-space`: `abundant`,
+    /// `limited` or `scarce`. Designs shift their consumption towards abundant inputs.
+    #[arg(long, value_name = "TIER")]
+    pub input_tier: Vec<InputTier>,
+
+    /// Uncertainty of each input's concentration, in the same order as `--input-space`. Used as
+    /// the perturbation of the input in the sensitivity report.
+    #[arg(long, value_name = "UNCERTAINTY", value_parser = finite_f64)]
+    pub input_uncertainty: Vec<f64>,
+
+    /// Number of mixes each input stays stable for, in the same order as `--input-space`, or
+    /// `none` for an input which does not degrade. Intermediates consumed later than that are
+    /// reported.
+    #[arg(long, value_name = "STEPS")]
+    pub max_steps_live: Vec<StepsLive>,
+
+    /// Concentration range no intermediate fluid of the design may have, i.e `0.4-0.5`. Can be
+    /// given multiple times.
+    #[arg(long, value_name = "LOW-HIGH")]
+    pub forbid_concentration: Vec<WindowSpec>,
+
+    /// Two named inputs which must not be mixed directly, i.e `acid,base`. With `acid,base@0.1`
+    /// they may meet once each makes up at most a tenth of the fluid carrying it. Can be given
+    /// multiple times.
+    #[arg(long, value_name = "NAME,NAME[@MAX_FRACTION]")]
+    pub incompatible: Vec<IncompatibleSpec>,
+
+    /// Largest volume an intermediate fluid of the design may have, in units of the smallest fluid
+    /// of the design.
+    #[arg(long, value_name = "VOLUME", value_parser = finite_f64)]
+    pub max_intermediate_volume: Option<f64>,
+
+    /// Volume ratio the mixer of the hardware can mix two fluids in, i.e `1:3`. Once given, designs
+    /// only mix in the given ratios. Can be given multiple times, give `1:1` as well if the mixer
+    /// supports equal volumes.
+    #[arg(long, value_name = "LHS:RHS")]
+    pub mix_ratio: Vec<MixRatio>,
+
+    /// Concentration the design must produce as an intermediate before the target, i.e `0.5` for a
+    /// protocol diluting a `0.5` stock first. Can be given multiple times.
+    #[arg(long, value_name = "CONCENTRATION", value_parser = finite_f64)]
+    pub require_intermediate: Vec<f64>,
+
+    /// Maximum number of distinct inputs a design may use. The most promising subsets of the
+    /// input space are searched, each within the time limit, and the cheapest design is reported.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_inputs_per_design: Option<u64>,
 
     /// Time limit in seconds.
-    #[arg(long)]
+    // The default is only taken by subcommands which do not search, i.e `doctor`, the others
+    // require it.
+    #[arg(long, required = true, default_value_t = 0, hide_default_value = true)]
     pub time_limit: u64,
 
+    /// Number of e-nodes the saturation stops at, the best design found until then is extracted.
+    #[arg(long, value_name = "NODES")]
+    pub node_limit: Option<usize>,
+
+    /// Number of iterations the saturation stops after.
+    #[arg(long, value_name = "ITERATIONS")]
+    pub iter_limit: Option<usize>,
+
+    /// Cap on the total run time in seconds, covering saturation, extraction and storage
+    /// allocation. Once reached, the best result so far is reported, i.e a storage unit count which
+    /// suffices along with a lower bound instead of the exact minimum.
+    #[arg(long, value_name = "SECONDS")]
+    pub max_total_seconds: Option<u64>,
+
+    /// Stops searching the minimum number of storage units once it is known within this many
+    /// units, i.e `1` may report that 4 storage units are needed and 5 suffice. The exact minimum
+    /// is searched by default.
+    #[arg(long, value_name = "UNITS", default_value_t = 0)]
+    pub storage_bound_gap: u64,
+
+    /// How the minimum number of storage units is searched: `binary-search`, checking one number
+    /// of units per solver call, or `optimize`, minimizing it with a single call of the z3
+    /// optimizer.
+    #[arg(long, value_name = "STRATEGY", default_value_t = ColorCountStrategy::BinarySearch)]
+    pub color_count_strategy: ColorCountStrategy,
+
+    /// How storage units are allocated: `exact`, the minimum number of units found with the z3
+    /// solver, or `heuristic`, a greedy or DSATUR coloring needing no solver along with a lower
+    /// bound of the minimum. Builds without z3 always allocate heuristically.
+    #[arg(long, value_name = "STRATEGY", default_value_t = RegAllocStrategy::Exact)]
+    pub reg_alloc_strategy: RegAllocStrategy,
+
+    /// Fixed time of each mix, in seconds. Enables reporting the estimated protocol time.
+    #[arg(long, value_name = "SECONDS", value_parser = finite_f64)]
+    pub mix_time_per_mix: Option<f64>,
+
+    /// Time per unit of volume mixed, in seconds. Enables reporting the estimated protocol time.
+    #[arg(long, value_name = "SECONDS", value_parser = finite_f64)]
+    pub mix_time_per_volume: Option<f64>,
+
+    /// Number of independent mixers on the chip, used for estimating the makespan of the design.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub mixers: u64,
+
+    /// Optimize the design for the total mixing time instead of the number of mixes.
+    #[arg(long)]
+    pub optimize_time: bool,
+
+    /// Optimize the design for the volume of the inputs it consumes beyond the one unit of the
+    /// target it produces, instead of the number of mixes.
+    #[arg(long, conflicts_with = "optimize_time")]
+    pub minimize_waste: bool,
+
+    /// Scale the penalty of a leaf of a concentration none of the inputs has by its volume, so
+    /// that designs needing less of a missing concentration are preferred.
+    #[arg(long)]
+    pub volume_weighted_proximity: bool,
+
+    /// Search with concentration steps of 0.1 first, then refine the design with steps of 0.01
+    /// and 0.001, sharing the time limit. Explores much smaller e-graphs than fine steps
+    /// everywhere.
+    #[arg(long, conflicts_with_all = ["checkpoint", "resume"])]
+    pub coarse_to_fine: bool,
+
+    /// Seed the e-graph with every concentration the inputs reach within this many mixes of equal
+    /// volumes, and only run the rules simplifying designs. Targets out of reach are not found.
+    /// At most `MAX_EXPANSION_DEPTH` (16) mixes.
+    #[arg(
+        long,
+        value_name = "MIXES",
+        conflicts_with = "coarse_to_fine",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_EXPANSION_DEPTH as u64)
+    )]
+    pub expansion_depth: Option<u64>,
+
+    /// Generate with a chain of stages instead of a single saturation, each stage running only if
+    /// the previous ones missed `--fallback-tolerance`. A stage is `direct-mix`, mixing the inputs
+    /// around the target once, `exhaustive`, enumerating every design up to
+    /// `--exhaustive-max-mixes` mixes, or `saturation`, optionally with its own concentration steps
+    /// and time limit in seconds.
+    /// example: `--fallback direct-mix --fallback saturation:0.1@2 --fallback saturation:0.01@10`
+    #[arg(long, value_name = "STAGE", conflicts_with_all = ["checkpoint", "resume"])]
+    pub fallback: Vec<GenerationStage>,
+
+    /// Largest distance to the target a design of a fallback stage may have to be accepted.
+    #[arg(long, value_name = "TOLERANCE", default_value_t = 0.0, value_parser = finite_f64, requires = "fallback")]
+    pub fallback_tolerance: f64,
+
+    /// Searches with at most this many distinct inputs enumerate every design instead of
+    /// saturating, proving the design with the fewest mixes optimal.
+    #[arg(long, value_name = "INPUTS", default_value_t = ExhaustiveLimits::default().max_inputs)]
+    pub exhaustive_max_inputs: usize,
+
+    /// Most mixes of the designs enumerated for small searches, targets needing more are
+    /// saturated. `0` always saturates.
+    #[arg(long, value_name = "MIXES", default_value_t = ExhaustiveLimits::default().max_mixes)]
+    pub exhaustive_max_mixes: usize,
+
+    /// Never extract arithmetic nodes, instead of only penalizing them. Guarantees that the
+    /// design only consists of mixes of fluids.
+    #[arg(long)]
+    pub constrained_extraction: bool,
+
+    /// E-classes the design is extracted from: `target`, the e-class of the target node, or
+    /// `best-fluid[:<tolerance>]`, the cheapest of it and every e-class whose fluid is within the
+    /// tolerance of the target.
+    #[arg(long, value_name = "ROOT", default_value_t = ExtractionRoot::Target)]
+    pub extraction_root: ExtractionRoot,
+
     /// Show dot output of the produced mixer graph
     #[arg(long)]
     pub show_dot: bool,
@@ -32,4 +261,299 @@ pub struct Args {
     /// Show interference graph for the produced flat-ir.
     #[arg(long)]
     pub show_interference: bool,
+
+    /// Show the flat ir as a linear protocol, annotated with the concentration and volume of each
+    /// fluid.
+    #[arg(long)]
+    pub show_linear: bool,
+
+    /// Dump the state of the pipeline after the given phase, can be repeated.
+    #[arg(long, value_name = "PHASE")]
+    pub print_after: Vec<String>,
+
+    /// Dump the state of the pipeline after every phase.
+    #[arg(long, conflicts_with = "print_after")]
+    pub print_after_all: bool,
+
+    /// Show how much the final concentration changes when each leaf, and each input, is perturbed
+    /// by its uncertainty.
+    #[arg(long)]
+    pub show_sensitivity: bool,
+
+    /// Simulate the design this many times with errors sampled for every pipetting and mix, and
+    /// report the distribution of the concentration it produces.
+    #[arg(long, value_name = "SAMPLES", conflicts_with = "benchmark")]
+    pub simulate: Option<usize>,
+
+    /// Relative error of each volume drawn into a mix, for `--simulate`.
+    /// example: `--pipetting-error normal:0.02` or `--pipetting-error uniform:0.05`
+    #[arg(
+        long,
+        value_name = "DISTRIBUTION",
+        default_value = "none",
+        requires = "simulate"
+    )]
+    pub pipetting_error: ErrorDistribution,
+
+    /// Relative error of the concentration each mix produces, for `--simulate`.
+    #[arg(
+        long,
+        value_name = "DISTRIBUTION",
+        default_value = "none",
+        requires = "simulate"
+    )]
+    pub mixing_error: ErrorDistribution,
+
+    /// Seed of the errors sampled by `--simulate`.
+    #[arg(long, default_value_t = 0, requires = "simulate")]
+    pub simulation_seed: u64,
+
+    /// Print the found mixer expression on multiple lines, indented by nesting level.
+    #[arg(long)]
+    pub pretty: bool,
+
+    /// Annotate each mix of the `--pretty` output with the concentration and volume it produces.
+    #[arg(long, requires = "pretty")]
+    pub annotate: bool,
+
+    /// Display numbers in reports rounded to this many decimal places. Mixer expressions and the
+    /// certificate are kept at full precision.
+    #[arg(long, value_name = "N", conflicts_with = "significant_figures")]
+    pub decimals: Option<usize>,
+
+    /// Display numbers in reports rounded to this many significant figures. Mixer expressions and
+    /// the certificate are kept at full precision.
+    #[arg(long, value_name = "N")]
+    pub significant_figures: Option<usize>,
+
+    /// Print the found mixer design as JSON to stdout. Human readable output and logs are printed
+    /// to stderr instead, so that stdout holds only the JSON document.
+    #[arg(long)]
+    pub json: bool,
+
+    /// Suppress human readable output. Errors are still reported to stderr.
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Periodically write the search state to this file, so that an interrupted search can be
+    /// resumed with `--resume`. A final checkpoint is also written on Ctrl-C.
+    #[arg(long, value_name = "PATH")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between two consecutive checkpoints.
+    #[arg(long, default_value_t = 60, requires = "checkpoint")]
+    pub checkpoint_interval: u64,
+
+    /// Resume the search from a checkpoint written by a previous run.
+    #[arg(long, value_name = "CHECKPOINT")]
+    pub resume: Option<PathBuf>,
+
+    /// Write the outputs of the run into a new folder below this directory: the config, the
+    /// intermediate artifacts of the search (extracted expressions, mixer graph, flat ir,
+    /// liveness table, interference graph and storage allocation), its logs and the report of the
+    /// design. The e-class of the target is written too if `--dump-target-class` is given.
+    /// `LATEST` holds the name of the folder of the latest run.
+    #[arg(long, value_name = "DIR", conflicts_with = "benchmark")]
+    pub artifacts_dir: Option<PathBuf>,
+
+    /// Keep only this many of the most recent runs below `--artifacts-dir`, removing older ones.
+    #[arg(long, value_name = "N", requires = "artifacts_dir")]
+    pub keep_runs: Option<usize>,
+
+    /// Write the e-class of the target as dot to this file, with the cost of each member and of
+    /// the expressions the extractor could pick. Also part of `--artifacts-dir`.
+    #[arg(long, value_name = "PATH", conflicts_with = "benchmark")]
+    pub dump_target_class: Option<PathBuf>,
+
+    /// E-classes reachable from the target e-class through at most this many e-nodes are part of
+    /// `--dump-target-class`.
+    #[arg(long, value_name = "DEPTH", default_value_t = 1)]
+    pub target_class_depth: usize,
+
+    /// Number of members of the target e-class listed with their cheapest expression in
+    /// `--dump-target-class`.
+    #[arg(long, value_name = "K", default_value_t = 5)]
+    pub target_class_top_k: usize,
+
+    /// Write the storage unit of each stored fluid of the design as JSON to this file, to be given
+    /// to a later run with `--allocation-hint`.
+    #[arg(long, value_name = "PATH", conflicts_with = "benchmark")]
+    pub emit_allocation: Option<PathBuf>,
+
+    /// Keep the storage units of a previous run, written with `--emit-allocation`, for the fluids
+    /// the design still stores. The number of storage units is not affected.
+    #[arg(long, value_name = "PATH")]
+    pub allocation_hint: Option<PathBuf>,
+
+    /// Keep every leaf drawing from an input in a fixed storage unit, i.e for inputs entering
+    /// through dedicated ports. The input is given by its name or concentration.
+    /// example: `--pin-input stockA=1 --pin-input 0=0`
+    #[arg(long, value_name = "INPUT=UNIT")]
+    pub pin_input: Vec<StoragePin>,
+
+    /// Write the design as a CSV plate map / transfer list to this file.
+    #[arg(long, value_name = "PATH")]
+    pub emit_plate_map: Option<PathBuf>,
+
+    /// Write the steps of the design, with the registers, storage units and fluids of each, as a
+    /// JSON protocol to this file.
+    #[arg(long, value_name = "PATH", conflicts_with = "benchmark")]
+    pub emit_protocol: Option<PathBuf>,
+
+    /// Number of rows of the plate used for `--emit-plate-map`.
+    #[arg(long, default_value_t = 8)]
+    pub plate_rows: usize,
+
+    /// Number of columns of the plate used for `--emit-plate-map`.
+    #[arg(long, default_value_t = 12)]
+    pub plate_columns: usize,
+
+    /// Write the design as an OpenDrop-style digital microfluidics protocol (JSON) to this file.
+    #[cfg(feature = "dmf")]
+    #[arg(long, value_name = "PATH", conflicts_with = "benchmark")]
+    pub emit_dmf: Option<PathBuf>,
+
+    /// Append the fingerprint, config, metrics and recipe hash of the run to this JSON lines
+    /// results database.
+    #[cfg(feature = "results-db")]
+    #[arg(long, value_name = "PATH", conflicts_with = "benchmark")]
+    pub results_db: Option<PathBuf>,
+
+    /// Instead of searching a single target, benchmark the search over this many randomly sampled
+    /// targets which are reachable from the input space, and print a statistical summary.
+    #[arg(
+        long,
+        value_name = "SAMPLES",
+        conflicts_with_all = ["target_concentration", "target", "checkpoint", "resume", "emit_plate_map"]
+    )]
+    pub benchmark: Option<usize>,
+
+    /// Seed used for sampling the benchmark targets.
+    #[arg(long, default_value_t = 0, requires = "benchmark")]
+    pub benchmark_seed: u64,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Plan producing a batch of targets one after another on the same chip. Reports the storage
+    /// units that suffice for the whole batch and the order of the targets minimizing peak storage.
+    /// Search options are given before the subcommand, i.e
+    /// `fluido --input-space 0 --input-space 0.4 --time-limit 5 plan 0.1 0.05`.
+    Plan {
+        /// Target concentrations of the batch.
+        #[arg(required = true, value_parser = finite_f64)]
+        targets: Vec<f64>,
+    },
+    /// Check the environment the search runs in: the linked z3 solver, the precision of numbers,
+    /// the rewrite rules and whether the output paths given before the subcommand are writable,
+    /// i.e `fluido --artifacts-dir out doctor`.
+    Doctor,
+}
+
+/// A closed concentration range, parsed from `<low>-<high>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowSpec {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl FromStr for WindowSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (low, high) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected `<low>-<high>`, found `{s}`"))?;
+        let bound = |bound: &str| {
+            finite_f64(bound).map_err(|e| format!("invalid bound `{bound}` in `{s}`: {e}"))
+        };
+        Ok(Self {
+            low: bound(low)?,
+            high: bound(high)?,
+        })
+    }
+}
+
+/// Number of mixes an input stays stable for, parsed from a number or `none`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepsLive(pub Option<usize>);
+
+impl FromStr for StepsLive {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "none" => Ok(Self(None)),
+            steps => steps
+                .parse()
+                .map(|steps| Self(Some(steps)))
+                .map_err(|_| format!("expected a number of mixes or `none`, found `{s}`")),
+        }
+    }
+}
+
+/// Parses an `f64` given on the command line, rejecting NaN, infinities and subnormals.
+pub fn finite_f64(s: &str) -> Result<f64, String> {
+    let value = s.trim().parse::<f64>().map_err(|e| e.to_string())?;
+    validate_f64(value).map_err(|e| e.to_string())
+}
+
+/// Parses a concentration unit given on the command line.
+fn parse_unit(s: &str) -> Result<ConcentrationUnit, String> {
+    s.parse().map_err(|e: UnitError| e.to_string())
+}
+
+/// Parses a reagent given on the command line.
+fn parse_reagent(s: &str) -> Result<Reagent, String> {
+    s.parse().map_err(|e: UnitError| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{finite_f64, Args, StepsLive, WindowSpec};
+    use clap::Parser;
+
+    #[test]
+    fn parse_window_spec() {
+        assert_eq!(
+            "0.4-0.5".parse::<WindowSpec>(),
+            Ok(WindowSpec {
+                low: 0.4,
+                high: 0.5
+            })
+        );
+        assert!("0.4".parse::<WindowSpec>().is_err());
+        assert!("0.4-x".parse::<WindowSpec>().is_err());
+        assert!("NaN-0.5".parse::<WindowSpec>().is_err());
+    }
+
+    #[test]
+    fn parse_steps_live() {
+        assert_eq!("3".parse::<StepsLive>(), Ok(StepsLive(Some(3))));
+        assert_eq!("none".parse::<StepsLive>(), Ok(StepsLive(None)));
+        assert!("-1".parse::<StepsLive>().is_err());
+    }
+
+    #[test]
+    fn non_finite_arguments_are_rejected() {
+        assert_eq!(finite_f64(" 0.5 "), Ok(0.5));
+        assert!(finite_f64("inf").is_err());
+        assert!(finite_f64("1e-310").is_err());
+
+        let parse = |extra: &[&str]| {
+            let base = ["fluido", "--input-space", "0", "--time-limit", "1"];
+            Args::try_parse_from(base.iter().chain(extra))
+        };
+        assert!(parse(&["--target-concentration", "0.1"]).is_ok());
+        assert!(parse(&["--target-concentration", "NaN"]).is_err());
+        assert!(parse(&["--target-concentration", "0.1", "--input-volume", "inf"]).is_err());
+        assert!(parse(&[
+            "--target-concentration",
+            "0.1",
+            "--mix-time-per-mix",
+            "-inf"
+        ])
+        .is_err());
+        assert!(parse(&["plan", "0.1", "nan"]).is_err());
+    }
 }
@@ -0,0 +1,112 @@
+use fluido_types::error::{
+    FluidoError, IRGenerationError, MixerGenerationError, StorageAllocationError,
+};
+use std::process::ExitCode;
+
+/// Stable process exit codes of the fluido CLI, so that scripts can tell different kind of
+/// failures apart.
+///
+/// | Code | Meaning                                                   |
+/// |------|-----------------------------------------------------------|
+/// | 0    | A mixer design is found.                                  |
+/// | 1    | Internal error, not covered by any other code.            |
+/// | 2    | Invalid input, i.e wrong CLI arguments or target.         |
+/// | 3    | Target is not reachable from the given input space.       |
+/// | 4    | Time, node or iteration limit reached before a design.    |
+/// | 5    | Found design violates a configured constraint.            |
+/// | 130  | Interrupted by Ctrl-C, after writing the final checkpoint. |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FluidoExitCode {
+    Success = 0,
+    InternalError = 1,
+    InvalidInput = 2,
+    TargetUnreachable = 3,
+    Timeout = 4,
+    ConstraintViolation = 5,
+    Interrupted = 130,
+}
+
+impl From<&FluidoError> for FluidoExitCode {
+    fn from(value: &FluidoError) -> Self {
+        match value {
+            FluidoError::MixerGenerationError(err) => match err {
+                MixerGenerationError::FailedToParseTarget(_) => Self::InvalidInput,
+                MixerGenerationError::TargetUnreachable(_) => Self::TargetUnreachable,
+                MixerGenerationError::TimeLimitReached(_)
+                | MixerGenerationError::NodeLimitReached(_)
+                | MixerGenerationError::IterationLimitReached(_) => Self::Timeout,
+                MixerGenerationError::SaturationError(_) => Self::InternalError,
+                MixerGenerationError::CheckpointError(_) => Self::InternalError,
+                MixerGenerationError::DuplicateRuleName(_) => Self::InvalidInput,
+                MixerGenerationError::RequiredIntermediatesUnreachable(_) => {
+                    Self::TargetUnreachable
+                }
+                MixerGenerationError::Cancelled => Self::InternalError,
+                MixerGenerationError::Interrupted(_) => Self::Interrupted,
+                MixerGenerationError::TargetNotExplored(_) => Self::TargetUnreachable,
+                MixerGenerationError::ExtractedExprError(_) => Self::InternalError,
+                MixerGenerationError::ReagentsUnreachable(_) => Self::TargetUnreachable,
+                MixerGenerationError::TooManyInputs(..) => Self::InvalidInput,
+                MixerGenerationError::ExpansionDepthTooLarge(..) => Self::InvalidInput,
+            },
+            FluidoError::IRGenerationError(IRGenerationError::ExprLimitExceeded(_)) => {
+                Self::InvalidInput
+            }
+            FluidoError::IRGenerationError(_) => Self::InternalError,
+            FluidoError::InterferenceGraphGenerationError(_) => Self::InternalError,
+            FluidoError::CertificateError(_) => Self::InternalError,
+            FluidoError::DesignValidationError(_) => Self::ConstraintViolation,
+            FluidoError::PipelineError(_) => Self::InternalError,
+            FluidoError::StorageAllocationError(err) => match err {
+                StorageAllocationError::UnknownPinnedInput(_) => Self::InvalidInput,
+                StorageAllocationError::ConflictingPins { .. } => Self::ConstraintViolation,
+            },
+            FluidoError::InvalidInput(_) => Self::InvalidInput,
+            FluidoError::UnitError(_) => Self::InvalidInput,
+            FluidoError::ConfigError(_) => Self::InvalidInput,
+            FluidoError::EvaluationError(_) => Self::InternalError,
+        }
+    }
+}
+
+impl From<&anyhow::Error> for FluidoExitCode {
+    fn from(value: &anyhow::Error) -> Self {
+        value
+            .downcast_ref::<FluidoError>()
+            .map(Self::from)
+            .unwrap_or(Self::InternalError)
+    }
+}
+
+impl From<FluidoExitCode> for ExitCode {
+    fn from(value: FluidoExitCode) -> Self {
+        ExitCode::from(value as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FluidoExitCode;
+    use fluido_types::error::{FluidoError, IRGenerationError, MixerGenerationError};
+
+    #[test]
+    fn exit_codes_are_distinct_per_error_kind() {
+        let unreachable: FluidoError = MixerGenerationError::TargetUnreachable(0.1.into()).into();
+        let timeout: FluidoError = MixerGenerationError::TimeLimitReached(1).into();
+        let invalid: FluidoError = MixerGenerationError::FailedToParseTarget(0.1.into()).into();
+        let internal: FluidoError = IRGenerationError::ParseError("".to_string()).into();
+        let interrupted: FluidoError = MixerGenerationError::Interrupted("c.json".into()).into();
+
+        assert_eq!(FluidoExitCode::from(&unreachable) as u8, 3);
+        assert_eq!(FluidoExitCode::from(&timeout) as u8, 4);
+        assert_eq!(FluidoExitCode::from(&invalid) as u8, 2);
+        assert_eq!(FluidoExitCode::from(&internal) as u8, 1);
+        assert_eq!(FluidoExitCode::from(&interrupted) as u8, 130);
+    }
+
+    #[test]
+    fn non_fluido_errors_are_internal() {
+        let err = anyhow::anyhow!("some error");
+        assert_eq!(FluidoExitCode::from(&err), FluidoExitCode::InternalError);
+    }
+}
@@ -1,59 +1,992 @@
 mod cmd;
+mod exit_code;
+mod target;
 
 use clap::Parser;
-use cmd::Args;
-use fluido_core::{Config, LogConfig, MixerGenerationConfig, MixerGenerator};
-use fluido_types::fluid::{Concentration, Fluid};
+use cmd::{Args, Command};
+use exit_code::FluidoExitCode;
+#[cfg(feature = "results-db")]
+use fluido_core::results_db::{ResultsDb, RunRecord};
+use fluido_core::{
+    allocation_hint::AllocationHint,
+    benchmark::{random_reachable_targets, run_benchmark},
+    confidence::Confidence,
+    doctor::{run_checks, CheckStatus, DoctorConfig},
+    equal_mixes_lower_bound,
+    export::plate_map::{self, WellNaming},
+    fallback::FallbackChain,
+    pipeline::PrintAfter,
+    planning::plan_batch,
+    reagents::ReagentLimits,
+    run_directory::{display_path, prune_runs, RunDirectory},
+    simulation::{simulate_fidelity, SimulationConfig, SimulationReport},
+    CheckpointConfig, ClassDumpConfig, Config, CostObjective, ExhaustiveLimits, ExtractionMode,
+    LogConfig, MixTimeModel, MixerDesign, MixerGenerationConfig, MixerGenerator, ProximityCost,
+    COARSE_TO_FINE_STAGES, EXHAUSTIVE_MAX_VOLUME,
+};
+use fluido_types::{
+    error::{ConfigError, FluidoError},
+    fluid::{
+        Concentration, ConcentrationWindow, Fluid, Incompatibility, MixerCapabilities, Volume,
+    },
+    input_space::{Input, InputSpace},
+    log::LogSink,
+    number::RoundingMode,
+    precision::Precision,
+    pretty::PrettyPrinter,
+    units::ConcentrationUnit,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    process::ExitCode,
+    time::Duration,
+};
+use target::{convert_to_working_unit, RelativeTarget};
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::try_parse()?;
-    handle_args(args)?;
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(err) => {
+            let _ = err.print();
+            // `--help` and `--version` are reported as errors by clap as well.
+            return if err.use_stderr() {
+                FluidoExitCode::InvalidInput.into()
+            } else {
+                FluidoExitCode::Success.into()
+            };
+        }
+    };
+    match handle_args(args) {
+        Ok(()) => FluidoExitCode::Success.into(),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            FluidoExitCode::from(&err).into()
+        }
+    }
+}
+
+fn handle_args(mut args: Args) -> anyhow::Result<()> {
+    if let Some(Command::Doctor) = &args.command {
+        return doctor(&args);
+    }
+    let quiet = args.quiet;
+    let json = args.json;
+    let mut out = report_writer(json);
+    let precision = precision(&args);
+    let pretty_printer = args
+        .pretty
+        .then(|| PrettyPrinter::new(2, args.annotate).with_precision(precision));
+    let show_linear = args.show_linear;
+    let show_sensitivity = args.show_sensitivity;
+    let volume_unit = match args.unit_volume {
+        Some(_) => "µL",
+        None => "units",
+    };
+    let simulation = args.simulate.map(|samples| {
+        SimulationConfig::new(samples)
+            .with_pipetting_error(args.pipetting_error)
+            .with_mixing_error(args.mixing_error)
+            .with_seed(args.simulation_seed)
+    });
+    let plate_map_path = args.emit_plate_map.clone();
+    let protocol_path = args.emit_protocol.clone();
+    let artifacts_dir = args.artifacts_dir.clone();
+    let keep_runs = args.keep_runs;
+    // Described before the inputs are converted, so that the config reads as given.
+    let run_config = format!("{args:#?}");
+    let target_class_path = args.dump_target_class.clone();
+    let allocation_path = args.emit_allocation.clone();
+    let allocation_hint = args
+        .allocation_hint
+        .as_ref()
+        .map(|path| read_allocation_hint(path))
+        .transpose()?;
+    let well_naming = WellNaming::new(args.plate_rows, args.plate_columns);
+    #[cfg(feature = "dmf")]
+    let dmf_path = args.emit_dmf.clone();
+    #[cfg(feature = "results-db")]
+    let results_db = args.results_db.clone().map(ResultsDb::open);
+    let reagent = args.reagent.clone().unwrap_or_default();
+    let working_unit = convert_to_working_unit(
+        args.unit,
+        args.target_concentration.as_mut(),
+        &mut args.input_space,
+        &reagent,
+    )
+    .map_err(FluidoError::from)?;
+    let input_space = input_space(&args)?;
+    if let Some(Command::Plan { targets }) = &args.command {
+        let targets = targets.clone();
+        return plan(args, &targets, &input_space);
+    }
+    if let Some(samples) = args.benchmark {
+        return benchmark(args, samples, &input_space);
+    }
+    // Presence of one of them is ensured by clap if not benchmarking.
+    let relative_target = args.target.clone();
+    let target_concentration = match &relative_target {
+        Some(relative_target) => relative_target.resolve(&args.input_space)?,
+        None => args
+            .target_concentration
+            .map(|target| target.value)
+            .unwrap_or_default(),
+    };
+    let target_concentration = exact_concentration(target_concentration)?;
+    if !quiet {
+        writeln!(
+            out,
+            "Starting to equality saturation, this will take ~{} seconds to finish.",
+            args.time_limit
+        )?;
+        match equal_mixes_lower_bound(&target_concentration, &input_space) {
+            Some(lower_bound) => writeln!(out, "needs at least {lower_bound} 1:1 mixes.")?,
+            None => writeln!(
+                out,
+                "no number of 1:1 mixes of the inputs reaches the target."
+            )?,
+        }
+    }
+    let mut config = Config::try_from(args)?;
+    if let Some(allocation_hint) = &allocation_hint {
+        config = config.with_allocation_hint(allocation_hint.clone());
+    }
+    let run_dir = artifacts_dir
+        .as_ref()
+        .map(|root| RunDirectory::create(root, &run_config))
+        .transpose()?;
+    if let Some(run_dir) = &run_dir {
+        let log = File::create(run_dir.logs().join("search.log"))?;
+        config = config.with_log_sink(LogSink::to_writer(Tee(report_writer(json), log)));
+    }
+
+    #[cfg(feature = "results-db")]
+    let (started, start) = (std::time::SystemTime::now(), std::time::Instant::now());
+    let (mixer_design, artifacts) = fluido_core::search_mixer_design_with_artifacts(
+        config,
+        target_concentration.clone(),
+        &input_space,
+    )?;
+    #[cfg(feature = "results-db")]
+    if let Some(results_db) = &results_db {
+        let record = RunRecord::new(
+            &run_config,
+            &target_concentration,
+            started,
+            start.elapsed(),
+            &mixer_design,
+        );
+        results_db.append(&record)?;
+    }
+    if let (Some(path), Some(dot)) = (target_class_path, &artifacts.target_class_dot) {
+        std::fs::write(path, dot)?;
+    }
+
+    let simulation = simulation.and_then(|simulation| {
+        simulate_fidelity(
+            mixer_design.certificate(),
+            &target_concentration,
+            &simulation,
+        )
+    });
+    let output = JsonOutput {
+        target: TargetRecord {
+            concentration: target_concentration.to_string(),
+            unit: working_unit,
+            relative_to: relative_target.as_ref(),
+        },
+        design: &mixer_design,
+        simulation: simulation.as_ref(),
+    };
+    if json {
+        println!("{}", serde_json::to_string(&output)?);
+    }
+    if let Some(run_dir) = &run_dir {
+        artifacts.write_to_dir(&run_dir.artifacts())?;
+        std::fs::write(
+            run_dir.reports().join("design.json"),
+            serde_json::to_string(&output)?,
+        )?;
+        if let (Some(root), Some(keep_runs)) = (&artifacts_dir, keep_runs) {
+            prune_runs(root, keep_runs)?;
+        }
+        if !quiet {
+            writeln!(
+                out,
+                "run outputs written to {}",
+                display_path(run_dir.path())
+            )?;
+        }
+    }
+    if let Some(allocation_path) = allocation_path {
+        let allocation = serde_json::to_string_pretty(&mixer_design.storage_assignments())?;
+        std::fs::write(&allocation_path, allocation)?;
+    }
+    if let Some(plate_map_path) = plate_map_path {
+        let csv = plate_map::to_csv(&mixer_design, &well_naming, &precision)?;
+        std::fs::write(&plate_map_path, csv)?;
+    }
+    if let Some(protocol_path) = protocol_path {
+        let protocol = serde_json::to_string_pretty(&mixer_design.to_protocol()?)?;
+        std::fs::write(&protocol_path, protocol)?;
+    }
+    #[cfg(feature = "dmf")]
+    if let Some(dmf_path) = dmf_path {
+        let protocol = fluido_core::export::dmf::to_dmf_json(&mixer_design)?;
+        std::fs::write(&dmf_path, protocol)?;
+    }
+    if !quiet {
+        let target = precision.format_limited(&target_concentration);
+        match &relative_target {
+            Some(relative_target) => writeln!(out, "target: {target} ({relative_target})")?,
+            None => writeln!(out, "target: {target}")?,
+        }
+        if let Some(working_unit) = working_unit {
+            writeln!(out, "concentrations are in {working_unit}")?;
+        }
+        match &pretty_printer {
+            Some(pretty_printer) => writeln!(
+                out,
+                "best expr:\n{}",
+                pretty_printer.print(mixer_design.expr())
+            )?,
+            None => writeln!(out, "best expr: {}", mixer_design.mixer_expr())?,
+        }
+        if show_linear {
+            write!(
+                out,
+                "{}",
+                mixer_design.linear_listing_with_precision(&precision)
+            )?;
+        }
+        writeln!(out, "cost: {}", precision.format(mixer_design.cost()))?;
+        match mixer_design.confidence() {
+            Confidence::Approximate { error } => writeln!(
+                out,
+                "confidence: approximate (off by {}).",
+                precision.format(error)
+            )?,
+            confidence => writeln!(out, "confidence: {confidence}.")?,
+        }
+        if mixer_design.is_optimal() {
+            writeln!(
+                out,
+                "optimal: no design of up to {EXHAUSTIVE_MAX_VOLUME} units of volume reaches the \
+                 target with fewer mixes."
+            )?;
+        }
+        writeln!(out, "recipe hash: {}", mixer_design.recipe_hash())?;
+        if let Some(fallback_report) = mixer_design.fallback_report() {
+            for attempt in &fallback_report.attempts {
+                writeln!(
+                    out,
+                    "fallback stage {} ({} seconds): {}",
+                    attempt.stage,
+                    precision.format(attempt.elapsed_seconds),
+                    attempt.outcome.report_with_precision(&precision)
+                )?;
+            }
+            writeln!(
+                out,
+                "design produced by fallback stage {}.",
+                fallback_report.selected_attempt().stage
+            )?;
+        }
+        match mixer_design.storage_units_lower_bound() {
+            Some(lower_bound) => writeln!(
+                out,
+                "need at least {} storage units, {} suffice ({}).",
+                lower_bound,
+                mixer_design.storage_units_needed(),
+                match mixer_design.confidence() {
+                    Confidence::Truncated =>
+                        "total time limit reached before the minimum was found",
+                    _ => "search stopped within the storage bound gap",
+                }
+            )?,
+            None => writeln!(
+                out,
+                "need at least {} storage units.",
+                mixer_design.storage_units_needed()
+            )?,
+        }
+        writeln!(
+            out,
+            "largest clique of the interference graph: {} storage units.",
+            mixer_design.storage_units_clique_bound()
+        )?;
+        if let Some(allocation_hint) = &allocation_hint {
+            writeln!(
+                out,
+                "kept {} of {} storage assignments of the allocation hint.",
+                allocation_hint.kept_assignments(&mixer_design),
+                allocation_hint.assignments.len()
+            )?;
+        }
+        let inputs_used = mixer_design
+            .inputs_used()
+            .iter()
+            .map(|concentration| precision.format_limited(concentration))
+            .collect::<Vec<_>>();
+        writeln!(out, "inputs used: {}", inputs_used.join(", "))?;
+        if let Some(estimated_time) = mixer_design.estimated_time() {
+            writeln!(
+                out,
+                "estimated protocol time: {} seconds.",
+                precision.format(estimated_time)
+            )?;
+        }
+        let schedule = mixer_design.schedule();
+        writeln!(
+            out,
+            "critical path: {}, makespan on {} mixer(s): {}.",
+            precision.format(schedule.critical_path),
+            schedule.mixers,
+            precision.format(schedule.makespan)
+        )?;
+        if !schedule.fits() {
+            writeln!(
+                out,
+                "mixes wait for a free mixer, {} mixers reach the critical path.",
+                mixer_design.min_mixers()
+            )?;
+        }
+        let makespans = schedule
+            .makespans
+            .iter()
+            .enumerate()
+            .map(|(ix, makespan)| format!("{}: {}", ix + 1, precision.format(*makespan)))
+            .collect::<Vec<_>>();
+        writeln!(
+            out,
+            "makespan by number of mixers: {}.",
+            makespans.join(", ")
+        )?;
+        for reagent in mixer_design.reagent_usage() {
+            writeln!(
+                out,
+                "input {}: uses {} {volume_unit} (+{} dead volume).",
+                precision.format_limited(&reagent.concentration),
+                precision.format(reagent.used_volume),
+                precision.format(reagent.dead_volume)
+            )?;
+        }
+        for intermediate in mixer_design.required_intermediates() {
+            writeln!(
+                out,
+                "required intermediate {}: produced by mix {}.",
+                precision.format_limited(&intermediate.concentration),
+                intermediate.step
+            )?;
+        }
+        for violation in mixer_design.stability_violations() {
+            writeln!(
+                out,
+                "warning: intermediate {} lives {} mixes (mix {} to mix {}), input {} is stable for {}.",
+                precision.format_fluid(&violation.fluid),
+                violation.steps_live(),
+                violation.produced_at,
+                violation.consumed_at,
+                precision.format_limited(&violation.input),
+                violation.max_steps_live
+            )?;
+        }
+        if show_sensitivity {
+            print_sensitivity(&mut out, &mixer_design, &precision)?;
+        }
+        if let Some(simulation) = &simulation {
+            writeln!(
+                out,
+                "simulated {} times: mean {}, std dev {}, {}% within [{}, {}], rms error {}.",
+                simulation.samples,
+                precision.format(simulation.mean),
+                precision.format(simulation.std_dev),
+                precision.format(simulation.interval * 100.0),
+                precision.format(simulation.lower),
+                precision.format(simulation.upper),
+                precision.format(simulation.rms_error)
+            )?;
+        }
+        let certificate = mixer_design.certificate();
+        if certificate.proves(&target_concentration) {
+            writeln!(out, "certificate: target concentration is reached exactly.")?;
+        } else {
+            writeln!(
+                out,
+                "certificate: design produces {} instead of the target.",
+                precision.format(certificate.result.concentration.into())
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the sensitivity of the design to each of its inputs and leaves.
+fn print_sensitivity(
+    out: &mut dyn Write,
+    mixer_design: &MixerDesign,
+    precision: &Precision,
+) -> io::Result<()> {
+    let sensitivity = mixer_design.sensitivity();
+    for stock in &sensitivity.stocks {
+        writeln!(
+            out,
+            "sensitivity to input {}: coefficient {}, ±{} moves the result by ±{}.",
+            precision.format_limited(&stock.concentration),
+            precision.format(stock.coefficient),
+            precision.format(stock.perturbation),
+            precision.format(stock.deviation)
+        )?;
+    }
+    for leaf in &sensitivity.leaves {
+        writeln!(
+            out,
+            "sensitivity to leaf {} ({}): coefficient {}.",
+            leaf.leaf,
+            precision.format_limited(&leaf.concentration),
+            precision.format(leaf.coefficient)
+        )?;
+    }
     Ok(())
 }
 
-fn handle_args(args: Args) -> anyhow::Result<()> {
-    println!(
-        "Starting to equality saturation, this will take ~{} seconds to finish.",
-        args.time_limit
+/// Runs the environment checks and prints each of them, failing if one of them failed.
+fn doctor(args: &Args) -> anyhow::Result<()> {
+    let output_paths = [
+        &args.checkpoint,
+        &args.artifacts_dir,
+        &args.dump_target_class,
+        &args.emit_allocation,
+        &args.emit_plate_map,
+        &args.emit_protocol,
+    ];
+    #[allow(unused_mut)]
+    let mut output_paths = output_paths
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    #[cfg(feature = "dmf")]
+    output_paths.extend(args.emit_dmf.clone());
+    #[cfg(feature = "results-db")]
+    output_paths.extend(args.results_db.clone());
+    let checks = run_checks(
+        &DoctorConfig::new()
+            .with_precision(precision(args))
+            .with_output_paths(output_paths),
     );
-    let target_concentration = Concentration::from(args.target_concentration);
-    let input_space = args
-        .input_space
+    for check in &checks {
+        println!("[{}] {}: {}", check.status, check.name, check.message);
+        if let Some(fix) = &check.fix {
+            println!("    fix: {fix}");
+        }
+    }
+    let failed = checks
         .iter()
-        .map(|input_concentration| {
-            let conc = Concentration::from(*input_concentration);
-            //TODO: Actually parse fluid vol from user.
-            Fluid::new(conc, 1.0.into())
-        })
-        .collect::<Vec<_>>();
-    let config = Config::from(args);
+        .filter(|check| check.status == CheckStatus::Failed)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} checks failed", checks.len());
+    }
+    Ok(())
+}
 
-    let mixer_design =
-        fluido_core::search_mixer_design(config, target_concentration, &input_space)?;
+/// Display precision of the numbers in reports, from `--decimals` or `--significant-figures`, or
+/// readable numbers without either.
+fn precision(args: &Args) -> Precision {
+    match (args.decimals, args.significant_figures) {
+        (Some(decimals), _) => Precision::DecimalPlaces(decimals),
+        (None, Some(figures)) => Precision::SignificantFigures(figures),
+        (None, None) => Precision::Readable,
+    }
+}
 
-    println!("best expr: {}", mixer_design.mixer_expr());
-    println!("cost: {}", mixer_design.cost());
-    println!(
-        "need at least {} storage units.",
-        mixer_design.storage_units_needed()
-    );
+/// Writer of the human readable output, stderr if `--json` keeps stdout for the JSON output.
+fn report_writer(json: bool) -> Box<dyn Write + Send> {
+    match json {
+        true => Box::new(io::stderr()),
+        false => Box::new(io::stdout()),
+    }
+}
+
+/// Writer duplicating the logs of the search into the log file of the run.
+struct Tee<W>(W, File);
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// JSON output of a search, the design along with the target it was searched for.
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    target: TargetRecord<'a>,
+    #[serde(flatten)]
+    design: &'a MixerDesign,
+    /// Distribution of the concentration the design produces, if simulated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    simulation: Option<&'a SimulationReport>,
+}
+
+/// Target as given by the user, and the absolute concentration it resolves to.
+#[derive(Serialize)]
+struct TargetRecord<'a> {
+    concentration: String,
+    /// Unit all concentrations are converted into, if given with units.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<ConcentrationUnit>,
+    relative_to: Option<&'a RelativeTarget>,
+}
+
+/// Reads the storage assignments written by `--emit-allocation` of a previous run.
+fn read_allocation_hint(path: &Path) -> Result<AllocationHint, FluidoError> {
+    let hint_str = std::fs::read_to_string(path).map_err(|e| {
+        FluidoError::InvalidInput(format!("failed to read allocation hint {path:?}: {e}"))
+    })?;
+    serde_json::from_str(&hint_str).map_err(|e| {
+        FluidoError::InvalidInput(format!("failed to parse allocation hint {path:?}: {e}"))
+    })
+}
 
+/// Converts a user given concentration, rejecting values which would silently lose precision.
+fn exact_concentration(value: f64) -> Result<Concentration, FluidoError> {
+    Concentration::from_f64_rounded(value, RoundingMode::Exact)
+        .map_err(|err| FluidoError::InvalidInput(err.to_string()))
+}
+
+/// Runs the search for randomly sampled reachable targets and prints a summary of the results.
+fn benchmark(args: Args, samples: usize, input_space: &InputSpace) -> anyhow::Result<()> {
+    let quiet = args.quiet;
+    let json = args.json;
+    let mut out = report_writer(json);
+    let precision = precision(&args);
+    let targets = random_reachable_targets(input_space, samples, args.benchmark_seed);
+    if targets.is_empty() {
+        return Err(FluidoError::InvalidInput(
+            "input space needs at least two different concentrations to sample targets".to_string(),
+        )
+        .into());
+    }
+    if !quiet {
+        writeln!(
+            out,
+            "Benchmarking {} targets, this will take up to ~{} seconds to finish.",
+            targets.len(),
+            targets.len() as u64 * args.time_limit
+        )?;
+    }
+    let config = Config::try_from(args)?.with_logging(LogConfig::silent());
+    let summary = run_benchmark(&config, &targets, input_space);
+
+    if json {
+        println!("{}", serde_json::to_string(&summary)?);
+    }
+    if !quiet {
+        writeln!(out, "{}", summary.report_with_precision(&precision))?;
+    }
     Ok(())
 }
 
-impl From<Args> for Config {
-    fn from(value: Args) -> Self {
+/// Searches a design for each target of the batch and prints their production plan.
+fn plan(args: Args, targets: &[f64], input_space: &InputSpace) -> anyhow::Result<()> {
+    let quiet = args.quiet;
+    let json = args.json;
+    let mut out = report_writer(json);
+    let precision = precision(&args);
+    if args.benchmark.is_some() {
+        return Err(FluidoError::InvalidInput(
+            "`--benchmark` cannot be combined with `plan`".to_string(),
+        )
+        .into());
+    }
+    let targets = targets
+        .iter()
+        .map(|target| exact_concentration(*target))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !quiet {
+        writeln!(
+            out,
+            "Planning {} targets, this will take up to ~{} seconds to finish.",
+            targets.len(),
+            targets.len() as u64 * args.time_limit
+        )?;
+    }
+    let config = Config::try_from(args)?.with_logging(LogConfig::silent());
+    let plan = plan_batch(&config, &targets, input_space)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&plan)?);
+    }
+    if !quiet {
+        for (&target_ix, storage) in plan.order.iter().zip(&plan.storage_per_target) {
+            writeln!(
+                out,
+                "target {}: {} ({} storage units in use)",
+                precision.format_limited(&targets[target_ix]),
+                plan.designs[target_ix].mixer_expr(),
+                storage
+            )?;
+        }
+        for intermediate in &plan.shared_intermediates {
+            let shared_by = intermediate
+                .designs
+                .iter()
+                .map(|&design| precision.format_limited(&targets[design]))
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "shared intermediate {} (volume {}): produced once for targets {}.",
+                precision.format_rational(&intermediate.fluid.concentration),
+                precision.format_rational(&intermediate.fluid.volume),
+                shared_by.join(", ")
+            )?;
+        }
+        for reused in &plan.reused_subdesigns {
+            let used_by = reused
+                .designs
+                .iter()
+                .map(|&design| precision.format_limited(&targets[design]))
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "stock {} (concentration {}): prepare volume {} for {} uses by targets {}.",
+                reused.expr,
+                precision.format_limited(reused.fluid.concentration()),
+                precision.format_limited(&reused.total_volume),
+                reused.uses,
+                used_by.join(", ")
+            )?;
+        }
+        writeln!(
+            out,
+            "need at least {} storage units for the batch.",
+            plan.storage_units_needed
+        )?;
+    }
+    Ok(())
+}
+
+impl TryFrom<Args> for Config {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Args) -> anyhow::Result<Self> {
         let time_limit = value.time_limit;
+        let reagent_limits = reagent_limits(&value)?;
+        let input_uncertainties = input_uncertainties(&value)?;
+        let input_stability = input_stability(&value)?;
+        let precision = precision(&value);
 
-        let mixer_generation_config =
+        let mut mixer_generation_config =
             MixerGenerationConfig::new(time_limit, MixerGenerator::EqualitySaturation);
+        if let Some(checkpoint_path) = value.checkpoint {
+            let checkpoint = CheckpointConfig::new(
+                checkpoint_path,
+                Duration::from_secs(value.checkpoint_interval),
+            );
+            // On Ctrl-C stop the saturation, so that a final checkpoint is written. Outside of
+            // saturation there is nothing to checkpoint, so Ctrl-C exits right away.
+            let interrupt = checkpoint.clone();
+            ctrlc::set_handler(move || {
+                if !interrupt.interrupt() {
+                    std::process::exit(FluidoExitCode::Interrupted as i32);
+                }
+            })?;
+            mixer_generation_config = mixer_generation_config.with_checkpoint(checkpoint);
+        }
+        if let Some(node_limit) = value.node_limit {
+            mixer_generation_config = mixer_generation_config.with_node_limit(node_limit);
+        }
+        if let Some(iter_limit) = value.iter_limit {
+            mixer_generation_config = mixer_generation_config.with_iter_limit(iter_limit);
+        }
+        if value.mix_time_per_mix.is_some() || value.mix_time_per_volume.is_some() {
+            let default_model = MixTimeModel::default();
+            let mix_time_model = MixTimeModel::new(
+                value.mix_time_per_mix.unwrap_or(default_model.per_mix),
+                value
+                    .mix_time_per_volume
+                    .unwrap_or(default_model.per_volume),
+            );
+            mixer_generation_config = mixer_generation_config.with_mix_time_model(mix_time_model);
+        }
+        if value.optimize_time {
+            mixer_generation_config =
+                mixer_generation_config.with_objective(CostObjective::MixTime);
+        }
+        if value.minimize_waste {
+            mixer_generation_config = mixer_generation_config.with_objective(CostObjective::Waste);
+        }
+        if value.volume_weighted_proximity {
+            mixer_generation_config =
+                mixer_generation_config.with_proximity_cost(ProximityCost::VolumeWeighted);
+        }
+        if value.coarse_to_fine {
+            mixer_generation_config =
+                mixer_generation_config.with_stages(COARSE_TO_FINE_STAGES.to_vec());
+        }
+        if let Some(expansion_depth) = value.expansion_depth {
+            mixer_generation_config =
+                mixer_generation_config.with_expansion_depth(expansion_depth as usize);
+        }
+        if !value.fallback.is_empty() {
+            mixer_generation_config = mixer_generation_config.with_fallback(FallbackChain::new(
+                value.fallback.clone(),
+                value.fallback_tolerance,
+            ));
+        }
+        if value.constrained_extraction {
+            mixer_generation_config =
+                mixer_generation_config.with_extraction(ExtractionMode::Constrained);
+        }
+        mixer_generation_config =
+            mixer_generation_config.with_extraction_root(value.extraction_root);
+        mixer_generation_config = mixer_generation_config.with_exhaustive_limits(
+            ExhaustiveLimits::new(value.exhaustive_max_inputs, value.exhaustive_max_mixes),
+        );
+        if let Some(resume_from) = value.resume {
+            mixer_generation_config = mixer_generation_config.with_resume_from(resume_from);
+        }
+        if !value.forbid_concentration.is_empty() {
+            let forbidden_windows = value
+                .forbid_concentration
+                .iter()
+                .map(|window| {
+                    Ok(ConcentrationWindow::new(
+                        exact_concentration(window.low)?,
+                        exact_concentration(window.high)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>, FluidoError>>()?;
+            mixer_generation_config =
+                mixer_generation_config.with_forbidden_windows(forbidden_windows);
+        }
+        if !value.incompatible.is_empty() {
+            let incompatibilities = value
+                .incompatible
+                .iter()
+                .map(|spec| {
+                    let [lhs, rhs] = spec.resolve(&value.input_space)?;
+                    Ok(
+                        Incompatibility::new(exact_concentration(lhs)?, exact_concentration(rhs)?)
+                            .with_max_fraction(spec.max_fraction),
+                    )
+                })
+                .collect::<Result<Vec<_>, FluidoError>>()?;
+            mixer_generation_config =
+                mixer_generation_config.with_incompatibilities(incompatibilities);
+        }
+        if let Some(max_intermediate_volume) = value.max_intermediate_volume {
+            let max_intermediate_volume =
+                Volume::from_f64_rounded(max_intermediate_volume, RoundingMode::Exact)
+                    .map_err(|err| FluidoError::InvalidInput(err.to_string()))?;
+            mixer_generation_config =
+                mixer_generation_config.with_max_intermediate_volume(max_intermediate_volume);
+        }
+        if !value.mix_ratio.is_empty() {
+            mixer_generation_config = mixer_generation_config
+                .with_capabilities(MixerCapabilities::new(value.mix_ratio.iter().copied()));
+        }
+        if !value.require_intermediate.is_empty() {
+            let required_intermediates = value
+                .require_intermediate
+                .iter()
+                .map(|concentration| exact_concentration(*concentration))
+                .collect::<Result<Vec<_>, _>>()?;
+            mixer_generation_config =
+                mixer_generation_config.with_required_intermediates(required_intermediates);
+        }
+        if value.dump_target_class.is_some() {
+            mixer_generation_config =
+                mixer_generation_config.with_target_class_dump(ClassDumpConfig {
+                    depth: value.target_class_depth,
+                    top_k: value.target_class_top_k,
+                    precision,
+                });
+        }
+
         let logging_config = LogConfig::new(
+            !value.quiet,
             value.show_dot,
             value.show_ir,
             value.show_liveness,
             value.show_interference,
-        );
+        )
+        .with_precision(precision)
+        .with_sink(match value.json {
+            true => LogSink::to_writer(io::stderr()),
+            false => LogSink::stdout(),
+        })
+        .with_print_after(if value.print_after_all {
+            PrintAfter::All
+        } else if !value.print_after.is_empty() {
+            PrintAfter::Phases(value.print_after.clone())
+        } else {
+            PrintAfter::None
+        });
 
-        Config::new(mixer_generation_config, logging_config)
+        let mut config = Config::new(mixer_generation_config, logging_config)
+            .with_reagent_limits(reagent_limits)
+            .with_input_uncertainties(input_uncertainties)
+            .with_input_stability(input_stability)
+            .with_mixers(value.mixers as usize)
+            .with_storage_pins(value.pin_input.clone())
+            .with_storage_bound_gap(value.storage_bound_gap)
+            .with_color_count_strategy(value.color_count_strategy)
+            .with_reg_alloc_strategy(value.reg_alloc_strategy)
+            .with_artifact_capture(
+                value.artifacts_dir.is_some() || value.dump_target_class.is_some(),
+            );
+        if let Some(max_inputs) = value.max_inputs_per_design {
+            config = config.with_max_inputs(max_inputs as usize);
+        }
+        if let Some(max_total_seconds) = value.max_total_seconds {
+            config = config.with_max_total_time(Duration::from_secs(max_total_seconds));
+        }
+        Ok(config)
+    }
+}
+
+/// Fails unless there are no values, or one value per `--input-space`.
+fn check_per_input(args: &Args, flag: &str, value_count: usize) -> Result<(), FluidoError> {
+    let input_count = args.input_space.len();
+    if value_count != 0 && value_count != input_count {
+        return Err(ConfigError::ValueCountMismatch {
+            flag: flag.to_string(),
+            expected: input_count,
+            found: value_count,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Collects the uncertainty of each input from `--input-uncertainty`.
+fn input_uncertainties(args: &Args) -> anyhow::Result<HashMap<Concentration, f64>> {
+    check_per_input(args, "--input-uncertainty", args.input_uncertainty.len())?;
+    let uncertainties = args
+        .input_space
+        .iter()
+        .zip(&args.input_uncertainty)
+        .map(|(input, uncertainty)| (Concentration::from(input.concentration), *uncertainty))
+        .collect();
+    Ok(uncertainties)
+}
+
+/// Collects the number of mixes each degrading input stays stable for from `--max-steps-live`.
+fn input_stability(args: &Args) -> anyhow::Result<HashMap<Concentration, usize>> {
+    check_per_input(args, "--max-steps-live", args.max_steps_live.len())?;
+    let stability = args
+        .input_space
+        .iter()
+        .zip(&args.max_steps_live)
+        .filter_map(|(input, steps)| Some((Concentration::from(input.concentration), steps.0?)))
+        .collect();
+    Ok(stability)
+}
+
+/// Collects the reservoir limits of the inputs from `--input-volume`, `--dead-volume` and
+/// `--unit-volume`.
+fn reagent_limits(args: &Args) -> anyhow::Result<Vec<ReagentLimits>> {
+    check_per_input(args, "--input-volume", args.input_volume.len())?;
+    check_per_input(args, "--dead-volume", args.dead_volume.len())?;
+    if let Some(unit_volume) = args.unit_volume.filter(|unit_volume| *unit_volume <= 0.0) {
+        return Err(FluidoError::InvalidInput(format!(
+            "`--unit-volume {unit_volume}` is not a positive volume"
+        ))
+        .into());
+    }
+    if let Some(dead_volume) = args.dead_volume.iter().find(|volume| **volume < 0.0) {
+        return Err(FluidoError::InvalidInput(format!(
+            "`--dead-volume {dead_volume}` is not a non-negative volume"
+        ))
+        .into());
+    }
+    if let Some(input_volume) = args.input_volume.iter().find(|volume| **volume <= 0.0) {
+        return Err(FluidoError::InvalidInput(format!(
+            "`--input-volume {input_volume}` is not a positive volume"
+        ))
+        .into());
+    }
+    let limits = args
+        .input_space
+        .iter()
+        .enumerate()
+        .map(|(ix, input)| {
+            ReagentLimits::new(
+                Concentration::from(input.concentration),
+                args.input_volume.get(ix).map(|vol| Volume::from(*vol)),
+                Volume::from(args.dead_volume.get(ix).copied().unwrap_or(0.0)),
+            )
+            .with_unit_volume(Volume::from(args.unit_volume.unwrap_or(1.0)))
+        })
+        .collect();
+    Ok(limits)
+}
+
+/// Collects the inputs from `--input-space`, along with their names and their tiers from
+/// `--input-tier`.
+fn input_space(args: &Args) -> anyhow::Result<InputSpace> {
+    check_per_input(args, "--input-tier", args.input_tier.len())?;
+    args.input_space
+        .iter()
+        .enumerate()
+        .map(|(ix, spec)| {
+            let conc = exact_concentration(spec.concentration)?;
+            //TODO: Actually parse fluid vol from user.
+            let mut input = Input::new(Fluid::new(conc, 1.0.into()))
+                .with_tier(args.input_tier.get(ix).copied().unwrap_or_default());
+            if let Some(name) = &spec.name {
+                input = input.with_name(name);
+            }
+            Ok(input)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reagent_limits, Args, FluidoExitCode};
+    use clap::Parser;
+
+    #[test]
+    fn non_positive_volumes_are_rejected() {
+        let limits = |volumes: &[&str]| {
+            let base = ["fluido", "--input-space", "0.4", "--input-space", "0"];
+            let args = Args::try_parse_from(
+                base.iter()
+                    .chain(["--target-concentration", "0.2", "--time-limit", "1"].iter())
+                    .chain(volumes),
+            )
+            .unwrap();
+            reagent_limits(&args).map_err(|err| FluidoExitCode::from(&err))
+        };
+        assert!(limits(&["--dead-volume", "0", "--dead-volume", "5"]).is_ok());
+        assert!(limits(&["--input-volume", "1", "--input-volume", "1"]).is_ok());
+        let invalid = Err(FluidoExitCode::InvalidInput);
+        assert_eq!(
+            limits(&["--dead-volume=-5", "--dead-volume=-5"]).map(|_| ()),
+            invalid
+        );
+        assert_eq!(
+            limits(&["--input-volume", "0", "--input-volume", "1"]).map(|_| ()),
+            invalid
+        );
+        assert_eq!(
+            limits(&["--input-volume=-1", "--input-volume", "1"]).map(|_| ()),
+            invalid
+        );
+        assert_eq!(limits(&["--unit-volume=-1"]).map(|_| ()), invalid);
     }
 }
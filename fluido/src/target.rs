@@ -0,0 +1,355 @@
+use crate::cmd::finite_f64;
+use fluido_types::{
+    error::{ConfigError, FluidoError, UnitError},
+    number::validate_f64,
+    suggest::closest_match,
+    units::{parse_concentration, ConcentrationUnit, Reagent},
+};
+use serde::Serialize;
+use std::{fmt::Display, str::FromStr};
+
+/// A concentration, optionally with a unit, parsed from i.e `0.1`, `0.1M`, `2%` or `10mg/mL`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConcentrationSpec {
+    pub value: f64,
+    pub unit: Option<ConcentrationUnit>,
+}
+
+impl FromStr for ConcentrationSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, unit) = parse_concentration(s).map_err(|e| e.to_string())?;
+        Ok(Self { value, unit })
+    }
+}
+
+/// An input of the input space, optionally named so that targets can refer to it.
+///
+/// Parsed from `<concentration>` or `<name>=<concentration>`, i.e `0.4` or `stockA=0.4M`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSpec {
+    pub name: Option<String>,
+    pub concentration: f64,
+    pub unit: Option<ConcentrationUnit>,
+}
+
+impl FromStr for InputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, concentration_str) = match s.split_once('=') {
+            Some((name, concentration_str)) if !name.trim().is_empty() => {
+                (Some(name.trim().to_string()), concentration_str)
+            }
+            Some(_) => return Err(format!("missing input name in `{s}`")),
+            None => (None, s),
+        };
+        let concentration = concentration_str
+            .parse::<ConcentrationSpec>()
+            .map_err(|e| format!("invalid concentration in `{s}`: {e}"))?;
+        Ok(Self {
+            name,
+            concentration: concentration.value,
+            unit: concentration.unit,
+        })
+    }
+}
+
+/// A target concentration given relative to a named input, parsed from `<factor>x:<name>`.
+///
+/// `0.25x:stockA` is a quarter of the concentration of `stockA`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelativeTarget {
+    pub factor: f64,
+    pub stock: String,
+}
+
+impl FromStr for RelativeTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (factor_str, stock) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected `<factor>x:<stock>`, found `{s}`"))?;
+        let factor = factor_str
+            .trim()
+            .strip_suffix('x')
+            .ok_or_else(|| format!("factor `{factor_str}` should end with `x`"))?;
+        let factor =
+            finite_f64(factor).map_err(|e| format!("invalid factor `{factor_str}`: {e}"))?;
+        let stock = stock.trim();
+        if stock.is_empty() {
+            return Err(format!("missing stock name in `{s}`"));
+        }
+        Ok(Self {
+            factor,
+            stock: stock.to_string(),
+        })
+    }
+}
+
+impl Display for RelativeTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x:{}", self.factor, self.stock)
+    }
+}
+
+impl RelativeTarget {
+    /// Absolute concentration of the target, using the concentration of the named input.
+    pub fn resolve(&self, input_space: &[InputSpec]) -> Result<f64, FluidoError> {
+        let stock = input_space
+            .iter()
+            .find(|input| input.name.as_deref() == Some(self.stock.as_str()))
+            .ok_or_else(|| unknown_input("--target", self.to_string(), &self.stock, input_space))?;
+        Ok(self.factor * stock.concentration)
+    }
+}
+
+/// Two named inputs which must not be mixed directly, parsed from `<name>,<name>` or
+/// `<name>,<name>@<max_fraction>`.
+///
+/// `acid,base@0.1` lets `acid` and `base` meet only once each makes up at most a tenth of the
+/// fluid carrying it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncompatibleSpec {
+    pub inputs: [String; 2],
+    pub max_fraction: f64,
+}
+
+impl FromStr for IncompatibleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (inputs, max_fraction) = match s.split_once('@') {
+            Some((inputs, max_fraction)) => {
+                let max_fraction = finite_f64(max_fraction)
+                    .map_err(|e| format!("invalid fraction `{max_fraction}`: {e}"))?;
+                if !(0.0..=1.0).contains(&max_fraction) {
+                    return Err(format!("fraction `{max_fraction}` is not between 0 and 1"));
+                }
+                (inputs, max_fraction)
+            }
+            None => (s, 0.0),
+        };
+        let (lhs, rhs) = inputs
+            .split_once(',')
+            .ok_or_else(|| format!("expected `<name>,<name>[@<max_fraction>]`, found `{s}`"))?;
+        let (lhs, rhs) = (lhs.trim(), rhs.trim());
+        if lhs.is_empty() || rhs.is_empty() {
+            return Err(format!("missing input name in `{s}`"));
+        }
+        if lhs == rhs {
+            return Err(format!("input `{lhs}` cannot be incompatible with itself"));
+        }
+        Ok(Self {
+            inputs: [lhs.to_string(), rhs.to_string()],
+            max_fraction,
+        })
+    }
+}
+
+impl Display for IncompatibleSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.inputs[0], self.inputs[1])?;
+        match self.max_fraction {
+            0.0 => Ok(()),
+            max_fraction => write!(f, "@{max_fraction}"),
+        }
+    }
+}
+
+impl IncompatibleSpec {
+    /// Concentrations of the named inputs.
+    pub fn resolve(&self, input_space: &[InputSpec]) -> Result<[f64; 2], FluidoError> {
+        let concentration = |name: &String| {
+            input_space
+                .iter()
+                .find(|input| input.name.as_ref() == Some(name))
+                .map(|input| input.concentration)
+                .ok_or_else(|| unknown_input("--incompatible", self.to_string(), name, input_space))
+        };
+        Ok([
+            concentration(&self.inputs[0])?,
+            concentration(&self.inputs[1])?,
+        ])
+    }
+}
+
+/// Error for the value of the flag referring to an input no `--input-space` is named after,
+/// suggesting the closest name of an input.
+fn unknown_input(flag: &str, value: String, name: &str, input_space: &[InputSpec]) -> FluidoError {
+    let names = input_space.iter().filter_map(|input| input.name.as_deref());
+    ConfigError::UnknownInput {
+        flag: flag.to_string(),
+        value,
+        name: name.to_string(),
+        suggestion: closest_match(name, names).map(ToString::to_string),
+    }
+    .into()
+}
+
+/// Converts the target and the inputs into a single working unit, so that the search sees
+/// concentrations of the same unit. The working unit is `unit` if given, the unit of the target
+/// if it has one, and the unit of the first input with one otherwise. Values without a unit are
+/// taken to be in the working unit already.
+///
+/// Returns the working unit, `None` if no concentration has a unit.
+pub fn convert_to_working_unit(
+    unit: Option<ConcentrationUnit>,
+    target: Option<&mut ConcentrationSpec>,
+    input_space: &mut [InputSpec],
+    reagent: &Reagent,
+) -> Result<Option<ConcentrationUnit>, UnitError> {
+    let working_unit = unit
+        .or(target.as_ref().and_then(|target| target.unit))
+        .or(input_space.iter().find_map(|input| input.unit));
+    let Some(working_unit) = working_unit else {
+        return Ok(None);
+    };
+    let convert = |value: f64, unit: Option<ConcentrationUnit>| -> Result<f64, UnitError> {
+        match unit {
+            Some(unit) => Ok(validate_f64(reagent.convert(value, unit, working_unit)?)?),
+            None => Ok(value),
+        }
+    };
+    if let Some(target) = target {
+        target.value = convert(target.value, target.unit)?;
+        target.unit = Some(working_unit);
+    }
+    for input in input_space {
+        input.concentration = convert(input.concentration, input.unit)?;
+        input.unit = Some(working_unit);
+    }
+    Ok(Some(working_unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        convert_to_working_unit, ConcentrationSpec, IncompatibleSpec, InputSpec, RelativeTarget,
+    };
+    use fluido_types::{
+        error::{ConfigError, FluidoError},
+        units::{ConcentrationUnit, Reagent},
+    };
+
+    #[test]
+    fn parse_input_spec() {
+        assert_eq!(
+            "stockA=0.4".parse::<InputSpec>(),
+            Ok(InputSpec {
+                name: Some("stockA".to_string()),
+                concentration: 0.4,
+                unit: None
+            })
+        );
+        assert_eq!(
+            "0.4".parse::<InputSpec>(),
+            Ok(InputSpec {
+                name: None,
+                concentration: 0.4,
+                unit: None
+            })
+        );
+        assert_eq!(
+            "stockA=0.5M".parse::<InputSpec>().unwrap().unit,
+            Some(ConcentrationUnit::Molar)
+        );
+        assert!("=0.4".parse::<InputSpec>().is_err());
+        assert!("stockA=high".parse::<InputSpec>().is_err());
+        assert!("stockA=NaN".parse::<InputSpec>().is_err());
+    }
+
+    #[test]
+    fn resolve_relative_target() {
+        let target = "0.25x:stockA".parse::<RelativeTarget>().unwrap();
+        assert_eq!(target.to_string(), "0.25x:stockA");
+
+        let input_space = vec![
+            "0".parse::<InputSpec>().unwrap(),
+            "stockA=0.4".parse::<InputSpec>().unwrap(),
+        ];
+        assert_eq!(target.resolve(&input_space).unwrap(), 0.1);
+        let missing = "0.5x:stockB".parse::<RelativeTarget>().unwrap();
+        assert_eq!(
+            missing.resolve(&input_space).unwrap_err().to_string(),
+            "`--target 0.5x:stockB` refers to unknown input `stockB`. Did you mean `stockA`? Name \
+             inputs with `--input-space stockB=<concentration>`."
+        );
+
+        assert!("0.25:stockA".parse::<RelativeTarget>().is_err());
+        assert!("0.25x".parse::<RelativeTarget>().is_err());
+        assert!("infx:stockA".parse::<RelativeTarget>().is_err());
+    }
+
+    #[test]
+    fn resolve_incompatible_inputs() {
+        let input_space = vec![
+            "buffer=0".parse::<InputSpec>().unwrap(),
+            "acid=0.4".parse::<InputSpec>().unwrap(),
+            "base=0.8".parse::<InputSpec>().unwrap(),
+        ];
+        let spec = "acid, base".parse::<IncompatibleSpec>().unwrap();
+        assert_eq!(spec.max_fraction, 0.0);
+        assert_eq!(spec.resolve(&input_space).unwrap(), [0.4, 0.8]);
+        let spec = "acid,base@0.1".parse::<IncompatibleSpec>().unwrap();
+        assert_eq!(spec.max_fraction, 0.1);
+        let missing = "acid,salt@0.1".parse::<IncompatibleSpec>().unwrap();
+        assert!(matches!(
+            missing.resolve(&input_space),
+            Err(FluidoError::ConfigError(ConfigError::UnknownInput { value, suggestion: None, .. }))
+                if value == "acid,salt@0.1"
+        ));
+
+        assert!("acid".parse::<IncompatibleSpec>().is_err());
+        assert!("acid,acid".parse::<IncompatibleSpec>().is_err());
+        assert!("acid,base@2".parse::<IncompatibleSpec>().is_err());
+        assert!("acid,@0.1".parse::<IncompatibleSpec>().is_err());
+    }
+
+    #[test]
+    fn concentrations_are_converted_to_the_working_unit() {
+        let nacl = "NaCl=50".parse::<Reagent>().unwrap();
+        let mut target = "0.1M".parse::<ConcentrationSpec>().unwrap();
+        let mut input_space = vec![
+            "0".parse::<InputSpec>().unwrap(),
+            "stockA=2%".parse::<InputSpec>().unwrap(),
+        ];
+        let unit =
+            convert_to_working_unit(None, Some(&mut target), &mut input_space, &nacl).unwrap();
+        assert_eq!(unit, Some(ConcentrationUnit::Molar));
+        assert_eq!(target.value, 0.1);
+        assert_eq!(input_space[0].concentration, 0.0);
+        assert_eq!(input_space[1].concentration, 0.4);
+
+        // An explicit unit takes precedence over the unit of the target.
+        let mut target = "0.1M".parse::<ConcentrationSpec>().unwrap();
+        let unit = convert_to_working_unit(
+            Some(ConcentrationUnit::Percent),
+            Some(&mut target),
+            &mut [],
+            &nacl,
+        )
+        .unwrap();
+        assert_eq!(unit, Some(ConcentrationUnit::Percent));
+        assert_eq!(target.value, 0.5);
+
+        let mut plain = vec!["0.4".parse::<InputSpec>().unwrap()];
+        assert_eq!(
+            convert_to_working_unit(None, None, &mut plain, &nacl),
+            Ok(None)
+        );
+        assert_eq!(plain[0].concentration, 0.4);
+
+        let mut target = "0.1M".parse::<ConcentrationSpec>().unwrap();
+        let mut input_space = vec!["2%".parse::<InputSpec>().unwrap()];
+        assert!(convert_to_working_unit(
+            None,
+            Some(&mut target),
+            &mut input_space,
+            &Reagent::default()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use std::process::Command;
+
+#[test]
+fn json_output_is_the_only_output_on_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_fluido"))
+        .args([
+            "--input-space",
+            "0.4",
+            "--input-space",
+            "0",
+            "--target-concentration",
+            "0.2",
+            "--time-limit",
+            "1",
+            "--json",
+        ])
+        .output()
+        .expect("fluido runs");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let design: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(design["target"]["concentration"], "0.2");
+    // Concentrations and volumes are decimals, not the fixed point numbers of the search.
+    let root = &design["mix_tree"]["nodes"][2]["fluid"];
+    assert_eq!(root["concentration"], 0.2);
+    assert_eq!(root["unit_volume"], 2.0);
+    let reagents = design["reagent_usage"].as_array().unwrap();
+    let concentrations = reagents.iter().map(|usage| usage["concentration"].as_f64());
+    assert_eq!(concentrations.collect::<Vec<_>>(), [Some(0.0), Some(0.4)]);
+    // Human readable output is still printed, to stderr.
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("best expr:"));
+}